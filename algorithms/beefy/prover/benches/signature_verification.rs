@@ -0,0 +1,84 @@
+// Copyright (C) 2022 ComposableFi.
+// SPDX-License-Identifier: Apache-2.0
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use beefy_light_client_primitives::SignatureWithAuthorityIndex;
+use beefy_prover::helpers::{
+	hash_authority_addresses, signature_verification_pool, verify_commitment_signatures,
+	verify_commitment_signatures_parallel,
+};
+use codec::Encode;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use sp_core::{ecdsa, keccak_256, Pair};
+
+/// Size of the signing authority set, about the one of Polkadot.
+const AUTHORITIES: usize = 300;
+
+/// A commitment hash signed by all the [`AUTHORITIES`], with the hashed addresses of the
+/// authorities.
+fn fixture() -> ([u8; 32], Vec<SignatureWithAuthorityIndex>, Vec<[u8; 32]>) {
+	let commitment_hash = keccak_256(b"commitment");
+	let pairs = (0..AUTHORITIES)
+		.map(|i| ecdsa::Pair::from_seed(&keccak_256(&(i as u64).encode())))
+		.collect::<Vec<_>>();
+	let authority_address_hashes =
+		hash_authority_addresses(pairs.iter().map(|pair| pair.public().encode()).collect())
+			.unwrap();
+	let signatures = pairs
+		.iter()
+		.enumerate()
+		.map(|(index, pair)| SignatureWithAuthorityIndex {
+			index: index as u32,
+			signature: pair.sign_prehashed(&commitment_hash).0,
+		})
+		.collect();
+	(commitment_hash, signatures, authority_address_hashes)
+}
+
+fn signature_verification(c: &mut Criterion) {
+	let (commitment_hash, signatures, authority_address_hashes) = fixture();
+	let threshold = ((2 * AUTHORITIES) / 3) + 1;
+
+	let mut group = c.benchmark_group("verify_commitment_signatures");
+	group.bench_function("sequential", |b| {
+		b.iter(|| {
+			verify_commitment_signatures(
+				&commitment_hash,
+				&signatures,
+				&authority_address_hashes,
+				threshold,
+			)
+			.unwrap()
+		})
+	});
+	for threads in [2, 4, 8] {
+		let pool = signature_verification_pool(threads).unwrap();
+		group.bench_with_input(BenchmarkId::new("parallel", threads), &pool, |b, pool| {
+			b.iter(|| {
+				verify_commitment_signatures_parallel(
+					&commitment_hash,
+					&signatures,
+					&authority_address_hashes,
+					threshold,
+					pool,
+				)
+				.unwrap()
+			})
+		});
+	}
+	group.finish();
+}
+
+criterion_group!(benches, signature_verification);
+criterion_main!(benches);
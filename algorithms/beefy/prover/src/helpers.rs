@@ -14,13 +14,20 @@
 // limitations under the License.
 
 use crate::{error::Error, Crypto};
-use beefy_light_client_primitives::{MerkleHasher, SignatureWithAuthorityIndex};
+use beefy_light_client_primitives::{
+	HostFunctions, MerkleHasher, SignatureWithAuthorityIndex, SignedCommitment,
+};
 use codec::{Decode, Encode};
 use frame_support::sp_runtime::traits::Convert;
-use sp_core::keccak_256;
+use light_client_common::TimestampCall;
+use rayon::prelude::*;
+use sp_core::{keccak_256, ByteArray};
 use sp_runtime::traits::BlakeTwo256;
 use sp_trie::{generate_trie_proof, TrieDBMutBuilder, TrieMut};
-use std::{collections::BTreeMap, sync::Arc};
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	sync::Arc,
+};
 use subxt::{Config, OnlineClient};
 
 /// Holds the timestamp inherent alongside a merkle-patricia trie proof of its existence in a given
@@ -71,7 +78,8 @@ pub async fn fetch_timestamp_extrinsic_with_proof<T: Config>(
 		if extrinsics.is_empty() {
 			return Err(From::from("Block has no extrinsics".to_string()))
 		}
-		let timestamp_ext = extrinsics[0].clone();
+		let timestamp_index = timestamp_extrinsic_index(client, &extrinsics);
+		let timestamp_ext = extrinsics[timestamp_index].clone();
 
 		let mut db = sp_trie::MemoryDB::<BlakeTwo256>::default();
 
@@ -85,7 +93,7 @@ pub async fn fetch_timestamp_extrinsic_with_proof<T: Config>(
 			}
 			*trie.root()
 		};
-		let key = codec::Compact::<u32>(0u32).encode();
+		let key = codec::Compact::<u32>(timestamp_index as u32).encode();
 
 		let extrinsic_proof =
 			generate_trie_proof::<sp_trie::LayoutV0<BlakeTwo256>, _, _, _>(&db, root, vec![&key])?;
@@ -96,6 +104,22 @@ pub async fn fetch_timestamp_extrinsic_with_proof<T: Config>(
 	Ok(TimeStampExtWithProof { ext, proof })
 }
 
+/// Returns the index of the `Timestamp::set` inherent among the encoded `extrinsics`. Inherents
+/// are ordered by the runtime, so the timestamp isn't necessarily the first one. Falls back to the
+/// first extrinsic if the runtime has no `Timestamp` pallet.
+fn timestamp_extrinsic_index<T: Config>(client: &OnlineClient<T>, extrinsics: &[Vec<u8>]) -> usize {
+	let metadata = client.metadata();
+	let Some((pallet_index, call_index)) = metadata
+		.pallet_by_name("Timestamp")
+		.and_then(|pallet| Some((pallet.index(), pallet.call_variant_by_name("set")?.index)))
+	else {
+		return 0
+	};
+	TimestampCall { pallet_index, call_index }
+		.find(extrinsics)
+		.map_or(0, |(index, _)| index)
+}
+
 /// Parachain idenitfier type
 pub type ParaId = u32;
 /// SCALE-encoded parachain header
@@ -200,8 +224,253 @@ pub fn hash_authority_addresses(encoded_public_keys: Vec<Vec<u8>>) -> Result<Vec
 	Ok(authority_address_hashes)
 }
 
+/// Returns true if `signature` over `commitment_hash` was produced by the authority whose address
+/// hash is at `signature.index` in `authority_address_hashes`.
+fn is_valid_signature(
+	commitment_hash: &[u8; 32],
+	signature: &SignatureWithAuthorityIndex,
+	authority_address_hashes: &[[u8; 32]],
+) -> bool {
+	let Some(authority_address_hash) = authority_address_hashes.get(signature.index as usize)
+	else {
+		return false
+	};
+	Crypto::secp256k1_ecdsa_recover_compressed(&signature.signature, commitment_hash)
+		.and_then(|public_key| beefy_primitives::crypto::AuthorityId::from_slice(&public_key).ok())
+		.map(|id| keccak_256(&beefy_mmr::BeefyEcdsaToEthereum::convert(id)))
+		.map_or(false, |hash| hash == *authority_address_hash)
+}
+
+/// Verifies the `signatures` of a BEEFY commitment in order, stopping as soon as `threshold` of
+/// them are valid. Returns the authority indices of the valid signatures. A commitment signed more
+/// than once by the same authority is rejected.
+pub fn verify_commitment_signatures(
+	commitment_hash: &[u8; 32],
+	signatures: &[SignatureWithAuthorityIndex],
+	authority_address_hashes: &[[u8; 32]],
+	threshold: usize,
+) -> Result<Vec<u32>, Error> {
+	let mut valid = Vec::with_capacity(threshold);
+	if threshold == 0 {
+		return Ok(valid)
+	}
+	let mut seen = BTreeSet::new();
+	for signature in signatures {
+		check_unique_index(&mut seen, signature)?;
+		if is_valid_signature(commitment_hash, signature, authority_address_hashes) {
+			valid.push(signature.index);
+			if valid.len() == threshold {
+				return Ok(valid)
+			}
+		}
+	}
+	Err(insufficient_signatures(valid.len(), threshold))
+}
+
+/// Builds the pool [`verify_commitment_signatures_parallel`] runs on, with `threads` threads (`0`
+/// uses one thread per CPU). Meant to be built once and shared by all the verifications.
+pub fn signature_verification_pool(threads: usize) -> Result<rayon::ThreadPool, Error> {
+	rayon::ThreadPoolBuilder::new()
+		.num_threads(threads)
+		.thread_name(|i| format!("beefy-signatures-{i}"))
+		.build()
+		.map_err(|e| Error::Custom(format!("Failed to build signature verification pool: {e}")))
+}
+
+/// Same as [`verify_commitment_signatures`], but checks the signatures on `pool`.
+///
+/// Signatures are verified in rounds of a few signatures per thread and the results of a round are
+/// aggregated in order, so the outcome is identical to the sequential version. Verification stops
+/// after the round in which the threshold is reached.
+pub fn verify_commitment_signatures_parallel(
+	commitment_hash: &[u8; 32],
+	signatures: &[SignatureWithAuthorityIndex],
+	authority_address_hashes: &[[u8; 32]],
+	threshold: usize,
+	pool: &rayon::ThreadPool,
+) -> Result<Vec<u32>, Error> {
+	let mut valid = Vec::with_capacity(threshold);
+	if threshold == 0 {
+		return Ok(valid)
+	}
+	let mut seen = BTreeSet::new();
+	let round_size = pool.current_num_threads() * 4;
+	for round in signatures.chunks(round_size) {
+		let results = pool.install(|| {
+			round
+				.par_iter()
+				.map(|signature| {
+					is_valid_signature(commitment_hash, signature, authority_address_hashes)
+				})
+				.collect::<Vec<_>>()
+		});
+		for (signature, is_valid) in round.iter().zip(results) {
+			check_unique_index(&mut seen, signature)?;
+			if is_valid {
+				valid.push(signature.index);
+				if valid.len() == threshold {
+					return Ok(valid)
+				}
+			}
+		}
+	}
+	Err(insufficient_signatures(valid.len(), threshold))
+}
+
+/// Verifies the signatures of `signed_commitment` on `pool`, requiring the same supermajority of
+/// the authority set hashed in `authority_address_hashes` as the BEEFY light client.
+pub fn verify_signed_commitment(
+	signed_commitment: &SignedCommitment,
+	authority_address_hashes: &[[u8; 32]],
+	pool: &rayon::ThreadPool,
+) -> Result<Vec<u32>, Error> {
+	let commitment_hash = keccak_256(&signed_commitment.commitment.encode());
+	let threshold = ((2 * authority_address_hashes.len()) / 3) + 1;
+	verify_commitment_signatures_parallel(
+		&commitment_hash,
+		&signed_commitment.signatures,
+		authority_address_hashes,
+		threshold,
+		pool,
+	)
+}
+
+fn check_unique_index(
+	seen: &mut BTreeSet<u32>,
+	signature: &SignatureWithAuthorityIndex,
+) -> Result<(), Error> {
+	if !seen.insert(signature.index) {
+		return Err(Error::Custom(format!(
+			"Commitment is signed more than once by authority {}",
+			signature.index
+		)))
+	}
+	Ok(())
+}
+
+fn insufficient_signatures(valid: usize, threshold: usize) -> Error {
+	Error::Custom(format!(
+		"Commitment has {valid} valid signatures, at least {threshold} are required"
+	))
+}
+
 /// Perform a highly unsafe type-casting between two types hidden behind an Arc.
 pub unsafe fn unsafe_arc_cast<T, U>(arc: Arc<T>) -> Arc<U> {
 	let ptr = Arc::into_raw(arc).cast::<U>();
 	Arc::from_raw(ptr)
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use sp_core::{ecdsa, Pair};
+
+	struct Fixture {
+		commitment_hash: [u8; 32],
+		signatures: Vec<SignatureWithAuthorityIndex>,
+		authority_address_hashes: Vec<[u8; 32]>,
+	}
+
+	/// Every authority signs `commitment_hash`, except the ones in `invalid`, which sign another
+	/// message.
+	fn fixture(authorities: usize, invalid: &[u32]) -> Fixture {
+		let commitment_hash = keccak_256(b"commitment");
+		let pairs = (0..authorities)
+			.map(|i| ecdsa::Pair::from_seed(&keccak_256(&(i as u64).encode())))
+			.collect::<Vec<_>>();
+		let authority_address_hashes =
+			hash_authority_addresses(pairs.iter().map(|pair| pair.public().encode()).collect())
+				.unwrap();
+		let signatures = pairs
+			.iter()
+			.enumerate()
+			.map(|(index, pair)| {
+				let index = index as u32;
+				let message =
+					if invalid.contains(&index) { keccak_256(b"other") } else { commitment_hash };
+				SignatureWithAuthorityIndex { index, signature: pair.sign_prehashed(&message).0 }
+			})
+			.collect();
+		Fixture { commitment_hash, signatures, authority_address_hashes }
+	}
+
+	/// Pools of 1, 2 and 8 threads, built once for all the tests.
+	fn pools() -> &'static [(usize, rayon::ThreadPool)] {
+		static POOLS: std::sync::OnceLock<Vec<(usize, rayon::ThreadPool)>> =
+			std::sync::OnceLock::new();
+		POOLS.get_or_init(|| {
+			[1, 2, 8]
+				.into_iter()
+				.map(|threads| (threads, signature_verification_pool(threads).unwrap()))
+				.collect()
+		})
+	}
+
+	fn verify_both(fixture: &Fixture, threshold: usize) -> Result<Vec<u32>, String> {
+		let sequential = verify_commitment_signatures(
+			&fixture.commitment_hash,
+			&fixture.signatures,
+			&fixture.authority_address_hashes,
+			threshold,
+		)
+		.map_err(|e| format!("{e:?}"));
+		for (threads, pool) in pools() {
+			let parallel = verify_commitment_signatures_parallel(
+				&fixture.commitment_hash,
+				&fixture.signatures,
+				&fixture.authority_address_hashes,
+				threshold,
+				pool,
+			)
+			.map_err(|e| format!("{e:?}"));
+			assert_eq!(sequential, parallel, "{threads} threads");
+		}
+		sequential
+	}
+
+	#[test]
+	fn parallel_verification_matches_sequential() {
+		let fixture = fixture(40, &[1, 5, 6, 30]);
+
+		// early exit: only the first `threshold` valid signatures are returned
+		let valid = verify_both(&fixture, 10).unwrap();
+		assert_eq!(valid, vec![0, 2, 3, 4, 7, 8, 9, 10, 11, 12]);
+
+		// exactly enough valid signatures
+		assert_eq!(verify_both(&fixture, 36).unwrap().len(), 36);
+
+		// not enough valid signatures
+		assert!(verify_both(&fixture, 37).is_err());
+		assert_eq!(verify_both(&fixture, 0).unwrap(), Vec::<u32>::new());
+	}
+
+	#[test]
+	fn signatures_from_wrong_authority_index_are_rejected() {
+		let mut fixture = fixture(10, &[]);
+		// signatures 0 and 1 claim each other's authority index
+		fixture.signatures[0].index = 1;
+		fixture.signatures[1].index = 0;
+		assert!(verify_both(&fixture, 9).is_err());
+		assert_eq!(verify_both(&fixture, 8).unwrap(), (2..10).collect::<Vec<_>>());
+
+		// out of range authority index
+		fixture.signatures[2].index = 10;
+		assert!(verify_both(&fixture, 8).is_err());
+	}
+
+	#[test]
+	fn repeated_authority_indices_are_rejected() {
+		let mut fixture = fixture(10, &[]);
+		// authority 0 signs twice, once in place of authority 1
+		fixture.signatures[1] = fixture.signatures[0].clone();
+		assert!(verify_both(&fixture, 9).is_err());
+
+		// the repeated signature is rejected even if it comes after an invalid one
+		let mut fixture = self::fixture(10, &[3]);
+		fixture.signatures[4] = fixture.signatures[3].clone();
+		assert!(verify_both(&fixture, 8).is_err());
+
+		// as long as the threshold is reached before the repetition
+		assert_eq!(verify_both(&fixture, 3).unwrap(), vec![0, 1, 2]);
+	}
+}
@@ -29,11 +29,11 @@ use beefy_light_client_primitives::{
 	ClientState, HostFunctions, MmrUpdateProof, ParachainHeader, PartialMmrLeaf,
 };
 use beefy_primitives::mmr::{BeefyNextAuthoritySet, MmrLeaf};
-use codec::Decode;
+use codec::{Decode, Encode};
 use error::Error;
 use helpers::{
-	fetch_timestamp_extrinsic_with_proof, prove_parachain_headers, ParaHeadsProof,
-	TimeStampExtWithProof,
+	fetch_timestamp_extrinsic_with_proof, hash_authority_addresses, prove_parachain_headers,
+	ParaHeadsProof, TimeStampExtWithProof,
 };
 use hex_literal::hex;
 use pallet_mmr_primitives::Proof;
@@ -356,6 +356,30 @@ where
 		 */
 	}
 
+	/// Returns the hashed addresses of the BEEFY authorities at the relay chain block
+	/// `block_number`, in the order the signatures of their commitments index them.
+	pub async fn fetch_beefy_authority_address_hashes(
+		&self,
+		block_number: u32,
+	) -> Result<Vec<[u8; 32]>, Error> {
+		let subxt_block_number: subxt::rpc::types::BlockNumber = block_number.into();
+		let block_hash = self
+			.relay_client
+			.rpc()
+			.block_hash(Some(subxt_block_number))
+			.await?
+			.ok_or_else(|| {
+				Error::Custom(format!("Failed to fetch block hash for block number {block_number}"))
+			})?;
+		let authorities = {
+			let key = T::Storage::beefy_authorities();
+			self.relay_client.storage().at(block_hash).fetch(&key).await?.ok_or_else(|| {
+				Error::Custom(format!("No beefy authorities found at block {block_number}"))
+			})?
+		};
+		hash_authority_addresses(authorities.into_iter().map(|x| x.encode()).collect())
+	}
+
 	/// Construct a beefy client state to be submitted to the counterparty chain
 	pub async fn construct_beefy_client_state(&self) -> Result<ClientState, Error> {
 		todo!("fetch beefy authorities")
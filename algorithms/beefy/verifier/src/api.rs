@@ -0,0 +1,244 @@
+// Copyright (C) 2022 ComposableFi.
+// SPDX-License-Identifier: Apache-2.0
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Stable verification entry points of the BEEFY light client, see the
+//! [crate level documentation](crate#versioning) for the compatibility guarantees.
+
+use crate::{
+	mmr_root_hash, parachain_mmr_leaf_hash, verify_commitment_signatures, verify_latest_mmr_leaf,
+	SigningAuthorities,
+};
+use beefy_light_client_primitives::{
+	error::BeefyClientError, BeefyNextAuthoritySet, ClientState, Hash, HostFunctions, MerkleHasher,
+	MmrUpdateProof, NodesUtils, ParachainHeader, SignedCommitment,
+};
+use beefy_primitives::mmr::MmrLeaf;
+use codec::{Decode, Encode};
+use pallet_mmr_primitives::Proof;
+use sp_core::{Hasher, H256};
+use sp_std::prelude::*;
+
+/// Proof that the MMR root of a signed commitment commits to the latest MMR leaf, along with the
+/// proof of the signing authorities.
+#[derive(sp_std::fmt::Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct MmrLeafProof {
+	/// Latest leaf added to the mmr
+	pub latest_mmr_leaf: MmrLeaf<u32, H256, H256, H256>,
+	/// Proof for the latest mmr leaf
+	pub mmr_proof: Proof<H256>,
+	/// Proof for the signing authorities against the trusted authority set root
+	pub authority_proof: Vec<Hash>,
+}
+
+impl MmrLeafProof {
+	/// Splits an [`MmrUpdateProof`] into the arguments of [`verify_mmr_root_update`].
+	pub fn split(update: MmrUpdateProof) -> (SignedCommitment, Self) {
+		let MmrUpdateProof { signed_commitment, latest_mmr_leaf, mmr_proof, authority_proof } =
+			update;
+		(signed_commitment, Self { latest_mmr_leaf, mmr_proof, authority_proof })
+	}
+}
+
+/// Trusted state resulting from a successful [`verify_mmr_root_update`].
+#[derive(sp_std::fmt::Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct NewTrustedState {
+	/// Light client state tracking the verified mmr root
+	pub client_state: ClientState,
+	/// Whether the commitment was signed by the next authority set, which became the current one
+	pub authorities_rotated: bool,
+}
+
+/// Outcome of a successful [`verify_beefy_header`], holding everything a host chain needs to
+/// update its view of the relay chain without decoding the header again.
+#[derive(sp_std::fmt::Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct VerificationResult {
+	/// Block number of the latest verified commitment
+	pub latest_beefy_height: u32,
+	/// Latest verified mmr root
+	pub mmr_root_hash: H256,
+	/// Authority set expected to sign the next commitment
+	pub current_authorities: BeefyNextAuthoritySet<H256>,
+	/// Authority set of the next session
+	pub next_authorities: BeefyNextAuthoritySet<H256>,
+	/// Whether the commitment was signed by the next authority set, which became the current one
+	pub authorities_rotated: bool,
+	/// Hashes of the verified parachain headers, in the order they were given
+	pub parachain_header_hashes: Vec<H256>,
+}
+
+impl VerificationResult {
+	/// Light client state tracking the verified mmr root.
+	pub fn client_state(&self) -> ClientState {
+		ClientState {
+			latest_beefy_height: self.latest_beefy_height,
+			mmr_root_hash: self.mmr_root_hash,
+			current_authorities: self.current_authorities.clone(),
+			next_authorities: self.next_authorities.clone(),
+		}
+	}
+}
+
+/// Verifies a beefy header: the optional mmr root update, then the inclusion of the optional
+/// parachain headers in the mmr root resulting from it.
+///
+/// Without an mmr update the parachain headers are verified against the trusted mmr root, and
+/// the result carries the trusted state.
+pub fn verify_beefy_header<H>(
+	trusted_state: &ClientState,
+	mmr_update: Option<(&SignedCommitment, &MmrLeafProof)>,
+	parachain_headers: Option<(&[ParachainHeader], &Proof<H256>)>,
+) -> Result<VerificationResult, BeefyClientError>
+where
+	H: HostFunctions + Clone,
+{
+	let (client_state, authorities_rotated) = match mmr_update {
+		Some((signed_commitment, mmr_proof)) =>
+			mmr_root_update::<H>(trusted_state, signed_commitment, mmr_proof)?,
+		None => (trusted_state.clone(), false),
+	};
+	let parachain_header_hashes = match parachain_headers {
+		Some((leaves, batch_proof)) =>
+			parachain_headers_inclusion::<H>(&client_state, leaves, batch_proof)?,
+		None => vec![],
+	};
+	let ClientState { latest_beefy_height, mmr_root_hash, current_authorities, next_authorities } =
+		client_state;
+	Ok(VerificationResult {
+		latest_beefy_height,
+		mmr_root_hash,
+		current_authorities,
+		next_authorities,
+		authorities_rotated,
+		parachain_header_hashes,
+	})
+}
+
+/// Verifies that known authorities signed the commitment, then verifies the latest mmr leaf
+/// against the signed mmr root, using it to rotate the view of the next authorities.
+///
+/// Thin wrapper around [`verify_beefy_header`], kept for existing callers.
+pub fn verify_mmr_root_update<H>(
+	trusted_state: &ClientState,
+	signed_commitment: &SignedCommitment,
+	mmr_proof: &MmrLeafProof,
+) -> Result<NewTrustedState, BeefyClientError>
+where
+	H: HostFunctions + Clone,
+{
+	let result =
+		verify_beefy_header::<H>(trusted_state, Some((signed_commitment, mmr_proof)), None)?;
+	Ok(NewTrustedState {
+		client_state: result.client_state(),
+		authorities_rotated: result.authorities_rotated,
+	})
+}
+
+/// Verifies the inclusion of the given parachain headers (the mmr `leaves`) in the trusted mmr
+/// root, using the mmr `batch_proof`.
+///
+/// Thin wrapper around [`verify_beefy_header`], kept for existing callers.
+pub fn verify_parachain_headers<H>(
+	trusted_state: &ClientState,
+	leaves: &[ParachainHeader],
+	batch_proof: &Proof<H256>,
+) -> Result<(), BeefyClientError>
+where
+	H: HostFunctions + Clone,
+{
+	verify_beefy_header::<H>(trusted_state, None, Some((leaves, batch_proof))).map(|_| ())
+}
+
+/// Returns the state resulting from the mmr root update and whether the authorities rotated.
+fn mmr_root_update<H>(
+	trusted_state: &ClientState,
+	signed_commitment: &SignedCommitment,
+	mmr_proof: &MmrLeafProof,
+) -> Result<(ClientState, bool), BeefyClientError>
+where
+	H: HostFunctions + Clone,
+{
+	let signing_authorities = verify_commitment_signatures::<H>(
+		trusted_state,
+		signed_commitment,
+		&mmr_proof.authority_proof,
+	)?;
+	let mmr_root_hash = mmr_root_hash(signed_commitment)?;
+
+	let latest_beefy_height = trusted_state.latest_beefy_height;
+	let commitment_block_number = signed_commitment.commitment.block_number;
+	if commitment_block_number <= latest_beefy_height {
+		return Err(BeefyClientError::OutdatedCommitment {
+			latest_beefy_height,
+			commitment_block_number,
+		})
+	}
+
+	verify_latest_mmr_leaf::<H>(mmr_root_hash, &mmr_proof.latest_mmr_leaf, &mmr_proof.mmr_proof)?;
+
+	let mut client_state = trusted_state.clone();
+	client_state.latest_beefy_height = commitment_block_number;
+	client_state.mmr_root_hash = mmr_root_hash;
+
+	let authorities_rotated = matches!(signing_authorities, SigningAuthorities::Next);
+	if authorities_rotated {
+		client_state.current_authorities = trusted_state.next_authorities.clone();
+		client_state.next_authorities = mmr_proof.latest_mmr_leaf.beefy_next_authority_set.clone();
+	}
+	Ok((client_state, authorities_rotated))
+}
+
+/// Returns the hashes of the parachain headers once their inclusion is verified.
+fn parachain_headers_inclusion<H>(
+	trusted_state: &ClientState,
+	leaves: &[ParachainHeader],
+	batch_proof: &Proof<H256>,
+) -> Result<Vec<H256>, BeefyClientError>
+where
+	H: HostFunctions + Clone,
+{
+	if batch_proof.leaf_indices.len() != leaves.len() {
+		Err(BeefyClientError::Custom(
+			"leaf indices is not equal to number of parachain headers".into(),
+		))?
+	}
+
+	let mmr_leaves = leaves
+		.iter()
+		.zip(&batch_proof.leaf_indices)
+		.map(|(parachain_header, leaf_index)| {
+			let node = parachain_mmr_leaf_hash::<H>(parachain_header)?;
+			Ok((mmr_lib::leaf_index_to_pos(*leaf_index), node))
+		})
+		.collect::<Result<Vec<_>, BeefyClientError>>()?;
+
+	let mmr_size = NodesUtils::new(batch_proof.leaf_count).size();
+	let proof =
+		mmr_lib::MerkleProof::<_, MerkleHasher<H>>::new(mmr_size, batch_proof.items.clone());
+
+	let root = proof.calculate_root(mmr_leaves)?;
+	if root != trusted_state.mmr_root_hash {
+		return Err(BeefyClientError::InvalidMmrProof {
+			expected: trusted_state.mmr_root_hash,
+			found: root,
+			location: "verifying_parachain_headers_inclusion",
+		})
+	}
+	Ok(leaves
+		.iter()
+		.map(|leaf| <H::BlakeTwo256 as Hasher>::hash(&leaf.parachain_header))
+		.collect())
+}
@@ -13,8 +13,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::MmrLeafProof;
 use beefy_light_client_primitives::{
-	error::BeefyClientError, EncodedVersionedFinalityProof, MmrUpdateProof, ParachainsUpdateProof,
+	error::BeefyClientError, EncodedVersionedFinalityProof, MmrUpdateProof,
 	SignatureWithAuthorityIndex, SignedCommitment,
 };
 use beefy_primitives::{
@@ -93,9 +94,11 @@ async fn test_verify_mmr_with_proof() {
 			.await
 			.unwrap();
 
+		let (signed_commitment, mmr_proof) = MmrLeafProof::split(mmr_update.clone());
 		client_state =
-			crate::verify_mmr_root_with_proof::<Crypto>(client_state.clone(), mmr_update.clone())
-				.unwrap();
+			crate::verify_mmr_root_update::<Crypto>(&client_state, &signed_commitment, &mmr_proof)
+				.unwrap()
+				.client_state;
 
 		let mmr_root_hash = signed_commitment.commitment.payload.get_raw(&MMR_ROOT_ID).unwrap();
 
@@ -141,9 +144,11 @@ async fn should_fail_with_incomplete_signature_threshold() {
 		authority_proof: vec![],
 	};
 
-	let res = crate::verify_mmr_root_with_proof::<Crypto>(
-		Prover::<PolkadotConfig>::get_initial_client_state(None).await,
-		mmr_update,
+	let (signed_commitment, mmr_proof) = MmrLeafProof::split(mmr_update);
+	let res = crate::verify_mmr_root_update::<Crypto>(
+		&Prover::<PolkadotConfig>::get_initial_client_state(None).await,
+		&signed_commitment,
+		&mmr_proof,
 	);
 
 	match res {
@@ -180,9 +185,11 @@ async fn should_fail_with_invalid_validator_set_id() {
 		authority_proof: vec![],
 	};
 
-	let res = crate::verify_mmr_root_with_proof::<Crypto>(
-		Prover::<PolkadotConfig>::get_initial_client_state(None).await,
-		mmr_update,
+	let (signed_commitment, mmr_proof) = MmrLeafProof::split(mmr_update);
+	let res = crate::verify_mmr_root_update::<Crypto>(
+		&Prover::<PolkadotConfig>::get_initial_client_state(None).await,
+		&signed_commitment,
+		&mmr_proof,
 	);
 	match res {
 		Err(BeefyClientError::AuthoritySetMismatch {
@@ -278,16 +285,16 @@ async fn verify_parachain_headers() {
 			.await
 			.unwrap();
 
-		let parachain_update_proof =
-			ParachainsUpdateProof { parachain_headers, mmr_proof: batch_proof };
-
 		let mmr_update =
 			parachain_client.fetch_mmr_update_proof_for(signed_commitment).await.unwrap();
+		let (signed_commitment, mmr_proof) = MmrLeafProof::split(mmr_update);
 
-		client_state = crate::verify_mmr_root_with_proof::<Crypto>(client_state, mmr_update)
-			.expect("verify_mmr_root_with_proof should not panic!");
+		client_state =
+			crate::verify_mmr_root_update::<Crypto>(&client_state, &signed_commitment, &mmr_proof)
+				.expect("verify_mmr_root_update should not panic!")
+				.client_state;
 
-		crate::verify_parachain_headers::<Crypto>(client_state.clone(), parachain_update_proof)
+		crate::verify_parachain_headers::<Crypto>(&client_state, &parachain_headers, &batch_proof)
 			.expect("verify_parachain_headers should not panic!");
 
 		println!(
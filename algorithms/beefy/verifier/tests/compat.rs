@@ -0,0 +1,389 @@
+// Copyright (C) 2022 ComposableFi.
+// SPDX-License-Identifier: Apache-2.0
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compatibility suite of the public verification API.
+//!
+//! This suite only goes through `beefy_light_client::api`. Runtimes vendoring a pinned copy of the
+//! light client can point the `beefy-light-client` dependency at their copy and run it to confirm
+//! that the copy accepts and rejects the same inputs as the release they're upgrading to.
+
+use beefy_light_client::api::{
+	verify_beefy_header, verify_mmr_root_update, verify_parachain_headers, MmrLeafProof,
+};
+use beefy_light_client_primitives::{
+	error::BeefyClientError, BeefyNextAuthoritySet, ClientState, MerkleHasher, MmrLeaf,
+	ParachainHeader, PartialMmrLeaf, SignatureWithAuthorityIndex, SignedCommitment,
+};
+use beefy_primitives::{known_payloads::MMR_ROOT_ID, mmr::MmrLeafVersion, Commitment, Payload};
+use beefy_prover::Crypto;
+use codec::Encode;
+use frame_support::sp_runtime::traits::Convert;
+use pallet_mmr_primitives::Proof;
+use sp_core::{blake2_256, ecdsa, keccak_256, Pair, H256};
+use sp_runtime::{generic::Header, traits::BlakeTwo256};
+use sp_trie::{LayoutV0, MemoryDB, TrieDBMutBuilder, TrieMut};
+
+const PARA_ID: u32 = 2000;
+const COMMITMENT_BLOCK_NUMBER: u32 = 10;
+
+/// A deterministic authority set of `len` keys derived from `seed`.
+struct Authorities {
+	id: u64,
+	pairs: Vec<ecdsa::Pair>,
+}
+
+impl Authorities {
+	fn new(id: u64, seed: u8, len: u8) -> Self {
+		let pairs = (0..len).map(|i| ecdsa::Pair::from_seed(&[seed + i; 32])).collect();
+		Self { id, pairs }
+	}
+
+	fn address_hashes(&self) -> Vec<[u8; 32]> {
+		self.pairs
+			.iter()
+			.map(|pair| {
+				let id = beefy_primitives::crypto::AuthorityId::from(pair.public());
+				keccak_256(&beefy_mmr::BeefyEcdsaToEthereum::convert(id))
+			})
+			.collect()
+	}
+
+	fn tree(&self) -> rs_merkle::MerkleTree<MerkleHasher<Crypto>> {
+		rs_merkle::MerkleTree::from_leaves(&self.address_hashes())
+	}
+
+	fn set(&self) -> BeefyNextAuthoritySet<H256> {
+		BeefyNextAuthoritySet {
+			id: self.id,
+			len: self.pairs.len() as u32,
+			root: H256(self.tree().root().expect("authority set is not empty")),
+		}
+	}
+
+	/// Signs the commitment with the first `count` authorities and proves their membership.
+	fn sign(&self, commitment: Commitment<u32>, count: usize) -> (SignedCommitment, Vec<[u8; 32]>) {
+		let commitment_hash = keccak_256(&commitment.encode());
+		let signatures = self.pairs[..count]
+			.iter()
+			.enumerate()
+			.map(|(index, pair)| SignatureWithAuthorityIndex {
+				index: index as u32,
+				signature: pair.sign_prehashed(&commitment_hash).0,
+			})
+			.collect();
+		let indices = (0..count).collect::<Vec<_>>();
+		let authority_proof = self.tree().proof(&indices).proof_hashes().to_vec();
+		(SignedCommitment { commitment, signatures }, authority_proof)
+	}
+}
+
+/// Inputs of the public API, for a relay chain whose mmr holds a single leaf committing to a
+/// single parachain header.
+struct Fixture {
+	trusted_state: ClientState,
+	signed_commitment: SignedCommitment,
+	mmr_proof: MmrLeafProof,
+	parachain_headers: Vec<ParachainHeader>,
+	batch_proof: Proof<H256>,
+}
+
+impl Fixture {
+	/// Builds a fixture whose commitment is signed by `signers` authorities of either the current
+	/// or the next authority set.
+	fn new(signed_by_next_authorities: bool, signers: usize) -> Self {
+		let current = Authorities::new(0, 1, 4);
+		let next = Authorities::new(1, 11, 4);
+		let upcoming = Authorities::new(2, 21, 4);
+
+		let partial_mmr_leaf = PartialMmrLeaf {
+			version: MmrLeafVersion::new(0, 0),
+			parent_number_and_hash: (COMMITMENT_BLOCK_NUMBER - 1, H256::repeat_byte(9)),
+			beefy_next_authority_set: upcoming.set(),
+		};
+		let (parachain_header, heads_root) = parachain_header(partial_mmr_leaf.clone());
+		let latest_mmr_leaf = MmrLeaf {
+			version: partial_mmr_leaf.version,
+			parent_number_and_hash: partial_mmr_leaf.parent_number_and_hash,
+			beefy_next_authority_set: partial_mmr_leaf.beefy_next_authority_set,
+			leaf_extra: heads_root,
+		};
+		// the root of a single leaf mmr is the hash of that leaf
+		let mmr_root = H256(keccak_256(&latest_mmr_leaf.encode()));
+		let single_leaf_proof = Proof { leaf_indices: vec![0], leaf_count: 1, items: vec![] };
+
+		let signing_authorities = if signed_by_next_authorities { &next } else { &current };
+		let commitment = Commitment {
+			payload: Payload::from_single_entry(MMR_ROOT_ID, mmr_root.as_bytes().to_vec()),
+			block_number: COMMITMENT_BLOCK_NUMBER,
+			validator_set_id: signing_authorities.id,
+		};
+		let (signed_commitment, authority_proof) = signing_authorities.sign(commitment, signers);
+
+		Fixture {
+			trusted_state: ClientState {
+				latest_beefy_height: 0,
+				mmr_root_hash: H256::zero(),
+				current_authorities: current.set(),
+				next_authorities: next.set(),
+			},
+			signed_commitment,
+			mmr_proof: MmrLeafProof {
+				latest_mmr_leaf,
+				mmr_proof: single_leaf_proof.clone(),
+				authority_proof,
+			},
+			parachain_headers: vec![parachain_header],
+			batch_proof: single_leaf_proof,
+		}
+	}
+
+	fn verify_mmr_root_update(&self) -> Result<ClientState, BeefyClientError> {
+		verify_mmr_root_update::<Crypto>(
+			&self.trusted_state,
+			&self.signed_commitment,
+			&self.mmr_proof,
+		)
+		.map(|state| state.client_state)
+	}
+}
+
+/// Builds the only parachain header of the parachain heads, returning it along with the root of
+/// the parachain heads.
+fn parachain_header(partial_mmr_leaf: PartialMmrLeaf) -> (ParachainHeader, H256) {
+	// unsigned extrinsic calling `Timestamp::set`
+	let timestamp_extrinsic = (4u8, 3u8, 0u8, codec::Compact(1_690_000_000_000u64)).encode();
+	let timestamp_key = codec::Compact(0u32).encode();
+
+	let mut db = MemoryDB::<BlakeTwo256>::default();
+	let mut extrinsics_root = Default::default();
+	{
+		let mut trie =
+			TrieDBMutBuilder::<LayoutV0<BlakeTwo256>>::new(&mut db, &mut extrinsics_root).build();
+		trie.insert(&timestamp_key, &timestamp_extrinsic).unwrap();
+	}
+	let extrinsic_proof = sp_trie::generate_trie_proof::<LayoutV0<BlakeTwo256>, _, _, _>(
+		&db,
+		extrinsics_root,
+		&[timestamp_key.clone()],
+	)
+	.unwrap();
+
+	let header = Header::<u32, BlakeTwo256> {
+		parent_hash: H256::repeat_byte(1),
+		number: 1,
+		state_root: H256::repeat_byte(2),
+		extrinsics_root,
+		digest: Default::default(),
+	}
+	.encode();
+
+	let heads_leaf = keccak_256(&(PARA_ID, header.clone()).encode());
+	let heads_tree = rs_merkle::MerkleTree::<MerkleHasher<Crypto>>::from_leaves(&[heads_leaf]);
+	let parachain_header = ParachainHeader {
+		parachain_header: header,
+		partial_mmr_leaf,
+		para_id: PARA_ID,
+		parachain_heads_proof: heads_tree.proof(&[0]).proof_hashes().to_vec(),
+		heads_leaf_index: 0,
+		heads_total_count: 1,
+		extrinsic_proof,
+		timestamp_extrinsic,
+	};
+	(parachain_header, H256(heads_tree.root().unwrap()))
+}
+
+#[test]
+fn accepts_commitment_signed_by_current_authorities() {
+	let fixture = Fixture::new(false, 3);
+	let new_state = verify_mmr_root_update::<Crypto>(
+		&fixture.trusted_state,
+		&fixture.signed_commitment,
+		&fixture.mmr_proof,
+	)
+	.unwrap();
+
+	assert!(!new_state.authorities_rotated);
+	let client_state = new_state.client_state;
+	assert_eq!(client_state.latest_beefy_height, COMMITMENT_BLOCK_NUMBER);
+	assert_eq!(
+		client_state.mmr_root_hash.as_bytes(),
+		&fixture.signed_commitment.commitment.payload.get_raw(&MMR_ROOT_ID).unwrap()[..]
+	);
+	assert_eq!(client_state.current_authorities, fixture.trusted_state.current_authorities);
+	assert_eq!(client_state.next_authorities, fixture.trusted_state.next_authorities);
+}
+
+#[test]
+fn rotates_authorities_on_commitment_signed_by_next_authorities() {
+	let fixture = Fixture::new(true, 3);
+	let new_state = verify_mmr_root_update::<Crypto>(
+		&fixture.trusted_state,
+		&fixture.signed_commitment,
+		&fixture.mmr_proof,
+	)
+	.unwrap();
+
+	assert!(new_state.authorities_rotated);
+	let client_state = new_state.client_state;
+	assert_eq!(client_state.current_authorities, fixture.trusted_state.next_authorities);
+	assert_eq!(
+		client_state.next_authorities,
+		fixture.mmr_proof.latest_mmr_leaf.beefy_next_authority_set
+	);
+}
+
+#[test]
+fn rejects_incomplete_signature_threshold() {
+	let fixture = Fixture::new(false, 2);
+	assert!(matches!(
+		fixture.verify_mmr_root_update(),
+		Err(BeefyClientError::IncompleteSignatureThreshold)
+	));
+}
+
+#[test]
+fn rejects_unknown_authority_set() {
+	let mut fixture = Fixture::new(false, 3);
+	fixture.signed_commitment.commitment.validator_set_id = 5;
+	assert!(matches!(
+		fixture.verify_mmr_root_update(),
+		Err(BeefyClientError::AuthoritySetMismatch { commitment_set_id: 5, .. })
+	));
+}
+
+#[test]
+fn rejects_tampered_signature() {
+	let mut fixture = Fixture::new(false, 3);
+	fixture.signed_commitment.signatures[1].signature[10] ^= 1;
+	assert!(matches!(
+		fixture.verify_mmr_root_update(),
+		Err(BeefyClientError::InvalidSignature | BeefyClientError::InvalidAuthorityProof)
+	));
+}
+
+#[test]
+fn rejects_outdated_commitment() {
+	let mut fixture = Fixture::new(false, 3);
+	fixture.trusted_state.latest_beefy_height = COMMITMENT_BLOCK_NUMBER;
+	assert!(matches!(
+		fixture.verify_mmr_root_update(),
+		Err(BeefyClientError::OutdatedCommitment { .. })
+	));
+}
+
+#[test]
+fn rejects_latest_leaf_not_in_signed_mmr() {
+	let mut fixture = Fixture::new(false, 3);
+	fixture.mmr_proof.latest_mmr_leaf.leaf_extra = H256::repeat_byte(7);
+	assert!(matches!(
+		fixture.verify_mmr_root_update(),
+		Err(BeefyClientError::InvalidMmrProof { .. })
+	));
+}
+
+#[test]
+fn accepts_parachain_headers_included_in_trusted_mmr() {
+	let fixture = Fixture::new(false, 3);
+	let client_state = fixture.verify_mmr_root_update().unwrap();
+	verify_parachain_headers::<Crypto>(
+		&client_state,
+		&fixture.parachain_headers,
+		&fixture.batch_proof,
+	)
+	.unwrap();
+}
+
+#[test]
+fn rejects_parachain_headers_against_untrusted_mmr_root() {
+	let fixture = Fixture::new(false, 3);
+	let res = verify_parachain_headers::<Crypto>(
+		&fixture.trusted_state,
+		&fixture.parachain_headers,
+		&fixture.batch_proof,
+	);
+	assert!(matches!(res, Err(BeefyClientError::InvalidMmrProof { .. })));
+}
+
+#[test]
+fn rejects_parachain_header_with_invalid_timestamp_proof() {
+	let mut fixture = Fixture::new(false, 3);
+	let client_state = fixture.verify_mmr_root_update().unwrap();
+	fixture.parachain_headers[0].timestamp_extrinsic.push(0);
+	let res = verify_parachain_headers::<Crypto>(
+		&client_state,
+		&fixture.parachain_headers,
+		&fixture.batch_proof,
+	);
+	assert!(matches!(res, Err(BeefyClientError::Custom(_))));
+}
+
+#[test]
+fn rejects_mismatched_leaf_indices() {
+	let mut fixture = Fixture::new(false, 3);
+	let client_state = fixture.verify_mmr_root_update().unwrap();
+	fixture.batch_proof.leaf_indices.push(1);
+	let res = verify_parachain_headers::<Crypto>(
+		&client_state,
+		&fixture.parachain_headers,
+		&fixture.batch_proof,
+	);
+	assert!(matches!(res, Err(BeefyClientError::Custom(_))));
+}
+
+#[test]
+fn verification_result_matches_the_verified_header() {
+	let fixture = Fixture::new(true, 3);
+	let result = verify_beefy_header::<Crypto>(
+		&fixture.trusted_state,
+		Some((&fixture.signed_commitment, &fixture.mmr_proof)),
+		Some((&fixture.parachain_headers, &fixture.batch_proof)),
+	)
+	.unwrap();
+
+	assert_eq!(result.latest_beefy_height, COMMITMENT_BLOCK_NUMBER);
+	assert_eq!(
+		result.mmr_root_hash.as_bytes(),
+		&fixture.signed_commitment.commitment.payload.get_raw(&MMR_ROOT_ID).unwrap()[..]
+	);
+	assert!(result.authorities_rotated);
+	assert_eq!(result.current_authorities, fixture.trusted_state.next_authorities);
+	assert_eq!(result.next_authorities, fixture.mmr_proof.latest_mmr_leaf.beefy_next_authority_set);
+	assert_eq!(
+		result.parachain_header_hashes,
+		vec![H256(blake2_256(&fixture.parachain_headers[0].parachain_header))]
+	);
+	// the compatibility wrapper reports the same state
+	assert_eq!(result.client_state(), fixture.verify_mmr_root_update().unwrap());
+}
+
+#[test]
+fn verification_result_without_mmr_update_keeps_the_trusted_state() {
+	let fixture = Fixture::new(false, 3);
+	let client_state = fixture.verify_mmr_root_update().unwrap();
+	let result = verify_beefy_header::<Crypto>(
+		&client_state,
+		None,
+		Some((&fixture.parachain_headers, &fixture.batch_proof)),
+	)
+	.unwrap();
+
+	assert!(!result.authorities_rotated);
+	assert_eq!(result.client_state(), client_state);
+	assert_eq!(result.parachain_header_hashes.len(), fixture.parachain_headers.len());
+
+	let result = verify_beefy_header::<Crypto>(&client_state, None, None).unwrap();
+	assert_eq!(result.client_state(), client_state);
+	assert!(result.parachain_header_hashes.is_empty());
+}
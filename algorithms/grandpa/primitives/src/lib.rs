@@ -24,6 +24,7 @@ extern crate alloc;
 use alloc::collections::BTreeMap;
 use codec::{Decode, Encode};
 use core::fmt::Debug;
+use scale_info::TypeInfo;
 use sp_consensus_grandpa::{AuthorityId, AuthorityList, AuthoritySignature};
 use sp_core::{ed25519, sp_std, H256};
 use sp_runtime::traits::Header;
@@ -47,7 +48,7 @@ pub type Commit<H> = finality_grandpa::Commit<
 /// Finality for block B is proved by providing:
 /// 1) the justification for the descendant block F;
 /// 2) headers sub-chain (B; F] if B != F;
-#[derive(Debug, PartialEq, Encode, Decode, Clone)]
+#[derive(Debug, PartialEq, Encode, Decode, TypeInfo, Clone)]
 pub struct FinalityProof<H: codec::Codec> {
 	/// The hash of block F for which justification is provided.
 	pub block: Hash,
@@ -57,6 +58,48 @@ pub struct FinalityProof<H: codec::Codec> {
 	pub unknown_headers: Vec<H>,
 }
 
+impl<H> FinalityProof<H>
+where
+	H: Header<Hash = Hash> + codec::Codec,
+{
+	/// Trims the unknown headers to the ancestry of the justified block after `latest_relay_hash`,
+	/// the relay chain block the client was last updated to. The headers the client already knows
+	/// of aren't needed to verify the proof.
+	pub fn trim_unknown_headers(&mut self, latest_relay_hash: Hash) -> Result<(), error::Error> {
+		let ancestry = justification::AncestryChain::<H>::new(&self.unknown_headers);
+		let mut headers = vec![];
+		let mut hash = self.block;
+		while hash != latest_relay_hash {
+			let header = ancestry.header(&hash).ok_or_else(|| {
+				anyhow::anyhow!(
+					"Unknown headers don't link block {:?} to block {latest_relay_hash:?}",
+					self.block
+				)
+			})?;
+			hash = *header.parent_hash();
+			headers.push(header.clone());
+		}
+		headers.reverse();
+		self.unknown_headers = headers;
+		Ok(())
+	}
+
+	/// Returns the number of the highest unknown header such that the headers up to it take at
+	/// most `max_bytes` once encoded, or [`None`] if the first one alone is bigger.
+	pub fn highest_header_within(&self, max_bytes: usize) -> Option<H::Number> {
+		let mut size = 0;
+		let mut highest = None;
+		for header in &self.unknown_headers {
+			size += header.encoded_size();
+			if size > max_bytes {
+				break
+			}
+			highest = Some(*header.number());
+		}
+		highest
+	}
+}
+
 /// Previous light client state.
 #[derive(Clone)]
 pub struct ClientState {
@@ -72,10 +115,12 @@ pub struct ClientState {
 	pub latest_relay_hash: Hash,
 	/// para_id of associated parachain
 	pub para_id: u32,
+	/// Index of the timestamp pallet in the runtime of the parachain, if known
+	pub timestamp_pallet_index: Option<u8>,
 }
 
 /// Holds relavant parachain proofs for both header and timestamp extrinsic.
-#[derive(Clone, Debug, Encode, Decode)]
+#[derive(Clone, Debug, Encode, Decode, TypeInfo)]
 pub struct ParachainHeaderProofs {
 	/// State proofs that prove a parachain header exists at a given relay chain height
 	pub state_proof: Vec<Vec<u8>>,
@@ -103,6 +148,9 @@ pub struct ParachainHeadersWithFinalityProof<H: codec::Codec> {
 pub trait HostFunctions: light_client_common::HostFunctions + 'static {
 	/// RelayChain header type.
 	type Header: Header;
+	/// Parachain header type, used to decode the parachain headers read from the relay chain
+	/// state. Parachains with 64-bit block numbers or another hasher should set it accordingly.
+	type ParachainHeader: Header;
 
 	/// Verify an ed25519 signature
 	fn ed25519_verify(sig: &ed25519::Signature, msg: &[u8], pub_key: &ed25519::Public) -> bool;
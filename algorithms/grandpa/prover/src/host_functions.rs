@@ -31,6 +31,7 @@ impl light_client_common::HostFunctions for HostFunctionsProvider {
 
 impl HostFunctions for HostFunctionsProvider {
 	type Header = sp_runtime::generic::Header<u32, BlakeTwo256>;
+	type ParachainHeader = sp_runtime::generic::Header<u32, BlakeTwo256>;
 
 	fn ed25519_verify(sig: &Signature, msg: &[u8], pubkey: &Public) -> bool {
 		pubkey.verify(&msg, sig)
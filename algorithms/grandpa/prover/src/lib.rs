@@ -199,6 +199,7 @@ where
 			para_id: self.para_id,
 			// we'll set this below
 			latest_para_height: u32::from(finalized_para_header.number()),
+			timestamp_pallet_index: None,
 		})
 	}
 
@@ -253,6 +254,8 @@ where
 	{
 		let mut finality_proof = if let Some(justification) = latest_justification {
 			let justification = GrandpaJustification::<H>::decode(&mut &*justification)?;
+			// the unknown headers must end at the justification target
+			latest_finalized_height = justification.commit.target_number;
 
 			FinalityProof::<H> {
 				block: justification.commit.target_hash,
@@ -428,6 +431,12 @@ where
 		}
 
 		unknown_headers.sort_by_key(|header| header.number());
+		let target = unknown_headers.last().map(|header| H256::from(T::Hash::from(header.hash())));
+		if target != Some(finality_proof.block) {
+			Err(anyhow!(
+				"Unknown headers don't end at the justification target {latest_finalized_height}"
+			))?
+		}
 		// overwrite unknown headers
 		finality_proof.unknown_headers = unknown_headers;
 
@@ -463,6 +472,24 @@ where
 		))
 	}
 
+	/// Returns the id of the authority set in the state of the relay chain at `block`, the set
+	/// finalizing the descendants of the block.
+	pub async fn current_set_id_at(&self, block: u32) -> Result<u64, anyhow::Error> {
+		let block_hash = self
+			.relay_client
+			.rpc()
+			.block_hash(Some(block.into()))
+			.await?
+			.ok_or_else(|| anyhow!("Failed to fetch block hash for block number {}", block))?;
+		let key = T::Storage::grandpa_current_set_id();
+		self.relay_client
+			.storage()
+			.at(block_hash)
+			.fetch(&key)
+			.await?
+			.ok_or_else(|| anyhow!("Failed to fetch current set id at block number {}", block))
+	}
+
 	/// Returns the session length in blocks
 	pub async fn session_length(&self) -> Result<u32, anyhow::Error> {
 		let metadata = self.relay_client.rpc().metadata().await?;
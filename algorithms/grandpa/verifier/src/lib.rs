@@ -26,13 +26,16 @@ use anyhow::anyhow;
 use codec::{Decode, Encode};
 use finality_grandpa::Chain;
 use hash_db::Hasher;
-use light_client_common::state_machine;
+use light_client_common::{state_machine, TimestampCall, MAX_TIMESTAMP_INHERENT_INDEX};
 use primitives::{
 	error,
-	justification::{find_scheduled_change, AncestryChain, GrandpaJustification},
-	parachain_header_storage_key, ClientState, HostFunctions, ParachainHeaderProofs,
+	justification::{
+		find_forced_change, find_scheduled_change, AncestryChain, GrandpaJustification,
+	},
+	parachain_header_storage_key, ClientState, FinalityProof, HostFunctions, ParachainHeaderProofs,
 	ParachainHeadersWithFinalityProof,
 };
+use sp_consensus_grandpa::AuthorityList;
 use sp_core::H256;
 use sp_runtime::traits::Header;
 use sp_trie::{LayoutV0, StorageProof};
@@ -108,8 +111,8 @@ where
 			// seems relay hash isn't in the finalized chain.
 			continue
 		}
-		let relay_chain_header =
-			headers.header(&hash).expect("Headers have been checked by AncestryChain; qed");
+		// the header of the latest relay chain block is known to the client already
+		let Some(relay_chain_header) = headers.header(&hash) else { continue };
 
 		let ParachainHeaderProofs { extrinsic_proof, extrinsic, state_proof } = proofs;
 		let proof = StorageProof::new(state_proof);
@@ -124,18 +127,43 @@ where
 		.remove(key.as_ref())
 		.flatten()
 		.ok_or_else(|| anyhow!("Invalid proof, parachain header not found"))?;
-		let parachain_header = H::decode(&mut &header[..])?;
-		para_heights.push(parachain_header.number().clone().into());
-		// Timestamp extrinsic should be the first inherent and hence the first extrinsic
+		let parachain_header = Host::ParachainHeader::decode(&mut &header[..]).map_err(|e| {
+			anyhow!("Failed to decode header of parachain {}: {e}", client_state.para_id)
+		})?;
+		let para_height: u32 = (*parachain_header.number()).try_into().map_err(|_| {
+			anyhow!(
+				"Parachain height {:?} exceeds the maximum supported height",
+				parachain_header.number()
+			)
+		})?;
+		para_heights.push(para_height);
+		let extrinsics_root = <[u8; 32]>::try_from(parachain_header.extrinsics_root().as_ref())
+			.map(H256::from)
+			.map_err(|_| anyhow!("Parachain extrinsics root must be 32 bytes"))?;
+		// Timestamp extrinsic is one of the inherents, which come first in the block, but it isn't
+		// necessarily the first one. Its index is only trusted past the first extrinsic if the
+		// extrinsic calls the timestamp pallet of the client state.
 		// https://github.com/paritytech/substrate/blob/d602397a0bbb24b5d627795b797259a44a5e29e9/primitives/trie/src/lib.rs#L99-L101
-		let key = codec::Compact(0u64).encode();
+		let mut timestamp_indices = match client_state.timestamp_pallet_index {
+			Some(pallet_index) => {
+				TimestampCall::new(pallet_index).decode(&extrinsic)?;
+				0..MAX_TIMESTAMP_INHERENT_INDEX
+			},
+			None => 0..1,
+		};
 		// verify extrinsic proof for timestamp extrinsic
-		sp_trie::verify_trie_proof::<LayoutV0<Host::BlakeTwo256>, _, _, _>(
-			parachain_header.extrinsics_root(),
-			&extrinsic_proof,
-			&vec![(key, Some(&extrinsic[..]))],
-		)
-		.map_err(|_| anyhow!("Invalid extrinsic proof"))?;
+		let is_valid_extrinsic_proof = timestamp_indices.any(|index| {
+			let key = codec::Compact(index).encode();
+			sp_trie::verify_trie_proof::<LayoutV0<Host::BlakeTwo256>, _, _, _>(
+				&extrinsics_root,
+				&extrinsic_proof,
+				&vec![(key, Some(&extrinsic[..]))],
+			)
+			.is_ok()
+		});
+		if !is_valid_extrinsic_proof {
+			Err(anyhow!("Invalid extrinsic proof"))?
+		}
 	}
 
 	// 4. set new client state, optionally rotating authorities
@@ -154,3 +182,91 @@ where
 
 	Ok(client_state)
 }
+
+/// Authority set change forced by a relay chain header, see [`find_forced_change_signal`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForcedChange {
+	/// Number of the header signalling the change.
+	pub signal_number: u32,
+	/// Number of the block from which the new authorities finalize the relay chain.
+	pub effective_number: u32,
+	/// The new authorities.
+	pub next_authorities: AuthorityList,
+}
+
+/// Finds the first authority set change forced by the relay chain `headers` of an update, above
+/// the `latest_relay_height` known to the client.
+///
+/// Forced changes are used to recover from stalls and take effect without being finalized by the
+/// current authorities, so nothing in an update proves the new authorities were legitimately
+/// installed. Clients are frozen instead when their current authorities finalize the signalling
+/// header, and have to be recovered through governance.
+pub fn find_forced_change_signal<'a, H>(
+	headers: impl IntoIterator<Item = &'a H>,
+	latest_relay_height: u32,
+) -> Option<ForcedChange>
+where
+	H: Header<Hash = H256, Number = u32> + 'a,
+{
+	headers
+		.into_iter()
+		.filter(|header| *header.number() > latest_relay_height)
+		.filter_map(|header| {
+			let (_, change) = find_forced_change(header)?;
+			Some(ForcedChange {
+				signal_number: *header.number(),
+				effective_number: header.number().saturating_add(change.delay),
+				next_authorities: change.next_authorities,
+			})
+		})
+		.min_by_key(|change| change.signal_number)
+}
+
+/// Verifies a standalone GRANDPA finality proof against the given authority set.
+///
+/// This checks that the unknown headers form a chain ending at the finalized block and that the
+/// justification for that block is signed by the authority set, but it doesn't check that the
+/// headers descend from any known block. Useful for validating misbehaviour evidence before
+/// submitting it.
+pub fn verify_grandpa_finality_proof<H, Host>(
+	current_set_id: u64,
+	current_authorities: &AuthorityList,
+	finality_proof: &FinalityProof<H>,
+) -> Result<(), error::Error>
+where
+	H: Header<Hash = H256, Number = u32>,
+	H::Number: finality_grandpa::BlockNumberOps + Into<u32>,
+	Host: HostFunctions,
+{
+	let headers = AncestryChain::<H>::new(&finality_proof.unknown_headers);
+
+	let target = finality_proof
+		.unknown_headers
+		.iter()
+		.max_by_key(|h| *h.number())
+		.ok_or_else(|| anyhow!("Unknown headers can't be empty!"))?;
+
+	if target.hash() != finality_proof.block {
+		Err(anyhow!("Latest finalized block should be highest block in unknown_headers"))?;
+	}
+
+	let base = finality_proof
+		.unknown_headers
+		.iter()
+		.min_by_key(|h| *h.number())
+		.ok_or_else(|| anyhow!("Unknown headers can't be empty!"))?;
+
+	headers.ancestry(base.hash(), target.hash()).map_err(|_| {
+		anyhow!("[verify_grandpa_finality_proof] Invalid ancestry (base -> target block)!")
+	})?;
+
+	let justification = GrandpaJustification::<H>::decode(&mut &finality_proof.justification[..])?;
+
+	if justification.commit.target_hash != finality_proof.block {
+		Err(anyhow!("Justification target hash and finality proof block hash mismatch"))?;
+	}
+
+	justification.verify::<Host>(current_set_id, current_authorities)?;
+
+	Ok(())
+}
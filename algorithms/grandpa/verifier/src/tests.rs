@@ -13,8 +13,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::verify_parachain_headers_with_grandpa_finality_proof;
+use crate::{
+	find_forced_change_signal, verify_grandpa_finality_proof,
+	verify_parachain_headers_with_grandpa_finality_proof,
+};
 use codec::{Decode, Encode};
+use finality_grandpa::{Precommit, SignedPrecommit};
 use futures::StreamExt;
 use grandpa_prover::{
 	beefy_prover::helpers::unsafe_arc_cast, host_functions::HostFunctionsProvider, GrandpaProver,
@@ -22,10 +26,17 @@ use grandpa_prover::{
 use hyperspace_core::substrate::DefaultConfig as PolkadotConfig;
 use polkadot_core_primitives::Header;
 use primitives::{
-	justification::GrandpaJustification, FinalityProof, ParachainHeadersWithFinalityProof,
+	justification::GrandpaJustification, parachain_header_storage_key, ClientState, Commit,
+	FinalityProof, ParachainHeaderProofs, ParachainHeadersWithFinalityProof,
 };
 use serde::{Deserialize, Serialize};
-use sp_core::H256;
+use sp_consensus_grandpa::{
+	AuthorityId, AuthorityList, AuthoritySignature, ConsensusLog, ScheduledChange,
+	GRANDPA_ENGINE_ID,
+};
+use sp_core::{Pair, H256};
+use sp_keyring::ed25519::Keyring;
+use sp_runtime::{traits::Header as _, Digest, DigestItem};
 use std::time::Duration;
 use subxt::{
 	config::substrate::{BlakeTwo256, SubstrateHeader},
@@ -151,3 +162,427 @@ async fn follow_grandpa_justifications() {
 		println!("========= Successfully verified grandpa justification =========");
 	}
 }
+
+const SET_ID: u64 = 1;
+const ROUND: u64 = 1;
+
+fn authority_list(authorities: &[Keyring]) -> AuthorityList {
+	authorities.iter().map(|id| (AuthorityId::from(id.public()), 1)).collect()
+}
+
+/// Builds a chain of `len` relay chain headers on top of `parent` and a justification for the
+/// last one signed by `signers`.
+fn signed_finality_proof(
+	signers: &[Keyring],
+	parent: H256,
+	parent_number: u32,
+	state_root: H256,
+	len: u32,
+) -> FinalityProof<Header> {
+	let digests = vec![Digest::default(); len as usize];
+	signed_finality_proof_with_digests(signers, SET_ID, parent, parent_number, state_root, digests)
+}
+
+/// Builds a chain of relay chain headers with the given `digests` on top of `parent` and a
+/// justification for the last one signed by `signers` as the authority set `set_id`.
+fn signed_finality_proof_with_digests(
+	signers: &[Keyring],
+	set_id: u64,
+	parent: H256,
+	parent_number: u32,
+	state_root: H256,
+	digests: Vec<Digest>,
+) -> FinalityProof<Header> {
+	let mut prev_hash = parent;
+	let mut headers = vec![];
+	for (number, digest) in (parent_number + 1..).zip(digests) {
+		let header = Header {
+			parent_hash: prev_hash,
+			number,
+			state_root,
+			extrinsics_root: Default::default(),
+			digest,
+		};
+		prev_hash = header.hash();
+		headers.push(header);
+	}
+	let target = headers.last().unwrap().clone();
+	let precommit = Precommit { target_hash: target.hash(), target_number: target.number };
+	let message = finality_grandpa::Message::Precommit(precommit.clone());
+	let precommits = signers
+		.iter()
+		.map(|id| {
+			let encoded = sp_consensus_grandpa::localized_payload(ROUND, set_id, &message);
+			SignedPrecommit {
+				precommit: precommit.clone(),
+				signature: AuthoritySignature::from(id.pair().sign(&encoded)),
+				id: AuthorityId::from(id.public()),
+			}
+		})
+		.collect();
+	let commit =
+		Commit::<Header> { target_hash: target.hash(), target_number: target.number, precommits };
+	let justification = Justification { round: ROUND, commit, votes_ancestries: vec![] };
+
+	FinalityProof {
+		block: target.hash(),
+		justification: justification.encode(),
+		unknown_headers: headers,
+	}
+}
+
+#[test]
+fn conflicting_valid_finality_proofs_are_accepted() {
+	let authorities = [Keyring::Alice, Keyring::Bob, Keyring::Charlie];
+	let authority_set = authority_list(&authorities);
+	let parent = H256::repeat_byte(1);
+
+	let first = signed_finality_proof(&authorities, parent, 10, H256::repeat_byte(2), 3);
+	let second = signed_finality_proof(&authorities, parent, 10, H256::repeat_byte(3), 3);
+	assert_ne!(first.block, second.block);
+
+	verify_grandpa_finality_proof::<Header, HostFunctionsProvider>(SET_ID, &authority_set, &first)
+		.expect("first finality proof should be valid");
+	verify_grandpa_finality_proof::<Header, HostFunctionsProvider>(SET_ID, &authority_set, &second)
+		.expect("second finality proof should be valid");
+}
+
+#[test]
+fn finality_proof_signed_by_unknown_authorities_is_rejected() {
+	let authority_set = authority_list(&[Keyring::Alice, Keyring::Bob, Keyring::Charlie]);
+	let proof = signed_finality_proof(
+		&[Keyring::Dave, Keyring::Eve, Keyring::Ferdie],
+		H256::repeat_byte(1),
+		10,
+		H256::repeat_byte(2),
+		3,
+	);
+
+	assert!(verify_grandpa_finality_proof::<Header, HostFunctionsProvider>(
+		SET_ID,
+		&authority_set,
+		&proof
+	)
+	.is_err());
+}
+
+#[test]
+fn finality_proof_with_broken_ancestry_is_rejected() {
+	let authorities = [Keyring::Alice, Keyring::Bob, Keyring::Charlie];
+	let authority_set = authority_list(&authorities);
+	let mut proof =
+		signed_finality_proof(&authorities, H256::repeat_byte(1), 10, H256::repeat_byte(2), 3);
+	// the target header no longer links to the base header
+	proof.unknown_headers.remove(1);
+
+	assert!(verify_grandpa_finality_proof::<Header, HostFunctionsProvider>(
+		SET_ID,
+		&authority_set,
+		&proof
+	)
+	.is_err());
+}
+
+const STALLED_AUTHORITIES: [Keyring; 3] = [Keyring::Alice, Keyring::Bob, Keyring::Charlie];
+const FORCED_AUTHORITIES: [Keyring; 3] = [Keyring::Dave, Keyring::Eve, Keyring::Ferdie];
+
+/// Digest of a relay chain header forcing the [`FORCED_AUTHORITIES`] after `delay` blocks, as
+/// deposited by `pallet_grandpa::schedule_change` with `forced` set.
+fn forced_change_digest(delay: u32) -> Digest {
+	let change = ScheduledChange { next_authorities: authority_list(&FORCED_AUTHORITIES), delay };
+	let log = ConsensusLog::<u32>::ForcedChange(0, change);
+	Digest { logs: vec![DigestItem::Consensus(GRANDPA_ENGINE_ID, log.encode())] }
+}
+
+/// Client of the relay chain stalled at block 10 with the [`STALLED_AUTHORITIES`].
+fn stalled_client_state() -> ClientState {
+	ClientState {
+		current_authorities: authority_list(&STALLED_AUTHORITIES),
+		current_set_id: SET_ID,
+		latest_relay_height: 10,
+		latest_para_height: 0,
+		latest_relay_hash: H256::repeat_byte(1),
+		para_id: 2000,
+		timestamp_pallet_index: None,
+	}
+}
+
+/// Relay chain headers 11..=14 where header 12 forces a change after `delay` blocks, justified by
+/// `signers` as the authority set `set_id`.
+fn forced_change_proof(
+	signers: &[Keyring],
+	set_id: u64,
+	delay: u32,
+) -> ParachainHeadersWithFinalityProof<Header> {
+	let digests =
+		vec![Digest::default(), forced_change_digest(delay), Digest::default(), Digest::default()];
+	let finality_proof = signed_finality_proof_with_digests(
+		signers,
+		set_id,
+		H256::repeat_byte(1),
+		10,
+		H256::repeat_byte(2),
+		digests,
+	);
+	ParachainHeadersWithFinalityProof {
+		finality_proof,
+		parachain_headers: Default::default(),
+		latest_para_height: 0,
+	}
+}
+
+#[test]
+fn forced_authority_set_changes_are_not_enacted() {
+	// the forced authorities can't finalize blocks for the client, which keeps the current set
+	let proof = forced_change_proof(&FORCED_AUTHORITIES, SET_ID + 1, 2);
+	assert!(verify_parachain_headers_with_grandpa_finality_proof::<Header, HostFunctionsProvider>(
+		stalled_client_state(),
+		proof
+	)
+	.is_err());
+
+	let proof = forced_change_proof(&STALLED_AUTHORITIES, SET_ID, 2);
+	let client_state = verify_parachain_headers_with_grandpa_finality_proof::<
+		Header,
+		HostFunctionsProvider,
+	>(stalled_client_state(), proof)
+	.expect("the current authorities should finalize the relay chain");
+	assert_eq!(client_state.current_set_id, SET_ID);
+	assert_eq!(client_state.current_authorities, authority_list(&STALLED_AUTHORITIES));
+}
+
+#[test]
+fn forced_authority_set_changes_are_found() {
+	let digests = vec![Digest::default(), forced_change_digest(3), forced_change_digest(0)];
+	let headers = signed_finality_proof_with_digests(
+		&STALLED_AUTHORITIES,
+		SET_ID,
+		H256::repeat_byte(1),
+		10,
+		H256::repeat_byte(2),
+		digests,
+	)
+	.unknown_headers;
+
+	let forced_change = find_forced_change_signal::<Header>(&headers, 10).unwrap();
+	assert_eq!(forced_change.signal_number, 12);
+	assert_eq!(forced_change.effective_number, 15);
+	assert_eq!(forced_change.next_authorities, authority_list(&FORCED_AUTHORITIES));
+	// the first change is known to the client already
+	assert_eq!(find_forced_change_signal::<Header>(&headers, 12).unwrap().signal_number, 13);
+	assert!(find_forced_change_signal::<Header>(&headers, 13).is_none());
+}
+
+/// Relay chain header 10, which the client was last updated to.
+fn known_relay_header() -> Header {
+	Header {
+		parent_hash: H256::repeat_byte(1),
+		number: 10,
+		state_root: H256::repeat_byte(2),
+		extrinsics_root: Default::default(),
+		digest: Default::default(),
+	}
+}
+
+fn client_state_at(header: &Header) -> ClientState {
+	ClientState {
+		current_authorities: authority_list(&STALLED_AUTHORITIES),
+		current_set_id: SET_ID,
+		latest_relay_height: header.number,
+		latest_para_height: 0,
+		latest_relay_hash: header.hash(),
+		para_id: 2000,
+		timestamp_pallet_index: None,
+	}
+}
+
+fn without_parachain_headers(
+	finality_proof: FinalityProof<Header>,
+) -> ParachainHeadersWithFinalityProof<Header> {
+	ParachainHeadersWithFinalityProof {
+		finality_proof,
+		parachain_headers: Default::default(),
+		latest_para_height: 0,
+	}
+}
+
+/// Index of the timestamp pallet in the runtime of the parachain of the fixtures.
+const TIMESTAMP_PALLET_INDEX: u8 = 3;
+
+/// Encoded unsigned extrinsic calling `pallet_index:call_index` with the encoded `args`.
+fn unsigned_extrinsic(pallet_index: u8, call_index: u8, args: impl Encode) -> Vec<u8> {
+	let mut extrinsic = vec![0b0000_0100, pallet_index, call_index];
+	extrinsic.extend(args.encode());
+	extrinsic.encode()
+}
+
+/// Update from the [`known_relay_header`] to a relay chain block including block 5 of the
+/// parachain, made of `extrinsics`, with the proof of the extrinsic at `timestamp_index` as the
+/// timestamp inherent.
+fn update_with_parachain_block(
+	extrinsics: &[Vec<u8>],
+	timestamp_index: u32,
+) -> ParachainHeadersWithFinalityProof<Header> {
+	use sp_runtime::traits::BlakeTwo256;
+	use sp_trie::{LayoutV0, MemoryDB, TrieDBMutBuilder, TrieMut};
+
+	let mut db = MemoryDB::<BlakeTwo256>::default();
+	let mut extrinsics_root = Default::default();
+	{
+		let mut trie =
+			TrieDBMutBuilder::<LayoutV0<BlakeTwo256>>::new(&mut db, &mut extrinsics_root).build();
+		for (index, extrinsic) in extrinsics.iter().enumerate() {
+			trie.insert(&codec::Compact(index as u32).encode(), extrinsic).unwrap();
+		}
+	}
+	let extrinsic_proof = sp_trie::generate_trie_proof::<LayoutV0<BlakeTwo256>, _, _, _>(
+		&db,
+		extrinsics_root,
+		&[codec::Compact(timestamp_index).encode()],
+	)
+	.unwrap();
+	let parachain_header = sp_runtime::generic::Header::<u32, BlakeTwo256> {
+		parent_hash: Default::default(),
+		number: 5,
+		state_root: Default::default(),
+		extrinsics_root,
+		digest: Default::default(),
+	};
+
+	let mut db = MemoryDB::<BlakeTwo256>::default();
+	let mut state_root = Default::default();
+	{
+		let mut trie =
+			TrieDBMutBuilder::<LayoutV0<BlakeTwo256>>::new(&mut db, &mut state_root).build();
+		trie.insert(
+			parachain_header_storage_key(2000).as_ref(),
+			&parachain_header.encode().encode(),
+		)
+		.unwrap();
+	}
+	let state_proof = db.drain().into_values().map(|(node, _)| node).collect();
+
+	let known = known_relay_header();
+	let finality_proof =
+		signed_finality_proof(&STALLED_AUTHORITIES, known.hash(), 10, state_root, 1);
+	let proofs = ParachainHeaderProofs {
+		state_proof,
+		extrinsic: extrinsics[timestamp_index as usize].clone(),
+		extrinsic_proof,
+	};
+	ParachainHeadersWithFinalityProof {
+		parachain_headers: [(finality_proof.block, proofs)].into(),
+		finality_proof,
+		latest_para_height: 5,
+	}
+}
+
+#[test]
+fn timestamp_inherent_index_is_bound_to_its_pallet() {
+	let timestamp =
+		unsigned_extrinsic(TIMESTAMP_PALLET_INDEX, 0, codec::Compact(1_690_000_000_000u64));
+	// an inherent of another pallet, whose first call also takes a compact integer
+	let other_inherent = unsigned_extrinsic(1, 0, codec::Compact(5u64));
+	let extrinsics = [other_inherent, timestamp];
+	let verify = |client_state, timestamp_index| {
+		verify_parachain_headers_with_grandpa_finality_proof::<Header, HostFunctionsProvider>(
+			client_state,
+			update_with_parachain_block(&extrinsics, timestamp_index),
+		)
+	};
+
+	let client_state = ClientState {
+		timestamp_pallet_index: Some(TIMESTAMP_PALLET_INDEX),
+		..client_state_at(&known_relay_header())
+	};
+	assert_eq!(verify(client_state.clone(), 1).unwrap().latest_para_height, 5);
+	// another inherent can't be passed off as the timestamp inherent
+	assert!(verify(client_state.clone(), 0).is_err());
+
+	// without the pallet, only the first extrinsic is taken for the timestamp inherent
+	let client_state = ClientState { timestamp_pallet_index: None, ..client_state };
+	assert!(verify(client_state.clone(), 1).is_err());
+	assert!(verify(client_state, 0).is_ok());
+}
+
+#[test]
+fn trimmed_finality_proofs_are_verified() {
+	let known = known_relay_header();
+	let mut proof =
+		signed_finality_proof(&STALLED_AUTHORITIES, known.hash(), 10, H256::repeat_byte(2), 4);
+	// the prover starts the unknown headers at the latest relay block of the client
+	proof.unknown_headers.insert(0, known.clone());
+	let untrimmed = verify_parachain_headers_with_grandpa_finality_proof::<
+		Header,
+		HostFunctionsProvider,
+	>(client_state_at(&known), without_parachain_headers(proof.clone()))
+	.unwrap();
+
+	proof.trim_unknown_headers(known.hash()).unwrap();
+	assert_eq!(
+		proof.unknown_headers.iter().map(|h| h.number).collect::<Vec<_>>(),
+		[11, 12, 13, 14]
+	);
+	let trimmed = verify_parachain_headers_with_grandpa_finality_proof::<
+		Header,
+		HostFunctionsProvider,
+	>(client_state_at(&known), without_parachain_headers(proof))
+	.unwrap();
+
+	assert_eq!(trimmed.latest_relay_hash, untrimmed.latest_relay_hash);
+	assert_eq!(trimmed.latest_relay_height, 14);
+}
+
+#[test]
+fn finality_proofs_not_descending_from_the_client_cant_be_trimmed() {
+	let mut proof = signed_finality_proof(
+		&STALLED_AUTHORITIES,
+		H256::repeat_byte(1),
+		10,
+		H256::repeat_byte(2),
+		4,
+	);
+	assert!(proof.trim_unknown_headers(known_relay_header().hash()).is_err());
+}
+
+#[test]
+fn oversized_updates_are_split_into_consecutive_updates() {
+	let known = known_relay_header();
+	let first =
+		signed_finality_proof(&STALLED_AUTHORITIES, known.hash(), 10, H256::repeat_byte(2), 6);
+	let second =
+		signed_finality_proof(&STALLED_AUTHORITIES, first.block, 16, H256::repeat_byte(2), 6);
+	// the update to block 22 the relayer builds at first
+	let update = FinalityProof {
+		block: second.block,
+		justification: second.justification.clone(),
+		unknown_headers: [first.unknown_headers.clone(), second.unknown_headers.clone()].concat(),
+	};
+	let max_update_size = update.unknown_headers[..8].encoded_size();
+	assert!(update.unknown_headers.encoded_size() > max_update_size);
+
+	// the client is advanced to the justified block 16 within the size limit first
+	let highest = update.highest_header_within(max_update_size).unwrap();
+	assert_eq!(highest, 18);
+	let mut intermediate = first;
+	intermediate.trim_unknown_headers(known.hash()).unwrap();
+	assert!(intermediate.unknown_headers.encoded_size() <= max_update_size);
+	let client_state = verify_parachain_headers_with_grandpa_finality_proof::<
+		Header,
+		HostFunctionsProvider,
+	>(client_state_at(&known), without_parachain_headers(intermediate))
+	.unwrap();
+	assert_eq!(client_state.latest_relay_height, 16);
+
+	let mut last = update;
+	last.trim_unknown_headers(client_state.latest_relay_hash).unwrap();
+	assert_eq!(last.unknown_headers.first().unwrap().number, 17);
+	assert!(last.unknown_headers.encoded_size() <= max_update_size);
+	let client_state = verify_parachain_headers_with_grandpa_finality_proof::<
+		Header,
+		HostFunctionsProvider,
+	>(client_state, without_parachain_headers(last))
+	.unwrap();
+	assert_eq!(client_state.latest_relay_height, 22);
+	assert_eq!(client_state.latest_relay_hash, second.block);
+}
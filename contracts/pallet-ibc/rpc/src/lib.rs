@@ -38,7 +38,10 @@ use jsonrpsee::{
 	core::{Error as RpcError, RpcResult as Result},
 	proc_macros::rpc,
 	tracing::log,
-	types::{error::CallError, ErrorObject},
+	types::{
+		error::{CallError, METHOD_NOT_FOUND_CODE},
+		ErrorObject,
+	},
 };
 use pallet_ibc::{
 	events::IbcEvent,
@@ -47,9 +50,9 @@ use pallet_ibc::{
 use sc_chain_spec::Properties;
 use sc_client_api::{BlockBackend, ProofProvider};
 use serde::{Deserialize, Serialize};
-use sp_api::ProvideRuntimeApi;
+use sp_api::{ApiExt, ProvideRuntimeApi};
 use sp_blockchain::HeaderBackend;
-use sp_core::{blake2_256, storage::ChildInfo};
+use sp_core::{blake2_256, storage::ChildInfo, Bytes};
 use sp_runtime::{
 	generic::{BlockId, SignedBlock},
 	traits::{Block as BlockT, Header as HeaderT},
@@ -449,6 +452,12 @@ where
 		&self,
 		block_numbers: Vec<BlockNumberOrHash<Hash>>,
 	) -> Result<HashMap<String, Vec<RawIbcEvent>>>;
+
+	/// Execute messages, given as their type url and value, in order as `deliver` does on the best
+	/// block without changing the state. Returns the error of each failed message, `None` for the
+	/// messages that succeed
+	#[method(name = "ibc_simulateMessages")]
+	fn simulate_messages(&self, messages: Vec<(String, Bytes)>) -> Result<Vec<Option<String>>>;
 }
 
 /// Converts a runtime trap into an RPC error.
@@ -460,6 +469,19 @@ fn runtime_error_into_rpc_error(e: impl std::fmt::Display) -> RpcError {
 	)))
 }
 
+/// Describes an error of the ibc handler recorded by pallet-ibc.
+fn ibc_error_message(error: &IbcError) -> String {
+	let (kind, message) = match error {
+		IbcError::Ics02Client { message } => ("client", message),
+		IbcError::Ics03Connection { message } => ("connection", message),
+		IbcError::Ics04Channel { message } => ("channel", message),
+		IbcError::Ics20FungibleTokenTransfer { message } => ("transfer", message),
+		IbcError::UnknownMessageTypeUrl { message } => ("unknown message type url", message),
+		IbcError::MalformedMessageBytes { message } => ("malformed message", message),
+	};
+	format!("{kind} error: {}", String::from_utf8_lossy(message))
+}
+
 /// An implementation of IBC specific RPC methods.
 pub struct IbcRpcHandler<C, B, AssetId> {
 	client: Arc<C>,
@@ -1766,6 +1788,33 @@ where
 		}
 		Ok(events)
 	}
+
+	fn simulate_messages(&self, messages: Vec<(String, Bytes)>) -> Result<Vec<Option<String>>> {
+		let api = self.client.runtime_api();
+		let at = self.client.info().best_hash;
+		let supported = api
+			.api_version::<dyn IbcRuntimeApi<Block, AssetId>>(at)
+			.map_err(runtime_error_into_rpc_error)?
+			.map_or(false, |version| version >= 2);
+		if !supported {
+			return Err(RpcError::Call(CallError::Custom(ErrorObject::owned(
+				METHOD_NOT_FOUND_CODE,
+				"The runtime doesn't support simulating messages",
+				None::<()>,
+			))))
+		}
+		let messages = messages
+			.into_iter()
+			.map(|(type_url, value)| pallet_ibc::Any { type_url, value: value.0 })
+			.collect();
+		let outcomes = api.simulate_messages(at, messages).map_err(|e| {
+			runtime_error_into_rpc_error(format!("[ibc_rpc]: failed to simulate messages: {e}"))
+		})?;
+		Ok(outcomes
+			.into_iter()
+			.map(|outcome| outcome.err().as_ref().map(ibc_error_message))
+			.collect())
+	}
 }
 
 impl<C, Block, AssetId> IbcRpcHandler<C, Block, AssetId>
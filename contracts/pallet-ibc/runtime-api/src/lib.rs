@@ -7,6 +7,7 @@ use sp_std::vec::Vec;
 
 sp_api::decl_runtime_apis! {
 	/// IBC Runtime Apis
+	#[api_version(2)]
 	pub trait IbcRuntimeApi<AssetId: codec::Codec> {
 		/// Get Ibc child trie prefix
 		fn child_trie_key() -> Vec<u8>;
@@ -78,5 +79,10 @@ sp_api::decl_runtime_apis! {
 		fn denom_traces(key: Option<AssetId>, offset: Option<u32>, limit: u64, count_total: bool) -> QueryDenomTracesResponse;
 
 		fn block_events(extrinsic_index: Option<u32>) -> Vec<Result<pallet_ibc::events::IbcEvent, pallet_ibc::errors::IbcError>>;
+
+		/// Executes the messages in order as `deliver` does without changing the state, returning
+		/// the outcome of each message
+		#[api_version(2)]
+		fn simulate_messages(messages: Vec<pallet_ibc::Any>) -> Vec<Result<(), pallet_ibc::errors::IbcError>>;
 	}
 }
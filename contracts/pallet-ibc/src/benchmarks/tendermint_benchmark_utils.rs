@@ -318,7 +318,10 @@ pub(crate) fn create_mock_grandpa_client_state() -> (
 		latest_para_height: 0,
 		para_id: 2087,
 		current_set_id: 0,
-		current_authorities: vec![],
+		current_authorities: vec![(
+			sp_consensus_grandpa::AuthorityId::from_slice(&[1u8; 32]).unwrap(),
+			1,
+		)],
 		_phantom: Default::default(),
 	};
 
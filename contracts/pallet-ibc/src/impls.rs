@@ -99,6 +99,22 @@ where
 			Self::deposit_event(events.into())
 		};
 	}
+
+	/// Executes `messages` in order as `deliver` does, returning the outcome of each message.
+	/// Meant to be called through the runtime api, whose changes to the state are discarded.
+	pub fn simulate_messages(
+		messages: Vec<crate::Any>,
+	) -> Vec<Result<(), crate::errors::IbcError>> {
+		let mut ctx = Context::<T>::new();
+		messages
+			.into_iter()
+			.map(|msg| {
+				ibc::core::ics26_routing::handler::deliver(&mut ctx, msg.into())
+					.map(|_| ())
+					.map_err(Into::into)
+			})
+			.collect()
+	}
 }
 
 impl<T: Config> Pallet<T>
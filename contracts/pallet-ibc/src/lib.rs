@@ -1113,7 +1113,7 @@ pub mod pallet {
 					)
 				},
 				AnyClientState::Wasm(_) => return Err(Error::<T>::ClientFreezeFailed.into()),
-				#[cfg(test)]
+				#[cfg(any(test, feature = "mocks"))]
 				AnyClientState::Mock(mut ms) => {
 					ms.frozen_height =
 						Some(Height::new(ms.latest_height().revision_number, height));
@@ -157,6 +157,7 @@ const GRANDPA_BLOCK_HASHES_CACHE_SIZE: u32 = 500;
 
 impl grandpa_client_primitives::HostFunctions for HostFunctionsManager {
 	type Header = RelayChainHeader;
+	type ParachainHeader = sp_runtime::generic::Header<u32, BlakeTwo256>;
 
 	fn ed25519_verify(sig: &ed25519::Signature, msg: &[u8], pub_key: &ed25519::Public) -> bool {
 		pub_key.verify(&msg, sig)
@@ -218,7 +219,7 @@ pub enum AnyClient {
 	Beefy(ics11_beefy::client_def::BeefyClient<HostFunctionsManager>),
 	Tendermint(ics07_tendermint::client_def::TendermintClient<HostFunctionsManager>),
 	Wasm(ics08_wasm::client_def::WasmClient<AnyClient, AnyClientState, AnyConsensusState>),
-	#[cfg(test)]
+	#[cfg(any(test, feature = "mocks"))]
 	Mock(ibc::mock::client_def::MockClient),
 }
 
@@ -228,7 +229,7 @@ pub enum AnyUpgradeOptions {
 	Beefy(ics11_beefy::client_state::UpgradeOptions),
 	Tendermint(ics07_tendermint::client_state::UpgradeOptions),
 	Wasm(Box<Self>),
-	#[cfg(test)]
+	#[cfg(any(test, feature = "mocks"))]
 	Mock(()),
 }
 
@@ -242,7 +243,7 @@ pub enum AnyClientState {
 	Tendermint(ics07_tendermint::client_state::ClientState<HostFunctionsManager>),
 	#[ibc(proto_url = "WASM_CLIENT_STATE_TYPE_URL")]
 	Wasm(ics08_wasm::client_state::ClientState<AnyClient, Self, AnyConsensusState>),
-	#[cfg(test)]
+	#[cfg(any(test, feature = "mocks"))]
 	#[ibc(proto_url = "MOCK_CLIENT_STATE_TYPE_URL")]
 	Mock(ibc::mock::client_state::MockClientState),
 }
@@ -302,7 +303,7 @@ pub enum AnyConsensusState {
 	Tendermint(ics07_tendermint::consensus_state::ConsensusState),
 	#[ibc(proto_url = "WASM_CONSENSUS_STATE_TYPE_URL")]
 	Wasm(ics08_wasm::consensus_state::ConsensusState<Self>),
-	#[cfg(test)]
+	#[cfg(any(test, feature = "mocks"))]
 	#[ibc(proto_url = "MOCK_CONSENSUS_STATE_TYPE_URL")]
 	Mock(ibc::mock::client_state::MockConsensusState),
 }
@@ -328,7 +329,7 @@ pub enum AnyClientMessage {
 	Tendermint(ics07_tendermint::client_message::ClientMessage),
 	#[ibc(proto_url = "WASM_CLIENT_MESSAGE_TYPE_URL")]
 	Wasm(ics08_wasm::client_message::ClientMessage<Self>),
-	#[cfg(test)]
+	#[cfg(any(test, feature = "mocks"))]
 	#[ibc(proto_url = "MOCK_CLIENT_MESSAGE_TYPE_URL")]
 	Mock(ibc::mock::header::MockClientMessage),
 }
@@ -354,7 +355,7 @@ impl AnyClientMessage {
 					h.inner.maybe_header_height(),
 				ics08_wasm::client_message::ClientMessage::Misbehaviour(_) => None,
 			},
-			#[cfg(test)]
+			#[cfg(any(test, feature = "mocks"))]
 			Self::Mock(inner) => match inner {
 				ibc::mock::header::MockClientMessage::Header(h) => Some(h.height()),
 				ibc::mock::header::MockClientMessage::Misbehaviour(_) => None,
@@ -404,6 +405,27 @@ impl AnyClientMessage {
 
 impl Protobuf<Any> for AnyClientMessage {}
 
+impl AnyClientMessage {
+	/// Type urls decoded by the [`TryFrom<Any>`] implementation: the client messages, and the
+	/// headers and misbehaviours they wrap.
+	pub const TYPE_URLS: &'static [&'static str] = &[
+		GRANDPA_CLIENT_MESSAGE_TYPE_URL,
+		GRANDPA_HEADER_TYPE_URL,
+		GRANDPA_MISBEHAVIOUR_TYPE_URL,
+		BEEFY_CLIENT_MESSAGE_TYPE_URL,
+		TENDERMINT_CLIENT_MESSAGE_TYPE_URL,
+		TENDERMINT_HEADER_TYPE_URL,
+		TENDERMINT_MISBEHAVIOUR_TYPE_URL,
+		WASM_CLIENT_MESSAGE_TYPE_URL,
+		WASM_HEADER_TYPE_URL,
+		WASM_MISBEHAVIOUR_TYPE_URL,
+		#[cfg(any(test, feature = "mocks"))]
+		ibc::mock::header::MOCK_HEADER_TYPE_URL,
+		#[cfg(any(test, feature = "mocks"))]
+		ibc::mock::misbehaviour::MOCK_MISBEHAVIOUR_TYPE_URL,
+	];
+}
+
 impl TryFrom<Any> for AnyClientMessage {
 	type Error = ics02_client::error::Error;
 
@@ -456,6 +478,18 @@ impl TryFrom<Any> for AnyClientMessage {
 					ics08_wasm::client_message::Misbehaviour::decode_vec(&value.value)
 						.map_err(ics02_client::error::Error::decode_raw_header)?,
 				))),
+			#[cfg(any(test, feature = "mocks"))]
+			ibc::mock::header::MOCK_HEADER_TYPE_URL =>
+				Ok(Self::Mock(ibc::mock::header::MockClientMessage::Header(
+					ibc::mock::header::MockHeader::decode_vec(&value.value)
+						.map_err(ics02_client::error::Error::decode_raw_header)?,
+				))),
+			#[cfg(any(test, feature = "mocks"))]
+			ibc::mock::misbehaviour::MOCK_MISBEHAVIOUR_TYPE_URL =>
+				Ok(Self::Mock(ibc::mock::header::MockClientMessage::Misbehaviour(
+					ibc::mock::misbehaviour::MockMisbehaviour::decode_vec(&value.value)
+						.map_err(ics02_client::error::Error::decode_raw_header)?,
+				))),
 			_ => Err(ics02_client::error::Error::unknown_consensus_state_type(value.type_url)),
 		}
 	}
@@ -493,16 +527,25 @@ impl From<AnyClientMessage> for Any {
 				value: msg.encode_vec().expect("encode_vec failed"),
 			},
 
-			#[cfg(test)]
-			AnyClientMessage::Mock(_msg) => panic!("MockHeader can't be serialized"),
+			#[cfg(any(test, feature = "mocks"))]
+			AnyClientMessage::Mock(msg) => match msg {
+				ibc::mock::header::MockClientMessage::Header(h) => Any {
+					type_url: ibc::mock::header::MOCK_HEADER_TYPE_URL.to_string(),
+					value: h.encode_vec().expect("encode_vec failed"),
+				},
+				ibc::mock::header::MockClientMessage::Misbehaviour(m) => Any {
+					type_url: ibc::mock::misbehaviour::MOCK_MISBEHAVIOUR_TYPE_URL.to_string(),
+					value: m.encode_vec().expect("encode_vec failed"),
+				},
+			},
 		}
 	}
 }
 
-#[cfg(test)]
+#[cfg(any(test, feature = "mocks"))]
 pub use mocks::*;
 
-#[cfg(test)]
+#[cfg(any(test, feature = "mocks"))]
 mod mocks {
 	pub const MOCK_CLIENT_STATE_TYPE_URL: &str = "/ibc.mock.ClientState";
 	pub const MOCK_CLIENT_MESSAGE_TYPE_URL: &str = "/ibc.mock.ClientMessage";
@@ -1,6 +1,6 @@
 use crate::{
 	ics20_fee::FlatFeeConverter,
-	light_clients::{AnyClientState, AnyConsensusState},
+	light_clients::{AnyClientMessage, AnyClientState, AnyConsensusState},
 	mock::*,
 	routing::Context,
 	Any, Config, ConsensusHeights, DenomToAssetId, Event, MultiAddress, Pallet,
@@ -161,6 +161,54 @@ fn initialize_connection() {
 	})
 }
 
+// deliver succeeds when some of its messages fail, the simulation reports each of them
+#[test]
+fn simulated_messages_report_their_own_outcome() {
+	new_test_ext().execute_with(|| {
+		let mock_client_state =
+			MockClientState::new(MockClientMessage::from(MockHeader::default()));
+		let mock_cs_state = MockConsensusState::new(MockHeader::default());
+		let client_id = ClientId::new(&mock_client_state.client_type(), 0).unwrap();
+		let counterparty_client_id = ClientId::new(&mock_client_state.client_type(), 1).unwrap();
+		let create_client = MsgCreateAnyClient::<Context<Test>>::new(
+			AnyClientState::Mock(mock_client_state),
+			AnyConsensusState::Mock(mock_cs_state),
+			Signer::from_str(MODULE_ID).unwrap(),
+		)
+		.unwrap()
+		.encode_vec()
+		.unwrap();
+		let create_client = Any { type_url: TYPE_URL.to_string(), value: create_client };
+		let malformed = Any { type_url: conn_open_init::TYPE_URL.to_string(), value: vec![0xff] };
+		let commitment_prefix: CommitmentPrefix =
+			<Test as Config>::PalletPrefix::get().to_vec().try_into().unwrap();
+		let open_init = conn_open_init::MsgConnectionOpenInit {
+			client_id,
+			counterparty: Counterparty::new(
+				counterparty_client_id,
+				Some(ConnectionId::new(1)),
+				commitment_prefix,
+			),
+			version: Some(ConnVersion::default()),
+			delay_period: Duration::from_nanos(1000),
+			signer: Signer::from_str(MODULE_ID).unwrap(),
+		};
+		let open_init = Any {
+			type_url: conn_open_init::TYPE_URL.to_string(),
+			value: open_init.encode_vec().unwrap(),
+		};
+		let messages = vec![create_client, malformed, open_init];
+
+		// the connection is opened on the client created by the first message
+		let outcomes = Pallet::<Test>::simulate_messages(messages.clone());
+		assert!(outcomes[0].is_ok());
+		assert!(outcomes[1].is_err());
+		assert!(outcomes[2].is_ok());
+
+		assert_ok!(Ibc::deliver(RuntimeOrigin::signed(AccountId32::new([0; 32])), messages));
+	})
+}
+
 // try to initialize a connection below the MinimumConnectionDelay
 #[test]
 fn initialize_connection_with_low_delay() {
@@ -1310,3 +1358,27 @@ fn test_next_and_previous_consensus_state_for_other_client_types() {
 		assert!(ctx.next_consensus_state(&client_id, Height::new(0, 400)).unwrap().is_some());
 	})
 }
+
+#[test]
+fn client_type_urls_are_decoded() {
+	use ibc::core::ics02_client::error::ErrorDetail;
+
+	let is_known = |result: Result<(), ibc::core::ics02_client::error::Error>| match result {
+		Err(e) => !matches!(
+			e.detail(),
+			ErrorDetail::UnknownClientStateType(_) | ErrorDetail::UnknownConsensusStateType(_)
+		),
+		Ok(()) => true,
+	};
+	let any = |type_url: &str| Any { type_url: type_url.to_string(), value: vec![0xff; 4] };
+	for type_url in AnyClientState::TYPE_URLS {
+		assert!(is_known(AnyClientState::try_from(any(type_url)).map(|_| ())), "{type_url}");
+	}
+	for type_url in AnyConsensusState::TYPE_URLS {
+		assert!(is_known(AnyConsensusState::try_from(any(type_url)).map(|_| ())), "{type_url}");
+	}
+	for type_url in AnyClientMessage::TYPE_URLS {
+		assert!(is_known(AnyClientMessage::try_from(any(type_url)).map(|_| ())), "{type_url}");
+	}
+	assert!(!is_known(AnyClientMessage::try_from(any("/ibc.unknown.v1.Header")).map(|_| ())));
+}
@@ -31,5 +31,10 @@ async fn main() -> anyhow::Result<()> {
 		subxt_codegen::build_script(&RELAY_URL, "polkadot").await?;
 		subxt_codegen::build_script(&PARA_URL, "parachain").await?;
 	}
+	#[cfg(feature = "grpc-server")]
+	{
+		tonic_build::compile_protos("src/proto/query.proto")?;
+		tonic_build::compile_protos("src/proto/admin.proto")?;
+	}
 	Ok(())
 }
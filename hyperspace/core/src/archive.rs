@@ -0,0 +1,192 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Archive of the misbehaviour found by the fisherman, kept for audits along with the
+//! conflicting proofs.
+
+use anyhow::anyhow;
+use primitives::{
+	misbehaviour::{DetectedMisbehaviour, MisbehaviourReport},
+	Chain,
+};
+use std::{
+	fs::{self, OpenOptions},
+	io::Write,
+	path::{Path, PathBuf},
+};
+
+/// Name of the archive file in the configured `misbehaviour_archive_dir`.
+pub const MISBEHAVIOUR_ARCHIVE_FILE: &str = "misbehaviour.jsonl";
+
+/// Archive of [`MisbehaviourReport`]s, appended as JSON lines to a file of its directory. A report
+/// is appended when the misbehaviour is found, and again with its `tx_id` once it's submitted.
+#[derive(Debug, Clone)]
+pub struct MisbehaviourArchive {
+	path: PathBuf,
+}
+
+impl MisbehaviourArchive {
+	/// Opens the archive in `dir`, creating the directory if it doesn't exist.
+	pub fn open(dir: impl AsRef<Path>) -> Result<Self, anyhow::Error> {
+		let dir = dir.as_ref();
+		fs::create_dir_all(dir)
+			.map_err(|e| anyhow!("Failed to create the misbehaviour archive {dir:?}: {e}"))?;
+		Ok(Self { path: dir.join(MISBEHAVIOUR_ARCHIVE_FILE) })
+	}
+
+	pub fn record(&self, report: &MisbehaviourReport) -> Result<(), anyhow::Error> {
+		let mut line = serde_json::to_vec(report)?;
+		line.push(b'\n');
+		let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+		file.write_all(&line)?;
+		file.sync_data()?;
+		Ok(())
+	}
+
+	/// Returns the reports of the misbehaviour detected at or after `since`, in seconds since the
+	/// unix epoch, in the order they were found. The submission of a report supersedes the
+	/// report recorded when it was found.
+	pub fn reports_since(&self, since: u64) -> Result<Vec<MisbehaviourReport>, anyhow::Error> {
+		let content = match fs::read_to_string(&self.path) {
+			Ok(content) => content,
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+			Err(e) => return Err(e.into()),
+		};
+		let mut reports = vec![];
+		for (i, line) in content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+			let report = serde_json::from_str::<MisbehaviourReport>(line)
+				.map_err(|e| anyhow!("Failed to decode line {} of {:?}: {e}", i + 1, self.path))?;
+			if report.detected_at < since {
+				continue
+			}
+			match reports.iter_mut().find(|found| same_incident(found, &report)) {
+				Some(found) => *found = report,
+				None => reports.push(report),
+			}
+		}
+		Ok(reports)
+	}
+}
+
+/// Whether both reports are of the same misbehaviour, whatever their submission.
+fn same_incident(a: &MisbehaviourReport, b: &MisbehaviourReport) -> bool {
+	a.chain == b.chain &&
+		a.client_id == b.client_id &&
+		a.detected_at == b.detected_at &&
+		a.first_proof == b.first_proof &&
+		a.second_proof == b.second_proof
+}
+
+/// Records the misbehaviour, if any, in the `archive` before submitting its evidence to the
+/// `counterparty` hosting the client, so that the evidence is kept even if the submission fails.
+pub async fn submit_misbehaviour<C: Chain>(
+	counterparty: &C,
+	archive: Option<&MisbehaviourArchive>,
+	misbehaviour: Option<DetectedMisbehaviour>,
+) {
+	let DetectedMisbehaviour { mut report, message } = match misbehaviour {
+		Some(misbehaviour) => misbehaviour,
+		None => return,
+	};
+	log::warn!(
+		target: "hyperspace",
+		"Found misbehaviour of client {} on {}",
+		report.client_id, report.chain
+	);
+	record_misbehaviour(archive, &report);
+	let Some(message) = message else {
+		log::warn!(
+			target: "hyperspace",
+			"Not submitting the misbehaviour of client {} to {}, the client would reject the evidence: {:?}",
+			report.client_id, report.chain, report.evidence_check
+		);
+		return
+	};
+	match counterparty.submit(vec![message]).await {
+		Ok(tx_id) => {
+			report.tx_id = Some(format!("{tx_id:?}"));
+			log::warn!(
+				target: "hyperspace",
+				"Submitted misbehaviour of client {} to {} in {tx_id:?}",
+				report.client_id, report.chain
+			);
+			record_misbehaviour(archive, &report);
+		},
+		Err(e) => log::error!(
+			target: "hyperspace",
+			"Failed to submit misbehaviour of client {} to {}: {e:?}",
+			report.client_id, report.chain
+		),
+	}
+}
+
+fn record_misbehaviour(archive: Option<&MisbehaviourArchive>, report: &MisbehaviourReport) {
+	if let Some(archive) = archive {
+		if let Err(e) = archive.record(report) {
+			log::error!(target: "hyperspace", "Failed to archive the misbehaviour of client {}: {e}", report.client_id);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ibc::core::ics24_host::identifier::ClientId;
+
+	fn archive(name: &str) -> MisbehaviourArchive {
+		let dir = std::env::temp_dir()
+			.join(format!("hyperspace-misbehaviour-{name}-{}", std::process::id()));
+		let _ = fs::remove_dir_all(&dir);
+		MisbehaviourArchive::open(dir).unwrap()
+	}
+
+	fn report(detected_at: u64) -> MisbehaviourReport {
+		MisbehaviourReport {
+			chain: "cosmos".to_string(),
+			client_id: ClientId::new("08-wasm", 0).unwrap(),
+			first_height: 10,
+			second_height: 12,
+			first_proof: vec![1, 2, 3],
+			second_proof: vec![4, 5, 6],
+			detected_at,
+			tx_id: None,
+			evidence_check: None,
+		}
+	}
+
+	#[test]
+	fn reports_are_read_back_from_the_archive() {
+		let archive = archive("read-back");
+		archive.record(&report(100)).unwrap();
+		archive.record(&report(200)).unwrap();
+		assert_eq!(archive.reports_since(0).unwrap(), vec![report(100), report(200)]);
+		assert_eq!(archive.reports_since(150).unwrap(), vec![report(200)]);
+		assert_eq!(archive.reports_since(201).unwrap(), vec![]);
+	}
+
+	#[test]
+	fn submissions_supersede_found_reports() {
+		let archive = archive("submission");
+		let submitted = MisbehaviourReport { tx_id: Some("0xabcd".to_string()), ..report(100) };
+		archive.record(&report(100)).unwrap();
+		archive.record(&report(200)).unwrap();
+		archive.record(&submitted).unwrap();
+		assert_eq!(archive.reports_since(0).unwrap(), vec![submitted, report(200)]);
+	}
+
+	#[test]
+	fn empty_archives_have_no_reports() {
+		assert_eq!(archive("empty").reports_since(0).unwrap(), vec![]);
+	}
+}
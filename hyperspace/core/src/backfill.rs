@@ -0,0 +1,121 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Relaying of the packets a chain sent before the relayer was started, e.g. when taking over a
+//! channel served by another relayer.
+//!
+//! A client can't be updated to a height older than its latest one, so the backfilled messages
+//! are proven at the consensus states the clients already have: the earliest one at or above
+//! the height of each packet. The client of the source is updated once, to its latest finalized
+//! height, so that the packets sent after its latest consensus state can be proven too. The
+//! connection delay is checked for each proof height, a packet whose delay hasn't passed yet is
+//! left to the relay loop, along with the timeouts.
+//!
+//! A batch that fails to be submitted, e.g. because another relayer delivered one of its packets
+//! meanwhile, is skipped. Its packets are left to the relay loop too, which finds them again with
+//! its packet queries.
+
+use crate::{packets, packets::PROCESS_PACKETS_BATCH_SIZE, queue};
+use anyhow::anyhow;
+use futures::StreamExt;
+use metrics::handler::MetricsHandler;
+use primitives::Chain;
+
+/// Relays the packets sent, and the acknowledgements written, on `source` at or above
+/// `from_height` that `sink` is missing.
+pub async fn backfill<A: Chain, B: Chain>(
+	source: &mut A,
+	sink: &B,
+	from_height: u64,
+	metrics: Option<&MetricsHandler>,
+) -> anyhow::Result<()> {
+	log::info!(target: "hyperspace", "Backfilling the packets of {} from height {from_height} to {}", source.name(), sink.name());
+	update_client(source, sink, metrics).await?;
+
+	let mut backfilled = 0;
+	let mut skipped = 0;
+	let mut previous_msgs = vec![];
+	loop {
+		let (msgs, _) =
+			packets::query_ready_and_timed_out_packets_from(&*source, sink, from_height, metrics)
+				.await?;
+		// the messages that can't be submitted are found again, they're left to the relay loop
+		if msgs.is_empty() || msgs == previous_msgs {
+			break
+		}
+		let total = msgs.len();
+		let mut submitted = 0;
+		skipped = 0;
+		for batch in msgs.chunks(PROCESS_PACKETS_BATCH_SIZE) {
+			if let Err(e) = queue::flush_message_batch(batch.to_vec(), metrics, sink).await {
+				log::error!(
+					target: "hyperspace",
+					"Skipping {} messages from {} to {} that failed to be backfilled, they're left to the relay loop: {e:?}",
+					batch.len(),
+					source.name(),
+					sink.name()
+				);
+				skipped += batch.len();
+				continue
+			}
+			submitted += batch.len();
+			log::info!(
+				target: "hyperspace",
+				"Backfilled {}/{total} messages from {} to {}",
+				submitted + skipped,
+				source.name(),
+				sink.name()
+			);
+		}
+		backfilled += submitted;
+		// the skipped batches would fail again
+		if submitted == 0 {
+			break
+		}
+		previous_msgs = msgs;
+	}
+	log::info!(target: "hyperspace", "Backfilled {backfilled} messages from {} to {}, {skipped} left to the relay loop", source.name(), sink.name());
+	Ok(())
+}
+
+/// Updates the client of `source` on `sink` to the height of the next finality event of
+/// `source`.
+async fn update_client<A: Chain, B: Chain>(
+	source: &mut A,
+	sink: &B,
+	metrics: Option<&MetricsHandler>,
+) -> anyhow::Result<()> {
+	let finality_event = source
+		.finality_notifications()
+		.await
+		.map_err(|e| anyhow!("Failed to subscribe to the finality of {}: {e:?}", source.name()))?
+		.next()
+		.await
+		.ok_or_else(|| anyhow!("Finality stream of {} ended", source.name()))?;
+	let updates = source
+		.query_latest_ibc_events(finality_event, sink)
+		.await
+		.map_err(|e| anyhow!("Failed to fetch IBC events for finality event {e}"))?;
+	let Some(height) = updates.iter().map(|(_, height, ..)| *height).max() else { return Ok(()) };
+	// the latest update may need the mandatory ones before it, e.g. when it was split
+	let msgs = updates
+		.into_iter()
+		.filter(|(_, update_height, _, update_type)| {
+			*update_height == height || !update_type.is_optional()
+		})
+		.map(|(msg_update_client, ..)| msg_update_client)
+		.collect();
+	log::info!(target: "hyperspace", "Updating client {} on {} to {height} for the backfill", source.client_id(), sink.name());
+	queue::flush_message_batch(msgs, metrics, sink).await
+}
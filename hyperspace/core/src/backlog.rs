@@ -0,0 +1,371 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Messages assembled by a relay direction that wait to be submitted to its sink.
+//!
+//! The messages are submitted by their own task, so that assembling them doesn't wait on the
+//! sink. The backlog is bounded: once it's full, the relay loop stops assembling packet messages
+//! until the submissions catch up, instead of letting the backlog grow while the sink is slow.
+//! Nothing is dropped, the packets that weren't assembled are found again by the next packet
+//! queries. Client updates keep being assembled, one batch at a time, so that the client of the
+//! source doesn't expire while the sink is slow.
+//!
+//! A batch stays in flight while it's submitted. If the submission fails, the batch is put back
+//! at the front of the backlog to be submitted again, so that its client updates aren't lost.
+
+use crate::queue::PacketDelivery;
+use ibc::Height;
+use ibc_proto::google::protobuf::Any;
+use std::{
+	collections::{HashSet, VecDeque},
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+use tokio::sync::Notify;
+
+/// Messages assembled from the same finality event, submitted together.
+#[derive(Debug, Clone)]
+pub struct PendingBatch {
+	pub msgs: Vec<Any>,
+	/// Heights of the client updates of the batch, claimed in the submitted updates of the path
+	pub claimed_updates: Vec<Height>,
+	/// Whether the batch was queued past the bound, see [`MessageBacklog::push_urgent`]
+	pub urgent: bool,
+	pub queued_at: Instant,
+	/// Number of failed submissions of the batch, see [`MessageBacklog::requeue`]
+	pub attempts: u32,
+}
+
+#[derive(Debug, Default)]
+struct Batches {
+	pending: VecDeque<PendingBatch>,
+	/// Batch taken by the submitter, until it's submitted or requeued
+	in_flight: Option<PendingBatch>,
+}
+
+/// Bounded queue of the [`PendingBatch`]es of a relay direction, shared by the relay loop and
+/// the task submitting them.
+#[derive(Debug, Clone)]
+pub struct MessageBacklog {
+	batches: Arc<Mutex<Batches>>,
+	/// Number of messages after which the backlog is full
+	capacity: usize,
+	pushed: Arc<Notify>,
+	popped: Arc<Notify>,
+}
+
+impl MessageBacklog {
+	pub fn new(capacity: usize) -> Self {
+		Self {
+			batches: Default::default(),
+			capacity,
+			pushed: Default::default(),
+			popped: Default::default(),
+		}
+	}
+
+	/// Number of messages waiting to be submitted.
+	pub fn depth(&self) -> usize {
+		self.batches.lock().unwrap().pending.iter().map(|batch| batch.msgs.len()).sum()
+	}
+
+	/// Whether no new packet messages should be assembled until some of the pending ones are
+	/// submitted.
+	pub fn is_full(&self) -> bool {
+		self.depth() >= self.capacity
+	}
+
+	/// Whether an urgent batch is waiting to be submitted.
+	pub fn has_urgent(&self) -> bool {
+		self.batches.lock().unwrap().pending.iter().any(|batch| batch.urgent)
+	}
+
+	/// How long the oldest pending batch has been waiting at `now`.
+	pub fn oldest_age(&self, now: Instant) -> Option<Duration> {
+		let batches = self.batches.lock().unwrap();
+		batches
+			.pending
+			.iter()
+			.map(|batch| now.saturating_duration_since(batch.queued_at))
+			.max()
+	}
+
+	/// Queues a batch after the pending ones. Packet messages for packets that already have a
+	/// pending or in flight message are left out, as they are delivered by that one. The batch is
+	/// queued even if it doesn't fit, the bound is enforced by not assembling batches while the
+	/// backlog is full.
+	pub fn push(&self, msgs: Vec<Any>, claimed_updates: Vec<Height>, now: Instant) {
+		self.queue(msgs, claimed_updates, false, now)
+	}
+
+	/// Queues a batch of client updates ahead of the pending batches, assembled while the
+	/// backlog is full so that the client doesn't expire.
+	pub fn push_urgent(&self, msgs: Vec<Any>, claimed_updates: Vec<Height>, now: Instant) {
+		self.queue(msgs, claimed_updates, true, now)
+	}
+
+	fn queue(&self, mut msgs: Vec<Any>, claimed_updates: Vec<Height>, urgent: bool, now: Instant) {
+		let mut batches = self.batches.lock().unwrap();
+		let pending = batches
+			.pending
+			.iter()
+			.chain(batches.in_flight.iter())
+			.flat_map(|batch| batch.msgs.iter())
+			.filter_map(PacketDelivery::from_msg)
+			.collect::<HashSet<_>>();
+		if !pending.is_empty() {
+			msgs.retain(|msg| {
+				PacketDelivery::from_msg(msg).map_or(true, |delivery| !pending.contains(&delivery))
+			});
+		}
+		if msgs.is_empty() {
+			return
+		}
+		let batch = PendingBatch { msgs, claimed_updates, urgent, queued_at: now, attempts: 0 };
+		if urgent {
+			batches.pending.push_front(batch);
+		} else {
+			batches.pending.push_back(batch);
+		}
+		drop(batches);
+		self.pushed.notify_one();
+	}
+
+	/// Waits for the next pending batch and takes it from the backlog. The batch is in flight
+	/// until it's either [`finish`](Self::finish)ed or [`requeue`](Self::requeue)d.
+	pub async fn pop(&self) -> PendingBatch {
+		loop {
+			let batch = {
+				let mut batches = self.batches.lock().unwrap();
+				let batch = batches.pending.pop_front();
+				batches.in_flight = batch.clone();
+				batch
+			};
+			if let Some(batch) = batch {
+				self.popped.notify_one();
+				return batch
+			}
+			self.pushed.notified().await;
+		}
+	}
+
+	/// Drops the batch in flight, once it's submitted or given up on.
+	pub fn finish(&self) {
+		self.batches.lock().unwrap().in_flight = None;
+	}
+
+	/// Puts the batch in flight back at the front of the backlog after a failed submission, with
+	/// `msgs` left to submit, to be taken again by the next [`pop`](Self::pop). A batch left
+	/// without messages is dropped.
+	pub fn requeue(&self, msgs: Vec<Any>) {
+		let mut batches = self.batches.lock().unwrap();
+		let Some(mut batch) = batches.in_flight.take() else { return };
+		if msgs.is_empty() {
+			return
+		}
+		batch.msgs = msgs;
+		batch.attempts += 1;
+		batches.pending.push_front(batch);
+		drop(batches);
+		self.pushed.notify_one();
+	}
+
+	/// Waits until a batch is taken from the backlog.
+	pub async fn popped(&self) {
+		self.popped.notified().await
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ibc::{
+		core::{
+			ics04_channel::{msgs::recv_packet::MsgRecvPacket, packet::Packet},
+			ics23_commitment::commitment::CommitmentProofBytes,
+			ics24_host::identifier::{ChannelId, PortId},
+		},
+		proofs::Proofs,
+		signer::Signer,
+		tx_msg::Msg,
+	};
+	use std::str::FromStr;
+	use tendermint_proto::Protobuf;
+
+	fn recv(sequence: u64) -> Any {
+		let proof = CommitmentProofBytes::try_from(vec![1u8]).unwrap();
+		let msg = MsgRecvPacket {
+			packet: Packet {
+				sequence: sequence.into(),
+				source_port: PortId::transfer(),
+				source_channel: ChannelId::new(0),
+				destination_port: PortId::transfer(),
+				destination_channel: ChannelId::new(1),
+				..Default::default()
+			},
+			proofs: Proofs::new(proof, None, None, None, Height::new(1, 10)).unwrap(),
+			signer: Signer::from_str("relayer").unwrap(),
+		};
+		Any { value: msg.encode_vec().unwrap(), type_url: msg.type_url() }
+	}
+
+	fn update_client() -> Any {
+		Any { type_url: "/ibc.core.client.v1.MsgUpdateClient".to_string(), value: vec![] }
+	}
+
+	fn sequences(msgs: &[Any]) -> Vec<u64> {
+		msgs.iter()
+			.filter_map(|msg| match PacketDelivery::from_msg(msg)? {
+				PacketDelivery::Recv { sequence, .. } => Some(sequence),
+				PacketDelivery::Commitment { sequence, .. } => Some(sequence),
+			})
+			.collect()
+	}
+
+	#[test]
+	fn pending_packets_are_not_queued_twice() {
+		let backlog = MessageBacklog::new(10);
+		let now = Instant::now();
+		backlog.push(vec![update_client(), recv(1), recv(2)], vec![], now);
+		// the packets are queried again before the first batch is submitted
+		backlog.push(vec![update_client(), recv(2), recv(3)], vec![], now);
+		backlog.push(vec![recv(1)], vec![], now);
+		assert_eq!(backlog.depth(), 5);
+
+		let first = futures::executor::block_on(backlog.pop());
+		assert_eq!(sequences(&first.msgs), vec![1, 2]);
+		let second = futures::executor::block_on(backlog.pop());
+		assert_eq!(sequences(&second.msgs), vec![3]);
+		assert_eq!(backlog.depth(), 0);
+	}
+
+	#[test]
+	fn urgent_batches_bypass_the_bound() {
+		let backlog = MessageBacklog::new(2);
+		let start = Instant::now();
+		backlog.push(vec![recv(1), recv(2)], vec![], start);
+		assert!(backlog.is_full());
+		assert!(!backlog.has_urgent());
+
+		let height = Height::new(0, 10);
+		backlog.push_urgent(vec![update_client()], vec![height], start + Duration::from_secs(5));
+		assert!(backlog.has_urgent());
+		assert_eq!(backlog.depth(), 3);
+		assert_eq!(
+			backlog.oldest_age(start + Duration::from_secs(8)),
+			Some(Duration::from_secs(8))
+		);
+
+		// the client update is submitted first
+		let batch = futures::executor::block_on(backlog.pop());
+		assert!(batch.urgent);
+		assert_eq!(batch.claimed_updates, vec![height]);
+		assert!(backlog.is_full());
+		futures::executor::block_on(backlog.pop());
+		assert!(!backlog.is_full());
+		assert_eq!(backlog.oldest_age(start), None);
+	}
+
+	#[test]
+	fn in_flight_packets_are_not_queued_twice() {
+		let backlog = MessageBacklog::new(10);
+		let now = Instant::now();
+		backlog.push(vec![update_client(), recv(1), recv(2)], vec![], now);
+		let batch = futures::executor::block_on(backlog.pop());
+		assert_eq!(backlog.depth(), 0);
+		// queried again while the first batch is submitted
+		backlog.push(vec![recv(2), recv(3)], vec![], now);
+		assert_eq!(backlog.depth(), 1);
+		backlog.finish();
+		backlog.push(vec![recv(2)], vec![], now);
+		assert_eq!(backlog.depth(), 2);
+		assert_eq!(sequences(&batch.msgs), vec![1, 2]);
+	}
+
+	#[test]
+	fn failed_batches_are_requeued_first() {
+		let backlog = MessageBacklog::new(10);
+		let start = Instant::now();
+		let height = Height::new(0, 10);
+		backlog.push(vec![update_client(), recv(1), recv(2)], vec![height], start);
+		backlog.push(vec![recv(3)], vec![], start + Duration::from_secs(1));
+
+		let batch = futures::executor::block_on(backlog.pop());
+		assert_eq!(batch.attempts, 0);
+		// packet 1 was delivered by the failed submission
+		backlog.requeue(vec![update_client(), recv(2)]);
+		assert_eq!(backlog.depth(), 3);
+		assert_eq!(backlog.oldest_age(start), Some(Duration::ZERO));
+
+		let batch = futures::executor::block_on(backlog.pop());
+		assert_eq!(batch.attempts, 1);
+		assert_eq!(batch.claimed_updates, vec![height]);
+		assert_eq!(batch.msgs[0], update_client());
+		assert_eq!(sequences(&batch.msgs), vec![2]);
+		backlog.finish();
+		// nothing to requeue once the batch is given up on
+		backlog.requeue(vec![update_client()]);
+		let batch = futures::executor::block_on(backlog.pop());
+		assert_eq!(sequences(&batch.msgs), vec![3]);
+		assert_eq!(backlog.depth(), 0);
+	}
+
+	/// A sink whose submissions are slower than the assembly of the messages, which stops once
+	/// the backlog is full and resumes as the sink catches up.
+	#[tokio::test]
+	async fn backlog_stays_bounded_while_the_sink_is_slow() {
+		const CAPACITY: usize = 20;
+		const BATCH_SIZE: u64 = 5;
+		const PACKETS: u64 = 200;
+		let backlog = MessageBacklog::new(CAPACITY);
+
+		let submitter = {
+			let backlog = backlog.clone();
+			tokio::spawn(async move {
+				let mut delivered = vec![];
+				while delivered.len() < PACKETS as usize {
+					let batch = backlog.pop().await;
+					// slow submission, recovering halfway
+					let delay = if delivered.len() < PACKETS as usize / 2 { 5 } else { 0 };
+					tokio::time::sleep(Duration::from_millis(delay)).await;
+					delivered.extend(sequences(&batch.msgs));
+					backlog.finish();
+				}
+				delivered
+			})
+		};
+
+		let mut max_depth = 0;
+		let mut next_sequence = 1;
+		while next_sequence <= PACKETS {
+			if backlog.is_full() {
+				backlog.popped().await;
+				continue
+			}
+			let msgs = (next_sequence..next_sequence + BATCH_SIZE).map(recv).collect();
+			backlog.push(msgs, vec![], Instant::now());
+			next_sequence += BATCH_SIZE;
+			max_depth = max_depth.max(backlog.depth());
+			tokio::task::yield_now().await;
+		}
+
+		let delivered = tokio::time::timeout(Duration::from_secs(30), submitter)
+			.await
+			.expect("the submitter didn't catch up")
+			.unwrap();
+		// a batch is only assembled while the backlog has room
+		assert!(max_depth < CAPACITY + BATCH_SIZE as usize, "{max_depth} pending messages");
+		assert_eq!(delivered, (1..=PACKETS).collect::<Vec<_>>());
+	}
+}
@@ -16,10 +16,14 @@
 
 use crate::{
 	chains,
+	forwarding::ForwardingConfig,
 	substrate::{
 		default::DefaultConfig, ComposableConfig, PicassoKusamaConfig, PicassoRococoConfig,
 	},
+	telemetry::TelemetryConfig,
+	validation::ConfigError,
 };
+use anyhow::anyhow;
 use async_trait::async_trait;
 #[cfg(feature = "cosmos")]
 use cosmos::client::{CosmosClient, CosmosClientConfig};
@@ -31,12 +35,13 @@ use ibc::{
 	core::{
 		ics02_client::{
 			client_state::ClientType,
-			events::{CodeId, UpdateClient},
+			events::UpdateClient,
 			msgs::{create_client::MsgCreateAnyClient, update_client::MsgUpdateAnyClient},
 		},
 		ics03_connection::msgs::{
 			conn_open_ack::MsgConnectionOpenAck, conn_open_try::MsgConnectionOpenTry,
 		},
+		ics04_channel::channel::IdentifiedChannelEnd,
 		ics23_commitment::commitment::CommitmentPrefix,
 		ics24_host::identifier::{ChannelId, ClientId, ConnectionId, PortId},
 	},
@@ -47,6 +52,8 @@ use ibc::{
 	tx_msg::Msg,
 	Height,
 };
+#[cfg(any(test, feature = "testing"))]
+use ibc_proto::ibc::applications::{fee::v1::Fee, transfer::v1::DenomTrace};
 use ibc_proto::{
 	google::protobuf::Any,
 	ibc::core::{
@@ -59,17 +66,22 @@ use ibc_proto::{
 		connection::v1::{IdentifiedConnection, QueryConnectionResponse},
 	},
 };
-use ics08_wasm::Bytes;
 use pallet_ibc::light_clients::{AnyClientMessage, AnyClientState, AnyConsensusState};
 #[cfg(any(test, feature = "testing"))]
 use pallet_ibc::Timeout;
 use parachain::{ParachainClient, ParachainClientConfig};
 use primitives::{
-	mock::LocalClientTypes, Chain, CommonClientState, IbcProvider, KeyProvider, LightClientSync,
-	MisbehaviourHandler, UpdateType,
+	any_registry::known_messages,
+	misbehaviour::DetectedMisbehaviour,
+	mock::LocalClientTypes,
+	relayed_connection_ids,
+	signer::{KeyRotation, KeySource},
+	Chain, ClientStatus, CommonClientState, ConnectionConfig, ErrorKind, HasErrorKind, IbcProvider,
+	KeyProvider, LightClientSync, MisbehaviourHandler, SimulationResult, UpdateType,
+	WasmUploadStatus,
 };
 use serde::{Deserialize, Serialize};
-use std::{pin::Pin, time::Duration};
+use std::{fmt, pin::Pin, str::FromStr, time::Duration};
 use tendermint_proto::Protobuf;
 use thiserror::Error;
 
@@ -80,16 +92,44 @@ pub struct Config {
 	pub core: CoreConfig,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct CoreConfig {
 	pub prometheus_endpoint: Option<String>,
+	/// Path of the database persisting the packet bookkeeping across restarts
+	#[serde(default)]
+	pub state_db_path: Option<String>,
+	/// Forwarding of the transfers sent from chain A over more than one path
+	#[serde(default)]
+	pub forwarding: Option<ForwardingConfig>,
+	/// Export of the spans of the relayed packets over OTLP, with the `telemetry` feature
+	#[serde(default)]
+	pub telemetry: Option<TelemetryConfig>,
+	/// Directory archiving the misbehaviour submitted by the fisherman
+	#[serde(default)]
+	pub misbehaviour_archive_dir: Option<String>,
+	/// Address of the gRPC server answering queries about the relayed path, with the
+	/// `grpc-server` feature
+	#[serde(default)]
+	pub grpc_endpoint: Option<String>,
+	/// Serves the admin service, e.g. the rotation of the relayer keys, on the `grpc_endpoint`,
+	/// which has to be a loopback address
+	#[serde(default)]
+	pub grpc_admin: bool,
+	/// Directory of the key files the admin service can rotate the keys to
+	#[serde(default)]
+	pub grpc_admin_key_dir: Option<String>,
+	/// Height of chain A from which its packets and acknowledgements missing on chain B are
+	/// relayed before the relay loop starts
+	#[serde(default)]
+	pub from_height_a: Option<u64>,
+	/// Height of chain B from which its packets and acknowledgements missing on chain A are
+	/// relayed before the relay loop starts
+	#[serde(default)]
+	pub from_height_b: Option<u64>,
 }
 
-impl From<String> for AnyError {
-	fn from(s: String) -> Self {
-		Self::Other(s)
-	}
-}
+/// How long [`AnyConfig::into_live_client`] waits for the chain to produce a block.
+pub const CLIENT_READY_TIMEOUT: Duration = Duration::from_secs(120);
 
 chains! {
 	Parachain(ParachainClientConfig, ParachainClient<DefaultConfig>),
@@ -101,7 +141,49 @@ chains! {
 	Cosmos(CosmosClientConfig, CosmosClient<DefaultConfig>),
 }
 
-fn wrap_any_msg_into_wasm(msg: Any, code_id: Bytes) -> Result<Any, anyhow::Error> {
+/// Number of bytes of the payload of a message that failed to be wrapped shown in its error.
+const WASM_PAYLOAD_PREFIX_LEN: usize = 32;
+
+/// Wraps the batch `msgs` for the 08-wasm clients. A message that fails to be wrapped fails the
+/// whole batch, unless `skip_invalid`, in which case it's dropped with a warning.
+fn wrap_msgs_into_wasm(
+	msgs: Vec<Any>,
+	code_id: &CodeId,
+	skip_invalid: bool,
+) -> Result<Vec<Any>, AnyError> {
+	let mut wrapped = Vec::with_capacity(msgs.len());
+	for msg in msgs {
+		match wrap_any_msg_into_wasm(msg, code_id) {
+			Ok(msg) => wrapped.push(msg),
+			Err(e) if skip_invalid => {
+				log::warn!(target: "hyperspace", "Skipping a message of the batch: {e}")
+			},
+			Err(e) => return Err(e),
+		}
+	}
+	Ok(wrapped)
+}
+
+fn wrap_any_msg_into_wasm(msg: Any, code_id: &CodeId) -> Result<Any, AnyError> {
+	match wrap_known_msg_into_wasm(&msg, code_id) {
+		Ok(Some(wrapped)) => Ok(wrapped),
+		Ok(None) => Ok(msg),
+		Err(e) => {
+			let prefix = &msg.value[..msg.value.len().min(WASM_PAYLOAD_PREFIX_LEN)];
+			let ellipsis = if prefix.len() < msg.value.len() { "..." } else { "" };
+			Err(anyhow!(
+				"Failed to wrap {} into a message to the 08-wasm client (payload 0x{}{ellipsis}): {e}",
+				msg.type_url,
+				hex::encode(prefix),
+			)
+			.into())
+		},
+	}
+}
+
+/// Wraps `msg` if it carries client states, consensus states or client messages, `None` for
+/// the messages passed through as is.
+fn wrap_known_msg_into_wasm(msg: &Any, code_id: &CodeId) -> Result<Option<Any>, anyhow::Error> {
 	// TODO: consider rewriting with Ics26Envelope
 	use ibc::core::{
 		ics02_client::msgs::{
@@ -116,36 +198,221 @@ fn wrap_any_msg_into_wasm(msg: Any, code_id: Bytes) -> Result<Any, anyhow::Error
 
 	let msg = match msg.type_url.as_str() {
 		CREATE_CLIENT_TYPE_URL => {
-			let mut msg_decoded =
-				MsgCreateAnyClient::<LocalClientTypes>::decode_vec(&msg.value).unwrap();
+			let mut msg_decoded = MsgCreateAnyClient::<LocalClientTypes>::decode_vec(&msg.value)?;
 			msg_decoded.consensus_state = AnyConsensusState::wasm(msg_decoded.consensus_state)?;
-			msg_decoded.client_state = AnyClientState::wasm(msg_decoded.client_state, code_id)?;
+			msg_decoded.client_state =
+				AnyClientState::wasm(msg_decoded.client_state, code_id.to_vec())?;
 			msg_decoded.to_any()
 		},
 		CONN_OPEN_TRY_TYPE_URL => {
-			let msg_decoded =
-				MsgConnectionOpenTry::<LocalClientTypes>::decode_vec(&msg.value).unwrap();
+			let msg_decoded = MsgConnectionOpenTry::<LocalClientTypes>::decode_vec(&msg.value)?;
 			msg_decoded.to_any()
 		},
 		CONN_OPEN_ACK_TYPE_URL => {
-			let msg_decoded =
-				MsgConnectionOpenAck::<LocalClientTypes>::decode_vec(&msg.value).unwrap();
+			let msg_decoded = MsgConnectionOpenAck::<LocalClientTypes>::decode_vec(&msg.value)?;
 			msg_decoded.to_any()
 		},
 		UPDATE_CLIENT_TYPE_URL => {
-			let mut msg_decoded =
-				MsgUpdateAnyClient::<LocalClientTypes>::decode_vec(&msg.value).unwrap();
+			let mut msg_decoded = MsgUpdateAnyClient::<LocalClientTypes>::decode_vec(&msg.value)?;
 			msg_decoded.client_message = AnyClientMessage::wasm(msg_decoded.client_message)?;
 
 			msg_decoded.to_any()
 		},
-		_ => msg,
+		// passed through as is, but unknown messages may need to be wrapped
+		_ => {
+			known_messages().check_known(msg, "the messages to the 08-wasm client");
+			return Ok(None)
+		},
 	};
-	Ok(msg)
+	Ok(Some(msg))
 }
 
 #[derive(Clone)]
 pub struct WasmChain {
 	pub inner: Box<AnyChain>,
-	pub code_id: Bytes,
+	pub code_id: CodeId,
+}
+
+/// Length of the checksums used as code ids by the 08-wasm module.
+pub const WASM_CODE_ID_LENGTH: usize = 32;
+
+/// Checksum of the code of the 08-wasm clients, parsed from its hex encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodeId([u8; WASM_CODE_ID_LENGTH]);
+
+impl CodeId {
+	pub fn as_bytes(&self) -> &[u8] {
+		&self.0
+	}
+
+	pub fn to_vec(&self) -> Vec<u8> {
+		self.0.to_vec()
+	}
+}
+
+impl FromStr for CodeId {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let bytes = hex::decode(s).map_err(|e| format!("{s:?} isn't valid hex: {e}"))?;
+		Self::try_from(bytes.as_slice())
+	}
+}
+
+impl TryFrom<&[u8]> for CodeId {
+	type Error = String;
+
+	fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+		bytes
+			.try_into()
+			.map(Self)
+			.map_err(|_| format!("is {} bytes long instead of {WASM_CODE_ID_LENGTH}", bytes.len()))
+	}
+}
+
+impl fmt::Display for CodeId {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", hex::encode(self.0))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ibc::core::ics02_client::msgs::{
+		create_client::TYPE_URL as CREATE_CLIENT_TYPE_URL,
+		update_client::TYPE_URL as UPDATE_CLIENT_TYPE_URL,
+	};
+	use pallet_ibc::light_clients::HostFunctionsManager;
+
+	const CODE_ID: &str = "cfd2199578332b5fd859f3b76cb0b29757c6b52c5df79566cdc3598039dbe43e";
+
+	fn grandpa_client_state() -> AnyClientState {
+		AnyClientState::Grandpa(ics10_grandpa::client_state::ClientState::<HostFunctionsManager> {
+			latest_relay_height: 20,
+			latest_para_height: 15,
+			para_id: 2000,
+			current_authorities: vec![(sp_core::ed25519::Public::from_raw([1; 32]).into(), 1)],
+			..Default::default()
+		})
+	}
+
+	fn grandpa_consensus_state() -> AnyConsensusState {
+		AnyConsensusState::Grandpa(ics10_grandpa::consensus_state::ConsensusState {
+			timestamp: tendermint_proto::google::protobuf::Timestamp {
+				seconds: 1_700_000_000,
+				nanos: 0,
+			}
+			.try_into()
+			.unwrap(),
+			root: vec![1; 32].into(),
+		})
+	}
+
+	#[test]
+	fn created_wasm_clients_carry_the_inner_latest_height() {
+		let code_id = CODE_ID.parse::<CodeId>().unwrap();
+		let msg = MsgCreateAnyClient::<LocalClientTypes> {
+			client_state: grandpa_client_state(),
+			consensus_state: grandpa_consensus_state(),
+			signer: Signer::from_str("relayer").unwrap(),
+		};
+
+		let wrapped = wrap_any_msg_into_wasm(msg.to_any(), &code_id).unwrap();
+		assert_eq!(wrapped.type_url, CREATE_CLIENT_TYPE_URL);
+		let decoded = MsgCreateAnyClient::<LocalClientTypes>::decode_vec(&wrapped.value).unwrap();
+		let AnyClientState::Wasm(client_state) = decoded.client_state else {
+			panic!("expected a wasm client state, got {:?}", decoded.client_state)
+		};
+		assert_eq!(client_state.latest_height, Height::new(2000, 15));
+		assert_eq!(client_state.code_id, code_id.to_vec());
+		assert_eq!(*client_state.inner, grandpa_client_state());
+		let AnyConsensusState::Wasm(consensus_state) = decoded.consensus_state else {
+			panic!("expected a wasm consensus state, got {:?}", decoded.consensus_state)
+		};
+		assert_eq!(*consensus_state.inner, grandpa_consensus_state());
+	}
+
+	#[test]
+	fn undecodable_messages_are_rejected() {
+		let code_id = CODE_ID.parse::<CodeId>().unwrap();
+		let msg = Any { type_url: CREATE_CLIENT_TYPE_URL.to_string(), value: vec![0xff; 4] };
+		assert!(wrap_any_msg_into_wasm(msg, &code_id).is_err());
+	}
+
+	fn corrupted_update() -> Any {
+		Any { type_url: UPDATE_CLIENT_TYPE_URL.to_string(), value: vec![0xff; 40] }
+	}
+
+	fn transfer() -> Any {
+		Any { type_url: "/ibc.applications.transfer.v1.MsgTransfer".to_string(), value: vec![1] }
+	}
+
+	#[test]
+	fn wrapping_errors_name_the_message_and_its_payload() {
+		let code_id = CODE_ID.parse::<CodeId>().unwrap();
+		let error = wrap_any_msg_into_wasm(corrupted_update(), &code_id).unwrap_err().to_string();
+		assert!(error.contains(UPDATE_CLIENT_TYPE_URL), "{error}");
+		assert!(
+			error.contains(&format!("0x{}...", "ff".repeat(WASM_PAYLOAD_PREFIX_LEN))),
+			"{error}"
+		);
+	}
+
+	#[test]
+	fn invalid_wasm_messages_fail_their_batch() {
+		let code_id = CODE_ID.parse::<CodeId>().unwrap();
+		let msgs = vec![transfer(), corrupted_update(), transfer()];
+		let error = wrap_msgs_into_wasm(msgs, &code_id, false).unwrap_err().to_string();
+		assert!(error.contains(UPDATE_CLIENT_TYPE_URL), "{error}");
+	}
+
+	#[test]
+	fn invalid_wasm_messages_can_be_skipped() {
+		let code_id = CODE_ID.parse::<CodeId>().unwrap();
+		let msgs = vec![transfer(), corrupted_update(), transfer()];
+		let wrapped = wrap_msgs_into_wasm(msgs, &code_id, true).unwrap();
+		assert_eq!(wrapped, vec![transfer(), transfer()]);
+	}
+
+	#[test]
+	fn code_ids_are_hex_encoded_checksums() {
+		let code_id = CODE_ID.parse::<CodeId>().unwrap();
+		assert_eq!(code_id.to_string(), CODE_ID);
+		assert_eq!(code_id.as_bytes().len(), WASM_CODE_ID_LENGTH);
+
+		let error = "cfd21995".parse::<CodeId>().unwrap_err();
+		assert_eq!(error, "is 4 bytes long instead of 32");
+		let error = "not hex".parse::<CodeId>().unwrap_err();
+		assert!(error.contains("isn't valid hex"), "{error}");
+	}
+
+	#[test]
+	fn errors_keep_the_kind_of_their_chain() {
+		let error = AnyError::Parachain(parachain::error::Error::PalletNotFound("Ibc"));
+		assert_eq!(error.kind(), ErrorKind::Unsupported);
+		let error = AnyError::from(anyhow!("Invalid finality event type"));
+		assert_eq!(error.kind(), ErrorKind::Execution);
+		// a chain error passed through `anyhow` is still classified by its type
+		let error = AnyError::from(
+			anyhow::Error::from(parachain::error::Error::NotFound("Header 12".to_string()))
+				.context("Failed to query the finalized header"),
+		);
+		assert_eq!(error.kind(), ErrorKind::NotFound);
+	}
+
+	#[cfg(feature = "cosmos")]
+	#[test]
+	fn cosmos_errors_keep_their_kind() {
+		let error = AnyError::Cosmos(cosmos::error::Error::TxFailed {
+			hash: "ABCD".to_string(),
+			codespace: cosmos::error::SDK_CODESPACE.to_string(),
+			code: 20,
+			log: "mempool is full".to_string(),
+		});
+		assert_eq!(error.kind(), ErrorKind::Transport);
+		// the message alone doesn't make an error transient
+		let error = AnyError::Cosmos(cosmos::error::Error::Custom("mempool is full".to_string()));
+		assert_eq!(error.kind(), ErrorKind::Execution);
+	}
 }
@@ -13,19 +13,40 @@
 // limitations under the License.
 
 use crate::{
-	chain::{AnyConfig, Config, CoreConfig},
-	fish, relay, Mode,
+	archive::MisbehaviourArchive,
+	backfill::backfill,
+	chain::{AnyChain, AnyConfig, CodeId, Config, CoreConfig},
+	fish,
+	forwarding::{relay_paths, ForwardingConfig, RelayPath},
+	logging::RelayPathId,
+	packets::construct_forced_timeout_message,
+	query_server::{self, AdminSettings},
+	relay,
+	store::SledRelayerStore,
+	telemetry,
+	validation::ensure_valid,
+	Mode,
 };
 use anyhow::{anyhow, Result};
 use clap::Parser;
-use ibc::core::{ics04_channel::channel::Order, ics24_host::identifier::PortId};
+use ibc::core::{
+	ics04_channel::channel::Order,
+	ics24_host::identifier::{ChannelId, ClientId, ConnectionId, PortId},
+};
 use metrics::{data::Metrics, handler::MetricsHandler, init_prometheus};
 use primitives::{
-	utils::{create_channel, create_clients, create_connection},
-	Chain, IbcProvider,
+	relayer_store::RelayerStore,
+	utils::{close_channel, create_channel, create_clients, create_connection},
+	Chain, IbcProvider, WasmUploadStatus,
 };
 use prometheus::Registry;
-use std::{num::NonZeroU64, path::PathBuf, str::FromStr, time::Duration};
+use std::{
+	num::NonZeroU64,
+	path::PathBuf,
+	str::FromStr,
+	sync::Arc,
+	time::{Duration, UNIX_EPOCH},
+};
 
 #[derive(Debug, Parser)]
 pub struct Cli {
@@ -33,6 +54,50 @@ pub struct Cli {
 	pub subcommand: Subcommand,
 }
 
+impl Cli {
+	/// Runs the subcommand, saving the updated configs of the commands that create ids.
+	pub async fn run(&self) -> Result<()> {
+		match &self.subcommand {
+			Subcommand::Relay(cmd) => cmd.run().await,
+			Subcommand::UploadWasm(cmd) => {
+				let new_config = cmd.run().await?;
+				cmd.save_config(&new_config).await
+			},
+			Subcommand::Wasm(cmd) => match &cmd.subcommand {
+				WasmSubcommand::MigrateClient(cmd) => {
+					let new_config = cmd.run().await?;
+					cmd.save_config(&new_config).await
+				},
+			},
+			Subcommand::CreateClients(cmd) => {
+				let new_config = cmd.create_clients().await?;
+				cmd.save_config(&new_config).await
+			},
+			Subcommand::CreateConnection(cmd) => {
+				let new_config = cmd.create_connection().await?;
+				cmd.save_config(&new_config).await
+			},
+			Subcommand::CreateChannel(cmd) => {
+				let new_config = cmd.create_channel().await?;
+				cmd.save_config(&new_config).await
+			},
+			Subcommand::Fish(cmd) => cmd.fish().await,
+			Subcommand::Query(cmd) => match &cmd.subcommand {
+				QuerySubcommand::ClientStatus(cmd) => cmd.run().await,
+				QuerySubcommand::Misbehaviour(cmd) => cmd.run().await,
+				QuerySubcommand::Channels(cmd) => cmd.run().await,
+			},
+			Subcommand::Channel(cmd) => match &cmd.subcommand {
+				ChannelSubcommand::Close(cmd) => cmd.run().await,
+			},
+			Subcommand::Packet(cmd) => match &cmd.subcommand {
+				PacketSubcommand::Timeout(cmd) => cmd.run().await,
+			},
+			Subcommand::RotateKey(cmd) => cmd.run().await,
+		}
+	}
+}
+
 /// Possible subcommands of the main binary.
 #[derive(Debug, Parser)]
 pub enum Subcommand {
@@ -40,6 +105,8 @@ pub enum Subcommand {
 	Relay(Cmd),
 	#[clap(name = "upload-wasm", about = "Upload a WASM blob to the chain")]
 	UploadWasm(UploadWasmCmd),
+	#[clap(name = "wasm", about = "Act on the WASM light clients of a chain")]
+	Wasm(WasmCmd),
 	#[clap(
 		name = "fish",
 		about = "Start the relayer in fishing mode (catching malicious transactions)"
@@ -51,6 +118,171 @@ pub enum Subcommand {
 	CreateConnection(Cmd),
 	#[clap(name = "create-channel", about = "Creates a channel on the specified port")]
 	CreateChannel(Cmd),
+	#[clap(name = "query", about = "Query the state of a chain")]
+	Query(QueryCmd),
+	#[clap(name = "channel", about = "Act on a channel of chain A")]
+	Channel(ChannelCmd),
+	#[clap(name = "packet", about = "Act on a packet sent from chain A")]
+	Packet(PacketCmd),
+	#[clap(
+		name = "rotate-key",
+		about = "Rotate the key signing the transactions of a running relayer"
+	)]
+	RotateKey(RotateKeyCmd),
+}
+
+#[derive(Debug, Clone, Parser)]
+pub struct QueryCmd {
+	#[clap(subcommand)]
+	pub subcommand: QuerySubcommand,
+}
+
+/// Possible subcommands of the `query` subcommand.
+#[derive(Debug, Clone, Parser)]
+pub enum QuerySubcommand {
+	#[clap(name = "client-status", about = "Query whether a client is active, expired or frozen")]
+	ClientStatus(ClientStatusCmd),
+	#[clap(name = "misbehaviour", about = "List the misbehaviour archived by the fisherman")]
+	Misbehaviour(MisbehaviourCmd),
+	#[clap(name = "channels", about = "List the channels of a chain of the path")]
+	Channels(ChannelsCmd),
+}
+
+#[derive(Debug, Clone, Parser)]
+pub struct WasmCmd {
+	#[clap(subcommand)]
+	pub subcommand: WasmSubcommand,
+}
+
+/// Possible subcommands of the `wasm` subcommand.
+#[derive(Debug, Clone, Parser)]
+pub enum WasmSubcommand {
+	#[clap(
+		name = "migrate-client",
+		about = "Migrate a WASM light client of the chain to uploaded code"
+	)]
+	MigrateClient(MigrateWasmClientCmd),
+}
+
+#[derive(Debug, Clone, Parser)]
+pub struct ChannelCmd {
+	#[clap(subcommand)]
+	pub subcommand: ChannelSubcommand,
+}
+
+/// Possible subcommands of the `channel` subcommand.
+#[derive(Debug, Clone, Parser)]
+pub enum ChannelSubcommand {
+	#[clap(
+		name = "close",
+		about = "Close a channel of chain A and relay the close handshake to chain B"
+	)]
+	Close(CloseChannelCmd),
+}
+
+#[derive(Debug, Clone, Parser)]
+pub struct PacketCmd {
+	#[clap(subcommand)]
+	pub subcommand: PacketSubcommand,
+}
+
+/// Possible subcommands of the `packet` subcommand.
+#[derive(Debug, Clone, Parser)]
+pub enum PacketSubcommand {
+	#[clap(
+		name = "timeout",
+		about = "Time out a packet sent from chain A that wasn't received on chain B"
+	)]
+	Timeout(TimeoutPacketCmd),
+}
+
+/// Paths of the configs of a pair of chains.
+#[derive(Debug, Clone, Parser)]
+pub struct PathConfigArgs {
+	/// Relayer chain A config path.
+	#[clap(long)]
+	config_a: String,
+	/// Relayer chain B config path.
+	#[clap(long)]
+	config_b: String,
+	/// Relayer core config path.
+	#[clap(long)]
+	config_core: String,
+}
+
+#[derive(Debug, Clone, Parser)]
+pub struct CloseChannelCmd {
+	#[clap(flatten)]
+	configs: PathConfigArgs,
+	/// Channel of chain A to close.
+	#[clap(long, alias = "channel-id")]
+	channel: String,
+	/// Port of the channel.
+	#[clap(long, alias = "port-id")]
+	port: String,
+}
+
+#[derive(Debug, Clone, Parser)]
+pub struct TimeoutPacketCmd {
+	#[clap(flatten)]
+	configs: PathConfigArgs,
+	/// Channel of chain A the packet was sent on.
+	#[clap(long, alias = "channel-id")]
+	channel: String,
+	/// Port of the channel.
+	#[clap(long, alias = "port-id")]
+	port: String,
+	/// Sequence of the packet.
+	#[clap(long)]
+	sequence: u64,
+}
+
+#[derive(Debug, Clone, Parser)]
+pub struct ClientStatusCmd {
+	/// Relayer chain config path.
+	#[clap(long)]
+	config: String,
+	/// Client to query, defaults to the client id of the config.
+	#[clap(long)]
+	client_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Parser)]
+pub struct MisbehaviourCmd {
+	/// Relayer core config path, whose `misbehaviour_archive_dir` is listed.
+	#[clap(long)]
+	config_core: String,
+	/// Only list the misbehaviour detected since this date, e.g. `2023-06-01` or
+	/// `2023-06-01T12:00:00Z`.
+	#[clap(long)]
+	since: Option<String>,
+}
+
+#[derive(Debug, Clone, Parser)]
+pub struct ChannelsCmd {
+	#[clap(flatten)]
+	configs: PathConfigArgs,
+	/// Chain whose channels are listed, `a`, `b` or the name of the chain.
+	#[clap(long)]
+	chain: String,
+}
+
+#[derive(Debug, Clone, Parser)]
+pub struct RotateKeyCmd {
+	/// Relayer core config path, whose `grpc_endpoint` the relayer serves the admin service on.
+	#[clap(long)]
+	config_core: String,
+	/// Chain whose key is rotated, `a`, `b` or the name of the chain.
+	#[clap(long)]
+	chain: String,
+	/// New key read by the relayer: the name of a file of the `grpc_admin_key_dir` of the relayer
+	/// holding the key, or `remote:<key id>@<url>` of a remote signer.
+	#[clap(long)]
+	key_source: String,
+	/// Minimum balance of the account of the new key, defaults to the `min_balance` of the chain
+	/// config.
+	#[clap(long)]
+	min_balance: Option<u128>,
 }
 
 #[derive(Debug, Clone, Parser)]
@@ -65,7 +297,7 @@ pub struct Cmd {
 	#[clap(long)]
 	config_core: String,
 	/// Port id for channel creation
-	#[clap(long)]
+	#[clap(long, alias = "port")]
 	port_id: Option<String>,
 	/// Connection delay period in seconds
 	#[clap(long)]
@@ -76,12 +308,31 @@ pub struct Cmd {
 	/// Channel version
 	#[clap(long)]
 	version: Option<String>,
+	/// Connection of chain A on which the channel is created, defaults to its primary connection
+	#[clap(long)]
+	connection_id: Option<String>,
 	/// New config path for A to avoid overriding existing configuration
 	#[clap(long)]
 	pub out_config_a: Option<String>,
 	/// New config path for B to avoid overriding existing configuration
 	#[clap(long)]
 	pub out_config_b: Option<String>,
+	/// Only print the created ids, instead of writing them back into the config files of A and B
+	/// when no out config path is given
+	#[clap(long)]
+	pub no_update_config: bool,
+	/// Reuse the most suitable existing clients, if both chains have one, instead of creating
+	/// new clients
+	#[clap(long)]
+	pub reuse_clients: bool,
+	/// Relay the packets and acknowledgements of chain A from this height before relaying new
+	/// ones, overrides `from_height_a` of the core config
+	#[clap(long)]
+	pub from_height_a: Option<u64>,
+	/// Relay the packets and acknowledgements of chain B from this height before relaying new
+	/// ones, overrides `from_height_b` of the core config
+	#[clap(long)]
+	pub from_height_b: Option<u64>,
 }
 
 #[derive(Debug, Clone, Parser)]
@@ -105,8 +356,11 @@ impl UploadWasmCmd {
 		let mut config: AnyConfig = toml::from_str(&file_content)?;
 		let client = config.clone().into_client().await?;
 		let wasm = tokio::fs::read(&self.wasm_path).await?;
-		let code_id = client.upload_wasm(wasm).await?;
+		let (code_id, status) = client.upload_wasm(wasm).await?;
 		let code_id_str = hex::encode(code_id);
+		if status == WasmUploadStatus::AlreadyStored {
+			log::info!("Wasm code {code_id_str} was already stored");
+		}
 		println!("{code_id_str}");
 		config.set_wasm_code_id(code_id_str);
 		Ok(config)
@@ -118,67 +372,441 @@ impl UploadWasmCmd {
 	}
 }
 
+#[derive(Debug, Clone, Parser)]
+pub struct MigrateWasmClientCmd {
+	/// Relayer chain config path, whose `wasm_code_id` is set to the new code.
+	#[clap(long)]
+	config: String,
+	/// New config path to avoid overriding existing configuration.
+	#[clap(long)]
+	pub out_config: Option<String>,
+	/// WASM light client to migrate.
+	#[clap(long)]
+	client_id: String,
+	/// Hex encoded checksum of the uploaded code the client is migrated to.
+	#[clap(long)]
+	code_id: String,
+	/// JSON message passed to the migrate entry point of the new code.
+	#[clap(long, default_value = "{}")]
+	migrate_msg: String,
+}
+
+impl MigrateWasmClientCmd {
+	/// Migrates the client and points the config at the new code, so that the clients created
+	/// from now on use it too.
+	pub async fn run(&self) -> Result<AnyConfig> {
+		let client_id = ClientId::from_str(&self.client_id)
+			.map_err(|e| anyhow!("Invalid client id {}: {e}", self.client_id))?;
+		let code_id = CodeId::from_str(&self.code_id)
+			.map_err(|e| anyhow!("Invalid code id {}: {e}", self.code_id))?;
+		let file_content = tokio::fs::read_to_string(&self.config).await?;
+		let mut config: AnyConfig = toml::from_str(&file_content)?;
+		let mut client = config.clone().into_client().await?;
+		client
+			.migrate_wasm_client(
+				client_id.clone(),
+				code_id.to_vec(),
+				self.migrate_msg.clone().into_bytes(),
+			)
+			.await?;
+		println!("Migrated {client_id} on {} to wasm code {code_id}", client.name());
+		config.set_wasm_code_id(code_id.to_string());
+		Ok(config)
+	}
+
+	pub async fn save_config(&self, new_config: &AnyConfig) -> Result<()> {
+		let path = self.out_config.as_ref().cloned().unwrap_or_else(|| self.config.clone());
+		write_config(path, new_config).await
+	}
+}
+
+impl ClientStatusCmd {
+	/// Prints the status of the client at the latest height of the chain.
+	pub async fn run(&self) -> Result<()> {
+		use tokio::fs::read_to_string;
+		let path: PathBuf = self.config.parse()?;
+		let file_content = read_to_string(path).await?;
+		let config: AnyConfig = toml::from_str(&file_content)?;
+		let client = config.into_client().await?;
+		let client_id = match &self.client_id {
+			Some(client_id) => ClientId::from_str(client_id)
+				.map_err(|e| anyhow!("Invalid client id {client_id}: {e}"))?,
+			None => client.client_id(),
+		};
+		let (height, _) = client.latest_height_and_timestamp().await?;
+		let status = client.query_client_status(height, client_id.clone()).await?;
+		println!("{client_id} on {} at {height}: {status}", client.name());
+		Ok(())
+	}
+}
+
+impl MisbehaviourCmd {
+	/// Prints the archived misbehaviour, one incident per line.
+	pub async fn run(&self) -> Result<()> {
+		let file_content = tokio::fs::read_to_string(&self.config_core).await?;
+		let config: CoreConfig = toml::from_str(&file_content)?;
+		let dir = config
+			.misbehaviour_archive_dir
+			.ok_or_else(|| anyhow!("No misbehaviour_archive_dir in {}", self.config_core))?;
+		let since = match &self.since {
+			Some(since) => parse_date(since)?,
+			None => 0,
+		};
+		for report in MisbehaviourArchive::open(dir)?.reports_since(since)? {
+			let detected_at = UNIX_EPOCH + Duration::from_secs(report.detected_at);
+			println!(
+				"{} client {} on {}: conflicting proofs of heights {} and {}, {}",
+				humantime::format_rfc3339_seconds(detected_at),
+				report.client_id,
+				report.chain,
+				report.first_height,
+				report.second_height,
+				match &report.tx_id {
+					Some(tx_id) => format!("submitted in {tx_id}"),
+					None => "not submitted".to_string(),
+				},
+			);
+		}
+		Ok(())
+	}
+}
+
+impl ChannelsCmd {
+	/// Prints the channels of the chain, one channel per row.
+	pub async fn run(&self) -> Result<()> {
+		let PathConfigArgs { config_a, config_b, config_core } = &self.configs;
+		let config = parse_config(config_a, config_b, config_core).await?;
+		let chain_config = match self.chain.as_str() {
+			"a" => config.chain_a,
+			"b" => config.chain_b,
+			name if name == config.chain_a.name() => config.chain_a,
+			name if name == config.chain_b.name() => config.chain_b,
+			name => return Err(anyhow!("No chain {name} on the path")),
+		};
+		let chain = chain_config.into_client().await?;
+		let rows = chain.query_identified_channels().await?.into_iter().map(|channel| {
+			let end = channel.channel_end;
+			let hops = end.connection_hops().iter().map(ToString::to_string).collect::<Vec<_>>();
+			let counterparty_channel =
+				end.counterparty().channel_id().map_or("-".to_string(), ToString::to_string);
+			[
+				channel.channel_id.to_string(),
+				channel.port_id.to_string(),
+				end.state().to_string(),
+				end.ordering().to_string(),
+				hops.join(","),
+				counterparty_channel,
+				end.counterparty().port_id().to_string(),
+				end.version().to_string(),
+			]
+		});
+		let header = [
+			"CHANNEL",
+			"PORT",
+			"STATE",
+			"ORDER",
+			"CONNECTION",
+			"COUNTERPARTY",
+			"COUNTERPARTY PORT",
+			"VERSION",
+		]
+		.map(ToString::to_string);
+		let rows = std::iter::once(header).chain(rows).collect::<Vec<_>>();
+		let mut widths = [0; 8];
+		for row in &rows {
+			for (width, cell) in widths.iter_mut().zip(row) {
+				*width = (*width).max(cell.len());
+			}
+		}
+		for row in rows {
+			let cells = row
+				.iter()
+				.zip(widths)
+				.map(|(cell, width)| format!("{cell:width$}"))
+				.collect::<Vec<_>>();
+			println!("{}", cells.join("  ").trim_end());
+		}
+		Ok(())
+	}
+}
+
+impl PathConfigArgs {
+	async fn into_clients(&self) -> Result<(AnyChain, AnyChain)> {
+		let config = parse_config(&self.config_a, &self.config_b, &self.config_core).await?;
+		Cmd::into_clients(&config).await
+	}
+
+	async fn into_live_clients(&self) -> Result<(AnyChain, AnyChain)> {
+		let config = parse_config(&self.config_a, &self.config_b, &self.config_core).await?;
+		Cmd::into_live_clients(&config).await
+	}
+}
+
+/// Parses the channel and port ids given on the command line.
+fn parse_channel(channel: &str, port: &str) -> Result<(ChannelId, PortId)> {
+	let channel_id =
+		ChannelId::from_str(channel).map_err(|e| anyhow!("Invalid channel id {channel}: {e}"))?;
+	let port_id = PortId::from_str(port).map_err(|e| anyhow!("Invalid port id {port}: {e}"))?;
+	Ok((channel_id, port_id))
+}
+
+impl CloseChannelCmd {
+	/// Submits `MsgChannelCloseInit` on chain A and relays the close handshake until the
+	/// counterparty channel is closed on chain B.
+	pub async fn run(&self) -> Result<()> {
+		let (channel_id, port_id) = parse_channel(&self.channel, &self.port)?;
+		let (mut chain_a, chain_b) = self.configs.into_live_clients().await?;
+
+		let chain_a_clone = chain_a.clone();
+		let chain_b_clone = chain_b.clone();
+		let handle = tokio::task::spawn(async move {
+			relay(chain_a_clone, chain_b_clone, None, None, Some(Mode::Light))
+				.await
+				.unwrap();
+		});
+
+		let result = close_channel(&mut chain_a, &chain_b, channel_id, port_id.clone()).await;
+		// the relay loop is only needed for the handshake
+		handle.abort();
+		let channel_id_b = result?;
+		println!("Closed channel {channel_id}/{port_id} on Chain {}", chain_a.name());
+		println!("Closed channel {channel_id_b} on Chain {}", chain_b.name());
+		Ok(())
+	}
+}
+
+impl TimeoutPacketCmd {
+	/// Submits the timeout of the packet on chain A, once it can be proven that chain B didn't
+	/// receive it.
+	pub async fn run(&self) -> Result<()> {
+		let (channel_id, port_id) = parse_channel(&self.channel, &self.port)?;
+		let (chain_a, chain_b) = self.configs.into_clients().await?;
+		let msg = construct_forced_timeout_message(
+			&chain_a,
+			&chain_b,
+			channel_id,
+			port_id.clone(),
+			self.sequence,
+		)
+		.await?;
+		let tx_id = chain_a.submit(vec![msg]).await?;
+		println!(
+			"Timed out packet {} of {channel_id}/{port_id} on Chain {} in {tx_id:?}",
+			self.sequence,
+			chain_a.name()
+		);
+		Ok(())
+	}
+}
+
+impl RotateKeyCmd {
+	/// Asks the running relayer to sign the next transactions to the chain with the new key.
+	pub async fn run(&self) -> Result<()> {
+		let file_content = tokio::fs::read_to_string(&self.config_core).await?;
+		let config: CoreConfig = toml::from_str(&file_content)?;
+		let addr = config
+			.grpc_endpoint
+			.ok_or_else(|| anyhow!("No grpc_endpoint in {}", self.config_core))?;
+		let addr = addr.parse().map_err(|e| anyhow!("Invalid grpc_endpoint {addr}: {e}"))?;
+		let rotation = query_server::request_key_rotation(
+			addr,
+			self.chain.clone(),
+			self.key_source.clone(),
+			self.min_balance,
+		)
+		.await?;
+		println!(
+			"Rotated the key of chain {} from account {} to {}",
+			self.chain, rotation.old_account, rotation.new_account
+		);
+		Ok(())
+	}
+}
+
+/// Parses an RFC 3339 date, with or without the time, into seconds since the unix epoch.
+fn parse_date(date: &str) -> Result<u64> {
+	let time = humantime::parse_rfc3339_weak(date)
+		.or_else(|_| humantime::parse_rfc3339_weak(&format!("{date} 00:00:00")))
+		.map_err(|e| anyhow!("Invalid date {date}: {e}"))?;
+	Ok(time.duration_since(UNIX_EPOCH)?.as_secs())
+}
+
 impl Cmd {
 	async fn parse_config(&self) -> Result<Config> {
-		use tokio::fs::read_to_string;
-		let path_a: PathBuf = self.config_a.parse()?;
-		let path_b: PathBuf = self.config_b.parse()?;
-		let path_core: PathBuf = self.config_core.parse()?;
-		let file_content = read_to_string(path_a).await?;
-		let config_a: AnyConfig = toml::from_str(&file_content)?;
-		let file_content = read_to_string(path_b).await?;
-		let config_b: AnyConfig = toml::from_str(&file_content)?;
-		let file_content = read_to_string(path_core).await?;
-		let config_core: CoreConfig = toml::from_str(&file_content)?;
-
-		Ok(Config { chain_a: config_a, chain_b: config_b, core: config_core })
+		parse_config(&self.config_a, &self.config_b, &self.config_core).await
+	}
+
+	/// Connects to both chains, once their configuration is checked against their state.
+	async fn into_clients(config: &Config) -> Result<(AnyChain, AnyChain)> {
+		let chain_a = config.chain_a.clone().into_client().await?;
+		let chain_b = config.chain_b.clone().into_client().await?;
+		ensure_valid(config.validate_on_chain(&chain_a, &chain_b).await?)?;
+		Ok((chain_a, chain_b))
+	}
+
+	/// Like [`Self::into_clients`], once both chains produce blocks, for the commands relaying
+	/// between them.
+	async fn into_live_clients(config: &Config) -> Result<(AnyChain, AnyChain)> {
+		let chain_a = config.chain_a.clone().into_live_client().await?;
+		let chain_b = config.chain_b.clone().into_live_client().await?;
+		ensure_valid(config.validate_on_chain(&chain_a, &chain_b).await?)?;
+		Ok((chain_a, chain_b))
 	}
 
 	// todo: IntoClient, since clients are generic, users must configure clients themselves.
 	/// Run the command
 	pub async fn run(&self) -> Result<()> {
 		let config = self.parse_config().await?;
-		let chain_a = config.chain_a.into_client().await?;
-		let chain_b = config.chain_b.into_client().await?;
+		let (mut chain_a, mut chain_b) = Self::into_live_clients(&config).await?;
+		let store = match &config.core.state_db_path {
+			Some(path) => Some(Arc::new(SledRelayerStore::open(path)?) as Arc<dyn RelayerStore>),
+			None => None,
+		};
+		chain_a.common_state_mut().relayer_store = store.clone();
+		chain_b.common_state_mut().relayer_store = store.clone();
+		if let Some(telemetry) = &config.core.telemetry {
+			telemetry::init(telemetry)?;
+		}
+		if let Some(addr) = &config.core.grpc_endpoint {
+			let addr = addr.parse().map_err(|e| anyhow!("Invalid grpc_endpoint {addr}: {e}"))?;
+			let (chain_a, chain_b) = (chain_a.clone(), chain_b.clone());
+			let admin = config.core.grpc_admin.then(|| AdminSettings {
+				key_dir: config.core.grpc_admin_key_dir.clone().map(PathBuf::from),
+			});
+			if admin.is_some() {
+				query_server::check_admin_endpoint(addr)?;
+			}
+			tokio::spawn(async move {
+				if let Err(e) = query_server::serve(addr, chain_a, chain_b, admin).await {
+					log::error!(target: "hyperspace", "Query server failed: {e}");
+				}
+			});
+		}
+
+		if let Some(from_height) = self.from_height_a.or(config.core.from_height_a) {
+			if let Err(e) = backfill(&mut chain_a, &chain_b, from_height, None).await {
+				log::error!(target: "hyperspace", "Failed to backfill the packets of {}, they're left to the relay loop: {e:?}", chain_a.name());
+			}
+		}
+		if let Some(from_height) = self.from_height_b.or(config.core.from_height_b) {
+			if let Err(e) = backfill(&mut chain_b, &chain_a, from_height, None).await {
+				log::error!(target: "hyperspace", "Failed to backfill the packets of {}, they're left to the relay loop: {e:?}", chain_b.name());
+			}
+		}
+
+		let result = if let Some(forwarding) = &config.core.forwarding {
+			Self::relay_forwarded(&config, chain_a, chain_b, forwarding, store).await
+		} else {
+			let (metrics_handler_a, metrics_handler_b) =
+				register_metrics(&chain_a, &chain_b, &config.core)?;
+			relay(chain_a, chain_b, Some(metrics_handler_a), Some(metrics_handler_b), None).await
+		};
+		telemetry::shutdown();
+		result
+	}
+
+	/// Relays the path between chain A and chain B along with the paths of the chains forwarding
+	/// its transfers.
+	async fn relay_forwarded(
+		config: &Config,
+		chain_a: AnyChain,
+		chain_b: AnyChain,
+		forwarding: &ForwardingConfig,
+		store: Option<Arc<dyn RelayerStore>>,
+	) -> Result<()> {
+		let mut configs = vec![(config.chain_a.clone(), config.chain_b.clone())];
+		let mut chains = vec![(chain_a, chain_b)];
+		for path in &forwarding.paths {
+			let path_config = Config {
+				chain_a: read_config(path.config_a.clone()).await?,
+				chain_b: read_config(path.config_b.clone()).await?,
+				core: config.core.clone(),
+			};
+			ensure_valid(path_config.validate())?;
+			let (mut path_chain_a, mut path_chain_b) =
+				Self::into_live_clients(&path_config).await?;
+			path_chain_a.common_state_mut().relayer_store = store.clone();
+			path_chain_b.common_state_mut().relayer_store = store.clone();
+			chains.push((path_chain_a, path_chain_b));
+			configs.push((path_config.chain_a, path_config.chain_b));
+		}
+
+		ensure_valid(forwarding.validate(&configs))?;
+		let senders = configs
+			.into_iter()
+			.zip(&chains)
+			.map(|((config, _), (chain, _))| (config, chain.clone()))
+			.collect::<Vec<_>>();
+		ensure_valid(forwarding.validate_on_chain(&senders).await?)?;
 
 		let registry =
 			Registry::new_custom(None, None).expect("this can only fail if the prefix is empty");
-		let metrics_a = Metrics::register(chain_a.name(), &registry)?;
-		let metrics_b = Metrics::register(chain_b.name(), &registry)?;
-		let mut metrics_handler_a = MetricsHandler::new(registry.clone(), metrics_a);
-		let mut metrics_handler_b = MetricsHandler::new(registry.clone(), metrics_b);
-		metrics_handler_a.link_with_counterparty(&mut metrics_handler_b);
-
-		if let Some(addr) = config.core.prometheus_endpoint.and_then(|s| s.parse().ok()) {
-			tokio::spawn(init_prometheus(addr, registry.clone()));
+		let mut paths = vec![];
+		for (i, (chain_a, chain_b)) in chains.into_iter().enumerate() {
+			// the intermediate chains are relayed on two paths, whose metrics need distinct names
+			let (name_a, name_b) = match i {
+				0 => (chain_a.name().to_string(), chain_b.name().to_string()),
+				_ => (
+					format!("{}-{}", chain_a.name(), chain_b.name()),
+					format!("{}-{}", chain_b.name(), chain_a.name()),
+				),
+			};
+			let path = RelayPathId::new(&chain_a, &chain_b);
+			let (metrics_a, metrics_b) = register_path_metrics(&name_a, &name_b, &path, &registry)?;
+			paths.push(RelayPath {
+				chain_a,
+				chain_b,
+				metrics_a: Some(metrics_a),
+				metrics_b: Some(metrics_b),
+			});
+		}
+		if let Some(addr) = config.core.prometheus_endpoint.as_ref().and_then(|s| s.parse().ok()) {
+			tokio::spawn(init_prometheus(addr, registry));
 		}
 
-		relay(chain_a, chain_b, Some(metrics_handler_a), Some(metrics_handler_b), None).await
+		relay_paths(paths, None).await
 	}
 
 	/// Run fisherman
 	pub async fn fish(&self) -> Result<()> {
 		let config = self.parse_config().await?;
-		let chain_a = config.chain_a.into_client().await?;
-		let chain_b = config.chain_b.into_client().await?;
+		let (chain_a, chain_b) = Self::into_live_clients(&config).await?;
+		let (metrics_handler_a, metrics_handler_b) =
+			register_metrics(&chain_a, &chain_b, &config.core)?;
+
+		let archive = match &config.core.misbehaviour_archive_dir {
+			Some(dir) => Some(MisbehaviourArchive::open(dir)?),
+			None => None,
+		};
 
-		fish(chain_a, chain_b).await
+		fish(chain_a, chain_b, Some(metrics_handler_a), Some(metrics_handler_b), archive).await
 	}
 
 	pub async fn create_clients(&self) -> Result<Config> {
 		let mut config = self.parse_config().await?;
-		let mut chain_a = config.chain_a.clone().into_client().await?;
-		let mut chain_b = config.chain_b.clone().into_client().await?;
+		let mut chain_a = config.chain_a.clone().into_live_client().await?;
+		let mut chain_b = config.chain_b.clone().into_live_client().await?;
 
-		let (client_id_a_on_b, client_id_b_on_a) =
-			create_clients(&mut chain_a, &mut chain_b).await?;
-		log::info!(
+		let existing_clients = if self.reuse_clients {
+			(
+				chain_b.find_suitable_client(&chain_a).await?,
+				chain_a.find_suitable_client(&chain_b).await?,
+			)
+		} else {
+			(None, None)
+		};
+		let (client_id_a_on_b, client_id_b_on_a) = match existing_clients {
+			(Some(client_id_a_on_b), Some(client_id_b_on_a)) =>
+				(client_id_a_on_b, client_id_b_on_a),
+			_ => create_clients(&mut chain_a, &mut chain_b).await?,
+		};
+		println!(
 			"ClientId for Chain {} on Chain {}: {}",
 			chain_b.name(),
 			chain_a.name(),
 			client_id_b_on_a
 		);
-		log::info!(
+		println!(
 			"ClientId for Chain {} on Chain {}: {}",
 			chain_a.name(),
 			chain_b.name(),
@@ -193,12 +821,12 @@ impl Cmd {
 	pub async fn create_connection(&self) -> Result<Config> {
 		let delay_period_seconds: NonZeroU64 = self
 			.delay_period
-			.expect("delay_period should be provided when creating a connection")
+			.ok_or_else(|| anyhow!("--delay-period must be specified when creating a connection"))?
 			.into();
 		let delay = Duration::from_secs(delay_period_seconds.into());
 		let mut config = self.parse_config().await?;
-		let mut chain_a = config.chain_a.clone().into_client().await?;
-		let mut chain_b = config.chain_b.clone().into_client().await?;
+		let mut chain_a = config.chain_a.clone().into_live_client().await?;
+		let mut chain_b = config.chain_b.clone().into_live_client().await?;
 
 		let chain_a_clone = chain_a.clone();
 		let chain_b_clone = chain_b.clone();
@@ -208,35 +836,50 @@ impl Cmd {
 				.unwrap();
 		});
 
-		let (connection_id_a, connection_id_b) =
-			create_connection(&mut chain_a, &mut chain_b, delay).await?;
-		log::info!("ConnectionId on Chain {}: {}", chain_a.name(), connection_id_a);
-		log::info!("ConnectionId on Chain {}: {}", chain_b.name(), connection_id_b);
+		let prefix = chain_b.connection_prefix();
+		let result = create_connection(&mut chain_a, &mut chain_b, delay, prefix).await;
+		// the relay loop is only needed for the handshake
 		handle.abort();
+		let (connection_id_a, connection_id_b) = result?;
+		println!("ConnectionId on Chain {}: {}", chain_a.name(), connection_id_a);
+		println!("ConnectionId on Chain {}: {}", chain_b.name(), connection_id_b);
 
-		config.chain_a.set_connection_id(connection_id_a);
-		config.chain_b.set_connection_id(connection_id_b);
+		// the existing connections of the config keep being relayed
+		config.chain_a.add_connection_id(connection_id_a);
+		config.chain_b.add_connection_id(connection_id_b);
 
 		Ok(config)
 	}
 
 	pub async fn create_channel(&self) -> Result<Config> {
-		let port_id = PortId::from_str(
-			self.port_id
-				.as_ref()
-				.expect("port_id must be specified when creating a channel")
-				.as_str(),
-		)
-		.expect("Port id was invalid");
+		let port_id = self
+			.port_id
+			.as_ref()
+			.ok_or_else(|| anyhow!("--port must be specified when creating a channel"))?;
+		let port_id =
+			PortId::from_str(port_id).map_err(|e| anyhow!("Invalid port id {port_id}: {e}"))?;
 		let version = self
 			.version
+			.clone()
+			.ok_or_else(|| anyhow!("--version must be specified when creating a channel"))?;
+		let order = self
+			.order
 			.as_ref()
-			.expect("version must be specified when creating a channel")
-			.clone();
-		let order = self.order.as_ref().expect("order must be specified when creating a channel, expected one of 'ordered' or 'unordered'").as_str();
+			.ok_or_else(|| anyhow!("--order must be specified when creating a channel"))?;
+		let order = Order::from_str(order).map_err(|_| {
+			anyhow!("Invalid order {order}, expected one of 'ordered' or 'unordered'")
+		})?;
 		let mut config = self.parse_config().await?;
-		let mut chain_a = config.chain_a.clone().into_client().await?;
-		let mut chain_b = config.chain_b.clone().into_client().await?;
+		let mut chain_a = config.chain_a.clone().into_live_client().await?;
+		let mut chain_b = config.chain_b.clone().into_live_client().await?;
+
+		let connection_id_a = match &self.connection_id {
+			Some(connection_id) => ConnectionId::from_str(connection_id)
+				.map_err(|e| anyhow!("Invalid connection id {connection_id}: {e}"))?,
+			None => chain_a
+				.connection_id()
+				.ok_or_else(|| anyhow!("The config of chain A must have a connection id"))?,
+		};
 
 		let chain_a_clone = chain_a.clone();
 		let chain_b_clone = chain_b.clone();
@@ -246,37 +889,123 @@ impl Cmd {
 				.unwrap();
 		});
 
-		let order = Order::from_str(order).expect("Expected one of 'ordered' or 'unordered'");
-		let connection_id = chain_a.connection_id().expect("Connection id should be defined");
-		let (channel_id_a, channel_id_b) = create_channel(
+		let result = create_channel(
 			&mut chain_a,
 			&mut chain_b,
-			connection_id,
+			connection_id_a.clone(),
 			port_id.clone(),
 			version,
 			order,
+			None,
 		)
-		.await?;
-		log::info!("ChannelId on Chain {}: {}", chain_a.name(), channel_id_a);
-		log::info!("ChannelId on Chain {}: {}", chain_b.name(), channel_id_b);
+		.await;
+		// the relay loop is only needed for the handshake
 		handle.abort();
+		let (channel_id_a, channel_id_b) = result?;
+		println!("ChannelId on Chain {}: {}", chain_a.name(), channel_id_a);
+		println!("ChannelId on Chain {}: {}", chain_b.name(), channel_id_b);
 
-		config.chain_a.set_channel_whitelist(channel_id_a, port_id.clone());
-		config.chain_b.set_channel_whitelist(channel_id_b, port_id);
+		// the channel is whitelisted on the connections it was created on
+		let (height, _) = chain_a.latest_height_and_timestamp().await?;
+		let connection_id_b = chain_a
+			.query_connection_end(height, connection_id_a.clone())
+			.await?
+			.connection
+			.and_then(|connection| connection.counterparty)
+			.and_then(|counterparty| counterparty.connection_id.parse::<ConnectionId>().ok());
+		config.chain_a.whitelist_connection_channel(
+			&connection_id_a,
+			channel_id_a,
+			port_id.clone(),
+		);
+		match connection_id_b {
+			Some(connection_id_b) =>
+				config
+					.chain_b
+					.whitelist_connection_channel(&connection_id_b, channel_id_b, port_id),
+			None => config.chain_b.set_channel_whitelist(channel_id_b, port_id),
+		}
 
 		Ok(config)
 	}
 
+	/// Writes the configs of A and B to their out config paths, or back into the input config
+	/// files unless `--no-update-config` is given.
 	pub async fn save_config(&self, new_config: &Config) -> Result<()> {
-		let path_a = self.out_config_a.as_ref().cloned().unwrap_or_else(|| self.config_a.clone());
-		let path_b = self.out_config_b.as_ref().cloned().unwrap_or_else(|| self.config_b.clone());
-		write_config(path_a, &new_config.chain_a).await?;
-		write_config(path_b, &new_config.chain_b).await
+		let path_a = self
+			.out_config_a
+			.clone()
+			.or_else(|| (!self.no_update_config).then(|| self.config_a.clone()));
+		let path_b = self
+			.out_config_b
+			.clone()
+			.or_else(|| (!self.no_update_config).then(|| self.config_b.clone()));
+		if let Some(path_a) = path_a {
+			write_config(path_a, &new_config.chain_a).await?;
+		}
+		if let Some(path_b) = path_b {
+			write_config(path_b, &new_config.chain_b).await?;
+		}
+		Ok(())
 	}
 }
 
+/// Reads and validates the configs of chains A and B and the core config.
+async fn parse_config(config_a: &str, config_b: &str, config_core: &str) -> Result<Config> {
+	let path_a: PathBuf = config_a.parse()?;
+	let path_b: PathBuf = config_b.parse()?;
+	let path_core: PathBuf = config_core.parse()?;
+	let config_a = read_config(path_a).await?;
+	let config_b = read_config(path_b).await?;
+	let file_content = tokio::fs::read_to_string(path_core).await?;
+	let config_core: CoreConfig = toml::from_str(&file_content)?;
+
+	let config = Config { chain_a: config_a, chain_b: config_b, core: config_core };
+	ensure_valid(config.validate())?;
+	Ok(config)
+}
+
+async fn read_config(path: PathBuf) -> Result<AnyConfig> {
+	let file_content = tokio::fs::read_to_string(path).await?;
+	Ok(toml::from_str(&file_content)?)
+}
+
 async fn write_config(path: String, config: &AnyConfig) -> Result<()> {
 	tokio::fs::write(path.parse::<PathBuf>()?, toml::to_string(config)?)
 		.await
 		.map_err(|e| anyhow!(e))
 }
+
+/// Registers the metrics of both chains and starts the prometheus endpoint, if configured.
+fn register_metrics(
+	chain_a: &impl Chain,
+	chain_b: &impl Chain,
+	config: &CoreConfig,
+) -> Result<(MetricsHandler, MetricsHandler)> {
+	let registry =
+		Registry::new_custom(None, None).expect("this can only fail if the prefix is empty");
+	let path = RelayPathId::new(chain_a, chain_b);
+	let (metrics_handler_a, metrics_handler_b) =
+		register_path_metrics(chain_a.name(), chain_b.name(), &path, &registry)?;
+
+	if let Some(addr) = config.prometheus_endpoint.as_ref().and_then(|s| s.parse().ok()) {
+		tokio::spawn(init_prometheus(addr, registry));
+	}
+
+	Ok((metrics_handler_a, metrics_handler_b))
+}
+
+/// Registers the metrics of both chains of a path under the given names, labelled with the path.
+fn register_path_metrics(
+	name_a: &str,
+	name_b: &str,
+	path: &RelayPathId,
+	registry: &Registry,
+) -> Result<(MetricsHandler, MetricsHandler)> {
+	let metrics_a = Metrics::register_for_path(name_a, path.as_str(), registry)?;
+	let metrics_b = Metrics::register_for_path(name_b, path.as_str(), registry)?;
+	let mut metrics_handler_a = MetricsHandler::new(registry.clone(), metrics_a);
+	let mut metrics_handler_b = MetricsHandler::new(registry.clone(), metrics_b);
+	metrics_handler_a.link_with_counterparty(&mut metrics_handler_b);
+	Ok((metrics_handler_a, metrics_handler_b))
+}
@@ -14,7 +14,7 @@
 
 #[cfg(feature = "testing")]
 use crate::send_packet_relay::packet_relay_status;
-use crate::Mode;
+use crate::{packets::utils::times_out_within_margin, telemetry, Mode};
 use codec::Encode;
 use ibc::{
 	core::{
@@ -35,7 +35,13 @@ use ibc::{
 			},
 		},
 		ics23_commitment::commitment::{CommitmentPrefix, CommitmentProofBytes},
-		ics24_host::identifier::ConnectionId,
+		ics24_host::{
+			identifier::ConnectionId,
+			path::{
+				AcksPath, ChannelEndsPath, ClientConsensusStatePath, ClientStatePath,
+				CommitmentsPath, ConnectionsPath,
+			},
+		},
 	},
 	events::{IbcEvent, IbcEventType},
 	proofs::{ConsensusProof, Proofs},
@@ -44,8 +50,11 @@ use ibc::{
 };
 use ibc_proto::google::protobuf::Any;
 use pallet_ibc::light_clients::AnyClientState;
-use primitives::{error::Error, mock::LocalClientTypes, Chain};
-use std::str::FromStr;
+use primitives::{
+	any_registry::known_messages, error::Error, mock::LocalClientTypes,
+	packet_filter::FilterAction, Chain,
+};
+use std::{fmt::Display, str::FromStr};
 use tendermint_proto::Protobuf;
 
 /// Connection proof type
@@ -65,6 +74,10 @@ pub async fn parse_events(
 	mode: Option<Mode>,
 ) -> Result<Vec<Any>, anyhow::Error> {
 	let mut messages = vec![];
+	// recv messages of packets deprioritized by the packet filter, sent after the other messages
+	let mut deprioritized_messages = vec![];
+	// latest height and timestamp of the sink, queried for the first packet
+	let mut sink_latest = None;
 	// 1. translate events to messages
 	for event in events {
 		match event {
@@ -85,33 +98,58 @@ pub async fn parse_events(
 					)?;
 					let counterparty = connection_end.counterparty();
 
-					let connection_proof =
-						CommitmentProofBytes::try_from(connection_response.proof)?;
-					let prefix: CommitmentPrefix = source.connection_prefix();
-					let client_state_response = source
-						.query_client_state(
-							open_init.height(),
-							open_init.attributes().client_id.clone(),
-						)
-						.await?;
+					let connection_proof = store_proof(
+						source,
+						&connection_id,
+						open_init.height(),
+						ConnectionsPath(connection_id.clone()),
+						connection_response.proof,
+					)
+					.await?;
+					let connection_proof = CommitmentProofBytes::try_from(connection_proof)?;
+					let prefix: CommitmentPrefix = source.connection_prefix_of(&connection_id);
+					let client_id = open_init.attributes().client_id.clone();
+					let client_state_response =
+						source.query_client_state(open_init.height(), client_id.clone()).await?;
 
 					let proof_height = connection_response.proof_height.ok_or_else(|| Error::Custom("[get_messages_for_events - open_conn_init] Proof height not found in response".to_string()))?;
 					let proof_height =
 						Height::new(proof_height.revision_number, proof_height.revision_height);
+					let client_state_proof = store_proof(
+						source,
+						&connection_id,
+						open_init.height(),
+						ClientStatePath(client_id.clone()),
+						client_state_response.proof,
+					)
+					.await?;
 					let client_state_proof =
-						CommitmentProofBytes::try_from(client_state_response.proof).ok();
+						CommitmentProofBytes::try_from(client_state_proof).ok();
 
 					let client_state = client_state_response
 						.client_state
 						.map(AnyClientState::try_from)
 						.ok_or_else(|| Error::Custom("Client state is empty".to_string()))??;
-					let consensus_proof = source
+					let consensus_height = client_state.latest_height();
+					let consensus_response = source
 						.query_client_consensus(
 							open_init.height(),
-							open_init.attributes().client_id.clone(),
-							client_state.latest_height(),
+							client_id.clone(),
+							consensus_height,
 						)
 						.await?;
+					let consensus_proof = store_proof(
+						source,
+						&connection_id,
+						open_init.height(),
+						ClientConsensusStatePath {
+							client_id: client_id.clone(),
+							epoch: consensus_height.revision_number,
+							height: consensus_height.revision_height,
+						},
+						consensus_response.proof,
+					)
+					.await?;
 					let host_consensus_state_proof =
 						query_host_consensus_state_proof(sink, client_state.clone()).await?;
 
@@ -120,17 +158,13 @@ pub async fn parse_events(
 						client_id: counterparty.client_id().clone(),
 						// client state proof is mandatory in conn_open_try
 						client_state: Some(client_state.clone()),
-						counterparty: Counterparty::new(
-							open_init.attributes().client_id.clone(),
-							Some(connection_id),
-							prefix,
-						),
+						counterparty: Counterparty::new(client_id, Some(connection_id), prefix),
 						counterparty_versions: connection_end.versions().to_vec(),
 						proofs: Proofs::new(
 							connection_proof,
 							client_state_proof,
 							Some(ConsensusProof::new(
-								CommitmentProofBytes::try_from(consensus_proof.proof)?,
+								CommitmentProofBytes::try_from(consensus_proof)?,
 								client_state.latest_height(),
 							)?),
 							None,
@@ -163,31 +197,56 @@ pub async fn parse_events(
 					)?;
 					let counterparty = connection_end.counterparty();
 
-					let connection_proof =
-						CommitmentProofBytes::try_from(connection_response.proof)?;
-					let client_state_response = source
-						.query_client_state(
-							open_try.height(),
-							open_try.attributes().client_id.clone(),
-						)
-						.await?;
+					let connection_proof = store_proof(
+						source,
+						&connection_id,
+						open_try.height(),
+						ConnectionsPath(connection_id.clone()),
+						connection_response.proof,
+					)
+					.await?;
+					let connection_proof = CommitmentProofBytes::try_from(connection_proof)?;
+					let client_id = open_try.attributes().client_id.clone();
+					let client_state_response =
+						source.query_client_state(open_try.height(), client_id.clone()).await?;
 
 					let proof_height = connection_response.proof_height.ok_or_else(|| Error::Custom("[get_messages_for_events - open_conn_try] Proof height not found in response".to_string()))?;
 					let proof_height =
 						Height::new(proof_height.revision_number, proof_height.revision_height);
+					let client_state_proof = store_proof(
+						source,
+						&connection_id,
+						open_try.height(),
+						ClientStatePath(client_id.clone()),
+						client_state_response.proof,
+					)
+					.await?;
 					let client_state_proof =
-						CommitmentProofBytes::try_from(client_state_response.proof).ok();
+						CommitmentProofBytes::try_from(client_state_proof).ok();
 					let client_state = client_state_response
 						.client_state
 						.map(AnyClientState::try_from)
 						.ok_or_else(|| Error::Custom("Client state is empty".to_string()))??;
-					let consensus_proof = source
+					let consensus_height = client_state.latest_height();
+					let consensus_response = source
 						.query_client_consensus(
 							open_try.height(),
-							open_try.attributes().client_id.clone(),
-							client_state.latest_height(),
+							client_id.clone(),
+							consensus_height,
 						)
 						.await?;
+					let consensus_proof = store_proof(
+						source,
+						&connection_id,
+						open_try.height(),
+						ClientConsensusStatePath {
+							client_id: client_id.clone(),
+							epoch: consensus_height.revision_number,
+							height: consensus_height.revision_height,
+						},
+						consensus_response.proof,
+					)
+					.await?;
 					let host_consensus_state_proof =
 						query_host_consensus_state_proof(sink, client_state.clone()).await?;
 					// Construct OpenAck
@@ -204,7 +263,7 @@ pub async fn parse_events(
 							connection_proof,
 							client_state_proof,
 							Some(ConsensusProof::new(
-								CommitmentProofBytes::try_from(consensus_proof.proof)?,
+								CommitmentProofBytes::try_from(consensus_proof)?,
 								client_state.latest_height(),
 							)?),
 							None,
@@ -246,8 +305,15 @@ pub async fn parse_events(
 					)?;
 					let counterparty = connection_end.counterparty();
 
-					let connection_proof =
-						CommitmentProofBytes::try_from(connection_response.proof)?;
+					let connection_proof = store_proof(
+						source,
+						&connection_id,
+						open_ack.height(),
+						ConnectionsPath(connection_id.clone()),
+						connection_response.proof,
+					)
+					.await?;
+					let connection_proof = CommitmentProofBytes::try_from(connection_proof)?;
 
 					let proof_height = connection_response.proof_height.ok_or_else(|| {
 						Error::Custom("[get_messages_for_events - open_conn_ack] Proof height not found in response".to_string())
@@ -318,7 +384,15 @@ pub async fn parse_events(
 						channel_end.version.clone(),
 					);
 
-					let channel_proof = CommitmentProofBytes::try_from(channel_response.proof)?;
+					let channel_proof = store_proof(
+						source,
+						&open_init.connection_id,
+						open_init.height(),
+						ChannelEndsPath(open_init.port_id.clone(), channel_id),
+						channel_response.proof,
+					)
+					.await?;
+					let channel_proof = CommitmentProofBytes::try_from(channel_proof)?;
 
 					let proof_height = channel_response.proof_height.expect(
 						"[get_messages_for_events - open_chan_init]Proof height should be present",
@@ -353,7 +427,15 @@ pub async fn parse_events(
 						})?)
 						.expect("Channel end decoding should not fail");
 					let counterparty = channel_end.counterparty();
-					let channel_proof = CommitmentProofBytes::try_from(channel_response.proof)?;
+					let channel_proof = store_proof(
+						source,
+						&open_try.connection_id,
+						open_try.height(),
+						ChannelEndsPath(open_try.port_id.clone(), channel_id),
+						channel_response.proof,
+					)
+					.await?;
+					let channel_proof = CommitmentProofBytes::try_from(channel_proof)?;
 
 					let proof_height = channel_response.proof_height.expect(
 						"[get_messages_for_events - open_chan_try] Proof height should be present",
@@ -361,9 +443,37 @@ pub async fn parse_events(
 					let proof_height =
 						Height::new(proof_height.revision_number, proof_height.revision_height);
 
+					let connection_response = source
+						.query_connection_end(open_try.height(), open_try.connection_id.clone())
+						.await?;
+					let counterparty_connection = connection_response
+						.connection
+						.and_then(|connection_end| connection_end.counterparty)
+						.ok_or_else(|| {
+							Error::Custom(format!(
+								"[get_messages_for_events - open_chan_try] Connection counterparty not found for {:?}",
+								open_try.connection_id
+							))
+						})?;
+
+					// the counterparty app may have opened with another version than the one
+					// proposed, let the sink decide whether to acknowledge it
+					let counterparty_version = sink.common_state().resolve_channel_version(
+						&ConnectionId::from_str(&counterparty_connection.connection_id)?,
+						&counterparty.port_id,
+						channel_end.version(),
+					)?;
+					if counterparty_version != channel_end.version {
+						log::info!(
+							target: "hyperspace",
+							"Resolved channel version {} of {} to {counterparty_version}",
+							channel_end.version, source.name()
+						);
+					}
+
 					let msg = MsgChannelOpenAck {
 						port_id: counterparty.port_id.clone(),
-						counterparty_version: channel_end.version.clone(),
+						counterparty_version,
 						proofs: Proofs::new(channel_proof, None, None, None, proof_height)?,
 						channel_id: counterparty.channel_id.expect("Expect channel id to be set"),
 						counterparty_channel_id: channel_id,
@@ -388,7 +498,15 @@ pub async fn parse_events(
 							))
 						})?)?;
 					let counterparty = channel_end.counterparty();
-					let channel_proof = CommitmentProofBytes::try_from(channel_response.proof)?;
+					let channel_proof = store_proof(
+						source,
+						&open_ack.connection_id,
+						open_ack.height(),
+						ChannelEndsPath(open_ack.port_id.clone(), channel_id),
+						channel_response.proof,
+					)
+					.await?;
+					let channel_proof = CommitmentProofBytes::try_from(channel_proof)?;
 
 					let proof_height =
 						channel_response.proof_height.expect("Proof height should be present");
@@ -420,7 +538,15 @@ pub async fn parse_events(
 						))
 					})?)?;
 				let counterparty = channel_end.counterparty();
-				let channel_proof = CommitmentProofBytes::try_from(channel_response.proof)?;
+				let channel_proof = store_proof(
+					source,
+					&close_init.connection_id,
+					close_init.height(),
+					ChannelEndsPath(close_init.port_id.clone(), channel_id),
+					channel_response.proof,
+				)
+				.await?;
+				let channel_proof = CommitmentProofBytes::try_from(channel_proof)?;
 
 				let proof_height =
 					channel_response.proof_height.expect("Proof height should be present");
@@ -460,6 +586,13 @@ pub async fn parse_events(
 								.to_string(),
 						)
 					})?)?;
+				if !source.common_state().is_channel_relayable(
+					channel_id,
+					&port_id,
+					channel_end.version(),
+				) {
+					continue
+				}
 				let connection_id = channel_end
 					.connection_hops
 					.get(0)
@@ -492,11 +625,55 @@ pub async fn parse_events(
 					continue
 				}
 
-				let packet_commitment_response = source
-					.query_packet_commitment(send_packet.height, &port_id, &channel_id, seq)
-					.await?;
-				let commitment_proof =
-					CommitmentProofBytes::try_from(packet_commitment_response.proof)?;
+				// A recv submitted now would likely land after the timeout, so the packet is left
+				// to be timed out on the source once the timeout elapses
+				let (sink_height, sink_timestamp) = match sink_latest {
+					Some(latest) => latest,
+					None => *sink_latest.insert(sink.latest_height_and_timestamp().await?),
+				};
+				let margin_blocks = sink.common_state().timeout_safety_margin_blocks;
+				if times_out_within_margin(
+					&packet,
+					sink_timestamp,
+					sink_height,
+					margin_blocks,
+					sink.expected_block_time(),
+				) {
+					log::debug!(target: "hyperspace", "Skipping packet as it times out on {} within {margin_blocks} blocks: {:?}", sink.name(), packet);
+					continue
+				}
+
+				// denied packets are left to time out
+				let action = source.common_state().packet_filter.evaluate(&packet).action;
+				if action == FilterAction::Deny {
+					continue
+				}
+
+				let packet_commitment_response = telemetry::proof_query(
+					source.name(),
+					sink.name(),
+					&packet,
+					telemetry::rpc(
+						source.name(),
+						"query_packet_commitment",
+						source.query_packet_commitment(
+							send_packet.height,
+							&port_id,
+							&channel_id,
+							seq,
+						),
+					),
+				)
+				.await?;
+				let commitment_proof = store_proof(
+					source,
+					&connection_id,
+					send_packet.height,
+					CommitmentsPath { port_id, channel_id, sequence: packet.sequence },
+					packet_commitment_response.proof,
+				)
+				.await?;
+				let commitment_proof = CommitmentProofBytes::try_from(commitment_proof)?;
 
 				let proof_height = packet_commitment_response
 					.proof_height
@@ -511,7 +688,11 @@ pub async fn parse_events(
 
 				let value = msg.encode_vec()?;
 				let msg = Any { value, type_url: msg.type_url() };
-				messages.push(msg);
+				if action == FilterAction::Deprioritize {
+					deprioritized_messages.push(msg);
+				} else {
+					messages.push(msg);
+				}
 				log::debug!(target: "hyperspace", "Sending packet {:?}", packet);
 			},
 			IbcEvent::WriteAcknowledgement(write_ack) => {
@@ -527,6 +708,13 @@ pub async fn parse_events(
 								.to_string(),
 						)
 					})?)?;
+				if !source.common_state().is_channel_relayable(
+					*channel_id,
+					port_id,
+					channel_end.version(),
+				) {
+					continue
+				}
 				let connection_id = channel_end
 					.connection_hops
 					.get(0)
@@ -546,12 +734,36 @@ pub async fn parse_events(
 				}
 				let seq = u64::from(write_ack.packet.sequence);
 				let packet = write_ack.packet;
-				let packet_acknowledgement_response = source
-					.query_packet_acknowledgement(write_ack.height, port_id, channel_id, seq)
-					.await?;
+				let packet_acknowledgement_response = telemetry::proof_query(
+					sink.name(),
+					source.name(),
+					&packet,
+					telemetry::rpc(
+						source.name(),
+						"query_packet_acknowledgement",
+						source.query_packet_acknowledgement(
+							write_ack.height,
+							port_id,
+							channel_id,
+							seq,
+						),
+					),
+				)
+				.await?;
 				let acknowledgement = write_ack.ack;
-				let commitment_proof =
-					CommitmentProofBytes::try_from(packet_acknowledgement_response.proof)?;
+				let commitment_proof = store_proof(
+					source,
+					&connection_id,
+					write_ack.height,
+					AcksPath {
+						port_id: port_id.clone(),
+						channel_id: *channel_id,
+						sequence: packet.sequence,
+					},
+					packet_acknowledgement_response.proof,
+				)
+				.await?;
+				let commitment_proof = CommitmentProofBytes::try_from(commitment_proof)?;
 
 				let proof_height = packet_acknowledgement_response
 					.proof_height
@@ -573,6 +785,10 @@ pub async fn parse_events(
 			_ => continue,
 		}
 	}
+	messages.extend(deprioritized_messages);
+	for msg in &messages {
+		known_messages().check_known(msg, &format!("the messages parsed from {}", source.name()));
+	}
 
 	// In light mode do not try to query channel state
 	if let Some(Mode::Light) = mode {
@@ -582,6 +798,23 @@ pub async fn parse_events(
 	Ok(messages)
 }
 
+/// Proof of `path` in the store of `connection_id` on `source` at `height`. The queries of
+/// `source` prove their values in its default store, so the `proof` they returned is only
+/// replaced when the connection has a store of its own.
+async fn store_proof(
+	source: &impl Chain,
+	connection_id: &ConnectionId,
+	height: Height,
+	path: impl Display,
+	proof: Vec<u8>,
+) -> Result<Vec<u8>, anyhow::Error> {
+	let prefix = source.connection_prefix_of(connection_id);
+	if prefix == source.connection_prefix() {
+		return Ok(proof)
+	}
+	Ok(source.query_proof(height, &prefix, vec![path.to_string().into_bytes()]).await?)
+}
+
 /// Fetch the consensus state proof for the sink chain.
 async fn query_host_consensus_state_proof(
 	sink: &impl Chain,
@@ -0,0 +1,60 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! ICS-29 relayer fees. The fee module of a chain credits the receive fee of a packet to the
+//! counterparty payee registered for the account that submitted the `MsgRecvPacket`, and the
+//! acknowledgement and timeout fees to the account that submitted the `MsgAcknowledgement` or
+//! `MsgTimeout`. The relayer submits its messages with its own account, so registering the
+//! counterparty payees is all it takes for the fees to be credited.
+
+use anyhow::anyhow;
+use primitives::{middleware::is_fee_enabled, Chain};
+
+/// Registers the `fee_payee_address` of `source` as the counterparty payee of the relayer's
+/// account of `sink` on the fee-enabled whitelisted channels of `sink`, so that the receive fees
+/// of the packets relayed to `sink` are credited on `source`.
+pub async fn register_counterparty_payees(
+	source: &impl Chain,
+	sink: &impl Chain,
+) -> Result<(), anyhow::Error> {
+	let payee = match &source.common_state().fee_payee_address {
+		Some(payee) => payee.clone(),
+		None => return Ok(()),
+	};
+	let (sink_height, _) = sink.latest_height_and_timestamp().await?;
+	let mut msgs = vec![];
+	for (channel_id, port_id) in sink.channel_whitelist() {
+		let channel = sink
+			.query_channel_end(sink_height, channel_id, port_id.clone())
+			.await?
+			.channel
+			.ok_or_else(|| {
+				anyhow!("Channel {channel_id}/{port_id} not found on {}", sink.name())
+			})?;
+		if !is_fee_enabled(&channel.version) {
+			continue
+		}
+		log::info!(target: "hyperspace", "Registering {payee} as the counterparty payee of the relayer on {channel_id}/{port_id} of {}", sink.name());
+		let msg = sink
+			.register_counterparty_payee_msg(channel_id, port_id, payee.clone())
+			.map_err(|e| {
+				anyhow!("Failed to register the counterparty payee on {}: {e:?}", sink.name())
+			})?;
+		msgs.push(msg);
+	}
+	if !msgs.is_empty() {
+		sink.submit(msgs).await?;
+	}
+	Ok(())
+}
@@ -0,0 +1,342 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Transfers forwarded from chain A to a chain several hops away, through intermediate chains
+//! running the packet forward middleware (PFM).
+//!
+//! The relayer can't change the data of the packets committed on chain A, so the forwarding
+//! instructions are attached to the memo of the transfers when they're sent, see
+//! [`ForwardingConfig::forward_transfer`]. Each hop is then relayed as a path of its own by
+//! [`relay_paths`].
+
+use crate::{
+	chain::{AnyChain, AnyConfig},
+	relay,
+	validation::ConfigError,
+	Mode,
+};
+use ibc::{
+	applications::transfer::{msgs::transfer::MsgTransfer, PrefixedCoin},
+	core::ics24_host::identifier::{ChannelId, PortId},
+	signer::Signer,
+};
+use metrics::handler::MetricsHandler;
+use primitives::Chain;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{path::PathBuf, str::FromStr};
+use tokio::task::JoinSet;
+
+/// Receiver of the transfers on the intermediate chains, which PFM replaces with its own
+/// account.
+const INTERMEDIATE_RECEIVER: &str = "pfm";
+
+/// Forwarding of transfers from chain A over an ordered list of hops.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForwardingConfig {
+	/// Channels of the transfers in order: the first one is the channel of chain A to chain B,
+	/// each following one the channel of an intermediate chain to the next chain.
+	pub hops: Vec<Hop>,
+	/// Paths relayed on top of the one between chain A and chain B, one per intermediate chain.
+	pub paths: Vec<PathConfig>,
+}
+
+/// A channel on which the transfers are sent or forwarded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hop {
+	/// Name of the chain sending the packets on the channel
+	pub chain: String,
+	pub channel_id: ChannelId,
+	#[serde(default = "PortId::transfer")]
+	pub port_id: PortId,
+}
+
+/// Config files of the chains of a path, the chain forwarding the transfers being chain A.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PathConfig {
+	pub config_a: PathBuf,
+	pub config_b: PathBuf,
+}
+
+impl ForwardingConfig {
+	/// Memo of the transfers sent on the first hop so that they reach `receiver` on the last
+	/// chain, in the format of the packet forward middleware.
+	pub fn memo(&self, receiver: &Signer) -> String {
+		let last = self.hops.len().saturating_sub(1);
+		let memo = self.hops.iter().enumerate().skip(1).rev().fold(None, |next, (i, hop)| {
+			let receiver =
+				if i == last { receiver.to_string() } else { INTERMEDIATE_RECEIVER.to_string() };
+			let mut forward = json!({
+				"receiver": receiver,
+				"port": hop.port_id.to_string(),
+				"channel": hop.channel_id.to_string(),
+			});
+			if let Some(next) = next {
+				forward["next"] = next;
+			}
+			Some(json!({ "forward": forward }))
+		});
+		memo.map(|memo| memo.to_string()).unwrap_or_default()
+	}
+
+	/// Sends `transfer` on the first hop, with a memo forwarding it to `receiver` on the last
+	/// chain.
+	pub fn forward_transfer(
+		&self,
+		mut transfer: MsgTransfer<PrefixedCoin>,
+		receiver: &Signer,
+	) -> MsgTransfer<PrefixedCoin> {
+		if let Some(first) = self.hops.first() {
+			transfer.source_port = first.port_id.clone();
+			transfer.source_channel = first.channel_id;
+		}
+		transfer.receiver = match self.hops.len() {
+			0 | 1 => receiver.clone(),
+			_ => Signer::from_str(INTERMEDIATE_RECEIVER).expect("receiver isn't empty; qed"),
+		};
+		transfer.memo = self.memo(receiver);
+		transfer
+	}
+
+	/// Checks the hops against the configs of the relayed `paths`, starting with the path between
+	/// chain A and chain B.
+	pub fn validate(&self, paths: &[(AnyConfig, AnyConfig)]) -> Vec<ConfigError> {
+		let mut errors = vec![];
+		let Some((chain_a, _)) = paths.first() else { return errors };
+
+		if self.hops.len() < 2 || self.hops.len() != paths.len() {
+			errors.push(ConfigError::new(
+				chain_a,
+				"forwarding.hops",
+				format!(
+					"lists {} hops, expected one per relayed path, or {}, and at least 2",
+					self.hops.len(),
+					paths.len()
+				),
+			));
+			return errors
+		}
+
+		for (i, (hop, (config_a, config_b))) in self.hops.iter().zip(paths).enumerate() {
+			if hop.chain != config_a.name() {
+				errors.push(ConfigError::new(
+					config_a,
+					"forwarding.hops",
+					format!("hop {i} is sent by {} instead of {}", hop.chain, config_a.name()),
+				));
+			}
+			// the intermediate chains are configured once for each of their paths
+			let next_sender = self.hops.get(i + 1).map(|hop| &hop.chain);
+			if next_sender.map_or(false, |sender| sender != config_b.name()) {
+				errors.push(ConfigError::new(
+					config_b,
+					"forwarding.paths",
+					format!(
+						"path {i} leads to {} instead of the next hop's chain {}",
+						config_b.name(),
+						next_sender.unwrap()
+					),
+				));
+			}
+			let whitelist = config_a.channel_whitelist();
+			if !whitelist.is_empty() && !whitelist.contains(&(hop.channel_id, hop.port_id.clone()))
+			{
+				errors.push(ConfigError::new(
+					config_a,
+					"channel_whitelist",
+					format!("doesn't list {}/{} of hop {i}", hop.channel_id, hop.port_id),
+				));
+			}
+		}
+
+		errors
+	}
+
+	/// Checks that the chains forwarding the transfers run the packet forward middleware, given
+	/// the relayed `paths`, starting with the path between chain A and chain B.
+	pub async fn validate_on_chain(
+		&self,
+		paths: &[(AnyConfig, AnyChain)],
+	) -> Result<Vec<ConfigError>, anyhow::Error> {
+		let mut errors = vec![];
+		for (config, chain) in paths.iter().skip(1) {
+			if !is_packet_forwarding_enabled(chain).await? {
+				errors.push(ConfigError::new(
+					config,
+					"forwarding.hops",
+					format!("{} doesn't run the packet forward middleware", chain.name()),
+				));
+			}
+		}
+		Ok(errors)
+	}
+}
+
+async fn is_packet_forwarding_enabled(chain: &AnyChain) -> Result<bool, anyhow::Error> {
+	match chain {
+		#[cfg(feature = "cosmos")]
+		AnyChain::Cosmos(chain) => Ok(chain.is_packet_forwarding_enabled().await?),
+		AnyChain::Wasm(chain) => Box::pin(is_packet_forwarding_enabled(&chain.inner)).await,
+		// the middleware is only available to cosmos chains
+		_ => Ok(false),
+	}
+}
+
+/// Both chains of a relayed path, with their metrics.
+pub struct RelayPath {
+	pub chain_a: AnyChain,
+	pub chain_b: AnyChain,
+	pub metrics_a: Option<MetricsHandler>,
+	pub metrics_b: Option<MetricsHandler>,
+}
+
+/// Relays all the `paths` concurrently, until one of them fails.
+pub async fn relay_paths(paths: Vec<RelayPath>, mode: Option<Mode>) -> Result<(), anyhow::Error> {
+	let mut tasks = JoinSet::new();
+	for RelayPath { chain_a, chain_b, metrics_a, metrics_b } in paths {
+		let name = format!("{}-{}", chain_a.name(), chain_b.name());
+		tasks.spawn(async move {
+			relay(chain_a, chain_b, metrics_a, metrics_b, mode)
+				.await
+				.map_err(|e| anyhow::anyhow!("Relaying {name} failed: {e}"))
+		});
+	}
+
+	let result = match tasks.join_next().await {
+		Some(result) =>
+			result.map_err(|e| anyhow::anyhow!("Relay task failed: {e}")).and_then(|r| r),
+		None => Ok(()),
+	};
+	tasks.shutdown().await;
+	result
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn hop(chain: &str, channel: u64) -> Hop {
+		Hop {
+			chain: chain.to_string(),
+			channel_id: ChannelId::new(channel),
+			port_id: PortId::transfer(),
+		}
+	}
+
+	fn forwarding(hops: Vec<Hop>) -> ForwardingConfig {
+		ForwardingConfig { hops, paths: vec![] }
+	}
+
+	fn chain(name: &str, channel_whitelist: &str) -> AnyConfig {
+		let config = format!(
+			r#"
+			type = "parachain"
+			name = "{name}"
+			para_id = 2000
+			parachain_rpc_url = "ws://127.0.0.1:9188"
+			relay_chain_rpc_url = "ws://127.0.0.1:9944"
+			commitment_prefix = "0x6962632f"
+			private_key = "//Alice"
+			ss58_version = 49
+			channel_whitelist = {channel_whitelist}
+			finality_protocol = "Grandpa"
+			key_type = "sr25519"
+			"#
+		);
+		toml::from_str(&config).unwrap()
+	}
+
+	fn fields(errors: &[ConfigError]) -> Vec<(&str, &str)> {
+		errors.iter().map(|e| (e.chain.as_str(), e.field)).collect()
+	}
+
+	#[test]
+	fn memos_forward_transfers_over_each_hop() {
+		let receiver = Signer::from_str("charlie").unwrap();
+		let two_hops = forwarding(vec![hop("a", 0), hop("b", 1)]);
+		let memo: serde_json::Value = serde_json::from_str(&two_hops.memo(&receiver)).unwrap();
+		assert_eq!(
+			memo,
+			json!({"forward": {"receiver": "charlie", "port": "transfer", "channel": "channel-1"}})
+		);
+
+		let three_hops = forwarding(vec![hop("a", 0), hop("b", 1), hop("c", 2)]);
+		let memo: serde_json::Value = serde_json::from_str(&three_hops.memo(&receiver)).unwrap();
+		assert_eq!(
+			memo,
+			json!({"forward": {
+				"receiver": "pfm",
+				"port": "transfer",
+				"channel": "channel-1",
+				"next": {"forward": {"receiver": "charlie", "port": "transfer", "channel": "channel-2"}},
+			}})
+		);
+
+		assert_eq!(forwarding(vec![hop("a", 0)]).memo(&receiver), "");
+	}
+
+	#[test]
+	fn transfers_are_sent_on_the_first_hop() {
+		let forwarding = forwarding(vec![hop("a", 3), hop("b", 1)]);
+		let transfer = MsgTransfer {
+			source_port: PortId::transfer(),
+			source_channel: ChannelId::new(0),
+			token: PrefixedCoin::from_str("100stake").unwrap(),
+			sender: Signer::from_str("alice").unwrap(),
+			receiver: Signer::from_str("bob").unwrap(),
+			timeout_height: Default::default(),
+			timeout_timestamp: Default::default(),
+			memo: "overwritten".to_string(),
+		};
+		let receiver = Signer::from_str("charlie").unwrap();
+
+		let transfer = forwarding.forward_transfer(transfer, &receiver);
+		assert_eq!(transfer.source_channel, ChannelId::new(3));
+		assert_eq!(transfer.receiver.to_string(), INTERMEDIATE_RECEIVER);
+		assert_eq!(transfer.memo, forwarding.memo(&receiver));
+	}
+
+	#[test]
+	fn hops_must_follow_the_relayed_paths() {
+		let paths =
+			vec![(chain("a", "[]"), chain("b", "[]")), (chain("b", "[]"), chain("c", "[]"))];
+		assert_eq!(forwarding(vec![hop("a", 0), hop("b", 1)]).validate(&paths), vec![]);
+
+		let missing_hop = forwarding(vec![hop("a", 0)]).validate(&paths);
+		assert_eq!(fields(&missing_hop), vec![("a", "forwarding.hops")]);
+
+		let wrong_sender = forwarding(vec![hop("a", 0), hop("c", 1)]).validate(&paths);
+		assert_eq!(
+			fields(&wrong_sender),
+			vec![("b", "forwarding.paths"), ("b", "forwarding.hops")]
+		);
+
+		// the second path doesn't start from chain b
+		let paths =
+			vec![(chain("a", "[]"), chain("b", "[]")), (chain("d", "[]"), chain("c", "[]"))];
+		let errors = forwarding(vec![hop("a", 0), hop("b", 1)]).validate(&paths);
+		assert_eq!(fields(&errors), vec![("d", "forwarding.hops")]);
+	}
+
+	#[test]
+	fn hops_must_be_whitelisted() {
+		let whitelisted = r#"[["channel-1", "transfer"]]"#;
+		let paths =
+			vec![(chain("a", "[]"), chain("b", "[]")), (chain("b", whitelisted), chain("c", "[]"))];
+		assert_eq!(forwarding(vec![hop("a", 0), hop("b", 1)]).validate(&paths), vec![]);
+
+		let errors = forwarding(vec![hop("a", 0), hop("b", 2)]).validate(&paths);
+		assert_eq!(fields(&errors), vec![("b", "channel_whitelist")]);
+	}
+}
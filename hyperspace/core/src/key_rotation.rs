@@ -0,0 +1,54 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Rotation of the keys signing the transactions of a running relayer, requested by the
+//! `rotate-key` command through the admin service of the gRPC server.
+//!
+//! The key is swapped in the signer shared by the clones of the client, so the batches waiting
+//! to be submitted are signed with the new key, without restarting the relayer. On chains whose
+//! messages name their signer, the messages assembled before the rotation are made to name the
+//! new account when they're signed, see [`primitives::signer::set_message_signer`].
+
+use anyhow::anyhow;
+use primitives::{
+	signer::{KeyRotation, KeySource},
+	Chain,
+};
+
+/// Minimum balance of the account of a new key when neither the request nor the chain config
+/// sets one, as an account without funds can't pay the fees.
+pub const DEFAULT_MIN_BALANCE: u128 = 1;
+
+/// Signs the next transactions to `chain` with the key of `key_source`, if its account holds at
+/// least `min_balance`, or else the `min_balance` of the chain config.
+pub async fn rotate_key(
+	chain: &impl Chain,
+	key_source: &KeySource,
+	min_balance: Option<u128>,
+) -> anyhow::Result<KeyRotation> {
+	let min_balance =
+		min_balance.or(chain.common_state().min_balance).unwrap_or(DEFAULT_MIN_BALANCE);
+	let rotation = chain
+		.rotate_key(key_source, min_balance)
+		.await
+		.map_err(|e| anyhow!("Failed to rotate the key of {}: {e}", chain.name()))?;
+	log::info!(
+		target: "hyperspace",
+		"Rotated the key of {} from account {} to {}",
+		chain.name(),
+		rotation.old_account,
+		rotation.new_account
+	);
+	Ok(rotation)
+}
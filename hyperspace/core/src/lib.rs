@@ -14,25 +14,89 @@
 
 #![warn(unused_variables)]
 
+pub mod archive;
+pub mod backfill;
+pub mod backlog;
 pub mod chain;
 pub mod command;
 pub mod events;
+pub mod fees;
+pub mod forwarding;
+pub mod key_rotation;
 pub mod logging;
 mod macros;
 pub mod packets;
+pub mod proof_heights;
+pub mod query_server;
 pub mod queue;
+pub mod store;
 pub mod substrate;
+pub mod telemetry;
 mod utils;
+pub mod validation;
+pub mod watchdog;
 
-use crate::utils::RecentStream;
+use crate::{
+	archive::{submit_misbehaviour, MisbehaviourArchive},
+	backlog::MessageBacklog,
+	logging::{LogScope, RelayPathId},
+	utils::{channel_updates, RecentStream},
+	watchdog::{spawn_alert_command, StallTransition, StallWatchdog},
+};
 use anyhow::anyhow;
 use events::{has_packet_events, parse_events};
 use futures::{future::ready, StreamExt, TryFutureExt};
-use ibc::{events::IbcEvent, Height};
+use ibc::{
+	core::{
+		ics02_client::msgs::update_client::MsgUpdateAnyClient, ics04_channel::channel::ChannelEnd,
+	},
+	events::IbcEvent,
+	Height,
+};
 use ibc_proto::google::protobuf::Any;
 use metrics::handler::MetricsHandler;
-use primitives::{Chain, IbcProvider, UndeliveredType, UpdateType};
-use std::collections::HashSet;
+use primitives::{
+	client_updates::{SubmittedClientUpdates, UpdateClaims},
+	middleware::unsupported_middleware,
+	mock::LocalClientTypes,
+	relay_stats::MessageCounts,
+	Chain, ClientStatus, ErrorKind, HasErrorKind, IbcProvider, UndeliveredType, UpdateType,
+};
+use std::{
+	collections::HashSet,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc,
+	},
+	time::{Duration, Instant},
+};
+use tendermint_proto::Protobuf;
+use tokio::{
+	sync::{mpsc, watch},
+	task::JoinSet,
+};
+
+/// How often the health of the finality streams is published to the metrics.
+const FINALITY_HEALTH_REPORT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How often the statistics of the misbehaviour checks are published to the metrics.
+const MISBEHAVIOUR_CHECKS_REPORT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How often the status of the clients of the path is checked.
+const CLIENT_STATUS_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often the diagnostics served by the health endpoint are published to the metrics.
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Number of health checks the relay loop can miss before the health endpoint reports it
+/// unresponsive.
+const MAX_MISSED_HEALTH_CHECKS: u32 = 3;
+
+/// How often the chains are checked for stalls.
+const STALL_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Number of expected block times after which a latest height that didn't change is stale.
+const MAX_STALE_BLOCKS: u32 = 20;
 
 #[derive(Copy, Debug, Clone)]
 pub enum Mode {
@@ -42,46 +106,387 @@ pub enum Mode {
 
 /// Core relayer loop, waits for new finality events and forwards any new [`ibc::IbcEvents`]
 /// to the counter party chain.
+///
+/// Each direction of the path is relayed by its own task, with its own finality subscription and
+/// submission pipeline, so that a slow chain only delays the packets it receives. The relayer
+/// stops as soon as one of the directions fails.
+///
+/// The messages of a direction are submitted by a task of their own, through a bounded
+/// [`MessageBacklog`], so that the finality events keep being processed while the sink is slow.
+/// Once the backlog is full, only client updates are assembled until it drains. A batch that fails
+/// to be submitted is put back at the front of the backlog. The transactions of both directions to
+/// a chain, the messages of one and the timeouts of the other, are submitted one at a time since
+/// they're signed by the same account.
 pub async fn relay<A, B>(
 	mut chain_a: A,
 	mut chain_b: B,
-	mut chain_a_metrics: Option<MetricsHandler>,
-	mut chain_b_metrics: Option<MetricsHandler>,
+	chain_a_metrics: Option<MetricsHandler>,
+	chain_b_metrics: Option<MetricsHandler>,
 	mode: Option<Mode>,
 ) -> Result<(), anyhow::Error>
 where
 	A: Chain,
 	B: Chain,
 {
-	let stream_a = RecentStream::new(chain_a.finality_notifications().await?);
-	let stream_b = RecentStream::new(chain_b.finality_notifications().await?);
-	let (mut chain_a_finality, mut chain_b_finality) = (stream_a, stream_b);
+	let (shutdown_sender, shutdown) = watch::channel(false);
+	let (shared_a, shared_b) = (SharedChainState::default(), SharedChainState::default());
+	// the finality protocols account for the updates that are still pending
+	let submitted_updates = SubmittedClientUpdates::default();
+	chain_a.common_state_mut().submitted_updates = submitted_updates.clone();
+	chain_b.common_state_mut().submitted_updates = submitted_updates.clone();
+	// each direction logs as the chain whose events it processes
+	let path = RelayPathId::new(&chain_a, &chain_b);
+	let scope_a = LogScope::chain(path.clone(), chain_a.name());
+	let scope_b = LogScope::chain(path, chain_b.name());
+	let mut directions = JoinSet::new();
+	directions.spawn(logging::scoped(
+		scope_a,
+		relay_direction(
+			chain_a.clone(),
+			chain_b.clone(),
+			chain_a_metrics,
+			mode,
+			submitted_updates.clone(),
+			shared_a.clone(),
+			shared_b.clone(),
+			shutdown.clone(),
+		),
+	));
+	directions.spawn(logging::scoped(
+		scope_b,
+		relay_direction(
+			chain_b,
+			chain_a,
+			chain_b_metrics,
+			mode,
+			submitted_updates,
+			shared_b,
+			shared_a,
+			shutdown,
+		),
+	));
+
+	let result = match directions.join_next().await {
+		Some(result) => result.map_err(|e| anyhow!("Relay task failed: {e}")).and_then(|r| r),
+		None => Ok(()),
+	};
+	let _ = shutdown_sender.send(true);
+	while let Some(other) = directions.join_next().await {
+		match other {
+			Ok(Err(e)) =>
+				log::error!(target: "hyperspace", "Relay task failed while shutting down: {e}"),
+			Err(e) =>
+				log::error!(target: "hyperspace", "Relay task failed while shutting down: {e}"),
+			Ok(Ok(())) => {},
+		}
+	}
+	result
+}
 
-	// Introduce altering between branches so that each branch gets a chance to execute first after
-	// another one
-	let mut first_executed = false;
+/// State of a chain of a path shared by the relay tasks of both directions.
+#[derive(Clone)]
+struct SharedChainState {
+	/// Held while submitting transactions to the chain, the relayer account of a chain is used by
+	/// both directions and its transactions must be sequenced
+	submissions: Arc<tokio::sync::Mutex<()>>,
+	/// Whether the client hosted on the chain is active, checked by the direction updating it
+	client_active: Arc<AtomicBool>,
+}
 
-	// loop forever
+impl Default for SharedChainState {
+	fn default() -> Self {
+		Self { submissions: Default::default(), client_active: Arc::new(AtomicBool::new(true)) }
+	}
+}
+
+/// Relays the events finalized on `source` to `sink` until `shutdown` is signalled.
+#[allow(clippy::too_many_arguments)]
+async fn relay_direction<A, B>(
+	mut source: A,
+	mut sink: B,
+	mut metrics: Option<MetricsHandler>,
+	mode: Option<Mode>,
+	submitted_updates: SubmittedClientUpdates,
+	source_state: SharedChainState,
+	sink_state: SharedChainState,
+	mut shutdown: watch::Receiver<bool>,
+) -> Result<(), anyhow::Error>
+where
+	A: Chain,
+	B: Chain,
+{
+	if let Err(e) = store::resume(&source, &sink).await {
+		log::error!(target: "hyperspace", "Failed to resume the saved state of {}: {e}", source.name());
+	}
+	// the other direction may already be submitting to the sink
+	let registration = sink_state.submissions.lock().await;
+	if let Err(e) = fees::register_counterparty_payees(&source, &sink).await {
+		log::error!(target: "hyperspace", "Failed to register the counterparty payees of {} on {}: {e}", source.name(), sink.name());
+	}
+	drop(registration);
+	let mut finality = RecentStream::new(source.finality_notifications().await?);
+	let mut finality_health_interval = tokio::time::interval(FINALITY_HEALTH_REPORT_INTERVAL);
+	let mut client_status_interval = tokio::time::interval(CLIENT_STATUS_CHECK_INTERVAL);
+	let mut health_check_interval = tokio::time::interval(HEALTH_CHECK_INTERVAL);
+	// a chain stalls after several block times, checking it more often than it produces blocks
+	// wouldn't notice the stall sooner
+	let mut stall_check_interval =
+		tokio::time::interval(STALL_CHECK_INTERVAL.min(source.expected_block_time()));
+	let mut watchdog = StallWatchdog::new(
+		source.expected_block_time(),
+		source.common_state().stall_factor,
+		Instant::now(),
+	);
+	let stop_packets_on_inactive_client = source.common_state().stop_packets_on_inactive_client ||
+		sink.common_state().stop_packets_on_inactive_client;
+	let mut relay_packets = true;
+	let backlog = MessageBacklog::new(sink.common_state().max_pending_messages);
+	// the submitter reports its transport errors, the sink is reconnected by the relay loop and
+	// published to the submitter
+	let (sink_sender, sink_updates) = watch::channel(sink.clone());
+	let (submission_error_sender, mut submission_errors) = mpsc::unbounded_channel();
+	// dropped when the direction stops, which aborts the submissions
+	let mut submitter = JoinSet::new();
+	submitter.spawn(logging::inherit_scope(submit_pending_messages(
+		source.clone(),
+		sink_updates,
+		submission_error_sender,
+		metrics.clone(),
+		backlog.clone(),
+		submitted_updates.clone(),
+		sink_state.submissions.clone(),
+	)));
+	// the latest height is polled in the background, a node that is slow to answer doesn't hold
+	// the events of the other branches
+	let (latest_height_sender, mut latest_heights) = watch::channel(None);
+	let mut height_poller = JoinSet::new();
+	height_poller.spawn(poll_latest_height(
+		source.clone(),
+		STALL_CHECK_INTERVAL.min(source.expected_block_time()),
+		latest_height_sender,
+	));
+
+	// loop until shutdown
 	loop {
 		tokio::select! {
-			// new finality event from chain A
-			result = chain_a_finality.next(), if !first_executed => {
-				first_executed = true;
-				process_finality_event(&mut chain_a, &mut chain_b, &mut chain_a_metrics, mode, result, &mut chain_a_finality, &mut chain_b_finality).await?;
+			// new finality event from the source, held while the client updates assembled under
+			// backpressure are still pending
+			result = finality.next(), if !backlog.is_full() || !backlog.has_urgent() => {
+				if result.is_some() {
+					watchdog.on_finality(Instant::now());
+					check_stall(&source, &mut watchdog, &finality, metrics.as_ref());
+				}
+				process_finality_event(&mut source, &mut sink, &sink_sender, &mut metrics, mode, relay_packets, &submitted_updates, &backlog, &source_state.submissions, result, &mut finality).await?;
 			}
-			// new finality event from chain B
-			result = chain_b_finality.next() => {
-				first_executed = false;
-				process_finality_event(&mut chain_b, &mut chain_a, &mut chain_b_metrics, mode, result, &mut chain_b_finality, &mut chain_a_finality).await?;
+			// re-evaluates the backpressure once the submissions progress
+			_ = backlog.popped(), if backlog.is_full() => {}
+			Some(e) = submission_errors.recv() => {
+				if let Err(e) = sink.handle_error(&e).await {
+					log::error!("Failed to handle error {:?}", e)
+				}
+				sink_sender.send_replace(sink.clone());
+			}
+			result = submitter.join_next() => {
+				return Err(anyhow!("Stopped submitting messages to {}: {result:?}", sink.name()))
+			}
+			// finality streams that stop yielding can't be noticed from the branch above
+			_ = finality_health_interval.tick() => {
+				report_finality_health(&source, metrics.as_ref());
+			}
+			_ = health_check_interval.tick() => {
+				report_chain_health(&source, metrics.as_ref()).await;
+				report_pending_messages(&sink, &backlog, metrics.as_ref());
+			}
+			Ok(()) = latest_heights.changed() => {
+				if let Some(height) = *latest_heights.borrow() {
+					watchdog.on_latest_height(height, Instant::now());
+				}
+			}
+			// a halted chain doesn't yield finality events, which would be waited on forever
+			_ = stall_check_interval.tick() => {
+				check_stall(&source, &mut watchdog, &finality, metrics.as_ref());
 			}
-			else => {
-				first_executed = false;
+			// each direction checks the client it updates, the other one is checked by the other
+			// direction
+			_ = client_status_interval.tick(), if !matches!(mode, Some(Mode::Light)) => {
+				let sink_client_active = is_client_active(&sink).await;
+				sink_state.client_active.store(sink_client_active, Ordering::Relaxed);
+				let source_client_active = source_state.client_active.load(Ordering::Relaxed);
+				let clients_active = source_client_active && sink_client_active;
+				if let Some(metrics) = metrics.as_ref() {
+					metrics.handle_counterparty_client_status(sink_client_active);
+				}
+				if !clients_active && stop_packets_on_inactive_client && relay_packets {
+					log::error!(target: "hyperspace", "Stopped relaying packets from {} to {} until both clients are active", source.name(), sink.name());
+				} else if clients_active && !relay_packets {
+					log::info!(target: "hyperspace", "Resumed relaying packets from {} to {}", source.name(), sink.name());
+				}
+				relay_packets = clients_active || !stop_packets_on_inactive_client;
+			}
+			_ = shutdown.changed() => {
+				log::info!(target: "hyperspace", "Stopped relaying from {} to {}", source.name(), sink.name());
+				return Ok(())
 			}
 		}
 	}
 }
 
-pub async fn fish<A, B>(chain_a: A, chain_b: B) -> Result<(), anyhow::Error>
+/// Publishes the health of the chain's finality streams, and how far behind its client is, to
+/// its metrics.
+fn report_finality_health(chain: &impl Chain, metrics: Option<&MetricsHandler>) {
+	let has_failed = chain.common_state().finality_health.decode_failure().is_some();
+	if let Some(metrics) = metrics {
+		metrics.handle_finality_decode_failure(has_failed);
+		metrics.handle_authority_set_lag(chain.common_state().authority_set_lag.get());
+	}
+}
+
+/// Publishes the latest height of `chain` to `heights` every `interval`, until the receiver is
+/// dropped.
+async fn poll_latest_height(
+	chain: impl Chain,
+	interval: Duration,
+	heights: watch::Sender<Option<u64>>,
+) {
+	let mut interval = tokio::time::interval(interval);
+	while !heights.is_closed() {
+		interval.tick().await;
+		match chain.latest_height_and_timestamp().await {
+			Ok((height, _)) => {
+				heights.send_replace(Some(height.revision_height));
+			},
+			Err(e) =>
+				log::debug!(target: "hyperspace", "Failed to query the latest height of {}: {e}", chain.name()),
+		}
+	}
+}
+
+/// Checks whether the chain stalled or resumed, alerting the operators of the changes. The
+/// first finality event received after a stall is held by the stream, so that the events it
+/// finalizes aren't replaced by the following ones before the backlog is processed.
+fn check_stall<A: Chain>(
+	chain: &A,
+	watchdog: &mut StallWatchdog,
+	finality: &RecentStream<A::FinalityEvent>,
+	metrics: Option<&MetricsHandler>,
+) {
+	let transition = match watchdog.check(Instant::now()) {
+		Some(transition) => transition,
+		None => return,
+	};
+	match transition {
+		StallTransition::Stalled(stall) => {
+			log::error!(target: "hyperspace", "{} stalled: {stall} for {} expected block times", chain.name(), chain.common_state().stall_factor);
+			finality.hold_next();
+		},
+		StallTransition::Resumed =>
+			log::info!(target: "hyperspace", "{} resumed after stalling", chain.name()),
+	}
+	if let Some(metrics) = metrics {
+		metrics.handle_chain_stall(watchdog.stall().is_some());
+	}
+	if let Some(command) = &chain.common_state().stall_alert_command {
+		spawn_alert_command(command.clone(), chain.name().to_string(), transition);
+	}
+}
+
+/// Publishes the latest height of the chain, whether its runtime api matches and the balance of
+/// the relayer's account, served by the health endpoint, to its metrics.
+async fn report_chain_health(chain: &impl Chain, metrics: Option<&MetricsHandler>) {
+	let metrics = match metrics {
+		Some(metrics) => metrics,
+		None => return,
+	};
+	metrics.handle_liveness(HEALTH_CHECK_INTERVAL * MAX_MISSED_HEALTH_CHECKS);
+	let latest_height = match chain.latest_height_and_timestamp().await {
+		Ok((height, _)) => Some(height.revision_height),
+		Err(e) => {
+			log::warn!(target: "hyperspace", "Failed to query the latest height of {}: {e}", chain.name());
+			None
+		},
+	};
+	metrics.handle_latest_height(latest_height, chain.expected_block_time() * MAX_STALE_BLOCKS);
+	metrics.handle_metadata_mismatch(chain.common_state().metadata_mismatches.len());
+
+	let min_balance = match chain.common_state().min_balance {
+		Some(min_balance) => min_balance,
+		None => return,
+	};
+	match chain.query_relayer_balance().await {
+		Ok(balance) => {
+			if balance < min_balance {
+				log::warn!(target: "hyperspace", "Balance of the relayer on {} is {balance}, below the minimum of {min_balance}", chain.name());
+			}
+			metrics.handle_balance(balance >= min_balance);
+		},
+		Err(e) =>
+			log::warn!(target: "hyperspace", "Failed to query the balance of the relayer on {}: {e}", chain.name()),
+	}
+}
+
+/// Publishes the number of messages waiting to be submitted to the sink of a direction, and how
+/// long the oldest has been waiting, to the metrics of its source.
+fn report_pending_messages(
+	sink: &impl Chain,
+	backlog: &MessageBacklog,
+	metrics: Option<&MetricsHandler>,
+) {
+	sink.common_state().relay_stats.set_pending_messages(backlog.depth());
+	if let Some(metrics) = metrics {
+		metrics.handle_pending_messages(backlog.depth(), backlog.oldest_age(Instant::now()));
+	}
+}
+
+/// Publishes the statistics of the chain's misbehaviour checks to its metrics.
+fn report_misbehaviour_checks(chain: &impl Chain, metrics: Option<&MetricsHandler>) {
+	let stats = chain.common_state().misbehaviour_checks.take();
+	if let Some(metrics) = metrics {
+		metrics.handle_misbehaviour_checks(
+			&stats.check_times,
+			stats.proof_cache_hits,
+			stats.proof_cache_misses,
+		);
+	}
+}
+
+/// Checks the status of the client hosted on `chain`, warning loudly if it isn't active. Errors
+/// are only logged, in which case the client is assumed to be active.
+async fn is_client_active(chain: &impl Chain) -> bool {
+	let client_id = chain.client_id();
+	let status = match chain.latest_height_and_timestamp().await {
+		Ok((height, _)) => chain.query_client_status(height, client_id.clone()).await,
+		Err(e) => Err(e),
+	};
+	match status {
+		Ok(ClientStatus::Active) => true,
+		Ok(status) => {
+			log::error!(
+				target: "hyperspace",
+				"Client {client_id} on {} is {status}! Packets can't be relayed until it's recovered",
+				chain.name()
+			);
+			false
+		},
+		Err(e) => {
+			log::warn!(
+				target: "hyperspace",
+				"Failed to query the status of client {client_id} on {}: {e}",
+				chain.name()
+			);
+			true
+		},
+	}
+}
+
+/// Checks the client updates of both chains for misbehaviour and submits its evidence, archiving
+/// the misbehaviour found in `archive`.
+pub async fn fish<A, B>(
+	chain_a: A,
+	chain_b: B,
+	chain_a_metrics: Option<MetricsHandler>,
+	chain_b_metrics: Option<MetricsHandler>,
+	archive: Option<MisbehaviourArchive>,
+) -> Result<(), anyhow::Error>
 where
 	A: Chain,
 	A::Error: From<B::Error>,
@@ -106,6 +511,9 @@ where
 		}),
 	);
 
+	let mut misbehaviour_checks_interval =
+		tokio::time::interval(MISBEHAVIOUR_CHECKS_REPORT_INTERVAL);
+
 	// loop forever
 	loop {
 		tokio::select! {
@@ -120,7 +528,8 @@ where
 					tokio::time::sleep(chain_a.expected_block_time()).await;
 				}
 				let message = chain_a.query_client_message(update).await.map_err(|e| { log::info!("error: {}", e); e })?;
-				chain_b.check_for_misbehaviour(&chain_a, message).await.map_err(|e| { log::info!("error: {}", e); e })?;
+				let misbehaviour = chain_b.check_for_misbehaviour(&chain_a, message).await.map_err(|e| { log::info!("error: {}", e); e })?;
+				submit_misbehaviour(&chain_a, archive.as_ref(), misbehaviour).await;
 			}
 			// new finality event from chain B
 			update = chain_b_client_updates.next() => {
@@ -133,7 +542,12 @@ where
 					tokio::time::sleep(chain_a.expected_block_time()).await;
 				}
 				let message = chain_b.query_client_message(update).await.map_err(|e| { log::info!("error: {}", e); e })?;
-				chain_a.check_for_misbehaviour(&chain_b, message).await.map_err(|e| { log::info!("error: {}", e); e })?;
+				let misbehaviour = chain_a.check_for_misbehaviour(&chain_b, message).await.map_err(|e| { log::info!("error: {}", e); e })?;
+				submit_misbehaviour(&chain_b, archive.as_ref(), misbehaviour).await;
+			}
+			_ = misbehaviour_checks_interval.tick() => {
+				report_misbehaviour_checks(&chain_a, chain_a_metrics.as_ref());
+				report_misbehaviour_checks(&chain_b, chain_b_metrics.as_ref());
 			}
 		}
 	}
@@ -141,14 +555,19 @@ where
 	Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn process_finality_event<A: Chain, B: Chain>(
 	source: &mut A,
 	sink: &mut B,
+	sink_sender: &watch::Sender<B>,
 	metrics: &mut Option<MetricsHandler>,
 	mode: Option<Mode>,
+	relay_packets: bool,
+	submitted_updates: &SubmittedClientUpdates,
+	backlog: &MessageBacklog,
+	source_submissions: &tokio::sync::Mutex<()>,
 	result: Option<A::FinalityEvent>,
 	stream_source: &mut RecentStream<A::FinalityEvent>,
-	stream_sink: &mut RecentStream<B::FinalityEvent>,
 ) -> anyhow::Result<()> {
 	match result {
 		// stream closed
@@ -164,23 +583,23 @@ async fn process_finality_event<A: Chain, B: Chain>(
 					},
 				};
 			};
-			*stream_sink = loop {
-				match sink.finality_notifications().await {
-					Ok(stream) => break RecentStream::new(stream),
-					Err(e) => {
-						log::error!("Failed to get finality notifications for {} {:?}. Trying again in 30 seconds...", sink.name(), e);
-						tokio::time::sleep(std::time::Duration::from_secs(30)).await;
-						let _ = sink.reconnect().await;
-					},
-				};
-			};
 		},
 		Some(finality_event) => {
 			log::info!("=======================================================");
 			log::info!("Received finality notification from {}", source.name(),);
 
-			let result =
-				process_some_finality_event(source, sink, metrics, mode, finality_event).await;
+			let result = process_some_finality_event(
+				source,
+				sink,
+				metrics,
+				mode,
+				relay_packets,
+				submitted_updates,
+				backlog,
+				source_submissions,
+				finality_event,
+			)
+			.await;
 
 			match result {
 				Ok(()) => {
@@ -190,12 +609,18 @@ async fn process_finality_event<A: Chain, B: Chain>(
 					source.set_rpc_call_delay(source_initial_rpc_call_delay);
 				},
 				Err(e) => {
-					log::error!("{}", e);
-					match sink.handle_error(&e).and_then(|_| source.handle_error(&e)).await {
-						Ok(_) => (),
-						Err(e) => {
-							log::error!("Failed to handle error {:?}", e)
-						},
+					let kind = error_kind::<A, B>(&e);
+					log::error!("{kind:?} error: {e}");
+					// only transport errors are recovered from, by reconnecting to the nodes
+					if kind == ErrorKind::Transport {
+						match sink.handle_error(&e).and_then(|_| source.handle_error(&e)).await {
+							Ok(_) => (),
+							Err(e) => {
+								log::error!("Failed to handle error {:?}", e)
+							},
+						}
+						// the submitter submits through the reconnected sink as well
+						sink_sender.send_replace(sink.clone());
 					}
 				},
 			}
@@ -204,11 +629,28 @@ async fn process_finality_event<A: Chain, B: Chain>(
 	Ok(())
 }
 
+/// Classifies an error of the relay loop by the error of either chain that caused it. The errors
+/// raised by the relayer itself are deterministic.
+fn error_kind<A: Chain, B: Chain>(error: &anyhow::Error) -> ErrorKind {
+	error
+		.chain()
+		.find_map(|e| {
+			let kind = e.downcast_ref::<<A as IbcProvider>::Error>().map(HasErrorKind::kind);
+			kind.or_else(|| e.downcast_ref::<<B as IbcProvider>::Error>().map(HasErrorKind::kind))
+		})
+		.unwrap_or(ErrorKind::Execution)
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn process_some_finality_event<A: Chain, B: Chain>(
 	source: &mut A,
 	sink: &mut B,
 	metrics: &mut Option<MetricsHandler>,
 	mode: Option<Mode>,
+	relay_packets: bool,
+	submitted_updates: &SubmittedClientUpdates,
+	backlog: &MessageBacklog,
+	source_submissions: &tokio::sync::Mutex<()>,
 	finality_event: <A as IbcProvider>::FinalityEvent,
 ) -> anyhow::Result<()> {
 	let updates = source
@@ -216,12 +658,25 @@ async fn process_some_finality_event<A: Chain, B: Chain>(
 		.await
 		.map_err(|e| anyhow!("Failed to fetch IBC events for finality event {e}"))?;
 	log::trace!(target: "hyperspace", "Received updates count: {}", updates.len());
+	// the packets left out while the sink catches up are found again by the next queries
+	let backpressure = backlog.is_full();
+	if backpressure {
+		log::warn!(
+			target: "hyperspace",
+			"{} messages to {} are pending, only relaying client updates until they are submitted",
+			backlog.depth(),
+			sink.name()
+		);
+	}
 	// query packets that can now be sent, at this sink height because of connection
 	// delay.
-	let (ready_packets, timeout_msgs) =
-		packets::query_ready_and_timed_out_packets(&*source, &*sink)
+	let (ready_packets, timeout_msgs) = if relay_packets && !backpressure {
+		packets::query_ready_and_timed_out_packets(&*source, &*sink, metrics.as_ref())
 			.await
-			.map_err(|e| anyhow!("Failed to parse events: {:?}", e))?;
+			.map_err(|e| anyhow!("Failed to parse events: {:?}", e))?
+	} else {
+		(vec![], vec![])
+	};
 
 	let mut msgs = Vec::new();
 
@@ -247,22 +702,160 @@ async fn process_some_finality_event<A: Chain, B: Chain>(
 		timeout_msgs.len()
 	);
 
-	process_updates(source, sink, metrics, mode, updates, &mut msgs).await?;
+	let mut batch_updates = Vec::new();
+	// released if the batch isn't queued
+	let mut claims = submitted_updates.claims(sink.name(), &source.client_id());
+	process_updates(
+		source,
+		sink,
+		metrics,
+		mode,
+		submitted_updates,
+		&mut claims,
+		updates,
+		&mut msgs,
+		&mut batch_updates,
+	)
+	.await?;
+	if !relay_packets || backpressure {
+		msgs.retain(|msg| !queue::is_packet_msg(msg));
+	}
 
 	msgs.extend(ready_packets);
 
-	process_messages(sink, metrics, msgs).await?;
+	let (source_ref, sink_ref) = (&*source, &*sink);
+	let (mut msgs, scheduled_updates) =
+		proof_heights::schedule_client_updates(msgs, batch_updates, move |height| {
+			proof_heights::client_has_consensus_state(
+				source_ref,
+				sink_ref,
+				submitted_updates,
+				height,
+			)
+		})
+		.await;
+	for (height, msg_update_client) in scheduled_updates {
+		if let Err(e) = pre_validate_update(source, sink, &msg_update_client).await {
+			log::error!(
+				target: "hyperspace",
+				"Dropping the messages proven at {height}, the client update of {} to that height failed verification against client {} on {}: {e:#}",
+				source.name(),
+				source.client_id(),
+				sink.name(),
+			);
+			msgs.retain(|msg| {
+				*msg != msg_update_client && proof_heights::proof_height(msg) != Some(height)
+			});
+			continue
+		}
+		claims.claim(height);
+	}
+
+	let claimed_updates = claims.into_heights();
+	if backpressure {
+		backlog.push_urgent(msgs, claimed_updates, Instant::now());
+	} else {
+		backlog.push(msgs, claimed_updates, Instant::now());
+	}
+	report_pending_messages(&*sink, backlog, metrics.as_ref());
+	// the other direction submits to the source as well
+	let _submissions = source_submissions.lock().await;
 	process_timeouts(source, metrics, timeout_msgs).await?;
 	Ok(())
 }
 
+/// Submits the batches of `backlog` to the sink published on `sinks`, in the order they were
+/// assembled by the relay loop of the direction.
+///
+/// A batch that fails to be submitted is requeued at the front of the backlog, without the packets
+/// that were delivered anyway, and resubmitted up to `max_submit_retries` times before it's given
+/// up on, its packets are then found again by the next packet queries. Transport errors are sent
+/// to `errors` for the relay loop to reconnect the sink, which is resubmitted to once published.
+#[allow(clippy::too_many_arguments)]
+async fn submit_pending_messages<A: Chain, B: Chain>(
+	source: A,
+	mut sinks: watch::Receiver<B>,
+	errors: mpsc::UnboundedSender<anyhow::Error>,
+	mut metrics: Option<MetricsHandler>,
+	backlog: MessageBacklog,
+	submitted_updates: SubmittedClientUpdates,
+	submissions: Arc<tokio::sync::Mutex<()>>,
+) {
+	let mut sink = sinks.borrow_and_update().clone();
+	loop {
+		let batch = backlog.pop().await;
+		if sinks.has_changed().unwrap_or(false) {
+			sink = sinks.borrow_and_update().clone();
+		}
+		let undelivered = match batch.attempts {
+			0 => Ok(Some(batch.msgs.clone())),
+			// the failed submission may have been included anyway
+			_ => queue::undelivered_messages(&sink, batch.msgs.clone()).await,
+		};
+		let (result, msgs) = match undelivered {
+			Ok(Some(msgs)) => {
+				let submission = submissions.lock().await;
+				let result = process_messages(&mut sink, &mut metrics, msgs.clone()).await;
+				drop(submission);
+				(result, msgs)
+			},
+			Ok(None) => (Ok(()), vec![]),
+			Err(e) => (Err(e), batch.msgs),
+		};
+		let e = match result {
+			Ok(()) => {
+				backlog.finish();
+				report_pending_messages(&sink, &backlog, metrics.as_ref());
+				let sink_initial_rpc_call_delay = sink.initial_rpc_call_delay();
+				sink.set_rpc_call_delay(sink_initial_rpc_call_delay);
+				continue
+			},
+			Err(e) => e,
+		};
+		let kind = error_kind::<A, B>(&e);
+		log::error!("{kind:?} error: {e}");
+		let max_attempts = sink.common_state().max_submit_retries;
+		if batch.attempts < max_attempts {
+			log::warn!(
+				target: "hyperspace",
+				"Requeuing {} messages to {} ({}/{max_attempts})",
+				msgs.len(),
+				sink.name(),
+				batch.attempts + 1
+			);
+			backlog.requeue(msgs);
+		} else {
+			log::error!(
+				target: "hyperspace",
+				"Giving up on {} messages to {} after {} failed submissions",
+				msgs.len(),
+				sink.name(),
+				batch.attempts + 1
+			);
+			backlog.finish();
+			// the updates weren't submitted, so they may be needed again
+			submitted_updates.release(sink.name(), &source.client_id(), &batch.claimed_updates);
+		}
+		report_pending_messages(&sink, &backlog, metrics.as_ref());
+		// only transport errors are recovered from, by reconnecting to the node
+		if kind == ErrorKind::Transport && errors.send(e).is_ok() {
+			// a relay loop that is stuck doesn't hold the submissions for longer than a block
+			let _ = tokio::time::timeout(sink.expected_block_time(), sinks.changed()).await;
+		}
+	}
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn process_updates<A: Chain, B: Chain>(
 	source: &mut A,
 	sink: &mut B,
 	metrics: &mut Option<MetricsHandler>,
 	mode: Option<Mode>,
+	submitted_updates: &SubmittedClientUpdates,
+	claims: &mut UpdateClaims,
 	updates: Vec<(Any, Height, Vec<IbcEvent>, UpdateType)>,
 	msgs: &mut Vec<Any>,
+	batch_updates: &mut Vec<(Height, Any)>,
 ) -> anyhow::Result<()> {
 	// for timeouts we need both chains to be up to date
 	let sink_has_undelivered_acks = sink.has_undelivered_sequences(UndeliveredType::Recvs) ||
@@ -276,6 +869,21 @@ async fn process_updates<A: Chain, B: Chain>(
 		} else {
 			HashSet::new()
 		};
+	// packets on a connection with a delay need the latest update right away, so that the delay
+	// passes before they time out
+	let latest_update_for_delayed_packets = updates
+		.iter()
+		.map(|(_, height, ..)| *height)
+		.max()
+		.filter(|_| sink.has_undelivered_sequences(UndeliveredType::DelayedRecvs));
+
+	store::record_events(
+		source,
+		&updates
+			.iter()
+			.map(|(_, height, events, _)| (*height, events.as_slice()))
+			.collect::<Vec<_>>(),
+	);
 
 	for (msg_update_client, height, events, update_type) in updates {
 		if let Some(metrics) = metrics.as_mut() {
@@ -284,6 +892,15 @@ async fn process_updates<A: Chain, B: Chain>(
 			}
 		}
 
+		telemetry::observe_events(source.name(), sink.name(), &events);
+		confirm_client_updates(source, submitted_updates, &events);
+
+		if source.common_state().auto_whitelist_channels ||
+			sink.common_state().auto_whitelist_channels
+		{
+			update_channel_whitelists(source, sink, height, &events).await?;
+		}
+
 		let event_types = events.iter().map(|ev| ev.event_type()).collect::<Vec<_>>();
 		let mut messages = parse_events(source, sink, events, mode)
 			.await
@@ -295,9 +912,10 @@ async fn process_updates<A: Chain, B: Chain>(
 			messages.len(), update_type.is_optional(),
 		);
 
-		let need_to_send_proofs_for_sequences = (sink_has_undelivered_acks ||
+		let need_to_send_proofs_for_sequences = ((sink_has_undelivered_acks ||
 			source_has_undelivered_acks) &&
-			mandatory_heights_for_undelivered_seqs.contains(&height.revision_height);
+			mandatory_heights_for_undelivered_seqs.contains(&height.revision_height)) ||
+			latest_update_for_delayed_packets == Some(height);
 		let common_state = source.common_state();
 		let skip_optional_updates = common_state.skip_optional_client_updates;
 
@@ -315,8 +933,10 @@ async fn process_updates<A: Chain, B: Chain>(
 			messages.is_empty(),
 		) {
 			(true, false, true) => {
-				// skip sending ibc messages if no new events
+				// skip sending ibc messages if no new events, the update is kept in case the
+				// messages of the batch are proven at its height
 				log::info!("Skipping finality notification for {}", sink.name());
+				batch_updates.push((height, msg_update_client));
 				continue
 			},
 			(false, _, true) =>
@@ -327,12 +947,122 @@ async fn process_updates<A: Chain, B: Chain>(
 				},
 			_ => log::info!("Received finalized events from: {} {event_types:#?}", source.name()),
 		};
-		msgs.push(msg_update_client);
+		if !claims.claim(height) {
+			log::debug!(target: "hyperspace", "Client update of {} at {height} was already submitted to {}", source.name(), sink.name());
+		} else if let Err(e) = pre_validate_update(source, sink, &msg_update_client).await {
+			// the client isn't updated, so the update is constructed again from the next
+			// finality event, along with the messages proven at its height
+			claims.release(height);
+			log::error!(
+				target: "hyperspace",
+				"Dropping client update of {} at {height} and its {} messages, it failed verification against client {} on {}: {e:#}",
+				source.name(),
+				messages.len(),
+				source.client_id(),
+				sink.name(),
+			);
+			continue
+		} else {
+			batch_updates.push((height, msg_update_client.clone()));
+			msgs.push(msg_update_client);
+		}
+		telemetry::record_client_update(sink.name(), &source.client_id(), height, &messages);
 		msgs.append(&mut messages);
 	}
 	Ok(())
 }
 
+/// Verifies `msg_update_client` against the client of `source` on `sink` if the updates of
+/// `source` are pre-validated, see [`Chain::verify_client_update`].
+async fn pre_validate_update<A: Chain, B: Chain>(
+	source: &A,
+	sink: &B,
+	msg_update_client: &Any,
+) -> anyhow::Result<()> {
+	if !source.common_state().pre_validate_updates {
+		return Ok(())
+	}
+	let msg = MsgUpdateAnyClient::<LocalClientTypes>::decode_vec(&msg_update_client.value)
+		.map_err(|e| anyhow!("Failed to decode client update: {e}"))?;
+	source.verify_client_update(sink, msg.client_message).await
+}
+
+/// Settles the pending updates of the clients hosted on `source` with its `UpdateClient` events.
+fn confirm_client_updates<A: Chain>(
+	source: &A,
+	submitted_updates: &SubmittedClientUpdates,
+	events: &[IbcEvent],
+) {
+	for event in events {
+		if let IbcEvent::UpdateClient(update) = event {
+			submitted_updates.confirm(source.name(), update.client_id(), update.consensus_height());
+			source
+				.common_state()
+				.relay_stats
+				.observe_client_update(update.client_id(), update.consensus_height());
+		}
+	}
+}
+
+/// Adds the channels opened on the relayed connections of `source` by its `events` at `height` to
+/// the whitelists of both chains, unless their middleware stack can't be relayed, and removes the
+/// closed ones.
+async fn update_channel_whitelists<A: Chain, B: Chain>(
+	source: &mut A,
+	sink: &mut B,
+	height: Height,
+	events: &[IbcEvent],
+) -> Result<(), anyhow::Error> {
+	let updates = source
+		.connection_ids()
+		.iter()
+		.flat_map(|connection_id| channel_updates(events, connection_id))
+		.collect::<Vec<_>>();
+	for update in updates {
+		let (channel_id, port_id) = &update.channel;
+		let (counterparty_channel_id, counterparty_port_id) = &update.counterparty;
+		if update.opened {
+			if source.channel_whitelist().contains(&update.channel) &&
+				sink.channel_whitelist().contains(&update.counterparty)
+			{
+				continue
+			}
+			let channel_response =
+				source.query_channel_end(height, *channel_id, port_id.clone()).await?;
+			let channel_end = ChannelEnd::try_from(channel_response.channel.ok_or_else(|| {
+				anyhow!("ChannelEnd not found for {channel_id}/{port_id} on {}", source.name())
+			})?)?;
+			let version = channel_end.version();
+			let relayable =
+				source.common_state().is_channel_relayable(*channel_id, port_id, version) &&
+					sink.common_state().is_channel_relayable(
+						*counterparty_channel_id,
+						counterparty_port_id,
+						version,
+					);
+			if !relayable {
+				log::info!(target: "hyperspace", "Not whitelisting channel {channel_id}/{port_id} on {} and {counterparty_channel_id}/{counterparty_port_id} on {}: unsupported middleware {:?} in version {version}", source.name(), sink.name(), unsupported_middleware(&version.to_string()));
+				continue
+			}
+			log::info!(target: "hyperspace", "Whitelisting channel {channel_id}/{port_id} on {} and {counterparty_channel_id}/{counterparty_port_id} on {}", source.name(), sink.name());
+			source.add_channel_to_whitelist(update.channel);
+			sink.add_channel_to_whitelist(update.counterparty);
+		} else {
+			let mut source_whitelist = source.channel_whitelist();
+			let mut sink_whitelist = sink.channel_whitelist();
+			let removed_from_source = source_whitelist.remove(&update.channel);
+			let removed_from_sink = sink_whitelist.remove(&update.counterparty);
+			if !removed_from_source && !removed_from_sink {
+				continue
+			}
+			log::info!(target: "hyperspace", "Removing closed channel {channel_id}/{port_id} on {} and {counterparty_channel_id}/{counterparty_port_id} on {} from the whitelists", source.name(), sink.name());
+			source.set_channel_whitelist(source_whitelist);
+			sink.set_channel_whitelist(sink_whitelist);
+		}
+	}
+	Ok(())
+}
+
 async fn process_messages<B: Chain>(
 	sink: &mut B,
 	metrics: &mut Option<MetricsHandler>,
@@ -345,9 +1075,16 @@ async fn process_messages<B: Chain>(
 		let type_urls = msgs.iter().map(|msg| msg.type_url.as_str()).collect::<Vec<_>>();
 		log::info!("Submitting messages to {}: {type_urls:#?}", sink.name());
 
-		queue::flush_message_batch(msgs, metrics.as_ref(), &*sink)
-			.await
-			.map_err(|e| anyhow!("Failed to submit messages: {:?}", e))?;
+		let counts = MessageCounts::of(&msgs);
+		let relay_stats = &sink.common_state().relay_stats;
+		queue::flush_message_batch(msgs, metrics.as_ref(), &*sink).await.map_err(|e| {
+			relay_stats.record_failed_submission();
+			anyhow!("Failed to submit messages: {:?}", e)
+		})?;
+		relay_stats.record_submission(counts);
+		if let Some(metrics) = metrics.as_ref() {
+			metrics.handle_successful_submission();
+		}
 		log::debug!(target: "hyperspace", "Successfully submitted messages to {}", sink.name());
 	}
 	Ok(())
@@ -364,9 +1101,18 @@ async fn process_timeouts<A: Chain>(
 		}
 		let type_urls = timeout_msgs.iter().map(|msg| msg.type_url.as_str()).collect::<Vec<_>>();
 		log::info!("Submitting timeout messages to {}: {type_urls:#?}", source.name());
+		let counts = MessageCounts::of(&timeout_msgs);
+		let relay_stats = &source.common_state().relay_stats;
 		queue::flush_message_batch(timeout_msgs, metrics.as_ref(), &*source)
 			.await
-			.map_err(|e| anyhow!("Failed to submit timeout messages: {:?}", e))?;
+			.map_err(|e| {
+				relay_stats.record_failed_submission();
+				anyhow!("Failed to submit timeout messages: {:?}", e)
+			})?;
+		relay_stats.record_submission(counts);
+		if let Some(metrics) = metrics.as_ref() {
+			metrics.handle_successful_submission();
+		}
 		log::debug!(target: "hyperspace", "Successfully submitted timeout messages to {}", source.name());
 	}
 	Ok(())
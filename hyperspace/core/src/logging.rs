@@ -12,11 +12,116 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+//! Logging setup, and the scope of the log lines of the relay loop.
+//!
+//! Every line is logged under the `hyperspace` target, so the lines of several relayers (or of
+//! several paths of one relayer) can't be told apart by their target. The relay loop runs its
+//! tasks in a [`LogScope`] naming the relayed path and the chain whose events are processed, and
+//! the lines logged from these tasks are prefixed with it.
+
 use log::LevelFilter;
+use primitives::Chain;
+use std::{
+	fmt::{self, Display},
+	future::Future,
+	io::Write,
+};
+
+tokio::task_local! {
+	static LOG_SCOPE: LogScope;
+}
 
 pub fn setup_logging() {
 	env_logger::builder()
 		.filter_module("hyper", LevelFilter::Info)
-		.format_module_path(false)
+		.format(|buf, record| {
+			write!(
+				buf,
+				"[{} {:<5} {}] ",
+				buf.timestamp(),
+				buf.default_styled_level(record.level()),
+				record.target()
+			)?;
+			if let Some(scope) = current_scope() {
+				write!(buf, "{scope} ")?;
+			}
+			writeln!(buf, "{}", record.args())
+		})
 		.init();
 }
+
+/// Identity of a relayed path, from the names and client ids of its chains.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RelayPathId(String);
+
+impl RelayPathId {
+	pub fn new(chain_a: &impl Chain, chain_b: &impl Chain) -> Self {
+		Self(format!(
+			"{}/{}<>{}/{}",
+			chain_a.name(),
+			chain_a.client_id(),
+			chain_b.name(),
+			chain_b.client_id()
+		))
+	}
+
+	pub fn as_str(&self) -> &str {
+		&self.0
+	}
+}
+
+impl Display for RelayPathId {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(&self.0)
+	}
+}
+
+/// Relayed path, and chain of that path, the log lines of a task are attributed to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogScope {
+	pub path: RelayPathId,
+	/// Name of the chain whose events are processed, if the task is specific to one chain.
+	pub chain: Option<String>,
+}
+
+impl LogScope {
+	pub fn path(path: RelayPathId) -> Self {
+		Self { path, chain: None }
+	}
+
+	pub fn chain(path: RelayPathId, chain: &str) -> Self {
+		Self { path, chain: Some(chain.to_string()) }
+	}
+}
+
+impl Display for LogScope {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "[{}]", self.path)?;
+		if let Some(chain) = &self.chain {
+			write!(f, "[{chain}]")?;
+		}
+		Ok(())
+	}
+}
+
+/// The scope of the current task, if it runs in one.
+pub fn current_scope() -> Option<LogScope> {
+	LOG_SCOPE.try_with(|scope| scope.clone()).ok()
+}
+
+/// Runs `future` in `scope`.
+pub async fn scoped<F: Future>(scope: LogScope, future: F) -> F::Output {
+	LOG_SCOPE.scope(scope, future).await
+}
+
+/// Runs `future` in the scope of the current task, for the futures spawned as separate tasks,
+/// which don't inherit it.
+pub fn inherit_scope<F: Future>(future: F) -> impl Future<Output = F::Output> {
+	let scope = current_scope();
+	async move {
+		match scope {
+			Some(scope) => scoped(scope, future).await,
+			None => future.await,
+		}
+	}
+}
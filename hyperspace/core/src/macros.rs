@@ -66,15 +66,33 @@ macro_rules! chains {
 			$(
 				$(#[$($meta)*])*
 				#[error("{0}")]
-				$name(<$client as IbcProvider>::Error),
+				$name(#[source] <$client as IbcProvider>::Error),
 			)*
 			#[error("{0}")]
-			Other(String),
+			Other(#[from] anyhow::Error),
 		}
 
-		impl From<anyhow::Error> for AnyError {
-			fn from(e: anyhow::Error) -> Self {
-				Self::Other(e.to_string())
+		impl HasErrorKind for AnyError {
+			fn kind(&self) -> ErrorKind {
+				match self {
+					$(
+						$(#[$($meta)*])*
+						Self::$name(e) => e.kind(),
+					)*
+					// the errors of the chains wrapped by the relayer keep their kind
+					Self::Other(e) => e
+						.chain()
+						.find_map(|e| {
+							$(
+								$(#[$($meta)*])*
+								if let Some(e) = e.downcast_ref::<<$client as IbcProvider>::Error>() {
+									return Some(e.kind())
+								}
+							)*
+							e.downcast_ref::<primitives::error::Error>().map(HasErrorKind::kind)
+						})
+						.unwrap_or(ErrorKind::Execution),
+				}
 			}
 		}
 
@@ -98,7 +116,7 @@ macro_rules! chains {
 						$(#[$($meta)*])*
 						Self::$name(chain) => {
 							let finality_event = downcast!(finality_event => AnyFinalityEvent::$name)
-								.ok_or_else(|| AnyError::Other("Invalid finality event type".to_owned()))?;
+								.ok_or_else(|| anyhow!("Invalid finality event type"))?;
 							chain.query_latest_ibc_events(finality_event, counterparty).await
 						}
 					)*
@@ -123,17 +141,22 @@ macro_rules! chains {
 				client_id: ClientId,
 				consensus_height: Height,
 			) -> Result<QueryConsensusStateResponse, Self::Error> {
-				match self {
-					$(
-						$(#[$($meta)*])*
-						Self::$name(chain) => chain
-							.query_client_consensus(at, client_id, consensus_height)
-							.await
-							.map_err(AnyError::$name),
-					)*
-					AnyChain::Wasm(c) =>
-						c.inner.query_client_consensus(at, client_id, consensus_height).await,
-				}
+				let query_cache = self.common_state().query_cache.clone();
+				query_cache
+					.client_consensus(at, client_id, consensus_height, |client_id| async move {
+						match self {
+							$(
+								$(#[$($meta)*])*
+								Self::$name(chain) => chain
+									.query_client_consensus(at, client_id, consensus_height)
+									.await
+									.map_err(AnyError::$name),
+							)*
+							AnyChain::Wasm(c) =>
+								c.inner.query_client_consensus(at, client_id, consensus_height).await,
+						}
+					})
+					.await
 			}
 
 			async fn query_client_state(
@@ -141,15 +164,37 @@ macro_rules! chains {
 				at: Height,
 				client_id: ClientId,
 			) -> Result<QueryClientStateResponse, Self::Error> {
+				let query_cache = self.common_state().query_cache.clone();
+				query_cache
+					.client_state(at, client_id, |client_id| async move {
+						match self {
+							$(
+								$(#[$($meta)*])*
+								Self::$name(chain) => chain
+									.query_client_state(at, client_id)
+									.await
+									.map_err(AnyError::$name),
+							)*
+							AnyChain::Wasm(c) => c.inner.query_client_state(at, client_id).await,
+						}
+					})
+					.await
+			}
+
+			async fn query_client_status(
+				&self,
+				at: Height,
+				client_id: ClientId,
+			) -> Result<ClientStatus, Self::Error> {
 				match self {
 					$(
 						$(#[$($meta)*])*
 						Self::$name(chain) => chain
-							.query_client_state(at, client_id)
+							.query_client_status(at, client_id)
 							.await
 							.map_err(AnyError::$name),
 					)*
-					AnyChain::Wasm(c) => c.inner.query_client_state(at, client_id).await,
+					AnyChain::Wasm(c) => c.inner.query_client_status(at, client_id).await,
 				}
 			}
 
@@ -188,16 +233,21 @@ macro_rules! chains {
 				}
 			}
 
-			async fn query_proof(&self, at: Height, keys: Vec<Vec<u8>>) -> Result<Vec<u8>, Self::Error> {
+			async fn query_proof(
+				&self,
+				at: Height,
+				prefix: &CommitmentPrefix,
+				keys: Vec<Vec<u8>>,
+			) -> Result<Vec<u8>, Self::Error> {
 				match self {
 					$(
 						$(#[$($meta)*])*
 						Self::$name(chain) => chain
-							.query_proof(at, keys)
+							.query_proof(at, prefix, keys)
 							.await
 							.map_err(AnyError::$name),
 					)*
-					AnyChain::Wasm(c) => c.inner.query_proof(at, keys).await,
+					AnyChain::Wasm(c) => c.inner.query_proof(at, prefix, keys).await,
 				}
 			}
 
@@ -376,6 +426,20 @@ macro_rules! chains {
 				}
 			}
 
+			fn is_channel_whitelisted_on(
+				&self,
+				channel: &(ChannelId, PortId),
+				connection_id: &ConnectionId,
+			) -> bool {
+				match self {
+					$(
+						$(#[$($meta)*])*
+						Self::$name(chain) => chain.is_channel_whitelisted_on(channel, connection_id),
+					)*
+					Self::Wasm(c) => c.inner.is_channel_whitelisted_on(channel, connection_id),
+				}
+			}
+
 			async fn query_connection_channels(
 				&self,
 				at: Height,
@@ -457,6 +521,23 @@ macro_rules! chains {
 				}
 			}
 
+			async fn closest_consensus_height(
+				&self,
+				client_id: ClientId,
+				target: Height,
+			) -> Result<Option<Height>, Self::Error> {
+				match self {
+					$(
+						$(#[$($meta)*])*
+						Self::$name(chain) => chain
+							.closest_consensus_height(client_id, target)
+							.await
+							.map_err(AnyError::$name),
+					)*
+					Self::Wasm(c) => c.inner.closest_consensus_height(client_id, target).await,
+				}
+			}
+
 			async fn query_host_consensus_state_proof(
 				&self,
 				client_state: &AnyClientState,
@@ -484,7 +565,68 @@ macro_rules! chains {
 							chain.query_ibc_balance(asset_id.into()).await.map_err(AnyError::$name),
 					)*
 					(Self::Wasm(c), asset_id) => c.inner.query_ibc_balance(asset_id).await,
-					(chain, _) => panic!("query_ibc_balance is not implemented for {}", chain.name()),
+					(chain, _) => Err(AnyError::Other(anyhow!(
+						"The asset to query the balance of doesn't belong to {}",
+						chain.name()
+					))),
+				}
+			}
+
+			async fn query_wasm_code_stored(&self, code_id: &[u8]) -> Result<bool, Self::Error> {
+				match self {
+					$(
+						$(#[$($meta)*])*
+						Self::$name(chain) =>
+							chain.query_wasm_code_stored(code_id).await.map_err(AnyError::$name),
+					)*
+					Self::Wasm(c) => c.inner.query_wasm_code_stored(code_id).await,
+				}
+			}
+
+			async fn migrate_wasm_client(
+				&mut self,
+				client_id: ClientId,
+				code_id: Vec<u8>,
+				migrate_msg: Vec<u8>,
+			) -> Result<(), Self::Error> {
+				match self {
+					$(
+						$(#[$($meta)*])*
+						Self::$name(chain) => chain
+							.migrate_wasm_client(client_id, code_id, migrate_msg)
+							.await
+							.map_err(AnyError::$name),
+					)*
+					Self::Wasm(c) => {
+						let new_code_id = CodeId::try_from(code_id.as_slice())
+							.map_err(|e| anyhow!("Invalid code id {}: {e}", hex::encode(&code_id)))?;
+						c.inner.migrate_wasm_client(client_id, code_id, migrate_msg).await?;
+						// the clients created from now on use the new code too
+						c.code_id = new_code_id;
+						Ok(())
+					},
+				}
+			}
+
+			async fn query_relayer_balance(&self) -> Result<u128, Self::Error> {
+				match self {
+					$(
+						$(#[$($meta)*])*
+						Self::$name(chain) =>
+							chain.query_relayer_balance().await.map_err(AnyError::$name),
+					)*
+					Self::Wasm(c) => c.inner.query_relayer_balance().await,
+				}
+			}
+
+			async fn query_denom_trace(&self, hash: String) -> Result<DenomTrace, Self::Error> {
+				match self {
+					$(
+						$(#[$($meta)*])*
+						Self::$name(chain) =>
+							chain.query_denom_trace(hash).await.map_err(AnyError::$name),
+					)*
+					Self::Wasm(c) => c.inner.query_denom_trace(hash).await,
 				}
 			}
 
@@ -498,6 +640,16 @@ macro_rules! chains {
 				}
 			}
 
+			fn connection_prefix_of(&self, connection_id: &ConnectionId) -> CommitmentPrefix {
+				match self {
+					$(
+						$(#[$($meta)*])*
+						Self::$name(chain) => chain.connection_prefix_of(connection_id),
+					)*
+					AnyChain::Wasm(c) => c.inner.connection_prefix_of(connection_id),
+				}
+			}
+
 			fn client_id(&self) -> ClientId {
 				match self {
 					$(
@@ -528,6 +680,16 @@ macro_rules! chains {
 				}
 			}
 
+			fn connection_ids(&self) -> Vec<ConnectionId> {
+				match self {
+					$(
+						$(#[$($meta)*])*
+						Self::$name(chain) => chain.connection_ids(),
+					)*
+					AnyChain::Wasm(c) => c.inner.connection_ids(),
+				}
+			}
+
 			fn client_type(&self) -> ClientType {
 				match self {
 					$(
@@ -558,13 +720,16 @@ macro_rules! chains {
 				}
 			}
 
-			async fn query_channels(&self) -> Result<Vec<(ChannelId, PortId)>, Self::Error> {
+			async fn query_identified_channels(
+				&self,
+			) -> Result<Vec<IdentifiedChannelEnd>, Self::Error> {
 				match self {
 					$(
 						$(#[$($meta)*])*
-						Self::$name(chain) => chain.query_channels().await.map_err(AnyError::$name),
+						Self::$name(chain) =>
+							chain.query_identified_channels().await.map_err(AnyError::$name),
 					)*
-					Self::Wasm(c) => c.inner.query_channels().await,
+					Self::Wasm(c) => c.inner.query_identified_channels().await,
 				}
 			}
 
@@ -635,7 +800,10 @@ macro_rules! chains {
 				}
 			}
 
-			async fn upload_wasm(&self, wasm: Vec<u8>) -> Result<Vec<u8>, Self::Error> {
+			async fn upload_wasm(
+				&self,
+				wasm: Vec<u8>,
+			) -> Result<(Vec<u8>, WasmUploadStatus), Self::Error> {
 				match self {
 					$(
 						$(#[$($meta)*])*
@@ -645,6 +813,24 @@ macro_rules! chains {
 				}
 			}
 
+			fn register_counterparty_payee_msg(
+				&self,
+				channel_id: ChannelId,
+				port_id: PortId,
+				counterparty_payee: String,
+			) -> Result<Any, Self::Error> {
+				match self {
+					$(
+						$(#[$($meta)*])*
+						Self::$name(chain) => chain
+							.register_counterparty_payee_msg(channel_id, port_id, counterparty_payee)
+							.map_err(AnyError::$name),
+					)*
+					Self::Wasm(c) =>
+						c.inner.register_counterparty_payee_msg(channel_id, port_id, counterparty_payee),
+				}
+			}
+
 			async fn query_connection_id_from_tx_hash(
 				&self,
 				tx_id: Self::TransactionId,
@@ -703,6 +889,21 @@ macro_rules! chains {
 				}
 			}
 
+			fn add_channel_to_connection_whitelist(
+				&mut self,
+				connection_id: ConnectionId,
+				channel: (ChannelId, PortId),
+			) {
+				match self {
+					$(
+						$(#[$($meta)*])*
+						Self::$name(chain) =>
+							chain.add_channel_to_connection_whitelist(connection_id, channel),
+					)*
+					Self::Wasm(c) => c.inner.add_channel_to_connection_whitelist(connection_id, channel),
+				}
+			}
+
 			fn set_connection_id(&mut self, connection_id: ConnectionId) {
 				match self {
 					$(
@@ -712,6 +913,16 @@ macro_rules! chains {
 					Self::Wasm(c) => c.inner.set_connection_id(connection_id),
 				}
 			}
+
+			fn add_connection_id(&mut self, connection_id: ConnectionId) {
+				match self {
+					$(
+						$(#[$($meta)*])*
+						Self::$name(chain) => chain.add_connection_id(connection_id),
+					)*
+					Self::Wasm(c) => c.inner.add_connection_id(connection_id),
+				}
+			}
 		}
 
 		#[async_trait]
@@ -720,7 +931,7 @@ macro_rules! chains {
 				&self,
 				counterparty: &C,
 				client_message: AnyClientMessage,
-			) -> Result<(), anyhow::Error> {
+			) -> Result<Option<DetectedMisbehaviour>, anyhow::Error> {
 				match self {
 					$(
 						$(#[$($meta)*])*
@@ -732,6 +943,7 @@ macro_rules! chains {
 			}
 		}
 
+		#[async_trait]
 		impl KeyProvider for AnyChain {
 			fn account_id(&self) -> Signer {
 				match self {
@@ -742,6 +954,20 @@ macro_rules! chains {
 					AnyChain::Wasm(c) => c.inner.account_id(),
 				}
 			}
+
+			async fn rotate_key(
+				&self,
+				key_source: &KeySource,
+				min_balance: u128,
+			) -> Result<KeyRotation, primitives::error::Error> {
+				match self {
+					$(
+						$(#[$($meta)*])*
+						Self::$name(chain) => chain.rotate_key(key_source, min_balance).await,
+					)*
+					AnyChain::Wasm(c) => c.inner.rotate_key(key_source, min_balance).await,
+				}
+			}
 		}
 
 		#[async_trait]
@@ -776,6 +1002,16 @@ macro_rules! chains {
 				}
 			}
 
+			async fn simulate(&self, messages: Vec<Any>) -> Result<SimulationResult, Self::Error> {
+				match self {
+					$(
+						$(#[$($meta)*])*
+						Self::$name(chain) => chain.simulate(messages).await.map_err(AnyError::$name),
+					)*
+					Self::Wasm(c) => c.inner.simulate(messages).await,
+				}
+			}
+
 			async fn finality_notifications(
 				&self,
 			) -> Result<Pin<Box<dyn Stream<Item = Self::FinalityEvent> + Send + Sync>>, Self::Error> {
@@ -806,10 +1042,8 @@ macro_rules! chains {
 							.map(|id| AnyTransactionId::$name(id)),
 					)*
 					Self::Wasm(chain) => {
-						let messages = messages
-							.into_iter()
-							.map(|msg| wrap_any_msg_into_wasm(msg, chain.code_id.clone()))
-							.collect::<Result<Vec<_>, _>>()?;
+						let skip_invalid = chain.inner.common_state().skip_invalid_wasm_messages;
+						let messages = wrap_msgs_into_wasm(messages, &chain.code_id, skip_invalid)?;
 						chain.inner.submit(messages).await.map_err(AnyError::into)
 					},
 				}
@@ -838,6 +1072,21 @@ macro_rules! chains {
 				}
 			}
 
+			async fn verify_client_update<C: Chain>(
+				&self,
+				counterparty: &C,
+				client_message: AnyClientMessage,
+			) -> Result<(), anyhow::Error> {
+				match self {
+					$(
+						$(#[$($meta)*])*
+						Self::$name(chain) =>
+							chain.verify_client_update(counterparty, client_message).await,
+					)*
+					Self::Wasm(c) => c.inner.verify_client_update(counterparty, client_message).await,
+				}
+			}
+
 			async fn handle_error(&mut self, e: &anyhow::Error) -> std::result::Result<(), anyhow::Error> {
 				match self {
 					$(
@@ -897,6 +1146,16 @@ macro_rules! chains {
 					Self::Wasm(c) => c.inner.reconnect().await,
 				}
 			}
+
+			async fn await_ready(&self, timeout: Duration) -> Result<(), anyhow::Error> {
+				match self {
+					$(
+						$(#[$($meta)*])*
+						Self::$name(chain) => chain.await_ready(timeout).await,
+					)*
+					Self::Wasm(c) => c.inner.await_ready(timeout).await,
+				}
+			}
 		}
 
 		#[async_trait]
@@ -952,6 +1211,20 @@ macro_rules! chains {
 				}
 			}
 
+			async fn send_transfer_with_fee(
+				&self,
+				params: MsgTransfer<PrefixedCoin>,
+				fee: Fee,
+			) -> Result<(), Self::Error> {
+				match self {
+					$(
+						$(#[$($meta)*])*
+						Self::$name(chain) => chain.send_transfer_with_fee(params, fee).await.map_err(AnyError::$name),
+					)*
+					Self::Wasm(c) => c.inner.send_transfer_with_fee(params, fee).await,
+				}
+			}
+
 			async fn send_ordered_packet(
 				&self,
 				channel_id: ChannelId,
@@ -966,6 +1239,25 @@ macro_rules! chains {
 				}
 			}
 
+			async fn send_raw_packet(
+				&self,
+				port_id: PortId,
+				channel_id: ChannelId,
+				data: Vec<u8>,
+				timeout: Timeout,
+			) -> Result<(), Self::Error> {
+				match self {
+					$(
+						$(#[$($meta)*])*
+						Self::$name(chain) => chain
+							.send_raw_packet(port_id, channel_id, data, timeout)
+							.await
+							.map_err(AnyError::$name),
+					)*
+					Self::Wasm(c) => c.inner.send_raw_packet(port_id, channel_id, data, timeout).await,
+				}
+			}
+
 			async fn subscribe_blocks(&self) -> Pin<Box<dyn Stream<Item = u64> + Send + Sync>> {
 				match self {
 					$(
@@ -985,11 +1277,47 @@ macro_rules! chains {
 					Self::Wasm(c) => c.inner.increase_counters().await,
 				}
 			}
+
+			async fn ensure_asset(
+				&self,
+				asset_id: AnyAssetId,
+				mint_to: Signer,
+				amount: u128,
+			) -> Result<(), Self::Error> {
+				match (self, asset_id) {
+					$(
+						$(#[$($meta)*])*
+						(Self::$name(chain), AnyAssetId::$name(asset_id)) => chain
+							.ensure_asset(asset_id, mint_to, amount)
+							.await
+							.map_err(AnyError::$name),
+					)*
+					(Self::Wasm(c), asset_id) => c.inner.ensure_asset(asset_id, mint_to, amount).await,
+					(chain, _) => Err(AnyError::Other(anyhow!(
+						"The asset to ensure doesn't belong to {}",
+						chain.name()
+					))),
+				}
+			}
+
+			async fn query_asset_id(&self, ibc_denom: String) -> Result<AnyAssetId, Self::Error> {
+				match self {
+					$(
+						$(#[$($meta)*])*
+						Self::$name(chain) => chain
+							.query_asset_id(ibc_denom)
+							.await
+							.map(AnyAssetId::$name)
+							.map_err(AnyError::$name),
+					)*
+					Self::Wasm(c) => c.inner.query_asset_id(ibc_denom).await,
+				}
+			}
 		}
 
 		impl AnyConfig {
 			pub async fn into_client(self) -> anyhow::Result<AnyChain> {
-				let maybe_wasm_code_id = self.wasm_code_id();
+				let maybe_wasm_code_id = self.wasm_code_id()?;
 				let chain = match self {
 					$(
 						$(#[$($meta)*])*
@@ -1003,6 +1331,74 @@ macro_rules! chains {
 				}
 			}
 
+			/// Like [`Self::into_client`], for the commands that need the chain to produce blocks,
+			/// failing if it doesn't within [`CLIENT_READY_TIMEOUT`] rather than once relaying.
+			pub async fn into_live_client(self) -> anyhow::Result<AnyChain> {
+				let chain = self.into_client().await?;
+				chain.await_ready(CLIENT_READY_TIMEOUT).await?;
+				Ok(chain)
+			}
+
+			pub fn name(&self) -> &str {
+				match self {
+					$(
+						$(#[$($meta)*])*
+						Self::$name(chain) => &chain.name,
+					)*
+				}
+			}
+
+			pub fn client_id(&self) -> Option<&ClientId> {
+				match self {
+					$(
+						$(#[$($meta)*])*
+						Self::$name(chain) => chain.client_id.as_ref(),
+					)*
+				}
+			}
+
+			pub fn connection_id(&self) -> Option<&ConnectionId> {
+				match self {
+					$(
+						$(#[$($meta)*])*
+						Self::$name(chain) => chain.connection_id.as_ref(),
+					)*
+				}
+			}
+
+			/// The connections relayed in addition to the primary [`Self::connection_id`].
+			pub fn connections(&self) -> &[ConnectionConfig] {
+				match self {
+					$(
+						$(#[$($meta)*])*
+						Self::$name(chain) => &chain.connections,
+					)*
+				}
+			}
+
+			pub fn connection_ids(&self) -> Vec<ConnectionId> {
+				relayed_connection_ids(self.connection_id(), self.connections())
+			}
+
+			pub fn channel_whitelist(&self) -> &[(ChannelId, PortId)] {
+				match self {
+					$(
+						$(#[$($meta)*])*
+						Self::$name(chain) => &chain.channel_whitelist,
+					)*
+				}
+			}
+
+			/// The configured `wasm_code_id`, before it's decoded by [`Self::wasm_code_id`].
+			pub fn raw_wasm_code_id(&self) -> Option<&str> {
+				match self {
+					$(
+						$(#[$($meta)*])*
+						Self::$name(chain) => chain.wasm_code_id.as_deref(),
+					)*
+				}
+			}
+
 			pub fn set_client_id(&mut self, client_id: ClientId) {
 				match self {
 					$(
@@ -1025,6 +1421,26 @@ macro_rules! chains {
 				}
 			}
 
+			/// Adds a relayed connection, which becomes the primary connection if there is none.
+			pub fn add_connection_id(&mut self, connection_id: ConnectionId) {
+				if self.connection_ids().contains(&connection_id) {
+					return
+				}
+				match self {
+					$(
+						$(#[$($meta)*])*
+						Self::$name(chain) => match chain.connection_id {
+							None => chain.connection_id = Some(connection_id),
+							Some(_) => chain.connections.push(ConnectionConfig {
+								connection_id,
+								channel_whitelist: vec![],
+								commitment_prefix: None,
+							}),
+						},
+					)*
+				}
+			}
+
 			pub fn set_channel_whitelist(&mut self, channel_id: ChannelId, port_id: PortId) {
 				match self {
 					$(
@@ -1036,17 +1452,42 @@ macro_rules! chains {
 				}
 			}
 
-			pub fn wasm_code_id(&self) -> Option<CodeId> {
-				let maybe_code_id = match self {
+			/// Whitelists a channel of `connection_id`, in the config of the connection unless it's
+			/// the primary connection.
+			pub fn whitelist_connection_channel(
+				&mut self,
+				connection_id: &ConnectionId,
+				channel_id: ChannelId,
+				port_id: PortId,
+			) {
+				match self {
 					$(
 						$(#[$($meta)*])*
-						Self::$name(chain) => chain.wasm_code_id.as_ref(),
+						Self::$name(chain) => {
+							match chain
+								.connections
+								.iter_mut()
+								.find(|c| &c.connection_id == connection_id)
+							{
+								Some(connection) if chain.connection_id.as_ref() != Some(connection_id) =>
+									connection.channel_whitelist.push((channel_id, port_id)),
+								_ => chain.channel_whitelist.push((channel_id, port_id)),
+							}
+						},
 					)*
-				};
-				let maybe_code_id =
-					maybe_code_id.map(|s| hex::decode(s).expect("Wasm code id is hex-encoded"));
+				}
+			}
 
-				maybe_code_id
+			/// The configured `wasm_code_id`, failing with an error naming the field if it isn't a
+			/// hex-encoded checksum.
+			pub fn wasm_code_id(&self) -> Result<Option<CodeId>, ConfigError> {
+				self.raw_wasm_code_id()
+					.map(|code_id| {
+						code_id
+							.parse()
+							.map_err(|message| ConfigError::new(self, "wasm_code_id", message))
+					})
+					.transpose()
 			}
 
 			pub fn set_wasm_code_id(&mut self, code_id: String) {
@@ -14,35 +14,47 @@
 
 #[cfg(feature = "testing")]
 use crate::send_packet_relay::packet_relay_status;
+use crate::telemetry;
+use anyhow::anyhow;
+use metrics::handler::MetricsHandler;
 use rand::Rng;
 use sp_runtime::Either::{Left, Right};
 use std::{
+	collections::BTreeMap,
 	sync::{
 		atomic::{AtomicUsize, Ordering},
 		Arc,
 	},
 	time::Duration,
 };
-use tokio::{task::JoinSet, time::sleep};
 
 use crate::packets::utils::{
-	construct_ack_message, construct_recv_message, construct_timeout_message,
-	get_timeout_proof_height, verify_delay_passed, VerifyDelayOn,
+	client_update_due_for_delay, construct_ack_message, construct_recv_message,
+	construct_timeout_message, ensure_packet_can_time_out, get_timeout_proof_height,
+	query_packets_concurrently, times_out_within_margin, verify_delay_passed, VerifyDelayOn,
 };
 use ibc::{
-	applications::transfer::packet::PacketData,
 	core::{
 		ics02_client::client_state::ClientState as ClientStateT,
 		ics03_connection::connection::ConnectionEnd,
-		ics04_channel::channel::{ChannelEnd, State},
+		ics04_channel::{
+			channel::{ChannelEnd, Order, State},
+			packet::Packet,
+		},
+		ics24_host::identifier::{ChannelId, ConnectionId, PortId},
 	},
 	Height,
 };
 use ibc_proto::google::protobuf::Any;
 use pallet_ibc::light_clients::AnyClientState;
 use primitives::{
-	error::Error, find_suitable_proof_height_for_client, packet_info_to_packet,
-	query_undelivered_acks, query_undelivered_sequences, Chain, UndeliveredType,
+	acknowledgement::AckResult,
+	error::Error,
+	find_suitable_proof_height_for_client,
+	packet_filter::{decode_transfer_data, FilterAction},
+	packet_info_to_packet, query_undelivered_acks, query_undelivered_sequences,
+	undelivered::Delivery,
+	Chain, UndeliveredType,
 };
 
 pub mod connection_delay;
@@ -50,6 +62,27 @@ pub mod utils;
 
 pub const PROCESS_PACKETS_BATCH_SIZE: usize = 100;
 
+/// The kinds of sequences left undelivered on a connection, which require client updates of
+/// the chains.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct UndeliveredSequences {
+	timeouts: bool,
+	recvs: bool,
+	delayed_recvs: bool,
+	acks: bool,
+}
+
+impl UndeliveredSequences {
+	fn merge(self, other: Self) -> Self {
+		Self {
+			timeouts: self.timeouts || other.timeouts,
+			recvs: self.recvs || other.recvs,
+			delayed_recvs: self.delayed_recvs || other.delayed_recvs,
+			acks: self.acks || other.acks,
+		}
+	}
+}
+
 /// Returns a tuple of messages, with the first item being packets that are ready to be sent to the
 /// sink chain. And the second item being packet timeouts that should be sent to the source.
 ///
@@ -62,12 +95,31 @@ pub const PROCESS_PACKETS_BATCH_SIZE: usize = 100;
 pub async fn query_ready_and_timed_out_packets(
 	source: &impl Chain,
 	sink: &impl Chain,
+	metrics: Option<&MetricsHandler>,
+) -> Result<(Vec<Any>, Vec<Any>), anyhow::Error> {
+	query_ready_and_timed_out_packets_from(source, sink, 0, metrics).await
+}
+
+/// Same as [`query_ready_and_timed_out_packets`], but only for the packets sent and the
+/// acknowledgements written on `source` at or above `from_height`.
+pub async fn query_ready_and_timed_out_packets_from(
+	source: &impl Chain,
+	sink: &impl Chain,
+	from_height: u64,
+	metrics: Option<&MetricsHandler>,
 ) -> Result<(Vec<Any>, Vec<Any>), anyhow::Error> {
 	let mut messages = vec![];
 	let mut timeout_messages = vec![];
+	// recv messages of packets deprioritized by the packet filter, sent after the other messages
+	let mut deprioritized_messages = vec![];
 	let (source_height, source_timestamp) = source.latest_height_and_timestamp().await?;
 	let (sink_height, sink_timestamp) = sink.latest_height_and_timestamp().await?;
+	let timeout_safety_margin_blocks = sink.common_state().timeout_safety_margin_blocks;
+	let sink_block_time = sink.expected_block_time();
 	let channel_whitelist = source.channel_whitelist();
+	// the channels of each connection are checked separately, as the connections may have
+	// different delays
+	let mut undelivered_by_connection = BTreeMap::<ConnectionId, UndeliveredSequences>::new();
 
 	// TODO: parallelize this
 	for (channel_id, port_id) in channel_whitelist {
@@ -94,11 +146,22 @@ pub async fn query_ready_and_timed_out_packets(
 			log::trace!(target: "hyperspace", "Skipping channel {:?}/{:?} because it is not open or closed", channel_id, port_id.clone());
 			continue
 		}
+		if !source.common_state().is_channel_relayable(
+			channel_id,
+			&port_id,
+			source_channel_end.version(),
+		) {
+			continue
+		}
 		let connection_id = source_channel_end
 			.connection_hops
 			.get(0)
 			.ok_or_else(|| Error::Custom("Channel end missing connection id".to_string()))?
 			.clone();
+		if !source.is_channel_whitelisted_on(&(channel_id, port_id.clone()), &connection_id) {
+			log::warn!(target: "hyperspace", "Skipping channel {channel_id}/{port_id} on {}: it is on {connection_id}, which doesn't whitelist it", source.name());
+			continue
+		}
 		let connection_response =
 			source.query_connection_end(source_height, connection_id.clone()).await?;
 		let source_connection_end =
@@ -179,6 +242,9 @@ pub async fn query_ready_and_timed_out_packets(
 		let latest_source_height_on_sink = source_client_state_on_sink.latest_height();
 
 		let max_packets_to_process = source.common_state().max_packets_to_process;
+		// the packets older than `from_height` are only filtered out once queried, so the batch
+		// is capped after they are
+		let sequences_to_query = if from_height == 0 { max_packets_to_process } else { usize::MAX };
 
 		// query packets that are waiting for connection delay.
 		let seqs = query_undelivered_sequences(
@@ -191,7 +257,7 @@ pub async fn query_ready_and_timed_out_packets(
 		)
 		.await?
 		.into_iter()
-		.take(max_packets_to_process)
+		.take(sequences_to_query)
 		.collect::<Vec<_>>();
 
 		log::debug!(target: "hyperspace", "Found {} undelivered packets for {:?}/{:?} for {seqs:?}", seqs.len(), channel_id, port_id.clone());
@@ -200,17 +266,42 @@ pub async fn query_ready_and_timed_out_packets(
 		log::trace!(target: "hyperspace", "SendPackets count before deduplication: {}", send_packets.len());
 		send_packets.sort();
 		send_packets.dedup();
+		send_packets.retain(|packet| packet.height.map_or(true, |height| height >= from_height));
+		send_packets.truncate(max_packets_to_process);
 		log::trace!(target: "hyperspace", "SendPackets count after deduplication: {}", send_packets.len());
-		let mut recv_packets_join_set: JoinSet<Result<_, anyhow::Error>> = JoinSet::new();
+		let source_channel_state = source_channel_end.state;
+		// packets to a channel closed on the sink are timed out on close with a proof of the
+		// closed channel, at a height of the sink known to the source
+		let sink_channel_closed_on_source = sink_channel_end.state == State::Closed &&
+			sink.query_channel_end(
+				latest_sink_height_on_source,
+				sink_channel_id,
+				sink_port_id.clone(),
+			)
+			.await
+			.ok()
+			.and_then(|response| response.channel)
+			.and_then(|channel| ChannelEnd::try_from(channel).ok())
+			.map_or(false, |channel| channel.state == State::Closed);
+		// the proofs are queried from the stores of the connection of the channel on each chain
+		let source_prefix = source.connection_prefix_of(&connection_id);
+		let sink_prefix = sink_channel_end
+			.connection_hops
+			.get(0)
+			.map_or_else(|| sink.connection_prefix(), |id| sink.connection_prefix_of(id));
+		let mut recv_packet_queries = vec![];
 		let source = Arc::new(source.clone());
 		let sink = Arc::new(sink.clone());
 		let timeout_packets_count = Arc::new(AtomicUsize::new(0));
 		let send_packets_count = Arc::new(AtomicUsize::new(0));
+		let delayed_recv_packets_count = Arc::new(AtomicUsize::new(0));
 		for send_packets in send_packets.chunks(PROCESS_PACKETS_BATCH_SIZE) {
 			for send_packet in send_packets.iter().cloned() {
+				let sequence = send_packet.sequence;
 				let source_connection_end = source_connection_end.clone();
 				let sink_channel_end = sink_channel_end.clone();
 				let source_connection_end = source_connection_end.clone();
+				let (source_prefix, sink_prefix) = (source_prefix.clone(), sink_prefix.clone());
 				let source = source.clone();
 				let sink = sink.clone();
 				let duration = Duration::from_millis(
@@ -218,8 +309,8 @@ pub async fn query_ready_and_timed_out_packets(
 				);
 				let timeout_packets_count = timeout_packets_count.clone();
 				let recv_packets_count = send_packets_count.clone();
-				recv_packets_join_set.spawn(async move {
-					sleep(duration).await;
+				let delayed_recv_packets_count = delayed_recv_packets_count.clone();
+				recv_packet_queries.push((sequence, duration, async move {
 					let source = &source;
 					let sink = &sink;
 					let packet = packet_info_to_packet(&send_packet);
@@ -228,23 +319,31 @@ pub async fn query_ready_and_timed_out_packets(
 						Error::Custom(format!("Packet height not found for packet {packet:?}"))
 					})?;
 
-					if packet.timed_out(&sink_timestamp, sink_height) {
+					let timed_out = packet.timed_out(&sink_timestamp, sink_height);
+					let delivery = Delivery::of(source_channel_state, sink_channel_end.state, timed_out);
+					if matches!(delivery, Some(Delivery::Timeout | Delivery::TimeoutOnClose)) {
 						timeout_packets_count.fetch_add(1, Ordering::SeqCst);
 						// so we know this packet has timed out on the sink, we need to find the maximum
 						// consensus state height at which we can generate a non-membership proof of the
 						// packet for the sink's client on the source.
-						let proof_height =
-							if let Some(proof_height) = get_timeout_proof_height(
-								&**source,
-								&**sink,
-								source_height,
-								sink_height,
-								sink_timestamp,
-								latest_sink_height_on_source,
-								&packet,
-								packet_height,
-							)
-							.await
+						let proof_height = if !timed_out {
+							// the packet can be timed out on close at any height the channel is closed
+							if !sink_channel_closed_on_source {
+								log::trace!(target: "hyperspace", "Skipping packet until the client of {} on {} sees the closed channel: {:?}", sink.name(), source.name(), packet);
+								return Ok(None)
+							}
+							latest_sink_height_on_source
+						} else if let Some(proof_height) = get_timeout_proof_height(
+							&**source,
+							&**sink,
+							source_height,
+							sink_height,
+							sink_timestamp,
+							latest_sink_height_on_source,
+							&packet,
+							packet_height,
+						)
+						.await
 						{
 							proof_height
 						} else {
@@ -275,6 +374,7 @@ pub async fn query_ready_and_timed_out_packets(
 							&**source,
 							&**sink,
 							&sink_channel_end,
+							&sink_prefix,
 							packet,
 							next_sequence_recv.next_sequence_receive,
 							proof_height,
@@ -285,11 +385,22 @@ pub async fn query_ready_and_timed_out_packets(
 						log::trace!(target: "hyperspace", "The packet has not timed out yet: {:?}", packet);
 					}
 
-					// If packet has not timed out but channel is closed on sink we skip
-					// Since we have no reference point for when this channel was closed so we can't
-					// calculate connection delays yet
-					if sink_channel_end.state == State::Closed {
-						log::debug!(target: "hyperspace", "Skipping packet as channel is closed on sink: {:?}", packet);
+					if delivery != Some(Delivery::Recv) {
+						log::debug!(target: "hyperspace", "Skipping packet as it can't be delivered while the channel is {} on {} and {} on {}: {:?}", source_channel_state, source.name(), sink_channel_end.state, sink.name(), packet);
+						return Ok(None)
+					}
+
+					// A recv submitted now would likely land after the timeout, so the packet is
+					// left to be timed out on the source once the timeout elapses
+					if times_out_within_margin(
+						&packet,
+						sink_timestamp,
+						sink_height,
+						timeout_safety_margin_blocks,
+						sink_block_time,
+					) {
+						log::debug!(target: "hyperspace", "Skipping packet as it times out on {} within {timeout_safety_margin_blocks} blocks: {:?}", sink.name(), packet);
+						timeout_packets_count.fetch_add(1, Ordering::SeqCst);
 						return Ok(None)
 					}
 
@@ -303,10 +414,28 @@ pub async fn query_ready_and_timed_out_packets(
 					// If sink does not have a client height that is equal to or greater than the packet
 					// creation height, we can't send it yet, packet_info.height should represent the packet
 					// creation height on source chain
+					// On a connection with a delay, the client update proving the packet has to be
+					// sent early enough for the delay to pass before the packet times out
+					let client_update_due = || {
+						let due = client_update_due_for_delay(
+							&packet,
+							sink_timestamp,
+							sink_height,
+							source_connection_end.delay_period(),
+							timeout_safety_margin_blocks,
+							sink_block_time,
+						);
+						if due {
+							log::debug!(target: "hyperspace", "Client update proving packet {} is due on {} for the connection delay to pass before the timeout", packet.sequence, sink.name());
+							delayed_recv_packets_count.fetch_add(1, Ordering::SeqCst);
+						}
+					};
+
 					if packet_height > latest_source_height_on_sink.revision_height {
 						// Sink does not have client update required to prove recv packet message
 						log::debug!(target: "hyperspace", "Skipping packet {:?} as sink does not have client update required to prove recv packet message", packet);
 						recv_packets_count.fetch_add(1, Ordering::SeqCst);
+						client_update_due();
 						return Ok(None)
 					}
 
@@ -324,6 +453,7 @@ pub async fn query_ready_and_timed_out_packets(
 						proof_height
 					} else {
 						log::trace!(target: "hyperspace", "Skipping packet {:?} as no proof height could be found", packet);
+						client_update_due();
 						return Ok(None)
 					};
 
@@ -350,42 +480,50 @@ pub async fn query_ready_and_timed_out_packets(
 					}
 
 					let list = &source.common_state().skip_tokens_list;
+					if let Some(decoded_data) = decode_transfer_data(&packet) {
+						if list.iter().any(|skiped_denom| decoded_data.token.denom.base_denom.as_str() == skiped_denom) {
+							log::info!(target: "hyperspace", "Skipping packet with ignored token: {:?}", packet);
+							return Ok(None)
+						}
+					}
 
-					let decoded_dara: PacketData = serde_json::from_str(&String::from_utf8_lossy(packet.data.as_ref())).map_err(|e| {
-						Error::Custom(format!(
-						"Failed to decode packet data for packet {:?}: {:?}",
-						packet, e
-						))
-					})?;
-
-					if list.iter().any(|skiped_denom| decoded_dara.token.denom.base_denom.as_str() == skiped_denom) {
-						log::info!(target: "hyperspace", "Skipping packet with ignored token: {:?}", packet);
+					// denied packets are left to time out
+					let action = source.common_state().packet_filter.evaluate(&packet).action;
+					if action == FilterAction::Deny {
 						return Ok(None)
 					}
 
-					let msg = construct_recv_message(&**source, &**sink, packet, proof_height).await?;
-					Ok(Some(Right(msg)))
-				});
+					let msg = construct_recv_message(&**source, &**sink, &source_prefix, packet, proof_height).await?;
+					Ok::<_, anyhow::Error>(Some(Right((msg, action == FilterAction::Deprioritize))))
+				}));
 			}
 		}
 
-		while let Some(result) = recv_packets_join_set.join_next().await {
-			let Some(either) = result?? else { continue };
+		// the proofs are queried from the source for both the received and the timed out packets
+		let proof_query_concurrency = source.common_state().proof_query_concurrency;
+		for result in
+			query_packets_concurrently(recv_packet_queries, proof_query_concurrency).await?
+		{
+			let Some(either) = result else { continue };
 			match either {
 				Left(msg) => timeout_messages.push(msg),
-				Right(msg) => messages.push(msg),
+				Right((msg, true)) => deprioritized_messages.push(msg),
+				Right((msg, false)) => messages.push(msg),
 			}
 		}
 
 		let timeouts_count = timeout_packets_count.load(Ordering::SeqCst);
 		log::debug!(target: "hyperspace", "Found {timeouts_count} packets that have timed out");
-		source
-			.on_undelivered_sequences(timeouts_count != 0, UndeliveredType::Timeouts)
-			.await;
-
 		let sends_count = send_packets_count.load(Ordering::SeqCst);
 		log::debug!(target: "hyperspace", "Found {sends_count} sent packets");
-		sink.on_undelivered_sequences(sends_count != 0, UndeliveredType::Recvs).await;
+		let delayed_recvs_count = delayed_recv_packets_count.load(Ordering::SeqCst);
+		let undelivered = undelivered_by_connection.entry(connection_id.clone()).or_default();
+		*undelivered = undelivered.merge(UndeliveredSequences {
+			timeouts: timeouts_count != 0,
+			recvs: sends_count != 0,
+			delayed_recvs: delayed_recvs_count != 0,
+			acks: false,
+		});
 
 		// Get acknowledgement messages
 		if source_channel_end.state == State::Closed {
@@ -404,25 +542,30 @@ pub async fn query_ready_and_timed_out_packets(
 		)
 		.await?
 		.into_iter()
-		.take(max_packets_to_process)
+		.take(sequences_to_query)
 		.collect::<Vec<_>>();
 
-		let acknowledgements =
+		let mut acknowledgements =
 			source.query_received_packets(channel_id, port_id.clone(), acks).await?;
+		acknowledgements.retain(|ack| ack.height.map_or(true, |height| height >= from_height));
+		acknowledgements.truncate(max_packets_to_process);
 		log::trace!(target: "hyperspace", "Got acknowledgements for channel {:?}: {:?}", channel_id, acknowledgements);
-		let mut acknowledgements_join_set: JoinSet<Result<_, anyhow::Error>> = JoinSet::new();
-		sink.on_undelivered_sequences(!acknowledgements.is_empty(), UndeliveredType::Acks)
-			.await;
+		let mut acknowledgement_queries = vec![];
+		if !acknowledgements.is_empty() {
+			undelivered_by_connection.entry(connection_id.clone()).or_default().acks = true;
+		}
 		for acknowledgements in acknowledgements.chunks(PROCESS_PACKETS_BATCH_SIZE) {
 			for acknowledgement in acknowledgements.iter().cloned() {
+				let sequence = acknowledgement.sequence;
 				let source_connection_end = source_connection_end.clone();
+				let source_prefix = source_prefix.clone();
 				let source = source.clone();
 				let sink = sink.clone();
+				let metrics = metrics.cloned();
 				let duration1 = Duration::from_millis(
 					rand::thread_rng().gen_range(1..source.rpc_call_delay().as_millis() as u64),
 				);
-				acknowledgements_join_set.spawn(async move {
-					sleep(duration1).await;
+				acknowledgement_queries.push((sequence, duration1, async move {
 					let source = &source;
 					let sink = &sink;
 					let packet = packet_info_to_packet(&acknowledgement);
@@ -484,17 +627,234 @@ pub async fn query_ready_and_timed_out_packets(
 						return Ok(None)
 					}
 
-					let msg = construct_ack_message(&**source, &**sink, packet, ack, proof_height).await?;
-					Ok(Some(msg))
-				});
+					report_acknowledgement(source.name(), sink.name(), &packet, &ack, metrics.as_ref());
+					let msg = construct_ack_message(&**source, &**sink, &source_prefix, packet, ack, proof_height).await?;
+					Ok::<_, anyhow::Error>(Some(msg))
+				}));
 			}
 		}
 
-		while let Some(result) = acknowledgements_join_set.join_next().await {
-			let Some(msg) = result?? else { continue };
+		for msg in query_packets_concurrently(acknowledgement_queries, proof_query_concurrency)
+			.await?
+			.into_iter()
+			.flatten()
+		{
 			messages.push(msg)
 		}
 	}
+	messages.extend(deprioritized_messages);
+
+	let mut undelivered = UndeliveredSequences::default();
+	for (connection_id, sequences) in undelivered_by_connection {
+		log::debug!(target: "hyperspace", "Undelivered sequences on connection {connection_id} of {}: {sequences:?}", source.name());
+		undelivered = undelivered.merge(sequences);
+	}
+	source
+		.on_undelivered_sequences(undelivered.timeouts, UndeliveredType::Timeouts)
+		.await;
+	sink.on_undelivered_sequences(undelivered.recvs, UndeliveredType::Recvs).await;
+	sink.on_undelivered_sequences(undelivered.delayed_recvs, UndeliveredType::DelayedRecvs)
+		.await;
+	sink.on_undelivered_sequences(undelivered.acks, UndeliveredType::Acks).await;
 
 	Ok((messages, timeout_messages))
 }
+
+/// Logs the outcome of `packet` written in its acknowledgement `ack` on `receiver`, which is
+/// relayed to `sender`, records it in the span of the packet and counts the error
+/// acknowledgements in the `metrics` of `receiver`.
+fn report_acknowledgement(
+	receiver: &str,
+	sender: &str,
+	packet: &Packet,
+	ack: &[u8],
+	metrics: Option<&MetricsHandler>,
+) {
+	let result = AckResult::decode(ack);
+	telemetry::record_acknowledgement(sender, packet, &result);
+	let sequence = packet.sequence;
+	let channel = format!("{}/{}", packet.source_channel, packet.source_port);
+	match &result {
+		AckResult::Success => {},
+		AckResult::Error(reason) => {
+			if let Some(metrics) = metrics {
+				metrics.handle_error_acknowledgement(&packet.destination_channel);
+			}
+			match decode_transfer_data(packet) {
+				Some(transfer) => log::warn!(
+					target: "hyperspace",
+					"Relaying error acknowledgement of packet {sequence} on {channel} from {receiver} to {sender}, of {} {}: {reason}",
+					transfer.token.amount,
+					transfer.token.denom,
+				),
+				None => log::warn!(
+					target: "hyperspace",
+					"Relaying error acknowledgement of packet {sequence} on {channel} from {receiver} to {sender}: {reason}"
+				),
+			}
+		},
+		AckResult::Unknown(_) => log::debug!(
+			target: "hyperspace",
+			"Relaying {result} of packet {sequence} on {channel} from {receiver} to {sender}"
+		),
+	}
+}
+
+/// Builds the `MsgTimeout`, or the `MsgTimeoutOnClose` if the counterparty channel is closed, of
+/// the packet `sequence` sent from `source` on `channel_id`, regardless of the channel
+/// whitelist and packet filters. Fails if the packet was received or can't be timed out yet.
+pub async fn construct_forced_timeout_message(
+	source: &impl Chain,
+	sink: &impl Chain,
+	channel_id: ChannelId,
+	port_id: PortId,
+	sequence: u64,
+) -> Result<Any, anyhow::Error> {
+	let (source_height, source_timestamp) = source.latest_height_and_timestamp().await?;
+	let (sink_height, sink_timestamp) = sink.latest_height_and_timestamp().await?;
+	let source_channel_end = source
+		.query_channel_end(source_height, channel_id, port_id.clone())
+		.await?
+		.channel
+		.map(ChannelEnd::try_from)
+		.transpose()?
+		.ok_or_else(|| anyhow!("Channel {channel_id}/{port_id} not found on {}", source.name()))?;
+	let commitment = source
+		.query_packet_commitment(source_height, &port_id, &channel_id, sequence)
+		.await?
+		.commitment;
+	if commitment.is_empty() {
+		return Err(anyhow!(
+			"Packet {sequence} of {channel_id}/{port_id} has no commitment on {}, it wasn't sent or was already acknowledged or timed out",
+			source.name()
+		))
+	}
+	let send_packet = source
+		.query_send_packets(channel_id, port_id.clone(), vec![sequence])
+		.await?
+		.into_iter()
+		.find(|packet| packet.sequence == sequence)
+		.ok_or_else(|| anyhow!("Packet {sequence} of {channel_id}/{port_id} not found"))?;
+	let packet = packet_info_to_packet(&send_packet);
+	let packet_height = send_packet
+		.height
+		.ok_or_else(|| anyhow!("Packet height not found for packet {sequence}"))?;
+
+	let sink_channel_id = source_channel_end
+		.counterparty()
+		.channel_id
+		.ok_or_else(|| anyhow!("Channel {channel_id}/{port_id} has no counterparty channel"))?;
+	let sink_port_id = source_channel_end.counterparty().port_id.clone();
+	let sink_channel_end = sink
+		.query_channel_end(sink_height, sink_channel_id, sink_port_id.clone())
+		.await?
+		.channel
+		.map(ChannelEnd::try_from)
+		.transpose()?
+		.ok_or_else(|| {
+			anyhow!("Channel {sink_channel_id}/{sink_port_id} not found on {}", sink.name())
+		})?;
+	let next_sequence_recv = sink
+		.query_next_sequence_recv(sink_height, &sink_port_id, &sink_channel_id)
+		.await?
+		.next_sequence_receive;
+	let received = if sink_channel_end.ordering == Order::Ordered {
+		next_sequence_recv > sequence
+	} else {
+		sink.query_packet_receipt(sink_height, &sink_port_id, &sink_channel_id, sequence)
+			.await?
+			.received
+	};
+	ensure_packet_can_time_out(
+		&packet,
+		received,
+		sink_channel_end.state,
+		sink_timestamp,
+		sink_height,
+	)
+	.map_err(|e| anyhow!("Refusing to time out the packet: {e}"))?;
+
+	let sink_client_state = source
+		.query_client_state(source_height, sink.client_id())
+		.await?
+		.client_state
+		.ok_or_else(|| anyhow!("Client state of {} not found on {}", sink.name(), source.name()))?;
+	let latest_sink_height_on_source = AnyClientState::try_from(sink_client_state)
+		.map_err(|_| anyhow!("Invalid client state of {} on {}", sink.name(), source.name()))?
+		.latest_height();
+	let proof_height = if sink_channel_end.state == State::Closed {
+		// the channel has to be closed at the height the proofs are queried at
+		let closed = sink
+			.query_channel_end(latest_sink_height_on_source, sink_channel_id, sink_port_id)
+			.await
+			.ok()
+			.and_then(|response| response.channel)
+			.and_then(|channel| ChannelEnd::try_from(channel).ok())
+			.map_or(false, |channel| channel.state == State::Closed);
+		closed.then_some(latest_sink_height_on_source)
+	} else {
+		get_timeout_proof_height(
+			source,
+			sink,
+			source_height,
+			sink_height,
+			sink_timestamp,
+			latest_sink_height_on_source,
+			&packet,
+			packet_height,
+		)
+		.await
+	};
+	let proof_height = proof_height.ok_or_else(|| {
+		anyhow!(
+			"Refusing to time out the packet: the client of {} on {} at {latest_sink_height_on_source} isn't updated past its timeout, relay a client update first",
+			sink.name(),
+			source.name()
+		)
+	})?;
+
+	let connection_id = source_channel_end
+		.connection_hops
+		.get(0)
+		.ok_or_else(|| anyhow!("Channel {channel_id}/{port_id} has no connection"))?
+		.clone();
+	let source_connection_end = source
+		.query_connection_end(source_height, connection_id.clone())
+		.await?
+		.connection
+		.map(ConnectionEnd::try_from)
+		.transpose()?
+		.ok_or_else(|| anyhow!("Connection {connection_id} not found on {}", source.name()))?;
+	if !verify_delay_passed(
+		source,
+		sink,
+		source_timestamp,
+		source_height,
+		sink_timestamp,
+		sink_height,
+		source_connection_end.delay_period(),
+		proof_height,
+		VerifyDelayOn::Source,
+	)
+	.await?
+	{
+		return Err(anyhow!(
+			"Refusing to time out the packet: the delay of connection {connection_id} hasn't passed since the proof height {proof_height}"
+		))
+	}
+
+	let sink_prefix = sink_channel_end
+		.connection_hops
+		.get(0)
+		.map_or_else(|| sink.connection_prefix(), |id| sink.connection_prefix_of(id));
+	construct_timeout_message(
+		source,
+		sink,
+		&sink_channel_end,
+		&sink_prefix,
+		packet,
+		next_sequence_recv,
+		proof_height,
+	)
+	.await
+}
@@ -12,7 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::packets::connection_delay::has_delay_elapsed;
+use crate::{packets::connection_delay::has_delay_elapsed, telemetry};
+use futures::Future;
 use ibc::{
 	core::{
 		ics02_client::client_state::ClientState as ClientStateT,
@@ -25,7 +26,7 @@ use ibc::{
 			},
 			packet::{Packet, TimeoutVariant},
 		},
-		ics23_commitment::commitment::CommitmentProofBytes,
+		ics23_commitment::commitment::{CommitmentPrefix, CommitmentProofBytes},
 		ics24_host::path::{
 			AcksPath, ChannelEndsPath, CommitmentsPath, ReceiptsPath, SeqRecvsPath,
 		},
@@ -38,8 +39,115 @@ use ibc::{
 use ibc_proto::google::protobuf::Any;
 use pallet_ibc::light_clients::AnyClientState;
 use primitives::{find_suitable_proof_height_for_client, Chain};
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 use tendermint_proto::Protobuf;
+use tokio::{sync::Semaphore, task::JoinSet, time::sleep};
+
+/// Runs the proof queries of a batch of packets, keyed by their sequences, each after its rpc
+/// call delay. At most `concurrency` of them are in flight if given, all of them otherwise, and
+/// the delays are waited out before taking a slot. The results are returned ordered by sequence,
+/// so that the messages built from them don't depend on which query completes first.
+pub async fn query_packets_concurrently<T, F>(
+	queries: impl IntoIterator<Item = (u64, Duration, F)>,
+	concurrency: Option<usize>,
+) -> Result<Vec<T>, anyhow::Error>
+where
+	T: Send + 'static,
+	F: Future<Output = Result<T, anyhow::Error>> + Send + 'static,
+{
+	let slots = concurrency.map(|concurrency| Arc::new(Semaphore::new(concurrency.max(1))));
+	let mut join_set = JoinSet::new();
+	for (sequence, delay, query) in queries {
+		let slots = slots.clone();
+		join_set.spawn(async move {
+			sleep(delay).await;
+			let _slot = match slots {
+				Some(slots) =>
+					Some(slots.acquire_owned().await.expect("the semaphore is never closed")),
+				None => None,
+			};
+			(sequence, query.await)
+		});
+	}
+	let mut results = Vec::new();
+	while let Some(result) = join_set.join_next().await {
+		results.push(result?);
+	}
+	results.sort_by_key(|(sequence, _)| *sequence);
+	results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Checks that `packet` can be timed out on the source with the sink at `sink_timestamp` and
+/// `sink_height`, returning why it can't otherwise. Packets are timed out on close regardless of
+/// their timeout once the channel is closed on the sink.
+pub fn ensure_packet_can_time_out(
+	packet: &Packet,
+	received: bool,
+	sink_channel_state: State,
+	sink_timestamp: Timestamp,
+	sink_height: Height,
+) -> Result<(), String> {
+	if received {
+		return Err(format!("packet {} was received on the counterparty", packet.sequence))
+	}
+	if sink_channel_state != State::Closed && !packet.timed_out(&sink_timestamp, sink_height) {
+		return Err(format!(
+			"the timeout of packet {} at height {} and timestamp {} hasn't elapsed on the counterparty, which is at height {sink_height} and timestamp {sink_timestamp}",
+			packet.sequence, packet.timeout_height, packet.timeout_timestamp
+		))
+	}
+	Ok(())
+}
+
+/// Returns `true` if `packet` hasn't timed out on the sink yet, but will within `margin_blocks`
+/// blocks of the sink, so that a `MsgRecvPacket` submitted now would likely fail. Packets without
+/// a timeout never expire.
+pub fn times_out_within_margin(
+	packet: &Packet,
+	sink_timestamp: Timestamp,
+	sink_height: Height,
+	margin_blocks: u32,
+	sink_block_time: Duration,
+) -> bool {
+	if margin_blocks == 0 || packet.timed_out(&sink_timestamp, sink_height) {
+		return false
+	}
+	let Ok(margin_timestamp) = sink_timestamp + sink_block_time * margin_blocks else {
+		return false
+	};
+	packet.timed_out(&margin_timestamp, sink_height.add(margin_blocks.into()))
+}
+
+/// Returns `true` if the client update proving `packet` has to be submitted to the sink now for
+/// the packet to be received before it times out. A `MsgRecvPacket` can only be submitted once
+/// `connection_delay` has passed since the update, which itself takes a block of the sink to be
+/// included, and has to land before the last `margin_blocks` blocks preceding the timeout (see
+/// [`times_out_within_margin`]). Packets without a timeout have no deadline, but nothing else
+/// would force the update proving them, so it is due right away. Always `false` on connections
+/// without a delay.
+pub fn client_update_due_for_delay(
+	packet: &Packet,
+	sink_timestamp: Timestamp,
+	sink_height: Height,
+	connection_delay: Duration,
+	margin_blocks: u32,
+	sink_block_time: Duration,
+) -> bool {
+	if connection_delay.is_zero() {
+		return false
+	}
+	if packet.timeout_height.is_zero() && packet.timeout_timestamp.nanoseconds() == 0 {
+		return true
+	}
+	if packet.timed_out(&sink_timestamp, sink_height) {
+		return false
+	}
+	let window = connection_delay + sink_block_time * (margin_blocks + 1);
+	let window_blocks =
+		calculate_block_delay(connection_delay, sink_block_time) + u64::from(margin_blocks) + 1;
+	let Ok(window_timestamp) = sink_timestamp + window else { return false };
+	packet.timed_out(&window_timestamp, sink_height.add(window_blocks))
+}
 
 #[allow(clippy::too_many_arguments)]
 pub async fn get_timeout_proof_height(
@@ -232,6 +340,7 @@ pub async fn construct_timeout_message(
 	source: &impl Chain,
 	sink: &impl Chain,
 	sink_channel_end: &ChannelEnd,
+	sink_prefix: &CommitmentPrefix,
 	packet: Packet,
 	next_sequence_recv: u64,
 	proof_height: Height,
@@ -243,11 +352,11 @@ pub async fn construct_timeout_message(
 	};
 	let key = get_key_path(path_type, &packet).into_bytes();
 
-	let proof_unreceived = sink.query_proof(proof_height, vec![key]).await?;
+	let proof_unreceived = sink.query_proof(proof_height, sink_prefix, vec![key]).await?;
 	let proof_unreceived = CommitmentProofBytes::try_from(proof_unreceived)?;
 	let msg = if sink_channel_end.state == State::Closed {
 		let channel_key = get_key_path(KeyPathType::ChannelPath, &packet).into_bytes();
-		let proof_closed = sink.query_proof(proof_height, vec![channel_key]).await?;
+		let proof_closed = sink.query_proof(proof_height, sink_prefix, vec![channel_key]).await?;
 		let proof_closed = CommitmentProofBytes::try_from(proof_closed)?;
 		let actual_proof_height = sink.get_proof_height(proof_height).await;
 		let msg = MsgTimeoutOnClose {
@@ -282,13 +391,29 @@ pub async fn construct_timeout_message(
 pub async fn construct_recv_message(
 	source: &impl Chain,
 	sink: &impl Chain,
+	source_prefix: &CommitmentPrefix,
 	packet: Packet,
 	proof_height: Height,
 ) -> Result<Any, anyhow::Error> {
 	let key = get_key_path(KeyPathType::CommitmentPath, &packet).into_bytes();
-	let proof = source.query_proof(proof_height, vec![key]).await?;
+	let (proof, actual_proof_height) =
+		telemetry::proof_query(source.name(), sink.name(), &packet, async {
+			let proof = telemetry::rpc(
+				source.name(),
+				"query_proof",
+				source.query_proof(proof_height, source_prefix, vec![key]),
+			)
+			.await?;
+			let actual_proof_height = telemetry::rpc(
+				source.name(),
+				"get_proof_height",
+				source.get_proof_height(proof_height),
+			)
+			.await;
+			Ok::<_, anyhow::Error>((proof, actual_proof_height))
+		})
+		.await?;
 	let commitment_proof = CommitmentProofBytes::try_from(proof)?;
-	let actual_proof_height = source.get_proof_height(proof_height).await;
 	let msg = MsgRecvPacket {
 		packet,
 		proofs: Proofs::new(commitment_proof, None, None, None, actual_proof_height)?,
@@ -302,15 +427,31 @@ pub async fn construct_recv_message(
 pub async fn construct_ack_message(
 	source: &impl Chain,
 	sink: &impl Chain,
+	source_prefix: &CommitmentPrefix,
 	packet: Packet,
 	ack: Vec<u8>,
 	proof_height: Height,
 ) -> Result<Any, anyhow::Error> {
 	let key = get_key_path(KeyPathType::AcksPath, &packet);
 	log::debug!(target: "hyperspace", "query proof for acks path: {:?}", key);
-	let proof = source.query_proof(proof_height, vec![key.into_bytes()]).await?;
+	let (proof, actual_proof_height) =
+		telemetry::proof_query(sink.name(), source.name(), &packet, async {
+			let proof = telemetry::rpc(
+				source.name(),
+				"query_proof",
+				source.query_proof(proof_height, source_prefix, vec![key.into_bytes()]),
+			)
+			.await?;
+			let actual_proof_height = telemetry::rpc(
+				source.name(),
+				"get_proof_height",
+				source.get_proof_height(proof_height),
+			)
+			.await;
+			Ok::<_, anyhow::Error>((proof, actual_proof_height))
+		})
+		.await?;
 	let commitment_proof = CommitmentProofBytes::try_from(proof)?;
-	let actual_proof_height = source.get_proof_height(proof_height).await;
 	let msg = MsgAcknowledgement {
 		packet,
 		proofs: Proofs::new(commitment_proof, None, None, None, actual_proof_height)?,
@@ -373,3 +514,208 @@ pub fn get_key_path(key_path_type: KeyPathType, packet: &Packet) -> String {
 		},
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const BLOCK_TIME: Duration = Duration::from_secs(6);
+
+	/// The sink produces a block every [`BLOCK_TIME`], starting at height 100.
+	fn sink_clock(blocks: u64) -> (Timestamp, Height) {
+		let start = Timestamp::from_nanoseconds(1_700_000_000_000_000_000).unwrap();
+		let timestamp = (start + BLOCK_TIME * blocks as u32).unwrap();
+		(timestamp, Height::new(1, 100 + blocks))
+	}
+
+	/// Whether the relayer receives the packet or times it out, with the sink at `blocks`.
+	fn handling(packet: &Packet, blocks: u64) -> &'static str {
+		let (timestamp, height) = sink_clock(blocks);
+		if packet.timed_out(&timestamp, height) {
+			"timeout"
+		} else if times_out_within_margin(packet, timestamp, height, 2, BLOCK_TIME) {
+			"hold"
+		} else {
+			"recv"
+		}
+	}
+
+	#[test]
+	fn packets_close_to_their_timeout_are_held_until_timed_out() {
+		let by_height = Packet { timeout_height: Height::new(1, 110), ..Default::default() };
+		let by_timestamp = Packet { timeout_timestamp: sink_clock(10).0, ..Default::default() };
+		for packet in [by_height, by_timestamp] {
+			let handling = (0..12).map(|blocks| handling(&packet, blocks)).collect::<Vec<_>>();
+			assert_eq!(&handling[..8], ["recv"; 8]);
+			assert_eq!(&handling[8..10], ["hold"; 2]);
+			assert_eq!(&handling[10..], ["timeout"; 2]);
+		}
+	}
+
+	#[test]
+	fn only_unreceived_packets_past_their_timeout_are_timed_out() {
+		let packet = Packet { timeout_height: Height::new(1, 110), ..Default::default() };
+		let (timestamp, height) = sink_clock(5);
+		assert!(ensure_packet_can_time_out(&packet, false, State::Open, timestamp, height)
+			.unwrap_err()
+			.contains("hasn't elapsed"));
+		// the packet can be timed out on close before its timeout
+		assert_eq!(
+			ensure_packet_can_time_out(&packet, false, State::Closed, timestamp, height),
+			Ok(())
+		);
+
+		let (timestamp, height) = sink_clock(10);
+		assert_eq!(
+			ensure_packet_can_time_out(&packet, false, State::Open, timestamp, height),
+			Ok(())
+		);
+		assert!(ensure_packet_can_time_out(&packet, true, State::Closed, timestamp, height)
+			.unwrap_err()
+			.contains("was received"));
+	}
+
+	#[test]
+	fn packets_without_timeout_are_always_received() {
+		let packet = Packet::default();
+		assert!((0..1000).all(|blocks| handling(&packet, blocks) == "recv"));
+	}
+
+	#[test]
+	fn zero_margin_disables_the_check() {
+		let packet = Packet { timeout_height: Height::new(1, 101), ..Default::default() };
+		let (timestamp, height) = sink_clock(0);
+		assert!(!times_out_within_margin(&packet, timestamp, height, 0, BLOCK_TIME));
+		assert!(times_out_within_margin(&packet, timestamp, height, 1, BLOCK_TIME));
+	}
+
+	#[test]
+	fn client_updates_are_due_when_the_delay_would_outlast_the_timeout() {
+		// 60s of delay is 10 blocks, plus a block to include the update and 2 blocks of margin
+		let delay = Duration::from_secs(60);
+		let by_height = Packet { timeout_height: Height::new(1, 120), ..Default::default() };
+		let by_timestamp = Packet { timeout_timestamp: sink_clock(20).0, ..Default::default() };
+		for packet in [by_height, by_timestamp] {
+			let due = (0..22)
+				.map(|blocks| {
+					let (timestamp, height) = sink_clock(blocks);
+					client_update_due_for_delay(&packet, timestamp, height, delay, 2, BLOCK_TIME)
+				})
+				.collect::<Vec<_>>();
+			assert_eq!(&due[..7], [false; 7]);
+			assert_eq!(&due[7..20], [true; 13]);
+			// timed out packets are timed out on the source instead
+			assert_eq!(&due[20..], [false; 2]);
+		}
+	}
+
+	#[test]
+	fn client_updates_are_never_due_without_a_delay() {
+		let packet = Packet { timeout_height: Height::new(1, 101), ..Default::default() };
+		let (timestamp, height) = sink_clock(0);
+		assert!(!client_update_due_for_delay(
+			&packet,
+			timestamp,
+			height,
+			Duration::ZERO,
+			2,
+			BLOCK_TIME
+		));
+		assert!(!client_update_due_for_delay(
+			&Packet::default(),
+			timestamp,
+			height,
+			Duration::ZERO,
+			2,
+			BLOCK_TIME
+		));
+	}
+
+	#[test]
+	fn client_updates_are_due_right_away_for_packets_without_a_timeout() {
+		let (timestamp, height) = sink_clock(0);
+		assert!(client_update_due_for_delay(
+			&Packet::default(),
+			timestamp,
+			height,
+			Duration::from_secs(60),
+			2,
+			BLOCK_TIME
+		));
+	}
+
+	/// Provider recording how many of its queries run at the same time.
+	#[derive(Default)]
+	struct MockProvider {
+		in_flight: std::sync::atomic::AtomicUsize,
+		max_in_flight: std::sync::atomic::AtomicUsize,
+	}
+
+	impl MockProvider {
+		async fn query_proof(&self, sequence: u64) -> Result<Vec<u8>, anyhow::Error> {
+			use std::sync::atomic::Ordering;
+			let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+			self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+			// the later packets are answered first
+			tokio::time::sleep(Duration::from_millis(100 - sequence)).await;
+			self.in_flight.fetch_sub(1, Ordering::SeqCst);
+			Ok(sequence.to_be_bytes().to_vec())
+		}
+	}
+
+	#[tokio::test]
+	async fn packet_proofs_are_queried_concurrently_in_order() {
+		let provider = Arc::new(MockProvider::default());
+		let queries = (1..=50u64).rev().map(|sequence| {
+			let provider = provider.clone();
+			(sequence, Duration::ZERO, async move { provider.query_proof(sequence).await })
+		});
+		let proofs = query_packets_concurrently(queries, Some(8)).await.unwrap();
+		assert_eq!(provider.max_in_flight.load(std::sync::atomic::Ordering::SeqCst), 8);
+		assert_eq!(
+			proofs,
+			(1..=50u64).map(|sequence| sequence.to_be_bytes().to_vec()).collect::<Vec<_>>()
+		);
+	}
+
+	#[tokio::test]
+	async fn packet_proofs_are_all_queried_at_once_by_default() {
+		let provider = Arc::new(MockProvider::default());
+		let queries = (1..=50u64).map(|sequence| {
+			let provider = provider.clone();
+			(sequence, Duration::ZERO, async move { provider.query_proof(sequence).await })
+		});
+		query_packets_concurrently(queries, None).await.unwrap();
+		assert_eq!(provider.max_in_flight.load(std::sync::atomic::Ordering::SeqCst), 50);
+	}
+
+	#[tokio::test]
+	async fn rpc_call_delays_do_not_take_query_slots() {
+		let provider = Arc::new(MockProvider::default());
+		// the first packet is delayed the longest, the others are queried meanwhile
+		let queries = (1..=4u64).map(|sequence| {
+			let provider = provider.clone();
+			let delay = Duration::from_millis(if sequence == 1 { 500 } else { 0 });
+			(sequence, delay, async move { provider.query_proof(sequence).await })
+		});
+		let start = std::time::Instant::now();
+		query_packets_concurrently(queries, Some(1)).await.unwrap();
+		// the delay overlaps the other queries, otherwise they'd all wait behind it for ~900ms
+		assert!(start.elapsed() < Duration::from_millis(750));
+	}
+
+	#[tokio::test]
+	async fn packet_proof_queries_fail_with_any_of_the_batch() {
+		let queries = (1..=3u64).map(|sequence| {
+			(sequence, Duration::ZERO, async move {
+				if sequence == 2 {
+					Err(anyhow::anyhow!("proof of packet {sequence} not found"))
+				} else {
+					Ok(sequence)
+				}
+			})
+		});
+		let error = query_packets_concurrently(queries, Some(2)).await.unwrap_err();
+		assert_eq!(error.to_string(), "proof of packet 2 not found");
+	}
+}
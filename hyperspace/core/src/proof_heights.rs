@@ -0,0 +1,347 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Check of the proof heights of a batch against the consensus states of the client verifying
+//! them.
+//!
+//! The messages of a batch are proven at the heights of the source they were queried at, while
+//! the optional client updates may have been left out of the batch. A message proven at a height
+//! the client of the sink has no consensus state at is rejected on chain, so before the batch is
+//! submitted, the update to its proof height is scheduled before it.
+
+use ibc::{
+	core::{
+		ics03_connection::msgs::{
+			conn_open_ack::TYPE_URL as CONN_OPEN_ACK_TYPE_URL,
+			conn_open_confirm::TYPE_URL as CONN_OPEN_CONFIRM_TYPE_URL,
+			conn_open_try::TYPE_URL as CONN_OPEN_TRY_TYPE_URL,
+		},
+		ics04_channel::msgs::{
+			acknowledgement::TYPE_URL as ACKNOWLEDGEMENT_TYPE_URL,
+			chan_close_confirm::TYPE_URL as CHAN_CLOSE_CONFIRM_TYPE_URL,
+			chan_open_ack::TYPE_URL as CHAN_OPEN_ACK_TYPE_URL,
+			chan_open_confirm::TYPE_URL as CHAN_OPEN_CONFIRM_TYPE_URL,
+			chan_open_try::TYPE_URL as CHAN_OPEN_TRY_TYPE_URL,
+			recv_packet::TYPE_URL as RECV_PACKET_TYPE_URL, timeout::TYPE_URL as TIMEOUT_TYPE_URL,
+			timeout_on_close::TYPE_URL as TIMEOUT_ON_CLOSE_TYPE_URL,
+		},
+	},
+	Height,
+};
+use ibc_proto::{
+	google::protobuf::Any,
+	ibc::core::{
+		channel::v1::{
+			MsgAcknowledgement, MsgChannelCloseConfirm, MsgChannelOpenAck, MsgChannelOpenConfirm,
+			MsgChannelOpenTry, MsgRecvPacket, MsgTimeout, MsgTimeoutOnClose,
+		},
+		client::v1::Height as RawHeight,
+		connection::v1::{MsgConnectionOpenAck, MsgConnectionOpenConfirm, MsgConnectionOpenTry},
+	},
+};
+use primitives::{client_updates::SubmittedClientUpdates, Chain};
+use prost::Message;
+use std::{
+	collections::{HashMap, HashSet},
+	future::Future,
+};
+
+/// Height of the consensus state the proofs of `msg` are verified against, `None` for the
+/// messages without proofs.
+pub fn proof_height(msg: &Any) -> Option<Height> {
+	let value = msg.value.as_slice();
+	let height: Option<RawHeight> = match msg.type_url.as_str() {
+		CONN_OPEN_TRY_TYPE_URL => MsgConnectionOpenTry::decode(value).ok()?.proof_height,
+		CONN_OPEN_ACK_TYPE_URL => MsgConnectionOpenAck::decode(value).ok()?.proof_height,
+		CONN_OPEN_CONFIRM_TYPE_URL => MsgConnectionOpenConfirm::decode(value).ok()?.proof_height,
+		CHAN_OPEN_TRY_TYPE_URL => MsgChannelOpenTry::decode(value).ok()?.proof_height,
+		CHAN_OPEN_ACK_TYPE_URL => MsgChannelOpenAck::decode(value).ok()?.proof_height,
+		CHAN_OPEN_CONFIRM_TYPE_URL => MsgChannelOpenConfirm::decode(value).ok()?.proof_height,
+		CHAN_CLOSE_CONFIRM_TYPE_URL => MsgChannelCloseConfirm::decode(value).ok()?.proof_height,
+		RECV_PACKET_TYPE_URL => MsgRecvPacket::decode(value).ok()?.proof_height,
+		ACKNOWLEDGEMENT_TYPE_URL => MsgAcknowledgement::decode(value).ok()?.proof_height,
+		TIMEOUT_TYPE_URL => MsgTimeout::decode(value).ok()?.proof_height,
+		TIMEOUT_ON_CLOSE_TYPE_URL => MsgTimeoutOnClose::decode(value).ok()?.proof_height,
+		_ => None,
+	};
+	height.map(|height| Height::new(height.revision_number, height.revision_height))
+}
+
+/// Orders the batch `msgs` so that every message is verified against a consensus state the
+/// client has once the messages before it are executed. Returns the batch and the updates
+/// scheduled into it.
+///
+/// `updates` are the client updates built along with the batch, whether they are in `msgs` or
+/// were left out as optional. The proofs of a message are verified:
+/// - against an update of the batch before it, or the update further in the batch is moved before
+///   it, along with the updates to lower heights so that they stay ascending,
+/// - against a consensus state the client already has, see `has_consensus_state`,
+/// - or against the left out update to its proof height, scheduled before it.
+///
+/// The updates moved or scheduled are placed before the higher updates already in the batch, so
+/// that the batch updates the client in ascending order. Otherwise a message is dropped, the
+/// packet queries find it again at a proof height the client has. The handshake messages are
+/// built from events that aren't queried again, so they are left for the chain to check instead.
+pub async fn schedule_client_updates<F, Fut>(
+	msgs: Vec<Any>,
+	updates: Vec<(Height, Any)>,
+	mut has_consensus_state: F,
+) -> (Vec<Any>, Vec<(Height, Any)>)
+where
+	F: FnMut(Height) -> Fut,
+	Fut: Future<Output = bool>,
+{
+	let (in_batch, mut left_out): (Vec<_>, Vec<_>) =
+		updates.into_iter().partition(|(_, update)| msgs.contains(update));
+	let mut slots = msgs
+		.into_iter()
+		.map(|msg| {
+			let update_height =
+				in_batch.iter().find(|(_, update)| *update == msg).map(|(height, _)| *height);
+			Some((msg, update_height))
+		})
+		.collect::<Vec<_>>();
+
+	// the messages, along with the heights of the client updates among them
+	let mut batch: Vec<(Any, Option<Height>)> = Vec::with_capacity(slots.len());
+	let mut scheduled = vec![];
+	let mut updated = HashSet::new();
+	let mut on_chain = HashMap::new();
+	for i in 0..slots.len() {
+		let Some((msg, update_height)) = slots[i].take() else { continue };
+		if let Some(height) = update_height {
+			updated.insert(height);
+			batch.push((msg, Some(height)));
+			continue
+		}
+		let Some(proof_height) = proof_height(&msg) else {
+			batch.push((msg, None));
+			continue
+		};
+		if updated.contains(&proof_height) {
+			batch.push((msg, None));
+			continue
+		}
+
+		if slots[i..].iter().flatten().any(|(_, height)| *height == Some(proof_height)) {
+			log::debug!(target: "hyperspace", "Moving the client update to {proof_height} before the {} proven at that height", msg.type_url);
+			for slot in &mut slots[i..] {
+				if matches!(slot, Some((_, Some(height))) if *height <= proof_height) {
+					let (update, height) = slot.take().expect("slot was matched as some; qed");
+					updated.extend(height);
+					insert_update(
+						&mut batch,
+						update,
+						height.expect("slot was matched as an update; qed"),
+					);
+				}
+			}
+			batch.push((msg, None));
+			continue
+		}
+
+		let has_consensus_state = match on_chain.get(&proof_height) {
+			Some(has_consensus_state) => *has_consensus_state,
+			None => {
+				let has = has_consensus_state(proof_height).await;
+				on_chain.insert(proof_height, has);
+				has
+			},
+		};
+		if has_consensus_state {
+			batch.push((msg, None));
+			continue
+		}
+
+		if let Some(position) = left_out.iter().position(|(height, _)| *height == proof_height) {
+			log::info!(target: "hyperspace", "Scheduling the client update to {proof_height} before the {} proven at that height", msg.type_url);
+			let (height, update) = left_out.remove(position);
+			updated.insert(height);
+			insert_update(&mut batch, update.clone(), height);
+			scheduled.push((height, update));
+			batch.push((msg, None));
+			continue
+		}
+		if is_handshake_msg(&msg) {
+			log::warn!(target: "hyperspace", "Submitting {} proven at {proof_height}, though no consensus state of the client at that height was found", msg.type_url);
+			batch.push((msg, None));
+			continue
+		}
+		log::warn!(target: "hyperspace", "Dropping {} proven at {proof_height}, the client has no consensus state at that height", msg.type_url);
+	}
+	(batch.into_iter().map(|(msg, _)| msg).collect(), scheduled)
+}
+
+/// Inserts the client update to `height` before the first higher update of `batch`, or at its
+/// end.
+fn insert_update(batch: &mut Vec<(Any, Option<Height>)>, update: Any, height: Height) {
+	let position = batch
+		.iter()
+		.position(|(_, update_height)| matches!(update_height, Some(h) if *h > height))
+		.unwrap_or(batch.len());
+	batch.insert(position, (update, Some(height)));
+}
+
+/// Whether `msg` is a connection or channel handshake message.
+fn is_handshake_msg(msg: &Any) -> bool {
+	matches!(
+		msg.type_url.as_str(),
+		CONN_OPEN_TRY_TYPE_URL |
+			CONN_OPEN_ACK_TYPE_URL |
+			CONN_OPEN_CONFIRM_TYPE_URL |
+			CHAN_OPEN_TRY_TYPE_URL |
+			CHAN_OPEN_ACK_TYPE_URL |
+			CHAN_OPEN_CONFIRM_TYPE_URL |
+			CHAN_CLOSE_CONFIRM_TYPE_URL
+	)
+}
+
+/// Whether the client of `source` on `sink` has a consensus state at `height`, or will have it
+/// once the updates already submitted are included.
+///
+/// The consensus state is queried at `height` directly. The heights listed by
+/// `closest_consensus_height` are only used when that query fails: on the nodes without the
+/// heights query they are collected from the client events, which is slow and misses the
+/// consensus states whose events were pruned.
+pub async fn client_has_consensus_state<A: Chain, B: Chain>(
+	source: &A,
+	sink: &B,
+	submitted_updates: &SubmittedClientUpdates,
+	height: Height,
+) -> bool {
+	let client_id = source.client_id();
+	if submitted_updates.is_claimed(sink.name(), &client_id, height) {
+		return true
+	}
+	let latest_height = match sink.latest_height_and_timestamp().await {
+		Ok((latest_height, _)) => latest_height,
+		// the consensus state is left for the chain to check
+		Err(e) => {
+			log::debug!(target: "hyperspace", "Failed to query the latest height of {}: {e:?}", sink.name());
+			return true
+		},
+	};
+	match sink.query_client_consensus(latest_height, client_id.clone(), height).await {
+		Ok(response) => response.consensus_state.is_some(),
+		Err(e) => {
+			log::debug!(target: "hyperspace", "Failed to query the consensus state of {client_id} at {height} on {}: {e:?}", sink.name());
+			matches!(
+				sink.closest_consensus_height(client_id, height).await,
+				Ok(Some(closest)) if closest == height
+			)
+		},
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::future::ready;
+
+	fn height(height: u64) -> Height {
+		Height::new(1, height)
+	}
+
+	fn update(height: u64) -> (Height, Any) {
+		let msg = Any {
+			type_url: "/ibc.core.client.v1.MsgUpdateClient".to_string(),
+			value: vec![height as u8],
+		};
+		(self::height(height), msg)
+	}
+
+	fn recv(sequence: u64, proof_height: u64) -> Any {
+		let msg = MsgRecvPacket {
+			proof_height: Some(RawHeight { revision_number: 1, revision_height: proof_height }),
+			proof_commitment: vec![sequence as u8],
+			..Default::default()
+		};
+		Any { type_url: RECV_PACKET_TYPE_URL.to_string(), value: msg.encode_to_vec() }
+	}
+
+	/// The counterparty hosting the client, with consensus states at `heights`.
+	fn counterparty(heights: &[u64]) -> impl FnMut(Height) -> std::future::Ready<bool> + '_ {
+		|height| ready(heights.contains(&height.revision_height))
+	}
+
+	#[test]
+	fn proof_heights_are_read_from_the_messages() {
+		assert_eq!(proof_height(&recv(1, 12)), Some(height(12)));
+		assert_eq!(proof_height(&update(12).1), None);
+	}
+
+	#[tokio::test]
+	async fn left_out_update_is_scheduled_before_its_messages() {
+		// the messages of the events at 10 are proven at 11, whose update was optional
+		let msgs = vec![update(10).1, recv(1, 11), recv(2, 11)];
+		let updates = vec![update(10), update(11)];
+		let (batch, scheduled) =
+			schedule_client_updates(msgs, updates, counterparty(&[5, 10])).await;
+		assert_eq!(batch, vec![update(10).1, update(11).1, recv(1, 11), recv(2, 11)]);
+		assert_eq!(scheduled, vec![update(11)]);
+	}
+
+	#[tokio::test]
+	async fn later_update_is_moved_before_its_messages() {
+		let msgs = vec![recv(1, 11), update(10).1, update(11).1, update(12).1];
+		let updates = vec![update(10), update(11), update(12)];
+		let (batch, scheduled) = schedule_client_updates(msgs, updates, counterparty(&[5])).await;
+		assert_eq!(batch, vec![update(10).1, update(11).1, recv(1, 11), update(12).1]);
+		assert!(scheduled.is_empty());
+	}
+
+	#[tokio::test]
+	async fn messages_proven_at_existing_consensus_states_are_left_in_place() {
+		let msgs = vec![update(10).1, recv(1, 5), recv(2, 10)];
+		let updates = vec![update(10), update(11)];
+		let (batch, scheduled) =
+			schedule_client_updates(msgs.clone(), updates, counterparty(&[5])).await;
+		assert_eq!(batch, msgs);
+		assert!(scheduled.is_empty());
+	}
+
+	#[tokio::test]
+	async fn left_out_update_is_scheduled_before_higher_updates() {
+		let msgs = vec![update(12).1, recv(1, 12), recv(2, 11)];
+		let updates = vec![update(11), update(12)];
+		let (batch, scheduled) = schedule_client_updates(msgs, updates, counterparty(&[5])).await;
+		assert_eq!(batch, vec![update(11).1, update(12).1, recv(1, 12), recv(2, 11)]);
+		assert_eq!(scheduled, vec![update(11)]);
+	}
+
+	#[tokio::test]
+	async fn handshake_messages_are_never_dropped() {
+		let open_try = Any {
+			type_url: CONN_OPEN_TRY_TYPE_URL.to_string(),
+			value: MsgConnectionOpenTry {
+				proof_height: Some(RawHeight { revision_number: 1, revision_height: 7 }),
+				..Default::default()
+			}
+			.encode_to_vec(),
+		};
+		let msgs = vec![update(10).1, open_try, recv(1, 7)];
+		let updates = vec![update(10)];
+		let (batch, _) = schedule_client_updates(msgs.clone(), updates, counterparty(&[5])).await;
+		assert_eq!(batch, msgs[..2].to_vec());
+	}
+
+	#[tokio::test]
+	async fn messages_proven_at_unreachable_heights_are_dropped() {
+		let msgs = vec![update(10).1, recv(1, 7), recv(2, 10)];
+		let updates = vec![update(10)];
+		let (batch, scheduled) = schedule_client_updates(msgs, updates, counterparty(&[5])).await;
+		assert_eq!(batch, vec![update(10).1, recv(2, 10)]);
+		assert!(scheduled.is_empty());
+	}
+}
@@ -0,0 +1,397 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! gRPC server answering read-only queries about the relayed path, enabled by the `grpc-server`
+//! feature and served on the `grpc_endpoint` of the core config.
+//!
+//! The `hyperspace.query.v1.Query` service of `src/proto/query.proto` is answered from the
+//! [`primitives::relay_stats::RelayStats`] the relay loop records in the common state of the
+//! chains, which clones share, and from the [`primitives::IbcProvider`] queries of the chains.
+//!
+//! The `hyperspace.admin.v1.Admin` service of `src/proto/admin.proto`, which changes the state
+//! of the relayer, is only served along when `grpc_admin` is set in the core config. As it isn't
+//! authenticated, it's only served on a loopback address, so that only the operator of the host
+//! can reach it.
+//!
+//! Without the `grpc-server` feature, the server isn't started.
+
+use anyhow::anyhow;
+use primitives::{signer::KeyRotation, Chain};
+use std::{net::SocketAddr, path::PathBuf};
+
+/// Settings of the admin service.
+#[derive(Debug, Clone, Default)]
+pub struct AdminSettings {
+	/// Directory of the key files the keys can be rotated to
+	pub key_dir: Option<PathBuf>,
+}
+
+/// Checks that the admin service can be served on `addr`, i.e. that it's a loopback address.
+pub fn check_admin_endpoint(addr: SocketAddr) -> Result<(), anyhow::Error> {
+	if !addr.ip().is_loopback() {
+		return Err(anyhow!("The admin service is only served on a loopback address, not on {addr}"))
+	}
+	Ok(())
+}
+
+/// Serves the queries about the path between `chain_a` and `chain_b` on `addr`, along with the
+/// admin service if `admin` is set.
+#[cfg_attr(not(feature = "grpc-server"), allow(unused_variables))]
+pub async fn serve<A: Chain, B: Chain>(
+	addr: SocketAddr,
+	chain_a: A,
+	chain_b: B,
+	admin: Option<AdminSettings>,
+) -> Result<(), anyhow::Error> {
+	if admin.is_some() {
+		check_admin_endpoint(addr)?;
+	}
+	#[cfg(feature = "grpc-server")]
+	{
+		server::serve(addr, chain_a, chain_b, admin).await
+	}
+	#[cfg(not(feature = "grpc-server"))]
+	{
+		Err(anyhow!(
+			"Can't serve queries on {addr}, hyperspace was built without the `grpc-server` feature"
+		))
+	}
+}
+
+/// Asks the relayer serving the admin service on `addr` to rotate the key of `chain`, `a`, `b` or
+/// the name of the chain, to the key `key_source` refers to, which the relayer reads.
+#[cfg_attr(not(feature = "grpc-server"), allow(unused_variables))]
+pub async fn request_key_rotation(
+	addr: SocketAddr,
+	chain: String,
+	key_source: String,
+	min_balance: Option<u128>,
+) -> Result<KeyRotation, anyhow::Error> {
+	#[cfg(feature = "grpc-server")]
+	{
+		use std::str::FromStr;
+
+		let mut client =
+			proto::admin::admin_client::AdminClient::connect(format!("http://{addr}")).await?;
+		let response = client
+			.rotate_key(proto::admin::RotateKeyRequest {
+				chain,
+				key_source,
+				min_balance: min_balance.map(|balance| balance.to_string()).unwrap_or_default(),
+			})
+			.await?
+			.into_inner();
+		Ok(KeyRotation {
+			old_account: ibc::signer::Signer::from_str(&response.old_account)?,
+			new_account: ibc::signer::Signer::from_str(&response.new_account)?,
+		})
+	}
+	#[cfg(not(feature = "grpc-server"))]
+	{
+		Err(anyhow!("hyperspace was built without the `grpc-server` feature"))
+	}
+}
+
+/// Types generated from `src/proto/query.proto`, including the client of the service.
+#[cfg(feature = "grpc-server")]
+pub mod proto {
+	tonic::include_proto!("hyperspace.query.v1");
+
+	/// Types generated from `src/proto/admin.proto`, including the client of the service.
+	pub mod admin {
+		tonic::include_proto!("hyperspace.admin.v1");
+	}
+}
+
+#[cfg(feature = "grpc-server")]
+mod server {
+	use super::proto::{
+		admin::{
+			admin_server::{Admin, AdminServer},
+			RotateKeyRequest, RotateKeyResponse,
+		},
+		query_server::{Query, QueryServer},
+		ChainInfo, Channel, ClientHeight, GetChainsRequest, GetChainsResponse,
+		GetClientInfoRequest, GetClientInfoResponse, GetPendingPacketsRequest,
+		GetPendingPacketsResponse, GetRelayStatsRequest, GetRelayStatsResponse, Height, RelayStats,
+	};
+	use crate::key_rotation::rotate_key;
+	use ibc::core::{
+		ics02_client::client_state::ClientState as _,
+		ics24_host::identifier::{ChannelId, ClientId, PortId},
+	};
+	use pallet_ibc::light_clients::AnyClientState;
+	use primitives::{
+		query_undelivered_acks, query_undelivered_sequences, signer::KeySource, Chain,
+	};
+	use std::{fmt::Display, net::SocketAddr, path::PathBuf, str::FromStr};
+	use tonic::{transport::Server, Request, Response, Status};
+
+	pub(super) async fn serve<A: Chain, B: Chain>(
+		addr: SocketAddr,
+		chain_a: A,
+		chain_b: B,
+		admin: Option<super::AdminSettings>,
+	) -> Result<(), anyhow::Error> {
+		log::info!(target: "hyperspace", "Serving queries on {addr}");
+		let admin = admin.map(|admin| {
+			log::info!(target: "hyperspace", "Serving admin requests on {addr}");
+			AdminServer::new(AdminService {
+				chain_a: chain_a.clone(),
+				chain_b: chain_b.clone(),
+				key_dir: admin.key_dir,
+			})
+		});
+		Server::builder()
+			.add_service(QueryServer::new(QueryService { chain_a, chain_b }))
+			.add_optional_service(admin)
+			.serve(addr)
+			.await?;
+		Ok(())
+	}
+
+	struct QueryService<A, B> {
+		chain_a: A,
+		chain_b: B,
+	}
+
+	struct AdminService<A, B> {
+		chain_a: A,
+		chain_b: B,
+		key_dir: Option<PathBuf>,
+	}
+
+	fn internal(e: impl Display) -> Status {
+		Status::internal(e.to_string())
+	}
+
+	fn height(height: ibc::Height) -> Height {
+		Height { revision_number: height.revision_number, revision_height: height.revision_height }
+	}
+
+	/// Whether `chain` is the one named in a request, where an empty name matches any chain.
+	fn is_named(chain: &impl Chain, name: &str) -> bool {
+		name.is_empty() || chain.name() == name
+	}
+
+	async fn chain_info(chain: &impl Chain) -> Result<ChainInfo, Status> {
+		let (latest_height, _) = chain.latest_height_and_timestamp().await.map_err(internal)?;
+		let mut channels = chain
+			.channel_whitelist()
+			.into_iter()
+			.map(|(channel_id, port_id)| Channel {
+				channel_id: channel_id.to_string(),
+				port_id: port_id.to_string(),
+			})
+			.collect::<Vec<_>>();
+		channels.sort_by(|a, b| (&a.port_id, &a.channel_id).cmp(&(&b.port_id, &b.channel_id)));
+		Ok(ChainInfo {
+			name: chain.name().to_string(),
+			client_id: chain.client_id().to_string(),
+			client_type: chain.client_type().to_string(),
+			connection_ids: chain.connection_ids().iter().map(ToString::to_string).collect(),
+			channels,
+			latest_height: Some(height(latest_height)),
+		})
+	}
+
+	/// The info of `client_id` if it is hosted on `chain`.
+	async fn client_info(
+		chain: &impl Chain,
+		client_id: &ClientId,
+	) -> Result<Option<GetClientInfoResponse>, Status> {
+		let (at, _) = chain.latest_height_and_timestamp().await.map_err(internal)?;
+		// a missing client fails to be queried on some chains, and is empty on others
+		let client_state = match chain.query_client_state(at, client_id.clone()).await {
+			Ok(response) => match response.client_state {
+				Some(client_state) => client_state,
+				None => return Ok(None),
+			},
+			Err(_) => return Ok(None),
+		};
+		let client_state = AnyClientState::try_from(client_state).map_err(internal)?;
+		let status = chain.query_client_status(at, client_id.clone()).await.map_err(internal)?;
+		let latest_observed_height = chain
+			.common_state()
+			.relay_stats
+			.snapshot()
+			.client_heights
+			.get(client_id)
+			.copied()
+			.map(height);
+		Ok(Some(GetClientInfoResponse {
+			chain: chain.name().to_string(),
+			client_id: client_id.to_string(),
+			client_type: client_state.client_type().to_string(),
+			latest_height: Some(height(client_state.latest_height())),
+			status: status.to_string(),
+			latest_observed_height,
+		}))
+	}
+
+	/// The packets of `channel_id`/`port_id` on `source` that weren't delivered to `sink`.
+	async fn pending_packets(
+		source: &impl Chain,
+		sink: &impl Chain,
+		channel_id: ChannelId,
+		port_id: PortId,
+	) -> Result<GetPendingPacketsResponse, Status> {
+		let (source_height, _) = source.latest_height_and_timestamp().await.map_err(internal)?;
+		let (sink_height, _) = sink.latest_height_and_timestamp().await.map_err(internal)?;
+		let undelivered_packets = query_undelivered_sequences(
+			source_height,
+			sink_height,
+			channel_id,
+			port_id.clone(),
+			source,
+			sink,
+		)
+		.await
+		.map_err(internal)?;
+		let undelivered_acknowledgements =
+			query_undelivered_acks(source_height, sink_height, channel_id, port_id, source, sink)
+				.await
+				.map_err(internal)?;
+		Ok(GetPendingPacketsResponse {
+			chain: source.name().to_string(),
+			undelivered_packets,
+			undelivered_acknowledgements,
+		})
+	}
+
+	fn relay_stats(chain: &impl Chain) -> RelayStats {
+		let stats = chain.common_state().relay_stats.snapshot();
+		RelayStats {
+			chain: chain.name().to_string(),
+			submitted_batches: stats.submitted_batches,
+			failed_batches: stats.failed_batches,
+			received_packets: stats.submitted.received_packets,
+			acknowledgements: stats.submitted.acknowledgements,
+			timeouts: stats.submitted.timeouts,
+			client_updates: stats.submitted.client_updates,
+			pending_messages: stats.pending_messages,
+			last_submission: stats.last_submission.unwrap_or_default(),
+			client_heights: stats
+				.client_heights
+				.into_iter()
+				.map(|(client_id, client_height)| ClientHeight {
+					client_id: client_id.to_string(),
+					height: Some(height(client_height)),
+				})
+				.collect(),
+		}
+	}
+
+	#[tonic::async_trait]
+	impl<A: Chain, B: Chain> Query for QueryService<A, B> {
+		async fn get_chains(
+			&self,
+			_request: Request<GetChainsRequest>,
+		) -> Result<Response<GetChainsResponse>, Status> {
+			let chains = vec![chain_info(&self.chain_a).await?, chain_info(&self.chain_b).await?];
+			Ok(Response::new(GetChainsResponse { chains }))
+		}
+
+		async fn get_client_info(
+			&self,
+			request: Request<GetClientInfoRequest>,
+		) -> Result<Response<GetClientInfoResponse>, Status> {
+			let request = request.into_inner();
+			let client_id = ClientId::from_str(&request.client_id)
+				.map_err(|e| Status::invalid_argument(format!("Invalid client id: {e}")))?;
+			let mut info = None;
+			if is_named(&self.chain_a, &request.chain) {
+				info = client_info(&self.chain_a, &client_id).await?;
+			}
+			if info.is_none() && is_named(&self.chain_b, &request.chain) {
+				info = client_info(&self.chain_b, &client_id).await?;
+			}
+			info.map(Response::new)
+				.ok_or_else(|| Status::not_found(format!("No client {client_id} on the path")))
+		}
+
+		async fn get_pending_packets(
+			&self,
+			request: Request<GetPendingPacketsRequest>,
+		) -> Result<Response<GetPendingPacketsResponse>, Status> {
+			let request = request.into_inner();
+			let channel_id = ChannelId::from_str(&request.channel_id)
+				.map_err(|e| Status::invalid_argument(format!("Invalid channel id: {e}")))?;
+			let port_id = PortId::from_str(&request.port_id)
+				.map_err(|e| Status::invalid_argument(format!("Invalid port id: {e}")))?;
+			let channel = (channel_id, port_id.clone());
+			let on_chain_a = if !request.chain.is_empty() {
+				if !is_named(&self.chain_a, &request.chain) &&
+					!is_named(&self.chain_b, &request.chain)
+				{
+					return Err(Status::not_found(format!("No chain {} on the path", request.chain)))
+				}
+				is_named(&self.chain_a, &request.chain)
+			} else {
+				// channels that aren't whitelisted on either chain are looked up on chain A
+				!self.chain_b.channel_whitelist().contains(&channel) ||
+					self.chain_a.channel_whitelist().contains(&channel)
+			};
+			let response = if on_chain_a {
+				pending_packets(&self.chain_a, &self.chain_b, channel_id, port_id).await?
+			} else {
+				pending_packets(&self.chain_b, &self.chain_a, channel_id, port_id).await?
+			};
+			Ok(Response::new(response))
+		}
+
+		async fn get_relay_stats(
+			&self,
+			_request: Request<GetRelayStatsRequest>,
+		) -> Result<Response<GetRelayStatsResponse>, Status> {
+			let chains = vec![relay_stats(&self.chain_a), relay_stats(&self.chain_b)];
+			Ok(Response::new(GetRelayStatsResponse { chains }))
+		}
+	}
+
+	#[tonic::async_trait]
+	impl<A: Chain, B: Chain> Admin for AdminService<A, B> {
+		async fn rotate_key(
+			&self,
+			request: Request<RotateKeyRequest>,
+		) -> Result<Response<RotateKeyResponse>, Status> {
+			let request = request.into_inner();
+			let key_source = KeySource::parse(&request.key_source, self.key_dir.as_deref())
+				.map_err(|e| Status::invalid_argument(format!("Invalid key source: {e}")))?;
+			let min_balance = match request.min_balance.as_str() {
+				"" => None,
+				balance => Some(balance.parse::<u128>().map_err(|e| {
+					Status::invalid_argument(format!("Invalid minimum balance: {e}"))
+				})?),
+			};
+			let (chain, rotation) = match request.chain.as_str() {
+				"a" =>
+					(self.chain_a.name(), rotate_key(&self.chain_a, &key_source, min_balance).await),
+				"b" =>
+					(self.chain_b.name(), rotate_key(&self.chain_b, &key_source, min_balance).await),
+				name if name == self.chain_a.name() =>
+					(name, rotate_key(&self.chain_a, &key_source, min_balance).await),
+				name if name == self.chain_b.name() =>
+					(name, rotate_key(&self.chain_b, &key_source, min_balance).await),
+				name => return Err(Status::not_found(format!("No chain {name} on the path"))),
+			};
+			let rotation = rotation.map_err(|e| Status::failed_precondition(e.to_string()))?;
+			Ok(Response::new(RotateKeyResponse {
+				chain: chain.to_string(),
+				old_account: rotation.old_account.to_string(),
+				new_account: rotation.new_account.to_string(),
+			}))
+		}
+	}
+}
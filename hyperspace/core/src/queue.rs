@@ -12,9 +12,30 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::{store, telemetry};
+use ibc::core::{
+	ics04_channel::{
+		msgs::{
+			acknowledgement::{MsgAcknowledgement, TYPE_URL as ACKNOWLEDGEMENT_TYPE_URL},
+			recv_packet::{MsgRecvPacket, TYPE_URL as RECV_PACKET_TYPE_URL},
+			timeout::{MsgTimeout, TYPE_URL as TIMEOUT_TYPE_URL},
+			timeout_on_close::{MsgTimeoutOnClose, TYPE_URL as TIMEOUT_ON_CLOSE_TYPE_URL},
+		},
+		packet::Packet,
+	},
+	ics24_host::identifier::{ChannelId, PortId},
+};
 use ibc_proto::google::protobuf::Any;
 use metrics::handler::MetricsHandler;
-use primitives::Chain;
+use primitives::{Chain, ErrorKind, HasErrorKind, RetryPolicy, SimulationResult};
+use rand::Rng;
+use std::{collections::HashSet, fmt, future::Future, time::Duration};
+use tendermint_proto::Protobuf;
+
+/// Delay before the first resubmission, doubled on every subsequent attempt.
+const SUBMIT_RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+/// Upper bound for the delay between two submissions, before jitter.
+const SUBMIT_RETRY_MAX_DELAY: Duration = Duration::from_secs(60);
 
 /// This sends messages to the sink chain in a gas-aware manner.
 pub async fn flush_message_batch(
@@ -22,6 +43,32 @@ pub async fn flush_message_batch(
 	metrics: Option<&MetricsHandler>,
 	sink: &impl Chain,
 ) -> Result<(), anyhow::Error> {
+	let msgs = if sink.common_state().simulate_messages {
+		let simulated = exclude_failing_messages(sink.name(), msgs.clone(), |msgs| async move {
+			sink.simulate(msgs).await.map_err(|e| {
+				let unsupported = e.kind() == ErrorKind::Unsupported;
+				let e = anyhow::Error::from(e);
+				if unsupported {
+					e.context(SimulationUnsupported)
+				} else {
+					e
+				}
+			})
+		})
+		.await;
+		match simulated {
+			Ok(msgs) if msgs.is_empty() => return Ok(()),
+			Ok(msgs) => msgs,
+			Err(e) if e.is::<SimulationUnsupported>() => {
+				log::warn!(target: "hyperspace", "Submitting the batch to {} without simulating it: {e:#}", sink.name());
+				msgs
+			},
+			Err(e) => return Err(e),
+		}
+	} else {
+		msgs
+	};
+
 	let block_max_weight = sink.block_max_weight();
 	let batch_weight = sink.estimate_weight(msgs.clone()).await?;
 
@@ -32,7 +79,7 @@ pub async fn flush_message_batch(
 	log::debug!(target: "hyperspace", "Outgoing messages weight: {} block max weight: {}", batch_weight, block_max_weight);
 	let ratio = (batch_weight / block_max_weight) as usize;
 	if ratio == 0 {
-		sink.submit(msgs).await?;
+		submit_with_retry(sink, msgs).await?;
 		return Ok(())
 	}
 
@@ -53,8 +100,344 @@ pub async fn flush_message_batch(
 	// TODO: return number of failed messages and record it to metrics
 	for batch in msgs.chunks(chunk_size) {
 		// send out batches.
-		sink.submit(batch.to_vec()).await?;
+		submit_with_retry(sink, batch.to_vec()).await?;
 	}
 
 	Ok(())
 }
+
+/// Context of the simulation errors of the sinks that can't simulate messages, whose batches are
+/// submitted as they are.
+#[derive(Debug)]
+struct SimulationUnsupported;
+
+impl fmt::Display for SimulationUnsupported {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "the sink doesn't support simulating messages")
+	}
+}
+
+/// Simulates `msgs` and drops the messages that would fail on the sink, so that a single
+/// poisoned message doesn't fail the whole batch.
+///
+/// When the sink attributes the failure to individual messages, those are dropped and the rest
+/// of the batch is simulated again. Otherwise, every packet message is simulated on its own
+/// along with the non-packet messages (e.g. client updates) it may depend on.
+async fn exclude_failing_messages<F, Fut>(
+	sink_name: &str,
+	mut msgs: Vec<Any>,
+	simulate: F,
+) -> Result<Vec<Any>, anyhow::Error>
+where
+	F: Fn(Vec<Any>) -> Fut,
+	Fut: Future<Output = Result<SimulationResult, anyhow::Error>>,
+{
+	let mut excluded = vec![];
+	while !msgs.is_empty() {
+		let result = simulate(msgs.clone()).await?;
+		if result.is_success() {
+			break
+		}
+
+		if result.message_results.len() == msgs.len() && result.message_results.contains(&true) {
+			let (passed, failed) = split_by_results(msgs, &result.message_results);
+			excluded.extend(failed);
+			msgs = passed;
+			continue
+		}
+
+		let (packets, context): (Vec<_>, Vec<_>) =
+			msgs.iter().enumerate().partition(|(_, msg)| is_packet_msg(msg));
+		let context = context.into_iter().map(|(_, msg)| msg.clone()).collect::<Vec<_>>();
+		let mut results = vec![true; msgs.len()];
+		for (index, packet) in packets.iter() {
+			let mut candidate = context.clone();
+			candidate.push((*packet).clone());
+			results[*index] = simulate(candidate).await?.is_success();
+		}
+		if packets.iter().all(|(index, _)| !results[*index]) {
+			// Nothing passes on its own, the failure is caused by the non-packet messages.
+			// Leave the batch untouched so that the submission reports the error.
+			break
+		}
+		let (passed, failed) = split_by_results(msgs, &results);
+		excluded.extend(failed);
+		msgs = passed;
+		break
+	}
+
+	for msg in excluded {
+		match PacketDelivery::from_msg(&msg) {
+			Some(
+				PacketDelivery::Recv { port_id, channel_id, sequence } |
+				PacketDelivery::Commitment { port_id, channel_id, sequence },
+			) => log::warn!(
+				target: "hyperspace",
+				"Excluding {} for packet {port_id}/{channel_id}/{sequence} from the batch to {sink_name}: simulation failed",
+				msg.type_url
+			),
+			None => log::warn!(
+				target: "hyperspace",
+				"Excluding {} from the batch to {sink_name}: simulation failed",
+				msg.type_url
+			),
+		}
+	}
+
+	Ok(msgs)
+}
+
+/// Splits `msgs` into the messages that passed and the messages that failed the simulation.
+fn split_by_results(msgs: Vec<Any>, results: &[bool]) -> (Vec<Any>, Vec<Any>) {
+	let (passed, failed): (Vec<_>, Vec<_>) =
+		msgs.into_iter().zip(results).partition(|(_, passed)| **passed);
+	(
+		passed.into_iter().map(|(msg, _)| msg).collect(),
+		failed.into_iter().map(|(msg, _)| msg).collect(),
+	)
+}
+
+pub(crate) fn is_packet_msg(msg: &Any) -> bool {
+	matches!(
+		msg.type_url.as_str(),
+		RECV_PACKET_TYPE_URL |
+			ACKNOWLEDGEMENT_TYPE_URL |
+			TIMEOUT_TYPE_URL |
+			TIMEOUT_ON_CLOSE_TYPE_URL
+	)
+}
+
+/// Submits `msgs` to the sink, resubmitting them with an exponential backoff as long as the
+/// error is a transport error, see [`ErrorKind::retry_policy`], and the configured number of
+/// retries hasn't been exhausted.
+///
+/// [`ErrorKind::retry_policy`]: primitives::ErrorKind::retry_policy
+async fn submit_with_retry(sink: &impl Chain, mut msgs: Vec<Any>) -> Result<(), anyhow::Error> {
+	let max_retries = sink.common_state().max_submit_retries;
+	let mut attempt = 0;
+	loop {
+		let submission = telemetry::submission(sink.name(), &msgs, sink.submit(msgs.clone()));
+		let error = match submission.await {
+			Ok(tx_id) => {
+				store::record_submission(sink, &tx_id, &msgs);
+				return Ok(())
+			},
+			Err(e) => e,
+		};
+		if attempt >= max_retries || error.kind().retry_policy() == RetryPolicy::Fail {
+			return Err(error.into())
+		}
+		attempt += 1;
+		let delay = backoff_delay(attempt);
+		log::warn!(
+			target: "hyperspace",
+			"Failed to submit messages to {}, retrying in {delay:?} ({attempt}/{max_retries}): {error:?}",
+			sink.name()
+		);
+		tokio::time::sleep(delay).await;
+
+		// The failed submission may have been included anyway, so make sure we don't deliver
+		// the same packets twice.
+		msgs = match undelivered_messages(sink, msgs).await? {
+			Some(msgs) => msgs,
+			None => {
+				log::info!(
+					target: "hyperspace",
+					"All packets of the failed batch were delivered to {}, not resubmitting",
+					sink.name()
+				);
+				return Ok(())
+			},
+		};
+	}
+}
+
+fn backoff_delay(attempt: u32) -> Duration {
+	let delay = SUBMIT_RETRY_BASE_DELAY
+		.saturating_mul(1 << attempt.saturating_sub(1).min(16))
+		.min(SUBMIT_RETRY_MAX_DELAY);
+	let jitter = rand::thread_rng().gen_range(0..=delay.as_millis() as u64 / 2);
+	delay + Duration::from_millis(jitter)
+}
+
+/// A packet message whose delivery can be checked on the sink.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum PacketDelivery {
+	/// `MsgRecvPacket`, delivered once the sink has received the sequence.
+	Recv { port_id: PortId, channel_id: ChannelId, sequence: u64 },
+	/// `MsgAcknowledgement`, `MsgTimeout` or `MsgTimeoutOnClose`, delivered once the sink has
+	/// deleted the packet commitment.
+	Commitment { port_id: PortId, channel_id: ChannelId, sequence: u64 },
+}
+
+impl PacketDelivery {
+	pub(crate) fn from_msg(msg: &Any) -> Option<Self> {
+		let (packet, is_recv) = decode_packet_msg(msg)?;
+		if is_recv {
+			return Some(Self::Recv {
+				port_id: packet.destination_port,
+				channel_id: packet.destination_channel,
+				sequence: packet.sequence.into(),
+			})
+		}
+		Some(Self::Commitment {
+			port_id: packet.source_port,
+			channel_id: packet.source_channel,
+			sequence: packet.sequence.into(),
+		})
+	}
+}
+
+/// Decodes the packet of a packet message, along with whether it's a `MsgRecvPacket`, submitted
+/// to the receiver of the packet rather than its sender.
+pub(crate) fn decode_packet_msg(msg: &Any) -> Option<(Packet, bool)> {
+	let packet = match msg.type_url.as_str() {
+		RECV_PACKET_TYPE_URL =>
+			return Some((MsgRecvPacket::decode_vec(&msg.value).ok()?.packet, true)),
+		ACKNOWLEDGEMENT_TYPE_URL => MsgAcknowledgement::decode_vec(&msg.value).ok()?.packet,
+		TIMEOUT_TYPE_URL => MsgTimeout::decode_vec(&msg.value).ok()?.packet,
+		TIMEOUT_ON_CLOSE_TYPE_URL => MsgTimeoutOnClose::decode_vec(&msg.value).ok()?.packet,
+		_ => return None,
+	};
+	Some((packet, false))
+}
+
+/// Re-queries the sink for the packet messages in `msgs` and returns the messages that still need
+/// to be submitted, or `None` if the batch has already been delivered.
+pub(crate) async fn undelivered_messages(
+	sink: &impl Chain,
+	msgs: Vec<Any>,
+) -> Result<Option<Vec<Any>>, anyhow::Error> {
+	let (latest_height, _) = sink.latest_height_and_timestamp().await?;
+	let msgs = msgs
+		.into_iter()
+		.map(|msg| {
+			let delivery = PacketDelivery::from_msg(&msg);
+			(msg, delivery)
+		})
+		.collect::<Vec<_>>();
+
+	let mut delivered = HashSet::new();
+	for delivery in msgs.iter().filter_map(|(_, delivery)| delivery.as_ref()) {
+		let pending = match delivery {
+			PacketDelivery::Recv { port_id, channel_id, sequence } =>
+				sink.query_unreceived_packets(
+					latest_height,
+					*channel_id,
+					port_id.clone(),
+					vec![*sequence],
+				)
+				.await?,
+			PacketDelivery::Commitment { port_id, channel_id, sequence } =>
+				sink.query_unreceived_acknowledgements(
+					latest_height,
+					*channel_id,
+					port_id.clone(),
+					vec![*sequence],
+				)
+				.await?,
+		};
+		if pending.is_empty() {
+			delivered.insert(delivery.clone());
+		}
+	}
+
+	Ok(retain_undelivered(msgs, &delivered))
+}
+
+/// Drops the packet messages that are already `delivered`. Returns `None` if the batch contained
+/// packet messages and all of them were delivered, since the remaining messages (e.g. client
+/// updates) were then included as well.
+fn retain_undelivered(
+	msgs: Vec<(Any, Option<PacketDelivery>)>,
+	delivered: &HashSet<PacketDelivery>,
+) -> Option<Vec<Any>> {
+	let has_packets = msgs.iter().any(|(_, delivery)| delivery.is_some());
+	let remaining = msgs
+		.into_iter()
+		.filter(|(_, delivery)| !matches!(delivery, Some(delivery) if delivered.contains(delivery)))
+		.collect::<Vec<_>>();
+	if has_packets && remaining.iter().all(|(_, delivery)| delivery.is_none()) {
+		return None
+	}
+	Some(remaining.into_iter().map(|(msg, _)| msg).collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn recv(sequence: u64) -> (Any, Option<PacketDelivery>) {
+		let delivery = PacketDelivery::Recv {
+			port_id: PortId::transfer(),
+			channel_id: ChannelId::new(0),
+			sequence,
+		};
+		let msg = Any { type_url: RECV_PACKET_TYPE_URL.to_string(), value: vec![sequence as u8] };
+		(msg, Some(delivery))
+	}
+
+	fn update_client() -> (Any, Option<PacketDelivery>) {
+		(Any { type_url: "/ibc.core.client.v1.MsgUpdateClient".to_string(), value: vec![] }, None)
+	}
+
+	#[test]
+	fn resubmission_skips_delivered_packets() {
+		let msgs = vec![update_client(), recv(1), recv(2)];
+		let delivered = HashSet::from([recv(1).1.unwrap()]);
+		let remaining = retain_undelivered(msgs, &delivered).unwrap();
+		assert_eq!(remaining, vec![update_client().0, recv(2).0]);
+	}
+
+	#[test]
+	fn delivered_batch_is_not_resubmitted() {
+		let msgs = vec![update_client(), recv(1), recv(2)];
+		let delivered = HashSet::from([recv(1).1.unwrap(), recv(2).1.unwrap()]);
+		assert_eq!(retain_undelivered(msgs, &delivered), None);
+	}
+
+	#[test]
+	fn batch_without_packets_is_resubmitted_as_is() {
+		let msgs = vec![update_client()];
+		assert_eq!(retain_undelivered(msgs, &HashSet::new()), Some(vec![update_client().0]));
+	}
+
+	const POISONED_SEQUENCE: u64 = 2;
+
+	/// Simulates like the Cosmos SDK, pointing at the poisoned message.
+	async fn attributing_simulator(msgs: Vec<Any>) -> Result<SimulationResult, anyhow::Error> {
+		let message_results =
+			msgs.iter().map(|msg| msg.value != vec![POISONED_SEQUENCE as u8]).collect();
+		Ok(SimulationResult { estimated_weight: 1, message_results })
+	}
+
+	/// Simulates like a parachain dry run, failing the whole batch.
+	async fn batch_simulator(msgs: Vec<Any>) -> Result<SimulationResult, anyhow::Error> {
+		let success = msgs.iter().all(|msg| msg.value != vec![POISONED_SEQUENCE as u8]);
+		Ok(SimulationResult { estimated_weight: 1, message_results: vec![success; msgs.len()] })
+	}
+
+	fn batch() -> Vec<Any> {
+		vec![update_client().0, recv(1).0, recv(POISONED_SEQUENCE).0, recv(3).0]
+	}
+
+	#[tokio::test]
+	async fn simulation_excludes_attributed_poisoned_message() {
+		let msgs = exclude_failing_messages("sink", batch(), attributing_simulator).await.unwrap();
+		assert_eq!(msgs, vec![update_client().0, recv(1).0, recv(3).0]);
+	}
+
+	#[tokio::test]
+	async fn simulation_isolates_unattributed_poisoned_message() {
+		let msgs = exclude_failing_messages("sink", batch(), batch_simulator).await.unwrap();
+		assert_eq!(msgs, vec![update_client().0, recv(1).0, recv(3).0]);
+	}
+
+	#[tokio::test]
+	async fn simulation_keeps_batch_failing_without_packets() {
+		let poisoned_update = Any { value: vec![POISONED_SEQUENCE as u8], ..update_client().0 };
+		let msgs = vec![poisoned_update, recv(1).0];
+		let result = exclude_failing_messages("sink", msgs.clone(), batch_simulator).await.unwrap();
+		assert_eq!(result, msgs);
+	}
+}
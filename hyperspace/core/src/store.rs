@@ -0,0 +1,272 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! [`RelayerStore`] backed by an embedded database, and the hooks persisting the state of the
+//! relay loop in the store of the source chain.
+
+use crate::queue::PacketDelivery;
+use ibc::{
+	core::ics24_host::identifier::{ChannelId, PortId},
+	events::IbcEvent,
+	Height,
+};
+use ibc_proto::google::protobuf::Any;
+use primitives::{
+	error::Error,
+	query_undelivered_sequences,
+	relayer_store::{channel_key, ChannelState, RelayerStore, UnconfirmedTx},
+	Chain, UndeliveredType,
+};
+use std::{
+	collections::{BTreeMap, HashMap},
+	fmt::Debug,
+	path::Path,
+	sync::Mutex,
+};
+
+/// Serializes the updates of the saved states, since the relay loops of both directions update
+/// the states of the channels of a chain: one records its events, the other the transactions
+/// submitted to it.
+static STATE_UPDATES: Mutex<()> = Mutex::new(());
+
+/// [`RelayerStore`] persisted with sled at the configured `state_db_path`.
+#[derive(Debug, Clone)]
+pub struct SledRelayerStore(sled::Db);
+
+impl SledRelayerStore {
+	pub fn open(path: impl AsRef<Path>) -> Result<Self, anyhow::Error> {
+		Ok(Self(sled::open(path)?))
+	}
+}
+
+impl RelayerStore for SledRelayerStore {
+	fn load(
+		&self,
+		chain: &str,
+		channel_id: ChannelId,
+		port_id: &PortId,
+	) -> Result<Option<ChannelState>, Error> {
+		let value = self
+			.0
+			.get(channel_key(chain, channel_id, port_id))
+			.map_err(|e| Error::Custom(format!("Failed to read the relayer store: {e}")))?;
+		value
+			.map(|value| {
+				serde_json::from_slice(&value).map_err(|e| {
+					Error::Custom(format!(
+						"Failed to decode the state of {channel_id}/{port_id}: {e}"
+					))
+				})
+			})
+			.transpose()
+	}
+
+	fn save(
+		&self,
+		chain: &str,
+		channel_id: ChannelId,
+		port_id: &PortId,
+		state: &ChannelState,
+	) -> Result<(), Error> {
+		let value = serde_json::to_vec(state)
+			.map_err(|e| Error::Custom(format!("Failed to encode the relayer state: {e}")))?;
+		self.0
+			.insert(channel_key(chain, channel_id, port_id), value)
+			.map_err(|e| Error::Custom(format!("Failed to write the relayer store: {e}")))?;
+		Ok(())
+	}
+
+	fn flush(&self) -> Result<(), Error> {
+		self.0
+			.flush()
+			.map_err(|e| Error::Custom(format!("Failed to flush the relayer store: {e}")))?;
+		Ok(())
+	}
+}
+
+/// Records the events emitted by `source` at each height of a batch in the state of its
+/// whitelisted channels, which are saved and flushed once for the whole batch.
+pub fn record_events(source: &impl Chain, events: &[(Height, &[IbcEvent])]) {
+	let store = match &source.common_state().relayer_store {
+		Some(store) => store,
+		None => return,
+	};
+	let _guard = STATE_UPDATES.lock().unwrap();
+	for (channel_id, port_id) in source.channel_whitelist() {
+		let result = store.load(source.name(), channel_id, &port_id).and_then(|state| {
+			let mut state = state.unwrap_or_default();
+			for (height, events) in events {
+				state.record_events(channel_id, &port_id, *height, events);
+			}
+			store.save(source.name(), channel_id, &port_id, &state)
+		});
+		if let Err(e) = result {
+			log::error!(target: "hyperspace", "Failed to record the state of channel {channel_id}/{port_id} of {}: {e}", source.name());
+		}
+	}
+	if let Err(e) = store.flush() {
+		log::error!(target: "hyperspace", "Failed to save the state of the channels of {}: {e}", source.name());
+	}
+}
+
+/// Records the packet messages of the transaction `tx_id` submitted to `sink` in the state of the
+/// channels of the sink they were submitted on, until the events of the sink confirm them.
+pub fn record_submission(sink: &impl Chain, tx_id: &impl Debug, msgs: &[Any]) {
+	let store = match &sink.common_state().relayer_store {
+		Some(store) => store,
+		None => return,
+	};
+	let mut txs = HashMap::<_, UnconfirmedTx>::new();
+	for delivery in msgs.iter().filter_map(PacketDelivery::from_msg) {
+		match delivery {
+			PacketDelivery::Recv { port_id, channel_id, sequence } => {
+				txs.entry((channel_id, port_id)).or_default().received.insert(sequence);
+			},
+			PacketDelivery::Commitment { port_id, channel_id, sequence } => {
+				txs.entry((channel_id, port_id)).or_default().acknowledged.insert(sequence);
+			},
+		}
+	}
+	if txs.is_empty() {
+		return
+	}
+	let _guard = STATE_UPDATES.lock().unwrap();
+	let tx_id = format!("{tx_id:?}");
+	for ((channel_id, port_id), tx) in txs {
+		let result = store.load(sink.name(), channel_id, &port_id).and_then(|state| {
+			let mut state = state.unwrap_or_default();
+			state.record_submission(tx_id.clone(), tx);
+			store.save(sink.name(), channel_id, &port_id, &state)
+		});
+		if let Err(e) = result {
+			log::error!(target: "hyperspace", "Failed to record transaction {tx_id} on channel {channel_id}/{port_id} of {}: {e}", sink.name());
+		}
+	}
+	if let Err(e) = store.flush() {
+		log::error!(target: "hyperspace", "Failed to save transaction {tx_id} of {}: {e}", sink.name());
+	}
+}
+
+/// Reconciles the saved state of the whitelisted channels of `source` with its packet
+/// commitments. If packets are waiting to be received, the sink is flagged with undelivered
+/// sequences, so that the client updates proving them are sent with the first finality event.
+/// The transactions submitted to `source` that weren't confirmed before the restart are checked
+/// as well, see [`reconcile_unconfirmed_txs`].
+pub async fn resume(source: &impl Chain, sink: &impl Chain) -> Result<(), anyhow::Error> {
+	let store = match &source.common_state().relayer_store {
+		Some(store) => store.clone(),
+		None => return Ok(()),
+	};
+	let (source_height, _) = source.latest_height_and_timestamp().await?;
+	let (sink_height, _) = sink.latest_height_and_timestamp().await?;
+	for (channel_id, port_id) in source.channel_whitelist() {
+		let mut state = match store.load(source.name(), channel_id, &port_id)? {
+			Some(state) => state,
+			None => continue,
+		};
+		let blocks_missed = match state.last_processed_height {
+			// the chain was reset since the state was saved, which is of no use anymore
+			Some(last)
+				if last.revision_number != source_height.revision_number ||
+					last.revision_height > source_height.revision_height =>
+			{
+				log::warn!(target: "hyperspace", "Discarding the saved state of channel {channel_id}/{port_id} of {}, processed up to {last} but the chain is at {source_height}", source.name());
+				state = ChannelState::default();
+				None
+			},
+			last => last.map(|last| source_height.revision_height - last.revision_height),
+		};
+		let committed = source
+			.query_packet_commitments(source_height, channel_id, port_id.clone())
+			.await?;
+		let sent_while_down = state.reconcile(committed);
+		let undelivered = query_undelivered_sequences(
+			source_height,
+			sink_height,
+			channel_id,
+			port_id.clone(),
+			source,
+			sink,
+		)
+		.await?;
+		log::info!(
+			target: "hyperspace",
+			"Resuming channel {channel_id}/{port_id} of {} stopped {} blocks ago: {} packets sent while the relayer was down, {} packets to receive on {}",
+			source.name(), blocks_missed.map_or_else(|| "?".to_string(), |blocks| blocks.to_string()), sent_while_down.len(), undelivered.len(), sink.name()
+		);
+		if !undelivered.is_empty() {
+			sink.on_undelivered_sequences(true, UndeliveredType::Recvs).await;
+		}
+		let unconfirmed_txs = std::mem::take(&mut state.unconfirmed_txs);
+		reconcile_unconfirmed_txs(source, source_height, channel_id, &port_id, unconfirmed_txs)
+			.await?;
+		store.save(source.name(), channel_id, &port_id, &state)?;
+	}
+	store.flush()?;
+	Ok(())
+}
+
+/// Checks whether the transactions submitted to `chain` on a channel before a restart, which
+/// weren't confirmed by its events, delivered their packets. If they didn't, the chain is flagged
+/// with undelivered sequences so that the packets are relayed again.
+async fn reconcile_unconfirmed_txs(
+	chain: &impl Chain,
+	height: Height,
+	channel_id: ChannelId,
+	port_id: &PortId,
+	txs: BTreeMap<String, UnconfirmedTx>,
+) -> Result<(), anyhow::Error> {
+	for (tx_id, tx) in txs {
+		let unreceived = if tx.received.is_empty() {
+			vec![]
+		} else {
+			chain
+				.query_unreceived_packets(
+					height,
+					channel_id,
+					port_id.clone(),
+					tx.received.into_iter().collect(),
+				)
+				.await?
+		};
+		let unacknowledged = if tx.acknowledged.is_empty() {
+			vec![]
+		} else {
+			chain
+				.query_unreceived_acknowledgements(
+					height,
+					channel_id,
+					port_id.clone(),
+					tx.acknowledged.into_iter().collect(),
+				)
+				.await?
+		};
+		if unreceived.is_empty() && unacknowledged.is_empty() {
+			log::debug!(target: "hyperspace", "Transaction {tx_id} submitted to {} before the restart delivered its packets", chain.name());
+			continue
+		}
+		log::warn!(
+			target: "hyperspace",
+			"Transaction {tx_id} submitted to {} on channel {channel_id}/{port_id} before the restart didn't deliver packets {unreceived:?} to receive and {unacknowledged:?} to acknowledge, relaying them again",
+			chain.name()
+		);
+		if !unreceived.is_empty() {
+			chain.on_undelivered_sequences(true, UndeliveredType::Recvs).await;
+		}
+		if !unacknowledged.is_empty() {
+			chain.on_undelivered_sequences(true, UndeliveredType::Acks).await;
+		}
+	}
+	Ok(())
+}
@@ -182,11 +182,15 @@ define_runtime_transactions!(
 	DummySendPingParamsWrapper,
 	parachain_subxt::api::runtime_types::pallet_ibc::Any,
 	RawMemo,
+	Transfer,
 	|x| parachain_subxt::api::tx().ibc().deliver(x),
 	|x, y, z, w| parachain_subxt::api::tx().ibc().transfer(x, CurrencyId(y), z, w),
 	|x| parachain_subxt::api::tx().sudo().sudo(x),
 	|_: DummySendPingParamsWrapper<FakeSendPingParams>| unimplemented("ping is not implemented"),
-	|| unimplemented("ibc_increase_counters is not implemented")
+	|| unimplemented("ibc_increase_counters is not implemented"),
+	|memo| Some(RawMemo(memo)),
+	|_, _| unimplemented("assets_force_create is not implemented"),
+	|_, _, _| unimplemented("assets_mint is not implemented")
 );
 
 define_ibc_event_wrapper!(IbcEventWrapper, MetadataIbcEvent,);
@@ -196,11 +200,16 @@ define_event_record!(
 	EventRecord<<<ComposableConfig as light_client_common::config::Config>::ParaRuntimeEvent as AsInner>::Inner, H256>,
 	IbcEventWrapper,
 	parachain_subxt::api::runtime_types::frame_system::Phase,
-	parachain_subxt::api::runtime_types::pallet_ibc::pallet::Event,
-	parachain_subxt::api::runtime_types::composable_runtime::RuntimeEvent
+	parachain_subxt::api::runtime_types::composable_runtime::RuntimeEvent,
+	{ Ibc => parachain_subxt::api::runtime_types::pallet_ibc::pallet::Event }
 );
 
-define_events!(ComposableEvents, parachain_subxt::api::ibc::events::Events, IbcEventWrapper);
+define_events!(
+	ComposableEvents,
+	parachain_subxt::api::ibc::events::Events,
+	parachain_subxt::api::runtime_types::pallet_ibc::errors::IbcError,
+	IbcEventWrapper
+);
 
 define_runtime_event!(
 	ComposableParaRuntimeEvent,
@@ -112,11 +112,15 @@ define_runtime_transactions!(
 	TransferParamsWrapper,
 	SendPingParamsWrapper,
 	parachain_subxt::api::runtime_types::pallet_ibc::Any,
+	Transfer,
 	|x| parachain_subxt::api::tx().ibc().deliver(x),
 	|x, y, z, w| parachain_subxt::api::tx().ibc().transfer(x, CurrencyId(y), z, w),
 	|x| parachain_subxt::api::tx().sudo().sudo(x),
 	|x| parachain_subxt::api::tx().ibc_ping().send_ping(x),
-	|| super::unimplemented("ibc_increase_counters is not implemented")
+	|| super::unimplemented("ibc_increase_counters is not implemented"),
+	|_| None,
+	|_, _| super::unimplemented("assets_force_create is not implemented"),
+	|_, _, _| super::unimplemented("assets_mint is not implemented")
 );
 
 define_ibc_event_wrapper!(IbcEventWrapper, MetadataIbcEvent,);
@@ -126,11 +130,16 @@ define_event_record!(
 	EventRecord<<<DaliConfig as light_client_common::config::Config>::ParaRuntimeEvent as AsInner>::Inner, H256>,
 	IbcEventWrapper,
 	parachain_subxt::api::runtime_types::frame_system::Phase,
-	parachain_subxt::api::runtime_types::pallet_ibc::pallet::Event,
-	parachain_subxt::api::runtime_types::dali_runtime::RuntimeEvent
+	parachain_subxt::api::runtime_types::dali_runtime::RuntimeEvent,
+	{ Ibc => parachain_subxt::api::runtime_types::pallet_ibc::pallet::Event }
 );
 
-define_events!(DaliEvents, parachain_subxt::api::ibc::events::Events, IbcEventWrapper);
+define_events!(
+	DaliEvents,
+	parachain_subxt::api::ibc::events::Events,
+	parachain_subxt::api::runtime_types::pallet_ibc::errors::IbcError,
+	IbcEventWrapper
+);
 
 define_runtime_event!(
 	DaliParaRuntimeEvent,
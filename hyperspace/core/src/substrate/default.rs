@@ -1,4 +1,5 @@
 use self::parachain_subxt::api::{
+	assets::calls::types::Mint,
 	ibc::calls::types::{Deliver, Transfer},
 	ibc_ping::calls::types::SendPing,
 	runtime_types::{
@@ -16,12 +17,11 @@ use crate::{
 	define_runtime_event, define_runtime_storage, define_runtime_transactions,
 	define_send_ping_params, define_transfer_params,
 };
-use async_trait::async_trait;
-use codec::{Compact, Decode, Encode};
+use codec::{Decode, Encode};
 use ibc_proto::google::protobuf::Any;
 use light_client_common::config::{
-	EventRecordT, IbcEventsT, LocalAddress, ParaLifecycleT, RuntimeCall, RuntimeStorage,
-	RuntimeTransactions,
+	EventRecordT, IbcEventsT, LocalAddress, ParaLifecycleT, ParachainRuntime, PolkadotParams,
+	RuntimeCall, RuntimeStorage, RuntimeTransactions,
 };
 use pallet_ibc::{events::IbcEvent as RawIbcEvent, MultiAddress, Timeout, TransferParams};
 use pallet_ibc_ping::SendPingParams;
@@ -29,21 +29,12 @@ use parachain_subxt::api::runtime_types::ibc_primitives::Timeout as RawTimeout;
 use relaychain::api::runtime_types::polkadot_runtime_parachains::paras::ParaLifecycle;
 use sp_core::{crypto::AccountId32, H256};
 use subxt::{
-	config::{
-		extrinsic_params::Era,
-		polkadot::{
-			PlainTip as Tip, PolkadotExtrinsicParams as ParachainExtrinsicParams,
-			PolkadotExtrinsicParamsBuilder as ParachainExtrinsicsParamsBuilder,
-		},
-		ExtrinsicParams,
-	},
 	events::Phase,
 	storage::{
 		address::{StaticStorageMapKey, Yes},
 		Address,
 	},
 	tx::Payload,
-	Error, OnlineClient,
 };
 
 pub mod parachain_subxt {
@@ -64,8 +55,14 @@ pub mod relaychain {
 
 pub type Balance = u128;
 
+/// Types of the default parachain runtime.
 #[derive(Debug, Clone)]
-pub enum DefaultConfig {}
+pub enum DefaultRuntime {}
+
+/// Config of the default parachain runtime, whose extrinsic params are of the kind `P`, the
+/// Polkadot ones unless specified.
+pub type DefaultConfig<P = PolkadotParams> =
+	light_client_common::config::DefaultConfig<DefaultRuntime, P>;
 
 define_id!(DefaultId, relaychain::api::runtime_types::polkadot_parachain::primitives::Id);
 
@@ -122,6 +119,7 @@ define_runtime_transactions!(
 	SendPingParamsWrapper,
 	parachain_subxt::api::runtime_types::pallet_ibc::Any,
 	RawMemo,
+	Mint,
 	|x| parachain_subxt::api::tx().ibc().deliver(x),
 	|x, y, z, w| parachain_subxt::api::tx().ibc().transfer(x, y, z, w),
 	|x| parachain_subxt::api::tx().sudo().sudo(x),
@@ -130,6 +128,22 @@ define_runtime_transactions!(
 		parachain_subxt::api::runtime_types::parachain_runtime::RuntimeCall::Ibc(
 			parachain_subxt::api::runtime_types::pallet_ibc::pallet::Call::increase_counters {}
 		)
+	),
+	|memo| Some(RawMemo(memo)),
+	|id, owner: AccountId32| DefaultParaRuntimeCall(
+		parachain_subxt::api::runtime_types::parachain_runtime::RuntimeCall::Assets(
+			parachain_subxt::api::runtime_types::pallet_assets::pallet::Call::force_create {
+				id,
+				owner: subxt::utils::MultiAddress::Id(subxt::utils::AccountId32(owner.into())),
+				is_sufficient: true,
+				min_balance: 1,
+			}
+		)
+	),
+	|id, beneficiary: AccountId32, amount| parachain_subxt::api::tx().assets().mint(
+		id,
+		subxt::utils::MultiAddress::Id(subxt::utils::AccountId32(beneficiary.into())),
+		amount
 	)
 );
 
@@ -140,11 +154,16 @@ define_event_record!(
 	EventRecord<<<DefaultConfig as light_client_common::config::Config>::ParaRuntimeEvent as AsInner>::Inner, H256>,
 	IbcEventWrapper,
 	parachain_subxt::api::runtime_types::frame_system::Phase,
-	parachain_subxt::api::runtime_types::pallet_ibc::pallet::Event,
-	parachain_subxt::api::runtime_types::parachain_runtime::RuntimeEvent
+	parachain_subxt::api::runtime_types::parachain_runtime::RuntimeEvent,
+	{ Ibc => parachain_subxt::api::runtime_types::pallet_ibc::pallet::Event }
 );
 
-define_events!(DefaultEvents, parachain_subxt::api::ibc::events::Events, IbcEventWrapper);
+define_events!(
+	DefaultEvents,
+	parachain_subxt::api::ibc::events::Events,
+	parachain_subxt::api::runtime_types::pallet_ibc::errors::IbcError,
+	IbcEventWrapper
+);
 
 define_runtime_event!(
 	DefaultParaRuntimeEvent,
@@ -158,40 +177,28 @@ define_runtime_call!(
 	parachain_subxt::api::runtime_types::pallet_ibc::pallet::Call
 );
 
-#[async_trait]
-impl light_client_common::config::Config for DefaultConfig {
-	type AssetId = u128;
-	type Signature = <Self as subxt::Config>::Signature;
-	type Address = <Self as subxt::Config>::Address;
-	type Tip = Tip;
+impl ParachainRuntime for DefaultRuntime {
 	type ParaRuntimeCall = DefaultParaRuntimeCall;
 	type ParaRuntimeEvent = DefaultParaRuntimeEvent;
 	type Events = DefaultEvents;
 	type EventRecord = DefaultEventRecord;
 	type Storage = DefaultRuntimeStorage;
 	type Tx = DefaultRuntimeTransactions;
-	type SignedExtra = (Era, CheckNonce, Compact<Balance>);
-
-	async fn custom_extrinsic_params(
-		client: &OnlineClient<Self>,
-	) -> Result<
-		<Self::ExtrinsicParams as ExtrinsicParams<Self::Index, Self::Hash>>::OtherParams,
-		Error,
-	> {
-		let params =
-			ParachainExtrinsicsParamsBuilder::new().era(Era::Immortal, client.genesis_hash());
-		Ok(params)
-	}
+	type CheckNonce = CheckNonce;
 }
 
-impl subxt::Config for DefaultConfig {
-	type Index = u32;
-	type Hash = H256;
-	type Hasher = subxt::config::substrate::BlakeTwo256;
-	type AccountId = AccountId32;
-	type Address = sp_runtime::MultiAddress<Self::AccountId, u32>;
-	type Header =
-		subxt::config::substrate::SubstrateHeader<u32, subxt::config::substrate::BlakeTwo256>;
-	type Signature = sp_runtime::MultiSignature;
-	type ExtrinsicParams = ParachainExtrinsicParams<Self>;
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use light_client_common::config::SubstrateParams;
+	use parachain::ParachainClient;
+	use primitives::Chain;
+
+	fn assert_chain<C: Chain>() {}
+
+	#[test]
+	fn parachain_clients_support_both_kinds_of_extrinsic_params() {
+		assert_chain::<ParachainClient<DefaultConfig<PolkadotParams>>>();
+		assert_chain::<ParachainClient<DefaultConfig<SubstrateParams>>>();
+	}
 }
@@ -682,11 +682,15 @@ macro_rules! define_runtime_transactions {
 		$send_ping_params_wrapper:expr,
 		$any: path,
 		$memo_message:ty,
+		$assets_mint_call:ty,
 		$ibc_deliver: expr,
 		$ibc_transfer: expr,
 		$sudo_sudo: expr,
 		$ibc_ping_send_ping: expr,
-		$ibc_increase_counters: expr
+		$ibc_increase_counters: expr,
+		$memo: expr,
+		$assets_force_create: expr,
+		$assets_mint: expr
 	) => {
 		pub struct $name;
 
@@ -695,6 +699,7 @@ macro_rules! define_runtime_transactions {
 			type Transfer = $transfer;
 			type Sudo = $sudo;
 			type SendPing = $send_ping;
+			type AssetsMint = $assets_mint_call;
 
 			type ParaRuntimeCall = $para_runtime_call;
 			type SendPingParams = $send_ping_params;
@@ -731,13 +736,42 @@ macro_rules! define_runtime_transactions {
 			fn ibc_increase_counters() -> Self::ParaRuntimeCall {
 				$ibc_increase_counters()
 			}
+
+			fn memo(memo: String) -> Option<Self::MemoMessage> {
+				$memo(memo)
+			}
+
+			fn assets_force_create(
+				asset_id: u128,
+				owner: sp_core::crypto::AccountId32,
+			) -> Self::ParaRuntimeCall {
+				$assets_force_create(asset_id, owner)
+			}
+
+			fn assets_mint(
+				asset_id: u128,
+				beneficiary: sp_core::crypto::AccountId32,
+				amount: u128,
+			) -> Payload<Self::AssetsMint> {
+				$assets_mint(asset_id, beneficiary, amount)
+			}
 		}
 	};
 }
 
+/// Implements [`light_client_common::config::EventRecordT`] for the event records of a runtime.
+/// The IBC events are extracted from the `Events` event of every listed pallet, keyed by its
+/// variant in the runtime event enum, e.g. `{ Ibc => pallet_ibc::pallet::Event }`.
 #[macro_export]
 macro_rules! define_event_record {
-	($name:ident, $event_record:ty, $ibc_event_wrapper: expr, $phase: path, $pallet_event: path, $runtime_event: path) => {
+	(
+		$name:ident,
+		$event_record:ty,
+		$ibc_event_wrapper: expr,
+		$phase: path,
+		$runtime_event: path,
+		{ $($variant: ident => $pallet_event: path),+ $(,)? }
+	) => {
 		#[derive(Decode, Encode)]
 		pub struct $name(pub $event_record);
 
@@ -753,21 +787,29 @@ macro_rules! define_event_record {
 				}
 			}
 
+			#[allow(unreachable_patterns)]
 			fn ibc_events(self) -> Option<Vec<pallet_ibc::events::IbcEvent>> {
-				use $pallet_event as PalletEvent;
 				use $runtime_event as RuntimeEvent;
-				if let RuntimeEvent::Ibc(PalletEvent::Events { events }) = self.0.event {
-					let events = events
-						.into_iter()
-						.filter_map(|event| {
-							let ev = event.ok()?;
-							Some(pallet_ibc::events::IbcEvent::from($ibc_event_wrapper(ev)))
-						})
-						.collect();
-					Some(events)
-				} else {
-					None
-				}
+				let events = match self.0.event {
+					$(
+						RuntimeEvent::$variant(event) => {
+							use $pallet_event as PalletEvent;
+							match event {
+								PalletEvent::Events { events } => events,
+								_ => return None,
+							}
+						},
+					)+
+					_ => return None,
+				};
+				let events = events
+					.into_iter()
+					.filter_map(|event| {
+						let ev = event.ok()?;
+						Some(pallet_ibc::events::IbcEvent::from($ibc_event_wrapper(ev)))
+					})
+					.collect();
+				Some(events)
 			}
 		}
 
@@ -781,24 +823,31 @@ macro_rules! define_event_record {
 	};
 }
 
+/// Implements [`light_client_common::config::IbcEventsT`] for the `Events` event of pallet-ibc. The
+/// pallet is looked up by the name the `$events` type was generated with, so a runtime mounting
+/// pallet-ibc under another name passes the events type of that pallet, e.g.
+/// `api::ibc_core::events::Events`. `$ibc_error` is the generated `pallet_ibc::errors::IbcError`
+/// recorded for the messages that failed.
 #[macro_export]
 macro_rules! define_events {
-	($name:ident, $events:ty, $ibc_event_wrapper: expr) => {
+	($name:ident, $events:ty, $ibc_error:ty, $ibc_event_wrapper: expr) => {
 		use light_client_common::config::AsInnerEvent;
 
 		pub struct $name(pub $events);
 
 		impl IbcEventsT for $name {
 			type IbcEvent = pallet_ibc::events::IbcEvent;
+			type IbcError = $ibc_error;
 
 			fn events(self) -> Vec<Self::IbcEvent> {
+				self.results().into_iter().filter_map(Result::ok).collect()
+			}
+
+			fn results(self) -> Vec<Result<Self::IbcEvent, Self::IbcError>> {
 				self.0
 					.events
 					.into_iter()
-					.filter_map(|event| {
-						let ev = event.ok()?;
-						Some(Self::IbcEvent::from($ibc_event_wrapper(ev)))
-					})
+					.map(|event| event.map(|ev| Self::IbcEvent::from($ibc_event_wrapper(ev))))
 					.collect()
 			}
 		}
@@ -884,3 +933,75 @@ macro_rules! define_asset_id {
 		}
 	};
 }
+
+#[cfg(test)]
+mod tests {
+	use super::super::default::{
+		parachain_subxt::api::runtime_types::{
+			frame_system::{self, EventRecord},
+			pallet_ibc::{events::IbcEvent as MetadataIbcEvent, pallet::Event as IbcPalletEvent},
+		},
+		IbcEventWrapper,
+	};
+	use crate::define_event_record;
+	use codec::{Decode, Encode};
+	use light_client_common::config::{AsInner, EventRecordT};
+	use pallet_ibc::events::IbcEvent;
+	use sp_core::H256;
+	use subxt::{
+		events::Phase,
+		ext::{scale_decode::DecodeAsType, scale_encode::EncodeAsType},
+	};
+
+	/// Events of a runtime mounting pallet-ibc twice, under names other than `Ibc`.
+	#[derive(Decode, Encode, DecodeAsType, EncodeAsType)]
+	#[decode_as_type(crate_path = "::subxt::ext::scale_decode")]
+	#[encode_as_type(crate_path = "::subxt::ext::scale_encode")]
+	pub enum RenamedRuntimeEvent {
+		System(u32),
+		IbcCore(IbcPalletEvent),
+		IbcTransfer(IbcPalletEvent),
+	}
+
+	define_event_record!(
+		RenamedEventRecord,
+		EventRecord<RenamedRuntimeEvent, H256>,
+		IbcEventWrapper,
+		self::frame_system::Phase,
+		self::RenamedRuntimeEvent,
+		{ IbcCore => self::IbcPalletEvent, IbcTransfer => self::IbcPalletEvent }
+	);
+
+	fn record(event: RenamedRuntimeEvent) -> RenamedEventRecord {
+		let phase = frame_system::Phase::ApplyExtrinsic(1);
+		RenamedEventRecord(EventRecord { phase, event, topics: vec![] })
+	}
+
+	fn new_block(revision_height: u64) -> IbcPalletEvent {
+		let event = MetadataIbcEvent::NewBlock { revision_height, revision_number: 1 };
+		IbcPalletEvent::Events { events: vec![Ok(event)] }
+	}
+
+	fn heights(record: RenamedEventRecord) -> Option<Vec<u64>> {
+		let events = record.ibc_events()?;
+		let heights = events.into_iter().map(|event| match event {
+			IbcEvent::NewBlock { revision_height, .. } => revision_height,
+			event => panic!("unexpected event {event:?}"),
+		});
+		Some(heights.collect())
+	}
+
+	#[test]
+	fn events_of_renamed_pallets_are_extracted() {
+		assert!(matches!(record(RenamedRuntimeEvent::System(0)).phase(), Phase::ApplyExtrinsic(1)));
+		assert_eq!(heights(record(RenamedRuntimeEvent::IbcCore(new_block(10)))), Some(vec![10]));
+		assert_eq!(
+			heights(record(RenamedRuntimeEvent::IbcTransfer(new_block(11)))),
+			Some(vec![11])
+		);
+		assert_eq!(heights(record(RenamedRuntimeEvent::System(0))), None);
+		let channel_opened =
+			IbcPalletEvent::ChannelOpened { channel_id: b"channel-0".to_vec(), port_id: vec![] };
+		assert_eq!(heights(record(RenamedRuntimeEvent::IbcCore(channel_opened))), None);
+	}
+}
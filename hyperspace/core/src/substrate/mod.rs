@@ -8,6 +8,7 @@ pub mod picasso_rococo;
 
 pub use composable::ComposableConfig;
 pub use default::DefaultConfig;
+pub use light_client_common::config::{PolkadotParams, SubstrateParams};
 pub use picasso_kusama::PicassoKusamaConfig;
 pub use picasso_rococo::PicassoRococoConfig;
 
@@ -0,0 +1,639 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! OpenTelemetry tracing of the relayed packets, enabled by the `telemetry` feature and exported
+//! over OTLP to the collector of the `telemetry` section of the core config.
+//!
+//! Every packet gets a `relay_packet` span, from the observation of its `SendPacket` event on the
+//! sender until its acknowledgement or timeout is observed there, with a child span for each
+//! stage of its relay: `proof_query`, `client_update`, `submit` and `ack_observation`.
+//! The outcome written in the acknowledgement of the packet is recorded on its span as
+//! `ibc.ack_result`, and error acknowledgements set its status to an error.
+//!
+//! The stages run with their span as the current [`opentelemetry::Context`], which is carried by
+//! the future rather than passed to the [`primitives::IbcProvider`] queries, so the spans the
+//! queries start off the current context (see [`rpc`]) show up as children of the stage.
+//!
+//! Without the `telemetry` feature, all of these are no-ops.
+
+use ibc::{
+	core::{ics04_channel::packet::Packet, ics24_host::identifier::ClientId},
+	events::IbcEvent,
+	Height,
+};
+use ibc_proto::google::protobuf::Any;
+use primitives::acknowledgement::AckResult;
+use serde::{Deserialize, Serialize};
+use std::{fmt::Debug, future::Future};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TelemetryConfig {
+	/// Endpoint of the OTLP collector receiving the spans, e.g. `http://localhost:4317`
+	pub otlp_endpoint: String,
+	/// Name of the service the spans are reported under
+	#[serde(default = "default_service_name")]
+	pub service_name: String,
+}
+
+fn default_service_name() -> String {
+	"hyperspace".to_string()
+}
+
+/// Exports the spans of the relayed packets to the collector of `config`.
+pub fn init(config: &TelemetryConfig) -> Result<(), anyhow::Error> {
+	#[cfg(feature = "telemetry")]
+	{
+		tracer::init(config)
+	}
+	#[cfg(not(feature = "telemetry"))]
+	{
+		log::warn!(
+			target: "hyperspace",
+			"Not exporting spans to {}, hyperspace was built without the `telemetry` feature",
+			config.otlp_endpoint
+		);
+		Ok(())
+	}
+}
+
+/// Flushes the spans that weren't exported yet.
+pub fn shutdown() {
+	#[cfg(feature = "telemetry")]
+	opentelemetry::global::shutdown_tracer_provider();
+}
+
+/// Starts the spans of the packets sent by `source` to `sink` in `events`, and records the
+/// observation of the acknowledgements and timeouts of the packets exchanged with `sink`.
+#[cfg_attr(not(feature = "telemetry"), allow(unused_variables))]
+pub fn observe_events(source: &str, sink: &str, events: &[IbcEvent]) {
+	#[cfg(feature = "telemetry")]
+	if let Some(tracer) = tracer::PACKET_TRACER.get() {
+		tracer.observe_events(source, sink, events)
+	}
+}
+
+/// Records that the packet messages in `msgs`, submitted to `chain`, are proven by the update of
+/// `client_id` to `height`.
+#[cfg_attr(not(feature = "telemetry"), allow(unused_variables))]
+pub fn record_client_update(chain: &str, client_id: &ClientId, height: Height, msgs: &[Any]) {
+	#[cfg(feature = "telemetry")]
+	if let Some(tracer) = tracer::PACKET_TRACER.get() {
+		tracer.record_client_update(chain, client_id, height, msgs)
+	}
+}
+
+/// Runs `query`, the query of a proof for `packet` sent by `sender` to `receiver`, in the
+/// `proof_query` span of the packet.
+#[cfg_attr(not(feature = "telemetry"), allow(unused_variables))]
+pub async fn proof_query<F: Future>(
+	sender: &str,
+	receiver: &str,
+	packet: &Packet,
+	query: F,
+) -> F::Output {
+	#[cfg(feature = "telemetry")]
+	if let Some(tracer) = tracer::PACKET_TRACER.get() {
+		return tracer.proof_query(sender, receiver, packet, query).await
+	}
+	query.await
+}
+
+/// Runs `submit`, the submission of `msgs` to `chain`, in a `submit` span of each packet of the
+/// batch, recording the transaction id it returns.
+#[cfg_attr(not(feature = "telemetry"), allow(unused_variables))]
+pub async fn submission<F, T, E>(chain: &str, msgs: &[Any], submit: F) -> Result<T, E>
+where
+	F: Future<Output = Result<T, E>>,
+	T: Debug,
+	E: Debug,
+{
+	#[cfg(feature = "telemetry")]
+	if let Some(tracer) = tracer::PACKET_TRACER.get() {
+		return tracer.submission(chain, msgs, submit).await
+	}
+	submit.await
+}
+
+/// Records the outcome of `packet`, sent by `sender`, written in its acknowledgement that is
+/// relayed back to `sender`.
+#[cfg_attr(not(feature = "telemetry"), allow(unused_variables))]
+pub fn record_acknowledgement(sender: &str, packet: &Packet, result: &AckResult) {
+	#[cfg(feature = "telemetry")]
+	if let Some(tracer) = tracer::PACKET_TRACER.get() {
+		tracer.record_acknowledgement(sender, packet, result)
+	}
+}
+
+/// Runs `call`, a query of `chain`, in a span that is a child of the current context. Calls made
+/// outside the stage of a packet aren't traced.
+#[cfg_attr(not(feature = "telemetry"), allow(unused_variables))]
+pub async fn rpc<F: Future>(chain: &str, method: &'static str, call: F) -> F::Output {
+	#[cfg(feature = "telemetry")]
+	if let Some(tracer) = tracer::PACKET_TRACER.get() {
+		return tracer.rpc(chain, method, call).await
+	}
+	call.await
+}
+
+#[cfg(feature = "telemetry")]
+mod tracer {
+	use super::TelemetryConfig;
+	use crate::queue::decode_packet_msg;
+	use anyhow::anyhow;
+	use ibc::{
+		core::{
+			ics04_channel::packet::Packet,
+			ics24_host::identifier::{ChannelId, ClientId, PortId},
+		},
+		events::IbcEvent,
+		Height,
+	};
+	use ibc_proto::google::protobuf::Any;
+	use opentelemetry::{
+		trace::{FutureExt, Status, TraceContextExt, Tracer as _},
+		Context, KeyValue,
+	};
+	use opentelemetry_sdk::{runtime, trace::Tracer, Resource};
+	use primitives::acknowledgement::AckResult;
+	use std::{
+		collections::{BTreeMap, HashMap},
+		fmt::Debug,
+		future::Future,
+		sync::{Mutex, OnceLock},
+	};
+
+	/// Number of packets traced at once. Past it, the spans of the oldest packets are ended.
+	const MAX_TRACED_PACKETS: usize = 10_000;
+
+	pub(super) static PACKET_TRACER: OnceLock<PacketTracer> = OnceLock::new();
+
+	pub(super) fn init(config: &TelemetryConfig) -> Result<(), anyhow::Error> {
+		let tracer = opentelemetry_otlp::new_pipeline()
+			.tracing()
+			.with_exporter(
+				opentelemetry_otlp::new_exporter().tonic().with_endpoint(&config.otlp_endpoint),
+			)
+			.with_trace_config(opentelemetry_sdk::trace::config().with_resource(Resource::new(
+				vec![KeyValue::new("service.name", config.service_name.clone())],
+			)))
+			.install_batch(runtime::Tokio)?;
+		PACKET_TRACER
+			.set(PacketTracer::new(tracer))
+			.map_err(|_| anyhow!("Telemetry is already initialized"))
+	}
+
+	/// An end of the channel of a packet, on `chain`.
+	#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+	struct PacketEnd {
+		chain: String,
+		port_id: PortId,
+		channel_id: ChannelId,
+		sequence: u64,
+	}
+
+	impl PacketEnd {
+		fn sender(chain: &str, packet: &Packet) -> Self {
+			Self {
+				chain: chain.to_string(),
+				port_id: packet.source_port.clone(),
+				channel_id: packet.source_channel,
+				sequence: packet.sequence.into(),
+			}
+		}
+
+		fn receiver(chain: &str, packet: &Packet) -> Self {
+			Self {
+				chain: chain.to_string(),
+				port_id: packet.destination_port.clone(),
+				channel_id: packet.destination_channel,
+				sequence: packet.sequence.into(),
+			}
+		}
+
+		/// End of the packet of a packet message submitted to `chain`.
+		fn of_msg(chain: &str, msg: &Any) -> Option<Self> {
+			let (packet, is_recv) = decode_packet_msg(msg)?;
+			Some(if is_recv {
+				Self::receiver(chain, &packet)
+			} else {
+				Self::sender(chain, &packet)
+			})
+		}
+	}
+
+	/// The `relay_packet` spans of the packets in flight, by the order they were started in. Both
+	/// ends of a packet point to its span, so it's found from the messages submitted to either
+	/// chain.
+	#[derive(Default)]
+	struct TracedPackets {
+		spans: BTreeMap<u64, (Context, [PacketEnd; 2])>,
+		ends: HashMap<PacketEnd, u64>,
+		next_id: u64,
+	}
+
+	impl TracedPackets {
+		fn get(&self, end: &PacketEnd) -> Option<Context> {
+			let id = self.ends.get(end)?;
+			self.spans.get(id).map(|(cx, _)| cx.clone())
+		}
+
+		fn insert(&mut self, cx: Context, ends: [PacketEnd; 2]) {
+			while self.spans.len() >= MAX_TRACED_PACKETS {
+				match self.spans.keys().next().copied() {
+					Some(id) => self.remove(id).span().end(),
+					None => break,
+				}
+			}
+			let id = self.next_id;
+			self.next_id += 1;
+			for end in &ends {
+				if let Some(previous) = self.ends.insert(end.clone(), id) {
+					// the packet was sent again, e.g. after a restart of the chain
+					self.remove(previous).span().end();
+				}
+			}
+			self.spans.insert(id, (cx, ends));
+		}
+
+		fn remove(&mut self, id: u64) -> Context {
+			let Some((cx, ends)) = self.spans.remove(&id) else { return Context::new() };
+			for end in ends {
+				if self.ends.get(&end) == Some(&id) {
+					self.ends.remove(&end);
+				}
+			}
+			cx
+		}
+	}
+
+	/// Traces the relay of packets with a [`Tracer`].
+	pub struct PacketTracer {
+		tracer: Tracer,
+		packets: Mutex<TracedPackets>,
+	}
+
+	impl PacketTracer {
+		pub fn new(tracer: Tracer) -> Self {
+			Self { tracer, packets: Mutex::new(TracedPackets::default()) }
+		}
+
+		/// Returns the context of the span of `packet`, starting it if the packet wasn't seen
+		/// yet.
+		fn packet_span(
+			&self,
+			sender: &str,
+			receiver: &str,
+			packet: &Packet,
+			send_height: Option<Height>,
+		) -> Context {
+			let mut packets = self.packets.lock().unwrap();
+			let ends = [PacketEnd::sender(sender, packet), PacketEnd::receiver(receiver, packet)];
+			if let Some(cx) = packets.get(&ends[0]) {
+				return cx
+			}
+			let mut attributes = vec![
+				KeyValue::new("hyperspace.source_chain", sender.to_string()),
+				KeyValue::new("hyperspace.sink_chain", receiver.to_string()),
+				KeyValue::new("ibc.source_port", packet.source_port.to_string()),
+				KeyValue::new("ibc.source_channel", packet.source_channel.to_string()),
+				KeyValue::new("ibc.destination_port", packet.destination_port.to_string()),
+				KeyValue::new("ibc.destination_channel", packet.destination_channel.to_string()),
+				KeyValue::new("ibc.sequence", u64::from(packet.sequence) as i64),
+			];
+			if let Some(height) = send_height {
+				attributes.push(KeyValue::new("hyperspace.send_height", height.to_string()));
+			}
+			let span = self
+				.tracer
+				.span_builder("relay_packet")
+				.with_attributes(attributes)
+				.start_with_context(&self.tracer, &Context::new());
+			let cx = Context::new().with_span(span);
+			packets.insert(cx.clone(), ends);
+			cx
+		}
+
+		fn find(&self, end: &PacketEnd) -> Option<Context> {
+			self.packets.lock().unwrap().get(end)
+		}
+
+		fn child(
+			&self,
+			parent: &Context,
+			name: &'static str,
+			attributes: Vec<KeyValue>,
+		) -> Context {
+			let span = self
+				.tracer
+				.span_builder(name)
+				.with_attributes(attributes)
+				.start_with_context(&self.tracer, parent);
+			parent.with_span(span)
+		}
+
+		/// Ends the span of the packet with the `end`, recording how it was `settled`.
+		fn settle(&self, end: &PacketEnd, settled: &'static str, height: Height) {
+			let mut packets = self.packets.lock().unwrap();
+			let Some(id) = packets.ends.get(end).copied() else { return };
+			let cx = packets.remove(id);
+			let span = cx.span();
+			span.add_event(settled, vec![KeyValue::new("hyperspace.height", height.to_string())]);
+			span.end();
+		}
+
+		pub fn observe_events(&self, source: &str, sink: &str, events: &[IbcEvent]) {
+			for event in events {
+				match event {
+					IbcEvent::SendPacket(send_packet) => {
+						self.packet_span(
+							source,
+							sink,
+							&send_packet.packet,
+							Some(send_packet.height),
+						);
+					},
+					IbcEvent::WriteAcknowledgement(write_ack) => {
+						let end = PacketEnd::receiver(source, &write_ack.packet);
+						let Some(parent) = self.find(&end) else { continue };
+						let attributes = vec![
+							KeyValue::new("hyperspace.chain", source.to_string()),
+							KeyValue::new("hyperspace.height", write_ack.height.to_string()),
+						];
+						self.child(&parent, "ack_observation", attributes).span().end();
+					},
+					IbcEvent::AcknowledgePacket(ack) => self.settle(
+						&PacketEnd::sender(source, &ack.packet),
+						"acknowledged",
+						ack.height,
+					),
+					IbcEvent::TimeoutPacket(timeout) => self.settle(
+						&PacketEnd::sender(source, &timeout.packet),
+						"timed_out",
+						timeout.height,
+					),
+					IbcEvent::TimeoutOnClosePacket(timeout) => self.settle(
+						&PacketEnd::sender(source, &timeout.packet),
+						"timed_out_on_close",
+						timeout.height,
+					),
+					_ => {},
+				}
+			}
+		}
+
+		pub fn record_client_update(
+			&self,
+			chain: &str,
+			client_id: &ClientId,
+			height: Height,
+			msgs: &[Any],
+		) {
+			for msg in msgs {
+				let Some(parent) = PacketEnd::of_msg(chain, msg).and_then(|end| self.find(&end))
+				else {
+					continue
+				};
+				let attributes = vec![
+					KeyValue::new("hyperspace.chain", chain.to_string()),
+					KeyValue::new("ibc.client_id", client_id.to_string()),
+					KeyValue::new("hyperspace.height", height.to_string()),
+				];
+				self.child(&parent, "client_update", attributes).span().end();
+			}
+		}
+
+		pub fn record_acknowledgement(&self, sender: &str, packet: &Packet, result: &AckResult) {
+			let Some(cx) = self.find(&PacketEnd::sender(sender, packet)) else { return };
+			let span = cx.span();
+			span.set_attribute(KeyValue::new("ibc.ack_result", result.to_string()));
+			if result.is_error() {
+				span.set_status(Status::error(result.to_string()));
+			}
+		}
+
+		pub async fn proof_query<F: Future>(
+			&self,
+			sender: &str,
+			receiver: &str,
+			packet: &Packet,
+			query: F,
+		) -> F::Output {
+			let parent = self.packet_span(sender, receiver, packet, None);
+			let cx = self.child(&parent, "proof_query", vec![]);
+			let output = query.with_context(cx.clone()).await;
+			cx.span().end();
+			output
+		}
+
+		pub async fn submission<F, T, E>(
+			&self,
+			chain: &str,
+			msgs: &[Any],
+			submit: F,
+		) -> Result<T, E>
+		where
+			F: Future<Output = Result<T, E>>,
+			T: Debug,
+			E: Debug,
+		{
+			let spans = msgs
+				.iter()
+				.filter_map(|msg| PacketEnd::of_msg(chain, msg))
+				.filter_map(|end| self.find(&end))
+				.map(|parent| {
+					let attributes = vec![
+						KeyValue::new("hyperspace.chain", chain.to_string()),
+						KeyValue::new("hyperspace.batch_size", msgs.len() as i64),
+					];
+					self.child(&parent, "submit", attributes)
+				})
+				.collect::<Vec<_>>();
+			let result = submit.await;
+			for cx in spans {
+				let span = cx.span();
+				match &result {
+					Ok(tx_id) => span
+						.set_attribute(KeyValue::new("hyperspace.tx_hash", format!("{tx_id:?}"))),
+					Err(e) => span.set_status(Status::error(format!("{e:?}"))),
+				}
+				span.end();
+			}
+			result
+		}
+
+		pub async fn rpc<F: Future>(
+			&self,
+			chain: &str,
+			method: &'static str,
+			call: F,
+		) -> F::Output {
+			let parent = Context::current();
+			if !parent.has_active_span() {
+				return call.await
+			}
+			let cx = self.child(
+				&parent,
+				method,
+				vec![KeyValue::new("hyperspace.chain", chain.to_string())],
+			);
+			let output = call.with_context(cx.clone()).await;
+			cx.span().end();
+			output
+		}
+	}
+}
+
+#[cfg(all(test, feature = "telemetry"))]
+mod tests {
+	use super::tracer::PacketTracer;
+	use ibc::{
+		core::{
+			ics04_channel::{
+				events::{AcknowledgePacket, SendPacket, WriteAcknowledgement},
+				msgs::recv_packet::MsgRecvPacket,
+				packet::{Packet, Sequence},
+			},
+			ics23_commitment::commitment::CommitmentProofBytes,
+			ics24_host::identifier::{ChannelId, ClientId, PortId},
+		},
+		events::IbcEvent,
+		proofs::Proofs,
+		signer::Signer,
+		timestamp::Timestamp,
+		tx_msg::Msg,
+		Height,
+	};
+	use ibc_proto::google::protobuf::Any;
+	use opentelemetry::{
+		trace::{SpanId, TracerProvider as _},
+		Value,
+	};
+	use opentelemetry_sdk::{
+		export::trace::SpanData, testing::trace::InMemorySpanExporter, trace::TracerProvider,
+	};
+	use primitives::acknowledgement::AckResult;
+	use std::str::FromStr;
+	use tendermint_proto::Protobuf;
+
+	fn packet() -> Packet {
+		Packet {
+			sequence: Sequence::from(7),
+			source_port: PortId::transfer(),
+			source_channel: ChannelId::new(0),
+			destination_port: PortId::transfer(),
+			destination_channel: ChannelId::new(0),
+			data: vec![],
+			timeout_height: Height::new(1, 1000),
+			timeout_timestamp: Timestamp::none(),
+		}
+	}
+
+	fn recv_msg(packet: Packet, proof: Vec<u8>) -> Any {
+		let proof = CommitmentProofBytes::try_from(proof).unwrap();
+		let msg = MsgRecvPacket {
+			packet,
+			proofs: Proofs::new(proof, None, None, None, Height::new(1, 10)).unwrap(),
+			signer: Signer::from_str("relayer").unwrap(),
+		};
+		Any { value: msg.encode_vec().unwrap(), type_url: msg.type_url() }
+	}
+
+	fn attribute(span: &SpanData, key: &str) -> Option<Value> {
+		span.attributes
+			.iter()
+			.find(|kv| kv.key.as_str() == key)
+			.map(|kv| kv.value.clone())
+	}
+
+	#[tokio::test]
+	async fn relayed_packets_are_traced_from_send_to_acknowledgement() {
+		let exporter = InMemorySpanExporter::default();
+		let provider = TracerProvider::builder().with_simple_exporter(exporter.clone()).build();
+		let tracer = PacketTracer::new(provider.tracer("hyperspace"));
+		let packet = packet();
+
+		// chain A sends the packet, which is relayed to chain B
+		let send_packet = SendPacket { height: Height::new(1, 9), packet: packet.clone() };
+		tracer.observe_events("chain-a", "chain-b", &[IbcEvent::SendPacket(send_packet)]);
+		let proof = tracer
+			.proof_query(
+				"chain-a",
+				"chain-b",
+				&packet,
+				tracer.rpc("chain-a", "query_proof", async { vec![1u8] }),
+			)
+			.await;
+		let msg = recv_msg(packet.clone(), proof);
+		let client_id = ClientId::new("07-tendermint", 0).unwrap();
+		tracer.record_client_update("chain-b", &client_id, Height::new(1, 10), &[msg.clone()]);
+		tracer
+			.submission("chain-b", &[msg], async { Ok::<_, ()>("0xabcd") })
+			.await
+			.unwrap();
+
+		// chain B acknowledges it, and the acknowledgement is relayed back to chain A
+		let write_ack = WriteAcknowledgement {
+			height: Height::new(1, 20),
+			packet: packet.clone(),
+			ack: vec![1],
+		};
+		tracer.observe_events("chain-b", "chain-a", &[IbcEvent::WriteAcknowledgement(write_ack)]);
+		tracer.record_acknowledgement(
+			"chain-a",
+			&packet,
+			&AckResult::Error("insufficient funds".to_string()),
+		);
+		let ack = AcknowledgePacket { height: Height::new(1, 12), packet };
+		tracer.observe_events("chain-a", "chain-b", &[IbcEvent::AcknowledgePacket(ack)]);
+
+		provider.force_flush();
+		let spans = exporter.get_finished_spans().unwrap();
+		let span = |name: &str| {
+			spans
+				.iter()
+				.find(|span| span.name == name)
+				.unwrap_or_else(|| panic!("missing {name} span"))
+		};
+		let root = span("relay_packet");
+		assert_eq!(root.parent_span_id, SpanId::INVALID);
+		for stage in ["proof_query", "client_update", "submit", "ack_observation"] {
+			let stage_span = span(stage);
+			assert_eq!(stage_span.parent_span_id, root.span_context.span_id(), "{stage}");
+			assert_eq!(stage_span.span_context.trace_id(), root.span_context.trace_id(), "{stage}");
+		}
+		assert_eq!(span("query_proof").parent_span_id, span("proof_query").span_context.span_id());
+
+		assert_eq!(attribute(root, "hyperspace.source_chain"), Some("chain-a".into()));
+		assert_eq!(attribute(root, "hyperspace.sink_chain"), Some("chain-b".into()));
+		assert_eq!(attribute(root, "ibc.sequence"), Some(Value::I64(7)));
+		assert_eq!(attribute(root, "hyperspace.send_height"), Some("1-9".into()));
+		assert_eq!(attribute(span("submit"), "hyperspace.tx_hash"), Some("\"0xabcd\"".into()));
+		assert_eq!(attribute(span("client_update"), "hyperspace.height"), Some("1-10".into()));
+		assert_eq!(attribute(span("ack_observation"), "hyperspace.chain"), Some("chain-b".into()));
+		assert_eq!(attribute(root, "ibc.ack_result"), Some("error: insufficient funds".into()));
+		assert!(root.events.iter().any(|event| event.name == "acknowledged"));
+	}
+
+	#[tokio::test]
+	async fn queries_outside_of_packet_stages_are_not_traced() {
+		let exporter = InMemorySpanExporter::default();
+		let provider = TracerProvider::builder().with_simple_exporter(exporter.clone()).build();
+		let tracer = PacketTracer::new(provider.tracer("hyperspace"));
+
+		tracer.rpc("chain-a", "query_latest_height", async {}).await;
+
+		provider.force_flush();
+		assert!(exporter.get_finished_spans().unwrap().is_empty());
+	}
+}
@@ -1,27 +1,76 @@
 use futures::{Stream, StreamExt};
+use ibc::{
+	core::{
+		ics04_channel::events::Attributes as ChannelAttributes,
+		ics24_host::identifier::{ChannelId, ClientId, ConnectionId, PortId},
+	},
+	events::IbcEvent,
+};
 use std::{
 	pin::Pin,
 	sync::{Arc, Mutex},
-	task::Poll,
+	task::{Poll, Waker},
 };
 
 /// Keeps the most recent value of a stream and acts as stream itself.
 pub struct RecentStream<T: Send + 'static> {
-	value: Arc<Mutex<Option<Option<T>>>>,
+	value: Arc<Mutex<RecentValue<T>>>,
+}
+
+struct RecentValue<T> {
+	/// Value kept by [`RecentStream::hold_next`], yielded before the most recent one
+	held: Option<T>,
+	latest: Option<T>,
+	hold_next: bool,
+	closed: bool,
+	waker: Option<Waker>,
 }
 
 impl<T: Send + 'static> RecentStream<T> {
 	pub fn new(mut stream: impl Stream<Item = T> + Send + Unpin + 'static) -> Self {
-		let value = Arc::new(Mutex::new(Some(None)));
+		let value = Arc::new(Mutex::new(RecentValue {
+			held: None,
+			latest: None,
+			hold_next: false,
+			closed: false,
+			waker: None,
+		}));
 		let value_cloned = value.clone();
 		tokio::spawn(async move {
 			while let Some(v) = stream.next().await {
-				*value_cloned.lock().unwrap() = Some(Some(v));
+				let mut value = value_cloned.lock().unwrap();
+				if value.hold_next {
+					value.held = Some(v);
+					value.hold_next = false;
+				} else {
+					value.latest = Some(v);
+				}
+				if let Some(waker) = value.waker.take() {
+					waker.wake();
+				}
+			}
+			let mut value = value_cloned.lock().unwrap();
+			value.closed = true;
+			if let Some(waker) = value.waker.take() {
+				waker.wake();
 			}
-			*value_cloned.lock().unwrap() = None;
 		});
 		Self { value }
 	}
+
+	/// Keeps the next value of the stream, or the pending one, until it's yielded instead of
+	/// replacing it with the values received in the meantime. The most recent value is yielded
+	/// right after it.
+	pub fn hold_next(&self) {
+		let mut value = self.value.lock().unwrap();
+		if value.held.is_some() {
+			return
+		}
+		match value.latest.take() {
+			Some(latest) => value.held = Some(latest),
+			None => value.hold_next = true,
+		}
+	}
 }
 
 impl<T: Send> Stream for RecentStream<T> {
@@ -33,15 +82,164 @@ impl<T: Send> Stream for RecentStream<T> {
 	) -> Poll<Option<Self::Item>> {
 		let this = self.get_mut();
 		let mut value = this.value.lock().unwrap();
-		match value.as_mut() {
-			Some(v) => match v.take() {
-				Some(v) => Poll::Ready(Some(v)),
-				None => {
-					cx.waker().wake_by_ref();
-					Poll::Pending
+		if let Some(v) = value.held.take() {
+			return Poll::Ready(Some(v))
+		}
+		if let Some(v) = value.latest.take() {
+			return Poll::Ready(Some(v))
+		}
+		if value.closed {
+			return Poll::Ready(None)
+		}
+		value.waker = Some(cx.waker().clone());
+		Poll::Pending
+	}
+}
+
+/// A channel of the relayed connection whose handshake completed, or which was closed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelUpdate {
+	pub opened: bool,
+	/// End of the channel on the chain that emitted the event
+	pub channel: (ChannelId, PortId),
+	/// End of the channel on the counterparty
+	pub counterparty: (ChannelId, PortId),
+}
+
+/// Returns the channels of `connection_id` opened or closed by `events`. The handshake completes
+/// with `OpenAckChannel` on the chain that initialized it and with `OpenConfirmChannel` on the
+/// other one.
+pub fn channel_updates(events: &[IbcEvent], connection_id: &ConnectionId) -> Vec<ChannelUpdate> {
+	events
+		.iter()
+		.filter_map(|event| {
+			let (opened, attributes) = match event {
+				IbcEvent::OpenAckChannel(ev) => (true, ChannelAttributes::from(ev.clone())),
+				IbcEvent::OpenConfirmChannel(ev) => (true, ChannelAttributes::from(ev.clone())),
+				IbcEvent::CloseInitChannel(ev) => (false, ChannelAttributes::from(ev.clone())),
+				IbcEvent::CloseConfirmChannel(ev) => (false, ChannelAttributes::from(ev.clone())),
+				_ => return None,
+			};
+			if &attributes.connection_id != connection_id {
+				return None
+			}
+			Some(ChannelUpdate {
+				opened,
+				channel: (attributes.channel_id?, attributes.port_id),
+				counterparty: (
+					attributes.counterparty_channel_id?,
+					attributes.counterparty_port_id,
+				),
+			})
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ibc::{
+		core::ics04_channel::events::{CloseInit, OpenAck, SendPacket},
+		Height,
+	};
+	use std::time::Duration;
+
+	fn open_ack(connection_id: u64) -> IbcEvent {
+		IbcEvent::OpenAckChannel(OpenAck {
+			height: Height::new(1, 10),
+			port_id: PortId::transfer(),
+			channel_id: Some(ChannelId::new(1)),
+			counterparty_channel_id: Some(ChannelId::new(2)),
+			connection_id: ConnectionId::new(connection_id),
+			counterparty_port_id: PortId::transfer(),
+		})
+	}
+
+	fn close_init(counterparty_channel_id: Option<ChannelId>) -> IbcEvent {
+		IbcEvent::CloseInitChannel(CloseInit {
+			height: Height::new(1, 10),
+			port_id: PortId::transfer(),
+			channel_id: ChannelId::new(1),
+			connection_id: ConnectionId::new(0),
+			counterparty_port_id: PortId::transfer(),
+			counterparty_channel_id,
+		})
+	}
+
+	#[test]
+	fn channel_updates_of_the_relayed_connection() {
+		let ends =
+			((ChannelId::new(1), PortId::transfer()), (ChannelId::new(2), PortId::transfer()));
+		let events = vec![
+			open_ack(0),
+			// a channel of another connection
+			open_ack(1),
+			IbcEvent::SendPacket(SendPacket {
+				height: Height::new(1, 10),
+				packet: Default::default(),
+			}),
+			close_init(Some(ChannelId::new(2))),
+		];
+		assert_eq!(
+			channel_updates(&events, &ConnectionId::new(0)),
+			vec![
+				ChannelUpdate {
+					opened: true,
+					channel: ends.0.clone(),
+					counterparty: ends.1.clone()
 				},
-			},
-			None => Poll::Ready(None),
+				ChannelUpdate { opened: false, channel: ends.0, counterparty: ends.1 },
+			]
+		);
+	}
+
+	#[tokio::test]
+	async fn recent_streams_yield_the_most_recent_value() {
+		let (sender, receiver) = futures::channel::mpsc::unbounded();
+		let mut stream = RecentStream::new(receiver);
+		for i in 0..3 {
+			sender.unbounded_send(i).unwrap();
 		}
+		tokio::time::sleep(Duration::from_millis(50)).await;
+		assert_eq!(stream.next().await, Some(2));
+
+		// a paused stream doesn't yield anything until it resumes
+		let next = tokio::time::timeout(Duration::from_millis(50), stream.next()).await;
+		assert!(next.is_err());
+		sender.unbounded_send(3).unwrap();
+		assert_eq!(stream.next().await, Some(3));
+
+		drop(sender);
+		assert_eq!(stream.next().await, None);
+	}
+
+	#[tokio::test]
+	async fn held_values_are_not_replaced() {
+		let (sender, receiver) = futures::channel::mpsc::unbounded();
+		let mut stream = RecentStream::new(receiver);
+		// the stream stalls, then resumes with a backlog of values
+		stream.hold_next();
+		for i in 0..3 {
+			sender.unbounded_send(i).unwrap();
+		}
+		tokio::time::sleep(Duration::from_millis(50)).await;
+		assert_eq!(stream.next().await, Some(0));
+		assert_eq!(stream.next().await, Some(2));
+
+		// a pending value is held as well
+		sender.unbounded_send(3).unwrap();
+		tokio::time::sleep(Duration::from_millis(50)).await;
+		stream.hold_next();
+		sender.unbounded_send(4).unwrap();
+		sender.unbounded_send(5).unwrap();
+		tokio::time::sleep(Duration::from_millis(50)).await;
+		assert_eq!(stream.next().await, Some(3));
+		assert_eq!(stream.next().await, Some(5));
+	}
+
+	#[test]
+	fn channels_without_counterparty_are_ignored() {
+		let events = vec![close_init(None)];
+		assert!(channel_updates(&events, &ConnectionId::new(0)).is_empty());
 	}
 }
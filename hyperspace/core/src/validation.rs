@@ -0,0 +1,662 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Checks of the relayer configuration run before the relay loop starts. Mistakes like a
+//! misspelled `store_prefix` would otherwise only surface as proof verification failures, once
+//! packets are relayed.
+
+use crate::{
+	chain::{AnyChain, AnyConfig, Config, CoreConfig},
+	query_server::check_admin_endpoint,
+};
+use ibc::core::ics24_host::identifier::{ClientId, ConnectionId, PortId};
+use ibc_proto::ibc::core::connection::v1::ConnectionEnd;
+use primitives::IbcProvider;
+use std::{collections::HashSet, net::SocketAddr, str::FromStr};
+use thiserror::Error;
+
+const TENDERMINT_CLIENT_TYPE: &str = "07-tendermint";
+const WASM_CLIENT_TYPE: &str = "08-wasm";
+const GRANDPA_CLIENT_TYPE: &str = "10-grandpa";
+const BEEFY_CLIENT_TYPE: &str = "11-beefy";
+/// Name the errors of the core config are reported for, in place of a chain name.
+const CORE_CONFIG: &str = "core";
+
+/// A mistake in the configuration of a chain.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{chain}: `{field}` {message}")]
+pub struct ConfigError {
+	/// Name of the misconfigured chain
+	pub chain: String,
+	/// Name of the offending config field
+	pub field: &'static str,
+	pub message: String,
+}
+
+impl ConfigError {
+	pub(crate) fn new(config: &AnyConfig, field: &'static str, message: impl Into<String>) -> Self {
+		Self { chain: config.name().to_string(), field, message: message.into() }
+	}
+}
+
+/// Fails with a single error listing all the `errors`, if any.
+pub fn ensure_valid(errors: Vec<ConfigError>) -> Result<(), anyhow::Error> {
+	if errors.is_empty() {
+		return Ok(())
+	}
+	let errors = errors.iter().map(|e| format!("\n  - {e}")).collect::<String>();
+	Err(anyhow::anyhow!("Invalid configuration:{errors}"))
+}
+
+impl Config {
+	/// Checks the configuration of both chains, without querying them.
+	pub fn validate(&self) -> Vec<ConfigError> {
+		let mut errors = validate_chain(&self.chain_a, &self.chain_b);
+		errors.extend(validate_chain(&self.chain_b, &self.chain_a));
+		errors.extend(validate_core(&self.core));
+		errors
+	}
+
+	/// Checks the ids referenced by the configuration of both chains against their state.
+	pub async fn validate_on_chain(
+		&self,
+		chain_a: &AnyChain,
+		chain_b: &AnyChain,
+	) -> Result<Vec<ConfigError>, anyhow::Error> {
+		let mut errors =
+			validate_chain_state(&self.chain_a, chain_a, &self.chain_b, chain_b).await?;
+		errors.extend(validate_chain_state(&self.chain_b, chain_b, &self.chain_a, chain_a).await?);
+		Ok(errors)
+	}
+}
+
+/// Type of the client tracking the chain, before it's wrapped in an 08-wasm client.
+fn client_type(config: &AnyConfig) -> &'static str {
+	match config {
+		AnyConfig::Parachain(config) |
+		AnyConfig::Composable(config) |
+		AnyConfig::PicassoRococo(config) |
+		AnyConfig::PicassoKusama(config) => match config.finality_protocol {
+			parachain::finality_protocol::FinalityProtocol::Grandpa => GRANDPA_CLIENT_TYPE,
+			parachain::finality_protocol::FinalityProtocol::Beefy => BEEFY_CLIENT_TYPE,
+		},
+		#[cfg(feature = "cosmos")]
+		AnyConfig::Cosmos(_) => TENDERMINT_CLIENT_TYPE,
+	}
+}
+
+/// Returns the name of the field configuring the commitment prefix of the chain, and its value.
+fn commitment_prefix(config: &AnyConfig) -> (&'static str, Vec<u8>) {
+	match config {
+		AnyConfig::Parachain(config) |
+		AnyConfig::Composable(config) |
+		AnyConfig::PicassoRococo(config) |
+		AnyConfig::PicassoKusama(config) => ("commitment_prefix", config.commitment_prefix.to_vec()),
+		#[cfg(feature = "cosmos")]
+		AnyConfig::Cosmos(config) => ("store_prefix", config.store_prefix.as_bytes().to_vec()),
+	}
+}
+
+/// Whether the chain hosts clients of the given type without wrapping them in 08-wasm clients.
+fn hosts_natively(config: &AnyConfig, client_type: &str) -> bool {
+	match config {
+		AnyConfig::Parachain(_) |
+		AnyConfig::Composable(_) |
+		AnyConfig::PicassoRococo(_) |
+		AnyConfig::PicassoKusama(_) => true,
+		#[cfg(feature = "cosmos")]
+		AnyConfig::Cosmos(_) => client_type == TENDERMINT_CLIENT_TYPE,
+	}
+}
+
+fn validate_core(core: &CoreConfig) -> Vec<ConfigError> {
+	let error =
+		|field, message: String| ConfigError { chain: CORE_CONFIG.to_string(), field, message };
+	let Some(endpoint) = &core.grpc_endpoint else { return vec![] };
+	// the endpoint would otherwise never be served
+	if !cfg!(feature = "grpc-server") {
+		return vec![error(
+			"grpc_endpoint",
+			"is set, but hyperspace was built without the `grpc-server` feature".to_string(),
+		)]
+	}
+	let addr = match endpoint.parse::<SocketAddr>() {
+		Ok(addr) => addr,
+		Err(e) =>
+			return vec![error("grpc_endpoint", format!("{endpoint:?} isn't an address: {e}"))],
+	};
+	if core.grpc_admin {
+		if let Err(e) = check_admin_endpoint(addr) {
+			return vec![error("grpc_admin", e.to_string())]
+		}
+	}
+	vec![]
+}
+
+fn validate_chain(config: &AnyConfig, counterparty: &AnyConfig) -> Vec<ConfigError> {
+	let mut errors = vec![];
+
+	let (prefix_field, prefix) = commitment_prefix(config);
+	if prefix.is_empty() {
+		errors.push(ConfigError::new(config, prefix_field, "is empty"));
+	}
+
+	// the 08-wasm clients of the counterparty hosted on this chain use this code
+	let wasm_code_id = config.raw_wasm_code_id();
+	if let Err(e) = config.wasm_code_id() {
+		errors.push(e);
+	}
+	let counterparty_client_type = client_type(counterparty);
+	if wasm_code_id.is_none() && !hosts_natively(config, counterparty_client_type) {
+		errors.push(ConfigError::new(
+			config,
+			"wasm_code_id",
+			format!(
+				"must be set, as {} hosts the {counterparty_client_type} client of {} through 08-wasm",
+				config.name(),
+				counterparty.name()
+			),
+		));
+	}
+
+	if let Some(client_id) = config.client_id() {
+		let expected_type = if counterparty.raw_wasm_code_id().is_some() {
+			WASM_CLIENT_TYPE
+		} else {
+			client_type(config)
+		};
+		if let Err(e) = ClientId::from_str(client_id.as_str()) {
+			errors.push(ConfigError::new(
+				config,
+				"client_id",
+				format!("{client_id} is invalid: {e}"),
+			));
+		} else if !client_id.as_str().starts_with(expected_type) {
+			errors.push(ConfigError::new(
+				config,
+				"client_id",
+				format!(
+					"{client_id} isn't a {expected_type} client, as expected on {}",
+					counterparty.name()
+				),
+			));
+		}
+	}
+
+	if let Some(connection_id) = config.connection_id() {
+		if let Err(e) = ConnectionId::from_str(connection_id.as_str()) {
+			errors.push(ConfigError::new(
+				config,
+				"connection_id",
+				format!("{connection_id} is invalid: {e}"),
+			));
+		}
+	}
+
+	let mut connection_ids = HashSet::new();
+	for connection in config.connections() {
+		let connection_id = &connection.connection_id;
+		if config.connection_id().is_none() {
+			errors.push(ConfigError::new(
+				config,
+				"connections",
+				format!("lists {connection_id}, but there is no primary connection_id"),
+			));
+		}
+		if Some(connection_id) == config.connection_id() || !connection_ids.insert(connection_id) {
+			errors.push(ConfigError::new(
+				config,
+				"connections",
+				format!("lists {connection_id} more than once"),
+			));
+		}
+		if connection.commitment_prefix.as_deref() == Some("") {
+			errors.push(ConfigError::new(
+				config,
+				"connections",
+				format!("gives {connection_id} an empty commitment_prefix"),
+			));
+		}
+	}
+
+	let mut whitelist = HashSet::new();
+	let connection_whitelists =
+		config.connections().iter().flat_map(|connection| &connection.channel_whitelist);
+	for (channel_id, port_id) in config.channel_whitelist().iter().chain(connection_whitelists) {
+		if let Err(e) = PortId::from_str(port_id.as_str()) {
+			errors.push(ConfigError::new(
+				config,
+				"channel_whitelist",
+				format!("{port_id} isn't a valid port id: {e}"),
+			));
+		}
+		if !whitelist.insert((channel_id, port_id)) {
+			errors.push(ConfigError::new(
+				config,
+				"channel_whitelist",
+				format!("lists {channel_id}/{port_id} more than once"),
+			));
+		}
+	}
+
+	errors
+}
+
+async fn validate_chain_state(
+	config: &AnyConfig,
+	chain: &AnyChain,
+	counterparty_config: &AnyConfig,
+	counterparty: &AnyChain,
+) -> Result<Vec<ConfigError>, anyhow::Error> {
+	let mut errors = vec![];
+	let (height, _) = chain.latest_height_and_timestamp().await?;
+	let (counterparty_height, _) = counterparty.latest_height_and_timestamp().await?;
+
+	if let Some(client_id) = config.client_id() {
+		if let Err(e) =
+			counterparty.query_client_state(counterparty_height, client_id.clone()).await
+		{
+			errors.push(ConfigError::new(
+				config,
+				"client_id",
+				format!("{client_id} wasn't found on {}: {e}", counterparty.name()),
+			));
+		}
+	}
+
+	if let Ok(Some(code_id)) = config.wasm_code_id() {
+		if !chain.query_wasm_code_stored(code_id.as_bytes()).await? {
+			errors.push(ConfigError::new(
+				config,
+				"wasm_code_id",
+				format!("isn't stored on {}", chain.name()),
+			));
+		}
+	}
+
+	if let Some(connection_id) = config.connection_id() {
+		let response = chain.query_connection_end(height, connection_id.clone()).await;
+		match response.map(|response| response.connection) {
+			Ok(Some(connection)) => errors.extend(validate_connection_end(
+				config,
+				counterparty_config,
+				&connection,
+				counterparty.connection_prefix().as_bytes(),
+			)),
+			Ok(None) => errors.push(ConfigError::new(
+				config,
+				"connection_id",
+				format!("{connection_id} wasn't found on {}", chain.name()),
+			)),
+			Err(e) => errors.push(ConfigError::new(
+				config,
+				"connection_id",
+				format!("{connection_id} wasn't found on {}: {e}", chain.name()),
+			)),
+		}
+	}
+
+	for connection in config.connections() {
+		let connection_id = &connection.connection_id;
+		let response = chain.query_connection_end(height, connection_id.clone()).await;
+		if !matches!(response.map(|response| response.connection), Ok(Some(_))) {
+			errors.push(ConfigError::new(
+				config,
+				"connections",
+				format!("{connection_id} wasn't found on {}", chain.name()),
+			));
+		}
+	}
+
+	Ok(errors)
+}
+
+/// Checks the `connection` configured on the chain against the configured ids and the
+/// `counterparty_prefix` returned by [`IbcProvider::connection_prefix`] of the counterparty.
+fn validate_connection_end(
+	config: &AnyConfig,
+	counterparty_config: &AnyConfig,
+	connection: &ConnectionEnd,
+	counterparty_prefix: &[u8],
+) -> Vec<ConfigError> {
+	let mut errors = vec![];
+	let connection_id = config.connection_id().map(ToString::to_string).unwrap_or_default();
+	let counterparty = connection.counterparty.clone().unwrap_or_default();
+
+	let stored_prefix = counterparty.prefix.map(|prefix| prefix.key_prefix).unwrap_or_default();
+	if stored_prefix != counterparty_prefix {
+		let (prefix_field, _) = commitment_prefix(counterparty_config);
+		errors.push(ConfigError::new(
+			counterparty_config,
+			prefix_field,
+			format!(
+				"{:?} doesn't match the prefix {:?} of {connection_id} on {}",
+				String::from_utf8_lossy(counterparty_prefix),
+				String::from_utf8_lossy(&stored_prefix),
+				config.name()
+			),
+		));
+	}
+
+	// the connection end is on the client of the counterparty hosted by this chain
+	let expected_ids = [
+		(counterparty_config.client_id().map(ToString::to_string), &connection.client_id),
+		(config.client_id().map(ToString::to_string), &counterparty.client_id),
+		(counterparty_config.connection_id().map(ToString::to_string), &counterparty.connection_id),
+	];
+	for (expected, found) in expected_ids {
+		match expected {
+			Some(expected) if &expected != found => errors.push(ConfigError::new(
+				config,
+				"connection_id",
+				format!("{connection_id} uses {found} instead of the configured {expected}"),
+			)),
+			_ => {},
+		}
+	}
+
+	errors
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ibc::core::ics24_host::identifier::ChannelId;
+	use ibc_proto::ibc::core::{commitment::v1::MerklePrefix, connection::v1::Counterparty};
+
+	const CODE_ID: &str = "cfd2199578332b5fd859f3b76cb0b29757c6b52c5df79566cdc3598039dbe43e";
+
+	/// Parses the `base` config, with its fields overridden by the `extra` ones.
+	fn parse(base: &str, extra: &str) -> AnyConfig {
+		let mut config = toml::from_str::<toml::Table>(base).unwrap();
+		config.extend(toml::from_str::<toml::Table>(extra).unwrap());
+		toml::Value::Table(config).try_into().unwrap()
+	}
+
+	fn parachain(extra: &str) -> AnyConfig {
+		let base = r#"
+			type = "parachain"
+			name = "parachain"
+			para_id = 2000
+			parachain_rpc_url = "ws://127.0.0.1:9188"
+			relay_chain_rpc_url = "ws://127.0.0.1:9944"
+			commitment_prefix = "0x6962632f"
+			private_key = "//Alice"
+			ss58_version = 49
+			channel_whitelist = []
+			finality_protocol = "Grandpa"
+			key_type = "sr25519"
+		"#;
+		parse(base, extra)
+	}
+
+	fn other_parachain(extra: &str) -> AnyConfig {
+		parachain(&format!("name = \"other\"\n{extra}"))
+	}
+
+	#[cfg(feature = "cosmos")]
+	fn cosmos(extra: &str) -> AnyConfig {
+		let base = r#"
+			type = "cosmos"
+			name = "cosmos"
+			rpc_url = "http://127.0.0.1:26657/"
+			grpc_url = "http://127.0.0.1:9090/"
+			websocket_url = "ws://127.0.0.1:26657/websocket"
+			chain_id = "ibcgo-1"
+			account_prefix = "cosmos"
+			store_prefix = "ibc"
+			max_tx_size = 200000
+			channel_whitelist = []
+			mnemonic = "oxygen fall sure lava energy veteran enroll frown question detail include maximum"
+		"#;
+		parse(base, extra)
+	}
+
+	fn config(chain_a: AnyConfig, chain_b: AnyConfig) -> Config {
+		let core = toml::from_str("").unwrap();
+		Config { chain_a, chain_b, core }
+	}
+
+	fn fields(errors: &[ConfigError]) -> Vec<(&str, &str)> {
+		errors.iter().map(|e| (e.chain.as_str(), e.field)).collect()
+	}
+
+	#[test]
+	#[cfg(feature = "grpc-server")]
+	fn grpc_endpoints_must_be_addresses() {
+		let mut valid = config(parachain(""), other_parachain(""));
+		valid.core.grpc_endpoint = Some("127.0.0.1:9100".to_string());
+		valid.core.grpc_admin = true;
+		assert_eq!(valid.validate(), vec![]);
+
+		let mut invalid = config(parachain(""), other_parachain(""));
+		invalid.core.grpc_endpoint = Some("localhost".to_string());
+		assert_eq!(fields(&invalid.validate()), vec![("core", "grpc_endpoint")]);
+
+		// the admin service is only served on loopback addresses
+		let mut public = config(parachain(""), other_parachain(""));
+		public.core.grpc_endpoint = Some("0.0.0.0:9100".to_string());
+		assert_eq!(public.validate(), vec![]);
+		public.core.grpc_admin = true;
+		assert_eq!(fields(&public.validate()), vec![("core", "grpc_admin")]);
+	}
+
+	#[test]
+	#[cfg(not(feature = "grpc-server"))]
+	fn grpc_endpoints_need_the_grpc_server() {
+		let mut config = config(parachain(""), other_parachain(""));
+		assert_eq!(config.validate(), vec![]);
+		config.core.grpc_endpoint = Some("127.0.0.1:9100".to_string());
+		assert_eq!(fields(&config.validate()), vec![("core", "grpc_endpoint")]);
+	}
+
+	#[test]
+	fn commitment_prefixes_must_not_be_empty() {
+		assert_eq!(config(parachain(""), other_parachain("")).validate(), vec![]);
+
+		let empty = parachain(r#"commitment_prefix = "0x""#);
+		let errors = config(empty, other_parachain("")).validate();
+		assert_eq!(fields(&errors), vec![("parachain", "commitment_prefix")]);
+	}
+
+	#[test]
+	#[cfg(feature = "cosmos")]
+	fn wasm_code_ids_must_be_checksums() {
+		let valid = config(cosmos(&format!(r#"wasm_code_id = "{CODE_ID}""#)), parachain(""));
+		assert_eq!(valid.validate(), vec![]);
+
+		let not_hex = config(cosmos(r#"wasm_code_id = "not hex""#), parachain(""));
+		assert_eq!(fields(&not_hex.validate()), vec![("cosmos", "wasm_code_id")]);
+
+		let too_short = config(cosmos(r#"wasm_code_id = "cfd21995""#), parachain(""));
+		let errors = too_short.validate();
+		assert_eq!(fields(&errors), vec![("cosmos", "wasm_code_id")]);
+		assert!(errors[0].message.contains("4 bytes long"), "{}", errors[0]);
+	}
+
+	#[test]
+	#[cfg(feature = "cosmos")]
+	fn client_types_must_be_supported_by_the_host() {
+		// cosmos chains only host grandpa clients through 08-wasm
+		let errors = config(cosmos(""), parachain("")).validate();
+		assert_eq!(fields(&errors), vec![("cosmos", "wasm_code_id")]);
+
+		// parachains host tendermint clients natively
+		let valid = config(parachain(""), cosmos(&format!(r#"wasm_code_id = "{CODE_ID}""#)));
+		assert_eq!(valid.validate(), vec![]);
+	}
+
+	#[test]
+	#[cfg(feature = "cosmos")]
+	fn client_ids_must_match_the_client_type() {
+		let wasm_code_id = format!(r#"wasm_code_id = "{CODE_ID}""#);
+		let valid = config(
+			parachain(r#"client_id = "08-wasm-0""#),
+			cosmos(&format!("{wasm_code_id}\nclient_id = \"07-tendermint-0\"")),
+		);
+		assert_eq!(valid.validate(), vec![]);
+
+		// the grandpa client is wrapped in an 08-wasm client on the cosmos chain
+		let unwrapped = config(parachain(r#"client_id = "10-grandpa-0""#), cosmos(&wasm_code_id));
+		assert_eq!(fields(&unwrapped.validate()), vec![("parachain", "client_id")]);
+
+		let typo = config(
+			parachain(""),
+			cosmos(&format!("{wasm_code_id}\nclient_id = \"07-tendermnt-0\"")),
+		);
+		assert_eq!(fields(&typo.validate()), vec![("cosmos", "client_id")]);
+	}
+
+	#[test]
+	fn ids_must_be_valid_identifiers() {
+		let valid = config(
+			parachain(r#"client_id = "10-grandpa-0""#),
+			other_parachain(r#"connection_id = "connection-0""#),
+		);
+		assert_eq!(valid.validate(), vec![]);
+
+		let invalid = config(
+			parachain(r#"client_id = "grandpa""#),
+			other_parachain(r#"connection_id = "connection/0""#),
+		);
+		assert_eq!(
+			fields(&invalid.validate()),
+			vec![("parachain", "client_id"), ("other", "connection_id")]
+		);
+	}
+
+	#[test]
+	fn channel_whitelists_must_list_valid_channels_once() {
+		let valid = parachain(
+			r#"channel_whitelist = [["channel-0", "transfer"], ["channel-1", "transfer"]]"#,
+		);
+		assert_eq!(config(valid, other_parachain("")).validate(), vec![]);
+
+		let duplicate = parachain(
+			r#"channel_whitelist = [["channel-0", "transfer"], ["channel-0", "transfer"]]"#,
+		);
+		let errors = config(duplicate, other_parachain("")).validate();
+		assert_eq!(fields(&errors), vec![("parachain", "channel_whitelist")]);
+
+		let invalid_port = parachain(r#"channel_whitelist = [["channel-0", "trans/fer"]]"#);
+		let errors = config(invalid_port, other_parachain("")).validate();
+		assert_eq!(fields(&errors), vec![("parachain", "channel_whitelist")]);
+	}
+
+	#[test]
+	fn additional_connections_must_be_listed_once() {
+		let connections = r#"
+			connection_id = "connection-0"
+			channel_whitelist = [["channel-0", "transfer"]]
+
+			[[connections]]
+			connection_id = "connection-1"
+			channel_whitelist = [["channel-1", "transfer"]]
+		"#;
+		let valid = parachain(connections);
+		assert_eq!(valid.connection_ids(), vec![ConnectionId::new(0), ConnectionId::new(1)]);
+		assert_eq!(config(valid, other_parachain("")).validate(), vec![]);
+
+		let duplicate =
+			parachain(&format!("{connections}\n[[connections]]\nconnection_id = \"connection-0\""));
+		let errors = config(duplicate, other_parachain("")).validate();
+		assert_eq!(fields(&errors), vec![("parachain", "connections")]);
+
+		let without_primary = parachain("[[connections]]\nconnection_id = \"connection-1\"");
+		let errors = config(without_primary, other_parachain("")).validate();
+		assert_eq!(fields(&errors), vec![("parachain", "connections")]);
+
+		let own_prefix = parachain(&format!("{connections}\ncommitment_prefix = \"ibc-v2\""));
+		assert_eq!(config(own_prefix, other_parachain("")).validate(), vec![]);
+		let empty_prefix = parachain(&format!("{connections}\ncommitment_prefix = \"\""));
+		let errors = config(empty_prefix, other_parachain("")).validate();
+		assert_eq!(fields(&errors), vec![("parachain", "connections")]);
+
+		// a channel is only whitelisted on one of the connections
+		let whitelisted_twice = parachain(&connections.replace("channel-1", "channel-0"));
+		let errors = config(whitelisted_twice, other_parachain("")).validate();
+		assert_eq!(fields(&errors), vec![("parachain", "channel_whitelist")]);
+	}
+
+	#[test]
+	fn created_connections_are_appended() {
+		let mut config = parachain("");
+		config.add_connection_id(ConnectionId::new(0));
+		config.add_connection_id(ConnectionId::new(1));
+		config.add_connection_id(ConnectionId::new(1));
+		assert_eq!(config.connection_id(), Some(&ConnectionId::new(0)));
+		assert_eq!(config.connection_ids(), vec![ConnectionId::new(0), ConnectionId::new(1)]);
+
+		config.whitelist_connection_channel(
+			&ConnectionId::new(1),
+			ChannelId::new(3),
+			PortId::transfer(),
+		);
+		config.whitelist_connection_channel(
+			&ConnectionId::new(0),
+			ChannelId::new(2),
+			PortId::transfer(),
+		);
+		assert_eq!(config.channel_whitelist(), &[(ChannelId::new(2), PortId::transfer())]);
+		assert_eq!(
+			config.connections()[0].channel_whitelist,
+			vec![(ChannelId::new(3), PortId::transfer())]
+		);
+	}
+
+	fn connection_end(
+		client_id: &str,
+		counterparty_client_id: &str,
+		prefix: &[u8],
+	) -> ConnectionEnd {
+		ConnectionEnd {
+			client_id: client_id.to_string(),
+			counterparty: Some(Counterparty {
+				client_id: counterparty_client_id.to_string(),
+				connection_id: "connection-1".to_string(),
+				prefix: Some(MerklePrefix { key_prefix: prefix.to_vec() }),
+			}),
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn connections_must_match_the_configured_ids_and_prefixes() {
+		let chain = parachain("client_id = \"10-grandpa-0\"\nconnection_id = \"connection-0\"");
+		let counterparty =
+			other_parachain("client_id = \"10-grandpa-1\"\nconnection_id = \"connection-1\"");
+
+		let valid = connection_end("10-grandpa-1", "10-grandpa-0", b"ibc/");
+		assert_eq!(validate_connection_end(&chain, &counterparty, &valid, b"ibc/"), vec![]);
+
+		// the counterparty's prefix doesn't match the one the connection was opened with
+		let errors = validate_connection_end(&chain, &counterparty, &valid, b"ibc");
+		assert_eq!(fields(&errors), vec![("other", "commitment_prefix")]);
+
+		let other_client = connection_end("10-grandpa-2", "10-grandpa-0", b"ibc/");
+		let errors = validate_connection_end(&chain, &counterparty, &other_client, b"ibc/");
+		assert_eq!(fields(&errors), vec![("parachain", "connection_id")]);
+		assert!(errors[0].message.contains("10-grandpa-2"), "{}", errors[0]);
+	}
+
+	#[test]
+	fn errors_name_the_chain_and_field() {
+		let error = ConfigError::new(&parachain(""), "client_id", "is invalid");
+		assert_eq!(error.to_string(), "parachain: `client_id` is invalid");
+
+		assert!(ensure_valid(vec![]).is_ok());
+		let message = ensure_valid(vec![error]).unwrap_err().to_string();
+		assert!(message.contains("parachain: `client_id` is invalid"), "{message}");
+	}
+}
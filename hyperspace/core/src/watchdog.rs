@@ -0,0 +1,215 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Detection of the chains that stopped finalizing or producing blocks, which the relay loop
+//! would otherwise wait on silently.
+
+use std::{
+	fmt,
+	process::{Command, ExitStatus},
+	time::{Duration, Instant},
+};
+
+/// What a stalled chain stopped doing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stall {
+	/// No finality notification was received
+	Finality,
+	/// The latest height of the chain didn't change
+	Height,
+}
+
+impl fmt::Display for Stall {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Stall::Finality => write!(f, "no finality notification was received"),
+			Stall::Height => write!(f, "the latest height didn't change"),
+		}
+	}
+}
+
+/// Change of the state of a chain noticed by [`StallWatchdog::check`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StallTransition {
+	Stalled(Stall),
+	Resumed,
+}
+
+/// Tracks the time since the last finality notification and the last change of the latest
+/// height of a chain.
+#[derive(Debug, Clone)]
+pub struct StallWatchdog {
+	/// Time after which the chain is stalled, `None` if the check is disabled
+	max_silence: Option<Duration>,
+	last_finality: Instant,
+	latest_height: Option<u64>,
+	last_height_change: Instant,
+	stall: Option<Stall>,
+}
+
+impl StallWatchdog {
+	/// Watchdog of a chain that is stalled after `stall_factor` times its `expected_block_time`,
+	/// disabled if `stall_factor` is 0.
+	pub fn new(expected_block_time: Duration, stall_factor: u32, now: Instant) -> Self {
+		Self {
+			max_silence: (stall_factor > 0).then(|| expected_block_time * stall_factor),
+			last_finality: now,
+			latest_height: None,
+			last_height_change: now,
+			stall: None,
+		}
+	}
+
+	pub fn on_finality(&mut self, now: Instant) {
+		self.last_finality = now;
+	}
+
+	pub fn on_latest_height(&mut self, height: u64, now: Instant) {
+		if self.latest_height != Some(height) {
+			self.latest_height = Some(height);
+			self.last_height_change = now;
+		}
+	}
+
+	/// Returns what the chain stopped doing, if it's stalled.
+	pub fn stall(&self) -> Option<Stall> {
+		self.stall
+	}
+
+	/// Checks whether the chain is stalled at `now`, returning the change since the last check.
+	pub fn check(&mut self, now: Instant) -> Option<StallTransition> {
+		let max_silence = self.max_silence?;
+		let stall = if now.saturating_duration_since(self.last_finality) > max_silence {
+			Some(Stall::Finality)
+		} else if now.saturating_duration_since(self.last_height_change) > max_silence {
+			Some(Stall::Height)
+		} else {
+			None
+		};
+		let transition = match (self.stall, stall) {
+			(None, Some(stall)) => Some(StallTransition::Stalled(stall)),
+			(Some(_), None) => Some(StallTransition::Resumed),
+			_ => None,
+		};
+		self.stall = stall;
+		transition
+	}
+}
+
+/// Runs the `command` configured to alert the operators that `chain` stalled or resumed, with
+/// the name of the chain in `HYPERSPACE_CHAIN`, `stalled` or `resumed` in
+/// `HYPERSPACE_CHAIN_STATUS` and the reason of the stall in `HYPERSPACE_STALL_REASON`.
+pub fn run_alert_command(
+	command: &str,
+	chain: &str,
+	transition: StallTransition,
+) -> std::io::Result<ExitStatus> {
+	let (status, reason) = match transition {
+		StallTransition::Stalled(stall) => ("stalled", stall.to_string()),
+		StallTransition::Resumed => ("resumed", String::new()),
+	};
+	Command::new("sh")
+		.arg("-c")
+		.arg(command)
+		.env("HYPERSPACE_CHAIN", chain)
+		.env("HYPERSPACE_CHAIN_STATUS", status)
+		.env("HYPERSPACE_STALL_REASON", reason)
+		.status()
+}
+
+/// Runs [`run_alert_command`] in the background, logging its failures.
+pub fn spawn_alert_command(command: String, chain: String, transition: StallTransition) {
+	tokio::task::spawn_blocking(move || match run_alert_command(&command, &chain, transition) {
+		Ok(status) if status.success() => {},
+		Ok(status) => log::error!(
+			target: "hyperspace",
+			"Stall alert command of {chain} exited with {status}"
+		),
+		Err(e) =>
+			log::error!(target: "hyperspace", "Failed to run the stall alert command of {chain}: {e}"),
+	});
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const BLOCK_TIME: Duration = Duration::from_secs(6);
+
+	#[test]
+	fn chains_stall_without_finality_notifications() {
+		let start = Instant::now();
+		let mut watchdog = StallWatchdog::new(BLOCK_TIME, 10, start);
+		let at = |secs: u64| start + Duration::from_secs(secs);
+		watchdog.on_latest_height(10, at(0));
+		assert_eq!(watchdog.check(at(60)), None);
+
+		// the height keeps increasing but nothing is finalized
+		watchdog.on_latest_height(20, at(60));
+		assert_eq!(watchdog.check(at(61)), Some(StallTransition::Stalled(Stall::Finality)));
+		assert_eq!(watchdog.check(at(62)), None);
+		assert_eq!(watchdog.stall(), Some(Stall::Finality));
+
+		watchdog.on_finality(at(70));
+		assert_eq!(watchdog.check(at(70)), Some(StallTransition::Resumed));
+		assert_eq!(watchdog.stall(), None);
+	}
+
+	#[test]
+	fn chains_stall_once_their_height_stops_changing() {
+		let start = Instant::now();
+		let mut watchdog = StallWatchdog::new(BLOCK_TIME, 10, start);
+		let at = |secs: u64| start + Duration::from_secs(secs);
+		watchdog.on_latest_height(10, at(0));
+		watchdog.on_finality(at(50));
+		watchdog.on_latest_height(10, at(50));
+		assert_eq!(watchdog.check(at(61)), Some(StallTransition::Stalled(Stall::Height)));
+
+		// the finality notifications stop as well
+		assert_eq!(watchdog.check(at(120)), None);
+		assert_eq!(watchdog.stall(), Some(Stall::Finality));
+
+		watchdog.on_finality(at(130));
+		watchdog.on_latest_height(11, at(130));
+		assert_eq!(watchdog.check(at(130)), Some(StallTransition::Resumed));
+	}
+
+	#[test]
+	fn stall_checks_can_be_disabled() {
+		let start = Instant::now();
+		let mut watchdog = StallWatchdog::new(BLOCK_TIME, 0, start);
+		assert_eq!(watchdog.check(start + Duration::from_secs(3600)), None);
+	}
+
+	#[test]
+	fn alert_commands_are_run_with_the_stalled_chain() {
+		let path = std::env::temp_dir().join(format!("hyperspace-stall-{}", std::process::id()));
+		let command = format!(
+			"echo \"$HYPERSPACE_CHAIN $HYPERSPACE_CHAIN_STATUS $HYPERSPACE_STALL_REASON\" > {}",
+			path.display()
+		);
+		let status =
+			run_alert_command(&command, "cosmos", StallTransition::Stalled(Stall::Finality))
+				.unwrap();
+		assert!(status.success());
+		assert_eq!(
+			std::fs::read_to_string(&path).unwrap().trim(),
+			"cosmos stalled no finality notification was received"
+		);
+
+		run_alert_command(&command, "cosmos", StallTransition::Resumed).unwrap();
+		assert_eq!(std::fs::read_to_string(&path).unwrap().trim(), "cosmos resumed");
+		let _ = std::fs::remove_file(path);
+	}
+}
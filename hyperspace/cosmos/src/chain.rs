@@ -1,9 +1,21 @@
-use super::{client::CosmosClient, tx::sign_tx};
-use crate::{error::Error, events::client_extract_attributes_from_tx, provider::FinalityEvent};
+use super::{
+	client::CosmosClient,
+	tx::{failed_message_index, sign_tx},
+};
+use crate::{
+	error::{is_disconnected, Error},
+	light_client::verify_header,
+	provider::FinalityEvent,
+	query::CosmosQueryBackend,
+};
+use anyhow::anyhow;
 use futures::{Stream, StreamExt};
 use ibc::{
 	core::{
-		ics02_client::{events::UpdateClient, msgs::ClientMsg},
+		ics02_client::{
+			events::UpdateClient,
+			msgs::{update_client::TYPE_URL as UPDATE_CLIENT_TYPE_URL, ClientMsg},
+		},
 		ics24_host::identifier::ChainId,
 		ics26_routing::msgs::Ics26Envelope,
 	},
@@ -13,21 +25,23 @@ use ibc::{
 use ibc_proto::{
 	cosmos::{
 		base::v1beta1::Coin,
-		tx::v1beta1::{service_client::ServiceClient, Fee, GetTxsEventRequest, OrderBy},
+		tx::v1beta1::{Fee, Tx},
 	},
 	google::protobuf::Any,
 };
-use pallet_ibc::light_clients::AnyClientMessage;
+use ics07_tendermint::client_message::ClientMessage;
+use pallet_ibc::light_clients::{AnyClientMessage, AnyClientState, AnyConsensusState};
 use primitives::{
-	mock::LocalClientTypes, Chain, CommonClientState, IbcProvider, LightClientSync,
-	MisbehaviourHandler,
+	any_registry::known_messages, misbehaviour::DetectedMisbehaviour, mock::LocalClientTypes,
+	signer::set_message_signer, utils, Chain, CommonClientState, ErrorKind, HasErrorKind,
+	IbcProvider, LightClientSync, MisbehaviourHandler, SimulationResult,
 };
 use prost::Message;
 use std::{pin::Pin, time::Duration};
 use tendermint_rpc::{
 	event::{Event, EventData},
 	query::{EventType, Query},
-	SubscriptionClient, WebSocketClient,
+	Client, Order, SubscriptionClient, WebSocketClient,
 };
 
 #[async_trait::async_trait]
@@ -61,10 +75,11 @@ where
 	}
 
 	async fn estimate_weight(&self, messages: Vec<Any>) -> Result<u64, Self::Error> {
-		let account_info = self.query_account().await?;
+		let (signer, account) = self.signer.current();
+		let account_info = self.query_account(account.account).await?;
 		let fee = self.get_fee();
 		let (_, tx_raw, _) =
-			sign_tx(self.keybase.clone(), self.chain_id.clone(), &account_info, vec![], fee)?;
+			sign_tx(&*signer, self.chain_id.clone(), &account_info, vec![], fee).await?;
 
 		let body_bytes_len = tx_raw.body_bytes.len();
 		// Full length of the transaction can then be derived from the length of the invariable
@@ -90,14 +105,54 @@ where
 		Ok(current_len as u64)
 	}
 
+	async fn simulate(&self, messages: Vec<Any>) -> Result<SimulationResult, Self::Error> {
+		let (signer, account) = self.signer.current();
+		let messages = messages
+			.into_iter()
+			.map(|msg| set_message_signer(msg, &account.account))
+			.collect::<Vec<_>>();
+		let account_info = self.query_account(account.account).await?;
+		let message_count = messages.len();
+		let (tx, _, tx_bytes) =
+			sign_tx(&*signer, self.chain_id.clone(), &account_info, messages, self.get_fee())
+				.await?;
+
+		let error = match self.query_backend.simulate(&tx, &tx_bytes).await {
+			Ok(response) => {
+				let gas_used = response.gas_info.map(|info| info.gas_used).unwrap_or_default();
+				return Ok(SimulationResult {
+					estimated_weight: gas_used,
+					message_results: vec![true; message_count],
+				})
+			},
+			Err(e) => e,
+		};
+		if error.kind() == ErrorKind::Transport {
+			return Err(error)
+		}
+
+		// The SDK aborts the transaction at the first failing message, the messages after it
+		// are assumed to succeed until they are simulated without it.
+		let message_results = match failed_message_index(&error.to_string()) {
+			Some(index) if index < message_count =>
+				(0..message_count).map(|i| i != index).collect(),
+			_ => vec![false; message_count],
+		};
+		log::debug!(target: "hyperspace_cosmos", "Simulation failed: {error:?}");
+		Ok(SimulationResult { estimated_weight: 0, message_results })
+	}
+
 	async fn finality_notifications(
 		&self,
-	) -> Result<Pin<Box<dyn Stream<Item = <Self as IbcProvider>::FinalityEvent> + Send + Sync>>, Error> {
+	) -> Result<
+		Pin<Box<dyn Stream<Item = <Self as IbcProvider>::FinalityEvent> + Send + Sync>>,
+		Error,
+	> {
 		let ws_client = self.rpc_ws_client().clone();
 		let subscription = ws_client
 			.subscribe(Query::from(EventType::NewBlock))
 			.await
-			.map_err(|e| Error::from(format!("failed to subscribe to new blocks {e:?}")))?
+			.map_err(Error::from)?
 			.chunks(6);
 		log::info!(target: "hyperspace_cosmos", "🛰️ Subscribed to {} listening to finality notifications", self.name);
 		let stream = subscription.filter_map(|events| {
@@ -143,97 +198,114 @@ where
 			.and_eq("update_client.consensus_heights", update.consensus_height().to_string());
 		// omit this field since the first three should be enough to identify the update
 		// .and_eq("update_client.header", hex::encode(&update.header.unwrap_or_default()))
-		use tendermint::abci::Event as AbciEvent;
 
-		let mut client = ServiceClient::connect(self.grpc_url().to_string())
-			.await
-			.map_err(|e| Error::from(e.to_string()))?;
-		let mut resp = client
-			.get_txs_event(GetTxsEventRequest {
-				events: query_str
-					.conditions
-					.into_iter()
-					.map(|c| c.to_string().replace(" = ", "="))
-					.collect(),
-				order_by: OrderBy::Desc.into(),
-				page: 1,
-				limit: 1,
-				..Default::default()
-			})
+		// searched over RPC rather than the tx service, whose REST gateway encodes the messages
+		// of the transactions as JSON
+		let response = self
+			.rpc_http_client
+			.tx_search(query_str, false, 1, 1, Order::Descending)
 			.await
-			.map_err(|e| Error::from(e.to_string()))?
-			.into_inner();
-		let mut idx = None;
-		let tx_response = resp.tx_responses.pop().ok_or_else(|| {
+			.map_err(Error::from)?;
+		let tx = response.txs.into_iter().next().ok_or_else(|| {
 			Error::from(format!("Failed to find tx for update client: {update:?}"))
 		})?;
-		'l: for log in tx_response.logs {
-			for ev in log.events {
-				let event = AbciEvent {
-					kind: ev.r#type,
-					attributes: ev
-						.attributes
-						.into_iter()
-						.map(|a| tendermint::abci::EventAttribute {
-							key: a.key,
-							value: a.value,
-							index: false,
-						})
-						.collect(),
-				};
-				let attr = client_extract_attributes_from_tx(
-					&event,
-					Height::new(self.id().version(), tx_response.height as u64),
-				)
-				.map_err(|e| Error::from(format!("Failed to extract attributes from tx: {e}")))?;
-				if attr.client_id == *update.client_id() &&
-					attr.client_type == update.client_type() &&
-					attr.consensus_height == update.consensus_height()
-				{
-					idx = Some(log.msg_index);
-					break 'l
-				}
-			}
-		}
-		let idx =
-			idx.ok_or(Error::from("Failed to find matching update client event".to_string()))?;
-		let x = resp
-			.txs
-			.pop()
-			.ok_or_else(|| {
-				Error::from(format!("Failed to find tx for update client in `txs`: {update:?}"))
-			})?
+		let body = Tx::decode(tx.tx.as_slice())
+			.map_err(|e| Error::from(format!("Failed to decode tx {}: {e}", tx.hash)))?
 			.body
 			.ok_or_else(|| {
 				Error::from(format!("Failed to find tx for update client in `body`: {update:?}"))
-			})?
+			})?;
+		let mut client_messages = body
 			.messages
-			.remove(idx as usize);
-		let envelope = Ics26Envelope::<LocalClientTypes>::try_from(x);
-		if let Ok(Ics26Envelope::Ics2Msg(ClientMsg::UpdateClient(update_msg))) = envelope {
-			return Ok(update_msg.client_message)
+			.into_iter()
+			.filter(|msg| {
+				msg.type_url == UPDATE_CLIENT_TYPE_URL &&
+					known_messages()
+						.check_known(msg, "the client updates checked for misbehaviour")
+			})
+			.filter_map(|msg| match Ics26Envelope::<LocalClientTypes>::try_from(msg) {
+				Ok(Ics26Envelope::Ics2Msg(ClientMsg::UpdateClient(update_msg)))
+					if update_msg.client_id == *update.client_id() =>
+					Some(update_msg.client_message),
+				_ => None,
+			})
+			.collect::<Vec<_>>();
+		// a transaction may update the client to several heights
+		if client_messages.len() > 1 {
+			client_messages
+				.retain(|msg| msg.maybe_header_height() == Some(update.consensus_height()));
 		}
-
-		Err(Error::from("Failed to find matching update client event".to_string()))
+		client_messages
+			.pop()
+			.ok_or_else(|| Error::from("Failed to find matching update client event".to_string()))
 	}
 
 	async fn get_proof_height(&self, block_height: Height) -> Height {
 		block_height.increment()
 	}
 
-	async fn handle_error(&mut self, error: &anyhow::Error) -> Result<(), anyhow::Error> {
-		let err_str = if let Some(rpc_err) = error.downcast_ref::<Error>() {
-			match rpc_err {
-				Error::RpcError(s) => s.clone(),
-				_ => "".to_string(),
-			}
-		} else {
-			error.to_string()
+	async fn verify_client_update<C: Chain>(
+		&self,
+		counterparty: &C,
+		client_message: AnyClientMessage,
+	) -> Result<(), anyhow::Error> {
+		let AnyClientMessage::Tendermint(ClientMessage::Header(header)) =
+			client_message.unpack_recursive_into()
+		else {
+			return Ok(())
+		};
+		let client_id = self.client_id();
+		let (at, now) = counterparty.latest_height_and_timestamp().await.map_err(|e| {
+			anyhow!("Failed to query latest height on {}: {e}", counterparty.name())
+		})?;
+		let client_state = counterparty
+			.query_client_state(at, client_id.clone())
+			.await
+			.map_err(|e| anyhow!("Failed to query client state {client_id}: {e}"))?
+			.client_state
+			.ok_or_else(|| anyhow!("Client state {client_id} not found"))?;
+		let Some(AnyClientState::Tendermint(client_state)) =
+			AnyClientState::decode_recursive(client_state, |cs| {
+				matches!(cs, AnyClientState::Tendermint(_))
+			})
+		else {
+			return Err(anyhow!("Client {client_id} is not a tendermint client"))
 		};
-		log::debug!(target: "hyperspace_cosmos", "Handling error: {err_str}");
-		if err_str.contains("dispatch task is gone") ||
-			err_str.contains("failed to send message to internal channel")
+		let trusted_height = header.trusted_height;
+		let consensus_state = counterparty
+			.query_client_consensus(at, client_id.clone(), trusted_height)
+			.await
+			.map_err(|e| {
+				anyhow!("Failed to query consensus state {client_id} at {trusted_height}: {e}")
+			})?
+			.consensus_state
+			.ok_or_else(|| anyhow!("Consensus state {client_id} at {trusted_height} not found"))?;
+		let consensus_state = match AnyConsensusState::try_from(consensus_state)
+			.map_err(|e| anyhow!("Failed to decode consensus state {client_id}: {e:?}"))?
 		{
+			AnyConsensusState::Tendermint(consensus_state) => consensus_state,
+			AnyConsensusState::Wasm(wasm) => match *wasm.inner {
+				AnyConsensusState::Tendermint(consensus_state) => consensus_state,
+				_ => return Err(anyhow!("Client {client_id} is not a tendermint client")),
+			},
+			_ => return Err(anyhow!("Client {client_id} is not a tendermint client")),
+		};
+		let now = now
+			.into_tm_time()
+			.ok_or_else(|| anyhow!("Latest timestamp of {} is unknown", counterparty.name()))?;
+		verify_header(&client_state, &consensus_state, &header, now)?;
+		Ok(())
+	}
+
+	async fn handle_error(&mut self, error: &anyhow::Error) -> Result<(), anyhow::Error> {
+		log::debug!(target: "hyperspace_cosmos", "Handling error: {error:?}");
+		let disconnected = error.chain().any(|e| {
+			e.downcast_ref::<Error>().map(Error::is_disconnected).unwrap_or_default() ||
+				e.downcast_ref::<tendermint_rpc::Error>()
+					.map(is_disconnected)
+					.unwrap_or_default()
+		});
+		if disconnected {
 			self.reconnect().await?;
 			self.common_state.rpc_call_delay *= 2;
 		}
@@ -259,14 +331,24 @@ where
 
 	async fn reconnect(&mut self) -> anyhow::Result<()> {
 		// TODO: don't reconnect if the url is not presented
-		let (rpc_client, ws_driver) = WebSocketClient::new(self.websocket_url().clone())
-			.await
-			.map_err(|e| Error::RpcError(format!("{e:?}")))?;
+		let (rpc_client, ws_driver) =
+			WebSocketClient::new(self.websocket_url().clone()).await.map_err(Error::from)?;
 		self.join_handles.lock().await.push(tokio::spawn(ws_driver.run()));
 		self.rpc_ws_client = Some(rpc_client);
 		log::info!(target: "hyperspace_cosmos", "Reconnected to cosmos chain");
 		Ok(())
 	}
+
+	async fn await_ready(&self, timeout: Duration) -> Result<(), anyhow::Error> {
+		utils::await_height_advance(
+			|| async { self.latest_height_and_timestamp().await.map(|(height, _)| height) },
+			timeout,
+			utils::READY_POLL_INTERVAL,
+		)
+		.await
+		.map(|_| ())
+		.map_err(|e| anyhow::anyhow!("{} at {} isn't ready: {e}", self.name, self.rpc_url))
+	}
 }
 
 impl<H> CosmosClient<H>
@@ -296,7 +378,7 @@ where
 		&self,
 		_counterparty: &C,
 		_client_message: AnyClientMessage,
-	) -> Result<(), anyhow::Error> {
-		Ok(())
+	) -> Result<Option<DetectedMisbehaviour>, anyhow::Error> {
+		Ok(None)
 	}
 }
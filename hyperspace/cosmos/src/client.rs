@@ -1,24 +1,33 @@
 #![allow(clippy::all)]
 use super::{
-	key_provider::KeyEntry,
+	key_provider::{account_from_public_key, KeyEntry},
+	latest_height::LatestHeightCache,
 	light_client::LightClient,
-	tx::{broadcast_tx, confirm_tx, sign_tx, simulate_tx},
+	tx::{broadcast_tx, confirm_tx, sign_tx},
+};
+use crate::{
+	consensus_heights::ConsensusHeights,
+	error::Error,
+	fees::{submit_with_fee_retry, FallbackFee, FeeState, GasPriceConfig},
+	query::{CosmosQueryBackend, FallbackQueryBackend, GrpcQueryBackend, RestQueryBackend},
 };
-use crate::error::Error;
 use bech32::ToBase32;
 use bip32::{DerivationPath, ExtendedPrivateKey, XPrv, XPub as ExtendedPublicKey};
 use core::convert::{From, Into, TryFrom};
 use digest::Digest;
-use ibc::core::{
-	ics02_client::height::Height,
-	ics23_commitment::commitment::{CommitmentPrefix, CommitmentProofBytes},
-	ics24_host::{
-		identifier::{ChainId, ChannelId, ClientId, ConnectionId, PortId},
-		IBC_QUERY_PATH,
+use ibc::{
+	core::{
+		ics02_client::height::Height,
+		ics23_commitment::commitment::{CommitmentPrefix, CommitmentProofBytes},
+		ics24_host::{
+			identifier::{ChainId, ChannelId, ClientId, ConnectionId, PortId},
+			IBC_QUERY_PATH,
+		},
 	},
+	timestamp::Timestamp,
 };
 use ibc_proto::{
-	cosmos::auth::v1beta1::{query_client::QueryClient, BaseAccount, QueryAccountRequest},
+	cosmos::{auth::v1beta1::BaseAccount, base::v1beta1::Coin, tx::v1beta1::Fee},
 	google::protobuf::Any,
 };
 use ics07_tendermint::{
@@ -27,15 +36,21 @@ use ics07_tendermint::{
 };
 use pallet_ibc::light_clients::{AnyClientState, AnyConsensusState, HostFunctionsManager};
 use primitives::{
-	Chain, CommonClientConfig, CommonClientState, IbcProvider, KeyProvider, UpdateType,
+	connection_prefixes,
+	packet_filter::SharedPacketFilter,
+	query_cache::QueryCache,
+	relayed_connection_ids,
+	replay::{RecordMode, RECORD_DIR_ENV, REPLAY_DIR_ENV},
+	signer::{set_message_signer, RemoteSigner, RotatingSigner, SignerConfig, TransactionSigner},
+	Chain, ChannelWhitelist, CommonClientConfig, CommonClientState, ConnectionConfig, IbcProvider,
+	KeyProvider, UpdateType,
 };
-use prost::Message;
 use quick_cache::sync::Cache;
 use rand::Rng;
 use ripemd::Ripemd160;
 use serde::{Deserialize, Serialize};
 use std::{
-	collections::HashSet,
+	collections::HashMap,
 	str::FromStr,
 	sync::{Arc, Mutex},
 	time::Duration,
@@ -53,11 +68,16 @@ use tokio::{
 const DEFAULT_FEE_DENOM: &str = "stake";
 const DEFAULT_FEE_AMOUNT: &str = "4000";
 const DEFAULT_GAS_LIMIT: u64 = (i64::MAX - 1) as u64;
+const DEFAULT_LATEST_HEIGHT_MAX_STALENESS_MS: u64 = 10_000;
 
 fn default_gas_limit() -> u64 {
 	DEFAULT_GAS_LIMIT
 }
 
+fn default_latest_height_max_staleness_ms() -> u64 {
+	DEFAULT_LATEST_HEIGHT_MAX_STALENESS_MS
+}
+
 fn default_fee_denom() -> String {
 	DEFAULT_FEE_DENOM.to_string()
 }
@@ -120,6 +140,16 @@ pub struct MnemonicEntry {
 	pub mnemonic: String,
 	pub prefix: String,
 }
+
+/// Account of the signer of the transactions of a [`CosmosClient`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignerAccount {
+	/// Bech32 account
+	pub account: String,
+	/// Address
+	pub address: Vec<u8>,
+}
+
 // Implements the [`crate::Chain`] trait for cosmos.
 /// This is responsible for:
 /// 1. Tracking a cosmos light client on a counter-party chain, advancing this light
@@ -139,30 +169,42 @@ pub struct CosmosClient<H> {
 	pub rpc_url: Url,
 	/// Chain grpc address
 	pub grpc_url: Option<Url>,
+	/// Chain REST (LCD) address
+	pub rest_url: Option<Url>,
+	/// Queries of the IBC state served over gRPC, or over REST when gRPC is unavailable
+	pub query_backend: FallbackQueryBackend,
+	/// Heights of the consensus states of the clients, listed with the query of the node's
+	/// ibc-go version
+	pub consensus_heights: ConsensusHeights,
 	/// Websocket chain ws client
 	pub websocket_url: Option<Url>,
 	/// Chain Id
 	pub chain_id: ChainId,
 	/// Light client id on counterparty chain
 	pub client_id: Arc<Mutex<Option<ClientId>>>,
-	/// Connection Id
-	pub connection_id: Arc<Mutex<Option<ConnectionId>>>,
+	/// Ids of the relayed connections, the primary connection first
+	pub connection_ids: Arc<Mutex<Vec<ConnectionId>>>,
 	/// Channels cleared for packet relay
-	pub channel_whitelist: Arc<Mutex<HashSet<(ChannelId, PortId)>>>,
+	pub channel_whitelist: Arc<Mutex<ChannelWhitelist>>,
 	/// Light Client instance
 	pub light_client: LightClient,
-	/// The key that signs transactions
-	pub keybase: KeyEntry,
+	/// The signer of transactions and its account, rotated by
+	/// [`KeyProvider::rotate_key`](primitives::KeyProvider::rotate_key)
+	pub signer: RotatingSigner<SignerAccount>,
 	/// Account prefix
 	pub account_prefix: String,
 	/// Reference to commitment
 	pub commitment_prefix: CommitmentPrefix,
+	/// Commitment prefixes of the connections that don't use [`Self::commitment_prefix`]
+	pub connection_prefixes: HashMap<ConnectionId, CommitmentPrefix>,
 	/// Fee denom
 	pub fee_denom: String,
 	/// Fee amount
 	pub fee_amount: String,
 	/// Fee amount
 	pub gas_limit: u64,
+	/// Gas prices and fallback denoms of the fees
+	pub fees: FeeState,
 	/// Maximun transaction size
 	pub max_tx_size: usize,
 	/// Finality protocol to use, eg Tenderminet
@@ -172,10 +214,14 @@ pub struct CosmosClient<H> {
 	pub tx_mutex: Arc<tokio::sync::Mutex<()>>,
 	/// Light-client blocks cache
 	pub light_block_cache: Arc<Cache<TmHeight, LightBlock>>,
+	/// Latest height, updated on new blocks
+	pub latest_height: LatestHeightCache,
 	/// Relayer data
 	pub common_state: CommonClientState,
 	/// Join handles for spawned tasks
 	pub join_handles: Arc<TokioMutex<Vec<JoinHandle<Result<(), tendermint_rpc::Error>>>>>,
+	/// Address of the contract sending the raw packets of the tests
+	pub packet_sender_contract: Option<String>,
 }
 
 /// config options for [`ParachainClient`]
@@ -187,6 +233,9 @@ pub struct CosmosClientConfig {
 	pub rpc_url: Url,
 	/// grpc url for cosmos
 	pub grpc_url: Option<Url>,
+	/// REST (LCD) url for cosmos, queried when the grpc endpoint is unavailable
+	#[serde(default)]
+	pub rest_url: Option<Url>,
 	/// websocket url for cosmos
 	pub websocket_url: Option<Url>,
 	/// Cosmos chain Id
@@ -195,6 +244,9 @@ pub struct CosmosClientConfig {
 	pub client_id: Option<ClientId>,
 	/// Connection Id
 	pub connection_id: Option<ConnectionId>,
+	/// Connections relayed in addition to `connection_id`
+	#[serde(default)]
+	pub connections: Vec<ConnectionConfig>,
 	/// Account prefix
 	pub account_prefix: String,
 	/// Fee denom
@@ -206,6 +258,14 @@ pub struct CosmosClientConfig {
 	/// Fee amount
 	#[serde(default = "default_gas_limit")]
 	pub gas_limit: u64,
+	/// Discovery of the gas price from the minimum gas prices of the node, paying the simulated
+	/// gas instead of `fee_amount` and `gas_limit`
+	#[serde(default)]
+	pub gas_price: Option<GasPriceConfig>,
+	/// Fees tried in order after `fee_denom` and `fee_amount` when the relayer can't afford the
+	/// fee in `fee_denom`, e.g. `[{ denom = "uatom", amount = "25" }]`
+	#[serde(default)]
+	pub fallback_fees: Vec<FallbackFee>,
 	/// Store prefix
 	pub store_prefix: String,
 	/// Maximun transaction size
@@ -213,6 +273,10 @@ pub struct CosmosClientConfig {
 	/// All the client states and headers will be wrapped in WASM ones using the WASM code ID.
 	#[serde(default)]
 	pub wasm_code_id: Option<String>,
+	/// Milliseconds for which the latest height received over the websocket is used instead of
+	/// querying it, 0 always queries it
+	#[serde(default = "default_latest_height_max_staleness_ms")]
+	pub latest_height_max_staleness_ms: u64,
 	/*
 	Here is a list of dropped configuration parameters from Hermes Config.toml
 	that could be set to default values or removed for the MVP phase:
@@ -235,13 +299,21 @@ pub struct CosmosClientConfig {
 	*/
 	/// Whitelisted channels
 	pub channel_whitelist: Vec<(ChannelId, PortId)>,
-	/// The key that signs transactions
+	/// The key that signs transactions, unless a remote signer is used
+	#[serde(default)]
 	pub mnemonic: String,
+	/// Signer of transactions
+	#[serde(default)]
+	pub signer: SignerConfig,
 	/// Common client config
 	#[serde(flatten)]
 	pub common: CommonClientConfig,
 	/// Skip transfer packets with the following tokens base denoms
 	pub skip_tokens_list: Option<Vec<String>>,
+	/// Address of the contract sending the raw packets of the tests, bound to the port
+	/// `wasm.<address>`
+	#[serde(default)]
+	pub packet_sender_contract: Option<String>,
 }
 
 impl<H> CosmosClient<H>
@@ -251,6 +323,17 @@ where
 {
 	/// Initializes a [`CosmosClient`] given a [`CosmosClientConfig`]
 	pub async fn new(config: CosmosClientConfig) -> Result<Self, Error> {
+		// the tendermint rpc, grpc and rest clients aren't recorded, so a replay would reach the
+		// node
+		if let Some(mode) = RecordMode::from_env().map_err(|e| Error::Custom(e.to_string()))? {
+			let variable = match mode {
+				RecordMode::Record(_) => RECORD_DIR_ENV,
+				RecordMode::Replay(_) => REPLAY_DIR_ENV,
+			};
+			return Err(Error::Custom(format!(
+				"The rpc calls of cosmos chains can't be recorded or replayed, {variable} must be unset"
+			)))
+		}
 		let mut rpc_client = None;
 
 		let mut join_handles = vec![];
@@ -259,36 +342,76 @@ where
 			(rpc_client, rpc_driver) = WebSocketClient::new(websocket_url.clone())
 				.await
 				.map(|(x, y)| (Some(x), y))
-				.map_err(|e| Error::RpcError(format!("failed to connect to Websocket {:?}", e)))?;
+				.map_err(Error::from)?;
 			join_handles.push(tokio::spawn(rpc_driver.run()));
 		} else {
 			log::warn!(target: "hyperspace_cosmos", "No websocket url provided for cosmos chain");
 		}
-		let rpc_http_client = HttpClient::new(config.rpc_url.clone())
-			.map_err(|e| Error::RpcError(format!("failed to connect to RPC {:?}", e)))?;
+		let rpc_http_client = HttpClient::new(config.rpc_url.clone()).map_err(Error::from)?;
 		let mut grpc_client = None;
 		if let Some(grpc_url) = &config.grpc_url {
-			grpc_client = tonic::transport::Endpoint::new(grpc_url.to_string())
-				.map_err(|e| Error::RpcError(format!("failed to connect to RPC {:?}", e)))?
-				.connect()
-				.await
-				.map(Some)
-				.map_err(|e| Error::RpcError(format!("failed to connect to RPC {:?}", e)))?;
+			let endpoint =
+				tonic::transport::Endpoint::new(grpc_url.to_string()).map_err(Error::from)?;
+			// an unreachable grpc endpoint is fallen back from when there is a REST one
+			grpc_client = match config.rest_url {
+				Some(_) => Some(endpoint.connect_lazy()),
+				None => Some(endpoint.connect().await?),
+			};
 		} else {
 			log::warn!(target: "hyperspace_cosmos", "No grpc url provided for cosmos chain");
 		}
+		let mut query_backends: Vec<Arc<dyn CosmosQueryBackend>> = vec![];
+		if let Some(grpc_client) = &grpc_client {
+			query_backends.push(Arc::new(GrpcQueryBackend::new(grpc_client.clone())));
+		}
+		if let Some(rest_url) = &config.rest_url {
+			query_backends.push(Arc::new(RestQueryBackend::new(rest_url.clone())));
+		}
+		if query_backends.is_empty() {
+			return Err(Error::Custom("Neither a grpc nor a rest url is provided".to_string()))
+		}
 
 		let chain_id = ChainId::from(config.chain_id);
+		let latest_height =
+			LatestHeightCache::new(Duration::from_millis(config.latest_height_max_staleness_ms));
+		match &config.websocket_url {
+			Some(websocket_url) if config.latest_height_max_staleness_ms > 0 =>
+				join_handles.push(tokio::spawn(
+					latest_height
+						.clone()
+						.follow_new_blocks(websocket_url.clone(), chain_id.clone()),
+				)),
+			_ => {},
+		}
+		let fees = FeeState::new(config.gas_price, config.fallback_fees);
+		if let (Some(_), Some(grpc_url)) = (&fees.gas_price, &config.grpc_url) {
+			let (fees, grpc_url) = (fees.clone(), grpc_url.clone());
+			join_handles.push(tokio::spawn(async move {
+				fees.refresh_gas_prices(grpc_url).await;
+				Ok(())
+			}));
+		}
 		let light_client =
 			LightClient::init_light_client(config.rpc_url.clone(), Duration::from_secs(10)).await?;
 		let commitment_prefix = CommitmentPrefix::try_from(config.store_prefix.as_bytes().to_vec())
 			.map_err(|e| Error::from(format!("Invalid store prefix {:?}", e)))?;
-
-		let keybase: KeyEntry = KeyEntry::try_from(MnemonicEntry {
-			mnemonic: config.mnemonic,
-			prefix: config.account_prefix.clone(),
-		})
-		.map_err(|e| e.to_string())?;
+		let connection_prefixes =
+			connection_prefixes(&config.connections).map_err(|e| Error::Custom(e.to_string()))?;
+
+		let signer: Arc<dyn TransactionSigner> = match config.signer {
+			SignerConfig::Local => Arc::new(
+				KeyEntry::try_from(MnemonicEntry {
+					mnemonic: config.mnemonic,
+					prefix: config.account_prefix.clone(),
+				})
+				.map_err(|e| e.to_string())?,
+			),
+			SignerConfig::Remote(remote) =>
+				Arc::new(RemoteSigner::connect(remote).await.map_err(Error::Signer)?),
+		};
+		let (account, address) =
+			account_from_public_key(&config.account_prefix, &signer.public_key())
+				.map_err(|e| Error::Custom(e.to_string()))?;
 
 		let rpc_call_delay = Duration::from_millis(1000);
 		Ok(Self {
@@ -299,21 +422,33 @@ where
 			grpc_client,
 			rpc_url: config.rpc_url,
 			grpc_url: config.grpc_url,
+			rest_url: config.rest_url,
+			query_backend: FallbackQueryBackend::new(query_backends),
+			consensus_heights: ConsensusHeights::default(),
 			websocket_url: config.websocket_url,
 			client_id: Arc::new(Mutex::new(config.client_id)),
-			connection_id: Arc::new(Mutex::new(config.connection_id)),
-			channel_whitelist: Arc::new(Mutex::new(config.channel_whitelist.into_iter().collect())),
+			connection_ids: Arc::new(Mutex::new(relayed_connection_ids(
+				config.connection_id.as_ref(),
+				&config.connections,
+			))),
+			channel_whitelist: Arc::new(Mutex::new(ChannelWhitelist::new(
+				config.channel_whitelist,
+				&config.connections,
+			))),
 			light_client,
 			account_prefix: config.account_prefix,
 			commitment_prefix,
+			connection_prefixes,
 			fee_denom: config.fee_denom,
 			fee_amount: config.fee_amount,
 			gas_limit: config.gas_limit,
+			fees,
 			max_tx_size: config.max_tx_size,
-			keybase,
+			signer: RotatingSigner::new(signer, SignerAccount { account, address }),
 			_phantom: std::marker::PhantomData,
 			tx_mutex: Default::default(),
 			light_block_cache: Arc::new(Cache::new(100000)),
+			latest_height,
 			common_state: CommonClientState {
 				skip_optional_client_updates: config.common.skip_optional_client_updates,
 				maybe_has_undelivered_packets: Default::default(),
@@ -322,8 +457,35 @@ where
 				misbehaviour_client_msg_queue: Arc::new(AsyncMutex::new(vec![])),
 				max_packets_to_process: config.common.max_packets_to_process as usize,
 				skip_tokens_list: config.skip_tokens_list.unwrap_or_default(),
+				max_submit_retries: config.common.max_submit_retries,
+				strict_middleware_check: config.common.strict_middleware_check,
+				unsupported_middleware_channels: Default::default(),
+				simulate_messages: config.common.simulate_messages,
+				skip_invalid_wasm_messages: config.common.skip_invalid_wasm_messages,
+				stop_packets_on_inactive_client: config.common.stop_packets_on_inactive_client,
+				finality_health: Default::default(),
+				authority_set_lag: Default::default(),
+				metadata_mismatches: vec![],
+				misbehaviour_checks: Default::default(),
+				channel_version_resolvers: Default::default(),
+				packet_filter: SharedPacketFilter::new(&config.common.packet_filters)
+					.map_err(|e| Error::Custom(e.to_string()))?,
+				query_cache: QueryCache::new(config.common.query_cache_capacity),
+				timeout_safety_margin_blocks: config.common.timeout_safety_margin_blocks,
+				auto_whitelist_channels: config.common.auto_whitelist_channels,
+				relayer_store: None,
+				fee_payee_address: config.common.fee_payee_address,
+				min_balance: config.common.min_balance.map(u128::from),
+				stall_factor: config.common.stall_factor,
+				stall_alert_command: config.common.stall_alert_command,
+				proof_query_concurrency: config.common.proof_query_concurrency,
+				max_pending_messages: config.common.max_pending_messages,
+				pre_validate_updates: config.common.pre_validate_updates,
+				submitted_updates: Default::default(),
+				relay_stats: Default::default(),
 			},
 			join_handles: Arc::new(TokioMutex::new(join_handles)),
+			packet_sender_contract: config.packet_sender_contract,
 		})
 	}
 
@@ -385,31 +547,78 @@ where
 
 	pub async fn submit_call(&self, messages: Vec<Any>) -> Result<Hash, Error> {
 		let _lock = self.tx_mutex.lock().await;
-		let account_info = self.query_account().await?;
+		let (signer, account) = self.signer.current();
+		// the messages assembled before a rotation of the key still name the previous account
+		let messages = messages
+			.into_iter()
+			.map(|msg| set_message_signer(msg, &account.account))
+			.collect::<Vec<_>>();
+		let account_info = self.query_account(account.account).await?;
 
 		// Sign transaction
 		let (tx, _, tx_bytes) = sign_tx(
-			self.keybase.clone(),
+			&*signer,
 			self.chain_id.clone(),
 			&account_info,
-			messages,
+			messages.clone(),
 			self.get_fee(),
-		)?;
+		)
+		.await?;
 
 		// Simulate transaction
-		let res = simulate_tx(self.grpc_url(), tx, tx_bytes.clone()).await?;
+		let res = self.query_backend.simulate(&tx, &tx_bytes).await?;
 		res.result
 			.map(|r| log::debug!(target: "hyperspace_cosmos", "Simulated transaction: events: {:?}\nlogs: {}", r.events, r.log));
+		let gas_used = res.gas_info.map(|info| info.gas_used).unwrap_or_default();
+		let fee = self.affordable_fee(gas_used).await?;
 
-		// Broadcast transaction
+		// Broadcast transaction, signed again if the fee is re-estimated
 		let client = &self.rpc_ws_client();
-		let hash = broadcast_tx(client, tx_bytes).await?;
+		let hash = submit_with_fee_retry(&self.fees, fee, |fee| {
+			let (messages, account_info) = (messages.clone(), &account_info);
+			async move {
+				let (_, _, tx_bytes) =
+					sign_tx(&*signer, self.chain_id.clone(), account_info, messages, fee).await?;
+				broadcast_tx(client, tx_bytes).await
+			}
+		})
+		.await?;
 		log::info!(target: "hyperspace_cosmos", "🤝 Transaction sent with hash: {:?}", hash);
 
 		// wait for confirmation
 		confirm_tx(client, hash).await
 	}
 
+	/// The fee of a transaction using `gas_used`, the first of the fees in `fee_denom` and the
+	/// fallback denoms that the relayer can afford.
+	async fn affordable_fee(&self, gas_used: u64) -> Result<Fee, Error> {
+		let primary =
+			FallbackFee { denom: self.fee_denom.clone(), amount: self.fee_amount.clone() };
+		let fees = self.fees.fees_for_gas(&primary, self.gas_limit, gas_used);
+		if self.fees.fallback_fees.is_empty() {
+			return Ok(fees.into_iter().next().expect("the primary fee is always tried; qed"))
+		}
+		let denoms = fees.iter().map(|fee| fee.amount[0].denom.clone()).collect::<Vec<_>>();
+		for fee in fees {
+			let (denom, amount) = (&fee.amount[0].denom, &fee.amount[0].amount);
+			let amount = amount.parse::<u128>().unwrap_or_default();
+			let balance = self
+				.query_ibc_balance(denom.clone())
+				.await?
+				.first()
+				.map(|coin| u128::try_from(coin.amount.as_u256()).unwrap_or(u128::MAX))
+				.unwrap_or_default();
+			if balance >= amount {
+				return Ok(fee)
+			}
+			log::warn!(target: "hyperspace_cosmos", "{} can't afford a fee of {amount}{denom}, its balance is {balance}{denom}", self.name);
+		}
+		Err(Error::Custom(format!(
+			"{} can't afford the fee in any of {denoms:?}: insufficient funds",
+			self.name,
+		)))
+	}
+
 	pub async fn fetch_light_block_with_cache(
 		&self,
 		height: TmHeight,
@@ -490,26 +699,60 @@ where
 		Ok(xs)
 	}
 
-	/// Uses the GRPC client to retrieve the account sequence
-	pub async fn query_account(&self) -> Result<BaseAccount, Error> {
-		let mut client = QueryClient::connect(self.grpc_url().to_string())
+	/// Queries the latest height and timestamp of the chain over RPC.
+	pub async fn query_latest_height_and_timestamp(&self) -> Result<(Height, Timestamp), Error> {
+		// We cannot rely on `/status` endpoint to provide details about the latest block.
+		// Instead, we need to pull block height via `/abci_info` and then fetch block
+		// metadata at the given height via `/blockchain` endpoint.
+		let abci_info = self.rpc_http_client.abci_info().await.map_err(Error::from)?;
+
+		// Query `/blockchain` endpoint to pull the block metadata corresponding to
+		// the latest block that the application committed.
+		// TODO: Replace this query with `/header`, once it's available.
+		//  https://github.com/informalsystems/tendermint-rs/pull/1101
+		let blocks = self
+			.rpc_http_client
+			.blockchain(abci_info.last_block_height, abci_info.last_block_height)
 			.await
-			.map_err(|e| Error::from(format!("GRPC client error: {:?}", e)))?;
-
-		let request =
-			tonic::Request::new(QueryAccountRequest { address: self.keybase.account.to_string() });
+			.map_err(Error::from)?;
+
+		let latest_app_block = blocks.block_metas.first().ok_or_else(|| {
+			Error::Custom("/blockchain endpoint for latest app. block".to_owned())
+		})?;
+
+		let height = Height::new(
+			ChainId::chain_version(latest_app_block.header.chain_id.as_str()),
+			u64::from(abci_info.last_block_height),
+		);
+		let timestamp = latest_app_block.header.time.into();
+		Ok((height, timestamp))
+	}
 
-		let response = client.account(request).await;
+	/// Whether the chain runs the packet forward middleware, found by querying its parameters
+	/// under `packetforward`, or `router` in its earlier versions.
+	pub async fn is_packet_forwarding_enabled(&self) -> Result<bool, Error> {
+		for path in ["/packetforward.v1.Query/Params", "/router.v1.Query/Params"] {
+			let response = self
+				.rpc_http_client
+				.abci_query(Some(path.to_owned()), vec![], None, false)
+				.await
+				.map_err(Error::from)?;
+			if response.code.is_ok() {
+				return Ok(true)
+			}
+		}
+		Ok(false)
+	}
 
-		// Querying for an account might fail, i.e. if the account doesn't actually exist
-		let resp_account =
-			match response.map_err(|e| Error::from(format!("{:?}", e)))?.into_inner().account {
-				Some(account) => account,
-				None => return Err(Error::from(format!("Account not found"))),
-			};
+	/// Retrieves the balance of `account` in `denom`
+	pub async fn query_balance(&self, account: String, denom: &str) -> Result<Coin, Error> {
+		// Querying for a balance might fail, i.e. if the account doesn't actually exist
+		self.query_backend.balance(&account, denom).await
+	}
 
-		Ok(BaseAccount::decode(resp_account.value.as_slice())
-			.map_err(|e| Error::from(format!("Failed to decode account {}", e)))?)
+	/// Retrieves the number and sequence of `account`
+	pub async fn query_account(&self, account: String) -> Result<BaseAccount, Error> {
+		self.query_backend.account(&account).await
 	}
 
 	pub async fn query_path(
@@ -518,7 +761,17 @@ where
 		height_query: Height,
 		prove: bool,
 	) -> Result<(AbciQuery, Vec<u8>), Error> {
-		let path = IBC_QUERY_PATH;
+		self.query_store_path(IBC_QUERY_PATH, data, height_query, prove).await
+	}
+
+	/// Queries the key `data` at the ABCI query `path` of a store, see [`store_query_path`].
+	pub async fn query_store_path(
+		&self,
+		path: &str,
+		data: Vec<u8>,
+		height_query: Height,
+		prove: bool,
+	) -> Result<(AbciQuery, Vec<u8>), Error> {
 		let height = TmHeight::try_from(height_query.revision_height)
 			.map_err(|e| Error::from(format!("Invalid height {}", e)))?;
 
@@ -532,9 +785,7 @@ where
 			.rpc_http_client
 			.abci_query(Some(path.to_owned()), data.clone(), height, prove)
 			.await
-			.map_err(|e| {
-				Error::from(format!("Failed to query chain {} with error {:?}", self.name, e))
-			})?;
+			.map_err(Error::from)?;
 
 		if !response.code.is_ok() {
 			// Fail with response log.
@@ -565,6 +816,11 @@ where
 	}
 }
 
+/// Returns the ABCI query path of the keys of the store with the commitment `prefix`.
+pub fn store_query_path(prefix: &CommitmentPrefix) -> String {
+	format!("store/{}/key", String::from_utf8_lossy(prefix.as_bytes()))
+}
+
 /// Checks that the two validator sets are equal. The default implementation
 /// of `Eq` cannot be used, because the `proposer` should be ignored.
 fn is_validators_equal(set_a: &ValidatorSet, set_b: &ValidatorSet) -> bool {
@@ -573,8 +829,8 @@ fn is_validators_equal(set_a: &ValidatorSet, set_b: &ValidatorSet) -> bool {
 
 #[cfg(test)]
 pub mod tests {
-	use super::MnemonicEntry;
-	use crate::key_provider::KeyEntry;
+	use super::{store_query_path, MnemonicEntry};
+	use crate::key_provider::{account_from_public_key, KeyEntry};
 
 	struct TestVector {
 		mnemonic: &'static str,
@@ -625,4 +881,21 @@ pub mod tests {
 			}
 		}
 	}
+
+	#[test]
+	fn accounts_are_derived_from_public_keys() {
+		for vector in TEST_VECTORS {
+			let (account, address) = account_from_public_key("cosmos", &vector.public_key).unwrap();
+			assert_eq!(account, vector.account);
+			assert_eq!(address.len(), 20);
+		}
+		assert!(account_from_public_key("cosmos", &[2; 32]).is_err());
+	}
+
+	#[test]
+	fn store_query_paths_follow_the_commitment_prefix() {
+		let prefix = |prefix: &str| prefix.as_bytes().to_vec().try_into().unwrap();
+		assert_eq!(store_query_path(&prefix("ibc")), ibc::core::ics24_host::IBC_QUERY_PATH);
+		assert_eq!(store_query_path(&prefix("ibc-v2")), "store/ibc-v2/key");
+	}
 }
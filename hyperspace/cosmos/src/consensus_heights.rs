@@ -0,0 +1,425 @@
+//! Selection of the consensus state of a client closest to a height.
+//!
+//! Since ibc-go v7 the node lists the heights of the consensus states of a client with the
+//! `ConsensusStateHeights` query. Older nodes don't serve it, so their heights are collected from
+//! the events of the transactions that created and updated the client instead, which is slower
+//! and misses the consensus states whose transactions were pruned.
+
+use crate::{
+	client::CosmosClient, error::Error, events::ibc_event_try_from_abci_event,
+	query::CosmosQueryBackend,
+};
+use ibc::{core::ics24_host::identifier::ClientId, events::IbcEvent, Height};
+use std::{
+	future::Future,
+	sync::{Arc, Mutex},
+};
+use tendermint_rpc::{query::Query, Client, Order};
+
+/// First ibc-go version serving the `ConsensusStateHeights` query.
+pub const CONSENSUS_STATE_HEIGHTS_MIN_IBC_GO_VERSION: u64 = 7;
+
+/// Number of transactions per page when walking the client events.
+const TX_SEARCH_PAGE_SIZE: u8 = 100;
+
+/// Lists the heights of the consensus states of the clients on a node, detecting on first use
+/// whether the node serves the `ConsensusStateHeights` query.
+#[derive(Clone, Default)]
+pub struct ConsensusHeights {
+	heights_query_supported: Arc<Mutex<Option<bool>>>,
+}
+
+impl ConsensusHeights {
+	/// Whether the ibc-go version of the node serves the `ConsensusStateHeights` query. The
+	/// detection is retried on the next call when the version can't be queried.
+	pub async fn heights_query_supported(&self, backend: &dyn CosmosQueryBackend) -> bool {
+		if let Some(supported) = *self.heights_query_supported.lock().unwrap() {
+			return supported
+		}
+		match backend.ibc_go_version().await {
+			Ok(version) => {
+				let supported =
+					version.map_or(false, |v| v >= CONSENSUS_STATE_HEIGHTS_MIN_IBC_GO_VERSION);
+				log::debug!(
+					target: "hyperspace_cosmos",
+					"Detected ibc-go version {version:?}, consensus state heights query supported: {supported}"
+				);
+				*self.heights_query_supported.lock().unwrap() = Some(supported);
+				supported
+			},
+			Err(e) => {
+				log::debug!(target: "hyperspace_cosmos", "Failed to query the ibc-go version: {e}");
+				false
+			},
+		}
+	}
+
+	/// Heights of the consensus states of the client, from the `ConsensusStateHeights` query when
+	/// the node serves it, or from `event_heights` otherwise.
+	pub async fn heights<F>(
+		&self,
+		backend: &dyn CosmosQueryBackend,
+		client_id: &ClientId,
+		event_heights: F,
+	) -> Result<Vec<Height>, Error>
+	where
+		F: Future<Output = Result<Vec<Height>, Error>>,
+	{
+		if self.heights_query_supported(backend).await {
+			match backend.consensus_state_heights(client_id).await {
+				Ok(heights) => return Ok(heights),
+				Err(e) => log::warn!(
+					target: "hyperspace_cosmos",
+					"Failed to query the consensus state heights of {client_id}, walking its events instead: {e}"
+				),
+			}
+		}
+		event_heights.await
+	}
+
+	/// Height of the latest consensus state of the client at or below `target`.
+	pub async fn closest<F>(
+		&self,
+		backend: &dyn CosmosQueryBackend,
+		client_id: &ClientId,
+		target: Height,
+		event_heights: F,
+	) -> Result<Option<Height>, Error>
+	where
+		F: Future<Output = Result<Vec<Height>, Error>>,
+	{
+		let heights = self.heights(backend, client_id, event_heights).await?;
+		Ok(closest_height(heights, target))
+	}
+}
+
+/// The highest of `heights` at or below `target`.
+pub fn closest_height(heights: impl IntoIterator<Item = Height>, target: Height) -> Option<Height> {
+	heights.into_iter().filter(|height| *height <= target).max()
+}
+
+impl<H> CosmosClient<H>
+where
+	H: 'static + Clone + Send + Sync,
+{
+	/// Heights of the consensus states of the client, collected from the events of the
+	/// transactions that created and updated it.
+	pub async fn query_consensus_heights_from_events(
+		&self,
+		client_id: &ClientId,
+	) -> Result<Vec<Height>, Error> {
+		let mut heights = vec![];
+		for event in ["create_client", "update_client"] {
+			let query = Query::eq(format!("{event}.client_id"), client_id.to_string());
+			let mut page = 1;
+			loop {
+				let response = self
+					.rpc_http_client
+					.tx_search(query.clone(), false, page, TX_SEARCH_PAGE_SIZE, Order::Ascending)
+					.await
+					.map_err(Error::from)?;
+				for tx in &response.txs {
+					let height = Height::new(self.chain_id.version(), tx.height.value());
+					for ev in &tx.tx_result.events {
+						match ibc_event_try_from_abci_event(ev, height) {
+							Ok(IbcEvent::CreateClient(e)) if e.client_id() == client_id =>
+								heights.push(e.0.consensus_height),
+							Ok(IbcEvent::UpdateClient(e)) if e.client_id() == client_id =>
+								heights.push(e.consensus_height()),
+							_ => (),
+						}
+					}
+				}
+				let fetched = page as usize * TX_SEARCH_PAGE_SIZE as usize;
+				if response.txs.is_empty() || fetched >= response.total_count as usize {
+					break
+				}
+				page += 1;
+			}
+		}
+		Ok(heights)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::query::GrpcQueryBackend;
+	use ibc_proto::{
+		cosmos::base::tendermint::v1beta1::{
+			service_server::{Service, ServiceServer},
+			AbciQueryRequest, AbciQueryResponse, GetBlockByHeightRequest, GetBlockByHeightResponse,
+			GetLatestBlockRequest, GetLatestBlockResponse, GetLatestValidatorSetRequest,
+			GetLatestValidatorSetResponse, GetNodeInfoRequest, GetNodeInfoResponse,
+			GetSyncingRequest, GetSyncingResponse, GetValidatorSetByHeightRequest,
+			GetValidatorSetByHeightResponse, Module, VersionInfo,
+		},
+		ibc::core::client::v1::{
+			query_server::{Query, QueryServer},
+			Height as RawHeight, QueryClientParamsRequest, QueryClientParamsResponse,
+			QueryClientStateRequest, QueryClientStateResponse, QueryClientStatesRequest,
+			QueryClientStatesResponse, QueryClientStatusRequest, QueryClientStatusResponse,
+			QueryConsensusStateHeightsRequest, QueryConsensusStateHeightsResponse,
+			QueryConsensusStateRequest, QueryConsensusStateResponse, QueryConsensusStatesRequest,
+			QueryConsensusStatesResponse, QueryUpgradedClientStateRequest,
+			QueryUpgradedClientStateResponse, QueryUpgradedConsensusStateRequest,
+			QueryUpgradedConsensusStateResponse,
+		},
+	};
+	use std::{
+		str::FromStr,
+		sync::atomic::{AtomicUsize, Ordering},
+	};
+	use tokio_stream::wrappers::TcpListenerStream;
+	use tonic::{transport::Channel, Request, Response, Status};
+
+	const CLIENT_ID: &str = "07-tendermint-0";
+
+	/// Heights of the consensus states of the client, unordered as the events of the updates
+	/// submitted by concurrent relayers.
+	fn consensus_heights() -> Vec<Height> {
+		[5, 40, 12, 25, 60].into_iter().map(|height| Height::new(1, height)).collect()
+	}
+
+	/// Client query service of a node, serving `ConsensusStateHeights` since ibc-go v7.
+	struct MockClientQuery {
+		ibc_go_version: u64,
+	}
+
+	fn unimplemented<T>() -> Result<Response<T>, Status> {
+		Err(Status::unimplemented("not served by the mock"))
+	}
+
+	#[tonic::async_trait]
+	impl Query for MockClientQuery {
+		async fn client_state(
+			&self,
+			_: Request<QueryClientStateRequest>,
+		) -> Result<Response<QueryClientStateResponse>, Status> {
+			unimplemented()
+		}
+
+		async fn client_states(
+			&self,
+			_: Request<QueryClientStatesRequest>,
+		) -> Result<Response<QueryClientStatesResponse>, Status> {
+			unimplemented()
+		}
+
+		async fn consensus_state(
+			&self,
+			_: Request<QueryConsensusStateRequest>,
+		) -> Result<Response<QueryConsensusStateResponse>, Status> {
+			unimplemented()
+		}
+
+		async fn consensus_states(
+			&self,
+			_: Request<QueryConsensusStatesRequest>,
+		) -> Result<Response<QueryConsensusStatesResponse>, Status> {
+			unimplemented()
+		}
+
+		async fn consensus_state_heights(
+			&self,
+			request: Request<QueryConsensusStateHeightsRequest>,
+		) -> Result<Response<QueryConsensusStateHeightsResponse>, Status> {
+			if self.ibc_go_version < CONSENSUS_STATE_HEIGHTS_MIN_IBC_GO_VERSION {
+				return unimplemented()
+			}
+			if request.into_inner().client_id != CLIENT_ID {
+				return Err(Status::not_found("client not found"))
+			}
+			let consensus_state_heights = consensus_heights()
+				.into_iter()
+				.map(|height| RawHeight {
+					revision_number: height.revision_number,
+					revision_height: height.revision_height,
+				})
+				.collect();
+			Ok(Response::new(QueryConsensusStateHeightsResponse {
+				consensus_state_heights,
+				pagination: None,
+			}))
+		}
+
+		async fn client_status(
+			&self,
+			_: Request<QueryClientStatusRequest>,
+		) -> Result<Response<QueryClientStatusResponse>, Status> {
+			unimplemented()
+		}
+
+		async fn client_params(
+			&self,
+			_: Request<QueryClientParamsRequest>,
+		) -> Result<Response<QueryClientParamsResponse>, Status> {
+			unimplemented()
+		}
+
+		async fn upgraded_client_state(
+			&self,
+			_: Request<QueryUpgradedClientStateRequest>,
+		) -> Result<Response<QueryUpgradedClientStateResponse>, Status> {
+			unimplemented()
+		}
+
+		async fn upgraded_consensus_state(
+			&self,
+			_: Request<QueryUpgradedConsensusStateRequest>,
+		) -> Result<Response<QueryUpgradedConsensusStateResponse>, Status> {
+			unimplemented()
+		}
+	}
+
+	/// Tendermint service of a node, reporting its ibc-go version in its build dependencies.
+	struct MockNodeService {
+		ibc_go_version: u64,
+		node_info_queries: Arc<AtomicUsize>,
+	}
+
+	#[tonic::async_trait]
+	impl Service for MockNodeService {
+		async fn get_node_info(
+			&self,
+			_: Request<GetNodeInfoRequest>,
+		) -> Result<Response<GetNodeInfoResponse>, Status> {
+			self.node_info_queries.fetch_add(1, Ordering::SeqCst);
+			let version = self.ibc_go_version;
+			let build_deps = vec![
+				Module {
+					path: "github.com/cosmos/cosmos-sdk".to_string(),
+					version: "v0.47.5".to_string(),
+					sum: String::new(),
+				},
+				Module {
+					path: format!("github.com/cosmos/ibc-go/v{version}"),
+					version: format!("v{version}.1.0"),
+					sum: String::new(),
+				},
+			];
+			Ok(Response::new(GetNodeInfoResponse {
+				default_node_info: None,
+				application_version: Some(VersionInfo { build_deps, ..Default::default() }),
+			}))
+		}
+
+		async fn get_syncing(
+			&self,
+			_: Request<GetSyncingRequest>,
+		) -> Result<Response<GetSyncingResponse>, Status> {
+			unimplemented()
+		}
+
+		async fn get_latest_block(
+			&self,
+			_: Request<GetLatestBlockRequest>,
+		) -> Result<Response<GetLatestBlockResponse>, Status> {
+			unimplemented()
+		}
+
+		async fn get_block_by_height(
+			&self,
+			_: Request<GetBlockByHeightRequest>,
+		) -> Result<Response<GetBlockByHeightResponse>, Status> {
+			unimplemented()
+		}
+
+		async fn get_latest_validator_set(
+			&self,
+			_: Request<GetLatestValidatorSetRequest>,
+		) -> Result<Response<GetLatestValidatorSetResponse>, Status> {
+			unimplemented()
+		}
+
+		async fn get_validator_set_by_height(
+			&self,
+			_: Request<GetValidatorSetByHeightRequest>,
+		) -> Result<Response<GetValidatorSetByHeightResponse>, Status> {
+			unimplemented()
+		}
+
+		async fn abci_query(
+			&self,
+			_: Request<AbciQueryRequest>,
+		) -> Result<Response<AbciQueryResponse>, Status> {
+			unimplemented()
+		}
+	}
+
+	/// Serves the query services of a node built with the given ibc-go version, returning the
+	/// backend querying it and the number of node info queries it served.
+	async fn serve(ibc_go_version: u64) -> (GrpcQueryBackend, Arc<AtomicUsize>) {
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let addr = listener.local_addr().unwrap();
+		let node_info_queries = Arc::new(AtomicUsize::new(0));
+		tokio::spawn(
+			tonic::transport::Server::builder()
+				.add_service(QueryServer::new(MockClientQuery { ibc_go_version }))
+				.add_service(ServiceServer::new(MockNodeService {
+					ibc_go_version,
+					node_info_queries: node_info_queries.clone(),
+				}))
+				.serve_with_incoming(TcpListenerStream::new(listener)),
+		);
+		let channel =
+			Channel::from_shared(format!("http://{addr}")).unwrap().connect().await.unwrap();
+		(GrpcQueryBackend::new(channel), node_info_queries)
+	}
+
+	#[test]
+	fn closest_height_is_the_highest_at_or_below_the_target() {
+		assert_eq!(closest_height(consensus_heights(), Height::new(1, 4)), None);
+		assert_eq!(
+			closest_height(consensus_heights(), Height::new(1, 12)),
+			Some(Height::new(1, 12))
+		);
+		assert_eq!(
+			closest_height(consensus_heights(), Height::new(1, 30)),
+			Some(Height::new(1, 25))
+		);
+		assert_eq!(
+			closest_height(consensus_heights(), Height::new(2, 1)),
+			Some(Height::new(1, 60))
+		);
+		assert_eq!(closest_height(vec![], Height::new(1, 30)), None);
+	}
+
+	#[tokio::test]
+	async fn heights_query_and_event_walk_select_the_same_height() {
+		let client_id = ClientId::from_str(CLIENT_ID).unwrap();
+		let targets = [4, 5, 13, 39, 40, 100].map(|height| Height::new(1, height));
+
+		// ibc-go v7 serves the heights, the events aren't walked
+		let (v7, node_info_queries) = serve(7).await;
+		let heights = ConsensusHeights::default();
+		let mut from_heights_query = vec![];
+		for target in targets {
+			let event_heights =
+				async { Err(Error::Custom("the events of the client were walked".to_string())) };
+			from_heights_query
+				.push(heights.closest(&v7, &client_id, target, event_heights).await.unwrap());
+		}
+		// the version is only detected once
+		assert_eq!(node_info_queries.load(Ordering::SeqCst), 1);
+
+		// ibc-go v6 doesn't, the heights come from the events
+		let (v6, _) = serve(6).await;
+		assert!(v6.consensus_state_heights(&client_id).await.is_err());
+		let heights = ConsensusHeights::default();
+		let mut from_events = vec![];
+		for target in targets {
+			let event_heights = async { Ok(consensus_heights()) };
+			from_events
+				.push(heights.closest(&v6, &client_id, target, event_heights).await.unwrap());
+		}
+		assert!(!heights.heights_query_supported(&v6).await);
+
+		assert_eq!(from_heights_query, from_events);
+		assert_eq!(
+			from_events,
+			[None, Some(5), Some(12), Some(25), Some(40), Some(60)]
+				.map(|height| height.map(|height| Height::new(1, height)))
+		);
+	}
+}
@@ -1,5 +1,16 @@
 use ibc::timestamp::ParseTimestampError;
+use primitives::{ErrorKind, HasErrorKind};
 use prost::DecodeError;
+use tendermint_rpc::error::ErrorDetail;
+use tonic::Code;
+
+/// Codespace of the errors raised by the Cosmos SDK itself.
+pub const SDK_CODESPACE: &str = "sdk";
+/// SDK error codes of transactions that can succeed when resubmitted: an account sequence
+/// mismatch, a full mempool and a transaction that was already in the mempool.
+pub const TRANSIENT_SDK_ERRORS: [u32; 3] = [32, 20, 19];
+/// SDK error code of a transaction whose fee is below the node's minimum gas price.
+pub const INSUFFICIENT_FEE: u32 = 13;
 
 /// Error definitions for the cosmos client in accordance with the parachain's Error type.
 #[derive(thiserror::Error, Debug)]
@@ -7,6 +18,24 @@ pub enum Error {
 	/// An error from the rpc interface
 	#[error("Rpc client error: {0}")]
 	RpcError(String),
+	/// An error returned by the tendermint rpc client
+	#[error("Rpc client error: {0}")]
+	Rpc(#[from] tendermint_rpc::Error),
+	/// A gRPC query that the node answered with an error status
+	#[error("gRPC error: {0}")]
+	Grpc(#[from] tonic::Status),
+	/// Failure to reach the gRPC endpoint
+	#[error("gRPC transport error: {0}")]
+	GrpcTransport(#[from] tonic::transport::Error),
+	/// A transaction rejected by `CheckTx` or that failed in `DeliverTx`
+	#[error("transaction {hash} failed with code {code} in codespace `{codespace}`: {log}")]
+	TxFailed { hash: String, codespace: String, code: u32, log: String },
+	/// The queried item doesn't exist on the chain
+	#[error("{0}")]
+	NotFound(String),
+	/// Failure to sign with the configured signer
+	#[error("Signer error: {0}")]
+	Signer(primitives::error::Error),
 	/// Custom error
 	#[error("{0}")]
 	Custom(String),
@@ -32,3 +61,100 @@ impl From<String> for Error {
 		Self::Custom(error)
 	}
 }
+
+impl Error {
+	/// Whether the error means the websocket connection to the node is gone.
+	pub fn is_disconnected(&self) -> bool {
+		matches!(self, Error::Rpc(e) if is_disconnected(e))
+	}
+}
+
+/// Whether the rpc error means the websocket connection to the node is gone.
+pub fn is_disconnected(error: &tendermint_rpc::Error) -> bool {
+	matches!(error.detail(), ErrorDetail::ChannelSend(_) | ErrorDetail::ClientInternal(_))
+}
+
+impl HasErrorKind for Error {
+	fn kind(&self) -> ErrorKind {
+		match self {
+			Error::RpcError(_) | Error::GrpcTransport(_) => ErrorKind::Transport,
+			Error::Rpc(e) => match e.detail() {
+				ErrorDetail::Response(_) => ErrorKind::Execution,
+				ErrorDetail::Serde(_) | ErrorDetail::Parse(_) | ErrorDetail::ParseInt(_) =>
+					ErrorKind::Decode,
+				ErrorDetail::MethodNotFound(_) => ErrorKind::Unsupported,
+				_ => ErrorKind::Transport,
+			},
+			Error::Grpc(status) => match status.code() {
+				Code::Unavailable |
+				Code::DeadlineExceeded |
+				Code::ResourceExhausted |
+				Code::Aborted |
+				Code::Cancelled => ErrorKind::Transport,
+				Code::NotFound => ErrorKind::NotFound,
+				Code::Unimplemented => ErrorKind::Unsupported,
+				_ => ErrorKind::Execution,
+			},
+			Error::TxFailed { codespace, code, .. }
+				if codespace == SDK_CODESPACE && TRANSIENT_SDK_ERRORS.contains(code) =>
+				ErrorKind::Transport,
+			Error::TxFailed { .. } => ErrorKind::Execution,
+			Error::NotFound(_) => ErrorKind::NotFound,
+			Error::Signer(e) => e.kind(),
+			Error::DecodeError(_) | Error::EncodeError(_) | Error::ParseTimestampError(_) =>
+				ErrorKind::Decode,
+			Error::Custom(_) | Error::TendermintError(_) | Error::TransferError(_) =>
+				ErrorKind::Execution,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ibc::timestamp::Timestamp;
+
+	fn tx_failed(codespace: &str, code: u32) -> Error {
+		Error::TxFailed {
+			hash: "ABCD".to_string(),
+			codespace: codespace.to_string(),
+			code,
+			log: String::new(),
+		}
+	}
+
+	#[test]
+	fn errors_are_classified() {
+		let cases = [
+			(tx_failed(SDK_CODESPACE, 32), ErrorKind::Transport),
+			(tx_failed(SDK_CODESPACE, 20), ErrorKind::Transport),
+			(tx_failed(SDK_CODESPACE, 11), ErrorKind::Execution),
+			(tx_failed("wasm", 20), ErrorKind::Execution),
+			(Error::Rpc(tendermint_rpc::Error::channel_send()), ErrorKind::Transport),
+			(
+				Error::Rpc(tendermint_rpc::Error::method_not_found("abci_query".to_string())),
+				ErrorKind::Unsupported,
+			),
+			(Error::Grpc(tonic::Status::unavailable("connection refused")), ErrorKind::Transport),
+			(Error::Grpc(tonic::Status::not_found("client state")), ErrorKind::NotFound),
+			(Error::Grpc(tonic::Status::unimplemented("v7 query")), ErrorKind::Unsupported),
+			(Error::Grpc(tonic::Status::invalid_argument("bad height")), ErrorKind::Execution),
+			(Error::NotFound("client 07-tendermint-0".to_string()), ErrorKind::NotFound),
+			// the message doesn't decide the kind any more
+			(Error::Custom("mempool is full".to_string()), ErrorKind::Execution),
+			(Error::DecodeError(DecodeError::new("invalid wire type")), ErrorKind::Decode),
+			(Error::ParseTimestampError("-1".parse::<Timestamp>().unwrap_err()), ErrorKind::Decode),
+		];
+		for (error, kind) in cases {
+			assert_eq!(error.kind(), kind, "{error}");
+		}
+	}
+
+	#[test]
+	fn dropped_websocket_is_detected() {
+		assert!(Error::Rpc(tendermint_rpc::Error::channel_send()).is_disconnected());
+		assert!(!Error::Rpc(tendermint_rpc::Error::method_not_found("status".to_string()))
+			.is_disconnected());
+		assert!(!Error::Custom("channel send".to_string()).is_disconnected());
+	}
+}
@@ -0,0 +1,396 @@
+//! Fees of the submitted transactions. With gas price discovery, the fee is the simulated gas
+//! times a multiplier, priced at the minimum gas price of the node, which is refreshed
+//! periodically and raised when the node rejects a fee as insufficient.
+
+use crate::error::{Error, INSUFFICIENT_FEE, SDK_CODESPACE};
+use futures::Future;
+use ibc_proto::cosmos::{
+	base::{
+		node::v1beta1::{service_client::ServiceClient, ConfigRequest},
+		v1beta1::Coin,
+	},
+	tx::v1beta1::Fee,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+	str::FromStr,
+	sync::{Arc, RwLock},
+	time::Duration,
+};
+use tendermint_rpc::Url;
+
+/// Factor by which a rejected fee is raised when the error doesn't tell the required fee.
+pub const FEE_BUMP: f64 = 1.5;
+
+fn default_gas_multiplier() -> f64 {
+	1.1
+}
+
+fn default_refresh_interval_secs() -> u64 {
+	60
+}
+
+/// Gas price discovery, set by the `gas_price` field of the cosmos config.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GasPriceConfig {
+	/// Multiplier of the simulated gas giving the gas limit of a transaction
+	#[serde(default = "default_gas_multiplier")]
+	pub gas_multiplier: f64,
+	/// Seconds between two queries of the minimum gas prices of the node
+	#[serde(default = "default_refresh_interval_secs")]
+	pub refresh_interval_secs: u64,
+}
+
+impl Default for GasPriceConfig {
+	fn default() -> Self {
+		Self {
+			gas_multiplier: default_gas_multiplier(),
+			refresh_interval_secs: default_refresh_interval_secs(),
+		}
+	}
+}
+
+/// Static fee of a transaction in a denom, tried when the relayer can't afford the fees before it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FallbackFee {
+	pub denom: String,
+	/// Amount paid in `denom` when gas price discovery doesn't price it
+	pub amount: String,
+}
+
+/// Price of a unit of gas in a denom, e.g. `0.025uatom`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GasPrice {
+	pub amount: f64,
+	pub denom: String,
+}
+
+impl FromStr for GasPrice {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let s = s.trim();
+		let split = s
+			.find(|c: char| !(c.is_ascii_digit() || c == '.'))
+			.ok_or_else(|| Error::Custom(format!("Gas price {s:?} has no denom")))?;
+		let (amount, denom) = s.split_at(split);
+		let amount = amount
+			.parse()
+			.map_err(|e| Error::Custom(format!("Invalid amount of gas price {s:?}: {e}")))?;
+		Ok(Self { amount, denom: denom.to_string() })
+	}
+}
+
+/// Parses comma separated gas prices, as in the `minimum-gas-prices` of the node config.
+pub fn parse_gas_prices(s: &str) -> Result<Vec<GasPrice>, Error> {
+	s.split(',')
+		.filter(|price| !price.trim().is_empty())
+		.map(GasPrice::from_str)
+		.collect()
+}
+
+/// Queries the minimum gas prices of the node at `grpc_url`.
+pub async fn query_min_gas_prices(grpc_url: Url) -> Result<Vec<GasPrice>, Error> {
+	let mut client = ServiceClient::connect(grpc_url.to_string()).await.map_err(Error::from)?;
+	let response = client.config(ConfigRequest {}).await.map_err(Error::from)?.into_inner();
+	parse_gas_prices(&response.minimum_gas_price)
+}
+
+/// Whether the node rejected a transaction because its fee was too low.
+pub fn is_insufficient_fee(error: &Error) -> bool {
+	matches!(
+		error,
+		Error::TxFailed { codespace, code: INSUFFICIENT_FEE, .. } if codespace == SDK_CODESPACE
+	)
+}
+
+/// Extracts the fee required in `denom` from an insufficient fee error of the Cosmos SDK, e.g.
+/// `insufficient fees; got: 4000stake required: 6000stake,10uatom: insufficient fee`.
+pub fn required_fee(error: &str, denom: &str) -> Option<u128> {
+	const MARKER: &str = "required: ";
+	let start = error.find(MARKER)? + MARKER.len();
+	let coins = error[start..].split(|c: char| c == ':' || c.is_whitespace()).next()?;
+	coins.split(',').find_map(|coin| {
+		let split = coin.find(|c: char| !c.is_ascii_digit())?;
+		let (amount, coin_denom) = coin.split_at(split);
+		(coin_denom == denom).then(|| amount.parse().ok()).flatten()
+	})
+}
+
+/// Fee parameters of a cosmos client beyond the static fee of its config, shared by its clones.
+#[derive(Debug, Clone, Default)]
+pub struct FeeState {
+	/// Gas price discovery, the static fee is used without it
+	pub gas_price: Option<GasPriceConfig>,
+	/// Fees tried in order after the primary one when the relayer can't afford the fee
+	pub fallback_fees: Vec<FallbackFee>,
+	/// Gas prices, from the node and raised by rejected fees
+	gas_prices: Arc<RwLock<Vec<GasPrice>>>,
+}
+
+impl FeeState {
+	pub fn new(gas_price: Option<GasPriceConfig>, fallback_fees: Vec<FallbackFee>) -> Self {
+		Self { gas_price, fallback_fees, gas_prices: Default::default() }
+	}
+
+	pub fn gas_prices(&self) -> Vec<GasPrice> {
+		self.gas_prices.read().unwrap().clone()
+	}
+
+	pub fn set_gas_prices(&self, gas_prices: Vec<GasPrice>) {
+		*self.gas_prices.write().unwrap() = gas_prices;
+	}
+
+	pub fn gas_price(&self, denom: &str) -> Option<f64> {
+		self.gas_prices
+			.read()
+			.unwrap()
+			.iter()
+			.find(|p| p.denom == denom)
+			.map(|p| p.amount)
+	}
+
+	/// The fee paying for `gas_used` in `denom`, if gas price discovery is enabled and the price
+	/// of `denom` is known.
+	pub fn fee_for_gas(&self, denom: &str, gas_used: u64) -> Option<Fee> {
+		let config = self.gas_price.as_ref()?;
+		let price = self.gas_price(denom)?;
+		let gas_limit = (gas_used as f64 * config.gas_multiplier).ceil() as u64;
+		let amount = (gas_limit as f64 * price).ceil() as u128;
+		Some(fee(denom, amount, gas_limit))
+	}
+
+	/// The fees of a transaction using `gas_used`, in the order they're tried: the `primary` fee
+	/// then the fallback fees, each priced at the gas price of its denom if it's known and paying
+	/// its own static amount with `gas_limit` otherwise.
+	pub fn fees_for_gas(&self, primary: &FallbackFee, gas_limit: u64, gas_used: u64) -> Vec<Fee> {
+		std::iter::once(primary)
+			.chain(&self.fallback_fees)
+			.map(|static_fee| {
+				self.fee_for_gas(&static_fee.denom, gas_used).unwrap_or_else(|| Fee {
+					amount: vec![Coin {
+						denom: static_fee.denom.clone(),
+						amount: static_fee.amount.clone(),
+					}],
+					gas_limit,
+					payer: "".to_string(),
+					granter: "".to_string(),
+				})
+			})
+			.collect()
+	}
+
+	/// Re-estimates a `fee` rejected with `error`: the fee required by the node if the error
+	/// tells it, the fee raised by [`FEE_BUMP`] otherwise. The gas price of the denom is raised
+	/// accordingly, for the following transactions.
+	pub fn bump(&self, fee: &Fee, error: &str) -> Fee {
+		let Some(coin) = fee.amount.first() else { return fee.clone() };
+		let amount = coin.amount.parse::<u128>().unwrap_or_default();
+		let bumped = (amount as f64 * FEE_BUMP).ceil() as u128;
+		let bumped = match required_fee(error, &coin.denom) {
+			Some(required) if required > amount => required,
+			_ => bumped.max(amount + 1),
+		};
+
+		if fee.gas_limit > 0 {
+			let price = bumped as f64 / fee.gas_limit as f64;
+			let mut gas_prices = self.gas_prices.write().unwrap();
+			match gas_prices.iter_mut().find(|p| p.denom == coin.denom) {
+				Some(gas_price) => gas_price.amount = gas_price.amount.max(price),
+				None => gas_prices.push(GasPrice { amount: price, denom: coin.denom.clone() }),
+			}
+		}
+		self::fee(&coin.denom, bumped, fee.gas_limit)
+	}
+
+	/// Refreshes the gas prices from the node every `refresh_interval_secs`.
+	pub async fn refresh_gas_prices(self, grpc_url: Url) {
+		let interval = self.gas_price.as_ref().map(|config| config.refresh_interval_secs);
+		let interval = Duration::from_secs(interval.unwrap_or_else(default_refresh_interval_secs));
+		loop {
+			match query_min_gas_prices(grpc_url.clone()).await {
+				Ok(gas_prices) => {
+					log::debug!(target: "hyperspace_cosmos", "Minimum gas prices: {gas_prices:?}");
+					self.set_gas_prices(gas_prices);
+				},
+				Err(e) =>
+					log::warn!(target: "hyperspace_cosmos", "Failed to refresh the gas prices: {e}"),
+			}
+			tokio::time::sleep(interval).await;
+		}
+	}
+}
+
+pub fn fee(denom: &str, amount: u128, gas_limit: u64) -> Fee {
+	Fee {
+		amount: vec![Coin { denom: denom.to_string(), amount: amount.to_string() }],
+		gas_limit,
+		payer: "".to_string(),
+		granter: "".to_string(),
+	}
+}
+
+/// Submits a transaction paying `fee`, and once more with a re-estimated fee if the node
+/// rejected it as insufficient.
+pub async fn submit_with_fee_retry<T, F, Fut>(
+	fees: &FeeState,
+	fee: Fee,
+	mut submit: F,
+) -> Result<T, Error>
+where
+	F: FnMut(Fee) -> Fut,
+	Fut: Future<Output = Result<T, Error>>,
+{
+	let error = match submit(fee.clone()).await {
+		Err(e) if is_insufficient_fee(&e) => e.to_string(),
+		result => return result,
+	};
+	let fee = fees.bump(&fee, &error);
+	log::warn!(target: "hyperspace_cosmos", "Fee was insufficient, retrying with {:?}: {error}", fee.amount);
+	submit(fee).await
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::Mutex;
+
+	fn rejected(code: u32, log: &str) -> Error {
+		Error::TxFailed {
+			hash: "ABCD".to_string(),
+			codespace: SDK_CODESPACE.to_string(),
+			code,
+			log: log.to_string(),
+		}
+	}
+
+	fn amount(fee: &Fee) -> (&str, &str) {
+		(fee.amount[0].amount.as_str(), fee.amount[0].denom.as_str())
+	}
+
+	#[test]
+	fn gas_prices_are_parsed_from_the_node_config() {
+		assert_eq!(
+			parse_gas_prices("0.025uatom,1stake").unwrap(),
+			vec![
+				GasPrice { amount: 0.025, denom: "uatom".to_string() },
+				GasPrice { amount: 1.0, denom: "stake".to_string() },
+			]
+		);
+		assert_eq!(parse_gas_prices("").unwrap(), vec![]);
+		assert!(parse_gas_prices("0.025").is_err());
+	}
+
+	#[test]
+	fn fees_pay_the_simulated_gas_at_the_gas_price() {
+		let fees = FeeState::new(Some(GasPriceConfig::default()), vec![]);
+		assert_eq!(fees.fee_for_gas("stake", 100_000), None);
+
+		fees.set_gas_prices(parse_gas_prices("0.025stake").unwrap());
+		let fee = fees.fee_for_gas("stake", 100_000).unwrap();
+		assert_eq!(fee.gas_limit, 110_000);
+		assert_eq!(amount(&fee), ("2750", "stake"));
+
+		// without discovery the static fee is used
+		let fees = FeeState::new(None, vec![]);
+		fees.set_gas_prices(parse_gas_prices("0.025stake").unwrap());
+		assert_eq!(fees.fee_for_gas("stake", 100_000), None);
+	}
+
+	#[test]
+	fn fallback_denoms_pay_their_own_amount() {
+		let primary = FallbackFee { denom: "stake".to_string(), amount: "4000".to_string() };
+		let fallback = FallbackFee { denom: "uatom".to_string(), amount: "25".to_string() };
+		let fees = FeeState::new(None, vec![fallback]);
+		let candidates = fees.fees_for_gas(&primary, 200_000, 100_000);
+		assert_eq!(
+			candidates.iter().map(amount).collect::<Vec<_>>(),
+			[("4000", "stake"), ("25", "uatom")]
+		);
+		assert!(candidates.iter().all(|fee| fee.gas_limit == 200_000));
+
+		// a denom priced by the node pays the simulated gas instead
+		let fees = FeeState::new(Some(GasPriceConfig::default()), fees.fallback_fees);
+		fees.set_gas_prices(parse_gas_prices("0.025stake").unwrap());
+		let candidates = fees.fees_for_gas(&primary, 200_000, 100_000);
+		assert_eq!(
+			candidates.iter().map(amount).collect::<Vec<_>>(),
+			[("2750", "stake"), ("25", "uatom")]
+		);
+	}
+
+	#[test]
+	fn required_fees_are_parsed_from_errors() {
+		let error =
+			"insufficient fees; got: 4000stake required: 6000stake,10uatom: insufficient fee";
+		assert_eq!(required_fee(error, "stake"), Some(6000));
+		assert_eq!(required_fee(error, "uatom"), Some(10));
+		assert_eq!(required_fee(error, "uosmo"), None);
+		assert_eq!(required_fee("out of gas", "stake"), None);
+	}
+
+	#[tokio::test]
+	async fn insufficient_fees_are_retried_once_with_the_bumped_fee() {
+		let fees = FeeState::new(Some(GasPriceConfig::default()), vec![]);
+		let submitted = Mutex::new(vec![]);
+		let result = submit_with_fee_retry(&fees, fee("stake", 4000, 100_000), |fee| {
+			submitted.lock().unwrap().push(fee);
+			let first = submitted.lock().unwrap().len() == 1;
+			async move {
+				if first {
+					Err(rejected(
+						INSUFFICIENT_FEE,
+						"insufficient fees; got: 4000stake required: 6000stake: insufficient fee",
+					))
+				} else {
+					Ok(())
+				}
+			}
+		})
+		.await;
+
+		assert!(result.is_ok());
+		let submitted = submitted.into_inner().unwrap();
+		assert_eq!(
+			submitted.iter().map(amount).collect::<Vec<_>>(),
+			[("4000", "stake"), ("6000", "stake")]
+		);
+		assert_eq!(submitted[1].gas_limit, 100_000);
+		// the following transactions pay the raised price
+		assert_eq!(fees.gas_price("stake"), Some(0.06));
+	}
+
+	#[tokio::test]
+	async fn fees_are_bumped_when_the_required_fee_is_unknown() {
+		let fees = FeeState::default();
+		let submitted = Mutex::new(vec![]);
+		let result: Result<(), _> =
+			submit_with_fee_retry(&fees, fee("stake", 4000, 100_000), |fee| {
+				submitted.lock().unwrap().push(fee);
+				async { Err(rejected(INSUFFICIENT_FEE, "insufficient fee")) }
+			})
+			.await;
+
+		// a single retry
+		assert!(result.is_err());
+		let submitted = submitted.into_inner().unwrap();
+		assert_eq!(
+			submitted.iter().map(amount).collect::<Vec<_>>(),
+			[("4000", "stake"), ("6000", "stake")]
+		);
+	}
+
+	#[tokio::test]
+	async fn other_errors_are_not_retried() {
+		let fees = FeeState::default();
+		let mut calls = 0;
+		let result: Result<(), _> =
+			submit_with_fee_retry(&fees, fee("stake", 4000, 100_000), |_| {
+				calls += 1;
+				async { Err(rejected(11, "out of gas")) }
+			})
+			.await;
+		assert!(result.is_err());
+		assert_eq!(calls, 1);
+	}
+}
@@ -1,8 +1,15 @@
-use super::client::CosmosClient;
+use super::client::{CosmosClient, MnemonicEntry, SignerAccount};
 use bech32::{ToBase32, Variant};
 use bip32::{XPrv as ExtendedPrivateKey, XPub as ExtendedPublicKey};
-use primitives::{error::Error, KeyProvider};
-use std::str::FromStr;
+use k256::ecdsa::{signature::Signer as _, Signature, SigningKey};
+use primitives::{
+	error::Error,
+	signer::{KeyRotation, KeySource, RemoteSigner, TransactionSigner},
+	KeyProvider,
+};
+use ripemd::Ripemd160;
+use sha2::{Digest, Sha256};
+use std::{str::FromStr, sync::Arc};
 use tendermint::account::Id as AccountId;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -28,21 +35,89 @@ impl KeyEntry {
 	}
 }
 
-impl<H> KeyProvider for CosmosClient<H> {
+#[async_trait::async_trait]
+impl TransactionSigner for KeyEntry {
+	fn public_key(&self) -> Vec<u8> {
+		self.public_key.public_key().to_bytes().to_vec()
+	}
+
+	async fn sign(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+		let private_key_bytes = self.private_key.private_key().to_bytes();
+		let signing_key = SigningKey::from_bytes(private_key_bytes.as_slice())
+			.map_err(|e| Error::from(e.to_string()))?;
+		let signature: Signature = signing_key.sign(payload);
+		Ok(signature.as_ref().to_vec())
+	}
+}
+
+/// Returns the bech32 account and the address of a compressed secp256k1 public key.
+pub fn account_from_public_key(
+	prefix: &str,
+	public_key: &[u8],
+) -> Result<(String, Vec<u8>), Error> {
+	k256::PublicKey::from_sec1_bytes(public_key)
+		.map_err(|e| Error::from(format!("Invalid secp256k1 public key: {e}")))?;
+	let public_key_hash: [u8; 20] = Ripemd160::digest(Sha256::digest(public_key)).into();
+	let account = bech32::encode(prefix, public_key_hash.to_base32(), Variant::Bech32)
+		.map_err(|e| Error::from(format!("Could not encode account id {e}")))?;
+	Ok((account, public_key_hash.into()))
+}
+
+#[async_trait::async_trait]
+impl<H: Clone + Send + Sync + 'static> KeyProvider for CosmosClient<H> {
 	fn account_id(&self) -> ibc::signer::Signer {
-		let key_entry = self.keybase.clone();
-		let address = hex::encode(key_entry.address);
-		let account = AccountId::from_str(address.as_str())
-			.map_err(|e| Error::from(format!("Could not parse account id {e}")))
-			.unwrap();
-		let bech32 =
-			bech32::encode(self.account_prefix.as_str(), account.to_base32(), Variant::Bech32)
-				.map_err(|e| Error::from(format!("Could not encode account id {e}")))
-				.unwrap();
-
-		bech32
-			.parse()
-			.map_err(|e| Error::from(format!("Could not parse account id {e}")))
-			.unwrap()
+		account_id(&self.account_prefix, &self.signer.account())
+	}
+
+	/// The account of the new key is checked to hold at least `min_balance` of the fee denom.
+	async fn rotate_key(
+		&self,
+		key_source: &KeySource,
+		min_balance: u128,
+	) -> Result<KeyRotation, Error> {
+		let signer: Arc<dyn TransactionSigner> = match key_source {
+			KeySource::Remote(remote) => Arc::new(RemoteSigner::connect(remote.clone()).await?),
+			_ => Arc::new(
+				KeyEntry::try_from(MnemonicEntry {
+					mnemonic: key_source.secret()?,
+					prefix: self.account_prefix.clone(),
+				})
+				.map_err(|e| Error::Custom(e.to_string()))?,
+			),
+		};
+		let (account, address) =
+			account_from_public_key(&self.account_prefix, &signer.public_key())?;
+		let account = SignerAccount { account, address };
+		let new_account = account_id(&self.account_prefix, &account);
+		let balance = self
+			.query_balance(account.account.clone(), &self.fee_denom)
+			.await
+			.map_err(|e| Error::Custom(e.to_string()))?;
+		let balance = balance.amount.parse::<u128>().map_err(|e| {
+			Error::Custom(format!("Invalid balance {} of {new_account}: {e}", balance.amount))
+		})?;
+		if balance < min_balance {
+			return Err(Error::Custom(format!(
+				"Account {new_account} holds {balance}{}, less than the minimum of {min_balance}",
+				self.fee_denom
+			)))
+		}
+		let old_account = self.signer.rotate(signer, account);
+		Ok(KeyRotation { old_account: account_id(&self.account_prefix, &old_account), new_account })
 	}
 }
+
+fn account_id(account_prefix: &str, account: &SignerAccount) -> ibc::signer::Signer {
+	let address = hex::encode(&account.address);
+	let account = AccountId::from_str(address.as_str())
+		.map_err(|e| Error::from(format!("Could not parse account id {e}")))
+		.unwrap();
+	let bech32 = bech32::encode(account_prefix, account.to_base32(), Variant::Bech32)
+		.map_err(|e| Error::from(format!("Could not encode account id {e}")))
+		.unwrap();
+
+	bech32
+		.parse()
+		.map_err(|e| Error::from(format!("Could not parse account id {e}")))
+		.unwrap()
+}
@@ -0,0 +1,180 @@
+//! Latest height of the chain, kept up to date by a `NewBlock` websocket subscription so that
+//! it doesn't need to be queried over RPC for every event.
+
+use futures::{Future, StreamExt};
+use ibc::{
+	core::{ics02_client::height::Height, ics24_host::identifier::ChainId},
+	timestamp::Timestamp,
+};
+use std::{sync::Arc, time::Duration};
+use tendermint_rpc::{
+	event::EventData,
+	query::{EventType, Query},
+	SubscriptionClient, Url, WebSocketClient,
+};
+use tokio::{sync::watch, time::Instant};
+
+/// Delay before resubscribing after the websocket connection dropped.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy)]
+struct LatestHeight {
+	height: Height,
+	timestamp: Timestamp,
+	updated_at: Instant,
+}
+
+/// Latest height and timestamp of the chain, shared by all the clones of a client.
+#[derive(Debug, Clone)]
+pub struct LatestHeightCache {
+	sender: Arc<watch::Sender<Option<LatestHeight>>>,
+	/// How long a height is used after it was received, [`Duration::ZERO`] disables the cache.
+	max_staleness: Duration,
+}
+
+impl LatestHeightCache {
+	pub fn new(max_staleness: Duration) -> Self {
+		let (sender, _) = watch::channel(None);
+		Self { sender: Arc::new(sender), max_staleness }
+	}
+
+	/// Records the latest height, unless a higher one is known already.
+	pub fn update(&self, height: Height, timestamp: Timestamp) {
+		let updated_at = Instant::now();
+		self.sender.send_if_modified(|latest| match latest {
+			Some(latest) if latest.height > height => false,
+			_ => {
+				*latest = Some(LatestHeight { height, timestamp, updated_at });
+				true
+			},
+		});
+	}
+
+	/// Returns the latest height and timestamp, if they were received recently enough.
+	pub fn get(&self) -> Option<(Height, Timestamp)> {
+		let latest = (*self.sender.borrow())?;
+		(latest.updated_at.elapsed() < self.max_staleness)
+			.then_some((latest.height, latest.timestamp))
+	}
+
+	/// Returns the latest height and timestamp, or the result of `query` if they're stale.
+	pub async fn get_or_query<F, E>(&self, query: F) -> Result<(Height, Timestamp), E>
+	where
+		F: Future<Output = Result<(Height, Timestamp), E>>,
+	{
+		if let Some(latest) = self.get() {
+			return Ok(latest)
+		}
+		let (height, timestamp) = query.await?;
+		self.update(height, timestamp);
+		Ok((height, timestamp))
+	}
+
+	/// Follows the blocks produced by the chain at `websocket_url` for as long as the cache is
+	/// used, resubscribing whenever the connection drops.
+	pub async fn follow_new_blocks(
+		self,
+		websocket_url: Url,
+		chain_id: ChainId,
+	) -> Result<(), tendermint_rpc::Error> {
+		let revision_number = ChainId::chain_version(chain_id.as_str());
+		// stops once all the clients are dropped
+		while Arc::strong_count(&self.sender) > 1 {
+			if let Err(e) = self.follow_subscription(&websocket_url, revision_number).await {
+				log::warn!(target: "hyperspace_cosmos", "New blocks subscription of {chain_id} failed: {e}, resubscribing in {RECONNECT_DELAY:?}");
+			}
+			tokio::time::sleep(RECONNECT_DELAY).await;
+		}
+		Ok(())
+	}
+
+	async fn follow_subscription(
+		&self,
+		websocket_url: &Url,
+		revision_number: u64,
+	) -> Result<(), tendermint_rpc::Error> {
+		let (client, driver) = WebSocketClient::new(websocket_url.clone()).await?;
+		let driver = tokio::spawn(driver.run());
+		let result = async {
+			let mut subscription = client.subscribe(Query::from(EventType::NewBlock)).await?;
+			while let Some(event) = subscription.next().await {
+				if let EventData::NewBlock { block: Some(block), .. } = event?.data {
+					let height = Height::new(revision_number, block.header.height.value());
+					self.update(height, block.header.time.into());
+				}
+			}
+			Ok(())
+		}
+		.await;
+		client.close().ok();
+		driver.abort();
+		result
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::atomic::{AtomicUsize, Ordering};
+
+	/// Number of `latest_height_and_timestamp` calls made by the relay loop per finality event.
+	const CALLS_PER_EVENT: usize = 5;
+
+	async fn latest_height(
+		cache: &LatestHeightCache,
+		queries: &AtomicUsize,
+	) -> (Height, Timestamp) {
+		cache
+			.get_or_query(async {
+				queries.fetch_add(1, Ordering::SeqCst);
+				Ok::<_, ()>((Height::new(1, 1), Timestamp::from_nanoseconds(1).unwrap()))
+			})
+			.await
+			.unwrap()
+	}
+
+	#[tokio::test]
+	async fn new_blocks_replace_height_queries() {
+		const EVENTS: u64 = 100;
+		let cache = LatestHeightCache::new(Duration::from_secs(60));
+		let queries = AtomicUsize::new(0);
+
+		// the first call happens before any block is received
+		assert_eq!(latest_height(&cache, &queries).await.0, Height::new(1, 1));
+		for block in 2..EVENTS + 2 {
+			cache.update(Height::new(1, block), Timestamp::from_nanoseconds(block).unwrap());
+			for _ in 0..CALLS_PER_EVENT {
+				assert_eq!(latest_height(&cache, &queries).await.0, Height::new(1, block));
+			}
+		}
+		assert_eq!(queries.load(Ordering::SeqCst), 1);
+	}
+
+	#[tokio::test]
+	async fn stale_heights_are_queried() {
+		let cache = LatestHeightCache::new(Duration::from_millis(50));
+		let queries = AtomicUsize::new(0);
+		cache.update(Height::new(1, 10), Timestamp::from_nanoseconds(10).unwrap());
+		assert_eq!(latest_height(&cache, &queries).await.0, Height::new(1, 10));
+		assert_eq!(queries.load(Ordering::SeqCst), 0);
+
+		// the subscription stopped delivering blocks
+		tokio::time::sleep(Duration::from_millis(100)).await;
+		assert_eq!(cache.get(), None);
+		latest_height(&cache, &queries).await;
+		assert_eq!(queries.load(Ordering::SeqCst), 1);
+
+		// the cache is disabled without a maximum staleness
+		let cache = LatestHeightCache::new(Duration::ZERO);
+		cache.update(Height::new(1, 10), Timestamp::from_nanoseconds(10).unwrap());
+		assert_eq!(cache.get(), None);
+	}
+
+	#[test]
+	fn heights_never_go_backwards() {
+		let cache = LatestHeightCache::new(Duration::from_secs(60));
+		cache.update(Height::new(1, 10), Timestamp::from_nanoseconds(10).unwrap());
+		cache.update(Height::new(1, 9), Timestamp::from_nanoseconds(9).unwrap());
+		assert_eq!(cache.get().map(|(height, _)| height), Some(Height::new(1, 10)));
+	}
+}
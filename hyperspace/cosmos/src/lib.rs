@@ -16,12 +16,16 @@ use ibc::core::ics02_client::height::Height;
 
 pub mod chain;
 pub mod client;
+pub mod consensus_heights;
 pub mod encode;
 pub mod error;
 pub mod events;
+pub mod fees;
 pub mod key_provider;
+pub mod latest_height;
 pub mod light_client;
 pub mod provider;
+pub mod query;
 #[cfg(any(test, feature = "testing"))]
 pub mod test_provider;
 pub mod tx;
@@ -1,10 +1,13 @@
 //! This section mainly has been ported from `InformalSystems/hermes/relayer/src/light_client`
 use crate::error::Error;
 use ibc::Height;
-use ics07_tendermint::{client_state::ClientState, ProdVerifier};
+use ics07_tendermint::{
+	client_message::Header, client_state::ClientState, consensus_state::ConsensusState,
+	ProdVerifier,
+};
 use pallet_ibc::light_clients::HostFunctionsManager;
-use std::time::Duration;
-use tendermint::trust_threshold::TrustThresholdFraction;
+use std::{str::FromStr, time::Duration};
+use tendermint::{trust_threshold::TrustThresholdFraction, Time};
 use tendermint_light_client::{
 	components::{
 		self,
@@ -16,8 +19,10 @@ use tendermint_light_client::{
 };
 use tendermint_light_client_verifier::{
 	options::Options as TmOptions,
-	types::{Height as TMHeight, LightBlock, PeerId, Status},
-	PredicateVerifier,
+	types::{
+		Height as TMHeight, LightBlock, PeerId, Status, TrustedBlockState, UntrustedBlockState,
+	},
+	PredicateVerifier, Verdict, Verifier,
 };
 use tendermint_rpc::{Client, HttpClient, Url};
 
@@ -92,3 +97,60 @@ impl LightClient {
 		Ok(target)
 	}
 }
+
+/// Verifies a tendermint `header` against the `client_state` of the client of the chain and its
+/// `trusted_consensus_state` at the trusted height of the header, as the client does when the
+/// header is submitted at `now`.
+pub fn verify_header(
+	client_state: &ClientState<HostFunctionsManager>,
+	trusted_consensus_state: &ConsensusState,
+	header: &Header,
+	now: Time,
+) -> Result<(), Error> {
+	let height = header.height();
+	if height.revision_number != client_state.chain_id.version() ||
+		height.revision_number != header.trusted_height.revision_number
+	{
+		return Err(Error::from(format!(
+			"Revision mismatch: client is at revision {}, header at {height} trusts {}",
+			client_state.chain_id.version(),
+			header.trusted_height
+		)))
+	}
+	if trusted_consensus_state.next_validators_hash !=
+		header.trusted_validator_set.hash_with::<HostFunctionsManager>()
+	{
+		return Err(Error::from(format!(
+			"Trusted validator set of header at {height} doesn't match the consensus state at {}",
+			header.trusted_height
+		)))
+	}
+
+	let chain_id = tendermint::chain::Id::from_str(client_state.chain_id.as_str())?;
+	let trusted_state = TrustedBlockState {
+		chain_id: &chain_id,
+		header_time: trusted_consensus_state.timestamp,
+		height: TMHeight::try_from(header.trusted_height.revision_height)?,
+		next_validators: &header.trusted_validator_set,
+		next_validators_hash: trusted_consensus_state.next_validators_hash,
+	};
+	let untrusted_state = UntrustedBlockState {
+		signed_header: &header.signed_header,
+		validators: &header.validator_set,
+		// the next validators aren't part of the header
+		next_validators: None,
+	};
+	let options = client_state
+		.as_light_client_options()
+		.map_err(|e| Error::from(format!("Invalid client state: {e}")))?;
+
+	let verifier: ProdVerifier<HostFunctionsManager> = PredicateVerifier::default();
+	match verifier.verify(untrusted_state, trusted_state, &options, now) {
+		Verdict::Success => Ok(()),
+		Verdict::NotEnoughTrust(tally) => Err(Error::from(format!(
+			"Not enough trusted validators signed header at {height}: {tally}"
+		))),
+		Verdict::Invalid(detail) =>
+			Err(Error::from(format!("Header at {height} is invalid: {detail}"))),
+	}
+}
@@ -1,11 +1,11 @@
 use super::{
-	client::CosmosClient,
+	client::{store_query_path, CosmosClient},
 	events::{
 		event_is_type_channel, event_is_type_client, event_is_type_connection,
 		ibc_event_try_from_abci_event, IbcEventWithHeight,
 	},
 };
-use crate::error::Error;
+use crate::{error::Error, query::CosmosQueryBackend};
 use futures::{
 	stream::{self, select_all},
 	Stream, StreamExt,
@@ -17,7 +17,7 @@ use ibc::{
 			client_state::ClientType, events as ClientEvents,
 			msgs::update_client::MsgUpdateAnyClient, trust_threshold::TrustThreshold,
 		},
-		ics04_channel::packet::Sequence,
+		ics04_channel::{channel::IdentifiedChannelEnd, packet::Sequence},
 		ics23_commitment::{commitment::CommitmentPrefix, specs::ProofSpecs},
 		ics24_host::{
 			identifier::{ChainId, ChannelId, ClientId, ConnectionId, PortId},
@@ -36,21 +36,21 @@ use ibc::{
 };
 use ibc_primitives::PacketInfo as IbcPacketInfo;
 use ibc_proto::{
-	cosmos::{bank::v1beta1::QueryBalanceRequest, base::query::v1beta1::PageRequest},
 	google::protobuf::Any,
-	ibc::core::{
-		channel::v1::{
-			Channel, QueryChannelResponse, QueryChannelsRequest, QueryChannelsResponse,
-			QueryConnectionChannelsRequest, QueryNextSequenceReceiveResponse,
-			QueryPacketAcknowledgementResponse, QueryPacketAcknowledgementsRequest,
-			QueryPacketCommitmentResponse, QueryPacketCommitmentsRequest,
-			QueryPacketReceiptResponse, QueryUnreceivedAcksRequest, QueryUnreceivedPacketsRequest,
-		},
-		client::v1::{
-			QueryClientStateResponse, QueryClientStatesRequest, QueryConsensusStateResponse,
+	ibc::{
+		applications::{fee::v1::MsgRegisterCounterpartyPayee, transfer::v1::DenomTrace},
+		core::{
+			channel::v1::{
+				Channel, QueryChannelResponse, QueryChannelsResponse,
+				QueryNextSequenceReceiveResponse, QueryPacketAcknowledgementResponse,
+				QueryPacketCommitmentResponse, QueryPacketReceiptResponse,
+			},
+			client::v1::{QueryClientStateResponse, QueryConsensusStateResponse},
+			connection::v1::{ConnectionEnd, IdentifiedConnection, QueryConnectionResponse},
 		},
-		connection::v1::{
-			ConnectionEnd, IdentifiedConnection, QueryConnectionResponse, QueryConnectionsRequest,
+		lightclients::wasm::v1::{
+			query_client::QueryClient as WasmQueryClient, ClientState as RawWasmClientState,
+			WasmCodeQuery,
 		},
 	},
 };
@@ -58,15 +58,20 @@ use ibc_rpc::PacketInfo;
 use ics07_tendermint::{
 	client_message::ClientMessage, client_state::ClientState, consensus_state::ConsensusState,
 };
-use ics08_wasm::msg::MsgPushNewWasmCode;
+use ics08_wasm::{
+	client_state::WASM_CLIENT_STATE_TYPE_URL,
+	msg::{MsgMigrateContract, MsgPushNewWasmCode},
+};
 use pallet_ibc::light_clients::{
 	AnyClientMessage, AnyClientState, AnyConsensusState, HostFunctionsManager,
 };
 use primitives::{
-	filter_events_by_ids, mock::LocalClientTypes, Chain, IbcProvider, KeyProvider, UpdateType,
+	filter_events_by_ids, mock::LocalClientTypes, set_primary_connection_id, Chain, ClientStatus,
+	IbcProvider, KeyProvider, UpdateType, WasmUploadStatus,
 };
 use prost::Message;
 use rand::Rng;
+use sha2::{Digest, Sha256};
 use std::{
 	collections::{hash_map::Entry, HashMap, HashSet},
 	pin::Pin,
@@ -83,6 +88,8 @@ use tendermint_rpc::{
 };
 use tokio::{task::JoinSet, time::sleep};
 
+pub use primitives::any_registry::{PAY_PACKET_FEE_TYPE_URL, REGISTER_COUNTERPARTY_PAYEE_TYPE_URL};
+
 // At least one *mandatory* update should happen during that period
 // TODO: make it configurable
 pub const NUMBER_OF_BLOCKS_TO_PROCESS_PER_ITER: u64 = 500;
@@ -331,6 +338,17 @@ where
 		})
 	}
 
+	async fn query_client_status(
+		&self,
+		at: Height,
+		client_id: ClientId,
+	) -> Result<ClientStatus, Self::Error> {
+		let status = self.query_backend.client_status(at, &client_id).await?;
+		status
+			.parse()
+			.map_err(|e: primitives::error::Error| Error::Custom(e.to_string()))
+	}
+
 	async fn query_connection_end(
 		&self,
 		at: Height,
@@ -366,8 +384,18 @@ where
 		})
 	}
 
-	async fn query_proof(&self, at: Height, keys: Vec<Vec<u8>>) -> Result<Vec<u8>, Self::Error> {
-		let (_, proof) = self.query_path(keys[0].clone(), at, true).await?;
+	async fn query_proof(
+		&self,
+		at: Height,
+		prefix: &CommitmentPrefix,
+		keys: Vec<Vec<u8>>,
+	) -> Result<Vec<u8>, Self::Error> {
+		let (_, proof) = if *prefix == self.commitment_prefix {
+			self.query_path(keys[0].clone(), at, true).await?
+		} else {
+			self.query_store_path(&store_query_path(prefix), keys[0].clone(), at, true)
+				.await?
+		};
 		Ok(proof)
 	}
 
@@ -462,39 +490,7 @@ where
 	}
 
 	async fn latest_height_and_timestamp(&self) -> Result<(Height, Timestamp), Self::Error> {
-		// We cannot rely on `/status` endpoint to provide details about the latest block.
-		// Instead, we need to pull block height via `/abci_info` and then fetch block
-		// metadata at the given height via `/blockchain` endpoint.
-		let abci_info = self
-			.rpc_http_client
-			.abci_info()
-			.await
-			.map_err(|e| Error::RpcError(format!("{e:?}")))?;
-
-		// Query `/blockchain` endpoint to pull the block metadata corresponding to
-		// the latest block that the application committed.
-		// TODO: Replace this query with `/header`, once it's available.
-		//  https://github.com/informalsystems/tendermint-rs/pull/1101
-		let blocks = self
-			.rpc_http_client
-			.blockchain(abci_info.last_block_height, abci_info.last_block_height)
-			.await
-			.map_err(|e| {
-				Error::RpcError(format!(
-					"failed to query /blockchain endpoint for latest app. block: {e:?}"
-				))
-			})?;
-
-		let latest_app_block = blocks.block_metas.first().ok_or_else(|| {
-			Error::Custom("/blockchain endpoint for latest app. block".to_owned())
-		})?;
-
-		let height = Height::new(
-			ChainId::chain_version(latest_app_block.header.chain_id.as_str()),
-			u64::from(abci_info.last_block_height),
-		);
-		let timestamp = latest_app_block.header.time.into();
-		Ok((height, timestamp))
+		self.latest_height.get_or_query(self.query_latest_height_and_timestamp()).await
 	}
 
 	async fn query_packet_commitments(
@@ -503,28 +499,7 @@ where
 		channel_id: ChannelId,
 		port_id: PortId,
 	) -> Result<Vec<u64>, Self::Error> {
-		let mut grpc_client =
-			ibc_proto::ibc::core::channel::v1::query_client::QueryClient::connect(
-				self.grpc_url().to_string(),
-			)
-			.await
-			.map_err(|e| Error::from(e.to_string()))?;
-
-		let request = QueryPacketCommitmentsRequest {
-			port_id: port_id.to_string(),
-			channel_id: channel_id.to_string(),
-			pagination: Some(PageRequest { limit: u32::MAX as _, ..Default::default() }),
-		};
-		let request = tonic::Request::new(request);
-		let response = grpc_client
-			.packet_commitments(request)
-			.await
-			.map_err(|e| Error::from(e.to_string()))?
-			.into_inner();
-
-		let commitment_sequences: Vec<u64> =
-			response.commitments.into_iter().map(|v| v.sequence).collect();
-		Ok(commitment_sequences)
+		self.query_backend.packet_commitments(&port_id, &channel_id).await
 	}
 
 	async fn query_packet_acknowledgements(
@@ -539,30 +514,7 @@ where
 			channel_id,
 			port_id
 		);
-		let mut grpc_client =
-			ibc_proto::ibc::core::channel::v1::query_client::QueryClient::connect(
-				self.grpc_url().to_string(),
-			)
-			.await
-			.map_err(|e| Error::from(e.to_string()))?;
-
-		let request = QueryPacketAcknowledgementsRequest {
-			port_id: port_id.to_string(),
-			channel_id: channel_id.to_string(),
-			packet_commitment_sequences: vec![],
-			pagination: Some(PageRequest { limit: u32::MAX as _, ..Default::default() }),
-		};
-		let request = tonic::Request::new(request);
-		let response = grpc_client
-			.packet_acknowledgements(request)
-			.await
-			.map_err(|e| Error::from(e.to_string()))?
-			.into_inner();
-
-		let commitment_sequences: Vec<u64> =
-			response.acknowledgements.into_iter().map(|v| v.sequence).collect();
-
-		Ok(commitment_sequences)
+		self.query_backend.packet_acknowledgements(&port_id, &channel_id).await
 	}
 
 	async fn query_unreceived_packets(
@@ -572,28 +524,7 @@ where
 		port_id: PortId,
 		seqs: Vec<u64>,
 	) -> Result<Vec<u64>, Self::Error> {
-		let mut grpc_client =
-			ibc_proto::ibc::core::channel::v1::query_client::QueryClient::connect(
-				self.grpc_url().to_string(),
-			)
-			.await
-			.map_err(|e| Error::from(e.to_string()))?;
-
-		let request = QueryUnreceivedPacketsRequest {
-			port_id: port_id.to_string(),
-			channel_id: channel_id.to_string(),
-			packet_commitment_sequences: seqs,
-		};
-		let request = tonic::Request::new(request);
-		let response = grpc_client
-			.unreceived_packets(request)
-			.await
-			.map_err(|e| Error::from(e.to_string()))?
-			.into_inner();
-
-		let commitment_sequences: Vec<u64> = response.sequences.into_iter().collect();
-
-		Ok(commitment_sequences)
+		self.query_backend.unreceived_packets(&port_id, &channel_id, &seqs).await
 	}
 
 	async fn query_unreceived_acknowledgements(
@@ -603,32 +534,21 @@ where
 		port_id: PortId,
 		seqs: Vec<u64>,
 	) -> Result<Vec<u64>, Self::Error> {
-		let mut grpc_client =
-			ibc_proto::ibc::core::channel::v1::query_client::QueryClient::connect(
-				self.grpc_url().to_string(),
-			)
+		self.query_backend
+			.unreceived_acknowledgements(&port_id, &channel_id, &seqs)
 			.await
-			.map_err(|e| Error::from(e.to_string()))?;
-
-		let request = QueryUnreceivedAcksRequest {
-			port_id: port_id.to_string(),
-			channel_id: channel_id.to_string(),
-			packet_ack_sequences: seqs,
-		};
-		let request = tonic::Request::new(request);
-		let response = grpc_client
-			.unreceived_acks(request)
-			.await
-			.map_err(|e| Error::from(e.to_string()))?
-			.into_inner();
-
-		let commitment_sequences: Vec<u64> = response.sequences.into_iter().collect();
-
-		Ok(commitment_sequences)
 	}
 
 	fn channel_whitelist(&self) -> HashSet<(ChannelId, PortId)> {
-		self.channel_whitelist.lock().unwrap().clone()
+		self.channel_whitelist.lock().unwrap().all()
+	}
+
+	fn is_channel_whitelisted_on(
+		&self,
+		channel: &(ChannelId, PortId),
+		connection_id: &ConnectionId,
+	) -> bool {
+		self.channel_whitelist.lock().unwrap().allows(channel, connection_id)
 	}
 
 	async fn query_connection_channels(
@@ -636,29 +556,7 @@ where
 		_at: Height,
 		connection_id: &ConnectionId,
 	) -> Result<QueryChannelsResponse, Self::Error> {
-		let mut grpc_client =
-			ibc_proto::ibc::core::channel::v1::query_client::QueryClient::connect(
-				self.grpc_url().to_string(),
-			)
-			.await
-			.map_err(|e| Error::from(format!("{e:?}")))?;
-		let request = tonic::Request::new(QueryConnectionChannelsRequest {
-			connection: connection_id.to_string(),
-			pagination: Some(PageRequest { limit: u32::MAX as _, ..Default::default() }),
-		});
-
-		let response = grpc_client
-			.connection_channels(request)
-			.await
-			.map_err(|e| Error::from(format!("{e:?}")))?
-			.into_inner();
-		let channels = QueryChannelsResponse {
-			channels: response.channels,
-			pagination: response.pagination,
-			height: response.height,
-		};
-
-		Ok(channels)
+		self.query_backend.connection_channels(connection_id).await
 	}
 
 	async fn query_send_packets(
@@ -693,7 +591,7 @@ where
 					    * error during the message processing) */
 				)
 				.await
-				.map_err(|e| Error::RpcError(format!("{e:?}")))?;
+				.map_err(Error::from)?;
 
 			for tx in response.txs {
 				for ev in &tx.tx_result.events {
@@ -771,7 +669,7 @@ where
 					    * of an error during the message processing) */
 				)
 				.await
-				.map_err(|e| Error::RpcError(format!("{e:?}")))?;
+				.map_err(Error::from)?;
 
 			for tx in response.txs {
 				for ev in &tx.tx_result.events {
@@ -846,17 +744,14 @@ where
 					Order::Ascending,
 				)
 				.await
-				.map_err(|e| Error::RpcError(format!("{e:?}")))?;
+				.map_err(Error::from)?;
 
 			for tx in response.txs {
 				for ev in &tx.tx_result.events {
 					let height = tx.height.value();
 					let ev =
 						ibc_event_try_from_abci_event(ev, Height::new(self.id().version(), height));
-					let timestamp = self
-						.query_timestamp_at(height)
-						.await
-						.map_err(|e| Error::RpcError(format!("{e:?}")))?;
+					let timestamp = self.query_timestamp_at(height).await?;
 					match ev {
 						Ok(IbcEvent::UpdateClient(e)) if e.client_id() == &client_id =>
 							return Ok((
@@ -873,7 +768,18 @@ where
 				}
 			}
 		}
-		Err(Error::from("not found".to_string()))
+		Err(Error::NotFound(format!("No update of client {client_id} found")))
+	}
+
+	async fn closest_consensus_height(
+		&self,
+		client_id: ClientId,
+		target: Height,
+	) -> Result<Option<Height>, Self::Error> {
+		let event_heights = self.query_consensus_heights_from_events(&client_id);
+		self.consensus_heights
+			.closest(&self.query_backend, &client_id, target, event_heights)
+			.await
 	}
 
 	async fn query_host_consensus_state_proof(
@@ -888,27 +794,7 @@ where
 		asset_id: Self::AssetId,
 	) -> Result<Vec<PrefixedCoin>, Self::Error> {
 		let denom = &asset_id;
-		let mut grpc_client = ibc_proto::cosmos::bank::v1beta1::query_client::QueryClient::connect(
-			self.grpc_url().to_string(),
-		)
-		.await
-		.map_err(|e| Error::from(format!("{e:?}")))?;
-
-		let request = tonic::Request::new(QueryBalanceRequest {
-			address: self.keybase.clone().account,
-			denom: denom.to_string(),
-		});
-
-		let response = grpc_client
-			.balance(request)
-			.await
-			.map(|r| r.into_inner())
-			.map_err(|e| Error::from(format!("{e:?}")))?;
-
-		// Querying for a balance might fail, i.e. if the account doesn't actually exist
-		let balance = response
-			.balance
-			.ok_or_else(|| Error::from(format!("No balance for denom {denom}")))?;
+		let balance = self.query_balance(self.signer.account().account, denom).await?;
 
 		Ok(vec![PrefixedCoin {
 			denom: PrefixedDenom {
@@ -919,10 +805,27 @@ where
 		}])
 	}
 
+	async fn query_relayer_balance(&self) -> Result<u128, Self::Error> {
+		let coins = self.query_ibc_balance(self.fee_denom.clone()).await?;
+		let amount = coins.first().map(|coin| coin.amount.as_u256()).unwrap_or_default();
+		Ok(u128::try_from(amount).unwrap_or(u128::MAX))
+	}
+
+	async fn query_denom_trace(&self, hash: String) -> Result<DenomTrace, Self::Error> {
+		self.query_backend.denom_trace(hash.trim_start_matches("ibc/")).await
+	}
+
 	fn connection_prefix(&self) -> CommitmentPrefix {
 		self.commitment_prefix.clone()
 	}
 
+	fn connection_prefix_of(&self, connection_id: &ConnectionId) -> CommitmentPrefix {
+		self.connection_prefixes
+			.get(connection_id)
+			.cloned()
+			.unwrap_or_else(|| self.connection_prefix())
+	}
+
 	fn client_id(&self) -> ClientId {
 		self.client_id()
 	}
@@ -932,20 +835,39 @@ where
 	}
 
 	fn connection_id(&self) -> Option<ConnectionId> {
-		self.connection_id.lock().unwrap().clone()
+		self.connection_ids.lock().unwrap().first().cloned()
+	}
+
+	fn connection_ids(&self) -> Vec<ConnectionId> {
+		self.connection_ids.lock().unwrap().clone()
 	}
 
 	/// Set the channel whitelist for the relayer task.
 	fn set_channel_whitelist(&mut self, channel_whitelist: HashSet<(ChannelId, PortId)>) {
-		*self.channel_whitelist.lock().unwrap() = channel_whitelist;
+		self.channel_whitelist.lock().unwrap().set(channel_whitelist);
 	}
 
 	fn add_channel_to_whitelist(&mut self, channel: (ChannelId, PortId)) {
 		self.channel_whitelist.lock().unwrap().insert(channel);
 	}
 
+	fn add_channel_to_connection_whitelist(
+		&mut self,
+		connection_id: ConnectionId,
+		channel: (ChannelId, PortId),
+	) {
+		self.channel_whitelist.lock().unwrap().insert_on(connection_id, channel);
+	}
+
 	fn set_connection_id(&mut self, connection_id: ConnectionId) {
-		*self.connection_id.lock().unwrap() = Some(connection_id);
+		set_primary_connection_id(&mut self.connection_ids.lock().unwrap(), connection_id);
+	}
+
+	fn add_connection_id(&mut self, connection_id: ConnectionId) {
+		let mut connection_ids = self.connection_ids.lock().unwrap();
+		if !connection_ids.contains(&connection_id) {
+			connection_ids.push(connection_id);
+		}
 	}
 
 	fn client_type(&self) -> ClientType {
@@ -955,67 +877,32 @@ where
 	async fn query_timestamp_at(&self, block_number: u64) -> Result<u64, Self::Error> {
 		let height = TmHeight::try_from(block_number)
 			.map_err(|e| Error::from(format!("Invalid block number: {e}")))?;
-		let response = self
-			.rpc_ws_client()
-			.block(height)
-			.await
-			.map_err(|e| Error::RpcError(e.to_string()))?;
+		let response = self.rpc_ws_client().block(height).await.map_err(Error::from)?;
 		let time: Timestamp = response.block.header.time.into();
 		Ok(time.nanoseconds())
 	}
 
 	async fn query_clients(&self) -> Result<Vec<ClientId>, Self::Error> {
-		let request = tonic::Request::new(QueryClientStatesRequest {
-			pagination: Some(PageRequest { limit: u32::MAX as _, ..Default::default() }),
-		});
-		let grpc_client = ibc_proto::ibc::core::client::v1::query_client::QueryClient::new(
-			self.grpc_client().clone(),
-		);
-		let response = grpc_client
-			.clone()
-			.client_states(request)
-			.await
-			.map_err(|e| {
-				Error::from(format!("Failed to query client states from grpc client: {e:?}"))
-			})?
-			.into_inner();
-
-		// Deserialize into domain type
-		let clients: Vec<ClientId> = response
-			.client_states
+		let clients = self
+			.query_backend
+			.client_ids()
+			.await?
 			.into_iter()
-			.filter_map(|cs| {
-				let id = ClientId::from_str(&cs.client_id).ok()?;
-				Some(id)
-			})
+			.filter_map(|client_id| ClientId::from_str(&client_id).ok())
 			.collect();
 		Ok(clients)
 	}
 
-	async fn query_channels(&self) -> Result<Vec<(ChannelId, PortId)>, Self::Error> {
-		let request = tonic::Request::new(QueryChannelsRequest {
-			pagination: Some(PageRequest { limit: u32::MAX as _, ..Default::default() }),
-		});
-		let mut grpc_client =
-			ibc_proto::ibc::core::channel::v1::query_client::QueryClient::connect(
-				self.grpc_url().to_string(),
-			)
-			.await
-			.map_err(|e| Error::from(format!("{e:?}")))?;
-		let response = grpc_client
-			.channels(request)
-			.await
-			.map_err(|e| Error::from(format!("{e:?}")))?
-			.into_inner()
-			.channels
+	async fn query_identified_channels(&self) -> Result<Vec<IdentifiedChannelEnd>, Self::Error> {
+		self.query_backend
+			.channels()
+			.await?
 			.into_iter()
-			.filter_map(|c| {
-				let id = ChannelId::from_str(&c.channel_id).ok()?;
-				let port_id = PortId::from_str(&c.port_id).ok()?;
-				Some((id, port_id))
+			.map(|channel| {
+				IdentifiedChannelEnd::try_from(channel)
+					.map_err(|e| Error::from(format!("Invalid channel: {e}")))
 			})
-			.collect::<Vec<_>>();
-		Ok(response)
+			.collect()
 	}
 
 	async fn query_connection_using_client(
@@ -1023,25 +910,10 @@ where
 		_height: u32,
 		client_id: String,
 	) -> Result<Vec<IdentifiedConnection>, Self::Error> {
-		let mut grpc_client =
-			ibc_proto::ibc::core::connection::v1::query_client::QueryClient::connect(
-				self.grpc_url().to_string(),
-			)
-			.await
-			.map_err(|e| Error::from(format!("{e:?}")))?;
-
-		let request = tonic::Request::new(QueryConnectionsRequest {
-			pagination: Some(PageRequest { limit: u32::MAX as _, ..Default::default() }),
-		});
-
-		let response = grpc_client
-			.connections(request)
-			.await
-			.map_err(|e| Error::from(format!("{e:?}")))?
-			.into_inner();
-
-		let connections = response
-			.connections
+		let connections = self
+			.query_backend
+			.connections()
+			.await?
 			.into_iter()
 			.filter(|conn| {
 				conn.client_id == client_id ||
@@ -1107,7 +979,7 @@ where
 					Order::Ascending,
 				)
 				.await
-				.map_err(|e| Error::from(format!("Failed to query tx hash: {e}")))?;
+				.map_err(Error::from)?;
 			match response.txs.into_iter().next() {
 				None => {
 					let elapsed = start_time.elapsed();
@@ -1174,7 +1046,7 @@ where
 					Order::Ascending,
 				)
 				.await
-				.map_err(|e| Error::from(format!("Failed to query tx hash: {e}")))?;
+				.map_err(Error::from)?;
 			match response.txs.into_iter().next() {
 				None => {
 					let elapsed = start_time.elapsed();
@@ -1242,7 +1114,7 @@ where
 					Order::Ascending,
 				)
 				.await
-				.map_err(|e| Error::from(format!("Failed to query tx hash: {e}")))?;
+				.map_err(Error::from)?;
 			match response.txs.into_iter().next() {
 				None => {
 					let elapsed = start_time.elapsed();
@@ -1291,7 +1163,14 @@ where
 		}
 	}
 
-	async fn upload_wasm(&self, wasm: Vec<u8>) -> Result<Vec<u8>, Self::Error> {
+	async fn upload_wasm(&self, wasm: Vec<u8>) -> Result<(Vec<u8>, WasmUploadStatus), Self::Error> {
+		let checksum = Sha256::digest(&wasm).to_vec();
+		if self.query_backend.wasm_code_stored(&checksum).await? {
+			let checksum_str = hex::encode(&checksum);
+			log::info!(target: "hyperspace_cosmos", "Wasm code {checksum_str} is already stored");
+			return Ok((checksum, WasmUploadStatus::AlreadyStored))
+		}
+
 		let msg = MsgPushNewWasmCode { signer: self.account_id(), code: wasm };
 		let hash = self.submit(vec![msg.into()]).await?;
 		let resp = self.wait_for_tx_result(hash).await?;
@@ -1329,7 +1208,98 @@ where
 		// 	Error::from(format!("Failed to upload wasm code to grpc endpoint: {:?}", e))
 		// })?;
 
-		Ok(code_id)
+		Ok((code_id, WasmUploadStatus::Uploaded))
+	}
+
+	async fn query_wasm_code_stored(&self, code_id: &[u8]) -> Result<bool, Self::Error> {
+		self.query_backend.wasm_code_stored(code_id).await
+	}
+
+	async fn migrate_wasm_client(
+		&mut self,
+		client_id: ClientId,
+		code_id: Vec<u8>,
+		migrate_msg: Vec<u8>,
+	) -> Result<(), Self::Error> {
+		let code_id_str = hex::encode(&code_id);
+		if !self.query_backend.wasm_code_stored(&code_id).await? {
+			return Err(Error::from(format!("Wasm code {code_id_str} isn't stored")))
+		}
+
+		let msg = MsgMigrateContract {
+			client_id: client_id.clone(),
+			code_id: code_id.clone(),
+			msg: migrate_msg,
+			signer: self.account_id(),
+		};
+		// 08-wasm only accepts the migration from its authority, the governance module, unless
+		// the chain lets anyone migrate the clients
+		let hash = self.submit(vec![msg.into()]).await?;
+		self.wait_for_tx_result(hash).await.map_err(|e| {
+			Error::from(format!(
+				"Failed to migrate {client_id} to wasm code {code_id_str}, the signer may not be allowed to migrate clients: {e}"
+			))
+		})?;
+
+		let (height, _) = self.latest_height_and_timestamp().await?;
+		let client_state =
+			self.query_client_state(height, client_id.clone())
+				.await?
+				.client_state
+				.ok_or_else(|| Error::NotFound(format!("Client {client_id} not found")))?;
+		match wasm_code_id(&client_state) {
+			Some(migrated_code_id) if migrated_code_id == code_id => {
+				log::info!(target: "hyperspace_cosmos", "Migrated {client_id} to wasm code {code_id_str}");
+				Ok(())
+			},
+			Some(migrated_code_id) => Err(Error::from(format!(
+				"Client {client_id} uses wasm code {} after its migration to {code_id_str}",
+				hex::encode(migrated_code_id)
+			))),
+			None => Err(Error::from(format!(
+				"Client {client_id} is a {} client, not a wasm one",
+				client_state.type_url
+			))),
+		}
+	}
+
+	fn register_counterparty_payee_msg(
+		&self,
+		channel_id: ChannelId,
+		port_id: PortId,
+		counterparty_payee: String,
+	) -> Result<Any, Self::Error> {
+		let msg = MsgRegisterCounterpartyPayee {
+			port_id: port_id.to_string(),
+			channel_id: channel_id.to_string(),
+			relayer: self.account_id().to_string(),
+			counterparty_payee,
+		};
+		Ok(Any {
+			type_url: REGISTER_COUNTERPARTY_PAYEE_TYPE_URL.to_string(),
+			value: msg.encode_to_vec(),
+		})
+	}
+}
+
+/// Returns the code id of a wasm `client_state`, or [`None`] if it isn't a wasm client.
+fn wasm_code_id(client_state: &Any) -> Option<Vec<u8>> {
+	if client_state.type_url != WASM_CLIENT_STATE_TYPE_URL {
+		return None
+	}
+	RawWasmClientState::decode(&*client_state.value).ok().map(|state| state.code_id)
+}
+
+/// Returns `true` if the 08-wasm module stores code with the given checksum.
+pub(crate) async fn query_wasm_code_stored(
+	grpc_client: tonic::transport::Channel,
+	checksum: &[u8],
+) -> Result<bool, Error> {
+	let request = tonic::Request::new(WasmCodeQuery { code_id: hex::encode(checksum) });
+	match WasmQueryClient::new(grpc_client).wasm_code(request).await {
+		Ok(response) => Ok(!response.into_inner().code.is_empty()),
+		Err(status) if status.code() == tonic::Code::NotFound => Ok(false),
+		Err(e) => Err(Error::from(e)),
 	}
 }
 
@@ -1349,9 +1319,7 @@ where
 			.rpc_http_client
 			.block_results(TmHeight::try_from(height)?)
 			.await
-			.map_err(|e| {
-			Error::from(format!("Failed to query block result for height {height:?}: {e:?}"))
-		})?;
+			.map_err(Error::from)?;
 
 		let tx_events = block_results
 			.txs_results
@@ -1373,11 +1341,10 @@ where
 					let is_filtered = filter_events_by_ids(
 						&ev,
 						&[self.client_id(), counterparty.client_id()],
-						&[self.connection_id(), counterparty.connection_id()]
-							.into_iter()
-							.flatten()
-							.collect::<Vec<_>>(),
+						&[self.connection_ids(), counterparty.connection_ids()].concat(),
 						&channel_and_port_ids,
+						self.common_state().auto_whitelist_channels ||
+							counterparty.common_state().auto_whitelist_channels,
 					);
 
 					if is_filtered {
@@ -1435,7 +1402,7 @@ impl<H: Clone + Send + Sync + 'static> CosmosClient<H> {
 					Order::Ascending,
 				)
 				.await
-				.map_err(|e| Error::from(format!("Failed to query tx hash: {e}")))?;
+				.map_err(Error::from)?;
 			match response.txs.into_iter().next() {
 				None => {
 					let elapsed = start_time.elapsed();
@@ -1472,3 +1439,81 @@ fn increment_proof_height(
 		..height
 	})
 }
+
+#[cfg(test)]
+mod tests {
+	use super::{query_wasm_code_stored, wasm_code_id};
+	use ibc_proto::{
+		google::protobuf::Any,
+		ibc::lightclients::wasm::v1::{
+			query_server::{Query, QueryServer},
+			ClientState as RawWasmClientState, WasmCodeQuery, WasmCodeResponse,
+		},
+	};
+	use ics08_wasm::client_state::WASM_CLIENT_STATE_TYPE_URL;
+	use prost::Message;
+	use sha2::{Digest, Sha256};
+	use tokio_stream::wrappers::TcpListenerStream;
+	use tonic::{transport::Channel, Request, Response, Status};
+
+	/// 08-wasm query service storing a single code.
+	struct MockWasmQuery {
+		code: Vec<u8>,
+	}
+
+	#[tonic::async_trait]
+	impl Query for MockWasmQuery {
+		async fn wasm_code(
+			&self,
+			request: Request<WasmCodeQuery>,
+		) -> Result<Response<WasmCodeResponse>, Status> {
+			if request.into_inner().code_id != hex::encode(Sha256::digest(&self.code)) {
+				return Err(Status::not_found("wasm code not found"))
+			}
+			Ok(Response::new(WasmCodeResponse { code: self.code.clone() }))
+		}
+	}
+
+	async fn serve(code: Vec<u8>) -> Channel {
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let addr = listener.local_addr().unwrap();
+		tokio::spawn(
+			tonic::transport::Server::builder()
+				.add_service(QueryServer::new(MockWasmQuery { code }))
+				.serve_with_incoming(TcpListenerStream::new(listener)),
+		);
+		Channel::from_shared(format!("http://{addr}")).unwrap().connect().await.unwrap()
+	}
+
+	#[tokio::test]
+	async fn finds_stored_wasm_code() {
+		let code = b"\0asm stored light client".to_vec();
+		let channel = serve(code.clone()).await;
+
+		assert!(query_wasm_code_stored(channel.clone(), &Sha256::digest(&code)).await.unwrap());
+		assert!(!query_wasm_code_stored(channel, &Sha256::digest(b"\0asm other light client"))
+			.await
+			.unwrap());
+	}
+
+	#[test]
+	fn code_id_is_read_from_wasm_client_states() {
+		let code_id = Sha256::digest(b"\0asm migrated light client").to_vec();
+		let client_state = RawWasmClientState {
+			data: vec![1, 2, 3],
+			code_id: code_id.clone(),
+			latest_height: None,
+		};
+		let wasm_client_state = Any {
+			type_url: WASM_CLIENT_STATE_TYPE_URL.to_string(),
+			value: client_state.encode_to_vec(),
+		};
+		assert_eq!(wasm_code_id(&wasm_client_state), Some(code_id));
+
+		let tendermint_client_state = Any {
+			type_url: "/ibc.lightclients.tendermint.v1.ClientState".to_string(),
+			value: client_state.encode_to_vec(),
+		};
+		assert_eq!(wasm_code_id(&tendermint_client_state), None);
+	}
+}
@@ -0,0 +1,1511 @@
+//! Queries of the IBC state that the node only serves through its query services, over gRPC or
+//! over their REST (LCD) gateway. Many public endpoints only expose the RPC and REST interfaces,
+//! so the queries fall back to REST when gRPC is unavailable.
+//!
+//! The state whose proofs are relayed (client and consensus states, connections, channels,
+//! packet commitments, acknowledgements and receipts) is queried with ABCI queries over RPC, see
+//! [`crate::client::CosmosClient::query_path`], so only the unproven queries go through the
+//! backends. As over gRPC, the height of a REST query is set by the `x-cosmos-block-height`
+//! header. The account and balance queries and the simulations of the transactions go through the
+//! backends as well, so that a node without gRPC can be relayed to.
+
+use crate::{error::Error, provider::query_wasm_code_stored};
+use hyper::{client::HttpConnector, Body, Client, Method, Request, StatusCode};
+use hyper_rustls::HttpsConnector;
+use ibc::{
+	core::ics24_host::identifier::{ChannelId, ClientId, ConnectionId, PortId},
+	Height,
+};
+use ibc_proto::{
+	cosmos::{
+		auth::v1beta1::{
+			query_client::QueryClient as AuthQueryClient, BaseAccount, QueryAccountRequest,
+		},
+		bank::v1beta1::{query_client::QueryClient as BankQueryClient, QueryBalanceRequest},
+		base::{
+			abci::v1beta1::GasInfo,
+			query::v1beta1::PageRequest,
+			tendermint::v1beta1::{service_client::ServiceClient, GetNodeInfoRequest},
+			v1beta1::Coin,
+		},
+		tx::v1beta1::{
+			service_client::ServiceClient as TxServiceClient, SimulateRequest, SimulateResponse, Tx,
+		},
+	},
+	ibc::{
+		applications::transfer::v1::{
+			query_client::QueryClient as TransferQueryClient, DenomTrace, QueryDenomTraceRequest,
+		},
+		core::{
+			channel::v1::{
+				query_client::QueryClient as ChannelQueryClient, Counterparty, IdentifiedChannel,
+				Order, QueryChannelsRequest, QueryChannelsResponse, QueryConnectionChannelsRequest,
+				QueryPacketAcknowledgementsRequest, QueryPacketCommitmentsRequest,
+				QueryUnreceivedAcksRequest, QueryUnreceivedPacketsRequest, State,
+			},
+			client::v1::{
+				query_client::QueryClient as ClientQueryClient, Height as RawHeight,
+				QueryClientStatesRequest, QueryClientStatusRequest,
+				QueryConsensusStateHeightsRequest,
+			},
+			connection::v1::{
+				query_client::QueryClient as ConnectionQueryClient,
+				Counterparty as ConnectionCounterparty, IdentifiedConnection,
+				QueryConnectionsRequest, State as ConnectionState, Version,
+			},
+		},
+	},
+};
+use prost::Message;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
+use std::{sync::Arc, time::Duration};
+use tendermint_rpc::Url;
+use tonic::transport::Channel;
+
+/// Header setting the height at which the node answers a query.
+const BLOCK_HEIGHT_HEADER: &str = "x-cosmos-block-height";
+
+/// Time after which a REST query is abandoned.
+const REST_QUERY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Page size of the list queries, which are expected to fit in a single page.
+const PAGE_LIMIT: u64 = u32::MAX as u64;
+
+/// Time after which a simulation is abandoned.
+const SIMULATION_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Interface of the queries served by the query services of the node.
+#[async_trait::async_trait]
+pub trait CosmosQueryBackend: Send + Sync {
+	/// Name of the interface, used in logs.
+	fn name(&self) -> &'static str;
+
+	/// Status of the client at `at`, e.g. `Active`.
+	async fn client_status(&self, at: Height, client_id: &ClientId) -> Result<String, Error>;
+
+	/// Sequences of the packets sent on the channel whose commitments are stored.
+	async fn packet_commitments(
+		&self,
+		port_id: &PortId,
+		channel_id: &ChannelId,
+	) -> Result<Vec<u64>, Error>;
+
+	/// Sequences of the packets received on the channel whose acknowledgements are stored.
+	async fn packet_acknowledgements(
+		&self,
+		port_id: &PortId,
+		channel_id: &ChannelId,
+	) -> Result<Vec<u64>, Error>;
+
+	/// Sequences among `seqs` of the packets the channel didn't receive.
+	async fn unreceived_packets(
+		&self,
+		port_id: &PortId,
+		channel_id: &ChannelId,
+		seqs: &[u64],
+	) -> Result<Vec<u64>, Error>;
+
+	/// Sequences among `seqs` of the packets sent on the channel that weren't acknowledged.
+	async fn unreceived_acknowledgements(
+		&self,
+		port_id: &PortId,
+		channel_id: &ChannelId,
+		seqs: &[u64],
+	) -> Result<Vec<u64>, Error>;
+
+	/// Channels opened on the connection.
+	async fn connection_channels(
+		&self,
+		connection_id: &ConnectionId,
+	) -> Result<QueryChannelsResponse, Error>;
+
+	/// All the channels of the chain, following the pages of the query.
+	async fn channels(&self) -> Result<Vec<IdentifiedChannel>, Error>;
+
+	/// Trace of the denom whose hash is `hash`, without the `ibc/` prefix.
+	async fn denom_trace(&self, hash: &str) -> Result<DenomTrace, Error>;
+
+	/// Heights of the consensus states stored for the client. The query is only served since
+	/// ibc-go v7.
+	async fn consensus_state_heights(&self, client_id: &ClientId) -> Result<Vec<Height>, Error>;
+
+	/// Major version of the ibc-go module the application of the node was built with, or `None`
+	/// if it isn't among its build dependencies.
+	async fn ibc_go_version(&self) -> Result<Option<u64>, Error>;
+
+	/// Account of `address`, whose number and sequence sign its transactions.
+	async fn account(&self, address: &str) -> Result<BaseAccount, Error>;
+
+	/// Balance of `address` in `denom`.
+	async fn balance(&self, address: &str, denom: &str) -> Result<Coin, Error>;
+
+	/// Simulates the signed transaction `tx`, encoded as `tx_bytes`.
+	async fn simulate(&self, tx: &Tx, tx_bytes: &[u8]) -> Result<SimulateResponse, Error>;
+
+	/// All the connections of the chain.
+	async fn connections(&self) -> Result<Vec<IdentifiedConnection>, Error>;
+
+	/// Ids of all the clients of the chain.
+	async fn client_ids(&self) -> Result<Vec<String>, Error>;
+
+	/// Whether the 08-wasm module stores code with the given checksum.
+	async fn wasm_code_stored(&self, checksum: &[u8]) -> Result<bool, Error>;
+}
+
+/// Major version of the ibc-go module among the build dependencies of an application, given by
+/// their path and version, e.g. 7 for `github.com/cosmos/ibc-go/v7` at `v7.3.1`.
+fn ibc_go_major_version<'a>(deps: impl IntoIterator<Item = (&'a str, &'a str)>) -> Option<u64> {
+	deps.into_iter()
+		.filter(|(path, _)| path.starts_with("github.com/cosmos/ibc-go"))
+		.find_map(|(_, version)| version.trim_start_matches('v').split('.').next()?.parse().ok())
+}
+
+/// Queries over the gRPC services of the node.
+#[derive(Clone)]
+pub struct GrpcQueryBackend {
+	channel: Channel,
+}
+
+impl GrpcQueryBackend {
+	pub fn new(channel: Channel) -> Self {
+		Self { channel }
+	}
+}
+
+fn grpc_error(e: tonic::Status) -> Error {
+	Error::Grpc(e)
+}
+
+#[async_trait::async_trait]
+impl CosmosQueryBackend for GrpcQueryBackend {
+	fn name(&self) -> &'static str {
+		"gRPC"
+	}
+
+	async fn client_status(&self, at: Height, client_id: &ClientId) -> Result<String, Error> {
+		let mut request =
+			tonic::Request::new(QueryClientStatusRequest { client_id: client_id.to_string() });
+		let height = at.revision_height.to_string().parse().map_err(|e| {
+			Error::Custom(format!("Invalid height {at} for the client status query: {e}"))
+		})?;
+		request.metadata_mut().insert(BLOCK_HEIGHT_HEADER, height);
+		let response = ClientQueryClient::new(self.channel.clone())
+			.client_status(request)
+			.await
+			.map_err(grpc_error)?;
+		Ok(response.into_inner().status)
+	}
+
+	async fn packet_commitments(
+		&self,
+		port_id: &PortId,
+		channel_id: &ChannelId,
+	) -> Result<Vec<u64>, Error> {
+		let request = QueryPacketCommitmentsRequest {
+			port_id: port_id.to_string(),
+			channel_id: channel_id.to_string(),
+			pagination: Some(PageRequest { limit: PAGE_LIMIT, ..Default::default() }),
+		};
+		let response = ChannelQueryClient::new(self.channel.clone())
+			.packet_commitments(request)
+			.await
+			.map_err(grpc_error)?;
+		Ok(response.into_inner().commitments.into_iter().map(|v| v.sequence).collect())
+	}
+
+	async fn packet_acknowledgements(
+		&self,
+		port_id: &PortId,
+		channel_id: &ChannelId,
+	) -> Result<Vec<u64>, Error> {
+		let request = QueryPacketAcknowledgementsRequest {
+			port_id: port_id.to_string(),
+			channel_id: channel_id.to_string(),
+			packet_commitment_sequences: vec![],
+			pagination: Some(PageRequest { limit: PAGE_LIMIT, ..Default::default() }),
+		};
+		let response = ChannelQueryClient::new(self.channel.clone())
+			.packet_acknowledgements(request)
+			.await
+			.map_err(grpc_error)?;
+		Ok(response.into_inner().acknowledgements.into_iter().map(|v| v.sequence).collect())
+	}
+
+	async fn unreceived_packets(
+		&self,
+		port_id: &PortId,
+		channel_id: &ChannelId,
+		seqs: &[u64],
+	) -> Result<Vec<u64>, Error> {
+		let request = QueryUnreceivedPacketsRequest {
+			port_id: port_id.to_string(),
+			channel_id: channel_id.to_string(),
+			packet_commitment_sequences: seqs.to_vec(),
+		};
+		let response = ChannelQueryClient::new(self.channel.clone())
+			.unreceived_packets(request)
+			.await
+			.map_err(grpc_error)?;
+		Ok(response.into_inner().sequences)
+	}
+
+	async fn unreceived_acknowledgements(
+		&self,
+		port_id: &PortId,
+		channel_id: &ChannelId,
+		seqs: &[u64],
+	) -> Result<Vec<u64>, Error> {
+		let request = QueryUnreceivedAcksRequest {
+			port_id: port_id.to_string(),
+			channel_id: channel_id.to_string(),
+			packet_ack_sequences: seqs.to_vec(),
+		};
+		let response = ChannelQueryClient::new(self.channel.clone())
+			.unreceived_acks(request)
+			.await
+			.map_err(grpc_error)?;
+		Ok(response.into_inner().sequences)
+	}
+
+	async fn connection_channels(
+		&self,
+		connection_id: &ConnectionId,
+	) -> Result<QueryChannelsResponse, Error> {
+		let request = QueryConnectionChannelsRequest {
+			connection: connection_id.to_string(),
+			pagination: Some(PageRequest { limit: PAGE_LIMIT, ..Default::default() }),
+		};
+		let response = ChannelQueryClient::new(self.channel.clone())
+			.connection_channels(request)
+			.await
+			.map_err(grpc_error)?
+			.into_inner();
+		Ok(QueryChannelsResponse {
+			channels: response.channels,
+			pagination: response.pagination,
+			height: response.height,
+		})
+	}
+
+	async fn channels(&self) -> Result<Vec<IdentifiedChannel>, Error> {
+		let mut channels = vec![];
+		let mut key = vec![];
+		loop {
+			let request = QueryChannelsRequest {
+				pagination: Some(PageRequest { key, limit: PAGE_LIMIT, ..Default::default() }),
+			};
+			let response = ChannelQueryClient::new(self.channel.clone())
+				.channels(request)
+				.await
+				.map_err(grpc_error)?
+				.into_inner();
+			channels.extend(response.channels);
+			match response.pagination {
+				Some(page) if !page.next_key.is_empty() => key = page.next_key,
+				_ => return Ok(channels),
+			}
+		}
+	}
+
+	async fn denom_trace(&self, hash: &str) -> Result<DenomTrace, Error> {
+		let request = QueryDenomTraceRequest { hash: hash.to_string() };
+		let response = TransferQueryClient::new(self.channel.clone())
+			.denom_trace(request)
+			.await
+			.map_err(grpc_error)?;
+		response
+			.into_inner()
+			.denom_trace
+			.ok_or_else(|| Error::NotFound(format!("No denom trace for hash {hash}")))
+	}
+
+	async fn consensus_state_heights(&self, client_id: &ClientId) -> Result<Vec<Height>, Error> {
+		let request = QueryConsensusStateHeightsRequest {
+			client_id: client_id.to_string(),
+			pagination: Some(PageRequest { limit: PAGE_LIMIT, ..Default::default() }),
+		};
+		let response = ClientQueryClient::new(self.channel.clone())
+			.consensus_state_heights(request)
+			.await
+			.map_err(grpc_error)?;
+		Ok(response
+			.into_inner()
+			.consensus_state_heights
+			.into_iter()
+			.map(|height| Height::new(height.revision_number, height.revision_height))
+			.collect())
+	}
+
+	async fn ibc_go_version(&self) -> Result<Option<u64>, Error> {
+		let response = ServiceClient::new(self.channel.clone())
+			.get_node_info(GetNodeInfoRequest {})
+			.await
+			.map_err(grpc_error)?;
+		let deps = response
+			.into_inner()
+			.application_version
+			.map(|version| version.build_deps)
+			.unwrap_or_default();
+		Ok(ibc_go_major_version(deps.iter().map(|dep| (&*dep.path, &*dep.version))))
+	}
+
+	async fn account(&self, address: &str) -> Result<BaseAccount, Error> {
+		let request = QueryAccountRequest { address: address.to_string() };
+		let response = AuthQueryClient::new(self.channel.clone())
+			.account(request)
+			.await
+			.map_err(grpc_error)?;
+		// Querying for an account might fail, i.e. if the account doesn't actually exist
+		let account = response
+			.into_inner()
+			.account
+			.ok_or_else(|| Error::NotFound(format!("Account {address} not found")))?;
+		BaseAccount::decode(account.value.as_slice())
+			.map_err(|e| Error::from(format!("Failed to decode account {}", e)))
+	}
+
+	async fn balance(&self, address: &str, denom: &str) -> Result<Coin, Error> {
+		let request =
+			QueryBalanceRequest { address: address.to_string(), denom: denom.to_string() };
+		let response = BankQueryClient::new(self.channel.clone())
+			.balance(request)
+			.await
+			.map_err(grpc_error)?;
+		response
+			.into_inner()
+			.balance
+			.ok_or_else(|| Error::from(format!("No balance for denom {denom}")))
+	}
+
+	async fn simulate(&self, tx: &Tx, tx_bytes: &[u8]) -> Result<SimulateResponse, Error> {
+		#[allow(deprecated)]
+		let request = SimulateRequest {
+			tx: Some(tx.clone()), // needed for simulation to go through with Cosmos SDK <  0.43
+			tx_bytes: tx_bytes.to_vec(), // needed for simulation to go through with Cosmos SDk >= 0.43
+		};
+		let response = tokio::time::timeout(
+			SIMULATION_TIMEOUT,
+			TxServiceClient::new(self.channel.clone()).simulate(request),
+		)
+		.await
+		.map_err(|_| Error::RpcError("simulation timed out".to_string()))?
+		.map_err(grpc_error)?;
+		Ok(response.into_inner())
+	}
+
+	async fn connections(&self) -> Result<Vec<IdentifiedConnection>, Error> {
+		let request = QueryConnectionsRequest {
+			pagination: Some(PageRequest { limit: PAGE_LIMIT, ..Default::default() }),
+		};
+		let response = ConnectionQueryClient::new(self.channel.clone())
+			.connections(request)
+			.await
+			.map_err(grpc_error)?;
+		Ok(response.into_inner().connections)
+	}
+
+	async fn client_ids(&self) -> Result<Vec<String>, Error> {
+		let request = QueryClientStatesRequest {
+			pagination: Some(PageRequest { limit: PAGE_LIMIT, ..Default::default() }),
+		};
+		let response = ClientQueryClient::new(self.channel.clone())
+			.client_states(request)
+			.await
+			.map_err(grpc_error)?;
+		Ok(response.into_inner().client_states.into_iter().map(|cs| cs.client_id).collect())
+	}
+
+	async fn wasm_code_stored(&self, checksum: &[u8]) -> Result<bool, Error> {
+		query_wasm_code_stored(self.channel.clone(), checksum).await
+	}
+}
+
+/// Queries over the REST gateway of the query services, whose responses are the JSON encoding of
+/// the gRPC ones.
+#[derive(Clone)]
+pub struct RestQueryBackend {
+	client: Client<HttpsConnector<HttpConnector>>,
+	url: String,
+}
+
+impl RestQueryBackend {
+	pub fn new(url: Url) -> Self {
+		let connector = hyper_rustls::HttpsConnectorBuilder::new()
+			.with_native_roots()
+			.https_or_http()
+			.enable_http1()
+			.build();
+		Self {
+			client: Client::builder().build(connector),
+			url: url.to_string().trim_end_matches('/').to_string(),
+		}
+	}
+
+	/// Queries `path` of the gateway, at `height` if given.
+	async fn get<T: DeserializeOwned>(
+		&self,
+		path: &str,
+		height: Option<Height>,
+	) -> Result<T, Error> {
+		let mut request = Request::get(format!("{}{path}", self.url));
+		if let Some(height) = height {
+			request = request.header(BLOCK_HEIGHT_HEADER, height.revision_height);
+		}
+		let request = request
+			.body(Body::empty())
+			.map_err(|e| Error::Custom(format!("Invalid REST query {path}: {e}")))?;
+		self.send(path, request).await
+	}
+
+	/// Posts the JSON encoded `body` to `path` of the gateway.
+	async fn post<B: Serialize, T: DeserializeOwned>(
+		&self,
+		path: &str,
+		body: &B,
+	) -> Result<T, Error> {
+		let body = serde_json::to_vec(body)
+			.map_err(|e| Error::Custom(format!("Invalid REST request {path}: {e}")))?;
+		let request = Request::builder()
+			.method(Method::POST)
+			.uri(format!("{}{path}", self.url))
+			.header("content-type", "application/json")
+			.body(Body::from(body))
+			.map_err(|e| Error::Custom(format!("Invalid REST request {path}: {e}")))?;
+		self.send(path, request).await
+	}
+
+	async fn send<T: DeserializeOwned>(
+		&self,
+		path: &str,
+		request: Request<Body>,
+	) -> Result<T, Error> {
+		let (status, body) = tokio::time::timeout(REST_QUERY_TIMEOUT, async {
+			let response = self.client.request(request).await?;
+			let status = response.status();
+			let body = hyper::body::to_bytes(response.into_body()).await?;
+			Ok::<_, hyper::Error>((status, body))
+		})
+		.await
+		.map_err(|_| Error::RpcError(format!("REST query {path} timed out")))?
+		.map_err(|e| Error::RpcError(format!("REST query {path} failed: {e}")))?;
+		if !status.is_success() {
+			let message =
+				format!("REST query {path} failed: {status} {}", String::from_utf8_lossy(&body));
+			// the gateway answers the errors of the query services with their gRPC status, e.g.
+			// the failures of the simulations, which aren't errors of the gateway
+			let code = serde_json::from_slice::<GatewayError>(&body).ok().map(|e| e.code);
+			return Err(match (status, code) {
+				(_, Some(GRPC_NOT_FOUND)) | (StatusCode::NOT_FOUND, None) =>
+					Error::NotFound(message),
+				(_, Some(GRPC_UNAVAILABLE | GRPC_DEADLINE_EXCEEDED)) => Error::RpcError(message),
+				(_, Some(_)) => Error::Custom(message),
+				(status, None) if status.is_server_error() => Error::RpcError(message),
+				_ => Error::Custom(message),
+			})
+		}
+		serde_json::from_slice(&body)
+			.map_err(|e| Error::Custom(format!("Invalid response to REST query {path}: {e}")))
+	}
+}
+
+/// Codes of the gRPC statuses the gateway answers with, see [`GatewayError`].
+const GRPC_DEADLINE_EXCEEDED: i32 = 4;
+const GRPC_NOT_FOUND: i32 = 5;
+const GRPC_UNAVAILABLE: i32 = 14;
+
+/// Error of a query service, as encoded by the gateway.
+#[derive(Deserialize)]
+struct GatewayError {
+	code: i32,
+}
+
+/// 64 bit integers are encoded as strings in JSON.
+fn u64_from_str<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+	String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+}
+
+fn u64s_from_strs<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u64>, D::Error> {
+	Vec::<String>::deserialize(deserializer)?
+		.into_iter()
+		.map(|s| s.parse().map_err(serde::de::Error::custom))
+		.collect()
+}
+
+#[derive(Deserialize)]
+struct ClientStatusResponse {
+	status: String,
+}
+
+#[derive(Deserialize)]
+struct PacketState {
+	#[serde(deserialize_with = "u64_from_str")]
+	sequence: u64,
+}
+
+#[derive(Deserialize)]
+struct PacketCommitmentsResponse {
+	commitments: Vec<PacketState>,
+}
+
+#[derive(Deserialize)]
+struct PacketAcknowledgementsResponse {
+	acknowledgements: Vec<PacketState>,
+}
+
+#[derive(Deserialize)]
+struct SequencesResponse {
+	#[serde(deserialize_with = "u64s_from_strs")]
+	sequences: Vec<u64>,
+}
+
+#[derive(Deserialize)]
+struct JsonHeight {
+	#[serde(deserialize_with = "u64_from_str")]
+	revision_number: u64,
+	#[serde(deserialize_with = "u64_from_str")]
+	revision_height: u64,
+}
+
+/// Channel with its enums encoded by name.
+#[derive(Deserialize)]
+struct JsonChannel {
+	state: String,
+	ordering: String,
+	counterparty: Option<Counterparty>,
+	connection_hops: Vec<String>,
+	version: String,
+	port_id: String,
+	channel_id: String,
+}
+
+impl TryFrom<JsonChannel> for IdentifiedChannel {
+	type Error = Error;
+
+	fn try_from(channel: JsonChannel) -> Result<Self, Error> {
+		let state = State::from_str_name(&channel.state)
+			.ok_or_else(|| Error::Custom(format!("Invalid channel state {}", channel.state)))?;
+		let ordering = Order::from_str_name(&channel.ordering)
+			.ok_or_else(|| Error::Custom(format!("Invalid channel order {}", channel.ordering)))?;
+		Ok(IdentifiedChannel {
+			state: state as i32,
+			ordering: ordering as i32,
+			counterparty: channel.counterparty,
+			connection_hops: channel.connection_hops,
+			version: channel.version,
+			port_id: channel.port_id,
+			channel_id: channel.channel_id,
+		})
+	}
+}
+
+#[derive(Deserialize)]
+struct ConnectionChannelsResponse {
+	channels: Vec<JsonChannel>,
+	height: Option<JsonHeight>,
+}
+
+#[derive(Deserialize)]
+struct PageResponse {
+	/// Base64 encoded key of the next page, if any.
+	next_key: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChannelsResponse {
+	channels: Vec<JsonChannel>,
+	pagination: Option<PageResponse>,
+}
+
+#[derive(Deserialize)]
+struct DenomTraceResponse {
+	denom_trace: Option<DenomTrace>,
+}
+
+#[derive(Deserialize)]
+struct ConsensusStateHeightsResponse {
+	consensus_state_heights: Vec<JsonHeight>,
+}
+
+#[derive(Deserialize)]
+struct BuildDep {
+	path: String,
+	version: String,
+}
+
+#[derive(Deserialize)]
+struct ApplicationVersion {
+	#[serde(default)]
+	build_deps: Vec<BuildDep>,
+}
+
+#[derive(Deserialize)]
+struct NodeInfoResponse {
+	application_version: Option<ApplicationVersion>,
+}
+
+/// Fields of a `BaseAccount`, the pubkey left out as the transactions are signed with the key
+/// of the relayer.
+#[derive(Deserialize)]
+struct JsonBaseAccount {
+	address: String,
+	#[serde(deserialize_with = "u64_from_str")]
+	account_number: u64,
+	#[serde(deserialize_with = "u64_from_str")]
+	sequence: u64,
+}
+
+#[derive(Deserialize)]
+struct JsonVestingAccount {
+	base_account: JsonBaseAccount,
+}
+
+/// Accounts of the auth module, including the vesting accounts which wrap a base account.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonAccount {
+	Base(JsonBaseAccount),
+	Module { base_account: JsonBaseAccount },
+	Vesting { base_vesting_account: JsonVestingAccount },
+}
+
+impl From<JsonAccount> for BaseAccount {
+	fn from(account: JsonAccount) -> Self {
+		let account = match account {
+			JsonAccount::Base(account) | JsonAccount::Module { base_account: account } => account,
+			JsonAccount::Vesting { base_vesting_account } => base_vesting_account.base_account,
+		};
+		BaseAccount {
+			address: account.address,
+			pub_key: None,
+			account_number: account.account_number,
+			sequence: account.sequence,
+		}
+	}
+}
+
+#[derive(Deserialize)]
+struct AccountResponse {
+	account: JsonAccount,
+}
+
+#[derive(Deserialize)]
+struct BalanceResponse {
+	balance: Option<Coin>,
+}
+
+#[derive(Serialize)]
+struct SimulateBody<'a> {
+	#[serde(with = "ibc_proto::base64")]
+	tx_bytes: &'a [u8],
+}
+
+#[derive(Deserialize)]
+struct JsonGasInfo {
+	#[serde(deserialize_with = "u64_from_str")]
+	gas_wanted: u64,
+	#[serde(deserialize_with = "u64_from_str")]
+	gas_used: u64,
+}
+
+#[derive(Deserialize)]
+struct SimulationResponse {
+	gas_info: Option<JsonGasInfo>,
+}
+
+/// Connection with its state encoded by name.
+#[derive(Deserialize)]
+struct JsonConnection {
+	id: String,
+	client_id: String,
+	versions: Vec<Version>,
+	state: String,
+	counterparty: Option<ConnectionCounterparty>,
+	#[serde(deserialize_with = "u64_from_str")]
+	delay_period: u64,
+}
+
+impl TryFrom<JsonConnection> for IdentifiedConnection {
+	type Error = Error;
+
+	fn try_from(connection: JsonConnection) -> Result<Self, Error> {
+		let state = ConnectionState::from_str_name(&connection.state).ok_or_else(|| {
+			Error::Custom(format!("Invalid connection state {}", connection.state))
+		})?;
+		Ok(IdentifiedConnection {
+			id: connection.id,
+			client_id: connection.client_id,
+			versions: connection.versions,
+			state: state as i32,
+			counterparty: connection.counterparty,
+			delay_period: connection.delay_period,
+		})
+	}
+}
+
+#[derive(Deserialize)]
+struct ConnectionsResponse {
+	connections: Vec<JsonConnection>,
+	pagination: Option<PageResponse>,
+}
+
+#[derive(Deserialize)]
+struct IdentifiedClientId {
+	client_id: String,
+}
+
+#[derive(Deserialize)]
+struct ClientStatesResponse {
+	client_states: Vec<IdentifiedClientId>,
+	pagination: Option<PageResponse>,
+}
+
+#[derive(Deserialize)]
+struct WasmCodeResponse {
+	code: String,
+}
+
+/// Escapes the characters of a base64 encoded page key, or of an ibc denom, that can't appear in
+/// a query string.
+fn encode_page_key(key: &str) -> String {
+	key.replace('+', "%2B").replace('/', "%2F").replace('=', "%3D")
+}
+
+/// Comma separated sequences, as in the paths of the unreceived packets queries.
+fn join_sequences(seqs: &[u64]) -> String {
+	seqs.iter().map(u64::to_string).collect::<Vec<_>>().join(",")
+}
+
+#[async_trait::async_trait]
+impl CosmosQueryBackend for RestQueryBackend {
+	fn name(&self) -> &'static str {
+		"REST"
+	}
+
+	async fn client_status(&self, at: Height, client_id: &ClientId) -> Result<String, Error> {
+		let path = format!("/ibc/core/client/v1/client_status/{client_id}");
+		let response: ClientStatusResponse = self.get(&path, Some(at)).await?;
+		Ok(response.status)
+	}
+
+	async fn packet_commitments(
+		&self,
+		port_id: &PortId,
+		channel_id: &ChannelId,
+	) -> Result<Vec<u64>, Error> {
+		let path = format!(
+			"/ibc/core/channel/v1/channels/{channel_id}/ports/{port_id}/packet_commitments?pagination.limit={PAGE_LIMIT}"
+		);
+		let response: PacketCommitmentsResponse = self.get(&path, None).await?;
+		Ok(response.commitments.into_iter().map(|v| v.sequence).collect())
+	}
+
+	async fn packet_acknowledgements(
+		&self,
+		port_id: &PortId,
+		channel_id: &ChannelId,
+	) -> Result<Vec<u64>, Error> {
+		let path = format!(
+			"/ibc/core/channel/v1/channels/{channel_id}/ports/{port_id}/packet_acknowledgements?pagination.limit={PAGE_LIMIT}"
+		);
+		let response: PacketAcknowledgementsResponse = self.get(&path, None).await?;
+		Ok(response.acknowledgements.into_iter().map(|v| v.sequence).collect())
+	}
+
+	async fn unreceived_packets(
+		&self,
+		port_id: &PortId,
+		channel_id: &ChannelId,
+		seqs: &[u64],
+	) -> Result<Vec<u64>, Error> {
+		// the sequences are a segment of the path, which can't be empty
+		if seqs.is_empty() {
+			return Ok(vec![])
+		}
+		let path = format!(
+			"/ibc/core/channel/v1/channels/{channel_id}/ports/{port_id}/packet_commitments/{}/unreceived_packets",
+			join_sequences(seqs)
+		);
+		let response: SequencesResponse = self.get(&path, None).await?;
+		Ok(response.sequences)
+	}
+
+	async fn unreceived_acknowledgements(
+		&self,
+		port_id: &PortId,
+		channel_id: &ChannelId,
+		seqs: &[u64],
+	) -> Result<Vec<u64>, Error> {
+		if seqs.is_empty() {
+			return Ok(vec![])
+		}
+		let path = format!(
+			"/ibc/core/channel/v1/channels/{channel_id}/ports/{port_id}/packet_commitments/{}/unreceived_acks",
+			join_sequences(seqs)
+		);
+		let response: SequencesResponse = self.get(&path, None).await?;
+		Ok(response.sequences)
+	}
+
+	async fn connection_channels(
+		&self,
+		connection_id: &ConnectionId,
+	) -> Result<QueryChannelsResponse, Error> {
+		let path = format!(
+			"/ibc/core/channel/v1/connections/{connection_id}/channels?pagination.limit={PAGE_LIMIT}"
+		);
+		let response: ConnectionChannelsResponse = self.get(&path, None).await?;
+		Ok(QueryChannelsResponse {
+			channels: response
+				.channels
+				.into_iter()
+				.map(IdentifiedChannel::try_from)
+				.collect::<Result<_, _>>()?,
+			pagination: None,
+			height: response.height.map(|height| RawHeight {
+				revision_number: height.revision_number,
+				revision_height: height.revision_height,
+			}),
+		})
+	}
+
+	async fn channels(&self) -> Result<Vec<IdentifiedChannel>, Error> {
+		let mut channels = vec![];
+		let mut path = format!("/ibc/core/channel/v1/channels?pagination.limit={PAGE_LIMIT}");
+		loop {
+			let response: ChannelsResponse = self.get(&path, None).await?;
+			for channel in response.channels {
+				channels.push(IdentifiedChannel::try_from(channel)?);
+			}
+			match response.pagination.and_then(|page| page.next_key) {
+				Some(key) if !key.is_empty() =>
+					path = format!(
+						"/ibc/core/channel/v1/channels?pagination.limit={PAGE_LIMIT}&pagination.key={}",
+						encode_page_key(&key)
+					),
+				_ => return Ok(channels),
+			}
+		}
+	}
+
+	async fn denom_trace(&self, hash: &str) -> Result<DenomTrace, Error> {
+		let path = format!("/ibc/apps/transfer/v1/denom_traces/{hash}");
+		let response: DenomTraceResponse = self.get(&path, None).await?;
+		response
+			.denom_trace
+			.ok_or_else(|| Error::NotFound(format!("No denom trace for hash {hash}")))
+	}
+
+	async fn consensus_state_heights(&self, client_id: &ClientId) -> Result<Vec<Height>, Error> {
+		let path = format!(
+			"/ibc/core/client/v1/consensus_states/{client_id}/heights?pagination.limit={PAGE_LIMIT}"
+		);
+		let response: ConsensusStateHeightsResponse = self.get(&path, None).await?;
+		Ok(response
+			.consensus_state_heights
+			.into_iter()
+			.map(|height| Height::new(height.revision_number, height.revision_height))
+			.collect())
+	}
+
+	async fn ibc_go_version(&self) -> Result<Option<u64>, Error> {
+		let response: NodeInfoResponse =
+			self.get("/cosmos/base/tendermint/v1beta1/node_info", None).await?;
+		let deps = response.application_version.map(|version| version.build_deps);
+		Ok(ibc_go_major_version(deps.iter().flatten().map(|dep| (&*dep.path, &*dep.version))))
+	}
+
+	async fn account(&self, address: &str) -> Result<BaseAccount, Error> {
+		let path = format!("/cosmos/auth/v1beta1/accounts/{address}");
+		let response: AccountResponse = self.get(&path, None).await?;
+		Ok(response.account.into())
+	}
+
+	async fn balance(&self, address: &str, denom: &str) -> Result<Coin, Error> {
+		let path = format!(
+			"/cosmos/bank/v1beta1/balances/{address}/by_denom?denom={}",
+			encode_page_key(denom)
+		);
+		let response: BalanceResponse = self.get(&path, None).await?;
+		response
+			.balance
+			.ok_or_else(|| Error::from(format!("No balance for denom {denom}")))
+	}
+
+	async fn simulate(&self, _tx: &Tx, tx_bytes: &[u8]) -> Result<SimulateResponse, Error> {
+		let body = SimulateBody { tx_bytes };
+		let response: SimulationResponse = tokio::time::timeout(
+			SIMULATION_TIMEOUT,
+			self.post("/cosmos/tx/v1beta1/simulate", &body),
+		)
+		.await
+		.map_err(|_| Error::RpcError("simulation timed out".to_string()))??;
+		Ok(SimulateResponse {
+			gas_info: response
+				.gas_info
+				.map(|info| GasInfo { gas_wanted: info.gas_wanted, gas_used: info.gas_used }),
+			result: None,
+		})
+	}
+
+	async fn connections(&self) -> Result<Vec<IdentifiedConnection>, Error> {
+		let mut connections = vec![];
+		let mut path = format!("/ibc/core/connection/v1/connections?pagination.limit={PAGE_LIMIT}");
+		loop {
+			let response: ConnectionsResponse = self.get(&path, None).await?;
+			for connection in response.connections {
+				connections.push(IdentifiedConnection::try_from(connection)?);
+			}
+			match response.pagination.and_then(|page| page.next_key) {
+				Some(key) if !key.is_empty() =>
+					path = format!(
+						"/ibc/core/connection/v1/connections?pagination.limit={PAGE_LIMIT}&pagination.key={}",
+						encode_page_key(&key)
+					),
+				_ => return Ok(connections),
+			}
+		}
+	}
+
+	async fn client_ids(&self) -> Result<Vec<String>, Error> {
+		let mut client_ids = vec![];
+		let mut path = format!("/ibc/core/client/v1/client_states?pagination.limit={PAGE_LIMIT}");
+		loop {
+			let response: ClientStatesResponse = self.get(&path, None).await?;
+			client_ids.extend(response.client_states.into_iter().map(|cs| cs.client_id));
+			match response.pagination.and_then(|page| page.next_key) {
+				Some(key) if !key.is_empty() =>
+					path = format!(
+						"/ibc/core/client/v1/client_states?pagination.limit={PAGE_LIMIT}&pagination.key={}",
+						encode_page_key(&key)
+					),
+				_ => return Ok(client_ids),
+			}
+		}
+	}
+
+	async fn wasm_code_stored(&self, checksum: &[u8]) -> Result<bool, Error> {
+		let path = format!("/ibc/lightclients/wasm/v1/code/{}", hex::encode(checksum));
+		match self.get::<WasmCodeResponse>(&path, None).await {
+			Ok(response) => Ok(!response.code.is_empty()),
+			Err(Error::NotFound(_)) => Ok(false),
+			Err(e) => Err(e),
+		}
+	}
+}
+
+/// Tries each of its backends in order, until one of them answers the query.
+#[derive(Clone)]
+pub struct FallbackQueryBackend {
+	backends: Vec<Arc<dyn CosmosQueryBackend>>,
+}
+
+impl FallbackQueryBackend {
+	pub fn new(backends: Vec<Arc<dyn CosmosQueryBackend>>) -> Self {
+		Self { backends }
+	}
+}
+
+/// Runs the query on each backend of a [`FallbackQueryBackend`] until one succeeds, returning
+/// the error of the last one otherwise.
+macro_rules! with_fallback {
+	($self:ident, $query:ident($($arg:expr),*)) => {{
+		let mut error = Error::Custom(
+			format!("No gRPC or REST endpoint to query {}", stringify!($query)),
+		);
+		for backend in &$self.backends {
+			match backend.$query($($arg),*).await {
+				Ok(result) => return Ok(result),
+				Err(e) => {
+					log::debug!(
+						target: "hyperspace_cosmos",
+						"Failed to query {} over {}: {e}",
+						stringify!($query),
+						backend.name()
+					);
+					error = e;
+				},
+			}
+		}
+		Err(error)
+	}};
+}
+
+#[async_trait::async_trait]
+impl CosmosQueryBackend for FallbackQueryBackend {
+	fn name(&self) -> &'static str {
+		"fallback"
+	}
+
+	async fn client_status(&self, at: Height, client_id: &ClientId) -> Result<String, Error> {
+		with_fallback!(self, client_status(at, client_id))
+	}
+
+	async fn packet_commitments(
+		&self,
+		port_id: &PortId,
+		channel_id: &ChannelId,
+	) -> Result<Vec<u64>, Error> {
+		with_fallback!(self, packet_commitments(port_id, channel_id))
+	}
+
+	async fn packet_acknowledgements(
+		&self,
+		port_id: &PortId,
+		channel_id: &ChannelId,
+	) -> Result<Vec<u64>, Error> {
+		with_fallback!(self, packet_acknowledgements(port_id, channel_id))
+	}
+
+	async fn unreceived_packets(
+		&self,
+		port_id: &PortId,
+		channel_id: &ChannelId,
+		seqs: &[u64],
+	) -> Result<Vec<u64>, Error> {
+		with_fallback!(self, unreceived_packets(port_id, channel_id, seqs))
+	}
+
+	async fn unreceived_acknowledgements(
+		&self,
+		port_id: &PortId,
+		channel_id: &ChannelId,
+		seqs: &[u64],
+	) -> Result<Vec<u64>, Error> {
+		with_fallback!(self, unreceived_acknowledgements(port_id, channel_id, seqs))
+	}
+
+	async fn connection_channels(
+		&self,
+		connection_id: &ConnectionId,
+	) -> Result<QueryChannelsResponse, Error> {
+		with_fallback!(self, connection_channels(connection_id))
+	}
+
+	async fn channels(&self) -> Result<Vec<IdentifiedChannel>, Error> {
+		with_fallback!(self, channels())
+	}
+
+	async fn denom_trace(&self, hash: &str) -> Result<DenomTrace, Error> {
+		with_fallback!(self, denom_trace(hash))
+	}
+
+	async fn consensus_state_heights(&self, client_id: &ClientId) -> Result<Vec<Height>, Error> {
+		with_fallback!(self, consensus_state_heights(client_id))
+	}
+
+	async fn ibc_go_version(&self) -> Result<Option<u64>, Error> {
+		with_fallback!(self, ibc_go_version())
+	}
+
+	async fn account(&self, address: &str) -> Result<BaseAccount, Error> {
+		with_fallback!(self, account(address))
+	}
+
+	async fn balance(&self, address: &str, denom: &str) -> Result<Coin, Error> {
+		with_fallback!(self, balance(address, denom))
+	}
+
+	async fn simulate(&self, tx: &Tx, tx_bytes: &[u8]) -> Result<SimulateResponse, Error> {
+		with_fallback!(self, simulate(tx, tx_bytes))
+	}
+
+	async fn connections(&self) -> Result<Vec<IdentifiedConnection>, Error> {
+		with_fallback!(self, connections())
+	}
+
+	async fn client_ids(&self) -> Result<Vec<String>, Error> {
+		with_fallback!(self, client_ids())
+	}
+
+	async fn wasm_code_stored(&self, checksum: &[u8]) -> Result<bool, Error> {
+		with_fallback!(self, wasm_code_stored(checksum))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use hyper::{
+		service::{make_service_fn, service_fn},
+		Response, Server, StatusCode,
+	};
+	use std::{
+		collections::HashMap,
+		convert::Infallible,
+		str::FromStr,
+		sync::{
+			atomic::{AtomicUsize, Ordering},
+			Mutex,
+		},
+	};
+
+	/// Serves the given JSON responses by path and query, recording the heights of the requests.
+	async fn serve_rest(
+		responses: HashMap<&'static str, &'static str>,
+	) -> (RestQueryBackend, Arc<Mutex<Vec<String>>>) {
+		let heights = Arc::new(Mutex::new(vec![]));
+		let service = {
+			let heights = heights.clone();
+			make_service_fn(move |_| {
+				let (responses, heights) = (responses.clone(), heights.clone());
+				async move {
+					Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+						if let Some(height) = request.headers().get(BLOCK_HEIGHT_HEADER) {
+							heights.lock().unwrap().push(height.to_str().unwrap().to_string());
+						}
+						let path = request.uri().path_and_query().unwrap().as_str();
+						let response = match responses.get(path) {
+							Some(body) => Response::new(Body::from(*body)),
+							None => {
+								let mut response = Response::new(Body::from("Not Implemented"));
+								*response.status_mut() = StatusCode::NOT_IMPLEMENTED;
+								response
+							},
+						};
+						async move { Ok::<_, Infallible>(response) }
+					}))
+				}
+			})
+		};
+		let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(service);
+		let url = format!("http://{}", server.local_addr());
+		tokio::spawn(server);
+		(RestQueryBackend::new(url.parse().unwrap()), heights)
+	}
+
+	fn lcd_responses() -> HashMap<&'static str, &'static str> {
+		HashMap::from([
+			(
+				"/ibc/core/client/v1/client_status/07-tendermint-0",
+				r#"{"status":"Active"}"#,
+			),
+			(
+				"/ibc/core/channel/v1/channels/channel-0/ports/transfer/packet_commitments?pagination.limit=4294967295",
+				r#"{"commitments":[{"port_id":"transfer","channel_id":"channel-0","sequence":"3","data":"AQI="},{"port_id":"transfer","channel_id":"channel-0","sequence":"5","data":"AQI="}],"pagination":{"next_key":null,"total":"2"},"height":{"revision_number":"1","revision_height":"120"}}"#,
+			),
+			(
+				"/ibc/core/channel/v1/channels/channel-0/ports/transfer/packet_acknowledgements?pagination.limit=4294967295",
+				r#"{"acknowledgements":[{"port_id":"transfer","channel_id":"channel-0","sequence":"1","data":"AQI="}],"pagination":{"next_key":null,"total":"1"},"height":{"revision_number":"1","revision_height":"120"}}"#,
+			),
+			(
+				"/ibc/core/channel/v1/channels/channel-0/ports/transfer/packet_commitments/3,5/unreceived_packets",
+				r#"{"sequences":["5"],"height":{"revision_number":"1","revision_height":"120"}}"#,
+			),
+			(
+				"/ibc/core/channel/v1/channels/channel-0/ports/transfer/packet_commitments/1,2/unreceived_acks",
+				r#"{"sequences":[],"height":{"revision_number":"1","revision_height":"120"}}"#,
+			),
+			(
+				"/ibc/core/channel/v1/connections/connection-0/channels?pagination.limit=4294967295",
+				r#"{"channels":[{"state":"STATE_OPEN","ordering":"ORDER_UNORDERED","counterparty":{"port_id":"transfer","channel_id":"channel-1"},"connection_hops":["connection-0"],"version":"ics20-1","port_id":"transfer","channel_id":"channel-0"}],"pagination":{"next_key":null,"total":"1"},"height":{"revision_number":"1","revision_height":"120"}}"#,
+			),
+			(
+				"/ibc/core/channel/v1/channels?pagination.limit=4294967295",
+				r#"{"channels":[{"state":"STATE_OPEN","ordering":"ORDER_UNORDERED","counterparty":{"port_id":"transfer","channel_id":"channel-1"},"connection_hops":["connection-0"],"version":"ics20-1","port_id":"transfer","channel_id":"channel-0"}],"pagination":{"next_key":"AQ+/","total":"2"},"height":{"revision_number":"1","revision_height":"120"}}"#,
+			),
+			(
+				"/ibc/core/channel/v1/channels?pagination.limit=4294967295&pagination.key=AQ%2B%2F",
+				r#"{"channels":[{"state":"STATE_INIT","ordering":"ORDER_ORDERED","counterparty":{"port_id":"ping","channel_id":""},"connection_hops":["connection-1"],"version":"ping-1","port_id":"ping","channel_id":"channel-1"}],"pagination":{"next_key":null,"total":"2"},"height":{"revision_number":"1","revision_height":"120"}}"#,
+			),
+			(
+				"/ibc/apps/transfer/v1/denom_traces/27A6394C3F9FF9C9DCF5DFFADF9BB5FE9A37C7E92B006199894CF1824DF9AC7C",
+				r#"{"denom_trace":{"path":"transfer/channel-0","base_denom":"uatom"}}"#,
+			),
+			(
+				"/ibc/core/client/v1/consensus_states/07-tendermint-0/heights?pagination.limit=4294967295",
+				r#"{"consensus_state_heights":[{"revision_number":"1","revision_height":"100"},{"revision_number":"1","revision_height":"110"}],"pagination":{"next_key":null,"total":"2"}}"#,
+			),
+			(
+				"/cosmos/base/tendermint/v1beta1/node_info",
+				r#"{"default_node_info":{"network":"testnet-1"},"application_version":{"name":"simd","build_deps":[{"path":"github.com/cosmos/cosmos-sdk","version":"v0.47.5","sum":""},{"path":"github.com/cosmos/ibc-go/v7","version":"v7.3.1","sum":""}]}}"#,
+			),
+			(
+				"/cosmos/auth/v1beta1/accounts/cosmos1relayer",
+				r#"{"account":{"@type":"/cosmos.auth.v1beta1.BaseAccount","address":"cosmos1relayer","pub_key":null,"account_number":"7","sequence":"42"}}"#,
+			),
+			(
+				"/cosmos/auth/v1beta1/accounts/cosmos1vesting",
+				r#"{"account":{"@type":"/cosmos.vesting.v1beta1.ContinuousVestingAccount","base_vesting_account":{"base_account":{"address":"cosmos1vesting","pub_key":null,"account_number":"8","sequence":"3"},"original_vesting":[],"delegated_free":[],"delegated_vesting":[],"end_time":"0"},"start_time":"0"}}"#,
+			),
+			(
+				"/cosmos/bank/v1beta1/balances/cosmos1relayer/by_denom?denom=ibc%2F27A6394C3F9FF9C9DCF5DFFADF9BB5FE9A37C7E92B006199894CF1824DF9AC7C",
+				r#"{"balance":{"denom":"ibc/27A6394C3F9FF9C9DCF5DFFADF9BB5FE9A37C7E92B006199894CF1824DF9AC7C","amount":"1000"}}"#,
+			),
+			(
+				"/cosmos/tx/v1beta1/simulate",
+				r#"{"gas_info":{"gas_wanted":"0","gas_used":"81234"},"result":{"data":"","log":"","events":[],"msg_responses":[]}}"#,
+			),
+			(
+				"/ibc/core/connection/v1/connections?pagination.limit=4294967295",
+				r#"{"connections":[{"id":"connection-0","client_id":"07-tendermint-0","versions":[{"identifier":"1","features":["ORDER_ORDERED","ORDER_UNORDERED"]}],"state":"STATE_OPEN","counterparty":{"client_id":"08-wasm-1","connection_id":"connection-1","prefix":{"key_prefix":"aWJj"}},"delay_period":"0"}],"pagination":{"next_key":null,"total":"1"},"height":{"revision_number":"1","revision_height":"120"}}"#,
+			),
+			(
+				"/ibc/core/client/v1/client_states?pagination.limit=4294967295",
+				r#"{"client_states":[{"client_id":"07-tendermint-0","client_state":{"@type":"/ibc.lightclients.tendermint.v1.ClientState","chain_id":"testnet-2"}},{"client_id":"09-localhost","client_state":{"@type":"/ibc.lightclients.localhost.v2.ClientState","latest_height":{"revision_number":"1","revision_height":"120"}}}],"pagination":{"next_key":null,"total":"2"}}"#,
+			),
+			(
+				"/ibc/lightclients/wasm/v1/code/0102",
+				r#"{"code":"AGFzbQ=="}"#,
+			),
+		])
+	}
+
+	#[tokio::test]
+	async fn rest_responses_are_decoded() {
+		let (rest, heights) = serve_rest(lcd_responses()).await;
+		let (port_id, channel_id) = (PortId::transfer(), ChannelId::new(0));
+
+		let client_id = ClientId::from_str("07-tendermint-0").unwrap();
+		assert_eq!(rest.client_status(Height::new(1, 120), &client_id).await.unwrap(), "Active");
+		assert_eq!(*heights.lock().unwrap(), vec!["120".to_string()]);
+
+		assert_eq!(rest.packet_commitments(&port_id, &channel_id).await.unwrap(), vec![3, 5]);
+		assert_eq!(rest.packet_acknowledgements(&port_id, &channel_id).await.unwrap(), vec![1]);
+		assert_eq!(rest.unreceived_packets(&port_id, &channel_id, &[3, 5]).await.unwrap(), vec![5]);
+		assert!(rest
+			.unreceived_acknowledgements(&port_id, &channel_id, &[1, 2])
+			.await
+			.unwrap()
+			.is_empty());
+		assert!(rest.unreceived_packets(&port_id, &channel_id, &[]).await.unwrap().is_empty());
+
+		let channels = rest
+			.connection_channels(&ConnectionId::from_str("connection-0").unwrap())
+			.await
+			.unwrap();
+		assert_eq!(
+			channels.channels,
+			vec![IdentifiedChannel {
+				state: State::Open as i32,
+				ordering: Order::Unordered as i32,
+				counterparty: Some(Counterparty {
+					port_id: "transfer".to_string(),
+					channel_id: "channel-1".to_string(),
+				}),
+				connection_hops: vec!["connection-0".to_string()],
+				version: "ics20-1".to_string(),
+				port_id: "transfer".to_string(),
+				channel_id: "channel-0".to_string(),
+			}]
+		);
+		assert_eq!(channels.height, Some(RawHeight { revision_number: 1, revision_height: 120 }));
+
+		// the channels of both pages are listed
+		let channels = rest.channels().await.unwrap();
+		assert_eq!(channels.len(), 2);
+		assert_eq!(channels[0].channel_id, "channel-0");
+		assert_eq!(channels[1].channel_id, "channel-1");
+		assert_eq!(channels[1].state, State::Init as i32);
+		assert_eq!(channels[1].ordering, Order::Ordered as i32);
+		assert_eq!(channels[1].connection_hops, vec!["connection-1".to_string()]);
+
+		let trace = rest
+			.denom_trace("27A6394C3F9FF9C9DCF5DFFADF9BB5FE9A37C7E92B006199894CF1824DF9AC7C")
+			.await
+			.unwrap();
+		assert_eq!(trace.path, "transfer/channel-0");
+		assert_eq!(trace.base_denom, "uatom");
+
+		assert_eq!(
+			rest.consensus_state_heights(&client_id).await.unwrap(),
+			vec![Height::new(1, 100), Height::new(1, 110)]
+		);
+		assert_eq!(rest.ibc_go_version().await.unwrap(), Some(7));
+
+		let account = rest.account("cosmos1relayer").await.unwrap();
+		assert_eq!((account.account_number, account.sequence), (7, 42));
+		// the base account of a vesting account is used
+		let account = rest.account("cosmos1vesting").await.unwrap();
+		assert_eq!(
+			(account.address.as_str(), account.account_number, account.sequence),
+			("cosmos1vesting", 8, 3)
+		);
+
+		let denom = "ibc/27A6394C3F9FF9C9DCF5DFFADF9BB5FE9A37C7E92B006199894CF1824DF9AC7C";
+		let balance = rest.balance("cosmos1relayer", denom).await.unwrap();
+		assert_eq!((balance.denom.as_str(), balance.amount.as_str()), (denom, "1000"));
+
+		let simulation = rest.simulate(&Tx::default(), &[1, 2, 3]).await.unwrap();
+		assert_eq!(simulation.gas_info.unwrap().gas_used, 81234);
+
+		let connections = rest.connections().await.unwrap();
+		assert_eq!(connections.len(), 1);
+		assert_eq!(connections[0].id, "connection-0");
+		assert_eq!(connections[0].state, ConnectionState::Open as i32);
+		assert_eq!(connections[0].counterparty.as_ref().unwrap().client_id, "08-wasm-1");
+		assert_eq!(connections[0].versions[0].features.len(), 2);
+
+		assert_eq!(
+			rest.client_ids().await.unwrap(),
+			vec!["07-tendermint-0".to_string(), "09-localhost".to_string()]
+		);
+
+		assert!(rest.wasm_code_stored(&[1, 2]).await.unwrap());
+	}
+
+	#[test]
+	fn ibc_go_version_is_parsed_from_build_deps() {
+		assert_eq!(ibc_go_major_version([("github.com/cosmos/ibc-go/v8", "v8.0.0")]), Some(8));
+		assert_eq!(ibc_go_major_version([("github.com/cosmos/ibc-go", "v1.2.0")]), Some(1));
+		assert_eq!(
+			ibc_go_major_version([
+				("github.com/cosmos/cosmos-sdk", "v0.47.5"),
+				("github.com/cosmos/ibc-go/v7", "v7.3.1")
+			]),
+			Some(7)
+		);
+		assert_eq!(ibc_go_major_version([("github.com/cosmos/cosmos-sdk", "v0.45.16")]), None);
+	}
+
+	#[tokio::test]
+	async fn rest_errors_are_reported() {
+		let (rest, _) = serve_rest(HashMap::new()).await;
+		let error = rest.denom_trace("ABCD").await.unwrap_err();
+		assert!(error.to_string().contains("501 Not Implemented"), "{error}");
+	}
+
+	#[tokio::test]
+	async fn gateway_errors_are_mapped_by_grpc_code() {
+		let (rest, _) = serve_rest(HashMap::from([
+			(
+				"/cosmos/tx/v1beta1/simulate",
+				r#"{"code":2,"message":"account sequence mismatch, expected 43, got 42","details":[]}"#,
+			),
+			(
+				"/ibc/lightclients/wasm/v1/code/0102",
+				r#"{"code":5,"message":"not found","details":[]}"#,
+			),
+		]))
+		.await;
+		// the rejected simulation isn't mistaken for an unavailable gateway
+		let error = rest.simulate(&Tx::default(), &[1, 2, 3]).await.unwrap_err();
+		assert!(
+			matches!(error, Error::Custom(ref e) if e.contains("sequence mismatch")),
+			"{error}"
+		);
+		assert!(!rest.wasm_code_stored(&[1, 2]).await.unwrap());
+	}
+
+	/// Backend of a node whose query services are unreachable.
+	struct UnavailableBackend {
+		queries: AtomicUsize,
+	}
+
+	#[async_trait::async_trait]
+	impl CosmosQueryBackend for UnavailableBackend {
+		fn name(&self) -> &'static str {
+			"unavailable"
+		}
+
+		async fn client_status(&self, _: Height, _: &ClientId) -> Result<String, Error> {
+			self.unavailable()
+		}
+
+		async fn packet_commitments(&self, _: &PortId, _: &ChannelId) -> Result<Vec<u64>, Error> {
+			self.unavailable()
+		}
+
+		async fn packet_acknowledgements(
+			&self,
+			_: &PortId,
+			_: &ChannelId,
+		) -> Result<Vec<u64>, Error> {
+			self.unavailable()
+		}
+
+		async fn unreceived_packets(
+			&self,
+			_: &PortId,
+			_: &ChannelId,
+			_: &[u64],
+		) -> Result<Vec<u64>, Error> {
+			self.unavailable()
+		}
+
+		async fn unreceived_acknowledgements(
+			&self,
+			_: &PortId,
+			_: &ChannelId,
+			_: &[u64],
+		) -> Result<Vec<u64>, Error> {
+			self.unavailable()
+		}
+
+		async fn connection_channels(
+			&self,
+			_: &ConnectionId,
+		) -> Result<QueryChannelsResponse, Error> {
+			self.unavailable()
+		}
+
+		async fn channels(&self) -> Result<Vec<IdentifiedChannel>, Error> {
+			self.unavailable()
+		}
+
+		async fn denom_trace(&self, _: &str) -> Result<DenomTrace, Error> {
+			self.unavailable()
+		}
+
+		async fn consensus_state_heights(&self, _: &ClientId) -> Result<Vec<Height>, Error> {
+			self.unavailable()
+		}
+
+		async fn ibc_go_version(&self) -> Result<Option<u64>, Error> {
+			self.unavailable()
+		}
+
+		async fn account(&self, _: &str) -> Result<BaseAccount, Error> {
+			self.unavailable()
+		}
+
+		async fn balance(&self, _: &str, _: &str) -> Result<Coin, Error> {
+			self.unavailable()
+		}
+
+		async fn simulate(&self, _: &Tx, _: &[u8]) -> Result<SimulateResponse, Error> {
+			self.unavailable()
+		}
+
+		async fn connections(&self) -> Result<Vec<IdentifiedConnection>, Error> {
+			self.unavailable()
+		}
+
+		async fn client_ids(&self) -> Result<Vec<String>, Error> {
+			self.unavailable()
+		}
+
+		async fn wasm_code_stored(&self, _: &[u8]) -> Result<bool, Error> {
+			self.unavailable()
+		}
+	}
+
+	impl UnavailableBackend {
+		fn unavailable<T>(&self) -> Result<T, Error> {
+			self.queries.fetch_add(1, Ordering::SeqCst);
+			Err(Error::RpcError("transport error: connection refused".to_string()))
+		}
+	}
+
+	#[tokio::test]
+	async fn queries_fall_back_to_rest() {
+		let (rest, _) = serve_rest(lcd_responses()).await;
+		let grpc = Arc::new(UnavailableBackend { queries: AtomicUsize::new(0) });
+		let backend = FallbackQueryBackend::new(vec![grpc.clone(), Arc::new(rest.clone())]);
+		let (port_id, channel_id) = (PortId::transfer(), ChannelId::new(0));
+
+		assert_eq!(backend.packet_commitments(&port_id, &channel_id).await.unwrap(), vec![3, 5]);
+		assert_eq!(grpc.queries.load(Ordering::SeqCst), 1);
+
+		// the first backend answers when it's available
+		let backend = FallbackQueryBackend::new(vec![Arc::new(rest), grpc.clone()]);
+		assert_eq!(backend.packet_commitments(&port_id, &channel_id).await.unwrap(), vec![3, 5]);
+		assert_eq!(grpc.queries.load(Ordering::SeqCst), 1);
+
+		// the error of the last backend is returned when none answers
+		let backend = FallbackQueryBackend::new(vec![grpc]);
+		let error = backend.denom_trace("ABCD").await.unwrap_err();
+		assert!(error.to_string().contains("connection refused"), "{error}");
+		let error = FallbackQueryBackend::new(vec![]).denom_trace("ABCD").await.unwrap_err();
+		assert!(error.to_string().contains("No gRPC or REST endpoint"), "{error}");
+	}
+}
@@ -1,19 +1,40 @@
-use super::client::CosmosClient;
+use super::{client::CosmosClient, provider::PAY_PACKET_FEE_TYPE_URL};
 use crate::error::Error;
 use core::pin::Pin;
 use futures::{Stream, StreamExt};
 use ibc::{
 	applications::transfer::{msgs::transfer::MsgTransfer, PrefixedCoin},
-	core::ics24_host::identifier::ChannelId,
+	core::ics24_host::identifier::{ChannelId, PortId},
+	signer::Signer,
 	tx_msg::Msg,
 };
-use primitives::TestProvider;
+use ibc_proto::{
+	google::protobuf::Any,
+	ibc::applications::fee::v1::{Fee, MsgPayPacketFee},
+};
+use primitives::{KeyProvider, TestProvider};
+use prost::Message;
+use serde_json::json;
 use tendermint_rpc::{
 	event::{Event, EventData},
 	query::{EventType, Query},
 	SubscriptionClient,
 };
 
+const EXECUTE_CONTRACT_TYPE_URL: &str = "/cosmwasm.wasm.v1.MsgExecuteContract";
+
+/// `cosmwasm.wasm.v1.MsgExecuteContract`, without the funds sent to the contract.
+#[derive(Clone, PartialEq, Message)]
+struct MsgExecuteContract {
+	#[prost(string, tag = "1")]
+	sender: String,
+	#[prost(string, tag = "2")]
+	contract: String,
+	/// JSON encoded message of the contract
+	#[prost(bytes = "vec", tag = "3")]
+	msg: Vec<u8>,
+}
+
 #[async_trait::async_trait]
 impl<H> TestProvider for CosmosClient<H>
 where
@@ -26,6 +47,29 @@ where
 		Ok(())
 	}
 
+	/// Initiate an ibc transfer on chain, escrowing the fee of the packet in the same transaction.
+	async fn send_transfer_with_fee(
+		&self,
+		msg: MsgTransfer<PrefixedCoin>,
+		fee: Fee,
+	) -> Result<(), Self::Error> {
+		let pay_packet_fee = MsgPayPacketFee {
+			fee: Some(fee),
+			source_port_id: msg.source_port.to_string(),
+			source_channel_id: msg.source_channel.to_string(),
+			signer: self.account_id().to_string(),
+			relayers: vec![],
+		};
+		let pay_packet_fee = Any {
+			type_url: PAY_PACKET_FEE_TYPE_URL.to_string(),
+			value: pay_packet_fee.encode_to_vec(),
+		};
+		// the fee is paid for the next packet sent on the channel
+		let hash = self.submit_call(vec![pay_packet_fee, msg.to_any()]).await?;
+		log::info!(target: "hyperspace_cosmos", "🤝 Incentivized transfer transaction confirmed with hash: {:?}", hash);
+		Ok(())
+	}
+
 	/// Send a packet on an ordered channel
 	async fn send_ordered_packet(
 		&self,
@@ -35,6 +79,48 @@ where
 		Err(Error::Custom("send_ordered_packet is not implemented yet".to_string()))
 	}
 
+	/// Send a packet from the contract configured in `packet_sender_contract`, which sends
+	/// `{"send_packet": {"channel_id", "data", "timeout_seconds"}}` with the hex encoded data on
+	/// its channels.
+	async fn send_raw_packet(
+		&self,
+		port_id: PortId,
+		channel_id: ChannelId,
+		data: Vec<u8>,
+		timeout: pallet_ibc::Timeout,
+	) -> Result<(), Self::Error> {
+		let contract = self.packet_sender_contract.as_ref().ok_or_else(|| {
+			Error::Custom(format!("No packet sender contract is configured on {}", self.name))
+		})?;
+		if port_id.as_str() != format!("wasm.{contract}") {
+			return Err(Error::Custom(format!(
+				"Port {port_id} isn't bound to the packet sender contract {contract}"
+			)))
+		}
+		// the contract times the packets out relative to the time of its block
+		let timeout_seconds = match timeout {
+			pallet_ibc::Timeout::Offset { timestamp: Some(timestamp), .. } => timestamp,
+			_ => return Err(Error::Custom("Only offset timestamp timeouts allowed".to_string())),
+		};
+		let execute = json!({
+			"send_packet": {
+				"channel_id": channel_id.to_string(),
+				"data": hex::encode(data),
+				"timeout_seconds": timeout_seconds,
+			}
+		});
+		let msg = MsgExecuteContract {
+			sender: self.account_id().to_string(),
+			contract: contract.clone(),
+			msg: execute.to_string().into_bytes(),
+		};
+		let msg =
+			Any { type_url: EXECUTE_CONTRACT_TYPE_URL.to_string(), value: msg.encode_to_vec() };
+		let hash = self.submit_call(vec![msg]).await?;
+		log::info!(target: "hyperspace_cosmos", "🤝 Raw packet transaction confirmed with hash: {:?}", hash);
+		Ok(())
+	}
+
 	/// Returns a stream that yields chain Block number
 	async fn subscribe_blocks(&self) -> Pin<Box<dyn Stream<Item = u64> + Send + Sync>> {
 		let ws_client = self.rpc_ws_client();
@@ -60,4 +146,26 @@ where
 	async fn increase_counters(&mut self) -> Result<(), Self::Error> {
 		unimplemented!()
 	}
+
+	/// Native denoms are funded at genesis, so there is nothing to do for them. IBC vouchers can
+	/// only be obtained by receiving a transfer.
+	async fn ensure_asset(
+		&self,
+		asset_id: Self::AssetId,
+		_mint_to: Signer,
+		_amount: u128,
+	) -> Result<(), Self::Error> {
+		if asset_id.starts_with("ibc/") {
+			return Err(Error::Custom(format!(
+				"{asset_id} is an ibc denom, it can only be transferred to {}",
+				self.name
+			)))
+		}
+		Ok(())
+	}
+
+	/// Assets are identified by their denom.
+	async fn query_asset_id(&self, ibc_denom: String) -> Result<Self::AssetId, Self::Error> {
+		Ok(ibc_denom)
+	}
 }
@@ -1,37 +1,32 @@
-use super::{
-	encode::{
-		encode_auth_info, encode_key_bytes, encode_sign_doc, encode_signer_info, encode_tx,
-		encode_tx_body,
-	},
-	key_provider::KeyEntry,
+use super::encode::{
+	encode_auth_info, encode_key_bytes, encode_sign_doc, encode_signer_info, encode_tx,
+	encode_tx_body,
 };
 use crate::error::Error;
 use core::time::Duration;
-use futures::TryFutureExt;
 use ibc::core::ics24_host::identifier::ChainId;
 use ibc_proto::{
 	cosmos::{
 		auth::v1beta1::BaseAccount,
-		tx::v1beta1::{
-			service_client::ServiceClient, Fee, SimulateRequest, SimulateResponse, Tx, TxRaw,
-		},
+		tx::v1beta1::{Fee, Tx, TxRaw},
 	},
 	google::protobuf::Any,
 };
+use primitives::signer::TransactionSigner;
 use prost::Message;
 use tendermint::Hash;
 use tendermint_rpc::{
-	endpoint::tx::Response as TxResponse, query::Query, Client, Order, Url, WebSocketClient,
+	endpoint::tx::Response as TxResponse, query::Query, Client, Order, WebSocketClient,
 };
 
-pub fn sign_tx(
-	key: KeyEntry,
+pub async fn sign_tx(
+	signer: &dyn TransactionSigner,
 	chain_id: ChainId,
 	account_info: &BaseAccount,
 	messages: Vec<Any>,
 	fee: Fee,
 ) -> Result<(Tx, TxRaw, Vec<u8>), Error> {
-	let pk_bytes = encode_key_bytes(&key)?;
+	let pk_bytes = encode_key_bytes(signer.public_key())?;
 	let signer_info = encode_signer_info(account_info.sequence, pk_bytes)?;
 
 	// Create and Encode AuthInfo
@@ -40,16 +35,16 @@ pub fn sign_tx(
 	// Create and Encode TxBody
 	let (body, body_bytes) = encode_tx_body(messages)?;
 
-	// Create and Encode TxRaw
-	let signature_bytes = encode_sign_doc(
-		key,
+	// Encode SignDoc and Create Signature
+	let sign_doc = encode_sign_doc(
 		body_bytes.clone(),
 		auth_info_bytes.clone(),
 		chain_id,
 		account_info.account_number,
 	)?;
+	let signature_bytes = signer.sign(&sign_doc).await.map_err(Error::Signer)?;
 
-	// Encode SignDoc and Create Signature
+	// Create and Encode TxRaw
 	let (tx_raw, tx_bytes) = encode_tx(body_bytes, auth_info_bytes, signature_bytes.clone())?;
 
 	let tx = Tx { body: Some(body), auth_info: Some(auth_info), signatures: vec![signature_bytes] };
@@ -57,36 +52,26 @@ pub fn sign_tx(
 	Ok((tx, tx_raw, tx_bytes))
 }
 
-pub async fn simulate_tx(
-	grpc_url: Url,
-	tx: Tx,
-	tx_bytes: Vec<u8>,
-) -> Result<SimulateResponse, Error> {
-	#[allow(deprecated)]
-	let req = SimulateRequest {
-		tx: Some(tx), // needed for simulation to go through with Cosmos SDK <  0.43
-		tx_bytes,     // needed for simulation to go through with Cosmos SDk >= 0.43
-	};
-	let mut client = ServiceClient::connect(grpc_url.clone().to_string())
-		.await
-		.map_err(|e| Error::from(e.to_string()))?;
-	let request = tonic::Request::new(req);
-
-	let response = tokio::time::timeout(
-		Duration::from_secs(15),
-		client.simulate(request).map_err(|e| Error::from(e.to_string())),
-	)
-	.await
-	.map_err(|_| Error::from("simulation timeout".to_string()))??
-	.into_inner();
-	Ok(response)
+/// Extracts the index of the message that aborted a simulated transaction from the error
+/// returned by the Cosmos SDK, e.g. `failed to execute message; message index: 2: ...`.
+pub fn failed_message_index(error: &str) -> Option<usize> {
+	const MARKER: &str = "message index: ";
+	let start = error.find(MARKER)? + MARKER.len();
+	let digits = error[start..].chars().take_while(|c| c.is_ascii_digit()).collect::<String>();
+	digits.parse().ok()
 }
 
 pub async fn broadcast_tx(rpc_client: &WebSocketClient, tx_bytes: Vec<u8>) -> Result<Hash, Error> {
-	let response = rpc_client
-		.broadcast_tx_sync(tx_bytes)
-		.await
-		.map_err(|e| Error::from(format!("failed to broadcast transaction {e:?}")))?;
+	let response = rpc_client.broadcast_tx_sync(tx_bytes).await?;
+	// rejected by `CheckTx`, e.g. with an insufficient fee
+	if response.code.is_err() {
+		return Err(Error::TxFailed {
+			hash: response.hash.to_string(),
+			codespace: response.codespace,
+			code: response.code.value(),
+			log: response.log,
+		})
+	}
 	Ok(response.hash)
 }
 
@@ -103,8 +88,7 @@ pub async fn confirm_tx(rpc_client: &WebSocketClient, tx_hash: Hash) -> Result<H
 				1, // get only the first Tx matching the query
 				Order::Ascending,
 			)
-			.await
-			.map_err(|e| Error::from(format!("failed to search for transaction {e:?}")))?;
+			.await?;
 		match response.txs.into_iter().next() {
 			None => {
 				let elapsed = start_time.elapsed();
@@ -122,20 +106,24 @@ pub async fn confirm_tx(rpc_client: &WebSocketClient, tx_hash: Hash) -> Result<H
 		}
 	};
 
-	let response_code = response.tx_result.code;
-	if response_code.is_err() {
-		return Err(Error::from(format!("transaction {tx_hash} failed with code {response_code:?}")))
+	if response.tx_result.code.is_err() {
+		return Err(Error::TxFailed {
+			hash: tx_hash.to_string(),
+			codespace: response.tx_result.codespace,
+			code: response.tx_result.code.value(),
+			log: response.tx_result.log,
+		})
 	}
 	Ok(response.hash)
 }
 
-pub fn encoded_tx_metrics(
-	key: KeyEntry,
+pub async fn encoded_tx_metrics(
+	signer: &dyn TransactionSigner,
 	chain_id: ChainId,
 	account_info: &BaseAccount,
 	fee: Fee,
 ) -> Result<(usize, usize), Error> {
-	let (_, tx_raw, _) = sign_tx(key, chain_id, account_info, vec![], fee)?;
+	let (_, tx_raw, _) = sign_tx(signer, chain_id, account_info, vec![], fee).await?;
 
 	let total_len = tx_raw.encoded_len();
 	let body_bytes_len = tx_raw.body_bytes.len();
@@ -147,3 +135,16 @@ pub fn encoded_tx_metrics(
 
 	Ok((total_len, envelope_len))
 }
+
+#[cfg(test)]
+mod tests {
+	use super::failed_message_index;
+
+	#[test]
+	fn parses_failed_message_index() {
+		let error = "status: Unknown, message: \"failed to execute message; message index: 12: \
+			receive packet verification failed: packet commitment bytes are not equal\"";
+		assert_eq!(failed_message_index(error), Some(12));
+		assert_eq!(failed_message_index("out of gas in location: WriteFlat"), None);
+	}
+}
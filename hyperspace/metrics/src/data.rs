@@ -50,7 +50,7 @@ pub struct LightClientMetrics {
 impl LightClientMetrics {
 	pub fn register(
 		client_id: &ClientId,
-		prefix: &str,
+		labels: &HashMap<String, String>,
 		registry: &Registry,
 	) -> Result<Self, PrometheusError> {
 		Ok(Self {
@@ -60,8 +60,8 @@ impl LightClientMetrics {
 						"hyperspace_light_client_revision_height",
 						"The height of the last trusted state",
 					)
-					.const_label("client_id", client_id.to_string())
-					.const_label("name", prefix.to_string()),
+					.const_labels(labels.clone())
+					.const_label("client_id", client_id.to_string()),
 				)?,
 				registry,
 			)?,
@@ -71,8 +71,8 @@ impl LightClientMetrics {
 						"hyperspace_light_client_revision",
 						"The revision of the last trusted state",
 					)
-					.const_label("client_id", client_id.to_string())
-					.const_label("name", prefix.to_string()),
+					.const_labels(labels.clone())
+					.const_label("client_id", client_id.to_string()),
 				)?,
 				registry,
 			)?,
@@ -82,8 +82,8 @@ impl LightClientMetrics {
 						"hyperspace_number_of_received_header_updates",
 						"Total number of header updates received",
 					)
-					.const_label("client_id", client_id.to_string())
-					.const_label("name", prefix.to_string()),
+					.const_labels(labels.clone())
+					.const_label("client_id", client_id.to_string()),
 				)?,
 				registry,
 			)?,
@@ -91,6 +91,37 @@ impl LightClientMetrics {
 	}
 }
 
+/// Name of the [`Metrics::finality_decode_failure`] gauge, checked by the health endpoint.
+pub const FINALITY_DECODE_FAILURE_METRIC: &str = "hyperspace_finality_decode_failure";
+/// Name of the [`Metrics::rpc_unreachable`] gauge, checked by the health endpoint.
+pub const RPC_UNREACHABLE_METRIC: &str = "hyperspace_rpc_unreachable";
+/// Name of the [`Metrics::latest_height`] gauge, checked by the health endpoint.
+pub const LATEST_HEIGHT_METRIC: &str = "hyperspace_latest_height";
+/// Name of the [`Metrics::latest_height_timestamp`] gauge, checked by the health endpoint.
+pub const LATEST_HEIGHT_TIMESTAMP_METRIC: &str = "hyperspace_latest_height_timestamp";
+/// Name of the [`Metrics::max_height_staleness`] gauge, checked by the health endpoint.
+pub const MAX_HEIGHT_STALENESS_METRIC: &str = "hyperspace_max_height_staleness";
+/// Name of the [`Metrics::counterparty_client_inactive`] gauge, checked by the health endpoint.
+pub const COUNTERPARTY_CLIENT_INACTIVE_METRIC: &str = "hyperspace_counterparty_client_inactive";
+/// Name of the [`Metrics::balance_insufficient`] gauge, checked by the health endpoint.
+pub const BALANCE_INSUFFICIENT_METRIC: &str = "hyperspace_balance_insufficient";
+/// Name of the [`Metrics::last_submission_timestamp`] gauge, checked by the health endpoint.
+pub const LAST_SUBMISSION_TIMESTAMP_METRIC: &str = "hyperspace_last_submission_timestamp";
+/// Name of the [`Metrics::liveness_timestamp`] gauge, checked by the health endpoint.
+pub const LIVENESS_TIMESTAMP_METRIC: &str = "hyperspace_liveness_timestamp";
+/// Name of the [`Metrics::max_liveness_age`] gauge, checked by the health endpoint.
+pub const MAX_LIVENESS_AGE_METRIC: &str = "hyperspace_max_liveness_age";
+/// Name of the [`Metrics::stalled`] gauge, checked by the health endpoint.
+pub const STALLED_METRIC: &str = "hyperspace_stalled";
+/// Name of the [`Metrics::metadata_mismatch`] gauge, checked by the health endpoint.
+pub const METADATA_MISMATCH_METRIC: &str = "hyperspace_metadata_mismatch";
+/// Name of the [`Metrics::pending_messages`] gauge, reported by the health endpoint.
+pub const PENDING_MESSAGES_METRIC: &str = "hyperspace_pending_messages";
+/// Name of the [`Metrics::oldest_pending_message_age`] gauge, reported by the health endpoint.
+pub const OLDEST_PENDING_MESSAGE_AGE_METRIC: &str = "hyperspace_oldest_pending_message_age";
+/// Name of the [`Metrics::acks_error_total`] counters.
+pub const ACKS_ERROR_TOTAL_METRIC: &str = "hyperspace_acks_error_total";
+
 #[derive(Clone)]
 pub struct Metrics {
 	/// Total number of "send packet" events received.
@@ -143,13 +174,76 @@ pub struct Metrics {
 
 	/// Latest processed height - helpful to prevent pushing the same event twice
 	pub latest_processed_height: Gauge<U64>,
+	/// Set to 1 while the finality notifications of the chain can't be decoded.
+	pub finality_decode_failure: Gauge<U64>,
+	/// Set to 1 while the RPC of the chain can't be reached.
+	pub rpc_unreachable: Gauge<U64>,
+	/// Latest height of the chain.
+	pub latest_height: Gauge<U64>,
+	/// Unix time in seconds at which the latest height was first seen.
+	pub latest_height_timestamp: Gauge<U64>,
+	/// Number of seconds after which a latest height that didn't change is stale.
+	pub max_height_staleness: Gauge<U64>,
+	/// Set to 1 while the client of the chain on the counterparty isn't active.
+	pub counterparty_client_inactive: Gauge<U64>,
+	/// Set to 1 while the balance of the relayer's account is below the configured minimum.
+	pub balance_insufficient: Gauge<U64>,
+	/// Unix time in seconds of the last successful submission of the chain's messages.
+	pub last_submission_timestamp: Gauge<U64>,
+	/// Unix time in seconds at which the relay loop last reported the health of the chain.
+	pub liveness_timestamp: Gauge<U64>,
+	/// Number of seconds without a health report after which the relay loop is unresponsive.
+	pub max_liveness_age: Gauge<U64>,
+	/// Set to 1 while the chain doesn't finalize or produce blocks.
+	pub stalled: Gauge<U64>,
+	/// Total number of times the chain stalled.
+	pub chain_stalled: Counter<U64>,
+	/// Number of the runtime items used by the client that don't match the live runtime.
+	pub metadata_mismatch: Gauge<U64>,
+	/// Number of authority sets the client of the chain on the counterparty is behind.
+	pub authority_set_lag: Gauge<U64>,
+	/// Number of messages relayed from the chain that wait to be submitted to the counterparty.
+	pub pending_messages: Gauge<U64>,
+	/// Number of seconds the oldest of the pending messages has been waiting.
+	pub oldest_pending_message_age: Gauge<U64>,
+	/// Time it takes to check a client update for misbehaviour, in milliseconds.
+	pub misbehaviour_check_time: Histogram,
+	/// Total number of finality proofs of the misbehaviour checks served from the cache.
+	pub finality_proof_cache_hits: Counter<U64>,
+	/// Total number of finality proofs of the misbehaviour checks fetched from the node.
+	pub finality_proof_cache_misses: Counter<U64>,
+	/// Total number of error acknowledgements written on the chain that were relayed, by the
+	/// channel they were written on.
+	pub acks_error_total: CounterVec<U64>,
 
 	/// Metrics prefix.
 	pub prefix: String,
+	/// Labels of all the metrics: the name of the chain, and the relayed path if any.
+	pub labels: HashMap<String, String>,
 }
 
 impl Metrics {
 	pub fn register(prefix: &str, registry: &Registry) -> Result<Self, PrometheusError> {
+		Self::register_with_labels(prefix, HashMap::new(), registry)
+	}
+
+	/// Registers the metrics of the chain named `prefix` on the relayed `path`, which labels all
+	/// of them, so that the metrics of several paths sharing a chain can be told apart.
+	pub fn register_for_path(
+		prefix: &str,
+		path: &str,
+		registry: &Registry,
+	) -> Result<Self, PrometheusError> {
+		let labels = HashMap::from([("path".to_string(), path.to_string())]);
+		Self::register_with_labels(prefix, labels, registry)
+	}
+
+	fn register_with_labels(
+		prefix: &str,
+		mut labels: HashMap<String, String>,
+		registry: &Registry,
+	) -> Result<Self, PrometheusError> {
+		labels.insert("name".to_string(), prefix.to_string());
 		Ok(Self {
 			number_of_received_send_packets: register(
 				Counter::with_opts(
@@ -157,7 +251,7 @@ impl Metrics {
 						format!("hyperspace_{prefix}_number_of_send_packet_events"),
 						"Total number of 'send packet' events.",
 					)
-					.const_label("name", prefix.to_string()),
+					.const_labels(labels.clone()),
 				)?,
 				registry,
 			)?,
@@ -167,7 +261,7 @@ impl Metrics {
 						format!("hyperspace_{prefix}_number_of_receive_packet_events"),
 						"Total number of 'receive packet' events.",
 					)
-					.const_label("name", prefix.to_string()),
+					.const_labels(labels.clone()),
 				)?,
 				registry,
 			)?,
@@ -177,7 +271,7 @@ impl Metrics {
 						"hyperspace_number_of_acknowledge_packet_events".to_string(),
 						"Total number of 'acknowledge packet' events.",
 					)
-					.const_label("name", prefix.to_string()),
+					.const_labels(labels.clone()),
 				)?,
 				registry,
 			)?,
@@ -187,7 +281,7 @@ impl Metrics {
 						"hyperspace_number_of_timeout_packet_events".to_string(),
 						"Total number of 'timeout packet' events.",
 					)
-					.const_label("name", prefix.to_string()),
+					.const_labels(labels.clone()),
 				)?,
 				registry,
 			)?,
@@ -199,7 +293,7 @@ impl Metrics {
 						"hyperspace_number_of_sent_packets".to_string(),
 						"Total number of sent packets",
 					)
-					.const_label("name", prefix.to_string()),
+					.const_labels(labels.clone()),
 				)?,
 				registry,
 			)?,
@@ -209,7 +303,7 @@ impl Metrics {
 						"hyperspace_number_of_sent_acknowledgments".to_string(),
 						"Total number of sent acknowledgments",
 					)
-					.const_label("name", prefix.to_string()),
+					.const_labels(labels.clone()),
 				)?,
 				registry,
 			)?,
@@ -219,7 +313,7 @@ impl Metrics {
 						"hyperspace_number_of_timed_out_packets".to_string(),
 						"Total number of timed out packets",
 					)
-					.const_label("name", prefix.to_string()),
+					.const_labels(labels.clone()),
 				)?,
 				registry,
 			)?,
@@ -229,7 +323,7 @@ impl Metrics {
 						"hyperspace_number_of_undelivered_packets".to_string(),
 						"Number of undelivered packets over time",
 					)
-					.const_label("name", prefix.to_string()),
+					.const_labels(labels.clone()),
 				)?,
 				registry,
 			)?,
@@ -239,7 +333,7 @@ impl Metrics {
 						"hyperspace_number_of_undelivered_acknowledgements".to_string(),
 						"Number of undelivered acknowledgements over time",
 					)
-					.const_label("name", prefix.to_string()),
+					.const_labels(labels.clone()),
 				)?,
 				registry,
 			)?,
@@ -250,7 +344,7 @@ impl Metrics {
 						"Gas cost for every sent tx bundle",
 					)
 					.buckets(vec![1.0, 10.0, 100.0, 1000.0, 10000.0, 100000.0, 1000000.0])
-					.const_label("name", prefix.to_string()),
+					.const_labels(labels.clone()),
 				)?,
 				registry,
 			)?,
@@ -261,7 +355,7 @@ impl Metrics {
 						"Transaction length for every sent tx bundle",
 					)
 					.buckets(vec![1.0, 10.0, 100.0, 1000.0, 10000.0, 100000.0, 1000000.0])
-					.const_label("name", prefix.to_string()),
+					.const_labels(labels.clone()),
 				)?,
 				registry,
 			)?,
@@ -273,7 +367,7 @@ impl Metrics {
 						"Time it takes to process a 'send packet' event",
 					)
 					.buckets(vec![1.0, 10.0, 100.0, 1000.0, 10000.0, 100000.0, 1000000.0])
-					.const_label("name", prefix.to_string()),
+					.const_labels(labels.clone()),
 				)?,
 				registry,
 			)?,
@@ -284,7 +378,7 @@ impl Metrics {
 						"Time it takes to process a 'receive packet' event",
 					)
 					.buckets(vec![1.0, 10.0, 100.0, 1000.0, 10000.0, 100000.0, 1000000.0])
-					.const_label("name", prefix.to_string()),
+					.const_labels(labels.clone()),
 				)?,
 				registry,
 			)?,
@@ -295,7 +389,7 @@ impl Metrics {
 						"Time it takes to process a 'acknowledge packet' event",
 					)
 					.buckets(vec![1.0, 10.0, 100.0, 1000.0, 10000.0, 100000.0, 1000000.0])
-					.const_label("name", prefix.to_string()),
+					.const_labels(labels.clone()),
 				)?,
 				registry,
 			)?,
@@ -306,7 +400,7 @@ impl Metrics {
 						"Time it takes to send and receive a packet",
 					)
 					.buckets(vec![1.0, 10.0, 100.0, 1000.0, 10000.0, 100000.0, 1000000.0])
-					.const_label("name", prefix.to_string()),
+					.const_labels(labels.clone()),
 				)?,
 				registry,
 			)?,
@@ -317,7 +411,7 @@ impl Metrics {
 						"Time it takes to send and receive an acknowledgment",
 					)
 					.buckets(vec![1.0, 10.0, 100.0, 1000.0, 10000.0, 100000.0, 1000000.0])
-					.const_label("name", prefix.to_string()),
+					.const_labels(labels.clone()),
 				)?,
 				registry,
 			)?,
@@ -328,7 +422,7 @@ impl Metrics {
 						"Time it takes to send and receive a timeout packet",
 					)
 					.buckets(vec![1.0, 10.0, 100.0, 1000.0, 10000.0, 100000.0, 1000000.0])
-					.const_label("name", prefix.to_string()),
+					.const_labels(labels.clone()),
 				)?,
 				registry,
 			)?,
@@ -339,7 +433,7 @@ impl Metrics {
 						"Average time between client updates",
 					)
 					.buckets(vec![1.0, 10.0, 100.0, 1000.0, 10000.0, 100000.0, 1000000.0])
-					.const_label("name", prefix.to_string()),
+					.const_labels(labels.clone()),
 				)?,
 				registry,
 			)?,
@@ -349,11 +443,211 @@ impl Metrics {
 						"hyperspace_latest_processed_height".to_string(),
 						"Latest processed finalized height",
 					)
-					.const_label("name", prefix.to_string()),
+					.const_labels(labels.clone()),
+				)?,
+				registry,
+			)?,
+			finality_decode_failure: register(
+				Gauge::with_opts(
+					Opts::new(
+						FINALITY_DECODE_FAILURE_METRIC.to_string(),
+						"Whether the finality notifications can't be decoded",
+					)
+					.const_labels(labels.clone()),
+				)?,
+				registry,
+			)?,
+			rpc_unreachable: register(
+				Gauge::with_opts(
+					Opts::new(
+						RPC_UNREACHABLE_METRIC.to_string(),
+						"Whether the RPC of the chain can't be reached",
+					)
+					.const_labels(labels.clone()),
+				)?,
+				registry,
+			)?,
+			latest_height: register(
+				Gauge::with_opts(
+					Opts::new(LATEST_HEIGHT_METRIC.to_string(), "Latest height of the chain")
+						.const_labels(labels.clone()),
+				)?,
+				registry,
+			)?,
+			latest_height_timestamp: register(
+				Gauge::with_opts(
+					Opts::new(
+						LATEST_HEIGHT_TIMESTAMP_METRIC.to_string(),
+						"Unix time at which the latest height was first seen",
+					)
+					.const_labels(labels.clone()),
+				)?,
+				registry,
+			)?,
+			max_height_staleness: register(
+				Gauge::with_opts(
+					Opts::new(
+						MAX_HEIGHT_STALENESS_METRIC.to_string(),
+						"Number of seconds after which the latest height is stale",
+					)
+					.const_labels(labels.clone()),
+				)?,
+				registry,
+			)?,
+			counterparty_client_inactive: register(
+				Gauge::with_opts(
+					Opts::new(
+						COUNTERPARTY_CLIENT_INACTIVE_METRIC.to_string(),
+						"Whether the client of the chain on the counterparty is not active",
+					)
+					.const_labels(labels.clone()),
+				)?,
+				registry,
+			)?,
+			balance_insufficient: register(
+				Gauge::with_opts(
+					Opts::new(
+						BALANCE_INSUFFICIENT_METRIC.to_string(),
+						"Whether the balance of the relayer is below the configured minimum",
+					)
+					.const_labels(labels.clone()),
+				)?,
+				registry,
+			)?,
+			last_submission_timestamp: register(
+				Gauge::with_opts(
+					Opts::new(
+						LAST_SUBMISSION_TIMESTAMP_METRIC.to_string(),
+						"Unix time of the last successful submission",
+					)
+					.const_labels(labels.clone()),
+				)?,
+				registry,
+			)?,
+			liveness_timestamp: register(
+				Gauge::with_opts(
+					Opts::new(
+						LIVENESS_TIMESTAMP_METRIC.to_string(),
+						"Unix time at which the relay loop last reported the health of the chain",
+					)
+					.const_labels(labels.clone()),
+				)?,
+				registry,
+			)?,
+			max_liveness_age: register(
+				Gauge::with_opts(
+					Opts::new(
+						MAX_LIVENESS_AGE_METRIC.to_string(),
+						"Number of seconds without a health report after which the relay loop is unresponsive",
+					)
+					.const_labels(labels.clone()),
+				)?,
+				registry,
+			)?,
+			stalled: register(
+				Gauge::with_opts(
+					Opts::new(
+						STALLED_METRIC.to_string(),
+						"Whether the chain doesn't finalize or produce blocks",
+					)
+					.const_labels(labels.clone()),
+				)?,
+				registry,
+			)?,
+			metadata_mismatch: register(
+				Gauge::with_opts(
+					Opts::new(
+						METADATA_MISMATCH_METRIC.to_string(),
+						"Number of the runtime items used by the client that don't match the live runtime",
+					)
+					.const_labels(labels.clone()),
+				)?,
+				registry,
+			)?,
+			chain_stalled: register(
+				Counter::with_opts(
+					Opts::new(
+						format!("hyperspace_{prefix}_chain_stalled"),
+						"Total number of times the chain stalled",
+					)
+					.const_labels(labels.clone()),
+				)?,
+				registry,
+			)?,
+			authority_set_lag: register(
+				Gauge::with_opts(
+					Opts::new(
+						format!("hyperspace_{prefix}_authority_set_lag"),
+						"Number of authority sets the client of the chain is behind",
+					)
+					.const_labels(labels.clone()),
+				)?,
+				registry,
+			)?,
+			pending_messages: register(
+				Gauge::with_opts(
+					Opts::new(
+						PENDING_MESSAGES_METRIC.to_string(),
+						"Number of messages waiting to be submitted to the counterparty",
+					)
+					.const_labels(labels.clone()),
+				)?,
+				registry,
+			)?,
+			oldest_pending_message_age: register(
+				Gauge::with_opts(
+					Opts::new(
+						OLDEST_PENDING_MESSAGE_AGE_METRIC.to_string(),
+						"Number of seconds the oldest pending message has been waiting",
+					)
+					.const_labels(labels.clone()),
+				)?,
+				registry,
+			)?,
+			misbehaviour_check_time: register(
+				Histogram::with_opts(
+					HistogramOpts::new(
+						"hyperspace_misbehaviour_check_time".to_string(),
+						"Time it takes to check a client update for misbehaviour",
+					)
+					.buckets(vec![1.0, 10.0, 100.0, 1000.0, 10000.0, 100000.0])
+					.const_labels(labels.clone()),
+				)?,
+				registry,
+			)?,
+			finality_proof_cache_hits: register(
+				Counter::with_opts(
+					Opts::new(
+						format!("hyperspace_{prefix}_finality_proof_cache_hits"),
+						"Total number of misbehaviour check finality proofs served from the cache",
+					)
+					.const_labels(labels.clone()),
+				)?,
+				registry,
+			)?,
+			finality_proof_cache_misses: register(
+				Counter::with_opts(
+					Opts::new(
+						format!("hyperspace_{prefix}_finality_proof_cache_misses"),
+						"Total number of misbehaviour check finality proofs fetched from the node",
+					)
+					.const_labels(labels.clone()),
+				)?,
+				registry,
+			)?,
+			acks_error_total: register(
+				CounterVec::new(
+					Opts::new(
+						ACKS_ERROR_TOTAL_METRIC.to_string(),
+						"Total number of relayed error acknowledgements written on the chain",
+					)
+					.const_labels(labels.clone()),
+					&["channel"],
 				)?,
 				registry,
 			)?,
 			prefix: prefix.to_string(),
+			labels,
 		})
 	}
 
@@ -397,7 +691,7 @@ impl Metrics {
 			},
 			None => {
 				let light_client_metrics =
-					LightClientMetrics::register(client_id, &self.prefix, registry)?;
+					LightClientMetrics::register(client_id, &self.labels, registry)?;
 				self.light_client_height.insert(client_id.clone(), light_client_metrics);
 				Ok(())
 			},
@@ -29,7 +29,7 @@ use std::{
 	collections::HashMap,
 	ops::DerefMut,
 	sync::{Arc, Mutex},
-	time::Instant,
+	time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 #[derive(Eq, PartialEq, Hash)]
@@ -51,6 +51,7 @@ impl From<Packet> for PacketId {
 
 pub type PacketMap = Arc<Mutex<HashMap<PacketId, Instant>>>;
 
+#[derive(Clone)]
 pub struct MetricsHandler {
 	registry: Registry,
 	metrics: Metrics,
@@ -227,6 +228,94 @@ impl MetricsHandler {
 		self.metrics.transaction_length_for_sent_tx_bundle.observe(batch_size as f64);
 	}
 
+	pub fn handle_finality_decode_failure(&self, has_failed: bool) {
+		self.metrics.finality_decode_failure.set(has_failed as u64);
+	}
+
+	/// Records the latest height of the chain, or `None` if its RPC couldn't be reached. The
+	/// height is stale once it didn't change for `max_staleness`.
+	pub fn handle_latest_height(&self, latest_height: Option<u64>, max_staleness: Duration) {
+		self.metrics.max_height_staleness.set(max_staleness.as_secs());
+		let latest_height = match latest_height {
+			Some(height) => height,
+			None => {
+				self.metrics.rpc_unreachable.set(1);
+				return
+			},
+		};
+		self.metrics.rpc_unreachable.set(0);
+		if latest_height != self.metrics.latest_height.get() {
+			self.metrics.latest_height.set(latest_height);
+			self.metrics.latest_height_timestamp.set(unix_time());
+		}
+	}
+
+	pub fn handle_counterparty_client_status(&self, is_active: bool) {
+		self.metrics.counterparty_client_inactive.set(!is_active as u64);
+	}
+
+	pub fn handle_balance(&self, is_sufficient: bool) {
+		self.metrics.balance_insufficient.set(!is_sufficient as u64);
+	}
+
+	pub fn handle_successful_submission(&self) {
+		self.metrics.last_submission_timestamp.set(unix_time());
+	}
+
+	/// Records that the relay loop is alive and reporting the health of the chain. It is
+	/// unresponsive once it didn't report for `max_age`.
+	pub fn handle_liveness(&self, max_age: Duration) {
+		self.metrics.max_liveness_age.set(max_age.as_secs());
+		self.metrics.liveness_timestamp.set(unix_time());
+	}
+
+	/// Records the number of runtime items used by the client that don't match the live runtime.
+	pub fn handle_metadata_mismatch(&self, mismatches: usize) {
+		self.metrics.metadata_mismatch.set(mismatches as u64);
+	}
+
+	/// Records whether the chain is stalled, counting the times it stalls.
+	pub fn handle_chain_stall(&self, is_stalled: bool) {
+		if is_stalled && self.metrics.stalled.get() == 0 {
+			self.metrics.chain_stalled.inc();
+		}
+		self.metrics.stalled.set(is_stalled as u64);
+	}
+
+	pub fn handle_authority_set_lag(&self, lag: u64) {
+		self.metrics.authority_set_lag.set(lag);
+	}
+
+	/// Records the number of messages waiting to be submitted to the counterparty and how long
+	/// the oldest one has been waiting.
+	pub fn handle_pending_messages(&self, count: usize, oldest_age: Option<Duration>) {
+		self.metrics.pending_messages.set(count as u64);
+		self.metrics
+			.oldest_pending_message_age
+			.set(oldest_age.unwrap_or_default().as_secs());
+	}
+
+	pub fn handle_misbehaviour_checks(
+		&self,
+		check_times: &[Duration],
+		proof_cache_hits: u64,
+		proof_cache_misses: u64,
+	) {
+		for time in check_times {
+			self.metrics.misbehaviour_check_time.observe(time.as_millis() as f64);
+		}
+		self.metrics.finality_proof_cache_hits.inc_by(proof_cache_hits);
+		self.metrics.finality_proof_cache_misses.inc_by(proof_cache_misses);
+	}
+
+	/// Counts an error acknowledgement written on the `channel_id` of the chain that was relayed.
+	pub fn handle_error_acknowledgement(&self, channel_id: &ChannelId) {
+		self.metrics
+			.acks_error_total
+			.with_label_values(&[&channel_id.to_string()])
+			.inc();
+	}
+
 	pub fn observe_last_packet_time(
 		&self,
 		packet: &Packet,
@@ -247,6 +336,10 @@ impl MetricsHandler {
 	}
 }
 
+fn unix_time() -> u64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
 fn observe_delta_time(maybe_time: &mut Option<Instant>, time_metrics: &Histogram) {
 	let now = Instant::now();
 	if let Some(last_time) = maybe_time {
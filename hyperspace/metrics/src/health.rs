@@ -0,0 +1,322 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Health of the relayed chains served by the `/health` endpoint, aggregated from the gauges
+//! published by the relay loop.
+
+use crate::data::{
+	BALANCE_INSUFFICIENT_METRIC, COUNTERPARTY_CLIENT_INACTIVE_METRIC,
+	FINALITY_DECODE_FAILURE_METRIC, LAST_SUBMISSION_TIMESTAMP_METRIC, LATEST_HEIGHT_METRIC,
+	LATEST_HEIGHT_TIMESTAMP_METRIC, LIVENESS_TIMESTAMP_METRIC, MAX_HEIGHT_STALENESS_METRIC,
+	MAX_LIVENESS_AGE_METRIC, METADATA_MISMATCH_METRIC, OLDEST_PENDING_MESSAGE_AGE_METRIC,
+	PENDING_MESSAGES_METRIC, RPC_UNREACHABLE_METRIC, STALLED_METRIC,
+};
+use prometheus::Registry;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Metrics aggregated in a [`ChainHealth`].
+const HEALTH_METRICS: [&str; 14] = [
+	FINALITY_DECODE_FAILURE_METRIC,
+	RPC_UNREACHABLE_METRIC,
+	LATEST_HEIGHT_METRIC,
+	LATEST_HEIGHT_TIMESTAMP_METRIC,
+	MAX_HEIGHT_STALENESS_METRIC,
+	COUNTERPARTY_CLIENT_INACTIVE_METRIC,
+	BALANCE_INSUFFICIENT_METRIC,
+	LAST_SUBMISSION_TIMESTAMP_METRIC,
+	STALLED_METRIC,
+	METADATA_MISMATCH_METRIC,
+	PENDING_MESSAGES_METRIC,
+	OLDEST_PENDING_MESSAGE_AGE_METRIC,
+	LIVENESS_TIMESTAMP_METRIC,
+	MAX_LIVENESS_AGE_METRIC,
+];
+
+/// Health of all the relayed chains.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HealthReport {
+	/// Whether no critical check fails on any chain.
+	pub healthy: bool,
+	pub chains: Vec<ChainHealth>,
+}
+
+/// Health of a relayed chain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChainHealth {
+	/// Name of the chain, as configured.
+	pub name: String,
+	pub rpc_reachable: bool,
+	pub latest_height: u64,
+	/// Number of seconds since the latest height changed, if it was reported yet.
+	pub height_staleness_secs: Option<u64>,
+	/// Number of seconds after which the latest height is stale, 0 disables the check.
+	pub max_height_staleness_secs: u64,
+	pub counterparty_client_active: bool,
+	pub balance_sufficient: bool,
+	pub finality_decodable: bool,
+	/// Whether the chain stopped finalizing or producing blocks, as noticed by the relay loop.
+	pub stalled: bool,
+	/// Whether the runtime items used by the client match the live runtime, as checked when the
+	/// client was created.
+	pub metadata_matches: bool,
+	/// Number of seconds since the chain's messages were last submitted successfully, if they
+	/// were. Informational only, as a path may not have anything to relay for a long time.
+	pub secs_since_last_submission: Option<u64>,
+	/// Number of the chain's messages waiting to be submitted to the counterparty.
+	/// Informational only, the relay loop applies backpressure once there are too many.
+	pub pending_messages: u64,
+	/// Number of seconds the oldest of the pending messages has been waiting.
+	pub oldest_pending_message_age_secs: u64,
+	/// Number of seconds since the relay loop last reported the health of the chain, if it did.
+	/// The other checks only reflect the last report, which is stale if the loop is stuck.
+	pub secs_since_liveness: Option<u64>,
+	/// Number of seconds without a report after which the relay loop is unresponsive, 0 disables
+	/// the check.
+	pub max_liveness_age_secs: u64,
+	/// Names of the critical checks that fail.
+	pub failing_checks: Vec<&'static str>,
+}
+
+impl ChainHealth {
+	fn new(name: String, gauges: &BTreeMap<&str, u64>, now: u64) -> Self {
+		let gauge = |metric: &str| gauges.get(metric).copied().unwrap_or_default();
+		let elapsed_since = |timestamp: u64| (timestamp > 0).then(|| now.saturating_sub(timestamp));
+
+		let mut health = Self {
+			name,
+			rpc_reachable: gauge(RPC_UNREACHABLE_METRIC) == 0,
+			latest_height: gauge(LATEST_HEIGHT_METRIC),
+			height_staleness_secs: elapsed_since(gauge(LATEST_HEIGHT_TIMESTAMP_METRIC)),
+			max_height_staleness_secs: gauge(MAX_HEIGHT_STALENESS_METRIC),
+			counterparty_client_active: gauge(COUNTERPARTY_CLIENT_INACTIVE_METRIC) == 0,
+			balance_sufficient: gauge(BALANCE_INSUFFICIENT_METRIC) == 0,
+			finality_decodable: gauge(FINALITY_DECODE_FAILURE_METRIC) == 0,
+			stalled: gauge(STALLED_METRIC) == 1,
+			metadata_matches: gauge(METADATA_MISMATCH_METRIC) == 0,
+			secs_since_last_submission: elapsed_since(gauge(LAST_SUBMISSION_TIMESTAMP_METRIC)),
+			pending_messages: gauge(PENDING_MESSAGES_METRIC),
+			oldest_pending_message_age_secs: gauge(OLDEST_PENDING_MESSAGE_AGE_METRIC),
+			secs_since_liveness: elapsed_since(gauge(LIVENESS_TIMESTAMP_METRIC)),
+			max_liveness_age_secs: gauge(MAX_LIVENESS_AGE_METRIC),
+			failing_checks: vec![],
+		};
+		let is_stale = health.max_height_staleness_secs > 0 &&
+			health.height_staleness_secs.unwrap_or_default() > health.max_height_staleness_secs;
+		let is_unresponsive = health.max_liveness_age_secs > 0 &&
+			health.secs_since_liveness.unwrap_or_default() > health.max_liveness_age_secs;
+		let checks = [
+			("rpc_unreachable", !health.rpc_reachable),
+			("stale_height", is_stale),
+			("counterparty_client_inactive", !health.counterparty_client_active),
+			("balance_insufficient", !health.balance_sufficient),
+			("finality_decode_failure", !health.finality_decodable),
+			("chain_stalled", health.stalled),
+			("metadata_mismatch", !health.metadata_matches),
+			("relay_loop_unresponsive", is_unresponsive),
+		];
+		health.failing_checks =
+			checks.into_iter().filter(|(_, fails)| *fails).map(|(check, _)| check).collect();
+		health
+	}
+
+	pub fn is_healthy(&self) -> bool {
+		self.failing_checks.is_empty()
+	}
+}
+
+/// Aggregates the health gauges of the chains registered in `registry` at the unix time `now`,
+/// in seconds.
+pub fn check_health(registry: &Registry, now: u64) -> HealthReport {
+	let families = registry.gather();
+	let mut chains = BTreeMap::<String, BTreeMap<&str, u64>>::new();
+	for family in &families {
+		let metric_name = match HEALTH_METRICS.iter().find(|name| **name == family.get_name()) {
+			Some(name) => *name,
+			None => continue,
+		};
+		for metric in family.get_metric() {
+			let chain = metric
+				.get_label()
+				.iter()
+				.find(|label| label.get_name() == "name")
+				.map(|label| label.get_value().to_string())
+				.unwrap_or_default();
+			chains
+				.entry(chain)
+				.or_default()
+				.insert(metric_name, metric.get_gauge().get_value() as u64);
+		}
+	}
+	let chains = chains
+		.into_iter()
+		.map(|(name, gauges)| ChainHealth::new(name, &gauges, now))
+		.collect::<Vec<_>>();
+	HealthReport { healthy: chains.iter().all(ChainHealth::is_healthy), chains }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{data::Metrics, handler::MetricsHandler};
+	use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+	fn now() -> u64 {
+		SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+	}
+
+	#[test]
+	fn chains_are_unhealthy_once_a_critical_check_fails() {
+		let registry = Registry::new();
+		let handler_a =
+			MetricsHandler::new(registry.clone(), Metrics::register("a", &registry).unwrap());
+		let handler_b =
+			MetricsHandler::new(registry.clone(), Metrics::register("b", &registry).unwrap());
+		for handler in [&handler_a, &handler_b] {
+			handler.handle_latest_height(Some(10), Duration::from_secs(60));
+			handler.handle_counterparty_client_status(true);
+			handler.handle_balance(true);
+		}
+
+		let report = check_health(&registry, now());
+		assert!(report.healthy);
+		assert_eq!(report.chains.len(), 2);
+		assert_eq!(report.chains[0].latest_height, 10);
+		assert_eq!(report.chains[0].secs_since_last_submission, None);
+
+		// the RPC of chain b is stopped
+		handler_b.handle_latest_height(None, Duration::from_secs(60));
+		handler_a.handle_balance(false);
+		let report = check_health(&registry, now());
+		assert!(!report.healthy);
+		assert_eq!(report.chains[0].name, "a");
+		assert_eq!(report.chains[0].failing_checks, vec!["balance_insufficient"]);
+		assert_eq!(report.chains[1].name, "b");
+		assert_eq!(report.chains[1].failing_checks, vec!["rpc_unreachable"]);
+		// the last known height is still reported
+		assert_eq!(report.chains[1].latest_height, 10);
+
+		handler_b.handle_latest_height(Some(11), Duration::from_secs(60));
+		handler_a.handle_balance(true);
+		assert!(check_health(&registry, now()).healthy);
+	}
+
+	#[test]
+	fn heights_are_stale_once_they_stop_changing() {
+		let registry = Registry::new();
+		let metrics = Metrics::register("a", &registry).unwrap();
+		metrics.latest_height.set(10);
+		metrics.latest_height_timestamp.set(1_000);
+		metrics.max_height_staleness.set(60);
+		metrics.last_submission_timestamp.set(100);
+
+		let report = check_health(&registry, 1_060);
+		assert!(report.healthy);
+		assert_eq!(report.chains[0].height_staleness_secs, Some(60));
+		// submissions don't affect the health
+		assert_eq!(report.chains[0].secs_since_last_submission, Some(960));
+
+		let report = check_health(&registry, 1_061);
+		assert!(!report.healthy);
+		assert_eq!(report.chains[0].failing_checks, vec!["stale_height"]);
+
+		// the check is disabled without a maximum staleness
+		metrics.max_height_staleness.set(0);
+		assert!(check_health(&registry, 1_061).healthy);
+	}
+
+	#[test]
+	fn chains_are_unhealthy_once_the_relay_loop_stops_reporting() {
+		let registry = Registry::new();
+		let metrics = Metrics::register("a", &registry).unwrap();
+		// the gauges of the last report look healthy, but the loop stopped updating them
+		metrics.latest_height.set(10);
+		metrics.latest_height_timestamp.set(1_000);
+		metrics.liveness_timestamp.set(1_000);
+		metrics.max_liveness_age.set(90);
+
+		let report = check_health(&registry, 1_090);
+		assert!(report.healthy);
+		assert_eq!(report.chains[0].secs_since_liveness, Some(90));
+
+		let report = check_health(&registry, 1_091);
+		assert!(!report.healthy);
+		assert_eq!(report.chains[0].failing_checks, vec!["relay_loop_unresponsive"]);
+
+		let handler = MetricsHandler::new(registry.clone(), metrics);
+		handler.handle_liveness(Duration::from_secs(90));
+		assert!(check_health(&registry, now()).healthy);
+	}
+
+	#[test]
+	fn stalled_chains_are_unhealthy_until_they_resume() {
+		let registry = Registry::new();
+		let metrics = Metrics::register("a", &registry).unwrap();
+		let handler = MetricsHandler::new(registry.clone(), metrics.clone());
+		handler.handle_chain_stall(true);
+		handler.handle_chain_stall(true);
+		let report = check_health(&registry, now());
+		assert!(!report.healthy);
+		assert_eq!(report.chains[0].failing_checks, vec!["chain_stalled"]);
+		// the chain stalled once
+		assert_eq!(metrics.chain_stalled.get(), 1);
+
+		handler.handle_chain_stall(false);
+		assert!(check_health(&registry, now()).healthy);
+		handler.handle_chain_stall(true);
+		assert_eq!(metrics.chain_stalled.get(), 2);
+	}
+
+	#[test]
+	fn pending_messages_are_reported_without_affecting_the_health() {
+		let registry = Registry::new();
+		let metrics = Metrics::register("a", &registry).unwrap();
+		let handler = MetricsHandler::new(registry.clone(), metrics);
+		handler.handle_pending_messages(1200, Some(Duration::from_secs(90)));
+		let report = check_health(&registry, now());
+		assert!(report.healthy);
+		assert_eq!(report.chains[0].pending_messages, 1200);
+		assert_eq!(report.chains[0].oldest_pending_message_age_secs, 90);
+
+		handler.handle_pending_messages(0, None);
+		let report = check_health(&registry, now());
+		assert_eq!(report.chains[0].pending_messages, 0);
+		assert_eq!(report.chains[0].oldest_pending_message_age_secs, 0);
+	}
+
+	#[test]
+	fn finality_decode_failures_are_critical() {
+		let registry = Registry::new();
+		let metrics = Metrics::register("a", &registry).unwrap();
+		metrics.finality_decode_failure.set(1);
+		let report = check_health(&registry, now());
+		assert!(!report.healthy);
+		assert_eq!(report.chains[0].failing_checks, vec!["finality_decode_failure"]);
+	}
+
+	#[test]
+	fn metadata_mismatches_are_critical() {
+		let registry = Registry::new();
+		let metrics = Metrics::register("a", &registry).unwrap();
+		let handler = MetricsHandler::new(registry.clone(), metrics);
+		handler.handle_metadata_mismatch(2);
+		let report = check_health(&registry, now());
+		assert!(!report.healthy);
+		assert!(!report.chains[0].metadata_matches);
+		assert_eq!(report.chains[0].failing_checks, vec!["metadata_mismatch"]);
+
+		handler.handle_metadata_mismatch(0);
+		assert!(check_health(&registry, now()).healthy);
+	}
+}
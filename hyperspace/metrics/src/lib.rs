@@ -14,6 +14,7 @@
 
 pub mod data;
 pub mod handler;
+pub mod health;
 
 use hyper::{
 	http::StatusCode,
@@ -31,7 +32,10 @@ pub use prometheus::{
 	Registry,
 };
 use prometheus::{core::Collector, Encoder, TextEncoder};
-use std::net::SocketAddr;
+use std::{
+	net::SocketAddr,
+	time::{SystemTime, UNIX_EPOCH},
+};
 
 pub fn register<T: Clone + Collector + 'static>(
 	metric: T,
@@ -71,6 +75,15 @@ async fn request_metrics(req: Request<Body>, registry: Registry) -> Result<Respo
 			.header("Content-Type", encoder.format_type())
 			.body(Body::from(buffer))
 			.map_err(Error::Http)
+	} else if req.uri().path() == "/health" {
+		let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+		let report = health::check_health(&registry, now);
+		let status = if report.healthy { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+		Response::builder()
+			.status(status)
+			.header("Content-Type", "application/json")
+			.body(Body::from(serde_json::to_vec(&report).expect("report is serializable")))
+			.map_err(Error::Http)
 	} else {
 		Response::builder()
 			.status(StatusCode::NOT_FOUND)
@@ -110,3 +123,52 @@ async fn init_prometheus_with_listener(
 
 	server.await.map_err(Into::into)
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{data::Metrics, handler::MetricsHandler};
+	use std::time::Duration;
+	use tokio::{
+		io::{AsyncReadExt, AsyncWriteExt},
+		net::{TcpListener, TcpStream},
+	};
+
+	async fn get(addr: SocketAddr, path: &str) -> String {
+		let mut stream = TcpStream::connect(addr).await.unwrap();
+		stream
+			.write_all(format!("GET {path} HTTP/1.0\r\n\r\n").as_bytes())
+			.await
+			.unwrap();
+		let mut response = String::new();
+		stream.read_to_string(&mut response).await.unwrap();
+		response
+	}
+
+	#[tokio::test]
+	async fn health_endpoint_flips_when_a_chain_rpc_stops() {
+		let registry = Registry::new();
+		let handler_a =
+			MetricsHandler::new(registry.clone(), Metrics::register("a", &registry).unwrap());
+		let handler_b =
+			MetricsHandler::new(registry.clone(), Metrics::register("b", &registry).unwrap());
+		handler_a.handle_latest_height(Some(10), Duration::from_secs(60));
+		handler_b.handle_latest_height(Some(20), Duration::from_secs(60));
+
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let addr = listener.local_addr().unwrap();
+		tokio::spawn(init_prometheus_with_listener(listener, registry));
+
+		let response = get(addr, "/health").await;
+		assert!(response.lines().next().unwrap().ends_with("200 OK"), "{response}");
+		assert!(response.contains(r#""healthy":true"#), "{response}");
+
+		handler_b.handle_latest_height(None, Duration::from_secs(60));
+		let response = get(addr, "/health").await;
+		assert!(
+			response.lines().next().unwrap().ends_with("503 Service Unavailable"),
+			"{response}"
+		);
+		assert!(response.contains(r#""failing_checks":["rpc_unreachable"]"#), "{response}");
+	}
+}
@@ -0,0 +1,92 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A grandpa client only verifies the justifications of the authority set it knows, and learns
+//! the next set from the update finalizing the last block of its set. When the relay chain moved
+//! past several sets since the last update, e.g. after a runtime upgrade, the client is caught up
+//! with an update at the last block of each set it missed, in order.
+
+use std::future::Future;
+
+/// Returns the last block finalized by the authority set `set_id` in `from..=to`, i.e. the first
+/// block at which the relay chain state has a later set, given the id of the set in the state of
+/// each block. Returns `None` if the set wasn't replaced by `to`.
+pub async fn find_authority_set_change<F, Fut, E>(
+	set_id: u64,
+	from: u32,
+	to: u32,
+	mut set_id_at: F,
+) -> Result<Option<u32>, E>
+where
+	F: FnMut(u32) -> Fut,
+	Fut: Future<Output = Result<u64, E>>,
+{
+	if from > to || set_id_at(to).await? <= set_id {
+		return Ok(None)
+	}
+	// the set ids only increase, so the change is found by bisection
+	let (mut low, mut high) = (from, to);
+	while low < high {
+		let middle = low + (high - low) / 2;
+		if set_id_at(middle).await? > set_id {
+			high = middle;
+		} else {
+			low = middle + 1;
+		}
+	}
+	Ok(Some(high))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use futures::executor::block_on;
+	use std::{cell::Cell, convert::Infallible};
+
+	/// Relay chain whose authority set changes at the given blocks, starting with set 0.
+	fn set_id_at(changes: &[u32], block: u32) -> u64 {
+		changes.iter().filter(|change| **change <= block).count() as u64
+	}
+
+	#[test]
+	fn the_last_block_of_each_missed_set_is_found() {
+		let changes = [120, 250, 251, 400];
+		let queries = Cell::new(0);
+		let query = |block| {
+			queries.set(queries.get() + 1);
+			async move { Ok::<_, Infallible>(set_id_at(&changes, block)) }
+		};
+		let mut boundaries = vec![];
+		let (mut set_id, mut from) = (0, 100);
+		while let Some(change) =
+			block_on(find_authority_set_change(set_id, from, 1000, query)).unwrap()
+		{
+			boundaries.push(change);
+			set_id += 1;
+			from = change;
+		}
+		assert_eq!(boundaries, changes);
+		// each change takes a logarithmic number of queries
+		assert!(queries.get() < 60, "{} queries", queries.get());
+	}
+
+	#[test]
+	fn sets_that_were_not_replaced_have_no_change() {
+		let changes = [120];
+		let query = |block| async move { Ok::<_, Infallible>(set_id_at(&changes, block)) };
+		assert_eq!(block_on(find_authority_set_change(1, 120, 1000, query)), Ok(None));
+		assert_eq!(block_on(find_authority_set_change(0, 100, 110, query)), Ok(None));
+		assert_eq!(block_on(find_authority_set_change(0, 120, 120, query)), Ok(Some(120)));
+	}
+}
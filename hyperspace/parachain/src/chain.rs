@@ -11,21 +11,32 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
-
-use super::{error::Error, signer::ExtrinsicSigner, ParachainClient};
-use crate::{parachain::UncheckedExtrinsic, provider::TransactionId, FinalityProtocol};
+use super::{
+	error::{rpc_error_of, Error},
+	signer::ExtrinsicSigner,
+	ParachainClient,
+};
+use crate::{
+	finality_proof_cache::CachedFinalityProof, parachain::UncheckedExtrinsic, FinalityProtocol,
+};
 use anyhow::anyhow;
 use codec::{Decode, Encode};
 use finality_grandpa::BlockNumberOps;
 use finality_grandpa_rpc::GrandpaApiClient;
-use futures::{Stream, StreamExt, TryFutureExt};
-use grandpa_light_client_primitives::{FinalityProof, ParachainHeaderProofs};
+use futures::{Stream, StreamExt};
+use grandpa_light_client::{
+	verify_grandpa_finality_proof, verify_parachain_headers_with_grandpa_finality_proof,
+};
+use grandpa_light_client_primitives::{
+	FinalityProof, ParachainHeaderProofs, ParachainHeadersWithFinalityProof,
+};
 use ibc::{
 	core::{
 		ics02_client::{
 			events::UpdateClient,
 			msgs::{update_client::MsgUpdateAnyClient, ClientMsg},
 		},
+		ics24_host::identifier::ClientId,
 		ics26_routing::msgs::Ics26Envelope,
 	},
 	events::IbcEvent,
@@ -33,13 +44,26 @@ use ibc::{
 	Height,
 };
 use ibc_proto::google::protobuf::Any;
-use ics10_grandpa::client_message::{ClientMessage, Misbehaviour, RelayChainHeader};
+use ibc_rpc::IbcApiClient;
+use ics10_grandpa::{
+	client_def::validate_unknown_headers,
+	client_message::{ClientMessage, Header as GrandpaHeader, Misbehaviour, RelayChainHeader},
+	client_state::ClientState as GrandpaClientState,
+};
+use ics11_beefy::client_message::{BeefyHeader, ClientMessage as BeefyClientMessage};
 use itertools::Itertools;
-use jsonrpsee_ws_client::WsClientBuilder;
+use jsonrpsee::{core::client::ClientT, rpc_params};
 use light_client_common::config::{EventRecordT, RuntimeCall, RuntimeTransactions};
-use pallet_ibc::light_clients::AnyClientMessage;
+use pallet_ibc::light_clients::{AnyClientMessage, AnyClientState, HostFunctionsManager};
 use primitives::{
-	mock::LocalClientTypes, Chain, CommonClientState, IbcProvider, MisbehaviourHandler,
+	any_registry::known_messages,
+	finality::{
+		decode_finality_stream, resubscribing_stream, DecodeFailureTracker,
+		FINALITY_DECODE_FAILURE_THRESHOLD,
+	},
+	misbehaviour::{unix_time_now, DetectedMisbehaviour, EvidenceCheck, MisbehaviourReport},
+	mock::LocalClientTypes,
+	replay, utils, Chain, CommonClientState, IbcProvider, MisbehaviourHandler, SimulationResult,
 };
 use sc_consensus_beefy_rpc::BeefyApiClient;
 use sp_core::{twox_128, H256};
@@ -50,12 +74,12 @@ use sp_runtime::{
 use std::{collections::BTreeMap, fmt::Display, pin::Pin, sync::Arc, time::Duration};
 use subxt::{
 	config::{
-		extrinsic_params::{BaseExtrinsicParamsBuilder, Era},
-		ExtrinsicParams, Header as HeaderT, Header,
+		extrinsic_params::BaseExtrinsicParamsBuilder, ExtrinsicParams, Header as HeaderT, Header,
 	},
 	events::Phase,
 };
 use tokio::time::sleep;
+use tokio_stream::wrappers::ReceiverStream;
 use transaction_payment_rpc::TransactionPaymentApiClient;
 use transaction_payment_runtime_api::RuntimeDispatchInfo;
 
@@ -70,6 +94,155 @@ type BeefyJustification =
 #[derive(Clone, serde::Serialize, serde::Deserialize)]
 struct JustificationNotification(sp_core::Bytes);
 
+/// A subscription to the encoded justifications of the relay chain.
+type JustificationSubscription = Pin<Box<dyn Stream<Item = Result<Vec<u8>, Error>> + Send>>;
+
+/// Connection to the relay chain the justifications are subscribed with. A failed subscription
+/// is renewed over a new connection, since the one it was on may have been dropped.
+#[derive(Clone)]
+struct RelayConnection {
+	url: String,
+	/// Name the calls of the connection are recorded or replayed as
+	transport: String,
+	client: Arc<std::sync::Mutex<Arc<jsonrpsee_ws_client::WsClient>>>,
+}
+
+impl RelayConnection {
+	fn new(url: String, transport: String, client: Arc<jsonrpsee_ws_client::WsClient>) -> Self {
+		Self { url, transport, client: Arc::new(std::sync::Mutex::new(client)) }
+	}
+
+	fn client(&self) -> Arc<jsonrpsee_ws_client::WsClient> {
+		self.client.lock().unwrap().clone()
+	}
+
+	async fn reconnect(&self) -> Result<Arc<jsonrpsee_ws_client::WsClient>, Error> {
+		let client = Arc::new(
+			replay::connect(&self.url, &self.transport)
+				.await
+				.map_err(|e| Error::from(e.to_string()))?,
+		);
+		*self.client.lock().unwrap() = client.clone();
+		Ok(client)
+	}
+}
+
+/// Subscribes to the grandpa justifications, keeping only every third one.
+async fn subscribe_grandpa_justifications(
+	relay_ws_client: Arc<jsonrpsee_ws_client::WsClient>,
+) -> Result<JustificationSubscription, Error> {
+	let subscription =
+		GrandpaApiClient::<JustificationNotification, H256, u32>::subscribe_justifications(
+			&*relay_ws_client,
+		)
+		.await?;
+	let mut skipped = 0;
+	Ok(Box::pin(
+		subscription
+			.map(|notification| {
+				notification
+					.map(|JustificationNotification(sp_core::Bytes(justification))| justification)
+					.map_err(Error::from)
+			})
+			.filter(move |notification| {
+				if notification.is_err() {
+					return futures::future::ready(true)
+				}
+				skipped = (skipped + 1) % 3;
+				futures::future::ready(skipped == 0)
+			}),
+	))
+}
+
+async fn subscribe_beefy_justifications(
+	relay_ws_client: Arc<jsonrpsee_ws_client::WsClient>,
+) -> Result<JustificationSubscription, Error> {
+	let subscription = BeefyApiClient::<JustificationNotification, H256>::subscribe_justifications(
+		&*relay_ws_client,
+	)
+	.await?;
+	Ok(Box::pin(subscription.map(|notification| {
+		notification
+			.map(|JustificationNotification(sp_core::Bytes(commitment))| commitment)
+			.map_err(Error::from)
+	})))
+}
+
+/// Returns the justification of the latest finalized block of the relay chain, used to catch up
+/// after resubscribing to the justifications.
+async fn latest_grandpa_justification(
+	relay_ws_client: Arc<jsonrpsee_ws_client::WsClient>,
+) -> Result<Option<Vec<u8>>, Error> {
+	let hash: H256 = relay_ws_client.request("chain_getFinalizedHead", rpc_params![]).await?;
+	let header = relay_ws_client
+		.request::<Option<RelayChainHeader>, _>("chain_getHeader", rpc_params![hash])
+		.await?
+		.ok_or_else(|| Error::NotFound(format!("No header found for hash: {hash:?}")))?;
+	let Some(encoded) = GrandpaApiClient::<JustificationNotification, H256, u32>::prove_finality(
+		&*relay_ws_client,
+		header.number,
+	)
+	.await?
+	else {
+		return Ok(None)
+	};
+	let proof = FinalityProof::<RelayChainHeader>::decode(&mut &encoded.0[..])?;
+	Ok(Some(proof.justification))
+}
+
+/// Yields the encoded grandpa justifications of the relay chain, resubscribing over a new
+/// connection whenever the subscription fails. The catch up justification of a resubscription is
+/// always yielded.
+async fn grandpa_justifications(
+	connection: RelayConnection,
+) -> Result<impl Stream<Item = Vec<u8>>, Error> {
+	let subscription = subscribe_grandpa_justifications(connection.client()).await?;
+	let resubscribe = connection.clone();
+	Ok(resubscribing_stream(
+		"grandpa",
+		subscription,
+		move || {
+			let connection = resubscribe.clone();
+			async move { subscribe_grandpa_justifications(connection.reconnect().await?).await }
+		},
+		move || latest_grandpa_justification(connection.client()),
+	))
+}
+
+/// Yields the encoded signed beefy commitments of the relay chain, resubscribing over a new
+/// connection whenever the subscription fails.
+async fn beefy_justifications(
+	connection: RelayConnection,
+) -> Result<impl Stream<Item = Vec<u8>>, Error> {
+	let subscription = subscribe_beefy_justifications(connection.client()).await?;
+	// the next signed commitment covers the commitments that were skipped
+	Ok(resubscribing_stream(
+		"beefy",
+		subscription,
+		move || {
+			let connection = connection.clone();
+			async move { subscribe_beefy_justifications(connection.reconnect().await?).await }
+		},
+		|| futures::future::ready(Ok(None)),
+	))
+}
+
+/// Forwards `payloads` from a task of its own, so that the stream handed out is `Sync`.
+fn forward_payloads(
+	payloads: impl Stream<Item = Vec<u8>> + Send + 'static,
+) -> ReceiverStream<Vec<u8>> {
+	let (tx, rx) = tokio::sync::mpsc::channel(32);
+	tokio::spawn(async move {
+		let mut payloads = Box::pin(payloads);
+		while let Some(payload) = payloads.next().await {
+			if tx.send(payload).await.is_err() {
+				break
+			}
+		}
+	});
+	ReceiverStream::new(rx)
+}
+
 #[async_trait::async_trait]
 impl<T: light_client_common::config::Config + Send + Sync + Clone + 'static> Chain
 	for ParachainClient<T>
@@ -103,105 +276,92 @@ where
 	}
 
 	async fn estimate_weight(&self, messages: Vec<Any>) -> Result<u64, Self::Error> {
-		let extrinsic = {
-			// todo: put this in utils
-			let signer = ExtrinsicSigner::<T, Self>::new(
-				self.key_store.clone(),
-				self.key_type_id.clone(),
-				self.public_key.clone(),
-			);
-
-			let messages = messages
-				.into_iter()
-				.map(|msg| Any { type_url: msg.type_url.clone(), value: msg.value })
-				.collect::<Vec<_>>();
-
-			let tx_params = BaseExtrinsicParamsBuilder::new()
-				.tip(T::Tip::from(100_000u128))
-				.era(Era::Immortal, self.para_client.genesis_hash());
-			let call = T::Tx::ibc_deliver(messages);
-			self.para_client
-				.tx()
-				.create_signed(&call, &signer, tx_params.into())
-				.await?
-				.encoded()
-				.to_vec()
-		};
+		let extrinsic = self.signed_deliver_extrinsic(messages).await?;
 		let dispatch_info = TransactionPaymentApiClient::<
 			H256,
 			RuntimeDispatchInfo<u128, sp_weights::Weight>,
 		>::query_info(&*self.para_ws_client, extrinsic.into(), None)
 		.await
-		.map_err(|e| Error::from(format!("Rpc Error From Estimating weight {:?}", e)))?;
+		.map_err(Error::from)?;
 		Ok(dispatch_info.weight.ref_time())
 	}
 
+	async fn simulate(&self, messages: Vec<Any>) -> Result<SimulationResult, Self::Error> {
+		let estimated_weight = self.estimate_weight(messages.clone()).await?;
+		// `ibc::deliver` succeeds even when some of its messages fail, so a dry run of the
+		// extrinsic can't tell whether they would. The messages are executed through the runtime
+		// api instead, the runtimes without it fail the call as unsupported.
+		let outcomes = IbcApiClient::<
+			u32,
+			H256,
+			<T as light_client_common::config::Config>::AssetId,
+		>::simulate_messages(
+			&*self.para_ws_client,
+			messages.into_iter().map(|msg| (msg.type_url, sp_core::Bytes(msg.value))).collect(),
+		)
+		.await
+		.map_err(Error::from)?;
+		for (index, error) in outcomes.iter().enumerate() {
+			if let Some(error) = error {
+				log::debug!(target: "hyperspace_parachain", "Message {index} fails on {}: {error}", self.name);
+			}
+		}
+		Ok(SimulationResult {
+			estimated_weight,
+			message_results: outcomes.iter().map(Option::is_none).collect(),
+		})
+	}
+
 	async fn finality_notifications(
 		&self,
 	) -> Result<
 		Pin<Box<dyn Stream<Item = <Self as IbcProvider>::FinalityEvent> + Send + Sync>>,
 		Error,
 	> {
+		let relay_metadata_refresher = metadata_refresher(self.relay_client.clone());
+		let relay_connection = RelayConnection::new(
+			self.relay_chain_rpc_url.clone(),
+			format!("{}-relay", self.name),
+			self.relay_ws_client.clone(),
+		);
 		match self.finality_protocol {
 			FinalityProtocol::Grandpa => {
-				let subscription =
-					GrandpaApiClient::<JustificationNotification, sp_core::H256, u32>::subscribe_justifications(
-						&*self.relay_ws_client,
-					)
-						.await?
-						.chunks(3)
-						.map(|mut notifs| notifs.remove(notifs.len() - 1)); // skip every 3 finality notifications
-
-				let stream = subscription.filter_map(|justification_notif| {
-					let encoded_justification = match justification_notif {
-						Ok(JustificationNotification(sp_core::Bytes(justification))) =>
-							justification,
-						Err(err) => {
-							log::error!("Failed to fetch Justification: {}", err);
-							return futures::future::ready(None)
-						},
-					};
-
-					let justification =
-						match GrandpaJustification::decode(&mut &*encoded_justification) {
-							Ok(j) => j,
-							Err(err) => {
-								log::error!("Grandpa Justification scale decode error: {}", err);
-								return futures::future::ready(None)
-							},
-						};
-					futures::future::ready(Some(Self::FinalityEvent::Grandpa(justification)))
-				});
+				let payloads = forward_payloads(grandpa_justifications(relay_connection).await?);
+
+				let tracker = DecodeFailureTracker::new(
+					"grandpa",
+					FINALITY_DECODE_FAILURE_THRESHOLD,
+					self.common_state.finality_health.clone(),
+				);
+				let stream = decode_finality_stream(
+					payloads,
+					tracker,
+					|justification| {
+						GrandpaJustification::decode(&mut &*justification)
+							.map(|justification| Self::FinalityEvent::Grandpa(justification))
+					},
+					relay_metadata_refresher,
+				);
 
 				Ok(Box::pin(Box::new(stream)))
 			},
 			FinalityProtocol::Beefy => {
-				let subscription =
-					BeefyApiClient::<JustificationNotification, sp_core::H256>::subscribe_justifications(
-						&*self.relay_ws_client,
-					)
-						.await
-						.expect("Failed to subscribe to beefy justifications");
-
-				let stream = subscription.filter_map(|commitment_notification| {
-					let encoded_commitment = match commitment_notification {
-						Ok(JustificationNotification(sp_core::Bytes(commitment))) => commitment,
-						Err(err) => {
-							log::error!("Failed to fetch Commitment: {}", err);
-							return futures::future::ready(None)
-						},
-					};
-
-					let signed_commitment =
-						match BeefyJustification::decode(&mut &*encoded_commitment) {
-							Ok(c) => c,
-							Err(err) => {
-								log::error!("SignedCommitment scale decode error: {}", err);
-								return futures::future::ready(None)
-							},
-						};
-					futures::future::ready(Some(Self::FinalityEvent::Beefy(signed_commitment)))
-				});
+				let payloads = forward_payloads(beefy_justifications(relay_connection).await?);
+
+				let tracker = DecodeFailureTracker::new(
+					"beefy",
+					FINALITY_DECODE_FAILURE_THRESHOLD,
+					self.common_state.finality_health.clone(),
+				);
+				let stream = decode_finality_stream(
+					payloads,
+					tracker,
+					|commitment| {
+						BeefyJustification::decode(&mut &*commitment)
+							.map(|signed_commitment| Self::FinalityEvent::Beefy(signed_commitment))
+					},
+					relay_metadata_refresher,
+				);
 
 				Ok(Box::pin(Box::new(stream)))
 			},
@@ -218,11 +378,11 @@ where
 		log::debug!(target: "hyperspace_parachain", "Sending message: {messages_urls_c}");
 
 		let call = T::Tx::ibc_deliver(messages.clone());
-		let (ext_hash, block_hash) = self.submit_call(call).await?;
+		let tx_id = self.submit_call(call).await?;
 
-		log::debug!(target: "hyperspace_parachain", "Submitted extrinsic (hash: {:?}) to block {:?}", ext_hash, block_hash);
+		log::debug!(target: "hyperspace_parachain", "Submitted extrinsic (hash: {:?}) to block {:?}", tx_id.ext_hash, tx_id.block_hash);
 
-		Ok(TransactionId { ext_hash, block_hash })
+		Ok(tx_id)
 	}
 
 	async fn query_client_message(&self, update: UpdateClient) -> Result<AnyClientMessage, Error> {
@@ -285,12 +445,10 @@ where
 			})
 			.ok_or_else(|| Error::from("No update client event found".to_owned()))?;
 
-		let block = self
-			.para_client
-			.rpc()
-			.block(Some(block_hash.into()))
-			.await?
-			.ok_or_else(|| Error::from(format!("Block not found for hash {:?}", block_hash)))?;
+		let block =
+			self.para_client.rpc().block(Some(block_hash.into())).await?.ok_or_else(|| {
+				Error::NotFound(format!("Block not found for hash {:?}", block_hash))
+			})?;
 
 		let extrinsic_opaque =
 			block.block.extrinsics.get(transaction_index).expect("Extrinsic not found");
@@ -306,10 +464,14 @@ where
 		let message = messages
 			.get(event_index)
 			.ok_or_else(|| Error::from(format!("Message index {} out of bounds", event_index)))?;
-		let envelope = Ics26Envelope::<LocalClientTypes>::try_from(Any {
-			type_url: message.type_url.clone(),
-			value: message.value.clone(),
-		});
+		let message = Any { type_url: message.type_url.clone(), value: message.value.clone() };
+		if !known_messages().check_known(&message, "the client updates checked for misbehaviour") {
+			return Err(Error::from(format!(
+				"Undecodable client update of type {}",
+				message.type_url
+			)))
+		}
+		let envelope = Ics26Envelope::<LocalClientTypes>::try_from(message);
 		match envelope {
 			Ok(Ics26Envelope::Ics2Msg(ClientMsg::UpdateClient(update_msg))) =>
 				return Ok(update_msg.client_message),
@@ -323,22 +485,40 @@ where
 		block_height
 	}
 
-	async fn handle_error(&mut self, error: &anyhow::Error) -> Result<(), anyhow::Error> {
-		let err_str = if let Some(rpc_err) = error.downcast_ref::<Error>() {
-			match rpc_err {
-				Error::RpcError(s) => s.clone(),
-				_ => "".to_string(),
-			}
-		} else {
-			error.to_string()
-		};
-		log::debug!(target: "hyperspace", "Handling error: {err_str}");
+	async fn verify_client_update<C: Chain>(
+		&self,
+		counterparty: &C,
+		client_message: AnyClientMessage,
+	) -> Result<(), anyhow::Error> {
+		match client_message.unpack_recursive_into() {
+			AnyClientMessage::Grandpa(ClientMessage::Header(header)) => {
+				let client_state =
+					query_grandpa_client_state(counterparty, self.client_id()).await?;
+				verify_grandpa_header(client_state, header)
+			},
+			AnyClientMessage::Beefy(BeefyClientMessage::Header(BeefyHeader {
+				mmr_update_proof: Some(mmr_update),
+				..
+			})) => self
+				.verify_beefy_commitment(&mmr_update.signed_commitment)
+				.await?
+				.map(|_| ())
+				.map_err(|e| anyhow!("Invalid beefy commitment: {e:?}")),
+			_ => Ok(()),
+		}
+	}
 
-		if err_str.contains("MaxSlotsExceeded") {
-			self.common_state.rpc_call_delay = self.common_state.rpc_call_delay * 2;
-		} else if err_str.contains("RestartNeeded") || err_str.contains("restart required") {
-			self.reconnect().await?;
-			self.common_state.rpc_call_delay = self.common_state.rpc_call_delay * 2;
+	async fn handle_error(&mut self, error: &anyhow::Error) -> Result<(), anyhow::Error> {
+		log::debug!(target: "hyperspace", "Handling error: {error:?}");
+		match rpc_error_of(error) {
+			Some(jsonrpsee::core::Error::MaxSlotsExceeded) => {
+				self.common_state.rpc_call_delay = self.common_state.rpc_call_delay * 2;
+			},
+			Some(jsonrpsee::core::Error::RestartNeeded(_)) => {
+				self.reconnect().await?;
+				self.common_state.rpc_call_delay = self.common_state.rpc_call_delay * 2;
+			},
+			_ => {},
 		}
 
 		Ok(())
@@ -346,16 +526,10 @@ where
 
 	async fn reconnect(&mut self) -> anyhow::Result<()> {
 		let relay_ws_client = Arc::new(
-			WsClientBuilder::default()
-				.build(&self.relay_chain_rpc_url)
-				.await
-				.map_err(|e| Error::from(format!("Rpc Error {:?}", e)))?,
+			replay::connect(&self.relay_chain_rpc_url, &format!("{}-relay", self.name)).await?,
 		);
 		let para_ws_client = Arc::new(
-			WsClientBuilder::default()
-				.build(&self.parachain_rpc_url)
-				.await
-				.map_err(|e| Error::from(format!("Rpc Error {:?}", e)))?,
+			replay::connect(&self.parachain_rpc_url, &format!("{}-para", self.name)).await?,
 		);
 
 		let para_client = subxt::OnlineClient::from_rpc_client(para_ws_client.clone()).await?;
@@ -371,6 +545,17 @@ where
 		Ok(())
 	}
 
+	async fn await_ready(&self, timeout: Duration) -> Result<(), anyhow::Error> {
+		utils::await_height_advance(
+			|| async { self.latest_height_and_timestamp().await.map(|(height, _)| height) },
+			timeout,
+			utils::READY_POLL_INTERVAL,
+		)
+		.await
+		.map(|_| ())
+		.map_err(|e| anyhow!("{} at {} isn't ready: {e}", self.name, self.parachain_rpc_url))
+	}
+
 	fn common_state(&self) -> &CommonClientState {
 		&self.common_state
 	}
@@ -406,16 +591,34 @@ where
 		&self,
 		counterparty: &C,
 		client_message: AnyClientMessage,
-	) -> Result<(), anyhow::Error> {
+	) -> Result<Option<DetectedMisbehaviour>, anyhow::Error> {
 		let client_message = client_message.unpack_recursive_into();
 		match client_message {
 			AnyClientMessage::Grandpa(ClientMessage::Header(header)) => {
+				let _timer = self.common_state.misbehaviour_checks.start_check();
 				let base_header = header
 					.finality_proof
 					.unknown_headers
 					.iter()
 					.min_by_key(|h| h.number)
 					.expect("unknown_headers always contain at least one header; qed");
+				let update_target = header
+					.finality_proof
+					.unknown_headers
+					.iter()
+					.map(|h| h.number)
+					.max()
+					.expect("unknown_headers always contain at least one header; qed");
+				if self
+					.finality_proof_cache
+					.is_canonical(update_target, header.finality_proof.block)
+				{
+					log::trace!(
+						target: "hyperspace_parachain",
+						"Skipping misbehaviour check of canonical block {update_target}"
+					);
+					return Ok(None)
+				}
 
 				let common_ancestor_header = self
 					.relay_client
@@ -427,22 +630,38 @@ where
 					})?;
 
 				let common_ancestor_block_number = u32::from(common_ancestor_header.number());
-				let encoded =
-					GrandpaApiClient::<JustificationNotification, H256, u32>::prove_finality(
-						&*self.relay_ws_client,
+				let update_block = header.finality_proof.block;
+				let (trusted_proof, cache_hit) = self
+					.finality_proof_cache
+					.get_or_fetch(
 						common_ancestor_block_number + 1,
+						update_target,
+						|block| async move {
+							let encoded = GrandpaApiClient::<
+								JustificationNotification,
+								H256,
+								u32,
+							>::prove_finality(&*self.relay_ws_client, block)
+							.await?
+							.ok_or_else(|| {
+								anyhow!("No justification found for block: {:?}", update_block)
+							})?
+							.0;
+							let proof =
+								FinalityProof::<RelayChainHeader>::decode(&mut &encoded[..])?;
+							let justification =
+								GrandpaJustification::decode(&mut &*proof.justification)?;
+							Ok::<_, anyhow::Error>(CachedFinalityProof {
+								target: justification.commit.target_number,
+								encoded: encoded.to_vec(),
+							})
+						},
 					)
-					.await?
-					.ok_or_else(|| {
-						anyhow!(
-							"No justification found for block: {:?}",
-							header.finality_proof.block
-						)
-					})?
-					.0;
+					.await?;
+				self.common_state.misbehaviour_checks.observe_proof_cache(cache_hit);
 
 				let mut trusted_finality_proof =
-					FinalityProof::<RelayChainHeader>::decode(&mut &encoded[..])?;
+					FinalityProof::<RelayChainHeader>::decode(&mut &trusted_proof.encoded[..])?;
 				let trusted_justification =
 					GrandpaJustification::decode(&mut &*trusted_finality_proof.justification)?;
 				let to_block = trusted_justification.commit.target_number;
@@ -457,6 +676,7 @@ where
 
 				let base_header_hash = base_header.hash();
 				if base_header_hash != trusted_base_header_hash.into() {
+					let detected_at = unix_time_now();
 					log::warn!(
 						"Found misbehaviour on client {}: {:?} != {:?}",
 						self.client_id
@@ -496,24 +716,454 @@ where
 						));
 					}
 
+					// the client verifies the evidence against the authority set it stores
+					let client_state =
+						query_grandpa_client_state(counterparty, self.client_id()).await?;
+					let check = check_misbehaviour_evidence(
+						&client_state,
+						&header.finality_proof,
+						&trusted_finality_proof,
+					);
+					if self.finality_proof_cache.record_evidence_check(check) {
+						log::warn!(
+							"Misbehaviour evidence for client {} verified against authority set {}: submitted finality proof valid: {}, trusted finality proof valid: {}",
+							self.client_id(),
+							check.set_id,
+							check.submitted_proof_valid,
+							check.trusted_proof_valid,
+						);
+					}
+
+					let first_proof = header.finality_proof.encode();
+					let second_proof = trusted_finality_proof.encode();
 					let misbehaviour = ClientMessage::Misbehaviour(Misbehaviour {
 						first_finality_proof: header.finality_proof,
 						second_finality_proof: trusted_finality_proof,
 					});
 
-					counterparty
-						.submit(vec![MsgUpdateAnyClient::<LocalClientTypes>::new(
+					let message = check.is_valid().then(|| {
+						MsgUpdateAnyClient::<LocalClientTypes>::new(
 							self.client_id(),
-							AnyClientMessage::Grandpa(misbehaviour.clone()),
+							AnyClientMessage::Grandpa(misbehaviour),
 							counterparty.account_id(),
 						)
-						.to_any()])
-						.map_err(|e| anyhow!("Failed to submit misbehaviour report: {:?}", e))
-						.await?;
+						.to_any()
+					});
+					return Ok(Some(DetectedMisbehaviour {
+						report: MisbehaviourReport {
+							chain: counterparty.name().to_string(),
+							client_id: self.client_id(),
+							first_height: update_target.into(),
+							second_height: to_block.into(),
+							first_proof,
+							second_proof,
+							detected_at,
+							tx_id: None,
+							evidence_check: Some(check),
+						},
+						message,
+					}))
+				} else {
+					self.finality_proof_cache.insert_canonical(from_block, base_header_hash);
+					self.finality_proof_cache
+						.insert_canonical(to_block, trusted_justification.commit.target_hash);
+				}
+			},
+			AnyClientMessage::Beefy(BeefyClientMessage::Header(BeefyHeader {
+				mmr_update_proof: Some(mmr_update),
+				..
+			})) => {
+				let _timer = self.common_state.misbehaviour_checks.start_check();
+				let signed_commitment = mmr_update.signed_commitment;
+				if let Err(e) = self.verify_beefy_commitment(&signed_commitment).await? {
+					let detected_at = unix_time_now();
+					log::warn!(
+						"Found misbehaviour on client {}: commitment of block {} isn't signed by the relay chain authorities: {e:?}",
+						self.client_id(),
+						signed_commitment.commitment.block_number,
+					);
+					// the beefy client has no misbehaviour evidence to freeze it with, the update
+					// is only reported
+					let height = u64::from(signed_commitment.commitment.block_number);
+					return Ok(Some(DetectedMisbehaviour {
+						report: MisbehaviourReport {
+							chain: counterparty.name().to_string(),
+							client_id: self.client_id(),
+							first_height: height,
+							second_height: height,
+							first_proof: signed_commitment.encode(),
+							second_proof: vec![],
+							detected_at,
+							tx_id: None,
+							evidence_check: None,
+						},
+						message: None,
+					}))
 				}
 			},
 			_ => {},
 		}
-		Ok(())
+		Ok(None)
+	}
+}
+
+/// Verifies the finality proofs of misbehaviour evidence against the authority set of the
+/// `client_state` of the client of the parachain, as the client does when the evidence is
+/// submitted. The evidence is only submitted if both proofs are valid.
+fn check_misbehaviour_evidence(
+	client_state: &GrandpaClientState<HostFunctionsManager>,
+	submitted_proof: &FinalityProof<RelayChainHeader>,
+	trusted_proof: &FinalityProof<RelayChainHeader>,
+) -> EvidenceCheck {
+	let verify = |proof: &FinalityProof<RelayChainHeader>| {
+		verify_grandpa_finality_proof::<RelayChainHeader, HostFunctionsManager>(
+			client_state.current_set_id,
+			&client_state.current_authorities,
+			proof,
+		)
+		.map_err(|e| log::debug!("Finality proof of misbehaviour evidence is invalid: {e}"))
+		.is_ok()
+	};
+	EvidenceCheck {
+		set_id: client_state.current_set_id,
+		submitted_proof_valid: verify(submitted_proof),
+		trusted_proof_valid: verify(trusted_proof),
+	}
+}
+
+/// Verifies a grandpa `header` against the `client_state` of the client of the parachain, as
+/// the client does when the header is submitted.
+fn verify_grandpa_header(
+	client_state: GrandpaClientState<HostFunctionsManager>,
+	header: GrandpaHeader,
+) -> Result<(), anyhow::Error> {
+	if client_state.para_id as u64 != header.height.revision_number {
+		return Err(anyhow!(
+			"Para id mismatch: expected {}, got {}",
+			client_state.para_id,
+			header.height.revision_number
+		))
+	}
+	validate_unknown_headers(&header.finality_proof).map_err(|e| anyhow!("{e}"))?;
+	let latest_relay_height = client_state.latest_relay_height;
+	let target = header.finality_proof.block;
+	let headers_with_finality_proof = ParachainHeadersWithFinalityProof {
+		finality_proof: header.finality_proof,
+		parachain_headers: header.parachain_headers,
+		latest_para_height: header.height.revision_height as u32,
+	};
+	verify_parachain_headers_with_grandpa_finality_proof::<RelayChainHeader, HostFunctionsManager>(
+		client_state.into(),
+		headers_with_finality_proof,
+	)
+	.map_err(|e| {
+		anyhow!(
+			"Update from relay chain block {latest_relay_height} to {target:?} at {} is invalid: {e}",
+			header.height
+		)
+	})?;
+	Ok(())
+}
+
+/// Queries the grandpa client state for `client_id` on the `counterparty` chain.
+async fn query_grandpa_client_state<C: Chain>(
+	counterparty: &C,
+	client_id: ClientId,
+) -> Result<GrandpaClientState<HostFunctionsManager>, anyhow::Error> {
+	let (latest_height, ..) = counterparty
+		.latest_height_and_timestamp()
+		.await
+		.map_err(|e| anyhow!("Failed to query latest height on {}: {e}", counterparty.name()))?;
+	let response = counterparty
+		.query_client_state(latest_height, client_id.clone())
+		.await
+		.map_err(|e| anyhow!("Failed to query client state {client_id}: {e}"))?;
+	let client_state = response
+		.client_state
+		.ok_or_else(|| anyhow!("Client state {client_id} not found"))?;
+	match AnyClientState::decode_recursive(client_state, |cs| {
+		matches!(cs, AnyClientState::Grandpa(_))
+	}) {
+		Some(AnyClientState::Grandpa(client_state)) => Ok(client_state),
+		_ => Err(anyhow!("Client {client_id} is not a grandpa client")),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use finality_grandpa::{Precommit, SignedPrecommit};
+	use grandpa_light_client_primitives::{justification, Commit};
+	use ibc::signer::Signer;
+	use sp_consensus_grandpa::{AuthorityId, AuthoritySignature};
+	use sp_core::{ed25519, Pair};
+	use std::str::FromStr;
+	use tendermint_proto::Protobuf;
+
+	const PARA_ID: u32 = 2000;
+	const SET_ID: u64 = 1;
+	const ROUND: u64 = 1;
+
+	fn authorities() -> Vec<ed25519::Pair> {
+		["//Alice", "//Bob", "//Charlie"]
+			.iter()
+			.map(|seed| ed25519::Pair::from_string(seed, None).unwrap())
+			.collect()
+	}
+
+	/// Client of the parachain on the counterparty, which knows relay chain block 10.
+	fn client_state() -> GrandpaClientState<HostFunctionsManager> {
+		GrandpaClientState {
+			latest_relay_height: 10,
+			latest_relay_hash: H256::repeat_byte(1),
+			para_id: PARA_ID,
+			current_set_id: SET_ID,
+			current_authorities: authorities()
+				.iter()
+				.map(|pair| (AuthorityId::from(pair.public()), 1))
+				.collect(),
+			..Default::default()
+		}
+	}
+
+	/// Update finalizing relay chain blocks 11 to 13, signed by all the authorities.
+	fn header() -> GrandpaHeader {
+		header_with_state_root(Default::default())
+	}
+
+	/// [`header`] with relay chain headers of the given `state_root`, which conflicts with it
+	/// unless the state root is the default one.
+	fn header_with_state_root(state_root: H256) -> GrandpaHeader {
+		let mut parent_hash = H256::repeat_byte(1);
+		let mut unknown_headers = vec![];
+		for number in 11..=13 {
+			let header = RelayChainHeader {
+				parent_hash,
+				number,
+				state_root,
+				extrinsics_root: Default::default(),
+				digest: Default::default(),
+			};
+			parent_hash = header.hash();
+			unknown_headers.push(header);
+		}
+		let target = unknown_headers.last().unwrap().clone();
+		let precommit = Precommit { target_hash: target.hash(), target_number: target.number };
+		let message = finality_grandpa::Message::Precommit(precommit.clone());
+		let payload = sp_consensus_grandpa::localized_payload(ROUND, SET_ID, &message);
+		let precommits = authorities()
+			.iter()
+			.map(|pair| SignedPrecommit {
+				precommit: precommit.clone(),
+				signature: AuthoritySignature::from(pair.sign(&payload)),
+				id: AuthorityId::from(pair.public()),
+			})
+			.collect();
+		let justification = justification::GrandpaJustification::<RelayChainHeader> {
+			round: ROUND,
+			commit: Commit::<RelayChainHeader> {
+				target_hash: target.hash(),
+				target_number: target.number,
+				precommits,
+			},
+			votes_ancestries: vec![],
+		};
+		GrandpaHeader {
+			finality_proof: FinalityProof {
+				block: target.hash(),
+				justification: justification.encode(),
+				unknown_headers,
+			},
+			parachain_headers: Default::default(),
+			height: Height::new(PARA_ID as u64, 0),
+		}
+	}
+
+	/// Replaces the justification of `header` by one where a precommit is signed for another
+	/// round.
+	fn corrupt_justification(header: &mut GrandpaHeader) {
+		let mut justification = justification::GrandpaJustification::<RelayChainHeader>::decode(
+			&mut &*header.finality_proof.justification,
+		)
+		.unwrap();
+		let precommit = &mut justification.commit.precommits[0];
+		let message = finality_grandpa::Message::Precommit(precommit.precommit.clone());
+		let payload = sp_consensus_grandpa::localized_payload(ROUND + 1, SET_ID, &message);
+		precommit.signature = AuthoritySignature::from(authorities()[0].sign(&payload));
+		header.finality_proof.justification = justification.encode();
+	}
+
+	#[test]
+	fn valid_updates_pass_pre_validation() {
+		verify_grandpa_header(client_state(), header()).unwrap();
+	}
+
+	#[test]
+	fn updates_with_a_corrupted_justification_fail_pre_validation() {
+		let mut header = header();
+		corrupt_justification(&mut header);
+
+		// the update is decoded from the message submitted to the counterparty
+		let msg = MsgUpdateAnyClient::<LocalClientTypes>::new(
+			ClientId::new("10-grandpa", 0).unwrap(),
+			AnyClientMessage::Grandpa(ClientMessage::Header(header)),
+			Signer::from_str("relayer").unwrap(),
+		);
+		let msg = MsgUpdateAnyClient::<LocalClientTypes>::decode_vec(&msg.to_any().value).unwrap();
+		let AnyClientMessage::Grandpa(ClientMessage::Header(header)) =
+			msg.client_message.unpack_recursive_into()
+		else {
+			panic!("not a grandpa header")
+		};
+		let error = verify_grandpa_header(client_state(), header).unwrap_err();
+		assert!(error.to_string().contains("is invalid"), "{error}");
+	}
+
+	#[test]
+	fn updates_of_another_parachain_fail_pre_validation() {
+		let mut header = header();
+		header.height = Height::new(PARA_ID as u64 + 1, 0);
+		assert!(verify_grandpa_header(client_state(), header).is_err());
+	}
+
+	#[test]
+	fn evidence_valid_for_the_stored_authority_set_is_submitted() {
+		let submitted = header_with_state_root(H256::repeat_byte(2)).finality_proof;
+		let trusted = header().finality_proof;
+		assert_ne!(submitted.block, trusted.block);
+
+		let check = check_misbehaviour_evidence(&client_state(), &submitted, &trusted);
+		assert_eq!(
+			check,
+			EvidenceCheck {
+				set_id: SET_ID,
+				submitted_proof_valid: true,
+				trusted_proof_valid: true
+			}
+		);
+		assert!(check.is_valid());
+	}
+
+	#[test]
+	fn evidence_of_another_authority_set_is_not_submitted() {
+		let submitted = header_with_state_root(H256::repeat_byte(2)).finality_proof;
+		let trusted = header().finality_proof;
+		// the client moved on to the next authority set, which signed neither proof
+		let client_state = GrandpaClientState { current_set_id: SET_ID + 1, ..client_state() };
+
+		let check = check_misbehaviour_evidence(&client_state, &submitted, &trusted);
+		assert_eq!(
+			check,
+			EvidenceCheck {
+				set_id: SET_ID + 1,
+				submitted_proof_valid: false,
+				trusted_proof_valid: false
+			}
+		);
+		assert!(!check.is_valid());
+	}
+
+	#[test]
+	fn evidence_with_an_invalid_trusted_proof_is_not_submitted() {
+		let submitted = header_with_state_root(H256::repeat_byte(2)).finality_proof;
+		let mut trusted = header();
+		corrupt_justification(&mut trusted);
+
+		let check =
+			check_misbehaviour_evidence(&client_state(), &submitted, &trusted.finality_proof);
+		assert!(check.submitted_proof_valid);
+		assert!(!check.trusted_proof_valid);
+		assert!(!check.is_valid());
+	}
+
+	/// Justification of the catch up after the relay chain node was restarted for `generation`.
+	const CATCH_UP: u8 = 0xff;
+
+	/// Serves the grandpa RPCs of a relay chain node on `addr`. The node sends justifications
+	/// `[generation, 1]` to `[generation, 6]` to each subscriber and proves the finality of the
+	/// latest block with justification `[generation, CATCH_UP]`.
+	async fn serve_relay_chain(
+		addr: std::net::SocketAddr,
+		generation: u8,
+	) -> jsonrpsee::server::ServerHandle {
+		let server = jsonrpsee::server::ServerBuilder::default().build(addr).await.unwrap();
+		let mut module = jsonrpsee::RpcModule::new(generation);
+		let header = RelayChainHeader {
+			parent_hash: Default::default(),
+			number: 10,
+			state_root: Default::default(),
+			extrinsics_root: Default::default(),
+			digest: Default::default(),
+		};
+		let hash = header.hash();
+		module.register_method("chain_getFinalizedHead", move |_, _| Ok(hash)).unwrap();
+		module
+			.register_method("chain_getHeader", move |_, _| Ok(Some(header.clone())))
+			.unwrap();
+		module
+			.register_method("grandpa_proveFinality", move |_, generation| {
+				let proof = FinalityProof::<RelayChainHeader> {
+					block: hash,
+					justification: vec![*generation, CATCH_UP],
+					unknown_headers: vec![],
+				};
+				Ok(Some(sp_core::Bytes(proof.encode())))
+			})
+			.unwrap();
+		module
+			.register_subscription(
+				"grandpa_subscribeJustifications",
+				"grandpa_justifications",
+				"grandpa_unsubscribeJustifications",
+				|_, mut sink, generation| {
+					sink.accept()?;
+					let generation = *generation;
+					tokio::spawn(async move {
+						for number in 1..=6 {
+							sink.send(&sp_core::Bytes(vec![generation, number])).unwrap();
+						}
+						// the subscription stays open until the node stops
+						futures::future::pending::<()>().await;
+						drop(sink);
+					});
+					Ok(())
+				},
+			)
+			.unwrap();
+		server.start(module).unwrap()
+	}
+
+	#[tokio::test]
+	async fn grandpa_justifications_survive_relay_chain_restarts() {
+		let addr = {
+			let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+			listener.local_addr().unwrap()
+		};
+		let url = format!("ws://{addr}");
+		let mut node = serve_relay_chain(addr, 0).await;
+		let client = Arc::new(replay::connect_with(None, &url, "relay").await.unwrap());
+		let connection = RelayConnection::new(url, "relay".to_string(), client);
+		let payloads = grandpa_justifications(connection).await.unwrap();
+		let mut payloads = Box::pin(payloads);
+
+		tokio::time::timeout(Duration::from_secs(60), async {
+			for generation in 0..3 {
+				if generation > 0 {
+					// the node dropped the connection of the previous subscription, which is
+					// renewed over a new one and caught up first
+					assert_eq!(payloads.next().await, Some(vec![generation, CATCH_UP]));
+				}
+				// every third justification is kept
+				assert_eq!(payloads.next().await, Some(vec![generation, 3]));
+				assert_eq!(payloads.next().await, Some(vec![generation, 6]));
+				if generation < 2 {
+					node.stop().unwrap();
+					node.stopped().await;
+					node = serve_relay_chain(addr, generation + 1).await;
+				}
+			}
+		})
+		.await
+		.expect("The justifications stopped after a restart of the relay chain node");
 	}
 }
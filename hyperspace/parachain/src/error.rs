@@ -13,6 +13,7 @@
 // limitations under the License.
 
 use ibc::{core::ics02_client, timestamp::ParseTimestampError};
+use primitives::{ErrorKind, HasErrorKind};
 use sp_runtime::traits::BlakeTwo256;
 use sp_trie::TrieError;
 use std::num::ParseIntError;
@@ -37,6 +38,12 @@ pub enum Error {
 	/// subxt error
 	#[error("Subxt error: {0:?}")]
 	Subxt(#[from] subxt::Error),
+	/// The extrinsic was included, but its dispatch failed
+	#[error("Extrinsic failed: {0}")]
+	ExtrinsicFailed(subxt::error::DispatchError),
+	/// The extrinsic was dispatched, but pallet-ibc failed to handle one of its messages
+	#[error("Ibc message failed: {}", ibc_error_message(.0))]
+	IbcMessageFailed(pallet_ibc::errors::IbcError),
 	/// subxt rpc error
 	#[error("Rpc threw an error")]
 	SubxtRRpc(#[from] subxt::error::RpcError),
@@ -49,6 +56,12 @@ pub enum Error {
 	/// Custom error
 	#[error("{0}")]
 	Custom(String),
+	/// The requested value doesn't exist on the chain
+	#[error("{0}")]
+	NotFound(String),
+	/// The signer of the relayer failed to sign
+	#[error("Signer error: {0}")]
+	Signer(primitives::error::Error),
 	#[error("Ibc channel error")]
 	IbcChannel(#[from] ibc::core::ics04_channel::error::Error),
 	/// Error querying packets
@@ -87,3 +100,135 @@ impl From<String> for Error {
 		Self::Custom(error)
 	}
 }
+
+impl Error {
+	/// The error of the websocket client of the node behind this error, if any.
+	pub fn rpc_error(&self) -> Option<&jsonrpsee::core::Error> {
+		match self {
+			Error::JosnrpseeError(e) => Some(e),
+			Error::Subxt(subxt::Error::Rpc(e)) | Error::SubxtRRpc(e) => client_error(e),
+			_ => None,
+		}
+	}
+}
+
+fn client_error(error: &subxt::error::RpcError) -> Option<&jsonrpsee::core::Error> {
+	match error {
+		subxt::error::RpcError::ClientError(e) => e.downcast_ref(),
+		_ => None,
+	}
+}
+
+/// The error of the websocket client of the node behind an error of the relay loop, if any.
+pub fn rpc_error_of(error: &anyhow::Error) -> Option<&jsonrpsee::core::Error> {
+	error.chain().find_map(|e| {
+		if let Some(e) = e.downcast_ref::<Error>() {
+			e.rpc_error()
+		} else if let Some(subxt::Error::Rpc(e)) = e.downcast_ref::<subxt::Error>() {
+			client_error(e)
+		} else {
+			e.downcast_ref()
+		}
+	})
+}
+
+impl HasErrorKind for Error {
+	fn kind(&self) -> ErrorKind {
+		match self {
+			Error::RpcError(_) => ErrorKind::Transport,
+			Error::NotFound(_) => ErrorKind::NotFound,
+			Error::Subxt(e) => e.kind(),
+			Error::SubxtRRpc(e) => e.kind(),
+			Error::JosnrpseeError(e) => e.kind(),
+			Error::Signer(e) => e.kind(),
+			Error::Codec(_) |
+			Error::Hex(_) |
+			Error::ClientStateRehydration(_) |
+			Error::ParseIntError(_) |
+			Error::ParseTimestamp(_) => ErrorKind::Decode,
+			Error::PalletNotFound(_) | Error::CallNotFound(_) | Error::MetadataError(_) =>
+				ErrorKind::Unsupported,
+			Error::Custom(_) |
+			Error::QueryPackets { .. } |
+			Error::ExtrinsicFailed(_) |
+			Error::IbcMessageFailed(_) |
+			Error::TrieProof(_) |
+			Error::IbcChannel(_) |
+			Error::HeaderConstruction(_) |
+			Error::IbcClient(_) |
+			Error::BeefyProver(_) |
+			Error::Ics20Error(_) => ErrorKind::Execution,
+		}
+	}
+}
+
+/// Describes an error recorded by pallet-ibc, whose message is the debug output of the error of
+/// the ibc handler.
+fn ibc_error_message(error: &pallet_ibc::errors::IbcError) -> String {
+	use pallet_ibc::errors::IbcError;
+	let (kind, message) = match error {
+		IbcError::Ics02Client { message } => ("client", message),
+		IbcError::Ics03Connection { message } => ("connection", message),
+		IbcError::Ics04Channel { message } => ("channel", message),
+		IbcError::Ics20FungibleTokenTransfer { message } => ("transfer", message),
+		IbcError::UnknownMessageTypeUrl { message } => ("unknown message type url", message),
+		IbcError::MalformedMessageBytes { message } => ("malformed message", message),
+	};
+	format!("{kind} error: {}", String::from_utf8_lossy(message))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn errors_are_classified() {
+		let cases = [
+			(Error::JosnrpseeError(jsonrpsee::core::Error::RequestTimeout), ErrorKind::Transport),
+			(
+				Error::JosnrpseeError(jsonrpsee::core::Error::RestartNeeded(
+					"connection closed".to_string(),
+				)),
+				ErrorKind::Transport,
+			),
+			(
+				Error::Subxt(subxt::Error::Rpc(subxt::error::RpcError::ClientError(Box::new(
+					jsonrpsee::core::Error::MaxSlotsExceeded,
+				)))),
+				ErrorKind::Transport,
+			),
+			(
+				Error::Signer(primitives::error::Error::RemoteSignerUnavailable(
+					"request timed out".to_string(),
+				)),
+				ErrorKind::Transport,
+			),
+			(
+				Error::JosnrpseeError(jsonrpsee::core::Error::MethodNotFound(
+					"ibc_queryClientState".to_string(),
+				)),
+				ErrorKind::Unsupported,
+			),
+			(Error::Custom("Module error: Ibc::ProcessingError".to_string()), ErrorKind::Execution),
+			// the message of an error doesn't change its kind
+			(Error::Custom("restart required".to_string()), ErrorKind::Execution),
+			(Error::NotFound("Client state not found".to_string()), ErrorKind::NotFound),
+			(Error::Codec("Not enough data to fill buffer".into()), ErrorKind::Decode),
+			(Error::Hex(hex::FromHexError::OddLength), ErrorKind::Decode),
+			(Error::PalletNotFound("Ibc"), ErrorKind::Unsupported),
+			(Error::CallNotFound("deliver"), ErrorKind::Unsupported),
+		];
+		for (error, kind) in cases {
+			assert_eq!(error.kind(), kind, "{error}");
+		}
+		// the relay loop reacts to the errors of the websocket client
+		let error = anyhow::Error::from(Error::SubxtRRpc(subxt::error::RpcError::ClientError(
+			Box::new(jsonrpsee::core::Error::MaxSlotsExceeded),
+		)));
+		assert!(matches!(rpc_error_of(&error), Some(jsonrpsee::core::Error::MaxSlotsExceeded)));
+		let error = anyhow::Error::from(subxt::Error::Rpc(subxt::error::RpcError::ClientError(
+			Box::new(jsonrpsee::core::Error::RestartNeeded("connection closed".to_string())),
+		)));
+		assert!(matches!(rpc_error_of(&error), Some(jsonrpsee::core::Error::RestartNeeded(_))));
+	}
+}
@@ -0,0 +1,221 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The misbehaviour checker compares every grandpa client update against a finality proof
+//! fetched from the relay chain with `prove_finality`, which is expensive for the node.
+//! Consecutive updates share most of their ancestry, so a trusted proof is reused for all the
+//! updates starting in the same bucket of relay chain blocks, as long as it finalizes their
+//! target. Updates whose target was already verified to be canonical aren't checked again.
+
+use primitives::misbehaviour::EvidenceCheck;
+use sp_core::H256;
+use std::{
+	collections::BTreeMap,
+	future::Future,
+	sync::{Arc, Mutex},
+};
+
+/// Default number of relay chain blocks sharing a cached finality proof.
+pub const DEFAULT_FINALITY_PROOF_CACHE_BUCKET_SIZE: u32 = 256;
+
+/// Maximum number of buckets with a cached finality proof.
+const MAX_CACHED_PROOFS: usize = 64;
+
+/// Maximum number of relay chain blocks remembered as canonical.
+const MAX_CANONICAL_HEADERS: usize = 1024;
+
+/// An encoded finality proof returned by `prove_finality`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedFinalityProof {
+	/// Number of the block finalized by the proof's justification
+	pub target: u32,
+	pub encoded: Vec<u8>,
+}
+
+#[derive(Debug)]
+struct Cache {
+	bucket_size: u32,
+	/// Proofs keyed by the bucket of the block they were requested for
+	proofs: BTreeMap<u32, CachedFinalityProof>,
+	/// Hashes of the relay chain blocks verified to be canonical, keyed by number
+	canonical: BTreeMap<u32, H256>,
+	/// Outcome of the last local verification of misbehaviour evidence
+	evidence_check: Option<EvidenceCheck>,
+}
+
+/// Cache of the trusted finality proofs of the misbehaviour checker, shared between clones of
+/// the client.
+#[derive(Debug, Clone)]
+pub struct FinalityProofCache(Arc<Mutex<Cache>>);
+
+impl Default for FinalityProofCache {
+	fn default() -> Self {
+		Self::new(DEFAULT_FINALITY_PROOF_CACHE_BUCKET_SIZE)
+	}
+}
+
+impl FinalityProofCache {
+	pub fn new(bucket_size: u32) -> Self {
+		Self(Arc::new(Mutex::new(Cache {
+			bucket_size: bucket_size.max(1),
+			proofs: Default::default(),
+			canonical: Default::default(),
+			evidence_check: None,
+		})))
+	}
+
+	/// Returns `true` if the block was verified to be canonical.
+	pub fn is_canonical(&self, number: u32, hash: H256) -> bool {
+		self.0.lock().unwrap().canonical.get(&number) == Some(&hash)
+	}
+
+	pub fn insert_canonical(&self, number: u32, hash: H256) {
+		let mut cache = self.0.lock().unwrap();
+		cache.canonical.insert(number, hash);
+		while cache.canonical.len() > MAX_CANONICAL_HEADERS {
+			cache.canonical.pop_first();
+		}
+	}
+
+	/// Records the outcome of a verification of misbehaviour evidence. Returns `true` if it
+	/// differs from the previous one.
+	pub fn record_evidence_check(&self, check: EvidenceCheck) -> bool {
+		self.0.lock().unwrap().evidence_check.replace(check) != Some(check)
+	}
+
+	/// Returns a finality proof usable to check an update whose unknown headers start at
+	/// `from_block` and end at `update_target`, calling `fetch` with `from_block` only if no
+	/// cached proof finalizes `update_target`. The returned flag is `true` on a cache hit.
+	pub async fn get_or_fetch<F, Fut, E>(
+		&self,
+		from_block: u32,
+		update_target: u32,
+		fetch: F,
+	) -> Result<(CachedFinalityProof, bool), E>
+	where
+		F: FnOnce(u32) -> Fut,
+		Fut: Future<Output = Result<CachedFinalityProof, E>>,
+	{
+		let bucket = {
+			let cache = self.0.lock().unwrap();
+			let bucket = from_block / cache.bucket_size;
+			match cache.proofs.get(&bucket) {
+				Some(proof) if proof.target >= update_target => return Ok((proof.clone(), true)),
+				_ => bucket,
+			}
+		};
+
+		let proof = fetch(from_block).await?;
+		let mut cache = self.0.lock().unwrap();
+		let cached = cache.proofs.entry(bucket).or_insert_with(|| proof.clone());
+		if cached.target < proof.target {
+			*cached = proof.clone();
+		}
+		while cache.proofs.len() > MAX_CACHED_PROOFS {
+			cache.proofs.pop_first();
+		}
+		Ok((proof, false))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use futures::executor::block_on;
+	use std::convert::Infallible;
+
+	/// Finality lags `HEAD_DISTANCE` blocks behind the head of the relay chain, so
+	/// `prove_finality` returns a proof for a block that far ahead of the requested one.
+	const HEAD_DISTANCE: u32 = 30;
+
+	#[test]
+	fn sequential_updates_reuse_proofs() {
+		let cache = FinalityProofCache::new(32);
+		let calls = Arc::new(Mutex::new(vec![]));
+
+		// 20 updates of 3 blocks each
+		for i in 0..20u32 {
+			let (from_block, update_target) = (3 * i + 1, 3 * i + 3);
+			let calls = calls.clone();
+			let (proof, _) = block_on(cache.get_or_fetch(from_block, update_target, |block| {
+				calls.lock().unwrap().push(block);
+				async move {
+					Ok::<_, Infallible>(CachedFinalityProof {
+						target: block + HEAD_DISTANCE,
+						encoded: block.to_le_bytes().to_vec(),
+					})
+				}
+			}))
+			.unwrap();
+			assert!(proof.target >= update_target);
+		}
+
+		// the first bucket is fetched again once its proof doesn't finalize the updates anymore
+		assert_eq!(*calls.lock().unwrap(), vec![1, 31, 34]);
+	}
+
+	#[test]
+	fn proofs_not_finalizing_the_update_are_refetched() {
+		let cache = FinalityProofCache::new(32);
+		let fetch = |target: u32| {
+			move |_| async move { Ok::<_, Infallible>(CachedFinalityProof { target, encoded: vec![] }) }
+		};
+
+		assert!(!block_on(cache.get_or_fetch(1, 5, fetch(10))).unwrap().1);
+		assert!(block_on(cache.get_or_fetch(2, 10, fetch(10))).unwrap().1);
+		assert!(!block_on(cache.get_or_fetch(2, 11, fetch(20))).unwrap().1);
+		assert!(block_on(cache.get_or_fetch(3, 20, fetch(20))).unwrap().1);
+		// a fetched proof older than the cached one doesn't replace it
+		assert!(!block_on(cache.get_or_fetch(4, 21, fetch(15))).unwrap().1);
+		assert!(block_on(cache.get_or_fetch(4, 20, fetch(0))).unwrap().1);
+		// fetch errors aren't cached
+		assert!(block_on(
+			cache.get_or_fetch(40, 41, |_| async { Err::<CachedFinalityProof, _>(()) })
+		)
+		.is_err());
+		assert!(!block_on(cache.get_or_fetch(40, 41, fetch(50))).unwrap().1);
+	}
+
+	#[test]
+	fn canonical_headers() {
+		let cache = FinalityProofCache::default();
+		let hash = H256::repeat_byte(1);
+		assert!(!cache.is_canonical(10, hash));
+		cache.insert_canonical(10, hash);
+		assert!(cache.is_canonical(10, hash));
+		assert!(!cache.is_canonical(10, H256::repeat_byte(2)));
+		assert!(!cache.is_canonical(11, hash));
+
+		for number in 11..=MAX_CANONICAL_HEADERS as u32 + 10 {
+			cache.insert_canonical(number, hash);
+		}
+		assert!(!cache.is_canonical(10, hash));
+		assert!(cache.is_canonical(11, hash));
+	}
+
+	#[test]
+	fn evidence_checks_are_recorded_on_change() {
+		let cache = FinalityProofCache::default();
+		let check =
+			EvidenceCheck { set_id: 1, submitted_proof_valid: true, trusted_proof_valid: false };
+		assert!(cache.record_evidence_check(check));
+		assert!(!cache.record_evidence_check(check));
+		assert!(cache.record_evidence_check(EvidenceCheck { trusted_proof_valid: true, ..check }));
+		assert!(cache.record_evidence_check(EvidenceCheck {
+			set_id: 2,
+			trusted_proof_valid: true,
+			..check
+		}));
+	}
+}
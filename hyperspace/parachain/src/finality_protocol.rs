@@ -14,7 +14,7 @@
 
 //! Light client protocols for parachains.
 
-use crate::{error::Error, ParachainClient};
+use crate::{authority_set::find_authority_set_change, error::Error, ParachainClient};
 use anyhow::anyhow;
 use beefy_light_client_primitives::{ClientState as BeefyPrimitivesClientState, NodesUtils};
 use codec::{Decode, Encode};
@@ -32,7 +32,7 @@ use ibc::{
 };
 use ibc_proto::google::protobuf::Any;
 use ibc_rpc::{BlockNumberOrHash, IbcApiClient};
-use ics10_grandpa::client_message::{ClientMessage, Header as GrandpaHeader};
+use ics10_grandpa::client_message::{ClientMessage, Header as GrandpaHeader, RelayChainHeader};
 use ics11_beefy::client_message::{
 	BeefyHeader, ClientMessage as BeefyClientMessage, ParachainHeadersWithProof,
 };
@@ -247,11 +247,14 @@ where
 	let authority_set_changed =
 		signed_commitment.commitment.validator_set_id == beefy_client_state.next_authorities.id;
 
+	// updates submitted to the counterparty aren't part of its client state until they're included
+	let expected_client_height = source.common_state().submitted_updates.expected_height(
+		counterparty.name(),
+		&source.client_id(),
+		client_state.latest_height(),
+	);
 	let is_update_required = source
-		.is_update_required(
-			latest_finalized_block.into(),
-			client_state.latest_height().revision_height,
-		)
+		.is_update_required(latest_finalized_block.into(), expected_client_height.revision_height)
 		.await?;
 
 	// if validator set has changed this is a mandatory update
@@ -293,11 +296,10 @@ where
 			filter_events_by_ids(
 				e,
 				&[source.client_id(), counterparty.client_id()],
-				&[source.connection_id(), counterparty.connection_id()]
-					.into_iter()
-					.flatten()
-					.collect::<Vec<_>>(),
+				&[source.connection_ids(), counterparty.connection_ids()].concat(),
 				&channel_and_port_ids,
+				source.common_state().auto_whitelist_channels ||
+					counterparty.common_state().auto_whitelist_channels,
 			)
 		})
 		.collect();
@@ -427,6 +429,47 @@ where
 	Ok(None)
 }
 
+/// Returns the justification of the highest relay chain block finalized by the authority set
+/// that finalized `relay_height`.
+async fn prove_finality<T>(
+	prover: &GrandpaProver<T>,
+	relay_height: u32,
+) -> anyhow::Result<GrandpaJustification<T::Header>>
+where
+	T: light_client_common::config::Config + Send + Sync,
+	u32: From<<<T as subxt::Config>::Header as HeaderT>::Number>
+		+ From<<<T as subxt::Config>::Header as Header>::Number>,
+	ParachainClient<T>: Chain + KeyProvider,
+	<<T as light_client_common::config::Config>::Signature as Verify>::Signer:
+		From<MultiSigner> + IdentifyAccount<AccountId = T::AccountId>,
+	<T as subxt::Config>::Address: From<<T as subxt::Config>::AccountId>,
+	<T as subxt::Config>::Signature: From<MultiSignature> + Send + Sync,
+	<<T as subxt::Config>::Header as Header>::Number:
+		BlockNumberOps + From<u32> + Display + Ord + sp_runtime::traits::Zero + One + Send + Sync,
+	T::Hash: From<sp_core::H256> + From<[u8; 32]>,
+	sp_core::H256: From<T::Hash>,
+	BTreeMap<H256, ParachainHeaderProofs>:
+		From<BTreeMap<<T as subxt::Config>::Hash, ParachainHeaderProofs>>,
+	<T::ExtrinsicParams as ExtrinsicParams<T::Index, T::Hash>>::OtherParams:
+		From<BaseExtrinsicParamsBuilder<T, T::Tip>> + Send + Sync,
+	<T as subxt::Config>::Header: Decode + Send + Sync + Clone,
+	<T as subxt::Config>::AccountId: Send + Sync,
+	<T as subxt::Config>::Address: Send + Sync,
+{
+	let encoded = GrandpaApiClient::<JustificationNotification, H256, u32>::prove_finality(
+		// we cast between the same type but different crate versions.
+		&*prover.relay_ws_client.clone(),
+		relay_height,
+	)
+	.await
+	.map_err(|_| anyhow!("Relay block {relay_height} has not been finalized"))?
+	.ok_or_else(|| anyhow!("No justification found for block: {:?}", relay_height))?
+	.0;
+
+	let finality_proof = FinalityProof::<T::Header>::decode(&mut &encoded[..])?;
+	Ok(GrandpaJustification::<T::Header>::decode(&mut &finality_proof.justification[..])?)
+}
+
 /// Query the latest events that have been finalized by the GRANDPA finality protocol.
 pub async fn query_latest_ibc_events_with_grandpa<T, C>(
 	source: &mut ParachainClient<T>,
@@ -481,26 +524,12 @@ where
 	// will always be finalized.
 	let next_relay_height = client_state.latest_relay_height + 1;
 
-	let encoded = GrandpaApiClient::<JustificationNotification, H256, u32>::prove_finality(
-		// we cast between the same type but different crate versions.
-		&*prover.relay_ws_client.clone(),
-		next_relay_height,
-	)
-	.await
-	.map_err(|_| {
-		Error::Custom(
-		format!("Next relay block {} has not been finalized, previous finalized height on counterparty {}",
-				next_relay_height, client_state.latest_relay_height
+	let mut justification = prove_finality(&prover, next_relay_height).await.map_err(|e| {
+		anyhow!(
+			"{e}, previous finalized height on counterparty {}",
+			client_state.latest_relay_height
 		)
-	)
-	})?
-	.ok_or_else(|| anyhow!("No justification found for block: {:?}", next_relay_height))?
-	.0;
-
-	let finality_proof = FinalityProof::<T::Header>::decode(&mut &encoded[..])?;
-
-	let mut justification =
-		GrandpaJustification::<T::Header>::decode(&mut &finality_proof.justification[..])?;
+	})?;
 
 	let diff = justification
 		.commit
@@ -519,6 +548,45 @@ where
 		}
 	}
 
+	// The client only verifies the justifications of the authority set it knows. If the
+	// justification was signed by a later set, the client is caught up with the justification of
+	// the last block of its set first, one set per update.
+	let signing_set_id = prover
+		.current_set_id_at(justification.commit.target_number.saturating_sub(1))
+		.await?;
+	let authority_set_lag = signing_set_id.saturating_sub(client_state.current_set_id);
+	source.common_state().authority_set_lag.set(authority_set_lag);
+	let catching_up = authority_set_lag > 0;
+	if catching_up {
+		if prover.current_set_id_at(client_state.latest_relay_height).await? >
+			client_state.current_set_id
+		{
+			return Err(anyhow!(
+				"The client of {} on {} has authority set {}, which the relay chain replaced before the client's latest relay height {}, the client has to be recovered",
+				source.name(),
+				counterparty.name(),
+				client_state.current_set_id,
+				client_state.latest_relay_height
+			))
+		}
+		let set_change = find_authority_set_change(
+			client_state.current_set_id,
+			client_state.latest_relay_height,
+			justification.commit.target_number,
+			|block| prover.current_set_id_at(block),
+		)
+		.await?
+		.ok_or_else(|| {
+			anyhow!(
+				"Change of authority set {} not found before relay block {}",
+				client_state.current_set_id,
+				justification.commit.target_number
+			)
+		})?;
+		log::warn!(target: "hyperspace_parachain", "The client of {} on {} is {authority_set_lag} authority sets behind, catching up with the change of set {} at relay block {set_change}", source.name(), counterparty.name(), client_state.current_set_id);
+		justification = prove_finality(&prover, set_change).await?;
+	}
+
 	// Sometimes the returned justification doesn't contain the header for the target block
 	// in the votes ancestry, so we need to fetch it manually
 	if !justification.votes_ancestries.is_empty() &&
@@ -608,11 +676,10 @@ where
 			let f = filter_events_by_ids(
 				e,
 				&[source.client_id(), counterparty.client_id()],
-				&[source.connection_id(), counterparty.connection_id()]
-					.into_iter()
-					.flatten()
-					.collect::<Vec<_>>(),
+				&[source.connection_ids(), counterparty.connection_ids()].concat(),
 				&channel_and_port_ids,
+				source.common_state().auto_whitelist_channels ||
+					counterparty.common_state().auto_whitelist_channels,
 			);
 			log::trace!(target: "hyperspace", "Filtering event: {:?}: {f}", e.event_type());
 			f
@@ -632,31 +699,106 @@ where
 	// In a situation where the sessions last a couple hours and we don't see any ibc events during
 	// a session we want to send some block updates in between the session, this would serve as
 	// checkpoints so we don't end up with a very large finality proof at the session end.
-	let is_update_required = source
-		.is_update_required(
-			latest_justification.commit.target_number.into(),
-			client_state.latest_relay_height.into(),
-		)
-		.await?;
+	// The relay chain height of an update that is still pending isn't known, but it's no longer
+	// required if the pending update covers the finalized parachain height already.
+	let expected_client_height = source.common_state().submitted_updates.expected_height(
+		counterparty.name(),
+		&source.client_id(),
+		Height::new(source.para_id as u64, client_state.latest_para_height as u64),
+	);
+	let is_update_required = expected_client_height.revision_height <
+		u64::from(finalized_para_height) &&
+		source
+			.is_update_required(
+				latest_justification.commit.target_number.into(),
+				client_state.latest_relay_height.into(),
+			)
+			.await?;
 
 	// We ensure we advance the finalized latest parachain height
 	if client_state.latest_para_height < finalized_para_height {
 		headers_with_events.insert(finalized_para_header.number());
 	}
 
-	let ParachainHeadersWithFinalityProof { finality_proof, parachain_headers, .. } = prover
-		.query_finalized_parachain_headers_with_proof::<T::Header>(
-			client_state.latest_relay_height,
-			justification.commit.target_number,
-			Some(justification.encode()),
-			headers_with_events.into_iter().collect(),
-		)
-		.await?;
+	let mut grandpa_header = grandpa_header_with_proof(
+		&prover,
+		client_state.latest_relay_height,
+		client_state.latest_relay_hash,
+		&justification,
+		headers_with_events.iter().cloned().collect(),
+		finalized_para_height,
+	)
+	.await?;
+
+	// forced changes aren't enacted by the client, which freezes when it sees one, see
+	// [`grandpa_light_client::find_forced_change_signal`]. The update isn't submitted, the client
+	// has to be recovered through governance.
+	if let Some(forced_change) = grandpa_light_client::find_forced_change_signal::<RelayChainHeader>(
+		&grandpa_header.finality_proof.unknown_headers,
+		client_state.latest_relay_height,
+	) {
+		return Err(anyhow!(
+			"The relay chain of {} forced an authority set change at block {}, the client on {} can't be updated past it and has to be recovered through governance",
+			source.name(),
+			forced_change.signal_number,
+			counterparty.name()
+		))
+	}
+
+	// while the relay chain headers of the update are over the size limit, the client is advanced
+	// to a justified relay chain block within the limit first
+	let mut latest_relay_height = client_state.latest_relay_height;
+	let mut intermediate_headers = vec![];
+	if let Some(max_update_size) = source.max_update_size {
+		let mut latest_relay_hash = client_state.latest_relay_hash;
+		let mut latest_para_height = client_state.latest_para_height;
+		while grandpa_header.finality_proof.unknown_headers.encoded_size() > max_update_size {
+			let Some(highest) =
+				grandpa_header.finality_proof.highest_header_within(max_update_size)
+			else {
+				break
+			};
+			let Some(intermediate_justification) =
+				find_next_justification(&prover, latest_relay_height + 1, highest + 1).await?
+			else {
+				log::warn!(target: "hyperspace_parachain", "No justification found in relay blocks {}..={highest} to split the update of the client of {} on {}", latest_relay_height + 1, source.name(), counterparty.name());
+				break
+			};
+			let intermediate_relay_height = intermediate_justification.commit.target_number;
+			let para_header = prover
+				.query_latest_finalized_parachain_header(intermediate_relay_height)
+				.await?;
+			let mut header_numbers = headers_with_events
+				.range(..=para_header.number())
+				.cloned()
+				.collect::<BTreeSet<_>>();
+			if latest_para_height < u32::from(para_header.number()) {
+				header_numbers.insert(para_header.number());
+				latest_para_height = u32::from(para_header.number());
+			}
+			let intermediate_header = grandpa_header_with_proof(
+				&prover,
+				latest_relay_height,
+				latest_relay_hash,
+				&intermediate_justification,
+				header_numbers.into_iter().collect(),
+				latest_para_height,
+			)
+			.await?;
+			latest_relay_height = intermediate_relay_height;
+			latest_relay_hash = intermediate_header.finality_proof.block;
+			trim_known_headers(&mut grandpa_header, latest_relay_hash)?;
+			intermediate_headers.push(intermediate_header);
+		}
+	}
+	if !intermediate_headers.is_empty() {
+		log::info!(target: "hyperspace_parachain", "Splitting the update of the client of {} on {} into {} updates", source.name(), counterparty.name(), intermediate_headers.len() + 1);
+	}
 
 	let target = source
 		.relay_client
 		.rpc()
-		.header(Some(finality_proof.block.into()))
+		.header(Some(grandpa_header.finality_proof.block.into()))
 		.await?
 		.ok_or_else(|| {
 			Error::from("Could not find relay chain header for justification target".to_string())
@@ -666,30 +808,107 @@ where
 		.expect("Should not panic, same struct from different crates");
 
 	let authority_set_changed_scheduled = find_scheduled_change(&target).is_some();
-	log::info!(target: "hyperspace_parachain", "authority_set_changed_scheduled = {authority_set_changed_scheduled}, timeout_update_required = {timeout_update_required}, is_update_required = {is_update_required}");
+	log::info!(target: "hyperspace_parachain", "authority_set_changed_scheduled = {authority_set_changed_scheduled}, catching_up = {catching_up}, timeout_update_required = {timeout_update_required}, is_update_required = {is_update_required}");
 	// if validator set has changed this is a mandatory update
-	let update_type =
-		match authority_set_changed_scheduled || timeout_update_required || is_update_required {
-			true => UpdateType::Mandatory,
-			false => UpdateType::Optional,
-		};
-
-	let grandpa_header = GrandpaHeader {
-		finality_proof: codec::Decode::decode(&mut &*finality_proof.encode())
-			.expect("Same struct from different crates,decode should not fail"),
-		parachain_headers: parachain_headers.into(),
-		height: Height::new(source.para_id as u64, finalized_para_height as u64),
+	let update_type = match authority_set_changed_scheduled ||
+		catching_up ||
+		timeout_update_required ||
+		is_update_required
+	{
+		true => UpdateType::Mandatory,
+		false => UpdateType::Optional,
 	};
-	let height = grandpa_header.height();
-	let update_header = {
+	let update_message = |grandpa_header: GrandpaHeader| -> Result<Any, anyhow::Error> {
 		let msg = MsgUpdateAnyClient::<LocalClientTypes> {
 			client_id: source.client_id(),
 			client_message: AnyClientMessage::Grandpa(ClientMessage::Header(grandpa_header)),
 			signer: counterparty.account_id(),
 		};
 		let value = msg.encode_vec()?;
-		Any { value, type_url: msg.type_url() }
+		Ok(Any { value, type_url: msg.type_url() })
+	};
+
+	// the events are relayed with the last update, the intermediate ones are needed for it
+	let mut updates = intermediate_headers
+		.into_iter()
+		.map(|header| {
+			let height = header.height();
+			Ok((update_message(header)?, height, vec![], UpdateType::Mandatory))
+		})
+		.collect::<Result<Vec<_>, anyhow::Error>>()?;
+	let height = grandpa_header.height();
+	updates.push((update_message(grandpa_header)?, height, events, update_type));
+
+	Ok(updates)
+}
+
+/// Builds the header updating a client last updated to the relay chain block
+/// `latest_relay_height` with hash `latest_relay_hash`, to the block finalized by `justification`,
+/// with the proofs of the parachain headers `header_numbers`. The relay chain headers the client
+/// already knows of are left out.
+async fn grandpa_header_with_proof<T>(
+	prover: &GrandpaProver<T>,
+	latest_relay_height: u32,
+	latest_relay_hash: H256,
+	justification: &GrandpaJustification<T::Header>,
+	header_numbers: Vec<<<T as subxt::Config>::Header as Header>::Number>,
+	para_height: u32,
+) -> anyhow::Result<GrandpaHeader>
+where
+	T: light_client_common::config::Config + Send + Sync,
+	u32: From<<<T as subxt::Config>::Header as HeaderT>::Number>
+		+ From<<<T as subxt::Config>::Header as Header>::Number>,
+	ParachainClient<T>: Chain + KeyProvider,
+	<<T as light_client_common::config::Config>::Signature as Verify>::Signer:
+		From<MultiSigner> + IdentifyAccount<AccountId = T::AccountId>,
+	<T as subxt::Config>::Address: From<<T as subxt::Config>::AccountId>,
+	<T as subxt::Config>::Signature: From<MultiSignature> + Send + Sync,
+	<<T as subxt::Config>::Header as Header>::Number:
+		BlockNumberOps + From<u32> + Display + Ord + sp_runtime::traits::Zero + One + Send + Sync,
+	T::Hash: From<sp_core::H256> + From<[u8; 32]>,
+	sp_core::H256: From<T::Hash>,
+	BTreeMap<H256, ParachainHeaderProofs>:
+		From<BTreeMap<<T as subxt::Config>::Hash, ParachainHeaderProofs>>,
+	<T::ExtrinsicParams as ExtrinsicParams<T::Index, T::Hash>>::OtherParams:
+		From<BaseExtrinsicParamsBuilder<T, T::Tip>> + Send + Sync,
+	<T as subxt::Config>::Header: Decode + Send + Sync + Clone,
+	<T as subxt::Config>::AccountId: Send + Sync,
+	<T as subxt::Config>::Address: Send + Sync,
+{
+	let ParachainHeadersWithFinalityProof { finality_proof, parachain_headers, .. } = prover
+		.query_finalized_parachain_headers_with_proof::<T::Header>(
+			latest_relay_height,
+			justification.commit.target_number,
+			Some(justification.encode()),
+			header_numbers,
+		)
+		.await?;
+	let mut grandpa_header = GrandpaHeader {
+		finality_proof: codec::Decode::decode(&mut &*finality_proof.encode())
+			.expect("Same struct from different crates,decode should not fail"),
+		parachain_headers: parachain_headers.into(),
+		height: Height::new(prover.para_id as u64, para_height as u64),
 	};
+	trim_known_headers(&mut grandpa_header, latest_relay_hash)?;
+	Ok(grandpa_header)
+}
 
-	Ok(vec![(update_header, height, events, update_type)])
+/// Leaves the relay chain headers up to `latest_relay_hash`, and the parachain headers they
+/// include, out of `grandpa_header`.
+fn trim_known_headers(
+	grandpa_header: &mut GrandpaHeader,
+	latest_relay_hash: H256,
+) -> anyhow::Result<()> {
+	grandpa_header
+		.finality_proof
+		.trim_unknown_headers(latest_relay_hash)
+		.map_err(|e| anyhow!("{e}"))?;
+	let relay_hashes = grandpa_header
+		.finality_proof
+		.unknown_headers
+		.iter()
+		.map(|header| header.hash())
+		.collect::<BTreeSet<_>>();
+	grandpa_header.parachain_headers.retain(|hash, _| relay_hashes.contains(hash));
+	Ok(())
 }
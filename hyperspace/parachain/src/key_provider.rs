@@ -13,19 +13,51 @@
 // limitations under the License.
 
 use super::ParachainClient;
-use primitives::KeyProvider;
+use crate::{signer::KeystoreSigner, KeyType};
+use primitives::{
+	error::Error,
+	signer::{KeyRotation, KeySource, RemoteSigner, TransactionSigner},
+	KeyProvider,
+};
 use sp_core::crypto::Ss58Codec;
-use sp_runtime::traits::IdentifyAccount;
-use std::str::FromStr;
+use sp_runtime::{traits::IdentifyAccount, MultiSigner};
+use std::{str::FromStr, sync::Arc};
 
-impl<T: light_client_common::config::Config> KeyProvider for ParachainClient<T> {
-	fn account_id(&self) -> ibc::signer::Signer {
-		let hex_string = self
-			.public_key
-			.clone()
-			.into_account()
-			.to_ss58check_with_version(self.ss58_version);
+impl<T: light_client_common::config::Config> ParachainClient<T> {
+	fn account_id_of(&self, public_key: MultiSigner) -> ibc::signer::Signer {
+		let hex_string = public_key.into_account().to_ss58check_with_version(self.ss58_version);
 
 		ibc::signer::Signer::from_str(&hex_string).expect("Account Id should be valid")
 	}
 }
+
+#[async_trait::async_trait]
+impl<T: light_client_common::config::Config + Send + Sync> KeyProvider for ParachainClient<T> {
+	fn account_id(&self) -> ibc::signer::Signer {
+		self.account_id_of(self.signer.account())
+	}
+
+	/// The new key is of the type of the current one.
+	async fn rotate_key(
+		&self,
+		key_source: &KeySource,
+		min_balance: u128,
+	) -> Result<KeyRotation, Error> {
+		let custom = |e: crate::error::Error| Error::Custom(e.to_string());
+		let key_type = KeyType::of(&self.signer.account());
+		let signer: Arc<dyn TransactionSigner> = match key_source {
+			KeySource::Remote(remote) => Arc::new(RemoteSigner::connect(remote.clone()).await?),
+			_ => Arc::new(KeystoreSigner::new(&key_type, &key_source.secret()?).map_err(custom)?),
+		};
+		let public_key = key_type.public_key(&signer.public_key()).map_err(custom)?;
+		let new_account = self.account_id_of(public_key.clone());
+		let balance = self.query_free_balance(&public_key).await.map_err(custom)?;
+		if balance < min_balance {
+			return Err(Error::Custom(format!(
+				"Account {new_account} holds {balance}, less than the minimum of {min_balance}"
+			)))
+		}
+		let old_public_key = self.signer.rotate(signer, public_key);
+		Ok(KeyRotation { old_account: self.account_id_of(old_public_key), new_account })
+	}
+}
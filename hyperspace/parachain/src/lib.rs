@@ -15,16 +15,18 @@
 #![allow(clippy::all)]
 
 use std::{
-	collections::{BTreeMap, HashSet},
-	path::PathBuf,
+	collections::{BTreeMap, HashMap},
 	str::FromStr,
 	sync::{Arc, Mutex},
 	time::Duration,
 };
 
+pub mod authority_set;
 pub mod chain;
 pub mod error;
+pub mod finality_proof_cache;
 pub mod key_provider;
+pub mod metadata;
 pub mod parachain;
 pub mod provider;
 pub mod signer;
@@ -40,17 +42,30 @@ use frame_support::Serialize;
 use serde::Deserialize;
 
 use crate::{
-	finality_protocol::FinalityProtocol, signer::ExtrinsicSigner, utils::fetch_max_extrinsic_weight,
+	finality_proof_cache::{FinalityProofCache, DEFAULT_FINALITY_PROOF_CACHE_BUCKET_SIZE},
+	finality_protocol::FinalityProtocol,
+	metadata::{check_runtime_api, MetadataMismatchPolicy},
+	provider::TransactionId,
+	signer::{ExtrinsicSigner, KeystoreSigner},
+	utils::fetch_max_extrinsic_weight,
 };
-use beefy_light_client_primitives::{ClientState, MmrUpdateProof};
-use beefy_prover::Prover;
-use codec::Decode;
+use beefy_light_client_primitives::{ClientState, MmrUpdateProof, SignedCommitment};
+use beefy_prover::{
+	helpers::{signature_verification_pool, verify_signed_commitment},
+	Prover,
+};
+use codec::{Decode, Encode};
 use grandpa_light_client_primitives::ParachainHeaderProofs;
 use grandpa_prover::GrandpaProver;
 use ibc::{
-	core::ics24_host::identifier::{ChannelId, ClientId, ConnectionId, PortId},
+	core::{
+		ics23_commitment::commitment::CommitmentPrefix,
+		ics24_host::identifier::{ChannelId, ClientId, ConnectionId, PortId},
+	},
+	events::IbcEvent,
 	timestamp::Timestamp,
 };
+use ibc_proto::google::protobuf::Any;
 use ics10_grandpa::{
 	client_state::ClientState as GrandpaClientState,
 	consensus_state::ConsensusState as GrandpaConsensusState,
@@ -59,21 +74,33 @@ use ics11_beefy::{
 	client_message::ParachainHeader, client_state::ClientState as BeefyClientState,
 	consensus_state::ConsensusState as BeefyConsensusState,
 };
-use jsonrpsee_ws_client::WsClientBuilder;
-use light_client_common::config::{AsInner, RuntimeStorage};
-use pallet_ibc::light_clients::{AnyClientState, AnyConsensusState, HostFunctionsManager};
+use light_client_common::config::{
+	AsInner, AsInnerEvent, IbcEventsT, RuntimeStorage, RuntimeTransactions,
+};
+use pallet_ibc::{
+	light_clients::{AnyClientState, AnyConsensusState, HostFunctionsManager},
+	TransferParams,
+};
 use pallet_mmr_primitives::Proof;
-use primitives::{CommonClientState, KeyProvider};
-use sc_keystore::LocalKeystore;
-use sp_core::{ecdsa, ed25519, sr25519, Bytes, Pair, H256};
-use sp_keystore::KeystorePtr;
+use primitives::{
+	connection_prefixes,
+	packet_filter::SharedPacketFilter,
+	query_cache::QueryCache,
+	relayed_connection_ids, replay,
+	signer::{RemoteSigner, RotatingSigner, SignerConfig, TransactionSigner},
+	ChannelWhitelist, CommonClientConfig, CommonClientState, ConnectionConfig, KeyProvider,
+};
+use sp_core::{blake2_128, crypto::AccountId32, ecdsa, ed25519, sr25519, twox_128, Bytes, H256};
 use sp_runtime::{
 	traits::{IdentifyAccount, One, Verify},
 	KeyTypeId, MultiSignature, MultiSigner,
 };
 use ss58_registry::Ss58AddressFormat;
 use subxt::{
-	config::{Header as HeaderT, Header},
+	config::{
+		extrinsic_params::{BaseExtrinsicParamsBuilder, Era},
+		ExtrinsicParams, Header as HeaderT, Header,
+	},
 	tx::TxPayload,
 };
 use tokio::sync::Mutex as AsyncMutex;
@@ -103,36 +130,43 @@ pub struct ParachainClient<T: light_client_common::config::Config> {
 	pub para_id: u32,
 	/// Light client id on counterparty chain
 	pub client_id: Arc<Mutex<Option<ClientId>>>,
-	/// Connection Id
-	pub connection_id: Arc<Mutex<Option<ConnectionId>>>,
+	/// Ids of the relayed connections, the primary connection first
+	pub connection_ids: Arc<Mutex<Vec<ConnectionId>>>,
 	/// Channels cleared for packet relay
-	pub channel_whitelist: Arc<Mutex<HashSet<(ChannelId, PortId)>>>,
+	pub channel_whitelist: Arc<Mutex<ChannelWhitelist>>,
 	/// ICS-23 provable store commitment prefix
 	pub commitment_prefix: Vec<u8>,
-	/// Public key for relayer on chain
-	pub public_key: MultiSigner,
-	/// Reference to keystore
-	pub key_store: KeystorePtr,
-	/// Key type Id
-	pub key_type_id: KeyTypeId,
+	/// Commitment prefixes of the connections that don't use [`Self::commitment_prefix`]
+	pub connection_prefixes: HashMap<ConnectionId, CommitmentPrefix>,
+	/// Signer of the relayer's extrinsics and its public key, rotated by
+	/// [`KeyProvider::rotate_key`](primitives::KeyProvider::rotate_key)
+	pub signer: RotatingSigner<MultiSigner>,
 	/// used for encoding relayer address.
 	pub ss58_version: Ss58AddressFormat,
 	/// the maximum extrinsic weight allowed by this client
 	pub max_extrinsic_weight: u64,
 	/// Finality protocol to use, eg Beefy, Grandpa
 	pub finality_protocol: FinalityProtocol,
+	/// Trusted finality proofs of the misbehaviour checker
+	pub finality_proof_cache: FinalityProofCache,
+	/// Maximum encoded size of the relay chain headers of a GRANDPA client update
+	pub max_update_size: Option<usize>,
+	/// Threads verifying the signatures of BEEFY commitments before they are relayed or checked
+	/// for misbehaviour
+	pub signature_verification_pool: Arc<rayon::ThreadPool>,
 	/// Common relayer data
 	pub common_state: CommonClientState,
 }
 
-enum KeyType {
+/// Digital signature scheme of the relayer account
+pub enum KeyType {
 	Sr25519,
 	Ed25519,
 	Ecdsa,
 }
 
 pub const DEFAULT_RPC_CALL_DELAY: Duration = Duration::from_millis(10);
-pub const WAIT_FOR_IN_BLOCK_TIMEOUT: Duration = Duration::from_secs(60 * 1);
+pub const WAIT_FOR_FINALIZATION_TIMEOUT: Duration = Duration::from_secs(60 * 2);
 
 impl KeyType {
 	pub fn to_key_type_id(&self) -> KeyTypeId {
@@ -142,6 +176,25 @@ impl KeyType {
 			KeyType::Ecdsa => KeyTypeId(ecdsa::CRYPTO_ID.0),
 		}
 	}
+
+	/// Type of the key of `public_key`.
+	pub fn of(public_key: &MultiSigner) -> Self {
+		match public_key {
+			MultiSigner::Sr25519(_) => KeyType::Sr25519,
+			MultiSigner::Ed25519(_) => KeyType::Ed25519,
+			MultiSigner::Ecdsa(_) => KeyType::Ecdsa,
+		}
+	}
+
+	/// Decodes a public key of this type.
+	pub fn public_key(&self, public_key: &[u8]) -> Result<MultiSigner, Error> {
+		let invalid = |_| Error::Custom(format!("Invalid public key {}", hex::encode(public_key)));
+		Ok(match self {
+			KeyType::Sr25519 => sr25519::Public::try_from(public_key).map_err(invalid)?.into(),
+			KeyType::Ed25519 => ed25519::Public::try_from(public_key).map_err(invalid)?.into(),
+			KeyType::Ecdsa => ecdsa::Public::try_from(public_key).map_err(invalid)?.into(),
+		})
+	}
 }
 
 impl FromStr for KeyType {
@@ -172,10 +225,17 @@ pub struct ParachainClientConfig {
 	pub client_id: Option<ClientId>,
 	/// Connection Id
 	pub connection_id: Option<ConnectionId>,
+	/// Connections relayed in addition to `connection_id`
+	#[serde(default)]
+	pub connections: Vec<ConnectionConfig>,
 	/// Commitment prefix
 	pub commitment_prefix: Bytes,
-	/// Raw private key for signing transactions
+	/// Raw private key for signing transactions, unless a remote signer is used
+	#[serde(default)]
 	pub private_key: String,
+	/// Signer of transactions
+	#[serde(default)]
+	pub signer: SignerConfig,
 	/// used for encoding relayer address.
 	pub ss58_version: u8,
 	/// Channels cleared for packet relay
@@ -187,61 +247,89 @@ pub struct ParachainClientConfig {
 	/// All the client states and headers will be wrapped in WASM ones using the WASM code ID.
 	#[serde(default)]
 	pub wasm_code_id: Option<String>,
+	/// Number of relay chain blocks sharing a trusted finality proof in the misbehaviour checker
+	#[serde(default)]
+	pub finality_proof_cache_bucket_size: Option<u32>,
+	/// What to do when the runtime api hyperspace was generated from doesn't match the live
+	/// runtimes
+	#[serde(default)]
+	pub metadata_mismatch: MetadataMismatchPolicy,
+	/// Maximum encoded size in bytes of the relay chain headers of a GRANDPA client update, the
+	/// updates carrying more headers are split into several consecutive ones
+	#[serde(default)]
+	pub max_update_size: Option<usize>,
+	/// Number of threads verifying the signatures of BEEFY commitments, `0` uses one thread per
+	/// CPU
+	#[serde(default)]
+	pub signature_verification_threads: usize,
+	/// Common client config
+	#[serde(flatten)]
+	pub common: CommonClientConfig,
 }
 
 impl<T> ParachainClient<T>
 where
 	T: light_client_common::config::Config,
 {
-	/// Initializes a [`ParachainClient`] given a [`ParachainConfig`]
-	pub async fn new(config: ParachainClientConfig) -> Result<Self, Error> {
+	/// Initializes a [`ParachainClient`] given a [`ParachainConfig`], once the runtime api it was
+	/// generated from is checked against the live runtimes.
+	pub async fn new(config: ParachainClientConfig) -> Result<Self, Error>
+	where
+		<T::Tx as RuntimeTransactions>::TransferParams: From<TransferParams<AccountId32>>,
+	{
 		let relay_ws_client = Arc::new(
-			WsClientBuilder::default()
-				.build(&config.relay_chain_rpc_url)
+			replay::connect(&config.relay_chain_rpc_url, &format!("{}-relay", config.name))
 				.await
-				.map_err(|e| Error::from(format!("Rpc Error {:?}", e)))?,
+				.map_err(|e| Error::from(e.to_string()))?,
 		);
 		let para_ws_client = Arc::new(
-			WsClientBuilder::default()
-				.build(&config.parachain_rpc_url)
+			replay::connect(&config.parachain_rpc_url, &format!("{}-para", config.name))
 				.await
-				.map_err(|e| Error::from(format!("Rpc Error {:?}", e)))?,
+				.map_err(|e| Error::from(e.to_string()))?,
 		);
 
 		let para_client = subxt::OnlineClient::from_rpc_client(para_ws_client.clone()).await?;
 
 		let relay_client = subxt::OnlineClient::from_rpc_client(relay_ws_client.clone()).await?;
 
+		let metadata_mismatches = check_runtime_api::<T>(
+			&para_client.metadata(),
+			&relay_client.metadata(),
+			config.para_id,
+			config.finality_protocol.clone(),
+		)
+		.iter()
+		.map(ToString::to_string)
+		.collect::<Vec<_>>();
+		if !metadata_mismatches.is_empty() {
+			let mismatches = metadata_mismatches.join(", ");
+			match config.metadata_mismatch {
+				MetadataMismatchPolicy::Refuse =>
+					return Err(Error::Custom(format!(
+						"The runtime api of {} doesn't match its live metadata: {mismatches}. \
+						Regenerate it, or set `metadata_mismatch = \"read_only\"` to only query the chain",
+						config.name
+					))),
+				MetadataMismatchPolicy::ReadOnly => log::error!(
+					target: "hyperspace",
+					"The runtime api of {} doesn't match its live metadata: {mismatches}. Transactions won't be submitted",
+					config.name
+				),
+			}
+		}
+
 		let max_extrinsic_weight = fetch_max_extrinsic_weight(&para_client).await?;
 
-		let temp_dir = PathBuf::from("/tmp/keystore");
-		let key_store: KeystorePtr = Arc::new(LocalKeystore::open(temp_dir, None).unwrap());
 		let key_type = KeyType::from_str(&config.key_type)?;
-		let key_type_id = key_type.to_key_type_id();
-
-		let public_key: MultiSigner = match key_type {
-			KeyType::Sr25519 => sr25519::Pair::from_string_with_seed(&config.private_key, None)
-				.map_err(|_| Error::Custom("invalid key".to_owned()))?
-				.0
-				.public()
-				.into(),
-			KeyType::Ed25519 => ed25519::Pair::from_string_with_seed(&config.private_key, None)
-				.map_err(|_| Error::Custom("invalid key".to_owned()))?
-				.0
-				.public()
-				.into(),
-			KeyType::Ecdsa => ecdsa::Pair::from_string_with_seed(&config.private_key, None)
-				.map_err(|_| Error::Custom("invalid key".to_owned()))?
-				.0
-				.public()
-				.into(),
+		let signer: Arc<dyn TransactionSigner> = match config.signer {
+			SignerConfig::Local => Arc::new(KeystoreSigner::new(&key_type, &config.private_key)?),
+			SignerConfig::Remote(remote) =>
+				Arc::new(RemoteSigner::connect(remote).await.map_err(Error::Signer)?),
 		};
+		let public_key = key_type.public_key(&signer.public_key())?;
+		let connection_prefixes =
+			connection_prefixes(&config.connections).map_err(|e| Error::Custom(e.to_string()))?;
 
-		key_store
-			.insert(key_type_id, &*config.private_key, public_key.as_ref())
-			.unwrap();
-
-		assert!(key_store.has_keys(&[(public_key.as_ref().to_vec(), key_type_id)]));
 		Ok(Self {
 			name: config.name,
 			parachain_rpc_url: config.parachain_rpc_url,
@@ -251,26 +339,131 @@ where
 			para_id: config.para_id,
 			client_id: Arc::new(Mutex::new(config.client_id)),
 			commitment_prefix: config.commitment_prefix.0,
-			connection_id: Arc::new(Mutex::new(config.connection_id)),
-			public_key,
-			key_store,
-			key_type_id,
+			connection_prefixes,
+			connection_ids: Arc::new(Mutex::new(relayed_connection_ids(
+				config.connection_id.as_ref(),
+				&config.connections,
+			))),
+			signer: RotatingSigner::new(signer, public_key),
 			max_extrinsic_weight,
 			para_ws_client,
 			relay_ws_client,
 			ss58_version: Ss58AddressFormat::from(config.ss58_version),
-			channel_whitelist: Arc::new(Mutex::new(config.channel_whitelist.into_iter().collect())),
+			channel_whitelist: Arc::new(Mutex::new(ChannelWhitelist::new(
+				config.channel_whitelist,
+				&config.connections,
+			))),
 			finality_protocol: config.finality_protocol,
-			common_state: CommonClientState {
-				skip_optional_client_updates: true,
-				maybe_has_undelivered_packets: Arc::new(Mutex::new(Default::default())),
-				rpc_call_delay: DEFAULT_RPC_CALL_DELAY,
-				initial_rpc_call_delay: DEFAULT_RPC_CALL_DELAY,
-				misbehaviour_client_msg_queue: Arc::new(AsyncMutex::new(vec![])),
-				..Default::default()
-			},
+			finality_proof_cache: FinalityProofCache::new(
+				config
+					.finality_proof_cache_bucket_size
+					.unwrap_or(DEFAULT_FINALITY_PROOF_CACHE_BUCKET_SIZE),
+			),
+			max_update_size: config.max_update_size,
+			signature_verification_pool: Arc::new(
+				signature_verification_pool(config.signature_verification_threads)
+					.map_err(Error::BeefyProver)?,
+			),
+			common_state: common_client_state(config.common, metadata_mismatches)?,
 		})
 	}
+
+	/// Verifies the signatures of `signed_commitment` against the BEEFY authorities of the relay
+	/// chain at the commitment block, on [`Self::signature_verification_pool`]. The outer error is
+	/// a failure to fetch the authorities, the inner one an invalid commitment.
+	pub async fn verify_beefy_commitment(
+		&self,
+		signed_commitment: &SignedCommitment,
+	) -> Result<Result<Vec<u32>, beefy_prover::error::Error>, Error>
+	where
+		u32: From<<<T as subxt::Config>::Header as Header>::Number>,
+	{
+		let prover = Prover {
+			relay_client: self.relay_client.clone(),
+			para_client: self.para_client.clone(),
+			para_id: self.para_id,
+		};
+		let authority_address_hashes = prover
+			.fetch_beefy_authority_address_hashes(signed_commitment.commitment.block_number)
+			.await
+			.map_err(Error::BeefyProver)?;
+
+		let (sender, receiver) = tokio::sync::oneshot::channel();
+		let pool = self.signature_verification_pool.clone();
+		let signed_commitment = signed_commitment.clone();
+		self.signature_verification_pool.spawn(move || {
+			let _ = sender.send(verify_signed_commitment(
+				&signed_commitment,
+				&authority_address_hashes,
+				&pool,
+			));
+		});
+		receiver
+			.await
+			.map_err(|_| Error::from("Beefy signature verification was cancelled".to_owned()))
+	}
+
+	/// Free balance of the account of `public_key`.
+	pub async fn query_free_balance(&self, public_key: &MultiSigner) -> Result<u128, Error> {
+		let account = <[u8; 32]>::from(public_key.clone().into_account());
+		let mut storage_key = twox_128(b"System").to_vec();
+		storage_key.extend(twox_128(b"Account"));
+		storage_key.extend(blake2_128(&account));
+		storage_key.extend(account);
+
+		let account_info = self.para_client.rpc().storage(&*storage_key, None).await?;
+		// `AccountInfo` starts with the nonce and three reference counters, followed by the free
+		// balance
+		match account_info {
+			Some(data) => <([u32; 4], u128)>::decode(&mut &*data.0)
+				.map(|(_, free)| free)
+				.map_err(|e| Error::from(format!("Failed to decode the account info: {e:?}"))),
+			None => Ok(0),
+		}
+	}
+}
+
+/// State shared by the clients of all chains, built from the `common` section of the config of a
+/// parachain.
+fn common_client_state(
+	common: CommonClientConfig,
+	metadata_mismatches: Vec<String>,
+) -> Result<CommonClientState, Error> {
+	Ok(CommonClientState {
+		skip_optional_client_updates: common.skip_optional_client_updates,
+		maybe_has_undelivered_packets: Arc::new(Mutex::new(Default::default())),
+		rpc_call_delay: DEFAULT_RPC_CALL_DELAY,
+		initial_rpc_call_delay: DEFAULT_RPC_CALL_DELAY,
+		misbehaviour_client_msg_queue: Arc::new(AsyncMutex::new(vec![])),
+		max_packets_to_process: common.max_packets_to_process as usize,
+		skip_tokens_list: vec![],
+		max_submit_retries: common.max_submit_retries,
+		strict_middleware_check: common.strict_middleware_check,
+		unsupported_middleware_channels: Default::default(),
+		simulate_messages: common.simulate_messages,
+		skip_invalid_wasm_messages: common.skip_invalid_wasm_messages,
+		stop_packets_on_inactive_client: common.stop_packets_on_inactive_client,
+		finality_health: Default::default(),
+		authority_set_lag: Default::default(),
+		metadata_mismatches,
+		misbehaviour_checks: Default::default(),
+		channel_version_resolvers: Default::default(),
+		packet_filter: SharedPacketFilter::new(&common.packet_filters)
+			.map_err(|e| Error::from(e.to_string()))?,
+		query_cache: QueryCache::new(common.query_cache_capacity),
+		timeout_safety_margin_blocks: common.timeout_safety_margin_blocks,
+		auto_whitelist_channels: common.auto_whitelist_channels,
+		relayer_store: None,
+		fee_payee_address: common.fee_payee_address,
+		min_balance: common.min_balance.map(u128::from),
+		stall_factor: common.stall_factor,
+		stall_alert_command: common.stall_alert_command,
+		proof_query_concurrency: common.proof_query_concurrency,
+		max_pending_messages: common.max_pending_messages,
+		pre_validate_updates: common.pre_validate_updates,
+		submitted_updates: Default::default(),
+		relay_stats: Default::default(),
+	})
 }
 
 impl<T: light_client_common::config::Config + Send + Sync> ParachainClient<T>
@@ -401,12 +594,23 @@ where
 		Ok(mmr_update)
 	}
 
-	/// Submits the given transaction to the parachain node, waits for it to be included in a block
-	/// and asserts that it was successfully dispatched on-chain.
+	/// Submits the given transaction to the parachain node, waits for it to be finalized and
+	/// asserts that it was successfully dispatched on-chain. Returns the IBC events emitted by the
+	/// extrinsic, or [`Error::ExtrinsicFailed`] if its dispatch failed.
 	///
 	/// We retry sending the transaction up to 5 times in the case where the transaction pool might
 	/// reject the transaction because of conflicting nonces.
-	pub async fn submit_call<C: TxPayload>(&self, call: C) -> Result<(T::Hash, T::Hash), Error> {
+	pub async fn submit_call<C: TxPayload>(
+		&self,
+		call: C,
+	) -> Result<TransactionId<T::Hash>, Error> {
+		if !self.common_state.metadata_mismatches.is_empty() {
+			return Err(Error::Custom(format!(
+				"{} is read-only, its runtime api doesn't match its live metadata: {}",
+				self.name,
+				self.common_state.metadata_mismatches.join(", ")
+			)))
+		}
 		// Try extrinsic submission five times in case of failures
 		let mut count = 0;
 		let progress = loop {
@@ -416,16 +620,16 @@ where
 
 			let other_params = T::custom_extrinsic_params(&self.para_client).await?;
 
-			let res = {
-				let signer = ExtrinsicSigner::<T, Self>::new(
-					self.key_store.clone(),
-					self.key_type_id.clone(),
-					self.public_key.clone(),
-				);
-				self.para_client
-					.tx()
-					.sign_and_submit_then_watch(&call, &signer, other_params)
-					.await
+			let signer = self.extrinsic_signer();
+			let res = match self
+				.para_client
+				.tx()
+				.create_partial_signed(&call, signer.account_id(), other_params)
+				.await
+			{
+				// signing errors are left to the submission retry policy
+				Ok(extrinsic) => signer.sign(extrinsic).await?.submit_and_watch().await,
+				Err(e) => Err(e),
 			};
 			match res {
 				Ok(progress) => break progress,
@@ -438,13 +642,69 @@ where
 		};
 
 		let tx_in_block =
-			tokio::time::timeout(WAIT_FOR_IN_BLOCK_TIMEOUT, progress.wait_for_in_block())
+			tokio::time::timeout(WAIT_FOR_FINALIZATION_TIMEOUT, progress.wait_for_finalized())
 				.await
 				.map_err(|e| {
-					Error::from(format!("[submit_call] Failed to wait for in block due to {:?}", e))
+					Error::from(format!(
+						"[submit_call] Failed to wait for finalization due to {:?}",
+						e
+					))
 				})??;
-		tx_in_block.wait_for_success().await?;
-		Ok((tx_in_block.extrinsic_hash(), tx_in_block.block_hash()))
+		// the dispatch error of a failed extrinsic is decoded with the metadata of the runtime
+		let events = tx_in_block.wait_for_success().await.map_err(|e| match e {
+			subxt::Error::Runtime(e) => Error::ExtrinsicFailed(e),
+			e => e.into(),
+		})?;
+		// pallet-ibc records the messages it failed to handle as errors, without failing the
+		// dispatch
+		let mut ibc_events = vec![];
+		for event in events.find::<<T::Events as AsInnerEvent>::Inner>() {
+			for result in <T::Events as AsInnerEvent>::from_inner(event?).results() {
+				let event = result.map_err(|e| {
+					match pallet_ibc::errors::IbcError::decode(&mut &*e.encode()) {
+						Ok(e) => Error::IbcMessageFailed(e),
+						Err(e) => Error::Codec(e),
+					}
+				})?;
+				let event = TryInto::<IbcEvent>::try_into(event).map_err(|_| {
+					Error::from(
+						"[submit_call] Failed to convert an event of pallet-ibc".to_string(),
+					)
+				})?;
+				ibc_events.push(event);
+			}
+		}
+		Ok(TransactionId {
+			ext_hash: tx_in_block.extrinsic_hash(),
+			block_hash: tx_in_block.block_hash(),
+			ibc_events,
+		})
+	}
+
+	/// Signer of the next extrinsic, with the current key of the relayer.
+	pub fn extrinsic_signer(&self) -> ExtrinsicSigner<T> {
+		let (signer, public_key) = self.signer.current();
+		ExtrinsicSigner::new(signer, public_key)
+	}
+
+	/// Builds a signed, immortal `ibc::deliver` extrinsic carrying the given messages, as used for
+	/// weight estimation and dry runs.
+	pub async fn signed_deliver_extrinsic(&self, messages: Vec<Any>) -> Result<Vec<u8>, Error>
+	where
+		<T::ExtrinsicParams as ExtrinsicParams<T::Index, T::Hash>>::OtherParams:
+			From<BaseExtrinsicParamsBuilder<T, T::Tip>>,
+	{
+		let signer = self.extrinsic_signer();
+		let tx_params = BaseExtrinsicParamsBuilder::new()
+			.tip(T::Tip::from(100_000u128))
+			.era(Era::Immortal, self.para_client.genesis_hash());
+		let call = T::Tx::ibc_deliver(messages);
+		let extrinsic = self
+			.para_client
+			.tx()
+			.create_partial_signed(&call, signer.account_id(), tx_params.into())
+			.await?;
+		Ok(signer.sign(extrinsic).await?.encoded().to_vec())
 	}
 
 	pub fn client_id(&self) -> ClientId {
@@ -625,6 +885,11 @@ where
 			client_state.latest_para_height = block_number;
 			client_state.para_id = self.para_id;
 			client_state.latest_relay_height = light_client_state.latest_relay_height;
+			client_state.timestamp_pallet_index = self
+				.para_client
+				.metadata()
+				.pallet_by_name("Timestamp")
+				.map(|pallet| pallet.index());
 
 			let subxt_block_number: subxt::rpc::types::BlockNumber = block_number.into();
 			let block_hash =
@@ -656,3 +921,56 @@ where
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ibc::core::ics04_channel::packet::Packet;
+	use primitives::packet_filter::FilterAction;
+
+	#[test]
+	fn common_state_is_built_from_the_config() {
+		let config: ParachainClientConfig = toml::from_str(
+			r#"
+name = "rococo_local"
+para_id = 2000
+parachain_rpc_url = "ws://127.0.0.1:9188"
+relay_chain_rpc_url = "ws://127.0.0.1:9944"
+commitment_prefix = "0x6962632f"
+private_key = "//Alice"
+ss58_version = 49
+channel_whitelist = [["channel-2", "transfer"]]
+finality_protocol = "Grandpa"
+key_type = "sr25519"
+max_packets_to_process = 10
+stop_packets_on_inactive_client = true
+skip_invalid_wasm_messages = true
+fee_payee_address = "5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY"
+
+[[packet_filters]]
+channel_id = "channel-2"
+port_id = "transfer"
+max_data_size = 16
+"#,
+		)
+		.unwrap();
+		let state = common_client_state(config.common, vec![]).unwrap();
+		assert_eq!(state.max_packets_to_process, 10);
+		assert!(state.stop_packets_on_inactive_client);
+		assert!(state.skip_invalid_wasm_messages);
+		assert_eq!(
+			state.fee_payee_address.as_deref(),
+			Some("5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY")
+		);
+
+		let packet = Packet {
+			source_port: PortId::transfer(),
+			source_channel: ChannelId::new(2),
+			data: vec![0; 17],
+			..Default::default()
+		};
+		assert_eq!(state.packet_filter.evaluate(&packet).action, FilterAction::Deny);
+		let packet = Packet { data: vec![0; 16], ..packet };
+		assert_eq!(state.packet_filter.evaluate(&packet).action, FilterAction::Allow);
+	}
+}
@@ -322,11 +322,10 @@ where
 			filter_events_by_ids(
 				e,
 				&[source.client_id(), counterparty.client_id()],
-				&[source.connection_id(), counterparty.connection_id()]
-					.into_iter()
-					.flatten()
-					.collect::<Vec<_>>(),
+				&[source.connection_ids(), counterparty.connection_ids()].concat(),
 				&channel_and_port_ids,
+				source.common_state().auto_whitelist_channels ||
+					counterparty.common_state().auto_whitelist_channels,
 			)
 		})
 		.collect();
@@ -0,0 +1,273 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Check of the runtime api the client was generated from against the metadata of the live
+//! runtimes.
+//!
+//! The static calls and storage addresses of the generated api carry the hash of their item in
+//! the metadata they were generated from. After a runtime upgrade removes or changes an item, its
+//! calls are encoded with the wrong arguments and its storage is decoded as the wrong type, so
+//! the items the relayer uses are compared with the live metadata when the client is created.
+
+use crate::finality_protocol::FinalityProtocol;
+use light_client_common::config::{RuntimeStorage, RuntimeTransactions};
+use pallet_ibc::{MultiAddress, Timeout, TransferParams};
+use serde::{Deserialize, Serialize};
+use sp_core::crypto::AccountId32;
+use std::fmt::{self, Display};
+use subxt::{storage::StorageAddress, tx::TxPayload, Metadata};
+
+/// What the client does when the runtime api it was generated from doesn't match the live
+/// runtimes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetadataMismatchPolicy {
+	/// The client isn't created.
+	#[default]
+	Refuse,
+	/// The client is created but doesn't submit transactions, so that the chain can still be
+	/// queried.
+	ReadOnly,
+}
+
+/// An item of the generated runtime api that doesn't match the live metadata.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataMismatch {
+	pub pallet: String,
+	/// Name of the call or storage entry.
+	pub item: String,
+	/// Whether the item no longer exists, rather than changed.
+	pub missing: bool,
+}
+
+impl MetadataMismatch {
+	fn compare(
+		pallet: &str,
+		item: &str,
+		expected: [u8; 32],
+		live: Option<[u8; 32]>,
+	) -> Option<Self> {
+		match live {
+			Some(hash) if hash == expected => None,
+			live => Some(Self {
+				pallet: pallet.to_string(),
+				item: item.to_string(),
+				missing: live.is_none(),
+			}),
+		}
+	}
+}
+
+impl Display for MetadataMismatch {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let state = if self.missing { "is missing" } else { "changed" };
+		write!(f, "{}::{} {state}", self.pallet, self.item)
+	}
+}
+
+/// Compares the storage entry of a static `address` with `metadata`. Dynamic addresses aren't
+/// checked.
+pub fn check_storage<A: StorageAddress>(
+	metadata: &Metadata,
+	address: &A,
+) -> Option<MetadataMismatch> {
+	let expected = address.validation_hash()?;
+	let live = metadata
+		.pallet_by_name(address.pallet_name())
+		.and_then(|pallet| pallet.storage_hash(address.entry_name()));
+	MetadataMismatch::compare(address.pallet_name(), address.entry_name(), expected, live)
+}
+
+/// Compares the call of a static `payload` with `metadata`. Dynamic payloads aren't checked.
+pub fn check_call<C: TxPayload>(metadata: &Metadata, payload: &C) -> Option<MetadataMismatch> {
+	let details = payload.validation_details()?;
+	let live = metadata
+		.pallet_by_name(details.pallet_name)
+		.and_then(|pallet| pallet.call_hash(details.call_name));
+	MetadataMismatch::compare(details.pallet_name, details.call_name, details.hash, live)
+}
+
+/// Compares the calls and storage entries the relayer uses with the metadata of the parachain
+/// and of its relay chain, whose storage the `finality_protocol` proofs are built from.
+pub fn check_runtime_api<T>(
+	para_metadata: &Metadata,
+	relay_metadata: &Metadata,
+	para_id: u32,
+	finality_protocol: FinalityProtocol,
+) -> Vec<MetadataMismatch>
+where
+	T: light_client_common::config::Config,
+	<T::Tx as RuntimeTransactions>::TransferParams: From<TransferParams<AccountId32>>,
+{
+	// the arguments of the calls don't affect their hashes
+	let transfer = TransferParams {
+		to: MultiAddress::Raw(vec![]),
+		source_channel: 0,
+		timeout: Timeout::Offset { timestamp: None, height: None },
+	};
+	let mut mismatches = vec![
+		check_call(para_metadata, &T::Tx::ibc_deliver(vec![])),
+		check_call(para_metadata, &T::Tx::ibc_transfer(transfer.into(), 0, 0, None)),
+		check_call(para_metadata, &T::Tx::sudo_sudo(T::Tx::ibc_increase_counters())),
+		check_storage(para_metadata, &T::Storage::timestamp_now()),
+		check_storage(relay_metadata, &T::Storage::paras_heads(para_id)),
+	];
+	match finality_protocol {
+		FinalityProtocol::Grandpa =>
+			mismatches.push(check_storage(relay_metadata, &T::Storage::grandpa_current_set_id())),
+		FinalityProtocol::Beefy => mismatches.extend([
+			check_storage(relay_metadata, &T::Storage::beefy_validator_set_id()),
+			check_storage(relay_metadata, &T::Storage::beefy_authorities()),
+			check_storage(relay_metadata, &T::Storage::mmr_leaf_beefy_next_authorities()),
+		]),
+	}
+	mismatches.into_iter().flatten().collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use codec::{Decode, Encode};
+	use frame_metadata::{
+		v14::{
+			ExtrinsicMetadata, PalletCallMetadata, PalletMetadata, PalletStorageMetadata,
+			RuntimeMetadataV14, StorageEntryMetadata, StorageEntryModifier, StorageEntryType,
+		},
+		RuntimeMetadataPrefixed,
+	};
+	use scale_info::{meta_type, MetaType, TypeInfo};
+	use subxt::{
+		ext::scale_encode::EncodeAsType,
+		storage::address::{Address, StaticStorageMapKey, Yes},
+		tx::Payload,
+	};
+
+	mod v1 {
+		use super::*;
+
+		#[allow(non_camel_case_types, dead_code)]
+		#[derive(TypeInfo)]
+		pub enum Call {
+			deliver { messages: Vec<Vec<u8>> },
+		}
+	}
+
+	mod v2 {
+		use super::*;
+
+		#[allow(non_camel_case_types, dead_code)]
+		#[derive(TypeInfo)]
+		pub enum Call {
+			deliver { messages: Vec<Vec<u8>>, fee: u128 },
+		}
+	}
+
+	#[derive(EncodeAsType)]
+	#[encode_as_type(crate_path = "subxt::ext::scale_encode")]
+	struct Deliver {
+		messages: Vec<Vec<u8>>,
+	}
+
+	/// Encoded metadata of a runtime with an `Ibc` pallet of the given call type, storing the
+	/// time in `Timestamp::Now` with the given type.
+	fn metadata_blob(call: MetaType, now: MetaType) -> Vec<u8> {
+		let pallets = vec![
+			PalletMetadata {
+				name: "Ibc",
+				storage: None,
+				calls: Some(PalletCallMetadata { ty: call }),
+				event: None,
+				constants: vec![],
+				error: None,
+				index: 0,
+			},
+			PalletMetadata {
+				name: "Timestamp",
+				storage: Some(PalletStorageMetadata {
+					prefix: "Timestamp",
+					entries: vec![StorageEntryMetadata {
+						name: "Now",
+						modifier: StorageEntryModifier::Default,
+						ty: StorageEntryType::Plain(now),
+						default: vec![0; 8],
+						docs: vec![],
+					}],
+				}),
+				calls: None,
+				event: None,
+				constants: vec![],
+				error: None,
+				index: 1,
+			},
+		];
+		let extrinsic =
+			ExtrinsicMetadata { ty: meta_type::<()>(), version: 4, signed_extensions: vec![] };
+		let metadata = RuntimeMetadataV14::new(pallets, extrinsic, meta_type::<()>());
+		RuntimeMetadataPrefixed::from(metadata).encode()
+	}
+
+	fn decode(blob: Vec<u8>) -> Metadata {
+		Metadata::decode(&mut &*blob).unwrap()
+	}
+
+	/// The static api generated from `metadata`.
+	fn generated_api(
+		metadata: &Metadata,
+	) -> (Payload<Deliver>, Address<StaticStorageMapKey, u64, Yes, Yes, ()>) {
+		let ibc = metadata.pallet_by_name("Ibc").unwrap();
+		let timestamp = metadata.pallet_by_name("Timestamp").unwrap();
+		let deliver = Payload::new_static(
+			"Ibc",
+			"deliver",
+			Deliver { messages: vec![] },
+			ibc.call_hash("deliver").unwrap(),
+		);
+		let now =
+			Address::new_static("Timestamp", "Now", vec![], timestamp.storage_hash("Now").unwrap());
+		(deliver, now)
+	}
+
+	#[test]
+	fn matching_metadata_passes_the_check() {
+		let metadata = decode(metadata_blob(meta_type::<v1::Call>(), meta_type::<u64>()));
+		let (deliver, now) = generated_api(&metadata);
+		// the blob of the live runtime is the one the api was generated from
+		let live = decode(metadata_blob(meta_type::<v1::Call>(), meta_type::<u64>()));
+		assert_eq!(check_call(&live, &deliver), None);
+		assert_eq!(check_storage(&live, &now), None);
+	}
+
+	#[test]
+	fn mismatched_metadata_fails_the_check() {
+		let metadata = decode(metadata_blob(meta_type::<v1::Call>(), meta_type::<u64>()));
+		let (deliver, now) = generated_api(&metadata);
+
+		// `deliver` takes a new argument, and the timestamp changed its type
+		let live = decode(metadata_blob(meta_type::<v2::Call>(), meta_type::<u32>()));
+		let deliver_mismatch = check_call(&live, &deliver).unwrap();
+		assert_eq!(deliver_mismatch.to_string(), "Ibc::deliver changed");
+		let now_mismatch = check_storage(&live, &now).unwrap();
+		assert_eq!(now_mismatch.to_string(), "Timestamp::Now changed");
+
+		let missing = Address::<StaticStorageMapKey, u64, Yes, Yes, ()>::new_static(
+			"Timestamp",
+			"DidUpdate",
+			vec![],
+			[0; 32],
+		);
+		let missing = check_storage(&live, &missing).unwrap();
+		assert!(missing.missing);
+		assert_eq!(missing.to_string(), "Timestamp::DidUpdate is missing");
+	}
+}
@@ -22,7 +22,11 @@ use grandpa_light_client_primitives::ParachainHeaderProofs;
 use ibc::{
 	applications::transfer::{Amount, PrefixedCoin, PrefixedDenom},
 	core::{
-		ics02_client::client_state::{ClientState, ClientType},
+		ics02_client::{
+			client_consensus::ConsensusState,
+			client_state::{ClientState, ClientType},
+		},
+		ics04_channel::channel::IdentifiedChannelEnd,
 		ics23_commitment::commitment::CommitmentPrefix,
 		ics24_host::identifier::{ChannelId, ClientId, ConnectionId, PortId},
 	},
@@ -32,16 +36,19 @@ use ibc::{
 };
 use ibc_proto::{
 	google::protobuf::Any,
-	ibc::core::{
-		channel::v1::{
-			IdentifiedChannel, QueryChannelResponse, QueryChannelsResponse,
-			QueryNextSequenceReceiveResponse, QueryPacketAcknowledgementResponse,
-			QueryPacketCommitmentResponse, QueryPacketReceiptResponse,
-		},
-		client::v1::{
-			IdentifiedClientState, QueryClientStateResponse, QueryConsensusStateResponse,
+	ibc::{
+		applications::transfer::v1::DenomTrace,
+		core::{
+			channel::v1::{
+				IdentifiedChannel, QueryChannelResponse, QueryChannelsResponse,
+				QueryNextSequenceReceiveResponse, QueryPacketAcknowledgementResponse,
+				QueryPacketCommitmentResponse, QueryPacketReceiptResponse,
+			},
+			client::v1::{
+				IdentifiedClientState, QueryClientStateResponse, QueryConsensusStateResponse,
+			},
+			connection::v1::{IdentifiedConnection, QueryConnectionResponse},
 		},
-		connection::v1::{IdentifiedConnection, QueryConnectionResponse},
 	},
 };
 use ibc_rpc::{IbcApiClient, PacketInfo};
@@ -51,7 +58,10 @@ use pallet_ibc::{
 	light_clients::{AnyClientState, AnyConsensusState, HostFunctionsManager},
 	HostConsensusProof,
 };
-use primitives::{apply_prefix, Chain, IbcProvider, KeyProvider, UpdateType};
+use primitives::{
+	apply_prefix, set_primary_connection_id, utils::ibc_denom_of, Chain, ClientStatus, IbcProvider,
+	KeyProvider, UpdateType, WasmUploadStatus,
+};
 use sp_core::H256;
 use sp_runtime::{
 	traits::{IdentifyAccount, One, Verify},
@@ -69,10 +79,15 @@ use subxt::config::{
 };
 use tokio_stream::wrappers::ReceiverStream;
 
+/// Number of denom traces fetched at once when looking for the trace of a hash.
+const DENOM_TRACES_PAGE_SIZE: u64 = 100;
+
 #[derive(Debug)]
 pub struct TransactionId<Hash> {
 	pub ext_hash: Hash,
 	pub block_hash: Hash,
+	/// IBC events emitted by the extrinsic
+	pub ibc_events: Vec<IbcEvent>,
 }
 
 #[async_trait::async_trait]
@@ -195,7 +210,7 @@ where
 			false,
 		)
 		.await
-		.map_err(|e| Error::from(format!("Rpc Error {:?}", e)))?;
+		.map_err(Error::from)?;
 		Ok(res)
 	}
 
@@ -211,10 +226,36 @@ where
 				client_id.to_string(),
 			)
 			.await
-			.map_err(|e| Error::from(format!("Rpc Error {:?}", e)))?;
+			.map_err(Error::from)?;
 		Ok(response)
 	}
 
+	async fn query_client_status(
+		&self,
+		at: Height,
+		client_id: ClientId,
+	) -> Result<ClientStatus, Self::Error> {
+		let response = self.query_client_state(at, client_id.clone()).await?;
+		let client_state = response
+			.client_state
+			.ok_or_else(|| Error::NotFound(format!("Client state for {client_id} not found")))?;
+		let client_state = AnyClientState::try_from(client_state)
+			.map_err(|e| Error::Custom(format!("Failed to decode client state: {e:?}")))?;
+		if client_state.frozen_height().is_some() {
+			return Ok(ClientStatus::Frozen)
+		}
+
+		let latest_height = client_state.latest_height();
+		let response = self.query_client_consensus(at, client_id.clone(), latest_height).await?;
+		let consensus_state = response.consensus_state.ok_or_else(|| {
+			Error::NotFound(format!("Consensus state for {client_id} at {latest_height} not found"))
+		})?;
+		let consensus_state = AnyConsensusState::try_from(consensus_state)
+			.map_err(|e| Error::Custom(format!("Failed to decode consensus state: {e:?}")))?;
+		let now = Timestamp::from_nanoseconds(self.query_timestamp_at(at.revision_height).await?)?;
+		Ok(ClientStatus::from_client_state(&client_state, consensus_state.timestamp(), now))
+	}
+
 	async fn query_connection_end(
 		&self,
 		at: Height,
@@ -226,7 +267,7 @@ where
 			connection_id.to_string(),
 		)
 		.await
-		.map_err(|e| Error::from(format!("Rpc Error {:?}", e)))?;
+		.map_err(Error::from)?;
 		Ok(response)
 	}
 
@@ -243,17 +284,23 @@ where
 			port_id.to_string(),
 		)
 		.await
-		.map_err(|e| Error::from(format!("Rpc Error {:?}", e)))?;
+		.map_err(Error::from)?;
 		Ok(response)
 	}
 
 	/// Query the proof of the given keys at the given height.
 	///
 	/// Note: all the keys will be prefixed with the connection prefix.
-	async fn query_proof(&self, at: Height, keys: Vec<Vec<u8>>) -> Result<Vec<u8>, Self::Error> {
-		let prefix = self.connection_prefix().into_vec();
-		let prefixed_keys =
-			keys.into_iter().map(|path| apply_prefix(prefix.clone(), path)).collect();
+	async fn query_proof(
+		&self,
+		at: Height,
+		prefix: &CommitmentPrefix,
+		keys: Vec<Vec<u8>>,
+	) -> Result<Vec<u8>, Self::Error> {
+		let prefixed_keys = keys
+			.into_iter()
+			.map(|path| apply_prefix(prefix.as_bytes().to_vec(), path))
+			.collect();
 
 		let proof = IbcApiClient::<u32, H256, <T as light_client_common::config::Config>::AssetId>::query_proof(
 			&*self.para_ws_client,
@@ -261,7 +308,7 @@ where
 			prefixed_keys,
 		)
 		.await
-		.map_err(|e| Error::from(format!("Rpc Error {:?}", e)))?;
+		.map_err(Error::from)?;
 		Ok(proof.proof)
 	}
 
@@ -281,7 +328,7 @@ where
 				seq,
 			)
 			.await
-			.map_err(|e| Error::from(format!("Rpc Error {:?}", e)))?;
+			.map_err(Error::from)?;
 		Ok(res)
 	}
 
@@ -300,7 +347,7 @@ where
 			seq,
 		)
 		.await
-		.map_err(|e| Error::from(format!("Rpc Error {:?}", e)))?;
+		.map_err(Error::from)?;
 		Ok(res)
 	}
 
@@ -317,7 +364,7 @@ where
 			port_id.to_string(),
 		)
 		.await
-		.map_err(|e| Error::from(format!("Rpc Error {:?}", e)))?;
+		.map_err(Error::from)?;
 		Ok(res)
 	}
 
@@ -336,7 +383,7 @@ where
 			seq,
 		)
 		.await
-		.map_err(|e| Error::from(format!("Rpc Error {:?}", e)))?;
+		.map_err(Error::from)?;
 		Ok(res)
 	}
 
@@ -382,7 +429,7 @@ where
 				port_id.to_string(),
 			)
 			.await
-			.map_err(|e| Error::from(format!("Rpc Error {:?}", e)))?;
+			.map_err(Error::from)?;
 		Ok(res.commitments.into_iter().map(|packet_state| packet_state.sequence).collect())
 	}
 
@@ -399,7 +446,7 @@ where
 			port_id.to_string(),
 		)
 		.await
-		.map_err(|e| Error::from(format!("Rpc Error {:?}", e)))?;
+		.map_err(Error::from)?;
 		Ok(res
 			.acknowledgements
 			.into_iter()
@@ -423,7 +470,7 @@ where
 				seqs,
 			)
 			.await
-			.map_err(|e| Error::from(format!("Rpc Error {:?}", e)))?;
+			.map_err(Error::from)?;
 		Ok(res)
 	}
 
@@ -450,12 +497,20 @@ where
 			seqs,
 		)
 		.await
-		.map_err(|e| Error::from(format!("Rpc Error {:?}", e)))?;
+		.map_err(Error::from)?;
 		Ok(res)
 	}
 
 	fn channel_whitelist(&self) -> HashSet<(ChannelId, PortId)> {
-		self.channel_whitelist.lock().unwrap().iter().cloned().collect()
+		self.channel_whitelist.lock().unwrap().all()
+	}
+
+	fn is_channel_whitelisted_on(
+		&self,
+		channel: &(ChannelId, PortId),
+		connection_id: &ConnectionId,
+	) -> bool {
+		self.channel_whitelist.lock().unwrap().allows(channel, connection_id)
 	}
 
 	async fn query_connection_channels(
@@ -470,7 +525,7 @@ where
 				connection_id.to_string(),
 			)
 			.await
-			.map_err(|e| Error::from(format!("Rpc Error {:?}", e)))?;
+			.map_err(Error::from)?;
 		Ok(response)
 	}
 
@@ -488,7 +543,7 @@ where
 				seqs,
 			)
 			.await
-			.map_err(|e| Error::from(format!("Rpc Error {:?}", e)))?;
+			.map_err(Error::from)?;
 
 		Ok(response)
 	}
@@ -507,7 +562,7 @@ where
 				seqs,
 			)
 			.await
-			.map_err(|e| Error::from(format!("Rpc Error {:?}", e)))?;
+			.map_err(Error::from)?;
 		Ok(response)
 	}
 
@@ -534,7 +589,7 @@ where
 			client_height.revision_height,
 		)
 		.await
-		.map_err(|e| Error::from(format!("Rpc Error {:?}", e)))?;
+		.map_err(Error::from)?;
 		Ok((
 			response.height.into(),
 			Timestamp::from_nanoseconds(response.timestamp)
@@ -579,7 +634,7 @@ where
 		&self,
 		asset_id: Self::AssetId,
 	) -> Result<Vec<PrefixedCoin>, Self::Error> {
-		let account = self.public_key.clone().into_account();
+		let account = self.signer.account().into_account();
 		let account = subxt::utils::AccountId32::from(<[u8; 32]>::from(account));
 		let mut hex_string = hex::encode(account.0.to_vec());
 		hex_string.insert_str(0, "0x");
@@ -593,17 +648,61 @@ where
 			asset_id,
 		)
 		.await
-		.map_err(|e| Error::from(format!("Rpc Error {:?}", e)))?;
+		.map_err(Error::from)?;
 		Ok(vec![PrefixedCoin {
 			denom: PrefixedDenom::from_str(&coin.denom)?,
 			amount: Amount::from_str(&coin.amount)?,
 		}])
 	}
 
+	async fn query_relayer_balance(&self) -> Result<u128, Self::Error> {
+		self.query_free_balance(&self.signer.account()).await
+	}
+
+	/// ibc denoms are stored by their full path rather than their hash, so the traces of all
+	/// the ibc assets are searched for the hash.
+	async fn query_denom_trace(&self, hash: String) -> Result<DenomTrace, Self::Error> {
+		let ibc_denom = format!("ibc/{}", hash.trim_start_matches("ibc/"));
+		let mut key = None;
+		loop {
+			let response = IbcApiClient::<
+				u32,
+				H256,
+				<T as light_client_common::config::Config>::AssetId,
+			>::query_denom_traces(
+				&*self.para_ws_client, key, None, Some(DENOM_TRACES_PAGE_SIZE), false
+			)
+			.await
+			.map_err(Error::from)?;
+
+			if let Some(trace) =
+				response.denom_traces.into_iter().find(|trace| ibc_denom_of(trace) == ibc_denom)
+			{
+				return Ok(trace)
+			}
+			key = match response.pagination {
+				// the next key is the encoded asset id, encoded once more as bytes
+				Some(pagination) if !pagination.next_key.is_empty() => {
+					let next_key = Vec::<u8>::decode(&mut &*pagination.next_key)?;
+					Some(Decode::decode(&mut &*next_key)?)
+				},
+				_ => break,
+			};
+		}
+		Err(Error::from(format!("No denom trace for {ibc_denom}")))
+	}
+
 	fn connection_prefix(&self) -> CommitmentPrefix {
 		CommitmentPrefix::try_from(self.commitment_prefix.clone()).expect("Should not fail")
 	}
 
+	fn connection_prefix_of(&self, connection_id: &ConnectionId) -> CommitmentPrefix {
+		self.connection_prefixes
+			.get(connection_id)
+			.cloned()
+			.unwrap_or_else(|| self.connection_prefix())
+	}
+
 	fn client_id(&self) -> ClientId {
 		self.client_id()
 	}
@@ -613,7 +712,11 @@ where
 	}
 
 	fn connection_id(&self) -> Option<ConnectionId> {
-		self.connection_id.lock().unwrap().clone()
+		self.connection_ids.lock().unwrap().first().cloned()
+	}
+
+	fn connection_ids(&self) -> Vec<ConnectionId> {
+		self.connection_ids.lock().unwrap().clone()
 	}
 
 	fn client_type(&self) -> ClientType {
@@ -627,7 +730,7 @@ where
 		let subxt_block_number: subxt::rpc::types::BlockNumber = block_number.into();
 		let block_hash =
 			self.para_client.rpc().block_hash(Some(subxt_block_number)).await?.ok_or_else(
-				|| Error::Custom("Block hash not found for block number".to_string()),
+				|| Error::NotFound("Block hash not found for block number".to_string()),
 			)?;
 		let timestamp_addr = T::Storage::timestamp_now();
 		let unix_timestamp_millis = self
@@ -649,7 +752,7 @@ where
 			<T as light_client_common::config::Config>::AssetId,
 		>::query_clients(&*self.para_ws_client)
 		.await
-		.map_err(|e| Error::from(format!("Rpc Error {:?}", e)))?;
+		.map_err(Error::from)?;
 		response
 			.into_iter()
 			.map(|client| {
@@ -659,24 +762,21 @@ where
 			.collect()
 	}
 
-	async fn query_channels(&self) -> Result<Vec<(ChannelId, PortId)>, Self::Error> {
+	async fn query_identified_channels(&self) -> Result<Vec<IdentifiedChannelEnd>, Self::Error> {
+		// the channels are read from the storage of pallet-ibc in a single runtime api call
 		let response = IbcApiClient::<u32, H256, <T as light_client_common::config::Config>::AssetId>::query_channels(
 			&*self.para_ws_client,
 		)
 		.await
-		.map_err(|e| Error::from(format!("Rpc Error {:?}", e)))?;
+		.map_err(Error::from)?;
 		response
 			.channels
 			.into_iter()
-			.map(|identified_chan| {
-				Ok((
-					ChannelId::from_str(&identified_chan.channel_id)
-						.expect("Failed to convert invalid string to channel id"),
-					PortId::from_str(&identified_chan.port_id)
-						.expect("Failed to convert invalid string to port id"),
-				))
+			.map(|channel| {
+				IdentifiedChannelEnd::try_from(channel)
+					.map_err(|e| Error::Custom(format!("Invalid channel: {e}")))
 			})
-			.collect::<Result<Vec<_>, _>>()
+			.collect()
 	}
 
 	async fn query_connection_using_client(
@@ -690,7 +790,7 @@ where
 			client_id,
 		)
 		.await
-		.map_err(|e| Error::from(format!("Rpc Error {:?}", e)))?;
+		.map_err(Error::from)?;
 
 		Ok(response)
 	}
@@ -733,8 +833,16 @@ where
 		&self,
 		tx_id: Self::TransactionId,
 	) -> Result<ClientId, Self::Error> {
+		let created = tx_id.ibc_events.iter().find_map(|event| match event {
+			IbcEvent::CreateClient(ev) => Some(ev.client_id().clone()),
+			_ => None,
+		});
+		if let Some(client_id) = created {
+			return Ok(client_id)
+		}
+
 		// Query newly created client Id
-		let TransactionId { ext_hash, block_hash } = tx_id;
+		let TransactionId { ext_hash, block_hash, .. } = tx_id;
 		let identified_client_state = IbcApiClient::<
 			u32,
 			H256,
@@ -743,7 +851,7 @@ where
 			&*self.para_ws_client, block_hash.into(), ext_hash.into()
 		)
 		.await
-		.map_err(|e| Error::from(format!("Rpc Error {:?}", e)))?;
+		.map_err(Error::from)?;
 
 		let client_id = ClientId::from_str(&identified_client_state.client_id)
 			.expect("Should have a valid client id");
@@ -754,8 +862,17 @@ where
 		&self,
 		tx_id: Self::TransactionId,
 	) -> Result<ConnectionId, Self::Error> {
+		let created = tx_id.ibc_events.iter().find_map(|event| match event {
+			IbcEvent::OpenInitConnection(ev) => ev.connection_id().cloned(),
+			IbcEvent::OpenTryConnection(ev) => ev.connection_id().cloned(),
+			_ => None,
+		});
+		if let Some(connection_id) = created {
+			return Ok(connection_id)
+		}
+
 		// Query newly created connection Id
-		let TransactionId { ext_hash, block_hash } = tx_id;
+		let TransactionId { ext_hash, block_hash, .. } = tx_id;
 		let identified_connection: IdentifiedConnection = IbcApiClient::<
 			u32,
 			H256,
@@ -766,7 +883,7 @@ where
 			ext_hash.into(),
 		)
 		.await
-		.map_err(|e| Error::from(format!("Rpc Error {:?}", e)))?;
+		.map_err(Error::from)?;
 
 		let connection_id = ConnectionId::from_str(&identified_connection.id)
 			.expect("Should have a valid connection id");
@@ -777,8 +894,17 @@ where
 		&self,
 		tx_id: Self::TransactionId,
 	) -> Result<(ChannelId, PortId), Self::Error> {
+		let created = tx_id.ibc_events.iter().find_map(|event| match event {
+			IbcEvent::OpenInitChannel(ev) => Some((*ev.channel_id()?, ev.port_id().clone())),
+			IbcEvent::OpenTryChannel(ev) => Some((*ev.channel_id()?, ev.port_id().clone())),
+			_ => None,
+		});
+		if let Some(channel) = created {
+			return Ok(channel)
+		}
+
 		// Query newly created channel Id
-		let TransactionId { ext_hash, block_hash } = tx_id;
+		let TransactionId { ext_hash, block_hash, .. } = tx_id;
 		let identified_channel: IdentifiedChannel = IbcApiClient::<
 			u32,
 			H256,
@@ -789,7 +915,7 @@ where
 			ext_hash.into(),
 		)
 		.await
-		.map_err(|e| Error::from(format!("Rpc Error {:?}", e)))?;
+		.map_err(Error::from)?;
 
 		let channel_id = ChannelId::from_str(&identified_channel.channel_id)
 			.expect("Should have a valid channel id");
@@ -800,18 +926,61 @@ where
 
 	/// Set the channel whitelist for the relayer task.
 	fn set_channel_whitelist(&mut self, channel_whitelist: HashSet<(ChannelId, PortId)>) {
-		*self.channel_whitelist.lock().unwrap() = channel_whitelist;
+		self.channel_whitelist.lock().unwrap().set(channel_whitelist);
 	}
 
 	fn add_channel_to_whitelist(&mut self, channel: (ChannelId, PortId)) {
 		self.channel_whitelist.lock().unwrap().insert(channel);
 	}
 
+	fn add_channel_to_connection_whitelist(
+		&mut self,
+		connection_id: ConnectionId,
+		channel: (ChannelId, PortId),
+	) {
+		self.channel_whitelist.lock().unwrap().insert_on(connection_id, channel);
+	}
+
 	fn set_connection_id(&mut self, connection_id: ConnectionId) {
-		*self.connection_id.lock().unwrap() = Some(connection_id);
+		set_primary_connection_id(&mut self.connection_ids.lock().unwrap(), connection_id);
 	}
 
-	async fn upload_wasm(&self, _wasm: Vec<u8>) -> Result<Vec<u8>, Self::Error> {
+	fn add_connection_id(&mut self, connection_id: ConnectionId) {
+		let mut connection_ids = self.connection_ids.lock().unwrap();
+		if !connection_ids.contains(&connection_id) {
+			connection_ids.push(connection_id);
+		}
+	}
+
+	async fn upload_wasm(
+		&self,
+		_wasm: Vec<u8>,
+	) -> Result<(Vec<u8>, WasmUploadStatus), Self::Error> {
+		// pallet-ibc doesn't store wasm light clients, so there is no code to look up either
 		Err(Error::Custom("Uploading WASM to parachain is not supported".to_string()))
 	}
+
+	async fn query_wasm_code_stored(&self, _code_id: &[u8]) -> Result<bool, Self::Error> {
+		// pallet-ibc doesn't store wasm light clients
+		Ok(false)
+	}
+
+	async fn migrate_wasm_client(
+		&mut self,
+		_client_id: ClientId,
+		_code_id: Vec<u8>,
+		_migrate_msg: Vec<u8>,
+	) -> Result<(), Self::Error> {
+		Err(Error::Custom("Migrating WASM clients on parachain is not supported".to_string()))
+	}
+
+	fn register_counterparty_payee_msg(
+		&self,
+		_channel_id: ChannelId,
+		_port_id: PortId,
+		_counterparty_payee: String,
+	) -> Result<Any, Self::Error> {
+		// pallet-ibc doesn't implement the ICS-29 fee middleware
+		Err(Error::Custom("ICS-29 relayer fees are not supported on parachains".to_string()))
+	}
 }
@@ -12,97 +12,140 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::{error::Error, KeyType};
 use codec::Decode;
-use primitives::KeyProvider;
+use primitives::signer::TransactionSigner;
+use sc_keystore::LocalKeystore;
+use sp_core::{ecdsa, ed25519, sr25519, Pair};
 use sp_keystore::{Keystore, KeystorePtr};
 use sp_runtime::{
 	traits::{IdentifyAccount, Verify},
 	KeyTypeId, MultiSignature, MultiSigner,
 };
-use subxt::tx::Signer;
+use std::{fmt, path::PathBuf, sync::Arc};
+use subxt::{
+	tx::{PartialExtrinsic, SubmittableExtrinsic},
+	OnlineClient,
+};
 
-/// A [`Signer`] implementation.
+/// [`TransactionSigner`] of a key held by the local keystore.
 #[derive(Clone)]
-pub struct ExtrinsicSigner<T: light_client_common::config::Config, Provider: KeyProvider> {
-	account_id: T::AccountId,
-	signer: MultiSigner,
+pub struct KeystoreSigner {
 	key_store: KeystorePtr,
 	key_type_id: KeyTypeId,
-	_phantom: std::marker::PhantomData<Provider>,
+	public_key: MultiSigner,
+}
+
+impl KeystoreSigner {
+	/// Inserts the key derived from `private_key` in the local keystore.
+	pub fn new(key_type: &KeyType, private_key: &str) -> Result<Self, Error> {
+		let temp_dir = PathBuf::from("/tmp/keystore");
+		let key_store: KeystorePtr = Arc::new(LocalKeystore::open(temp_dir, None).unwrap());
+		let key_type_id = key_type.to_key_type_id();
+
+		let public_key: MultiSigner = match key_type {
+			KeyType::Sr25519 => sr25519::Pair::from_string_with_seed(private_key, None)
+				.map_err(|_| Error::Custom("invalid key".to_owned()))?
+				.0
+				.public()
+				.into(),
+			KeyType::Ed25519 => ed25519::Pair::from_string_with_seed(private_key, None)
+				.map_err(|_| Error::Custom("invalid key".to_owned()))?
+				.0
+				.public()
+				.into(),
+			KeyType::Ecdsa => ecdsa::Pair::from_string_with_seed(private_key, None)
+				.map_err(|_| Error::Custom("invalid key".to_owned()))?
+				.0
+				.public()
+				.into(),
+		};
+
+		key_store.insert(key_type_id, private_key, public_key.as_ref()).unwrap();
+
+		assert!(key_store.has_keys(&[(public_key.as_ref().to_vec(), key_type_id)]));
+		Ok(Self { key_store, key_type_id, public_key })
+	}
+}
+
+impl fmt::Debug for KeystoreSigner {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("KeystoreSigner")
+			.field("key_type_id", &self.key_type_id)
+			.field("public_key", &self.public_key)
+			.finish()
+	}
 }
 
-impl<T, P> ExtrinsicSigner<T, P>
+#[async_trait::async_trait]
+impl TransactionSigner for KeystoreSigner {
+	fn public_key(&self) -> Vec<u8> {
+		self.public_key.as_ref().to_vec()
+	}
+
+	async fn sign(&self, payload: &[u8]) -> Result<Vec<u8>, primitives::error::Error> {
+		let crypto_type_id = match &self.public_key {
+			MultiSigner::Ed25519(_) => ed25519::CRYPTO_ID,
+			MultiSigner::Sr25519(_) => sr25519::CRYPTO_ID,
+			MultiSigner::Ecdsa(_) => ecdsa::CRYPTO_ID,
+		};
+		Keystore::sign_with(
+			&*self.key_store,
+			self.key_type_id,
+			crypto_type_id,
+			self.public_key.as_ref(),
+			payload,
+		)
+		.map_err(|e| primitives::error::Error::Custom(format!("Keystore error: {e}")))?
+		.ok_or_else(|| {
+			primitives::error::Error::Custom("Key not found in the keystore".to_string())
+		})
+	}
+}
+
+/// Signs extrinsics with the [`TransactionSigner`] of a parachain.
+#[derive(Clone)]
+pub struct ExtrinsicSigner<T: light_client_common::config::Config> {
+	account_id: T::AccountId,
+	public_key: MultiSigner,
+	signer: Arc<dyn TransactionSigner>,
+}
+
+impl<T> ExtrinsicSigner<T>
 where
 	T: light_client_common::config::Config + Send + Sync,
 	<<T as light_client_common::config::Config>::Signature as Verify>::Signer:
 		From<MultiSigner> + IdentifyAccount<AccountId = T::AccountId>,
-	P: KeyProvider,
-	MultiSigner: From<MultiSigner>,
-	<T as subxt::Config>::AccountId: Send + Sync,
-	<T as subxt::Config>::Address: Send + Sync,
-	<T as subxt::Config>::Signature: Send + Sync,
+	T::AccountId: Into<<T as subxt::Config>::Address> + Clone,
+	<T as subxt::Config>::Signature: From<MultiSignature>,
 {
-	/// Creates a new [`Signer`] from a key store reference and key type
-	pub fn new(key_store: KeystorePtr, key_type_id: KeyTypeId, public_key: MultiSigner) -> Self {
+	/// Creates a new [`ExtrinsicSigner`] signing for the account of `public_key`
+	pub fn new(signer: Arc<dyn TransactionSigner>, public_key: MultiSigner) -> Self {
 		let account_id =
 			<<T as light_client_common::config::Config>::Signature as Verify>::Signer::from(
 				public_key.clone(),
 			)
 			.into_account();
-		Self {
-			account_id,
-			key_store,
-			key_type_id,
-			signer: MultiSigner::from(public_key),
-			_phantom: Default::default(),
-		}
+		Self { account_id, public_key, signer }
 	}
-}
 
-impl<T, P> Signer<T> for ExtrinsicSigner<T, P>
-where
-	T: light_client_common::config::Config + Send + Sync,
-	T::AccountId: Into<<T as subxt::Config>::Address> + Clone + 'static,
-	P: KeyProvider + 'static,
-	<T as subxt::Config>::Signature: From<MultiSignature> + Send + Sync,
-	<T as subxt::Config>::AccountId: Send + Sync,
-	<T as subxt::Config>::Address: Send + Sync,
-{
-	fn account_id(&self) -> &T::AccountId {
+	pub fn account_id(&self) -> &T::AccountId {
 		&self.account_id
 	}
 
-	fn address(&self) -> <T as subxt::Config>::Address {
-		self.account_id.clone().into()
-	}
-
-	fn sign(&self, signer_payload: &[u8]) -> <T as subxt::Config>::Signature {
-		let (crypto_type_id, public_key) = match &self.signer {
-			MultiSigner::Ed25519(key) => (sp_core::ed25519::CRYPTO_ID, key.0.to_vec()),
-			MultiSigner::Sr25519(key) => (sp_core::sr25519::CRYPTO_ID, key.0.to_vec()),
-			MultiSigner::Ecdsa(key) => (sp_core::ecdsa::CRYPTO_ID, key.0.to_vec()),
-		};
-		let encoded_sig = Keystore::sign_with(
-			&*self.key_store,
-			self.key_type_id,
-			crypto_type_id,
-			&public_key,
-			signer_payload,
-		)
-		.ok()
-		.flatten()
-		.expect("Signing should not fail");
-		let signature: MultiSignature = match self.signer {
-			MultiSigner::Ed25519(_) => sp_core::ed25519::Signature::decode(&mut &encoded_sig[..])
-				.expect("Should decode same signature type as public key; qed")
-				.into(),
-			MultiSigner::Sr25519(_) => sp_core::sr25519::Signature::decode(&mut &encoded_sig[..])
-				.expect("Should decode same signature type as public key; qed")
-				.into(),
-			MultiSigner::Ecdsa(_) => sp_core::ecdsa::Signature::decode(&mut &encoded_sig[..])
-				.expect("Should decode same signature type as public key; qed")
-				.into(),
+	/// Signs the extrinsic with the key of the account.
+	pub async fn sign(
+		&self,
+		extrinsic: PartialExtrinsic<T, OnlineClient<T>>,
+	) -> Result<SubmittableExtrinsic<T, OnlineClient<T>>, Error> {
+		let encoded_sig =
+			self.signer.sign(&extrinsic.signer_payload()).await.map_err(Error::Signer)?;
+		let signature: MultiSignature = match self.public_key {
+			MultiSigner::Ed25519(_) => ed25519::Signature::decode(&mut &encoded_sig[..])?.into(),
+			MultiSigner::Sr25519(_) => sr25519::Signature::decode(&mut &encoded_sig[..])?.into(),
+			MultiSigner::Ecdsa(_) => ecdsa::Signature::decode(&mut &encoded_sig[..])?.into(),
 		};
-		signature.into()
+		Ok(extrinsic
+			.sign_with_address_and_signature(&self.account_id.clone().into(), &signature.into()))
 	}
 }
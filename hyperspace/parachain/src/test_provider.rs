@@ -13,24 +13,27 @@
 // limitations under the License.
 
 use crate::{signer::ExtrinsicSigner, Error, ParachainClient};
-use codec::Decode;
+use codec::{Decode, Encode};
 use finality_grandpa::BlockNumberOps;
 use futures::{Stream, StreamExt};
 use grandpa_light_client_primitives::ParachainHeaderProofs;
 use ibc::{
-	applications::transfer::{msgs::transfer::MsgTransfer, PrefixedCoin},
-	core::ics24_host::identifier::{ChannelId, ClientId},
+	applications::transfer::{msgs::transfer::MsgTransfer, PrefixedCoin, PrefixedDenom},
+	core::ics24_host::identifier::{ChannelId, ClientId, PortId},
+	events::IbcEvent,
+	signer::Signer,
 };
-use ibc_proto::google::protobuf::Any;
+use ibc_proto::{google::protobuf::Any, ibc::applications::fee::v1::Fee};
 use ibc_rpc::IbcApiClient;
 use jsonrpsee::{core::client::SubscriptionClientT, rpc_params};
 use light_client_common::config::RuntimeTransactions;
 use pallet_ibc::{MultiAddress, Timeout, TransferParams};
 use pallet_ibc_ping::SendPingParams;
-use primitives::{KeyProvider, TestProvider};
+use primitives::{IbcProvider, KeyProvider, TestProvider};
 use sp_core::{
+	blake2_128,
 	crypto::{AccountId32, Ss58Codec},
-	H256,
+	twox_128, twox_64, H256,
 };
 use sp_runtime::{
 	traits::{IdentifyAccount, One, Verify},
@@ -70,7 +73,14 @@ where
 
 	pub async fn submit_create_client_msg(&self, msg: Any) -> Result<ClientId, Error> {
 		let call = T::Tx::ibc_deliver(vec![msg]);
-		let (ext_hash, block_hash) = self.submit_call(call).await?;
+		let tx_id = self.submit_call(call).await?;
+		let created = tx_id.ibc_events.iter().find_map(|event| match event {
+			IbcEvent::CreateClient(ev) => Some(ev.client_id().clone()),
+			_ => None,
+		});
+		if let Some(client_id) = created {
+			return Ok(client_id)
+		}
 
 		// Query newly created client Id
 		let identified_client_state = IbcApiClient::<
@@ -78,10 +88,12 @@ where
 			H256,
 			<T as light_client_common::config::Config>::AssetId,
 		>::query_newly_created_client(
-			&*self.para_ws_client, block_hash.into(), ext_hash.into()
+			&*self.para_ws_client,
+			tx_id.block_hash.into(),
+			tx_id.ext_hash.into(),
 		)
 		.await
-		.map_err(|e| Error::from(format!("Rpc Error {:?}", e)))?;
+		.map_err(Error::from)?;
 
 		let client_id = ClientId::from_str(&identified_client_state.client_id)
 			.expect("Should have a valid client id");
@@ -93,29 +105,38 @@ where
 		params: TransferParams<AccountId32>,
 		asset_id: u128,
 		amount: u128,
+		memo: Option<String>,
 	) -> Result<(), Error> {
+		// a memo the runtime can't carry would be lost
+		let memo = match memo {
+			Some(memo) => Some(T::Tx::memo(memo.clone()).ok_or_else(|| {
+				Error::Custom(format!("The runtime of {} can't carry the memo {memo:?}", self.name))
+			})?),
+			None => None,
+		};
 		// Submit extrinsic to parachain node
-		let call = T::Tx::ibc_transfer(params.into(), asset_id, amount, None);
+		let call = T::Tx::ibc_transfer(params.into(), asset_id, amount, memo);
 		self.submit_call(call).await?;
 		Ok(())
 	}
 
 	pub async fn submit_sudo_call(&self, call: T::ParaRuntimeCall) -> Result<(), Error> {
-		let signer = ExtrinsicSigner::<T, Self>::new(
-			self.key_store.clone(),
-			self.key_type_id.clone(),
-			self.public_key.clone(),
-		);
+		let signer = self.extrinsic_signer();
 
 		let ext = T::Tx::sudo_sudo(call);
 		// Submit extrinsic to parachain node
 
 		let other_params = T::custom_extrinsic_params(&self.para_client).await?;
 
-		let _progress = self
+		let extrinsic = self
 			.para_client
 			.tx()
-			.sign_and_submit_then_watch(&ext, &signer, other_params)
+			.create_partial_signed(&ext, signer.account_id(), other_params)
+			.await?;
+		let _progress = signer
+			.sign(extrinsic)
+			.await?
+			.submit_and_watch()
 			.await?
 			.wait_for_in_block()
 			.await?
@@ -148,7 +169,7 @@ where
 		From<BaseExtrinsicParamsBuilder<T, T::Tip>> + Send + Sync,
 	<T as subxt::Config>::AccountId: Send + Sync,
 	<T as subxt::Config>::Address: Send + Sync,
-	<T as light_client_common::config::Config>::AssetId: Clone,
+	<T as light_client_common::config::Config>::AssetId: Clone + Into<u128>,
 	<<T as light_client_common::config::Config>::Tx as RuntimeTransactions>::TransferParams:
 		From<TransferParams<AccountId32>>,
 	<<T as light_client_common::config::Config>::Tx as RuntimeTransactions>::SendPingParams:
@@ -174,23 +195,50 @@ where
 			"Sending transfer: {:?}, asset id: {asset_id}, amount: {amount}",
 			transfer.token.denom
 		);
-		self.transfer_tokens(params, asset_id, amount).await?;
+		let memo = (!transfer.memo.is_empty()).then(|| transfer.memo.clone());
+		self.transfer_tokens(params, asset_id, amount, memo).await?;
 
 		Ok(())
 	}
 
+	async fn send_transfer_with_fee(
+		&self,
+		_transfer: MsgTransfer<PrefixedCoin>,
+		_fee: Fee,
+	) -> Result<(), Self::Error> {
+		// pallet-ibc doesn't implement the ICS-29 fee middleware
+		Err(Error::Custom("ICS-29 relayer fees are not supported on parachains".to_string()))
+	}
+
 	async fn send_ordered_packet(
 		&self,
 		channel_id: ChannelId,
 		timeout: Timeout,
 	) -> Result<(), Self::Error> {
+		let ping = PortId::from_str("ping").expect("ping is a valid port id; qed");
+		self.send_raw_packet(ping, channel_id, "ping".as_bytes().to_vec(), timeout)
+			.await
+	}
+
+	async fn send_raw_packet(
+		&self,
+		port_id: PortId,
+		channel_id: ChannelId,
+		data: Vec<u8>,
+		timeout: Timeout,
+	) -> Result<(), Self::Error> {
+		// the ping pallet is the only application of the test runtime sending raw packets
+		if port_id.as_str() != "ping" {
+			return Err(Error::Custom(format!("Can't send raw packets on port {port_id}")))
+		}
 		let (timeout_height, timestamp) = match timeout {
-			Timeout::Offset { timestamp, height } => (height.unwrap(), timestamp.unwrap()),
-			_ => panic!("Only offset timeouts allowed"),
+			Timeout::Offset { timestamp: Some(timestamp), height: Some(height) } =>
+				(height, timestamp),
+			_ => return Err(Error::Custom("Only offset timeouts allowed".to_string())),
 		};
 
 		let params = SendPingParams {
-			data: "ping".as_bytes().to_vec(),
+			data,
 			timeout_height_offset: timeout_height,
 			timeout_timestamp_offset: timestamp,
 			channel_id: channel_id.sequence(),
@@ -224,4 +272,69 @@ where
 		let call = T::Tx::ibc_increase_counters();
 		self.submit_sudo_call(call).await.map(|_| ())
 	}
+
+	async fn ensure_asset(
+		&self,
+		asset_id: Self::AssetId,
+		mint_to: Signer,
+		amount: u128,
+	) -> Result<(), Self::Error> {
+		let account = |signer: Signer| {
+			AccountId32::from_ss58check(signer.as_ref())
+				.map_err(|e| Error::Custom(format!("Invalid account {signer}: {e:?}")))
+		};
+		let owner = account(self.account_id())?;
+		let beneficiary = account(mint_to)?;
+		// the asset details are stored in the `Asset` map of pallet-assets
+		let mut storage_key = twox_128(b"Assets").to_vec();
+		storage_key.extend(twox_128(b"Asset"));
+		storage_key.extend(blake2_128(&asset_id.encode()));
+		storage_key.extend(asset_id.encode());
+		let exists = self.para_client.rpc().storage(&*storage_key, None).await?.is_some();
+		let balance = if exists {
+			let coin = IbcApiClient::<u32, H256, T::AssetId>::query_balance_with_address(
+				&*self.para_ws_client,
+				format!("0x{}", hex::encode(&beneficiary)),
+				asset_id.clone(),
+			)
+			.await
+			.map_err(Error::from)?;
+			u128::from_str(&coin.amount)
+				.map_err(|e| Error::Custom(format!("Invalid balance {}: {e}", coin.amount)))?
+		} else {
+			0
+		};
+
+		let asset_id = asset_id.into();
+		// the relayer account is made the owner of the asset, so that it can mint it
+		if !exists {
+			log::info!("Creating asset {asset_id}");
+			self.submit_sudo_call(T::Tx::assets_force_create(asset_id, owner)).await?;
+		}
+		// only the missing amount is minted, so that the balance doesn't grow with every run
+		if balance < amount {
+			log::info!("Minting {} of asset {asset_id} to {beneficiary}", amount - balance);
+			self.submit_call(T::Tx::assets_mint(asset_id, beneficiary, amount - balance))
+				.await?;
+		}
+		Ok(())
+	}
+
+	/// Reads the asset id of the denom's full path from the `IbcDenoms` map of pallet-ibc.
+	async fn query_asset_id(&self, ibc_denom: String) -> Result<Self::AssetId, Self::Error> {
+		let trace = self.query_denom_trace(ibc_denom.clone()).await?;
+		let denom = PrefixedDenom::try_from(trace)?.to_string().into_bytes().encode();
+		let mut storage_key = twox_128(b"Ibc").to_vec();
+		storage_key.extend(twox_128(b"IbcDenoms"));
+		storage_key.extend(twox_64(&denom));
+		storage_key.extend(denom);
+
+		let asset_id = self
+			.para_client
+			.rpc()
+			.storage(&*storage_key, None)
+			.await?
+			.ok_or_else(|| Error::Custom(format!("No asset holds {ibc_denom}")))?;
+		Ok(Decode::decode(&mut &*asset_id.0)?)
+	}
 }
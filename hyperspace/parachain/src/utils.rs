@@ -59,3 +59,33 @@ pub async fn fetch_max_extrinsic_weight<T: light_client_common::config::Config>(
 		.unwrap_or(Weight::from_parts(u64::MAX, 0));
 	Ok(max_extrinsic_weight.ref_time())
 }
+
+/// Fetches the latest metadata and runtime version of the chain and sets them on the client.
+pub async fn refresh_metadata<T: subxt::Config>(
+	client: &subxt::OnlineClient<T>,
+) -> Result<(), Error> {
+	let metadata = client.rpc().metadata().await?;
+	let runtime_version = client.rpc().runtime_version(None).await?;
+	client.set_metadata(metadata);
+	client.set_runtime_version(subxt::client::RuntimeVersion {
+		spec_version: runtime_version.spec_version,
+		transaction_version: runtime_version.transaction_version,
+	});
+	Ok(())
+}
+
+/// Returns a callback that refreshes the metadata of the client in the background, used to
+/// recover from finality notifications that can't be decoded anymore.
+pub fn metadata_refresher<T: subxt::Config>(
+	client: subxt::OnlineClient<T>,
+) -> impl FnMut() + Send + Sync + 'static {
+	move || {
+		let client = client.clone();
+		tokio::spawn(async move {
+			log::info!(target: "hyperspace_parachain", "Refreshing relay chain metadata");
+			if let Err(e) = refresh_metadata(&client).await {
+				log::error!(target: "hyperspace_parachain", "Failed to refresh relay chain metadata: {e:?}");
+			}
+		});
+	}
+}
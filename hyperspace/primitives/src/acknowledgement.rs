@@ -0,0 +1,103 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Decoding of the acknowledgements written by the receivers of packets, so that the relayer can
+//! tell why a packet failed on its destination.
+
+use crate::packet_filter::hex_packet_data;
+use ibc::applications::transfer::acknowledgement::Acknowledgement as TransferAcknowledgement;
+use ibc_proto::ibc::core::channel::v1::{
+	acknowledgement::Response, Acknowledgement as RawAcknowledgement,
+};
+use prost::Message;
+use std::fmt::{self, Display};
+
+/// The outcome of a packet on its destination, as written in its acknowledgement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AckResult {
+	/// The packet was handled.
+	Success,
+	/// The packet failed to be handled, for the given reason.
+	Error(String),
+	/// The acknowledgement isn't in a known format.
+	Unknown(Vec<u8>),
+}
+
+impl AckResult {
+	/// Decodes an acknowledgement, either in the JSON format of ICS20 (`{"result": ...}` or
+	/// `{"error": ...}`) or in the protobuf envelope of ICS4.
+	pub fn decode(ack: &[u8]) -> Self {
+		if let Ok(ack) = serde_json::from_slice::<TransferAcknowledgement>(ack) {
+			return match ack {
+				TransferAcknowledgement::Result(_) => Self::Success,
+				TransferAcknowledgement::Error(e) => Self::Error(e),
+			}
+		}
+		match RawAcknowledgement::decode(ack) {
+			Ok(RawAcknowledgement { response: Some(Response::Result(_)) }) => Self::Success,
+			Ok(RawAcknowledgement { response: Some(Response::Error(e)) }) => Self::Error(e),
+			_ => Self::Unknown(ack.to_vec()),
+		}
+	}
+
+	pub fn is_error(&self) -> bool {
+		matches!(self, Self::Error(_))
+	}
+}
+
+impl Display for AckResult {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Success => write!(f, "success"),
+			Self::Error(e) => write!(f, "error: {e}"),
+			Self::Unknown(ack) => write!(f, "unknown acknowledgement {}", hex_packet_data(ack)),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn transfer_acknowledgements_are_decoded() {
+		assert_eq!(AckResult::decode(br#"{"result":"AQ=="}"#), AckResult::Success);
+		assert_eq!(
+			AckResult::decode(br#"{"error":"insufficient funds"}"#),
+			AckResult::Error("insufficient funds".to_string())
+		);
+	}
+
+	#[test]
+	fn acknowledgement_envelopes_are_decoded() {
+		let ack = |response| RawAcknowledgement { response: Some(response) }.encode_to_vec();
+		assert_eq!(AckResult::decode(&ack(Response::Result(vec![1]))), AckResult::Success);
+		assert_eq!(
+			AckResult::decode(&ack(Response::Error("out of gas".to_string()))),
+			AckResult::Error("out of gas".to_string())
+		);
+	}
+
+	#[test]
+	fn unknown_acknowledgements_are_kept_opaque() {
+		for ack in [vec![], vec![1], br#"{"other":"field"}"#.to_vec()] {
+			let result = AckResult::decode(&ack);
+			assert_eq!(result, AckResult::Unknown(ack.clone()));
+			assert_eq!(
+				result.to_string(),
+				format!("unknown acknowledgement 0x{}", hex::encode(&ack))
+			);
+		}
+	}
+}
@@ -0,0 +1,210 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Registry of the protobuf [`Any`] messages known to the relayer by their type url, so that the
+//! messages of an unknown type are reported instead of passed through silently.
+
+use crate::mock::LocalClientTypes;
+use ibc::{
+	applications::transfer::{msgs::transfer, PrefixedCoin},
+	core::ics26_routing::msgs::{self as ics26, Ics26Envelope},
+	protobuf::Protobuf,
+};
+use ibc_proto::{
+	google::protobuf::Any,
+	ibc::applications::{
+		fee::v1::{MsgPayPacketFee, MsgRegisterCounterpartyPayee},
+		transfer::v1::MsgTransfer as RawMsgTransfer,
+	},
+};
+use ics08_wasm::msg::{
+	MsgMigrateContract, MsgPushNewWasmCode, WASM_MIGRATE_CONTRACT_TYPE_URL,
+	WASM_PUSH_WASM_CODE_TYPE_URL,
+};
+use pallet_ibc::light_clients::{AnyClientMessage, AnyClientState, AnyConsensusState};
+use prost::Message;
+use std::{collections::BTreeMap, sync::OnceLock};
+
+/// Type url of `MsgRegisterCounterpartyPayee` of the ICS-29 fee module.
+pub const REGISTER_COUNTERPARTY_PAYEE_TYPE_URL: &str =
+	"/ibc.applications.fee.v1.MsgRegisterCounterpartyPayee";
+
+/// Type url of `MsgPayPacketFee` of the ICS-29 fee module.
+pub const PAY_PACKET_FEE_TYPE_URL: &str = "/ibc.applications.fee.v1.MsgPayPacketFee";
+
+/// Decodes the value of a message to check that it's valid.
+pub type AnyDecoder = fn(&Any) -> Result<(), String>;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum AnyRegistryError {
+	#[error("Unknown message type {type_url} of {size} bytes")]
+	Unknown { type_url: String, size: usize },
+	#[error("Invalid {type_url} message of {size} bytes: {error}")]
+	Invalid { type_url: String, size: usize, error: String },
+}
+
+/// Decoders of the messages, client states, consensus states and client messages known to the
+/// relayer, by type url.
+#[derive(Debug, Clone, Default)]
+pub struct AnyRegistry {
+	decoders: BTreeMap<&'static str, AnyDecoder>,
+}
+
+impl AnyRegistry {
+	pub fn register(&mut self, type_url: &'static str, decoder: AnyDecoder) -> &mut Self {
+		self.decoders.insert(type_url, decoder);
+		self
+	}
+
+	pub fn contains(&self, type_url: &str) -> bool {
+		self.decoders.contains_key(type_url)
+	}
+
+	/// Type urls of the registered messages, in lexicographic order.
+	pub fn type_urls(&self) -> impl Iterator<Item = &'static str> + '_ {
+		self.decoders.keys().copied()
+	}
+
+	/// Checks that `msg` is of a known type and that its value decodes as such.
+	pub fn decode(&self, msg: &Any) -> Result<(), AnyRegistryError> {
+		let size = msg.value.len();
+		let decoder = self
+			.decoders
+			.get(msg.type_url.as_str())
+			.ok_or_else(|| AnyRegistryError::Unknown { type_url: msg.type_url.clone(), size })?;
+		decoder(msg).map_err(|error| AnyRegistryError::Invalid {
+			type_url: msg.type_url.clone(),
+			size,
+			error,
+		})
+	}
+
+	/// Returns whether `msg` is of a known type and decodes as such, warning about it with its
+	/// type url and size otherwise. `context` tells what the message was used for.
+	pub fn check_known(&self, msg: &Any, context: &str) -> bool {
+		match self.decode(msg) {
+			Ok(()) => true,
+			Err(e) => {
+				log::warn!(target: "hyperspace", "{e} in {context}");
+				false
+			},
+		}
+	}
+}
+
+/// Registers each of the `$type_urls` with the `TryFrom<Any>` implementation of `$ty`.
+macro_rules! register_any {
+	($registry:ident, $ty:ty, $type_urls:expr) => {
+		for type_url in $type_urls {
+			$registry.register(type_url, |msg| {
+				<$ty>::try_from(msg.clone()).map(|_| ()).map_err(|e| e.to_string())
+			});
+		}
+	};
+}
+
+/// Registry of the IBC messages submitted by the relayer and of the client types it relays
+/// between.
+pub fn known_messages() -> &'static AnyRegistry {
+	static REGISTRY: OnceLock<AnyRegistry> = OnceLock::new();
+	REGISTRY.get_or_init(|| {
+		let mut registry = AnyRegistry::default();
+		register_any!(registry, Ics26Envelope<LocalClientTypes>, ics26::TYPE_URLS.iter().copied());
+		register_any!(registry, AnyClientState, AnyClientState::TYPE_URLS.iter().copied());
+		register_any!(registry, AnyConsensusState, AnyConsensusState::TYPE_URLS.iter().copied());
+		register_any!(registry, AnyClientMessage, AnyClientMessage::TYPE_URLS.iter().copied());
+		registry.register(transfer::TYPE_URL, |msg| {
+			let raw = RawMsgTransfer::decode(&*msg.value).map_err(|e| e.to_string())?;
+			transfer::MsgTransfer::<PrefixedCoin>::try_from(raw)
+				.map(|_| ())
+				.map_err(|e| e.to_string())
+		});
+		registry.register(WASM_PUSH_WASM_CODE_TYPE_URL, |msg| {
+			MsgPushNewWasmCode::decode_vec(&msg.value)
+				.map(|_| ())
+				.map_err(|e| e.to_string())
+		});
+		registry.register(WASM_MIGRATE_CONTRACT_TYPE_URL, |msg| {
+			MsgMigrateContract::decode_vec(&msg.value)
+				.map(|_| ())
+				.map_err(|e| e.to_string())
+		});
+		registry.register(REGISTER_COUNTERPARTY_PAYEE_TYPE_URL, |msg| {
+			MsgRegisterCounterpartyPayee::decode(&*msg.value)
+				.map(|_| ())
+				.map_err(|e| e.to_string())
+		});
+		registry.register(PAY_PACKET_FEE_TYPE_URL, |msg| {
+			MsgPayPacketFee::decode(&*msg.value).map(|_| ()).map_err(|e| e.to_string())
+		});
+
+		registry
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn decoded_type_urls_are_registered() {
+		let type_urls = ics26::TYPE_URLS
+			.iter()
+			.chain(AnyClientState::TYPE_URLS)
+			.chain(AnyConsensusState::TYPE_URLS)
+			.chain(AnyClientMessage::TYPE_URLS)
+			.chain([
+				&WASM_PUSH_WASM_CODE_TYPE_URL,
+				&WASM_MIGRATE_CONTRACT_TYPE_URL,
+				&transfer::TYPE_URL,
+			]);
+		for type_url in type_urls {
+			let msg = Any { type_url: type_url.to_string(), value: vec![0xff; 4] };
+			assert!(
+				matches!(known_messages().decode(&msg), Err(AnyRegistryError::Invalid { .. })),
+				"{type_url} isn't registered"
+			);
+		}
+	}
+
+	#[test]
+	fn unknown_and_invalid_messages_are_rejected() {
+		let registry = known_messages();
+		let msg = Any { type_url: "/ibc.unknown.v1.Msg".to_string(), value: vec![1, 2, 3] };
+		assert!(!registry.check_known(&msg, "test"));
+		assert_eq!(
+			registry.decode(&msg),
+			Err(AnyRegistryError::Unknown { type_url: msg.type_url.clone(), size: 3 })
+		);
+
+		let msg = Any { type_url: ics26::TYPE_URLS[0].to_string(), value: vec![0xff; 4] };
+		assert!(!registry.check_known(&msg, "test"));
+		assert!(matches!(registry.decode(&msg), Err(AnyRegistryError::Invalid { size: 4, .. })));
+	}
+
+	#[test]
+	fn fee_messages_are_decoded() {
+		let msg = MsgRegisterCounterpartyPayee {
+			port_id: "transfer".to_string(),
+			channel_id: "channel-0".to_string(),
+			relayer: "relayer".to_string(),
+			counterparty_payee: "payee".to_string(),
+		};
+		let msg = Any {
+			type_url: REGISTER_COUNTERPARTY_PAYEE_TYPE_URL.to_string(),
+			value: msg.encode_to_vec(),
+		};
+		assert_eq!(known_messages().decode(&msg), Ok(()));
+	}
+}
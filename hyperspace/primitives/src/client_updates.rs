@@ -0,0 +1,321 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Client updates submitted by the relayer. An update is pending from its submission until its
+//! `UpdateClient` event is observed, during which the client state queried from the chain
+//! doesn't reflect it yet. Updates whose event isn't observed within [`PENDING_UPDATE_TTL`] are
+//! assumed to be dropped, and may be submitted again.
+
+use ibc::{core::ics24_host::identifier::ClientId, Height};
+use std::{
+	collections::{BTreeMap, BTreeSet, HashMap},
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+/// Number of submitted update heights remembered per client.
+const MAX_TRACKED_UPDATES: usize = 256;
+
+/// How long a submitted update stays pending without its `UpdateClient` event being observed.
+pub const PENDING_UPDATE_TTL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Default)]
+struct ClientUpdates {
+	/// Heights of the updates claimed for submission, or observed on chain
+	claimed: BTreeSet<Height>,
+	/// Heights of the claimed updates whose `UpdateClient` event wasn't observed yet, with the
+	/// time they were claimed at
+	pending: BTreeMap<Height, Instant>,
+}
+
+impl ClientUpdates {
+	/// Releases the pending updates claimed more than [`PENDING_UPDATE_TTL`] before `now`, whose
+	/// transactions were most likely dropped.
+	fn expire(&mut self, now: Instant) {
+		let expired = self
+			.pending
+			.iter()
+			.filter(|(_, claimed_at)| {
+				now.saturating_duration_since(**claimed_at) >= PENDING_UPDATE_TTL
+			})
+			.map(|(height, _)| *height)
+			.collect::<Vec<_>>();
+		for height in expired {
+			log::warn!(target: "hyperspace", "No UpdateClient event observed for the update to {height} within {PENDING_UPDATE_TTL:?}, releasing it");
+			self.pending.remove(&height);
+			self.claimed.remove(&height);
+		}
+	}
+
+	fn prune(&mut self) {
+		while self.claimed.len() > MAX_TRACKED_UPDATES {
+			self.claimed.pop_first();
+		}
+		while self.pending.len() > MAX_TRACKED_UPDATES {
+			self.pending.pop_first();
+		}
+	}
+}
+
+/// Client updates submitted by the relay tasks of a path, keyed by the chain hosting the client.
+/// Shared between the tasks so that the same update is never submitted twice.
+#[derive(Debug, Clone, Default)]
+pub struct SubmittedClientUpdates(Arc<Mutex<HashMap<(String, ClientId), ClientUpdates>>>);
+
+impl SubmittedClientUpdates {
+	/// Claims the submission of the update of `client_id` on `chain` to `height`. Returns `false`
+	/// if it has already been claimed.
+	pub fn claim(&self, chain: &str, client_id: &ClientId, height: Height) -> bool {
+		let mut updates = self.0.lock().unwrap();
+		let updates = updates.entry((chain.to_string(), client_id.clone())).or_default();
+		let now = Instant::now();
+		updates.expire(now);
+		if !updates.claimed.insert(height) {
+			return false
+		}
+		updates.pending.insert(height, now);
+		updates.prune();
+		true
+	}
+
+	/// Whether the update of `client_id` on `chain` to `height` was claimed for submission, or
+	/// observed on chain.
+	pub fn is_claimed(&self, chain: &str, client_id: &ClientId, height: Height) -> bool {
+		let mut updates = self.0.lock().unwrap();
+		updates
+			.get_mut(&(chain.to_string(), client_id.clone()))
+			.map_or(false, |updates| {
+				updates.expire(Instant::now());
+				updates.claimed.contains(&height)
+			})
+	}
+
+	/// Claims of updates of `client_id` on `chain` that are released if dropped before being
+	/// handed over, see [`UpdateClaims`].
+	pub fn claims(&self, chain: &str, client_id: &ClientId) -> UpdateClaims {
+		UpdateClaims {
+			updates: self.clone(),
+			chain: chain.to_string(),
+			client_id: client_id.clone(),
+			heights: vec![],
+		}
+	}
+
+	/// Releases the claims of updates that failed to be submitted.
+	pub fn release(&self, chain: &str, client_id: &ClientId, heights: &[Height]) {
+		let mut updates = self.0.lock().unwrap();
+		if let Some(updates) = updates.get_mut(&(chain.to_string(), client_id.clone())) {
+			for height in heights {
+				updates.claimed.remove(height);
+				updates.pending.remove(height);
+			}
+		}
+	}
+
+	/// Records the `UpdateClient` event of `client_id` on `chain` to `height`, which settles the
+	/// pending updates up to that height.
+	pub fn confirm(&self, chain: &str, client_id: &ClientId, height: Height) {
+		let mut updates = self.0.lock().unwrap();
+		let updates = updates.entry((chain.to_string(), client_id.clone())).or_default();
+		// updates that are still pending below the height were superseded
+		updates.pending.retain(|pending, _| *pending > height);
+		updates.claimed.insert(height);
+		updates.prune();
+	}
+
+	/// Height of `client_id` on `chain` once the pending updates are included, given its
+	/// `latest_height` queried from the chain.
+	pub fn expected_height(
+		&self,
+		chain: &str,
+		client_id: &ClientId,
+		latest_height: Height,
+	) -> Height {
+		let mut updates = self.0.lock().unwrap();
+		updates
+			.get_mut(&(chain.to_string(), client_id.clone()))
+			.and_then(|updates| {
+				updates.expire(Instant::now());
+				updates.pending.keys().next_back().copied()
+			})
+			.map_or(latest_height, |pending| pending.max(latest_height))
+	}
+}
+
+/// Updates of a client claimed while assembling a batch of messages. The claims are released
+/// when dropped, so that the updates of a batch that failed to be assembled can be claimed again,
+/// unless they were handed over to the batch with [`UpdateClaims::into_heights`].
+#[derive(Debug)]
+pub struct UpdateClaims {
+	updates: SubmittedClientUpdates,
+	chain: String,
+	client_id: ClientId,
+	heights: Vec<Height>,
+}
+
+impl UpdateClaims {
+	/// Claims the submission of the update to `height`, see [`SubmittedClientUpdates::claim`].
+	pub fn claim(&mut self, height: Height) -> bool {
+		if !self.updates.claim(&self.chain, &self.client_id, height) {
+			return false
+		}
+		self.heights.push(height);
+		true
+	}
+
+	/// Releases the claim of the update to `height` right away.
+	pub fn release(&mut self, height: Height) {
+		self.heights.retain(|claimed| *claimed != height);
+		self.updates.release(&self.chain, &self.client_id, &[height]);
+	}
+
+	/// Hands over the claimed heights, which are then released by their new owner.
+	pub fn into_heights(mut self) -> Vec<Height> {
+		std::mem::take(&mut self.heights)
+	}
+}
+
+impl Drop for UpdateClaims {
+	fn drop(&mut self) {
+		self.updates.release(&self.chain, &self.client_id, &self.heights);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn client_id() -> ClientId {
+		ClientId::new("07-tendermint", 0).unwrap()
+	}
+
+	#[test]
+	fn client_updates_are_claimed_once() {
+		let updates = SubmittedClientUpdates::default();
+		let shared = updates.clone();
+		let client_id = client_id();
+		let height = Height::new(1, 10);
+
+		assert!(updates.claim("chain_b", &client_id, height));
+		assert!(!shared.claim("chain_b", &client_id, height));
+		assert!(shared.is_claimed("chain_b", &client_id, height));
+		// the same height of another client, or on another chain, is a different update
+		assert!(shared.claim("chain_a", &client_id, height));
+		assert!(shared.claim("chain_b", &ClientId::new("07-tendermint", 1).unwrap(), height));
+
+		shared.release("chain_b", &client_id, &[height]);
+		assert!(!updates.is_claimed("chain_b", &client_id, height));
+		assert!(updates.claim("chain_b", &client_id, height));
+	}
+
+	#[test]
+	fn dropped_claims_are_released() {
+		let updates = SubmittedClientUpdates::default();
+		let client_id = client_id();
+		let (first, second) = (Height::new(1, 10), Height::new(1, 11));
+
+		let mut claims = updates.claims("chain_b", &client_id);
+		assert!(claims.claim(first));
+		assert!(!claims.claim(first));
+		assert!(claims.claim(second));
+		claims.release(second);
+		assert!(!updates.is_claimed("chain_b", &client_id, second));
+		// the batch failed to be assembled
+		drop(claims);
+		assert!(!updates.is_claimed("chain_b", &client_id, first));
+
+		let mut claims = updates.claims("chain_b", &client_id);
+		assert!(claims.claim(first));
+		assert_eq!(claims.into_heights(), vec![first]);
+		assert!(updates.is_claimed("chain_b", &client_id, first));
+	}
+
+	#[test]
+	fn oldest_claims_are_forgotten() {
+		let updates = SubmittedClientUpdates::default();
+		let client_id = client_id();
+		for height in 0..=MAX_TRACKED_UPDATES as u64 {
+			assert!(updates.claim("chain_b", &client_id, Height::new(1, height)));
+		}
+		assert!(updates.claim("chain_b", &client_id, Height::new(1, 0)));
+		assert!(!updates.claim("chain_b", &client_id, Height::new(1, MAX_TRACKED_UPDATES as u64)));
+	}
+
+	#[test]
+	fn pending_updates_are_settled_by_their_events() {
+		let updates = SubmittedClientUpdates::default();
+		let client_id = client_id();
+		let on_chain = Height::new(1, 5);
+		assert_eq!(updates.expected_height("chain_b", &client_id, on_chain), on_chain);
+
+		assert!(updates.claim("chain_b", &client_id, Height::new(1, 10)));
+		assert!(updates.claim("chain_b", &client_id, Height::new(1, 12)));
+		assert_eq!(updates.expected_height("chain_b", &client_id, on_chain), Height::new(1, 12));
+
+		updates.confirm("chain_b", &client_id, Height::new(1, 10));
+		assert_eq!(updates.expected_height("chain_b", &client_id, on_chain), Height::new(1, 12));
+		// the update to 12 failed
+		updates.release("chain_b", &client_id, &[Height::new(1, 12)]);
+		assert_eq!(updates.expected_height("chain_b", &client_id, on_chain), on_chain);
+
+		// an update submitted by another relayer settles the older pending ones
+		assert!(updates.claim("chain_b", &client_id, Height::new(1, 12)));
+		updates.confirm("chain_b", &client_id, Height::new(1, 14));
+		assert_eq!(updates.expected_height("chain_b", &client_id, on_chain), on_chain);
+		assert!(!updates.claim("chain_b", &client_id, Height::new(1, 14)));
+	}
+
+	#[test]
+	fn updates_arent_duplicated_before_their_confirmation() {
+		let updates = SubmittedClientUpdates::default();
+		let client_id = client_id();
+		let on_chain = Height::new(1, 5);
+
+		// the first finality event submits an update to 10
+		let expected = updates.expected_height("chain_b", &client_id, on_chain);
+		assert_eq!(expected, on_chain);
+		assert!(updates.claim("chain_b", &client_id, Height::new(1, 10)));
+
+		// the second one arrives before the update is included, while the client state queried
+		// from chain b is still at 5
+		let expected = updates.expected_height("chain_b", &client_id, on_chain);
+		assert_eq!(expected, Height::new(1, 10));
+		assert!(!updates.claim("chain_b", &client_id, Height::new(1, 10)));
+
+		updates.confirm("chain_b", &client_id, Height::new(1, 10));
+		assert_eq!(updates.expected_height("chain_b", &client_id, Height::new(1, 10)), expected);
+		assert!(!updates.claim("chain_b", &client_id, Height::new(1, 10)));
+	}
+
+	#[test]
+	fn dropped_updates_expire() {
+		let updates = SubmittedClientUpdates::default();
+		let client_id = client_id();
+		let on_chain = Height::new(1, 5);
+		assert!(updates.claim("chain_b", &client_id, Height::new(1, 10)));
+		updates.confirm("chain_b", &client_id, Height::new(1, 10));
+		assert!(updates.claim("chain_b", &client_id, Height::new(1, 12)));
+
+		// the transaction of the update to 12 was dropped
+		let claimed_at = Instant::now().checked_sub(PENDING_UPDATE_TTL).unwrap();
+		for pending in updates.0.lock().unwrap().values_mut().flat_map(|u| u.pending.values_mut()) {
+			*pending = claimed_at;
+		}
+		assert_eq!(updates.expected_height("chain_b", &client_id, on_chain), on_chain);
+		assert!(!updates.is_claimed("chain_b", &client_id, Height::new(1, 12)));
+		assert!(updates.claim("chain_b", &client_id, Height::new(1, 12)));
+		// confirmed updates don't expire
+		assert!(updates.is_claimed("chain_b", &client_id, Height::new(1, 10)));
+	}
+}
@@ -12,6 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::{ErrorKind, HasErrorKind};
+use jsonrpsee_types::error::{CallError, METHOD_NOT_FOUND_CODE};
 use std::string::FromUtf8Error;
 use thiserror::Error;
 
@@ -27,6 +29,9 @@ pub enum Error {
 	/// Custom error
 	#[error("{0}")]
 	Custom(String),
+	/// The remote signer couldn't be reached or failed to answer, it may succeed when retried
+	#[error("Remote signer unavailable: {0}")]
+	RemoteSignerUnavailable(String),
 	/// Scale codec error
 	#[error("Scale decoding error")]
 	Codec(#[from] codec::Error),
@@ -50,3 +55,89 @@ impl From<String> for Error {
 		Self::Custom(error)
 	}
 }
+
+impl HasErrorKind for Error {
+	fn kind(&self) -> ErrorKind {
+		match self {
+			Error::Subxt(e) => e.kind(),
+			Error::SubxtRRpc(e) => e.kind(),
+			Error::RemoteSignerUnavailable(_) => ErrorKind::Transport,
+			Error::Codec(_) | Error::HexDecode(_) | Error::StringFromUtf8(_) => ErrorKind::Decode,
+			Error::IbcClientError(_) |
+			Error::IbcChannelError(_) |
+			Error::IbcConnectionError(_) |
+			Error::IbcProofError(_) |
+			Error::Custom(_) => ErrorKind::Execution,
+		}
+	}
+}
+
+impl HasErrorKind for subxt::Error {
+	fn kind(&self) -> ErrorKind {
+		match self {
+			subxt::Error::Io(_) => ErrorKind::Transport,
+			subxt::Error::Codec(_) | subxt::Error::Decode(_) => ErrorKind::Decode,
+			subxt::Error::Metadata(_) => ErrorKind::Unsupported,
+			subxt::Error::Rpc(e) => e.kind(),
+			_ => ErrorKind::Execution,
+		}
+	}
+}
+
+impl HasErrorKind for subxt::error::RpcError {
+	fn kind(&self) -> ErrorKind {
+		match self {
+			subxt::error::RpcError::ClientError(e) =>
+				if let Some(e) = e.downcast_ref::<jsonrpsee_core::Error>() {
+					e.kind()
+				} else if let Some(e) = e.downcast_ref::<Error>() {
+					// e.g. a call missing from the recordings of a replayed client
+					e.kind()
+				} else {
+					ErrorKind::Transport
+				},
+			// the subscription was dropped
+			_ => ErrorKind::Transport,
+		}
+	}
+}
+
+/// Code of the errors of substrate nodes for the transactions that their pool deems invalid.
+pub const POOL_INVALID_TX: i32 = 1010;
+
+/// Codes of the errors of the transaction pool of substrate nodes that may go away when the
+/// transaction is submitted again: temporarily banned, already imported, too low priority and
+/// immediately dropped transactions.
+pub const TRANSIENT_POOL_ERRORS: [i32; 4] = [1012, 1013, 1014, 1016];
+
+/// Reason given by substrate nodes for the invalid transactions whose nonce was already used, e.g.
+/// by another transaction of the relayer.
+pub const STALE_TRANSACTION: &str = "Transaction is outdated";
+
+impl HasErrorKind for jsonrpsee_core::Error {
+	fn kind(&self) -> ErrorKind {
+		use jsonrpsee_core::Error as RpcError;
+		match self {
+			RpcError::Transport(_) |
+			RpcError::Internal(_) |
+			RpcError::RestartNeeded(_) |
+			RpcError::RequestTimeout |
+			RpcError::MaxSlotsExceeded => ErrorKind::Transport,
+			RpcError::ParseError(_) | RpcError::InvalidResponse(_) => ErrorKind::Decode,
+			RpcError::MethodNotFound(_) | RpcError::HttpNotImplemented => ErrorKind::Unsupported,
+			RpcError::Call(CallError::Custom(error)) => {
+				let reason =
+					error.data().and_then(|data| serde_json::from_str::<&str>(data.get()).ok());
+				let stale = error.code() == POOL_INVALID_TX && reason == Some(STALE_TRANSACTION);
+				if stale || TRANSIENT_POOL_ERRORS.contains(&error.code()) {
+					ErrorKind::Transport
+				} else if error.code() == METHOD_NOT_FOUND_CODE {
+					ErrorKind::Unsupported
+				} else {
+					ErrorKind::Execution
+				}
+			},
+			_ => ErrorKind::Execution,
+		}
+	}
+}
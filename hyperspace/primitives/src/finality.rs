@@ -0,0 +1,395 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Finality notifications that fail to decode are skipped. An isolated failure is usually a
+//! transient RPC hiccup, but consecutive failures mean the static types no longer match the
+//! chain (e.g. after a runtime upgrade) and that finality is no longer being relayed, which is
+//! escalated through [`FinalityHealth`].
+//!
+//! Subscriptions to finality notifications that fail or end are reopened by
+//! [`resubscribing_stream`], so that an RPC hiccup doesn't end the finality stream of a chain.
+
+use futures::{future::ready, stream, Future, Stream, StreamExt};
+use std::{
+	fmt::{Display, Write},
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, Mutex,
+	},
+	time::Duration,
+};
+
+/// Number of consecutive decode failures after which a finality stream is considered broken.
+pub const FINALITY_DECODE_FAILURE_THRESHOLD: u32 = 5;
+
+/// Maximum number of payload bytes included in the hexdump of an escalation.
+const HEXDUMP_MAX_LEN: usize = 512;
+
+/// Delay before resubscribing to a finality stream whose subscription failed or ended, doubled
+/// for each consecutive failure up to [`RESUBSCRIBE_MAX_DELAY`].
+pub const RESUBSCRIBE_BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Maximum delay between two attempts to resubscribe to a finality stream.
+pub const RESUBSCRIBE_MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// A finality stream whose notifications can't be decoded anymore.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FinalityDecodeFailure {
+	/// Name of the finality stream, e.g. `grandpa` or `beefy`.
+	pub stream: String,
+	/// Number of consecutive notifications that failed to decode.
+	pub consecutive_failures: u32,
+	/// Decoding error of the first failing notification.
+	pub error: String,
+	/// Hexdump of the first failing notification.
+	pub hexdump: String,
+}
+
+/// Health of the finality streams of a chain. Shared between clones, so that the relayer can
+/// report the state of the streams it has handed out.
+#[derive(Debug, Clone, Default)]
+pub struct FinalityHealth(Arc<Mutex<Option<FinalityDecodeFailure>>>);
+
+impl FinalityHealth {
+	/// Returns the escalated decode failure, if any.
+	pub fn decode_failure(&self) -> Option<FinalityDecodeFailure> {
+		self.0.lock().unwrap().clone()
+	}
+
+	fn set(&self, failure: Option<FinalityDecodeFailure>) -> Option<FinalityDecodeFailure> {
+		std::mem::replace(&mut *self.0.lock().unwrap(), failure)
+	}
+}
+
+/// Number of authority sets the client of a chain on its counterparty is behind the chain's
+/// finality, while it's being caught up. Shared between clones like [`FinalityHealth`].
+#[derive(Debug, Clone, Default)]
+pub struct AuthoritySetLag(Arc<AtomicU64>);
+
+impl AuthoritySetLag {
+	pub fn get(&self) -> u64 {
+		self.0.load(Ordering::SeqCst)
+	}
+
+	pub fn set(&self, lag: u64) {
+		self.0.store(lag, Ordering::SeqCst)
+	}
+}
+
+/// Tracks the consecutive decode failures of a single finality stream.
+#[derive(Debug)]
+pub struct DecodeFailureTracker {
+	stream: String,
+	threshold: u32,
+	consecutive_failures: u32,
+	first_failure: Option<(String, Vec<u8>)>,
+	health: FinalityHealth,
+}
+
+impl DecodeFailureTracker {
+	/// Creates a tracker for the stream with the given name, escalating to `health`.
+	pub fn new(stream: impl Into<String>, threshold: u32, health: FinalityHealth) -> Self {
+		Self {
+			stream: stream.into(),
+			threshold,
+			consecutive_failures: 0,
+			first_failure: None,
+			health,
+		}
+	}
+
+	/// Records a successfully decoded notification, clearing an escalated failure.
+	pub fn on_decoded(&mut self) {
+		self.consecutive_failures = 0;
+		self.first_failure = None;
+		if let Some(failure) = self.health.set(None) {
+			log::info!(
+				target: "hyperspace",
+				"Finality stream {} recovered after {} undecodable notifications",
+				self.stream,
+				failure.consecutive_failures
+			);
+		}
+	}
+
+	/// Records a notification that failed to decode. Returns `true` if this failure reached the
+	/// threshold, in which case it has been escalated.
+	pub fn on_decode_error(&mut self, payload: &[u8], error: impl Display) -> bool {
+		self.consecutive_failures += 1;
+		log::error!(
+			target: "hyperspace",
+			"Failed to decode {} finality notification ({} in a row): {error}",
+			self.stream,
+			self.consecutive_failures
+		);
+		let (first_error, first_payload) =
+			self.first_failure.get_or_insert_with(|| (error.to_string(), payload.to_vec()));
+		if self.consecutive_failures != self.threshold {
+			return false
+		}
+
+		let failure = FinalityDecodeFailure {
+			stream: self.stream.clone(),
+			consecutive_failures: self.consecutive_failures,
+			error: first_error.clone(),
+			hexdump: hexdump(first_payload),
+		};
+		log::error!(
+			target: "hyperspace",
+			"Finality decode failure: the last {} {} finality notifications couldn't be decoded, \
+			finality is not being relayed. The static types may be outdated. First error: {}\n\
+			First failing payload ({} bytes):\n{}",
+			failure.consecutive_failures,
+			failure.stream,
+			failure.error,
+			first_payload.len(),
+			failure.hexdump
+		);
+		self.health.set(Some(failure));
+		true
+	}
+}
+
+/// Decodes the payloads of a finality stream, skipping the ones that fail to decode and tracking
+/// them with `tracker`. `on_escalation` is called once the failures reach the threshold, so
+/// that the caller can try to recover (e.g. by refreshing the metadata).
+pub fn decode_finality_stream<S, T, E, F, R>(
+	stream: S,
+	mut tracker: DecodeFailureTracker,
+	mut decode: F,
+	mut on_escalation: R,
+) -> impl Stream<Item = T>
+where
+	S: Stream<Item = Vec<u8>>,
+	E: Display,
+	F: FnMut(&[u8]) -> Result<T, E>,
+	R: FnMut(),
+{
+	stream.filter_map(move |payload| {
+		let item = match decode(&payload) {
+			Ok(item) => {
+				tracker.on_decoded();
+				Some(item)
+			},
+			Err(e) => {
+				if tracker.on_decode_error(&payload, e) {
+					on_escalation();
+				}
+				None
+			},
+		};
+		ready(item)
+	})
+}
+
+/// Yields the payloads of `subscription`, resubscribing with `subscribe` after a backoff whenever
+/// the subscription fails or ends. The notifications sent while the stream wasn't subscribed are
+/// lost, so after resubscribing, the payload `catch_up` returns for the latest finalized block is
+/// yielded first, which covers the finalized range that was skipped. The stream never ends.
+pub fn resubscribing_stream<S, E, F, Fut, C, CFut>(
+	name: impl Into<String>,
+	subscription: S,
+	subscribe: F,
+	catch_up: C,
+) -> impl Stream<Item = Vec<u8>>
+where
+	S: Stream<Item = Result<Vec<u8>, E>> + Unpin,
+	E: Display,
+	F: FnMut() -> Fut,
+	Fut: Future<Output = Result<S, E>>,
+	C: FnMut() -> CFut,
+	CFut: Future<Output = Result<Option<Vec<u8>>, E>>,
+{
+	let resubscriber = Resubscriber {
+		name: name.into(),
+		subscription: Some(subscription),
+		subscribe,
+		catch_up,
+		failures: 0,
+	};
+	stream::unfold(resubscriber, |mut resubscriber| async move {
+		let payload = resubscriber.next().await;
+		Some((payload, resubscriber))
+	})
+}
+
+struct Resubscriber<S, F, C> {
+	name: String,
+	subscription: Option<S>,
+	subscribe: F,
+	catch_up: C,
+	/// Number of times the stream failed since it last yielded a notification.
+	failures: u32,
+}
+
+impl<S, E, F, Fut, C, CFut> Resubscriber<S, F, C>
+where
+	S: Stream<Item = Result<Vec<u8>, E>> + Unpin,
+	E: Display,
+	F: FnMut() -> Fut,
+	Fut: Future<Output = Result<S, E>>,
+	C: FnMut() -> CFut,
+	CFut: Future<Output = Result<Option<Vec<u8>>, E>>,
+{
+	async fn next(&mut self) -> Vec<u8> {
+		loop {
+			let Some(subscription) = self.subscription.as_mut() else {
+				self.failures += 1;
+				if let Some(payload) = self.resubscribe().await {
+					return payload
+				}
+				continue
+			};
+			match subscription.next().await {
+				Some(Ok(payload)) => {
+					self.failures = 0;
+					return payload
+				},
+				Some(Err(e)) =>
+					log::error!(target: "hyperspace", "The {} finality stream failed: {e}", self.name),
+				None =>
+					log::error!(target: "hyperspace", "The {} finality stream ended", self.name),
+			}
+			self.subscription = None;
+		}
+	}
+
+	/// Resubscribes after the backoff of the current failure, returning the catch up payload if
+	/// it succeeded.
+	async fn resubscribe(&mut self) -> Option<Vec<u8>> {
+		let delay = RESUBSCRIBE_BASE_DELAY
+			.saturating_mul(1 << self.failures.saturating_sub(1).min(16))
+			.min(RESUBSCRIBE_MAX_DELAY);
+		log::warn!(target: "hyperspace", "Resubscribing to the {} finality stream in {delay:?}", self.name);
+		tokio::time::sleep(delay).await;
+		match (self.subscribe)().await {
+			Ok(subscription) => self.subscription = Some(subscription),
+			Err(e) => {
+				log::error!(target: "hyperspace", "Failed to resubscribe to the {} finality stream: {e}", self.name);
+				return None
+			},
+		}
+		log::info!(target: "hyperspace", "Resubscribed to the {} finality stream", self.name);
+		match (self.catch_up)().await {
+			Ok(payload) => payload,
+			Err(e) => {
+				// the next notification covers the skipped range as well
+				log::warn!(target: "hyperspace", "Failed to catch up the {} finality stream: {e}", self.name);
+				None
+			},
+		}
+	}
+}
+
+/// Formats the first [`HEXDUMP_MAX_LEN`] bytes of `bytes` as a hexdump, 16 bytes per line.
+fn hexdump(bytes: &[u8]) -> String {
+	let mut dump = String::new();
+	for (line, chunk) in bytes[..bytes.len().min(HEXDUMP_MAX_LEN)].chunks(16).enumerate() {
+		let _ = writeln!(dump, "{:08x}  {}", line * 16, hex::encode(chunk));
+	}
+	if bytes.len() > HEXDUMP_MAX_LEN {
+		let _ = writeln!(dump, "... {} more bytes", bytes.len() - HEXDUMP_MAX_LEN);
+	}
+	dump
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use codec::{Decode, Encode};
+	use futures::{executor::block_on, stream};
+
+	fn decode(payload: &[u8]) -> Result<u32, codec::Error> {
+		u32::decode(&mut &*payload)
+	}
+
+	#[test]
+	fn escalates_at_threshold_and_recovers() {
+		let health = FinalityHealth::default();
+		let escalations = Arc::new(Mutex::new(0));
+		let tracker = DecodeFailureTracker::new("grandpa", 3, health.clone());
+
+		let undecodable = vec![0xffu8];
+		let payloads = vec![
+			1u32.encode(),
+			undecodable.clone(),
+			undecodable.clone(),
+			// isolated failures are skipped
+			2u32.encode(),
+			undecodable.clone(),
+			undecodable.clone(),
+			undecodable.clone(),
+			undecodable,
+		];
+		let escalations_clone = escalations.clone();
+		let decoded = block_on(
+			decode_finality_stream(stream::iter(payloads), tracker, decode, move || {
+				*escalations_clone.lock().unwrap() += 1
+			})
+			.collect::<Vec<_>>(),
+		);
+		assert_eq!(decoded, vec![1, 2]);
+		assert_eq!(*escalations.lock().unwrap(), 1);
+		let failure = health.decode_failure().unwrap();
+		assert_eq!(failure.stream, "grandpa");
+		assert_eq!(failure.consecutive_failures, 3);
+		assert_eq!(failure.hexdump, "00000000  ff\n");
+
+		let tracker = DecodeFailureTracker::new("grandpa", 3, health.clone());
+		let decoded = block_on(
+			decode_finality_stream(stream::iter(vec![3u32.encode()]), tracker, decode, || {})
+				.collect::<Vec<_>>(),
+		);
+		assert_eq!(decoded, vec![3]);
+		assert_eq!(health.decode_failure(), None);
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn finality_stream_survives_resubscriptions() {
+		type Subscription = stream::Iter<std::vec::IntoIter<Result<Vec<u8>, String>>>;
+		let subscription = |payloads: Vec<Result<Vec<u8>, String>>| stream::iter(payloads);
+		// the first attempt to resubscribe is refused, the resubscribed streams end
+		let mut resubscriptions: Vec<Result<Subscription, String>> = vec![
+			Err("connection refused".to_string()),
+			Ok(subscription(vec![Ok(vec![4]), Ok(vec![5])])),
+			Ok(subscription(vec![Ok(vec![7])])),
+		]
+		.into_iter()
+		.rev()
+		.collect();
+		let mut catch_ups = vec![vec![6], vec![3]];
+
+		let start = tokio::time::Instant::now();
+		let payloads = resubscribing_stream(
+			"grandpa",
+			subscription(vec![Ok(vec![1]), Ok(vec![2]), Err("connection reset".to_string())]),
+			move || ready(resubscriptions.pop().unwrap()),
+			move || ready(Ok(catch_ups.pop())),
+		)
+		.take(7)
+		.collect::<Vec<_>>()
+		.await;
+		assert_eq!(payloads, (1..=7).map(|i| vec![i]).collect::<Vec<_>>());
+		// backoffs of 1s and 2s for the first reconnect, then 1s for the second
+		assert_eq!(start.elapsed(), RESUBSCRIBE_BASE_DELAY * 4);
+	}
+
+	#[test]
+	fn hexdump_is_truncated() {
+		let dump = hexdump(&[0xab; HEXDUMP_MAX_LEN + 4]);
+		assert_eq!(dump.lines().count(), HEXDUMP_MAX_LEN / 16 + 1);
+		assert!(dump.starts_with("00000000  abababababababababababababababab\n00000010"));
+		assert!(dump.ends_with("... 4 more bytes\n"));
+	}
+}
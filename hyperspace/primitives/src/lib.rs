@@ -17,14 +17,17 @@
 use futures::Stream;
 use ibc_proto::{
 	google::protobuf::Any,
-	ibc::core::{
-		channel::v1::{
-			QueryChannelResponse, QueryNextSequenceReceiveResponse,
-			QueryPacketAcknowledgementResponse, QueryPacketCommitmentResponse,
-			QueryPacketReceiptResponse,
+	ibc::{
+		applications::{fee::v1::Fee, transfer::v1::DenomTrace},
+		core::{
+			channel::v1::{
+				QueryChannelResponse, QueryNextSequenceReceiveResponse,
+				QueryPacketAcknowledgementResponse, QueryPacketCommitmentResponse,
+				QueryPacketReceiptResponse,
+			},
+			client::v1::{QueryClientStateResponse, QueryConsensusStateResponse},
+			connection::v1::QueryConnectionResponse,
 		},
-		client::v1::{QueryClientStateResponse, QueryConsensusStateResponse},
-		connection::v1::QueryConnectionResponse,
 	},
 };
 use rand::Rng;
@@ -39,7 +42,18 @@ use std::{
 };
 use tokio::{sync::Mutex as AsyncMutex, task::JoinSet, time::sleep};
 
-use crate::error::Error;
+use crate::{
+	client_updates::SubmittedClientUpdates,
+	error::Error,
+	finality::{AuthoritySetLag, FinalityHealth},
+	misbehaviour::{DetectedMisbehaviour, MisbehaviourCheckRecorder},
+	packet_filter::{ChannelPacketFilter, SharedPacketFilter},
+	query_cache::{QueryCache, DEFAULT_QUERY_CACHE_CAPACITY},
+	relay_stats::RelayStats,
+	relayer_store::RelayerStore,
+	signer::{KeyRotation, KeySource},
+	undelivered::UndeliveredInfo,
+};
 #[cfg(any(feature = "testing", test))]
 use ibc::applications::transfer::msgs::transfer::MsgTransfer;
 use ibc::{
@@ -51,9 +65,10 @@ use ibc::{
 			events::UpdateClient,
 		},
 		ics04_channel::{
-			channel::{ChannelEnd, Order},
+			channel::{ChannelEnd, IdentifiedChannelEnd, Order},
 			context::calculate_block_delay,
 			packet::Packet,
+			Version,
 		},
 		ics23_commitment::commitment::CommitmentPrefix,
 		ics24_host::identifier::{ChannelId, ClientId, ConnectionId, PortId},
@@ -69,8 +84,21 @@ use ibc_proto::ibc::core::{
 use ibc_rpc::PacketInfo;
 use pallet_ibc::light_clients::{AnyClientMessage, AnyClientState, AnyConsensusState};
 
+pub mod acknowledgement;
+pub mod any_registry;
+pub mod client_updates;
 pub mod error;
+pub mod finality;
+pub mod middleware;
+pub mod misbehaviour;
 pub mod mock;
+pub mod packet_filter;
+pub mod query_cache;
+pub mod relay_stats;
+pub mod relayer_store;
+pub mod replay;
+pub mod signer;
+pub mod undelivered;
 pub mod utils;
 
 pub enum UpdateMessage {
@@ -95,6 +123,12 @@ impl UpdateType {
 	}
 }
 
+/// Resolves the channel version the counterparty app opened with in `ChanOpenTry` into the
+/// version that should be sent back in `ChanOpenAck`. The acknowledged version is verified
+/// against the counterparty channel end, so resolvers either accept the version or return an
+/// error, which aborts the handshake.
+pub type ChannelVersionResolver = fn(proposed: &str) -> Result<String, anyhow::Error>;
+
 fn default_skip_optional_client_updates() -> bool {
 	true
 }
@@ -103,6 +137,309 @@ fn max_packets_to_process() -> u32 {
 	50
 }
 
+fn default_max_submit_retries() -> u32 {
+	5
+}
+
+fn default_query_cache_capacity() -> usize {
+	DEFAULT_QUERY_CACHE_CAPACITY
+}
+
+fn default_stall_factor() -> u32 {
+	20
+}
+
+fn default_max_pending_messages() -> usize {
+	1000
+}
+
+/// Tells the relayer whether a failed [`Chain::submit`] may be attempted again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryPolicy {
+	/// The error is transient (e.g. a dropped connection or a nonce race), the same messages can
+	/// be submitted again after a backoff.
+	Retry,
+	/// The error is deterministic (e.g. a decoding error or an execution failure), submitting the
+	/// same messages again would fail the same way.
+	Fail,
+}
+
+/// Category of the errors returned by the chains, telling the relayer how to react to them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+	/// The node couldn't be reached or rejected the request for a transient reason (e.g. a
+	/// dropped connection or a nonce race), the same request may succeed later.
+	Transport,
+	/// The request reached the chain and failed deterministically, e.g. a failed transaction.
+	Execution,
+	/// A value returned by the chain couldn't be decoded.
+	Decode,
+	/// The requested value doesn't exist on the chain.
+	NotFound,
+	/// The chain doesn't support the request, e.g. because of an outdated runtime metadata.
+	Unsupported,
+}
+
+impl ErrorKind {
+	/// Only transport errors are worth retrying.
+	pub fn retry_policy(self) -> RetryPolicy {
+		match self {
+			ErrorKind::Transport => RetryPolicy::Retry,
+			_ => RetryPolicy::Fail,
+		}
+	}
+}
+
+/// Errors that can be classified into an [`ErrorKind`], implemented by the error of every
+/// [`IbcProvider`]. Errors are classified by their type, so the errors of the nodes have to be kept
+/// rather than turned into messages where they're returned.
+pub trait HasErrorKind {
+	fn kind(&self) -> ErrorKind;
+}
+
+/// Outcome of a [`Chain::simulate`] call.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SimulationResult {
+	/// Estimated weight (or gas) needed to execute the whole batch.
+	pub estimated_weight: u64,
+	/// Whether each message of the batch, in submission order, executed successfully.
+	pub message_results: Vec<bool>,
+}
+
+impl SimulationResult {
+	/// Returns `true` if every message of the batch executed successfully.
+	pub fn is_success(&self) -> bool {
+		self.message_results.iter().all(|ok| *ok)
+	}
+}
+
+/// Outcome of a [`Chain::upload_wasm`] call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WasmUploadStatus {
+	/// The code was stored by this call.
+	Uploaded,
+	/// Code with the same checksum was already stored, nothing was submitted.
+	AlreadyStored,
+}
+
+/// Status of a light client, as returned by [`IbcProvider::query_client_status`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientStatus {
+	/// The client can be updated and used to verify proofs.
+	Active,
+	/// The trusting period elapsed since the latest consensus state, the client can't be
+	/// updated anymore.
+	Expired,
+	/// Misbehaviour was submitted for the client.
+	Frozen,
+}
+
+impl ClientStatus {
+	/// Computes the status of `client_state`, whose latest consensus state was created at
+	/// `consensus_timestamp`, at the time `now`.
+	pub fn from_client_state(
+		client_state: &AnyClientState,
+		consensus_timestamp: Timestamp,
+		now: Timestamp,
+	) -> Self {
+		if client_state.frozen_height().is_some() {
+			return ClientStatus::Frozen
+		}
+		let elapsed = now.duration_since(&consensus_timestamp).unwrap_or_default();
+		if client_state.expired(elapsed) {
+			ClientStatus::Expired
+		} else {
+			ClientStatus::Active
+		}
+	}
+
+	pub fn is_active(&self) -> bool {
+		matches!(self, ClientStatus::Active)
+	}
+}
+
+impl std::fmt::Display for ClientStatus {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ClientStatus::Active => write!(f, "Active"),
+			ClientStatus::Expired => write!(f, "Expired"),
+			ClientStatus::Frozen => write!(f, "Frozen"),
+		}
+	}
+}
+
+impl FromStr for ClientStatus {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"Active" => Ok(ClientStatus::Active),
+			"Expired" => Ok(ClientStatus::Expired),
+			"Frozen" => Ok(ClientStatus::Frozen),
+			s => Err(Error::Custom(format!("Unknown client status: {s}"))),
+		}
+	}
+}
+
+/// A client on this chain considered by [`IbcProvider::find_suitable_client`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientCandidate {
+	pub client_id: ClientId,
+	/// Type of the client, unwrapped from 08-wasm
+	pub client_type: ClientType,
+	pub status: ClientStatus,
+	pub latest_height: Height,
+}
+
+/// Picks the [`ClientStatus::Active`] client of type `client_type` with the highest latest height.
+/// Clients at the same height are ordered by id, so that the choice doesn't depend on the order
+/// in which the clients are listed.
+pub fn select_suitable_client(
+	candidates: impl IntoIterator<Item = ClientCandidate>,
+	client_type: &str,
+) -> Option<ClientId> {
+	candidates
+		.into_iter()
+		.filter(|candidate| candidate.client_type == client_type && candidate.status.is_active())
+		.max_by(|a, b| {
+			a.latest_height
+				.cmp(&b.latest_height)
+				.then_with(|| a.client_id.as_str().cmp(b.client_id.as_str()))
+		})
+		.map(|candidate| candidate.client_id)
+}
+
+/// A connection relayed in addition to the primary `connection_id` of a chain config, e.g. a
+/// connection with a delay for high-value transfers next to one without delay.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConnectionConfig {
+	pub connection_id: ConnectionId,
+	/// Channels of the connection cleared for packet relay, added to the channel whitelist
+	#[serde(default)]
+	pub channel_whitelist: Vec<(ChannelId, PortId)>,
+	/// Commitment prefix of the store holding the connection, if it isn't the store of the
+	/// chain's own prefix, e.g. a contract-based store next to pallet-ibc
+	#[serde(default)]
+	pub commitment_prefix: Option<String>,
+}
+
+/// Returns the commitment prefixes of the `connections` configured with their own prefix.
+pub fn connection_prefixes(
+	connections: &[ConnectionConfig],
+) -> Result<HashMap<ConnectionId, CommitmentPrefix>, Error> {
+	connections
+		.iter()
+		.filter_map(|connection| {
+			let prefix = connection.commitment_prefix.as_ref()?;
+			let prefix = CommitmentPrefix::try_from(prefix.as_bytes().to_vec()).map_err(|e| {
+				Error::Custom(format!(
+					"Invalid commitment prefix {prefix:?} of {}: {e}",
+					connection.connection_id
+				))
+			});
+			Some(prefix.map(|prefix| (connection.connection_id.clone(), prefix)))
+		})
+		.collect()
+}
+
+/// Returns the ids of the connections relayed on a chain, the primary `connection_id` first,
+/// without duplicates.
+pub fn relayed_connection_ids(
+	connection_id: Option<&ConnectionId>,
+	connections: &[ConnectionConfig],
+) -> Vec<ConnectionId> {
+	let mut connection_ids = vec![];
+	for connection_id in
+		connection_id.into_iter().chain(connections.iter().map(|c| &c.connection_id))
+	{
+		if !connection_ids.contains(connection_id) {
+			connection_ids.push(connection_id.clone());
+		}
+	}
+	connection_ids
+}
+
+/// Makes `connection_id` the primary connection of `connection_ids`, replacing the previous
+/// primary connection.
+pub fn set_primary_connection_id(
+	connection_ids: &mut Vec<ConnectionId>,
+	connection_id: ConnectionId,
+) {
+	connection_ids.retain(|id| id != &connection_id);
+	match connection_ids.first_mut() {
+		Some(primary) => *primary = connection_id,
+		None => connection_ids.push(connection_id),
+	}
+}
+
+/// Channels cleared for packet relay, either on any relayed connection or only on the connection
+/// whose config lists them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChannelWhitelist {
+	/// Channels of the chain's own whitelist, relayed on any connection
+	channels: HashSet<(ChannelId, PortId)>,
+	/// Channels of the per-connection whitelists
+	by_connection: HashMap<ConnectionId, HashSet<(ChannelId, PortId)>>,
+}
+
+impl ChannelWhitelist {
+	/// Builds the whitelist of a chain config from its own whitelist and those of its
+	/// `connections`.
+	pub fn new(channels: Vec<(ChannelId, PortId)>, connections: &[ConnectionConfig]) -> Self {
+		let mut whitelist =
+			Self { channels: channels.into_iter().collect(), by_connection: HashMap::new() };
+		for connection in connections {
+			for channel in &connection.channel_whitelist {
+				whitelist.insert_on(connection.connection_id.clone(), channel.clone());
+			}
+		}
+		whitelist
+	}
+
+	/// Returns the channels whitelisted on any connection.
+	pub fn all(&self) -> HashSet<(ChannelId, PortId)> {
+		self.channels
+			.iter()
+			.chain(self.by_connection.values().flatten())
+			.cloned()
+			.collect()
+	}
+
+	/// Whether `channel`, found on `connection_id`, is cleared for relay. A channel listed by
+	/// the whitelist of another connection isn't.
+	pub fn allows(&self, channel: &(ChannelId, PortId), connection_id: &ConnectionId) -> bool {
+		self.channels.contains(channel) ||
+			self.by_connection
+				.get(connection_id)
+				.map(|channels| channels.contains(channel))
+				.unwrap_or_default()
+	}
+
+	/// Whitelists `channel` on any connection.
+	pub fn insert(&mut self, channel: (ChannelId, PortId)) {
+		self.channels.insert(channel);
+	}
+
+	/// Whitelists `channel` on `connection_id` only.
+	pub fn insert_on(&mut self, connection_id: ConnectionId, channel: (ChannelId, PortId)) {
+		self.by_connection.entry(connection_id).or_default().insert(channel);
+	}
+
+	/// Replaces the whitelisted channels by `channels`. The channels that stay whitelisted keep
+	/// their connection.
+	pub fn set(&mut self, channels: HashSet<(ChannelId, PortId)>) {
+		for connection_channels in self.by_connection.values_mut() {
+			connection_channels.retain(|channel| channels.contains(channel));
+		}
+		self.by_connection
+			.retain(|_, connection_channels| !connection_channels.is_empty());
+		self.channels = channels
+			.into_iter()
+			.filter(|channel| !self.by_connection.values().any(|c| c.contains(channel)))
+			.collect();
+	}
+}
+
 // TODO: move other fields like `client_id`, `connection_id`, etc. here
 /// Common relayer parameters
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -112,6 +449,88 @@ pub struct CommonClientConfig {
 	pub skip_optional_client_updates: bool,
 	#[serde(default = "max_packets_to_process")]
 	pub max_packets_to_process: u32,
+	/// Maximum number of times a batch is resubmitted after a retryable error
+	#[serde(default = "default_max_submit_retries")]
+	pub max_submit_retries: u32,
+	/// Refuse to whitelist or relay channels with an unsupported middleware stack
+	#[serde(default)]
+	pub strict_middleware_check: bool,
+	/// Simulate outgoing batches before submitting them and drop the messages that fail
+	#[serde(default)]
+	pub simulate_messages: bool,
+	/// Drop the messages to this chain that can't be wrapped for its 08-wasm clients, with a
+	/// warning, instead of failing their whole batch
+	#[serde(default)]
+	pub skip_invalid_wasm_messages: bool,
+	/// Stop relaying packets while either client of the path is expired or frozen
+	#[serde(default)]
+	pub stop_packets_on_inactive_client: bool,
+	/// Rules deciding which packets of a channel are relayed
+	#[serde(default)]
+	pub packet_filters: Vec<ChannelPacketFilter>,
+	/// Number of client state and consensus state query responses cached, 0 disables the cache
+	#[serde(default = "default_query_cache_capacity")]
+	pub query_cache_capacity: usize,
+	/// Number of blocks of this chain before their timeout during which received packets aren't
+	/// relayed anymore, but timed out once their timeout elapses. Disabled by default, 2 blocks
+	/// is enough to avoid most receipts failing on a timeout
+	#[serde(default)]
+	pub timeout_safety_margin_blocks: u32,
+	/// Whitelist the channels opened on the relayed connection while the relayer is running
+	#[serde(default)]
+	pub auto_whitelist_channels: bool,
+	/// Address of this chain credited with the ICS-29 fees of the packets relayed to the
+	/// counterparty
+	#[serde(default)]
+	pub fee_payee_address: Option<String>,
+	/// Minimum balance of the relayer's account in the fee token, below which the chain is
+	/// reported unhealthy
+	#[serde(default)]
+	pub min_balance: Option<u64>,
+	/// Number of expected block times without a finality notification or a new height after
+	/// which the chain is reported stalled, 0 disables the check
+	#[serde(default = "default_stall_factor")]
+	pub stall_factor: u32,
+	/// Shell command run when the chain stalls or resumes, with the name of the chain in
+	/// `HYPERSPACE_CHAIN` and its status in `HYPERSPACE_CHAIN_STATUS`
+	#[serde(default)]
+	pub stall_alert_command: Option<String>,
+	/// Maximum number of packets whose proofs are queried at the same time, unbounded if unset
+	#[serde(default)]
+	pub proof_query_concurrency: Option<usize>,
+	/// Number of messages to this chain that may wait to be submitted before the relayer stops
+	/// assembling packet messages for it
+	#[serde(default = "default_max_pending_messages")]
+	pub max_pending_messages: usize,
+	/// Verify the client updates of this chain against its client on the counterparty before
+	/// submitting them, and drop those that fail
+	#[serde(default)]
+	pub pre_validate_updates: bool,
+}
+
+impl Default for CommonClientConfig {
+	fn default() -> Self {
+		Self {
+			skip_optional_client_updates: default_skip_optional_client_updates(),
+			max_packets_to_process: max_packets_to_process(),
+			max_submit_retries: default_max_submit_retries(),
+			strict_middleware_check: false,
+			simulate_messages: false,
+			skip_invalid_wasm_messages: false,
+			stop_packets_on_inactive_client: false,
+			packet_filters: vec![],
+			query_cache_capacity: default_query_cache_capacity(),
+			timeout_safety_margin_blocks: 0,
+			auto_whitelist_channels: false,
+			fee_payee_address: None,
+			min_balance: None,
+			stall_factor: default_stall_factor(),
+			stall_alert_command: None,
+			proof_query_concurrency: None,
+			max_pending_messages: default_max_pending_messages(),
+			pre_validate_updates: false,
+		}
+	}
 }
 
 /// A common data that all clients should keep.
@@ -133,6 +552,79 @@ pub struct CommonClientState {
 	pub misbehaviour_client_msg_queue: Arc<AsyncMutex<Vec<AnyClientMessage>>>,
 	pub max_packets_to_process: usize,
 	pub skip_tokens_list: Vec<String>,
+	/// Maximum number of times a batch is resubmitted after a retryable error
+	pub max_submit_retries: u32,
+	/// Refuse to whitelist or relay channels with an unsupported middleware stack. Otherwise,
+	/// such channels are relayed with a warning.
+	pub strict_middleware_check: bool,
+	/// Channels with an unsupported middleware stack already reported, so that they are only
+	/// warned about once.
+	pub unsupported_middleware_channels: Arc<Mutex<HashSet<(ChannelId, PortId)>>>,
+	/// Simulate outgoing batches before submitting them, so that messages failing on the sink
+	/// are dropped instead of failing the whole batch.
+	pub simulate_messages: bool,
+	/// Drop the messages of a batch to the 08-wasm clients of this chain that fail to be wrapped,
+	/// instead of failing the batch.
+	pub skip_invalid_wasm_messages: bool,
+	/// Stop relaying packets while either client of the path isn't [`ClientStatus::Active`].
+	/// Client updates are still submitted.
+	pub stop_packets_on_inactive_client: bool,
+	/// Health of the finality streams returned by [`Chain::finality_notifications`].
+	pub finality_health: FinalityHealth,
+	/// Authority sets the client of this chain on the counterparty is behind, see
+	/// [`AuthoritySetLag`].
+	pub authority_set_lag: AuthoritySetLag,
+	/// Items of the runtime api the client was generated from that don't match the live runtime,
+	/// found when the client was created. The client doesn't submit transactions while any does.
+	pub metadata_mismatches: Vec<String>,
+	/// Statistics of [`MisbehaviourHandler::check_for_misbehaviour`].
+	pub misbehaviour_checks: MisbehaviourCheckRecorder,
+	/// Callbacks negotiating the version of the channels opened by this chain, by connection and
+	/// port, set for the duration of their handshake. Shared between clones, so they can be set
+	/// after the relayer task has been spawned.
+	pub channel_version_resolvers:
+		Arc<Mutex<HashMap<(ConnectionId, PortId), ChannelVersionResolver>>>,
+	/// Filters applied to the packets sent on the source chain before they are relayed. Shared
+	/// between clones, so it can be reloaded while the relayer is running.
+	pub packet_filter: SharedPacketFilter,
+	/// Responses of the client state and consensus state queries at past heights.
+	pub query_cache: QueryCache,
+	/// Packets timing out on this chain within this many blocks, estimated with
+	/// [`IbcProvider::expected_block_time`], aren't received anymore. They are timed out on the
+	/// source once their timeout elapses instead.
+	pub timeout_safety_margin_blocks: u32,
+	/// Add the channels whose handshake completes on the relayed connection to the whitelists of
+	/// both chains, and remove them once they are closed.
+	pub auto_whitelist_channels: bool,
+	/// Packet bookkeeping persisted across restarts, if configured.
+	pub relayer_store: Option<Arc<dyn RelayerStore>>,
+	/// Address of this chain credited with the ICS-29 receive fees of the packets relayed to the
+	/// counterparty. Registered as the counterparty payee of the relayer's account of the
+	/// counterparty on its fee-enabled channels at startup.
+	pub fee_payee_address: Option<String>,
+	/// Balance of the relayer's account returned by [`IbcProvider::query_relayer_balance`]
+	/// below which the chain is reported unhealthy, if any.
+	pub min_balance: Option<u128>,
+	/// Number of [`IbcProvider::expected_block_time`]s without a finality notification or a new
+	/// height after which the chain is reported stalled, 0 disables the check.
+	pub stall_factor: u32,
+	/// Shell command run when the chain stalls or resumes, if any.
+	pub stall_alert_command: Option<String>,
+	/// Maximum number of packets of a batch whose proofs are queried from this chain at the same
+	/// time, all of them if unset.
+	pub proof_query_concurrency: Option<usize>,
+	/// Number of assembled messages to this chain that may wait to be submitted before the relay
+	/// loop applies backpressure.
+	pub max_pending_messages: usize,
+	/// Verify the client updates of this chain with [`Chain::verify_client_update`] before
+	/// submitting them. An update that fails is dropped, and constructed again from the next
+	/// finality event.
+	pub pre_validate_updates: bool,
+	/// Client updates submitted to the counterparty, shared with it by the relay tasks of the
+	/// path.
+	pub submitted_updates: SubmittedClientUpdates,
+	/// Statistics of the messages submitted to this chain, served by the relayer's query server
+	pub relay_stats: RelayStats,
 }
 
 impl Default for CommonClientState {
@@ -146,6 +638,31 @@ impl Default for CommonClientState {
 			misbehaviour_client_msg_queue: Arc::new(Default::default()),
 			max_packets_to_process: 100,
 			skip_tokens_list: Default::default(),
+			max_submit_retries: default_max_submit_retries(),
+			strict_middleware_check: false,
+			unsupported_middleware_channels: Default::default(),
+			simulate_messages: false,
+			skip_invalid_wasm_messages: false,
+			stop_packets_on_inactive_client: false,
+			finality_health: Default::default(),
+			authority_set_lag: Default::default(),
+			metadata_mismatches: vec![],
+			misbehaviour_checks: Default::default(),
+			channel_version_resolvers: Default::default(),
+			packet_filter: Default::default(),
+			query_cache: Default::default(),
+			timeout_safety_margin_blocks: 0,
+			auto_whitelist_channels: false,
+			relayer_store: None,
+			fee_payee_address: None,
+			min_balance: None,
+			stall_factor: default_stall_factor(),
+			stall_alert_command: None,
+			proof_query_concurrency: None,
+			max_pending_messages: default_max_pending_messages(),
+			pre_validate_updates: false,
+			submitted_updates: Default::default(),
+			relay_stats: Default::default(),
 		}
 	}
 }
@@ -177,6 +694,80 @@ impl CommonClientState {
 	pub fn set_rpc_call_delay(&mut self, delay: Duration) {
 		self.rpc_call_delay = delay;
 	}
+
+	/// Sets the resolver of the channels opened on `connection_id` and `port_id`, or removes it
+	/// once their handshake is over.
+	pub fn set_channel_version_resolver(
+		&self,
+		connection_id: ConnectionId,
+		port_id: PortId,
+		resolver: Option<ChannelVersionResolver>,
+	) {
+		let mut resolvers = self.channel_version_resolvers.lock().unwrap();
+		match resolver {
+			Some(resolver) => resolvers.insert((connection_id, port_id), resolver),
+			None => resolvers.remove(&(connection_id, port_id)),
+		};
+	}
+
+	/// Returns the version that should be acknowledged for the `proposed` counterparty version of
+	/// a channel opened on `connection_id` and `port_id`. Without a resolver the proposed version
+	/// is accepted as is.
+	pub fn resolve_channel_version(
+		&self,
+		connection_id: &ConnectionId,
+		port_id: &PortId,
+		proposed: &Version,
+	) -> Result<Version, Error> {
+		let resolver = self
+			.channel_version_resolvers
+			.lock()
+			.unwrap()
+			.get(&(connection_id.clone(), port_id.clone()))
+			.copied();
+		match resolver {
+			Some(resolver) => resolver(&proposed.to_string()).map(Version::new).map_err(|e| {
+				Error::Custom(format!("Failed to resolve channel version {proposed}: {e}"))
+			}),
+			None => Ok(proposed.clone()),
+		}
+	}
+
+	/// Checks the middleware stack of a channel's version against
+	/// [`middleware::SUPPORTED_MIDDLEWARE_STACKS`]. Channels with an unsupported stack are only
+	/// relayed when `strict_middleware_check` is disabled.
+	pub fn is_channel_relayable(
+		&self,
+		channel_id: ChannelId,
+		port_id: &PortId,
+		version: &Version,
+	) -> bool {
+		let unsupported = middleware::unsupported_middleware(&version.to_string());
+		if unsupported.is_empty() {
+			return true
+		}
+		let first_seen = self
+			.unsupported_middleware_channels
+			.lock()
+			.unwrap()
+			.insert((channel_id, port_id.clone()));
+		let level = if first_seen { log::Level::Warn } else { log::Level::Debug };
+		if self.strict_middleware_check {
+			log::log!(
+				target: "hyperspace",
+				level,
+				"Refusing to relay channel {channel_id}/{port_id}: unsupported middleware {unsupported:?} in version {version}"
+			);
+			false
+		} else {
+			log::log!(
+				target: "hyperspace",
+				level,
+				"Relaying channel {channel_id}/{port_id} with unknown_middleware_stack={unsupported:?} in version {version}"
+			);
+			true
+		}
+	}
 }
 
 pub fn apply_prefix(mut commitment_prefix: Vec<u8>, path: impl Into<Vec<u8>>) -> Vec<u8> {
@@ -189,11 +780,14 @@ pub fn apply_prefix(mut commitment_prefix: Vec<u8>, path: impl Into<Vec<u8>>) ->
 /// - acknowledgement packet (`Acks`),
 /// - receive packet (`Recvs`)
 /// - timeout packet (`Timeouts`)
+/// - receive packet on a connection with a delay, whose client update has to be sent now for the
+///   delay to pass before the packet times out (`DelayedRecvs`)
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum UndeliveredType {
 	Acks,
 	Recvs,
 	Timeouts,
+	DelayedRecvs,
 }
 
 /// Provides an interface for accessing new events and Ibc data on the chain which must be
@@ -207,8 +801,8 @@ pub trait IbcProvider {
 	/// Asset Id
 	type AssetId: Clone;
 
-	/// Error type, just needs to implement standard error trait.
-	type Error: std::error::Error + From<String> + Send + Sync + 'static;
+	/// Error type, classified into an [`ErrorKind`] so the relayer knows how to react to it.
+	type Error: std::error::Error + HasErrorKind + Send + Sync + 'static;
 
 	/// Query the latest ibc events finalized by the recent finality event. Use the counterparty
 	/// [`Chain`] to query the on-chain [`ClientState`] so you can scan for new events in between
@@ -240,6 +834,13 @@ pub trait IbcProvider {
 		client_id: ClientId,
 	) -> Result<QueryClientStateResponse, Self::Error>;
 
+	/// Query the status of the client with the given id
+	async fn query_client_status(
+		&self,
+		at: Height,
+		client_id: ClientId,
+	) -> Result<ClientStatus, Self::Error>;
+
 	/// Query connection end with proof
 	async fn query_connection_end(
 		&self,
@@ -255,8 +856,13 @@ pub trait IbcProvider {
 		port_id: PortId,
 	) -> Result<QueryChannelResponse, Self::Error>;
 
-	/// Query proof for provided key path
-	async fn query_proof(&self, at: Height, keys: Vec<Vec<u8>>) -> Result<Vec<u8>, Self::Error>;
+	/// Query proof for provided key path, in the store of the commitment `prefix`
+	async fn query_proof(
+		&self,
+		at: Height,
+		prefix: &CommitmentPrefix,
+		keys: Vec<Vec<u8>>,
+	) -> Result<Vec<u8>, Self::Error>;
 
 	/// Query packet commitment with proof
 	async fn query_packet_commitment(
@@ -338,9 +944,16 @@ pub trait IbcProvider {
 		seqs: Vec<u64>,
 	) -> Result<Vec<u64>, Self::Error>;
 
-	/// Channel whitelist
+	/// Channel whitelist, the channels of every relayed connection
 	fn channel_whitelist(&self) -> HashSet<(ChannelId, PortId)>;
 
+	/// Whether `channel` is cleared for relay on `connection_id`, the connection it was found on.
+	fn is_channel_whitelisted_on(
+		&self,
+		channel: &(ChannelId, PortId),
+		connection_id: &ConnectionId,
+	) -> bool;
+
 	/// Query all channels for a connection
 	async fn query_connection_channels(
 		&self,
@@ -378,6 +991,17 @@ pub trait IbcProvider {
 		client_height: Height,
 	) -> Result<(Height, Timestamp), Self::Error>;
 
+	/// Height of the latest consensus state of the client on this chain at or below `target`.
+	/// `None` if there is none or if the chain can't list the consensus states of its clients,
+	/// which are then searched one height at a time.
+	async fn closest_consensus_height(
+		&self,
+		_client_id: ClientId,
+		_target: Height,
+	) -> Result<Option<Height>, Self::Error> {
+		Ok(None)
+	}
+
 	/// Return a proof for the host consensus state at the given height to be included in the
 	/// consensus state proof.
 	async fn query_host_consensus_state_proof(
@@ -391,27 +1015,51 @@ pub trait IbcProvider {
 		asset_id: Self::AssetId,
 	) -> Result<Vec<PrefixedCoin>, Self::Error>;
 
+	/// Returns the balance of the relayer's account in the token paying the transaction fees.
+	async fn query_relayer_balance(&self) -> Result<u128, Self::Error>;
+
+	/// Returns the trace of the ibc denom with the given hash, which may be prefixed with `ibc/`.
+	async fn query_denom_trace(&self, hash: String) -> Result<DenomTrace, Self::Error>;
+
 	/// Return the chain connection prefix
 	fn connection_prefix(&self) -> CommitmentPrefix;
 
+	/// Return the commitment prefix of the store holding `connection_id` and its channels and
+	/// packets, [`IbcProvider::connection_prefix`] unless the connection has its own.
+	fn connection_prefix_of(&self, connection_id: &ConnectionId) -> CommitmentPrefix;
+
 	/// Return the host chain's light client id on counterparty chain
 	fn client_id(&self) -> ClientId;
 
 	/// Set the client id for the relayer task.
 	fn set_client_id(&mut self, client_id: ClientId);
 
-	/// Return the connection id on this chain
+	/// Return the primary connection id on this chain
 	fn connection_id(&self) -> Option<ConnectionId>;
 
+	/// Return the ids of all the connections relayed on this chain, the primary
+	/// [`IbcProvider::connection_id`] first.
+	fn connection_ids(&self) -> Vec<ConnectionId>;
+
 	/// Set the channel whitelist for the relayer task.
 	fn set_channel_whitelist(&mut self, channel_whitelist: HashSet<(ChannelId, PortId)>);
 
 	/// Set the channel whitelist for the relayer task.
 	fn add_channel_to_whitelist(&mut self, channel: (ChannelId, PortId));
 
-	/// Set the connection id for the relayer task.
+	/// Add a channel to the whitelist of the relayed connection `connection_id` only.
+	fn add_channel_to_connection_whitelist(
+		&mut self,
+		connection_id: ConnectionId,
+		channel: (ChannelId, PortId),
+	);
+
+	/// Set the primary connection id for the relayer task.
 	fn set_connection_id(&mut self, connection_id: ConnectionId);
 
+	/// Add a connection relayed by the relayer task, after the primary connection.
+	fn add_connection_id(&mut self, connection_id: ConnectionId);
+
 	/// Returns the client type of this chain.
 	fn client_type(&self) -> ClientType;
 
@@ -421,8 +1069,19 @@ pub trait IbcProvider {
 	/// Should return a list of all clients on the chain
 	async fn query_clients(&self) -> Result<Vec<ClientId>, Self::Error>;
 
-	/// Should return a list of all clients on the chain
-	async fn query_channels(&self) -> Result<Vec<(ChannelId, PortId)>, Self::Error>;
+	/// Should return all the channels of the chain with their ends, in as few queries as the
+	/// chain allows.
+	async fn query_identified_channels(&self) -> Result<Vec<IdentifiedChannelEnd>, Self::Error>;
+
+	/// Returns the ids of all the channels of the chain.
+	async fn query_channels(&self) -> Result<Vec<(ChannelId, PortId)>, Self::Error> {
+		Ok(self
+			.query_identified_channels()
+			.await?
+			.into_iter()
+			.map(|channel| (channel.channel_id, channel.port_id))
+			.collect())
+	}
 
 	/// Query all connection states for associated client
 	async fn query_connection_using_client(
@@ -462,7 +1121,119 @@ pub trait IbcProvider {
 		tx_id: Self::TransactionId,
 	) -> Result<(ChannelId, PortId), Self::Error>;
 
-	async fn upload_wasm(&self, wasm: Vec<u8>) -> Result<Vec<u8>, Self::Error>;
+	/// Stores the wasm light client code on chain, unless code with the same checksum is already
+	/// stored. Returns the checksum, used as the code id by the 08-wasm module, either way.
+	async fn upload_wasm(&self, wasm: Vec<u8>) -> Result<(Vec<u8>, WasmUploadStatus), Self::Error>;
+
+	/// Returns `true` if the 08-wasm module of this chain stores code with the given checksum.
+	async fn query_wasm_code_stored(&self, code_id: &[u8]) -> Result<bool, Self::Error>;
+
+	/// Migrates the 08-wasm client `client_id` of this chain to the stored code with the checksum
+	/// `code_id`, passing `migrate_msg` to the migrate entry point of the new code. Returns once
+	/// the client uses the new code.
+	async fn migrate_wasm_client(
+		&mut self,
+		client_id: ClientId,
+		code_id: Vec<u8>,
+		migrate_msg: Vec<u8>,
+	) -> Result<(), Self::Error>;
+
+	/// Should return a `MsgRegisterCounterpartyPayee` registering `counterparty_payee` as the
+	/// address of the counterparty credited with the ICS-29 receive fees of the packets the
+	/// relayer's account of this chain receives on `channel_id`/`port_id`.
+	fn register_counterparty_payee_msg(
+		&self,
+		channel_id: ChannelId,
+		port_id: PortId,
+		counterparty_payee: String,
+	) -> Result<Any, Self::Error>;
+
+	/// Returns the sequences of the packets sent on `channel_id`/`port_id` at `at` that the
+	/// `counterparty` hasn't received at its latest height, classified by the message delivering
+	/// them given the state of both channel ends.
+	async fn undelivered_sequences<C>(
+		&self,
+		at: Height,
+		counterparty: &C,
+		channel_id: ChannelId,
+		port_id: PortId,
+	) -> Result<UndeliveredInfo, anyhow::Error>
+	where
+		Self: Sized,
+		C: IbcProvider + Sync,
+	{
+		let (counterparty_height, counterparty_timestamp) =
+			counterparty.latest_height_and_timestamp().await?;
+		let channel_end = query_decoded_channel_end(self, at, channel_id, port_id.clone()).await?;
+		let counterparty_channel_id = channel_end
+			.counterparty()
+			.channel_id
+			.ok_or_else(|| Error::Custom("Expected counterparty channel id".to_string()))?;
+		let counterparty_channel_end = query_decoded_channel_end(
+			counterparty,
+			counterparty_height,
+			counterparty_channel_id,
+			channel_end.counterparty().port_id.clone(),
+		)
+		.await?;
+
+		let seqs = query_undelivered_sequences(
+			at,
+			counterparty_height,
+			channel_id,
+			port_id.clone(),
+			self,
+			counterparty,
+		)
+		.await?;
+		let packets = self.query_send_packets(channel_id, port_id, seqs).await?;
+		Ok(UndeliveredInfo::classify(
+			channel_end.state,
+			counterparty_channel_end.state,
+			packets.iter().map(|packet_info| {
+				let packet = packet_info_to_packet(packet_info);
+				(
+					packet_info.sequence,
+					packet.timed_out(&counterparty_timestamp, counterparty_height),
+				)
+			}),
+		))
+	}
+
+	/// Returns the client of `counterparty` on this chain to relay with: the active client of
+	/// the counterparty's [`IbcProvider::client_type`] with the highest latest height, or `None`
+	/// if there is no such client. Clients whose state or status can't be queried are skipped.
+	async fn find_suitable_client<C>(
+		&self,
+		counterparty: &C,
+	) -> Result<Option<ClientId>, anyhow::Error>
+	where
+		Self: Sized,
+		C: IbcProvider + Sync,
+	{
+		let (height, _) = self.latest_height_and_timestamp().await?;
+		let mut candidates = vec![];
+		for client_id in self.query_clients().await? {
+			let client_state = self
+				.query_client_state(height, client_id.clone())
+				.await
+				.ok()
+				.and_then(|response| response.client_state)
+				.and_then(|client_state| AnyClientState::try_from(client_state).ok());
+			let status = self.query_client_status(height, client_id.clone()).await;
+			match (client_state, status) {
+				(Some(client_state), Ok(status)) => candidates.push(ClientCandidate {
+					client_type: client_state.unpack_recursive().client_type(),
+					latest_height: client_state.latest_height(),
+					status,
+					client_id,
+				}),
+				_ =>
+					log::debug!(target: "hyperspace", "Skipping client {client_id} on {height}: its state or status can't be queried"),
+			}
+		}
+		Ok(select_suitable_client(candidates, &counterparty.client_type()))
+	}
 }
 
 /// Provides an interface that allows us run the hyperspace-testsuite
@@ -473,6 +1244,14 @@ pub trait TestProvider: Chain + Clone + 'static {
 	/// Initiate an ibc transfer on chain.
 	async fn send_transfer(&self, params: MsgTransfer<PrefixedCoin>) -> Result<(), Self::Error>;
 
+	/// Initiate an ibc transfer on chain, paying `fee` to the relayers of the packet with the
+	/// ICS-29 fee middleware.
+	async fn send_transfer_with_fee(
+		&self,
+		params: MsgTransfer<PrefixedCoin>,
+		fee: Fee,
+	) -> Result<(), Self::Error>;
+
 	/// Send a packet on an ordered channel
 	async fn send_ordered_packet(
 		&self,
@@ -480,29 +1259,67 @@ pub trait TestProvider: Chain + Clone + 'static {
 		timeout: pallet_ibc::Timeout,
 	) -> Result<(), Self::Error>;
 
+	/// Send a packet with arbitrary `data` from the application bound to `port_id`, for the
+	/// applications other than ICS-20 transfers
+	async fn send_raw_packet(
+		&self,
+		port_id: PortId,
+		channel_id: ChannelId,
+		data: Vec<u8>,
+		timeout: pallet_ibc::Timeout,
+	) -> Result<(), Self::Error>;
+
 	/// Returns a stream that yields chain Block number
 	async fn subscribe_blocks(&self) -> Pin<Box<dyn Stream<Item = u64> + Send + Sync>>;
 
 	/// Increases IBC counters by 1 to check that relayer uses proper values for source/sink chains.
 	async fn increase_counters(&mut self) -> Result<(), Self::Error>;
+
+	/// Makes sure the asset exists on chain and that `mint_to` holds at least `amount` of it, so
+	/// that tests can transfer it. Calling it again doesn't mint more.
+	async fn ensure_asset(
+		&self,
+		asset_id: Self::AssetId,
+		mint_to: Signer,
+		amount: u128,
+	) -> Result<(), Self::Error>;
+
+	/// Returns the asset holding the vouchers of the given ibc denom, see
+	/// [`utils::ibc_denom_for`].
+	async fn query_asset_id(&self, ibc_denom: String) -> Result<Self::AssetId, Self::Error>;
 }
 
 /// Provides an interface for managing key management for signing.
+#[async_trait::async_trait]
 pub trait KeyProvider {
 	/// Should return the relayer's account id on the host chain as a string in the expected format
 	/// Could be a hexadecimal, bech32 or ss58 string, any format the chain supports
 	fn account_id(&self) -> Signer;
+
+	/// Signs the next transactions with the key of `key_source`, once its account is checked to
+	/// hold at least `min_balance` of the token paying the fees. The key is swapped for all the
+	/// clones of the client, the transactions being signed keep the previous key.
+	async fn rotate_key(
+		&self,
+		key_source: &KeySource,
+		min_balance: u128,
+	) -> Result<KeyRotation, Error> {
+		let _ = (key_source, min_balance);
+		Err(Error::Custom("Key rotation isn't supported by this chain".to_string()))
+	}
 }
 
 /// Provides an interface for managing IBC misbehaviour.
 #[async_trait::async_trait]
 pub trait MisbehaviourHandler {
-	/// Check the client message for misbehaviour and submit it to the chain if any.
+	/// Check the client message for misbehaviour, returning the evidence to be submitted to the
+	/// counterparty if any. The evidence is returned rather than submitted, so that it can be
+	/// archived even if its submission fails.
 	async fn check_for_misbehaviour<C: Chain>(
 		&self,
 		counterparty: &C,
 		client_message: AnyClientMessage,
-	) -> Result<(), anyhow::Error>;
+	) -> Result<Option<DetectedMisbehaviour>, anyhow::Error>;
 }
 
 /// Provides an interface for syncing light clients to the latest state
@@ -533,6 +1350,10 @@ pub trait Chain:
 	/// Should return an estimate of the weight of a batch of messages.
 	async fn estimate_weight(&self, msg: Vec<Any>) -> Result<u64, Self::Error>;
 
+	/// Executes a batch of messages against the latest state without submitting it, returning
+	/// the estimated weight of the batch and whether each message would succeed.
+	async fn simulate(&self, messages: Vec<Any>) -> Result<SimulationResult, Self::Error>;
+
 	/// Return a stream that yields when new [`IbcEvents`] are ready to be queried.
 	async fn finality_notifications(
 		&self,
@@ -551,6 +1372,17 @@ pub trait Chain:
 
 	async fn get_proof_height(&self, block_height: Height) -> Height;
 
+	/// Verifies `client_message`, an update of the client of this chain on `counterparty`,
+	/// against the current state of that client, as the client will when the update is submitted.
+	/// Chains that can't verify their updates natively accept all of them.
+	async fn verify_client_update<C: Chain>(
+		&self,
+		_counterparty: &C,
+		_client_message: AnyClientMessage,
+	) -> Result<(), anyhow::Error> {
+		Ok(())
+	}
+
 	async fn handle_error(&mut self, error: &anyhow::Error) -> Result<(), anyhow::Error>;
 
 	fn common_state(&self) -> &CommonClientState;
@@ -578,6 +1410,19 @@ pub trait Chain:
 	}
 
 	async fn reconnect(&mut self) -> anyhow::Result<()>;
+
+	/// Waits until the latest height of the chain advances, to check that its node is reachable
+	/// and producing blocks. Fails after `timeout`.
+	async fn await_ready(&self, timeout: Duration) -> Result<(), anyhow::Error> {
+		utils::await_height_advance(
+			|| async { self.latest_height_and_timestamp().await.map(|(height, _)| height) },
+			timeout,
+			utils::READY_POLL_INTERVAL,
+		)
+		.await
+		.map(|_| ())
+		.map_err(|e| anyhow::anyhow!("{} isn't ready: {e}", self.name()))
+	}
 }
 
 /// Returns undelivered packet sequences that have been sent out from
@@ -588,17 +1433,11 @@ pub async fn query_undelivered_sequences(
 	sink_height: Height,
 	channel_id: ChannelId,
 	port_id: PortId,
-	source: &impl Chain,
-	sink: &impl Chain,
+	source: &impl IbcProvider,
+	sink: &impl IbcProvider,
 ) -> Result<Vec<u64>, anyhow::Error> {
-	let channel_response =
-		source.query_channel_end(source_height, channel_id, port_id.clone()).await?;
-	let channel_end = ChannelEnd::try_from(
-		channel_response
-			.channel
-			.ok_or_else(|| Error::Custom("ChannelEnd not could not be decoded".to_string()))?,
-	)
-	.map_err(|e| Error::Custom(e.to_string()))?;
+	let channel_end =
+		query_decoded_channel_end(source, source_height, channel_id, port_id.clone()).await?;
 	// First we fetch all packet commitments from source
 	let seqs = source
 		.query_packet_commitments(source_height, channel_id, port_id.clone())
@@ -631,6 +1470,23 @@ pub async fn query_undelivered_sequences(
 	Ok(undelivered_sequences)
 }
 
+/// Queries and decodes the channel end of `channel_id`/`port_id` on `chain` at `at`.
+async fn query_decoded_channel_end(
+	chain: &impl IbcProvider,
+	at: Height,
+	channel_id: ChannelId,
+	port_id: PortId,
+) -> Result<ChannelEnd, anyhow::Error> {
+	let channel_response = chain.query_channel_end(at, channel_id, port_id).await?;
+	let channel_end = ChannelEnd::try_from(
+		channel_response
+			.channel
+			.ok_or_else(|| Error::Custom("ChannelEnd not could not be decoded".to_string()))?,
+	)
+	.map_err(|e| Error::Custom(e.to_string()))?;
+	Ok(channel_end)
+}
+
 /// Queries the `source` chain for packet acknowledgements that have not been seen by the `sink`
 /// chain.
 pub async fn query_undelivered_acks(
@@ -721,6 +1577,23 @@ pub async fn find_suitable_proof_height_for_client(
 	if timestamp_to_match.is_none() {
 		// try to find latest states first, because relayer's strategy is to submit the most
 		// recent ones
+		if let Some(height) = sink
+			.closest_consensus_height(client_id.clone(), latest_client_height)
+			.await
+			.ok()
+			.flatten()
+			.filter(|height| *height >= start_height)
+		{
+			let proof_height = source.get_proof_height(height).await;
+			if sink
+				.query_client_update_time_and_height(client_id.clone(), proof_height)
+				.await
+				.is_ok()
+			{
+				log::info!("Found proof height on {} as {}:{}", sink.name(), height, proof_height);
+				return Some(height)
+			}
+		}
 		for height in start_height.revision_height..=latest_client_height.revision_height {
 			let temp_height = Height::new(start_height.revision_number, height);
 			let consensus_state =
@@ -884,11 +1757,15 @@ pub async fn query_maximum_height_for_timeout_proofs(
 	min_timeout_height
 }
 
+/// Returns whether `ev` concerns the relayed clients, connections or whitelisted channels. With
+/// `auto_whitelist_channels`, the handshake events of any channel on the relayed connections pass
+/// as well, so that channels opened by third parties can be relayed and whitelisted.
 pub fn filter_events_by_ids(
 	ev: &IbcEvent,
 	client_ids: &[ClientId],
 	connection_ids: &[ConnectionId],
 	channel_and_port_ids: &HashSet<(ChannelId, PortId)>,
+	auto_whitelist_channels: bool,
 ) -> bool {
 	use ibc::core::{
 		ics02_client::events::Attributes as ClientAttributes,
@@ -927,6 +1804,10 @@ pub fn filter_events_by_ids(
 				.map(|id| channel_ids.contains(&id))
 				.unwrap_or(false)
 	};
+	let filter_channel_handshake = |packet: &ChannelAttributes| {
+		filter_channel_attributes(packet) ||
+			(auto_whitelist_channels && connection_ids.contains(&packet.connection_id))
+	};
 
 	let v = match ev {
 		IbcEvent::SendPacket(e) => filter_packet(&e.packet),
@@ -944,13 +1825,13 @@ pub fn filter_events_by_ids(
 		IbcEvent::OpenAckConnection(e) => filter_connection_attributes(&e.0),
 		IbcEvent::OpenConfirmConnection(e) => filter_connection_attributes(&e.0),
 		IbcEvent::OpenInitChannel(e) =>
-			filter_channel_attributes(&ChannelAttributes::from(e.clone())),
+			filter_channel_handshake(&ChannelAttributes::from(e.clone())),
 		IbcEvent::OpenTryChannel(e) =>
-			filter_channel_attributes(&ChannelAttributes::from(e.clone())),
+			filter_channel_handshake(&ChannelAttributes::from(e.clone())),
 		IbcEvent::OpenAckChannel(e) =>
-			filter_channel_attributes(&ChannelAttributes::from(e.clone())),
+			filter_channel_handshake(&ChannelAttributes::from(e.clone())),
 		IbcEvent::OpenConfirmChannel(e) =>
-			filter_channel_attributes(&ChannelAttributes::from(e.clone())),
+			filter_channel_handshake(&ChannelAttributes::from(e.clone())),
 		IbcEvent::CloseInitChannel(e) =>
 			filter_channel_attributes(&ChannelAttributes::from(e.clone())),
 		IbcEvent::CloseConfirmChannel(e) =>
@@ -966,3 +1847,260 @@ pub fn filter_events_by_ids(
 	}
 	v
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn ics20_v2_resolver(proposed: &str) -> Result<String, anyhow::Error> {
+		match proposed {
+			"ics20-1" => Ok("ics20-2".to_string()),
+			_ => Err(anyhow::anyhow!("unsupported version")),
+		}
+	}
+
+	#[test]
+	fn resolve_channel_version_uses_shared_resolver() {
+		let state = CommonClientState::default();
+		let connection_id = ConnectionId::new(0);
+		let port_id = PortId::transfer();
+		let proposed = Version::new("ics20-1".to_string());
+		assert_eq!(
+			state.resolve_channel_version(&connection_id, &port_id, &proposed).unwrap(),
+			proposed
+		);
+
+		// the resolver is set after cloning, as `create_channel` does for the relayer task
+		let relayer_state = state.clone();
+		state.set_channel_version_resolver(
+			connection_id.clone(),
+			port_id.clone(),
+			Some(ics20_v2_resolver),
+		);
+		assert_eq!(
+			relayer_state
+				.resolve_channel_version(&connection_id, &port_id, &proposed)
+				.unwrap(),
+			Version::new("ics20-2".to_string())
+		);
+		assert!(relayer_state
+			.resolve_channel_version(&connection_id, &port_id, &Version::new("ics27-1".to_string()))
+			.is_err());
+		// the channels of other connections aren't negotiated
+		let other_connection = ConnectionId::new(1);
+		assert_eq!(
+			relayer_state
+				.resolve_channel_version(&other_connection, &port_id, &proposed)
+				.unwrap(),
+			proposed
+		);
+
+		state.set_channel_version_resolver(connection_id.clone(), port_id.clone(), None);
+		assert_eq!(
+			relayer_state
+				.resolve_channel_version(&connection_id, &port_id, &proposed)
+				.unwrap(),
+			proposed
+		);
+	}
+
+	#[test]
+	fn strict_middleware_check_rejects_unknown_stacks() {
+		let fee_transfer =
+			Version::new(r#"{"fee_version":"ics29-1","app_version":"ics20-1"}"#.to_string());
+		let fee_hooks_transfer = Version::new(
+			r#"{"fee_version":"ics29-1","app_version":"{\"hooks_version\":\"wasm-hooks-1\",\"app_version\":\"ics20-1\"}"}"#
+				.to_string(),
+		);
+		let channel_id = ChannelId::new(0);
+		let port_id = PortId::transfer();
+
+		let mut state = CommonClientState::default();
+		assert!(state.is_channel_relayable(channel_id, &port_id, &fee_transfer));
+		assert!(state.is_channel_relayable(channel_id, &port_id, &fee_hooks_transfer));
+
+		state.strict_middleware_check = true;
+		assert!(state.is_channel_relayable(channel_id, &port_id, &fee_transfer));
+		assert!(!state.is_channel_relayable(channel_id, &port_id, &fee_hooks_transfer));
+		assert_eq!(
+			*state.unsupported_middleware_channels.lock().unwrap(),
+			HashSet::from([(channel_id, port_id)])
+		);
+	}
+
+	#[test]
+	fn client_status_parses_ibc_go_statuses() {
+		for status in [ClientStatus::Active, ClientStatus::Expired, ClientStatus::Frozen] {
+			assert_eq!(status.to_string().parse::<ClientStatus>().unwrap(), status);
+		}
+		assert!("Unauthorized".parse::<ClientStatus>().is_err());
+	}
+
+	fn pool_error(code: i32, reason: &str) -> jsonrpsee_core::Error {
+		let error = jsonrpsee_types::ErrorObject::owned(code, "Invalid Transaction", Some(reason));
+		jsonrpsee_core::Error::Call(jsonrpsee_types::error::CallError::Custom(error))
+	}
+
+	#[test]
+	fn transient_node_errors_are_retryable() {
+		for error in [
+			pool_error(error::POOL_INVALID_TX, error::STALE_TRANSACTION),
+			pool_error(1014, "Priority is too low: (1 vs 1)"),
+			jsonrpsee_core::Error::Transport(anyhow::anyhow!("Connection refused (os error 111)")),
+			jsonrpsee_core::Error::RestartNeeded("connection closed".to_string()),
+			jsonrpsee_core::Error::MaxSlotsExceeded,
+		] {
+			assert_eq!(error.kind().retry_policy(), RetryPolicy::Retry, "{error}");
+		}
+		for error in [
+			pool_error(error::POOL_INVALID_TX, "Inability to pay some fees"),
+			jsonrpsee_core::Error::ParseError(serde_json::from_str::<u32>("").unwrap_err()),
+			jsonrpsee_core::Error::MethodNotFound("ibc_queryClientState".to_string()),
+		] {
+			assert_eq!(error.kind().retry_policy(), RetryPolicy::Fail, "{error}");
+		}
+	}
+
+	#[test]
+	fn methods_missing_on_the_node_are_unsupported() {
+		let error = jsonrpsee_types::ErrorObject::owned(
+			jsonrpsee_types::error::METHOD_NOT_FOUND_CODE,
+			"Method not found",
+			None::<()>,
+		);
+		let error = jsonrpsee_core::Error::Call(jsonrpsee_types::error::CallError::Custom(error));
+		assert_eq!(error.kind(), ErrorKind::Unsupported);
+		let error = jsonrpsee_core::Error::MethodNotFound("ibc_simulateMessages".to_string());
+		assert_eq!(error.kind(), ErrorKind::Unsupported);
+	}
+
+	#[test]
+	fn relayed_connections_start_with_the_primary_one() {
+		let connection = |id| ConnectionConfig {
+			connection_id: ConnectionId::new(id),
+			channel_whitelist: vec![],
+			commitment_prefix: None,
+		};
+		let connection_ids =
+			relayed_connection_ids(Some(&ConnectionId::new(0)), &[connection(1), connection(0)]);
+		assert_eq!(connection_ids, vec![ConnectionId::new(0), ConnectionId::new(1)]);
+		assert_eq!(relayed_connection_ids(None, &[connection(2)]), vec![ConnectionId::new(2)]);
+
+		let mut connection_ids = vec![];
+		set_primary_connection_id(&mut connection_ids, ConnectionId::new(1));
+		set_primary_connection_id(&mut connection_ids, ConnectionId::new(0));
+		assert_eq!(connection_ids, vec![ConnectionId::new(0)]);
+		let mut connection_ids = vec![ConnectionId::new(0), ConnectionId::new(1)];
+		set_primary_connection_id(&mut connection_ids, ConnectionId::new(1));
+		assert_eq!(connection_ids, vec![ConnectionId::new(1)]);
+	}
+
+	#[test]
+	fn connection_whitelists_only_clear_their_connection() {
+		let channel = |id| (ChannelId::new(id), PortId::transfer());
+		let connection = ConnectionConfig {
+			connection_id: ConnectionId::new(1),
+			channel_whitelist: vec![channel(1)],
+			commitment_prefix: None,
+		};
+		let mut whitelist = ChannelWhitelist::new(vec![channel(0)], &[connection]);
+		assert_eq!(whitelist.all(), [channel(0), channel(1)].into_iter().collect());
+		assert!(whitelist.allows(&channel(0), &ConnectionId::new(0)));
+		assert!(whitelist.allows(&channel(1), &ConnectionId::new(1)));
+		// a channel of connection-1 found on connection-0 isn't relayed
+		assert!(!whitelist.allows(&channel(1), &ConnectionId::new(0)));
+
+		whitelist.insert_on(ConnectionId::new(0), channel(2));
+		whitelist.set([channel(0), channel(2)].into_iter().collect());
+		assert_eq!(whitelist.all(), [channel(0), channel(2)].into_iter().collect());
+		assert!(!whitelist.allows(&channel(1), &ConnectionId::new(1)));
+		assert!(!whitelist.allows(&channel(2), &ConnectionId::new(1)));
+		assert!(whitelist.allows(&channel(2), &ConnectionId::new(0)));
+	}
+
+	#[test]
+	fn connections_may_have_their_own_prefix() {
+		let connection = |id, prefix: Option<&str>| ConnectionConfig {
+			connection_id: ConnectionId::new(id),
+			channel_whitelist: vec![],
+			commitment_prefix: prefix.map(ToString::to_string),
+		};
+		let prefixes =
+			connection_prefixes(&[connection(1, Some("wasm/")), connection(2, None)]).unwrap();
+		assert_eq!(prefixes.len(), 1);
+		assert_eq!(prefixes[&ConnectionId::new(1)].as_bytes(), b"wasm/");
+
+		let error = connection_prefixes(&[connection(1, Some(""))]).unwrap_err();
+		assert!(error.to_string().contains("connection-1"), "{error}");
+	}
+
+	#[test]
+	fn relayer_errors_are_classified() {
+		let rpc_error = |error| subxt::error::RpcError::ClientError(Box::new(error));
+		let cases = [
+			(
+				Error::SubxtRRpc(rpc_error(jsonrpsee_core::Error::RequestTimeout)),
+				ErrorKind::Transport,
+			),
+			(
+				Error::Subxt(subxt::Error::Rpc(rpc_error(pool_error(1013, "Already imported")))),
+				ErrorKind::Transport,
+			),
+			(Error::RemoteSignerUnavailable("request timed out".to_string()), ErrorKind::Transport),
+			// errors are classified by their type, not by their message
+			(Error::Custom("Connection refused (os error 111)".to_string()), ErrorKind::Execution),
+			(Error::Codec("invalid enum variant".into()), ErrorKind::Decode),
+			(Error::HexDecode(hex::FromHexError::OddLength), ErrorKind::Decode),
+		];
+		for (error, kind) in cases {
+			assert_eq!(error.kind(), kind, "{error}");
+		}
+		assert_eq!(ErrorKind::Transport.retry_policy(), RetryPolicy::Retry);
+		assert_eq!(ErrorKind::NotFound.retry_policy(), RetryPolicy::Fail);
+	}
+
+	fn candidate(id: &str, status: ClientStatus, height: u64) -> ClientCandidate {
+		ClientCandidate {
+			client_id: ClientId::from_str(id).unwrap(),
+			client_type: "10-grandpa".to_string(),
+			status,
+			latest_height: Height::new(1, height),
+		}
+	}
+
+	#[test]
+	fn suitable_client_is_the_highest_active_one() {
+		// the frozen client is the highest, and listed first like a naive selection would take it
+		let clients = vec![
+			candidate("10-grandpa-0", ClientStatus::Frozen, 200),
+			candidate("10-grandpa-1", ClientStatus::Active, 100),
+			candidate("10-grandpa-2", ClientStatus::Expired, 150),
+			candidate("10-grandpa-3", ClientStatus::Active, 50),
+		];
+		assert_eq!(
+			select_suitable_client(clients.clone(), "10-grandpa"),
+			Some(ClientId::from_str("10-grandpa-1").unwrap())
+		);
+		assert_eq!(select_suitable_client(clients, "07-tendermint"), None);
+	}
+
+	#[test]
+	fn suitable_client_selection_is_deterministic() {
+		let clients = vec![
+			candidate("10-grandpa-4", ClientStatus::Active, 100),
+			candidate("10-grandpa-5", ClientStatus::Active, 100),
+		];
+		let reversed = clients.iter().cloned().rev().collect::<Vec<_>>();
+		assert_eq!(
+			select_suitable_client(clients, "10-grandpa"),
+			select_suitable_client(reversed, "10-grandpa")
+		);
+		assert_eq!(
+			select_suitable_client(
+				[candidate("10-grandpa-0", ClientStatus::Frozen, 1)],
+				"10-grandpa"
+			),
+			None
+		);
+	}
+}
@@ -0,0 +1,138 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Channel versions of applications wrapped in middleware are JSON objects holding the
+//! middleware version and the version of the wrapped application, e.g.
+//! `{"fee_version":"ics29-1","app_version":"ics20-1"}`. The wrapped version may itself be such an
+//! object.
+
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+
+/// Middleware stacks the relayer knows how to relay, outermost component first.
+pub const SUPPORTED_MIDDLEWARE_STACKS: &[&[&str]] =
+	&[&["ics20-1"], &["ics29-1", "ics20-1"], &["ping-1"]];
+
+/// Version of the ICS-29 fee middleware.
+pub const FEE_VERSION: &str = "ics29-1";
+
+const APP_VERSION_KEY: &str = "app_version";
+
+/// Splits a channel version into its middleware stack, outermost component first. A version that
+/// is not a JSON object is a stack with a single application.
+pub fn parse_middleware_stack(version: &str) -> Vec<String> {
+	let mut stack = vec![];
+	let mut version = version.to_string();
+	loop {
+		let fields = match serde_json::from_str::<Map<String, Value>>(&version) {
+			Ok(fields) => fields,
+			Err(_) => {
+				stack.push(version);
+				return stack
+			},
+		};
+		let mut app_version = None;
+		for (key, value) in fields {
+			let value = match value {
+				Value::String(value) => value,
+				value => value.to_string(),
+			};
+			if key == APP_VERSION_KEY {
+				app_version = Some(value);
+			} else if key.ends_with("_version") {
+				stack.push(value);
+			} else {
+				// middleware metadata we can't interpret
+				stack.push(key);
+			}
+		}
+		match app_version {
+			Some(app_version) => version = app_version,
+			None => return stack,
+		}
+	}
+}
+
+/// Returns the components of the version's middleware stack that make it unsupported, or an
+/// empty list if the stack is one of [`SUPPORTED_MIDDLEWARE_STACKS`]. If every component is known
+/// but the combination isn't, the whole stack is returned.
+pub fn unsupported_middleware(version: &str) -> Vec<String> {
+	let stack = parse_middleware_stack(version);
+	let is_supported = SUPPORTED_MIDDLEWARE_STACKS.iter().any(|supported| {
+		supported.len() == stack.len() && supported.iter().zip(&stack).all(|(a, b)| a == b)
+	});
+	if is_supported {
+		return vec![]
+	}
+
+	let known = SUPPORTED_MIDDLEWARE_STACKS
+		.iter()
+		.flat_map(|stack| stack.iter().copied())
+		.collect::<HashSet<_>>();
+	let unknown = stack
+		.iter()
+		.filter(|component| !known.contains(component.as_str()))
+		.cloned()
+		.collect::<Vec<_>>();
+	if unknown.is_empty() {
+		stack
+	} else {
+		unknown
+	}
+}
+
+/// Returns `true` if the channel's middleware stack includes the ICS-29 fee middleware, so that
+/// relayers are paid for the packets they relay on the channel.
+pub fn is_fee_enabled(version: &str) -> bool {
+	parse_middleware_stack(version).iter().any(|component| component == FEE_VERSION)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const FEE_TRANSFER: &str = r#"{"fee_version":"ics29-1","app_version":"ics20-1"}"#;
+	const FEE_HOOKS_TRANSFER: &str = r#"{"fee_version":"ics29-1","app_version":"{\"hooks_version\":\"wasm-hooks-1\",\"app_version\":\"ics20-1\"}"}"#;
+
+	#[test]
+	fn parses_nested_middleware_stacks() {
+		assert_eq!(parse_middleware_stack("ics20-1"), vec!["ics20-1"]);
+		assert_eq!(parse_middleware_stack(FEE_TRANSFER), vec!["ics29-1", "ics20-1"]);
+		assert_eq!(
+			parse_middleware_stack(FEE_HOOKS_TRANSFER),
+			vec!["ics29-1", "wasm-hooks-1", "ics20-1"]
+		);
+	}
+
+	#[test]
+	fn reports_unsupported_components() {
+		assert!(unsupported_middleware("ics20-1").is_empty());
+		assert!(unsupported_middleware("ping-1").is_empty());
+		assert!(unsupported_middleware(FEE_TRANSFER).is_empty());
+		assert_eq!(unsupported_middleware(FEE_HOOKS_TRANSFER), vec!["wasm-hooks-1"]);
+		// known components in an unknown order
+		assert_eq!(
+			unsupported_middleware(r#"{"fee_version":"ics29-1","app_version":"ping-1"}"#),
+			vec!["ics29-1", "ping-1"]
+		);
+	}
+
+	#[test]
+	fn detects_fee_enabled_channels() {
+		assert!(is_fee_enabled(FEE_TRANSFER));
+		assert!(is_fee_enabled(FEE_HOOKS_TRANSFER));
+		assert!(!is_fee_enabled("ics20-1"));
+		assert!(!is_fee_enabled(r#"{"hooks_version":"wasm-hooks-1","app_version":"ics20-1"}"#));
+	}
+}
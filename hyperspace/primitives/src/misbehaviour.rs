@@ -0,0 +1,140 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use ibc::core::ics24_host::identifier::ClientId;
+use ibc_proto::google::protobuf::Any;
+use serde::{Deserialize, Serialize};
+use std::{
+	sync::{Arc, Mutex},
+	time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Misbehaviour found by [`crate::MisbehaviourHandler::check_for_misbehaviour`], along with the
+/// evidence for it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MisbehaviourReport {
+	/// Chain hosting the client, to which the evidence was submitted
+	pub chain: String,
+	/// Client that was fed the conflicting proof
+	pub client_id: ClientId,
+	/// Height finalized by the proof submitted to the client
+	pub first_height: u64,
+	/// Height finalized by the conflicting proof, fetched from the chain
+	pub second_height: u64,
+	/// Encoding of the proof submitted to the client, e.g. the SCALE encoded grandpa finality
+	/// proof
+	#[serde(with = "hex::serde")]
+	pub first_proof: Vec<u8>,
+	/// Encoding of the conflicting proof
+	#[serde(with = "hex::serde")]
+	pub second_proof: Vec<u8>,
+	/// Seconds since the unix epoch at which the misbehaviour was found
+	pub detected_at: u64,
+	/// Id of the transaction that submitted the evidence, unset until its submission succeeds
+	#[serde(default)]
+	pub tx_id: Option<String>,
+	/// Outcome of the verification of the proofs against the state of the client, if the
+	/// relayer verifies the evidence before submitting it
+	#[serde(default)]
+	pub evidence_check: Option<EvidenceCheck>,
+}
+
+/// Outcome of the verification of the finality proofs of misbehaviour evidence against the
+/// authority set stored in the client, as the client verifies them when the evidence is
+/// submitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EvidenceCheck {
+	/// Authority set of the client the proofs were verified against
+	pub set_id: u64,
+	/// Whether the proof submitted to the client is valid
+	pub submitted_proof_valid: bool,
+	/// Whether the conflicting proof fetched from the chain is valid
+	pub trusted_proof_valid: bool,
+}
+
+impl EvidenceCheck {
+	/// The client only accepts the evidence if both proofs are valid.
+	pub fn is_valid(&self) -> bool {
+		self.submitted_proof_valid && self.trusted_proof_valid
+	}
+}
+
+/// Misbehaviour found by [`crate::MisbehaviourHandler::check_for_misbehaviour`], which still has
+/// to be submitted to the chain hosting the client.
+#[derive(Debug, Clone)]
+pub struct DetectedMisbehaviour {
+	pub report: MisbehaviourReport,
+	/// Client update carrying the evidence, signed by the account of the hosting chain. Unset if
+	/// the client would reject the evidence, in which case the misbehaviour is only reported.
+	pub message: Option<Any>,
+}
+
+/// Seconds since the unix epoch, for [`MisbehaviourReport::detected_at`].
+pub fn unix_time_now() -> u64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// Statistics of the misbehaviour checks recorded since they were last taken.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MisbehaviourCheckStats {
+	/// Duration of every check
+	pub check_times: Vec<Duration>,
+	/// Number of finality proofs served from the cache
+	pub proof_cache_hits: u64,
+	/// Number of finality proofs fetched from the node
+	pub proof_cache_misses: u64,
+}
+
+/// Records the statistics of [`crate::MisbehaviourHandler::check_for_misbehaviour`]. Shared
+/// between clones, so that the relayer can publish them to the metrics.
+#[derive(Debug, Clone, Default)]
+pub struct MisbehaviourCheckRecorder(Arc<Mutex<MisbehaviourCheckStats>>);
+
+impl MisbehaviourCheckRecorder {
+	pub fn observe_check(&self, time: Duration) {
+		self.0.lock().unwrap().check_times.push(time);
+	}
+
+	/// Starts timing a check, which is observed when the returned timer is dropped.
+	pub fn start_check(&self) -> CheckTimer {
+		CheckTimer { recorder: self.clone(), started: Instant::now() }
+	}
+
+	pub fn observe_proof_cache(&self, hit: bool) {
+		let mut stats = self.0.lock().unwrap();
+		if hit {
+			stats.proof_cache_hits += 1;
+		} else {
+			stats.proof_cache_misses += 1;
+		}
+	}
+
+	/// Takes the statistics recorded since the last call.
+	pub fn take(&self) -> MisbehaviourCheckStats {
+		std::mem::take(&mut *self.0.lock().unwrap())
+	}
+}
+
+/// Observes the duration of a check when dropped, whichever way the check returns.
+#[must_use]
+pub struct CheckTimer {
+	recorder: MisbehaviourCheckRecorder,
+	started: Instant,
+}
+
+impl Drop for CheckTimer {
+	fn drop(&mut self) {
+		self.recorder.observe_check(self.started.elapsed());
+	}
+}
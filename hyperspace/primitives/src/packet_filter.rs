@@ -0,0 +1,763 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Operator-configured filters deciding which packets of a channel get relayed.
+//!
+//! Each channel has an ordered list of rules, and the first rule matching a packet decides its
+//! [`FilterAction`]. Packets matching no rule are allowed. A rule matches when all of its
+//! criteria match. Criteria on the ICS20 fields (sender, receiver, denom and amount) only match
+//! packets of the transfer port whose data decodes to [`PacketData`], so that packets of other
+//! applications bypass them, while the data size criteria apply to every packet.
+//!
+//! The spam limits of a channel, a maximum data size, deny lists of senders and receivers and
+//! minimum amounts per denom, deny the packets exceeding them before its rules are evaluated.
+//!
+//! Filters only apply to the packets received on the counterparty. The acknowledgements and
+//! timeouts of the packets that were relayed are never filtered.
+
+use crate::error::Error;
+use ibc::{
+	applications::transfer::{packet::PacketData, Amount},
+	core::{
+		ics04_channel::packet::Packet,
+		ics24_host::identifier::{ChannelId, PortId},
+	},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::{BTreeMap, HashMap, HashSet},
+	fmt::{self, Display},
+	str::FromStr,
+	sync::{Arc, Mutex, RwLock},
+};
+
+/// What to do with a packet matched by a rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterAction {
+	/// Relay the packet.
+	Allow,
+	/// Don't relay the packet. It isn't dead-lettered, so it will eventually time out.
+	Deny,
+	/// Relay the packet after the other messages of the batch.
+	Deprioritize,
+}
+
+impl Display for FilterAction {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			FilterAction::Allow => write!(f, "allow"),
+			FilterAction::Deny => write!(f, "deny"),
+			FilterAction::Deprioritize => write!(f, "deprioritize"),
+		}
+	}
+}
+
+/// A filter rule, as written in the relayer config. Unset criteria match any packet.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PacketFilterRule {
+	/// Sender of the transfer
+	#[serde(default)]
+	pub sender: Option<String>,
+	/// Receiver of the transfer
+	#[serde(default)]
+	pub receiver: Option<String>,
+	/// Denom of the transfer, either the base denom or the full denom trace
+	#[serde(default)]
+	pub denom: Option<String>,
+	/// Minimum amount of the transfer, inclusive
+	#[serde(default)]
+	pub min_amount: Option<String>,
+	/// Maximum amount of the transfer, inclusive
+	#[serde(default)]
+	pub max_amount: Option<String>,
+	/// Minimum size of the packet data in bytes, inclusive
+	#[serde(default)]
+	pub min_data_size: Option<usize>,
+	/// Maximum size of the packet data in bytes, inclusive
+	#[serde(default)]
+	pub max_data_size: Option<usize>,
+	pub action: FilterAction,
+}
+
+/// The filter rules of a channel, as written in the relayer config.
+///
+/// The spam limits are evaluated as deny rules preceding `rules`, numbered first in the audit
+/// trail: the data size limit, then the senders, the receivers and the minimum amounts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelPacketFilter {
+	pub channel_id: ChannelId,
+	pub port_id: PortId,
+	#[serde(default)]
+	pub rules: Vec<PacketFilterRule>,
+	/// Packets with more data, in bytes, are denied
+	#[serde(default)]
+	pub max_data_size: Option<usize>,
+	/// Transfers sent by these accounts are denied
+	#[serde(default)]
+	pub deny_senders: Vec<String>,
+	/// Transfers to these accounts are denied
+	#[serde(default)]
+	pub deny_receivers: Vec<String>,
+	/// Transfers of these denoms, either the base denom or the full denom trace, with a smaller
+	/// amount are denied
+	#[serde(default)]
+	pub min_amounts: BTreeMap<String, String>,
+}
+
+impl ChannelPacketFilter {
+	/// A filter of the channel evaluating `rules` only.
+	pub fn new(channel_id: ChannelId, port_id: PortId, rules: Vec<PacketFilterRule>) -> Self {
+		Self {
+			channel_id,
+			port_id,
+			rules,
+			max_data_size: None,
+			deny_senders: vec![],
+			deny_receivers: vec![],
+			min_amounts: BTreeMap::new(),
+		}
+	}
+}
+
+/// A [`PacketFilterRule`] with its amounts parsed.
+#[derive(Debug, Clone)]
+struct Rule {
+	sender: Option<String>,
+	receiver: Option<String>,
+	denom: Option<String>,
+	min_amount: Option<Amount>,
+	max_amount: Option<Amount>,
+	/// Exclusive maximum amount, for the minimum amounts of the spam limits
+	below_amount: Option<Amount>,
+	min_data_size: Option<usize>,
+	max_data_size: Option<usize>,
+	action: FilterAction,
+}
+
+fn parse_amount(amount: &str) -> Result<Amount, Error> {
+	Amount::from_str(amount)
+		.map_err(|e| Error::Custom(format!("Invalid amount {amount} in packet filter: {e}")))
+}
+
+impl Rule {
+	fn new(rule: &PacketFilterRule) -> Result<Self, Error> {
+		Ok(Self {
+			sender: rule.sender.clone(),
+			receiver: rule.receiver.clone(),
+			denom: rule.denom.clone(),
+			min_amount: rule.min_amount.as_deref().map(parse_amount).transpose()?,
+			max_amount: rule.max_amount.as_deref().map(parse_amount).transpose()?,
+			below_amount: None,
+			min_data_size: rule.min_data_size,
+			max_data_size: rule.max_data_size,
+			action: rule.action,
+		})
+	}
+
+	fn deny() -> Self {
+		Self {
+			sender: None,
+			receiver: None,
+			denom: None,
+			min_amount: None,
+			max_amount: None,
+			below_amount: None,
+			min_data_size: None,
+			max_data_size: None,
+			action: FilterAction::Deny,
+		}
+	}
+
+	/// The deny rules of the spam limits of `filter`.
+	fn spam_limits(filter: &ChannelPacketFilter) -> Result<Vec<Self>, Error> {
+		let oversized = filter
+			.max_data_size
+			.map(|max| Self { min_data_size: Some(max + 1), ..Self::deny() });
+		let senders = filter
+			.deny_senders
+			.iter()
+			.map(|sender| Self { sender: Some(sender.clone()), ..Self::deny() });
+		let receivers = filter
+			.deny_receivers
+			.iter()
+			.map(|receiver| Self { receiver: Some(receiver.clone()), ..Self::deny() });
+		let mut rules = oversized.into_iter().chain(senders).chain(receivers).collect::<Vec<_>>();
+		for (denom, min) in &filter.min_amounts {
+			rules.push(Self {
+				denom: Some(denom.clone()),
+				below_amount: Some(parse_amount(min)?),
+				..Self::deny()
+			});
+		}
+		Ok(rules)
+	}
+
+	fn has_field_criteria(&self) -> bool {
+		self.sender.is_some() ||
+			self.receiver.is_some() ||
+			self.denom.is_some() ||
+			self.min_amount.is_some() ||
+			self.max_amount.is_some() ||
+			self.below_amount.is_some()
+	}
+
+	fn matches(&self, data_size: usize, transfer: Option<&PacketData>) -> bool {
+		if self.min_data_size.map_or(false, |min| data_size < min) ||
+			self.max_data_size.map_or(false, |max| data_size > max)
+		{
+			return false
+		}
+		if !self.has_field_criteria() {
+			return true
+		}
+		let Some(transfer) = transfer else { return false };
+		let amount = transfer.token.amount;
+		let denom = &transfer.token.denom;
+		self.sender.as_ref().map_or(true, |sender| transfer.sender.as_ref() == sender) &&
+			self.receiver
+				.as_ref()
+				.map_or(true, |receiver| transfer.receiver.as_ref() == receiver) &&
+			self.denom.as_ref().map_or(true, |expected| {
+				denom.base_denom.as_str() == expected || &denom.to_string() == expected
+			}) && self.min_amount.map_or(true, |min| amount >= min) &&
+			self.max_amount.map_or(true, |max| amount <= max) &&
+			self.below_amount.map_or(true, |below| amount < below)
+	}
+}
+
+/// Decodes the data of an ICS20 packet. Returns `None` for packets of other ports.
+pub fn decode_transfer_data(packet: &Packet) -> Option<PacketData> {
+	if packet.source_port != PortId::transfer() {
+		return None
+	}
+	serde_json::from_slice(&packet.data).ok()
+}
+
+/// Number of bytes of the data of non-transfer packets included in the logs.
+pub const MAX_LOGGED_DATA_SIZE: usize = 64;
+
+/// Hex encodes the data of a packet for the logs, truncated to [`MAX_LOGGED_DATA_SIZE`] bytes.
+pub fn hex_packet_data(data: &[u8]) -> String {
+	if data.len() > MAX_LOGGED_DATA_SIZE {
+		format!("0x{}...", hex::encode(&data[..MAX_LOGGED_DATA_SIZE]))
+	} else {
+		format!("0x{}", hex::encode(data))
+	}
+}
+
+/// The outcome of filtering a packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FilterDecision {
+	pub action: FilterAction,
+	/// Index of the matching rule in the channel's rules, if any
+	pub rule: Option<usize>,
+}
+
+impl FilterDecision {
+	const DEFAULT: Self = Self { action: FilterAction::Allow, rule: None };
+}
+
+/// Audit trail of a packet that was denied or deprioritized.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+	pub action: FilterAction,
+	pub rule: usize,
+	pub port_id: PortId,
+	pub channel_id: ChannelId,
+	pub sequence: u64,
+	pub data_size: usize,
+	/// Decoded ICS20 fields, for transfer packets
+	pub transfer: Option<PacketData>,
+	/// Hex encoded data of the packets that aren't ICS20 transfers, truncated to
+	/// [`MAX_LOGGED_DATA_SIZE`] bytes
+	pub raw_data: Option<String>,
+}
+
+impl AuditEntry {
+	pub fn log(&self) {
+		log::info!(target: "hyperspace::audit", "{self}");
+	}
+}
+
+impl Display for AuditEntry {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"packet filter: {} {}/{} sequence {} (rule #{}, data size {})",
+			self.action, self.port_id, self.channel_id, self.sequence, self.rule, self.data_size
+		)?;
+		if let Some(transfer) = &self.transfer {
+			write!(
+				f,
+				": sender={} receiver={} denom={} amount={}",
+				transfer.sender, transfer.receiver, transfer.token.denom, transfer.token.amount
+			)?;
+		} else if let Some(data) = &self.raw_data {
+			write!(f, ": data={data}")?;
+		}
+		Ok(())
+	}
+}
+
+/// The packet filters of all the channels of a chain.
+#[derive(Debug, Clone, Default)]
+pub struct PacketFilter {
+	channels: HashMap<(PortId, ChannelId), Vec<Rule>>,
+}
+
+impl PacketFilter {
+	pub fn new(filters: &[ChannelPacketFilter]) -> Result<Self, Error> {
+		let mut channels = HashMap::new();
+		for filter in filters {
+			let mut rules = Rule::spam_limits(filter)?;
+			for rule in &filter.rules {
+				rules.push(Rule::new(rule)?);
+			}
+			if channels.insert((filter.port_id.clone(), filter.channel_id), rules).is_some() {
+				return Err(Error::Custom(format!(
+					"Duplicate packet filter for channel {}/{}",
+					filter.port_id, filter.channel_id
+				)))
+			}
+		}
+		Ok(Self { channels })
+	}
+
+	/// Filters a packet sent on its source channel.
+	pub fn evaluate(&self, packet: &Packet) -> (FilterDecision, Option<AuditEntry>) {
+		let Some(rules) = self.channels.get(&(packet.source_port.clone(), packet.source_channel))
+		else {
+			return (FilterDecision::DEFAULT, None)
+		};
+		let transfer = decode_transfer_data(packet);
+		let data_size = packet.data.len();
+		let Some((rule, action)) = rules
+			.iter()
+			.enumerate()
+			.find(|(_, rule)| rule.matches(data_size, transfer.as_ref()))
+			.map(|(i, rule)| (i, rule.action))
+		else {
+			return (FilterDecision::DEFAULT, None)
+		};
+
+		let decision = FilterDecision { action, rule: Some(rule) };
+		let audit = (action != FilterAction::Allow).then(|| AuditEntry {
+			action,
+			rule,
+			port_id: packet.source_port.clone(),
+			channel_id: packet.source_channel,
+			sequence: packet.sequence.into(),
+			data_size,
+			raw_data: transfer.is_none().then(|| hex_packet_data(&packet.data)),
+			transfer,
+		});
+		(decision, audit)
+	}
+}
+
+/// Number of audited packets remembered by a [`SharedPacketFilter`], past which they are forgotten
+/// and audited again.
+pub const MAX_AUDITED_PACKETS: usize = 100_000;
+
+/// A [`PacketFilter`] shared between clones of a chain, so that it can be reloaded while the
+/// relayer is running.
+#[derive(Debug, Clone, Default)]
+pub struct SharedPacketFilter {
+	filter: Arc<RwLock<PacketFilter>>,
+	/// Packets whose audit entry was logged since the filters were loaded, by channel, sequence
+	/// and action. Undelivered packets are filtered again on every pass of the relay loop.
+	audited: Arc<Mutex<HashSet<(PortId, ChannelId, u64, FilterAction)>>>,
+}
+
+impl SharedPacketFilter {
+	pub fn new(filters: &[ChannelPacketFilter]) -> Result<Self, Error> {
+		Ok(Self {
+			filter: Arc::new(RwLock::new(PacketFilter::new(filters)?)),
+			audited: Default::default(),
+		})
+	}
+
+	/// Replaces the filters. An invalid config is rejected and the current filters are kept.
+	pub fn reload(&self, filters: &[ChannelPacketFilter]) -> Result<(), Error> {
+		let filter = PacketFilter::new(filters)?;
+		*self.filter.write().unwrap() = filter;
+		self.audited.lock().unwrap().clear();
+		log::info!(target: "hyperspace", "Reloaded packet filters of {} channels", filters.len());
+		Ok(())
+	}
+
+	/// Filters a packet and logs the audit entry of denied and deprioritized packets, once per
+	/// packet.
+	pub fn evaluate(&self, packet: &Packet) -> FilterDecision {
+		let (decision, audit) = self.filter.read().unwrap().evaluate(packet);
+		if let Some(audit) = audit {
+			if self.first_audit(&audit) {
+				audit.log();
+			}
+		}
+		decision
+	}
+
+	/// Records the audit of a packet, returning `false` if it was already audited.
+	fn first_audit(&self, audit: &AuditEntry) -> bool {
+		let mut audited = self.audited.lock().unwrap();
+		if audited.len() >= MAX_AUDITED_PACKETS {
+			audited.clear();
+		}
+		audited.insert((audit.port_id.clone(), audit.channel_id, audit.sequence, audit.action))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ibc::applications::transfer::{PrefixedCoin, PrefixedDenom};
+
+	fn transfer_packet(sender: &str, receiver: &str, denom: &str, amount: u64) -> Packet {
+		let data = PacketData {
+			token: PrefixedCoin {
+				denom: PrefixedDenom::from_str(denom).unwrap(),
+				amount: Amount::from_str(&amount.to_string()).unwrap(),
+			},
+			sender: sender.parse().unwrap(),
+			receiver: receiver.parse().unwrap(),
+			memo: String::new(),
+		};
+		Packet {
+			sequence: 7.into(),
+			source_port: PortId::transfer(),
+			source_channel: ChannelId::new(0),
+			data: serde_json::to_vec(&data).unwrap(),
+			..Default::default()
+		}
+	}
+
+	fn filter(rules: Vec<PacketFilterRule>) -> PacketFilter {
+		PacketFilter::new(&[ChannelPacketFilter::new(ChannelId::new(0), PortId::transfer(), rules)])
+			.unwrap()
+	}
+
+	fn action(filter: &PacketFilter, packet: &Packet) -> FilterAction {
+		filter.evaluate(packet).0.action
+	}
+
+	fn deny() -> PacketFilterRule {
+		PacketFilterRule { action: FilterAction::Deny, ..Default::default() }
+	}
+
+	#[test]
+	fn matches_each_rule_type() {
+		let packet = transfer_packet("alice", "bob", "transfer/channel-1/uatom", 100);
+
+		let by_sender = filter(vec![PacketFilterRule { sender: Some("alice".into()), ..deny() }]);
+		assert_eq!(action(&by_sender, &packet), FilterAction::Deny);
+		let by_sender = filter(vec![PacketFilterRule { sender: Some("bob".into()), ..deny() }]);
+		assert_eq!(action(&by_sender, &packet), FilterAction::Allow);
+
+		let by_receiver = filter(vec![PacketFilterRule { receiver: Some("bob".into()), ..deny() }]);
+		assert_eq!(action(&by_receiver, &packet), FilterAction::Deny);
+
+		for denom in ["uatom", "transfer/channel-1/uatom"] {
+			let by_denom = filter(vec![PacketFilterRule { denom: Some(denom.into()), ..deny() }]);
+			assert_eq!(action(&by_denom, &packet), FilterAction::Deny, "{denom}");
+		}
+		let by_denom = filter(vec![PacketFilterRule { denom: Some("uosmo".into()), ..deny() }]);
+		assert_eq!(action(&by_denom, &packet), FilterAction::Allow);
+
+		for (min, max, expected) in [
+			(Some("100"), None, FilterAction::Deny),
+			(Some("101"), None, FilterAction::Allow),
+			(None, Some("100"), FilterAction::Deny),
+			(None, Some("99"), FilterAction::Allow),
+			(Some("1"), Some("1000"), FilterAction::Deny),
+		] {
+			let by_amount = filter(vec![PacketFilterRule {
+				min_amount: min.map(Into::into),
+				max_amount: max.map(Into::into),
+				..deny()
+			}]);
+			assert_eq!(action(&by_amount, &packet), expected, "{min:?}..{max:?}");
+		}
+
+		let size = packet.data.len();
+		let by_size = filter(vec![PacketFilterRule { max_data_size: Some(size - 1), ..deny() }]);
+		assert_eq!(action(&by_size, &packet), FilterAction::Allow);
+		let by_size = filter(vec![PacketFilterRule { min_data_size: Some(size), ..deny() }]);
+		assert_eq!(action(&by_size, &packet), FilterAction::Deny);
+	}
+
+	fn spam_limits(configure: impl FnOnce(&mut ChannelPacketFilter)) -> PacketFilter {
+		let allow = PacketFilterRule { action: FilterAction::Allow, ..Default::default() };
+		let mut filter =
+			ChannelPacketFilter::new(ChannelId::new(0), PortId::transfer(), vec![allow]);
+		configure(&mut filter);
+		PacketFilter::new(&[filter]).unwrap()
+	}
+
+	#[test]
+	fn spam_limits_deny_before_the_rules() {
+		let packet = transfer_packet("alice", "bob", "transfer/channel-1/uatom", 100);
+		assert_eq!(action(&spam_limits(|_| {}), &packet), FilterAction::Allow);
+
+		let size = packet.data.len();
+		let oversized = spam_limits(|filter| filter.max_data_size = Some(size - 1));
+		assert_eq!(
+			oversized.evaluate(&packet).0,
+			FilterDecision { action: FilterAction::Deny, rule: Some(0) }
+		);
+		let oversized = spam_limits(|filter| filter.max_data_size = Some(size));
+		assert_eq!(action(&oversized, &packet), FilterAction::Allow);
+
+		let senders =
+			spam_limits(|filter| filter.deny_senders = vec!["carol".into(), "alice".into()]);
+		assert_eq!(
+			senders.evaluate(&packet).0,
+			FilterDecision { action: FilterAction::Deny, rule: Some(1) }
+		);
+		let senders = spam_limits(|filter| filter.deny_senders = vec!["carol".into()]);
+		assert_eq!(action(&senders, &packet), FilterAction::Allow);
+
+		let receivers = spam_limits(|filter| filter.deny_receivers = vec!["bob".into()]);
+		assert_eq!(action(&receivers, &packet), FilterAction::Deny);
+		let receivers = spam_limits(|filter| filter.deny_receivers = vec!["alice".into()]);
+		assert_eq!(action(&receivers, &packet), FilterAction::Allow);
+
+		// the minimum amount is inclusive, and only applies to its denom
+		for (denom, min, expected) in [
+			("uatom", "101", FilterAction::Deny),
+			("transfer/channel-1/uatom", "101", FilterAction::Deny),
+			("uatom", "100", FilterAction::Allow),
+			("uosmo", "1000", FilterAction::Allow),
+		] {
+			let min_amounts = spam_limits(|filter| {
+				filter.min_amounts = [(denom.to_string(), min.to_string())].into_iter().collect()
+			});
+			assert_eq!(action(&min_amounts, &packet), expected, "{min}{denom}");
+		}
+		let invalid = ChannelPacketFilter {
+			min_amounts: [("uatom".to_string(), "-1".to_string())].into_iter().collect(),
+			..ChannelPacketFilter::new(ChannelId::new(0), PortId::transfer(), vec![])
+		};
+		assert!(PacketFilter::new(&[invalid]).is_err());
+
+		// packets of other applications are only limited by their size
+		let mut ping = packet;
+		ping.source_port = PortId::from_str("ping").unwrap();
+		let mut filter =
+			ChannelPacketFilter::new(ChannelId::new(0), ping.source_port.clone(), vec![]);
+		filter.deny_senders = vec!["alice".into()];
+		filter.min_amounts = [("uatom".to_string(), "1000".to_string())].into_iter().collect();
+		let engine = PacketFilter::new(&[filter.clone()]).unwrap();
+		assert_eq!(action(&engine, &ping), FilterAction::Allow);
+		filter.max_data_size = Some(1);
+		let engine = PacketFilter::new(&[filter]).unwrap();
+		assert_eq!(action(&engine, &ping), FilterAction::Deny);
+	}
+
+	#[test]
+	fn first_matching_rule_wins() {
+		let dust = PacketFilterRule {
+			max_amount: Some("10".into()),
+			action: FilterAction::Deprioritize,
+			..Default::default()
+		};
+		let trusted = PacketFilterRule {
+			sender: Some("alice".into()),
+			action: FilterAction::Allow,
+			..Default::default()
+		};
+		let engine = filter(vec![trusted, dust, deny()]);
+
+		// allowed before the catch-all deny, even for dust amounts
+		let packet = transfer_packet("alice", "bob", "uatom", 1);
+		assert_eq!(
+			engine.evaluate(&packet).0,
+			FilterDecision { action: FilterAction::Allow, rule: Some(0) }
+		);
+		let packet = transfer_packet("carol", "bob", "uatom", 1);
+		assert_eq!(
+			engine.evaluate(&packet).0,
+			FilterDecision { action: FilterAction::Deprioritize, rule: Some(1) }
+		);
+		let packet = transfer_packet("carol", "bob", "uatom", 11);
+		assert_eq!(
+			engine.evaluate(&packet).0,
+			FilterDecision { action: FilterAction::Deny, rule: Some(2) }
+		);
+
+		// a rule matches only if all of its criteria match
+		let both = PacketFilterRule {
+			sender: Some("alice".into()),
+			denom: Some("uosmo".into()),
+			..deny()
+		};
+		let engine = filter(vec![both]);
+		assert_eq!(
+			action(&engine, &transfer_packet("alice", "bob", "uatom", 1)),
+			FilterAction::Allow
+		);
+		assert_eq!(
+			action(&engine, &transfer_packet("alice", "bob", "uosmo", 1)),
+			FilterAction::Deny
+		);
+	}
+
+	#[test]
+	fn other_channels_and_ports_bypass_field_rules() {
+		let packet = transfer_packet("alice", "bob", "uatom", 100);
+		let engine = filter(vec![deny()]);
+		let mut other_channel = packet.clone();
+		other_channel.source_channel = ChannelId::new(1);
+		assert_eq!(engine.evaluate(&other_channel).0, FilterDecision::DEFAULT);
+
+		// packets of other applications are only matched by the size rules
+		let engine = PacketFilter::new(&[ChannelPacketFilter::new(
+			ChannelId::new(0),
+			PortId::from_str("ping").unwrap(),
+			vec![
+				PacketFilterRule { sender: Some("alice".into()), ..deny() },
+				PacketFilterRule {
+					min_data_size: Some(4),
+					action: FilterAction::Deprioritize,
+					..Default::default()
+				},
+			],
+		)])
+		.unwrap();
+		let mut ping = packet;
+		ping.source_port = PortId::from_str("ping").unwrap();
+		assert_eq!(
+			engine.evaluate(&ping).0,
+			FilterDecision { action: FilterAction::Deprioritize, rule: Some(1) }
+		);
+		let audit = engine.evaluate(&ping).1.unwrap();
+		assert_eq!(audit.transfer, None);
+		assert!(audit.to_string().ends_with(&format!(": data={}", hex_packet_data(&ping.data))));
+		ping.data = b"abc".to_vec();
+		assert_eq!(engine.evaluate(&ping).0, FilterDecision::DEFAULT);
+	}
+
+	#[test]
+	fn audit_entries() {
+		let packet = transfer_packet("alice", "bob", "uatom", 100);
+		let engine = filter(vec![
+			PacketFilterRule {
+				sender: Some("carol".into()),
+				action: FilterAction::Allow,
+				..Default::default()
+			},
+			PacketFilterRule { receiver: Some("bob".into()), ..deny() },
+		]);
+
+		let (decision, audit) = engine.evaluate(&packet);
+		assert_eq!(decision.action, FilterAction::Deny);
+		let audit = audit.unwrap();
+		assert_eq!(audit.rule, 1);
+		assert_eq!(audit.sequence, 7);
+		assert_eq!(audit.data_size, packet.data.len());
+		assert_eq!(
+			audit.to_string(),
+			format!(
+				"packet filter: deny transfer/channel-0 sequence 7 (rule #1, data size {}): \
+				sender=alice receiver=bob denom=uatom amount=100",
+				packet.data.len()
+			)
+		);
+
+		assert_eq!(audit.raw_data, None);
+
+		// allowed packets aren't audited
+		let packet = transfer_packet("carol", "bob", "uatom", 100);
+		assert_eq!(engine.evaluate(&packet).1, None);
+	}
+
+	#[test]
+	fn logged_packet_data_is_truncated() {
+		assert_eq!(hex_packet_data(b"ping"), "0x70696e67");
+		let data = vec![0xab; MAX_LOGGED_DATA_SIZE + 1];
+		assert_eq!(hex_packet_data(&data), format!("0x{}...", "ab".repeat(MAX_LOGGED_DATA_SIZE)));
+	}
+
+	#[test]
+	fn reload_keeps_filters_on_invalid_config() {
+		let packet = transfer_packet("alice", "bob", "uatom", 100);
+		let channel =
+			|rules| ChannelPacketFilter::new(ChannelId::new(0), PortId::transfer(), rules);
+		let shared = SharedPacketFilter::default();
+		assert_eq!(shared.evaluate(&packet).action, FilterAction::Allow);
+
+		shared.reload(&[channel(vec![deny()])]).unwrap();
+		assert_eq!(shared.evaluate(&packet).action, FilterAction::Deny);
+
+		let invalid = PacketFilterRule { min_amount: Some("-1".into()), ..deny() };
+		assert!(shared.reload(&[channel(vec![invalid])]).is_err());
+		assert!(shared.reload(&[channel(vec![]), channel(vec![])]).is_err());
+		assert_eq!(shared.evaluate(&packet).action, FilterAction::Deny);
+	}
+
+	#[test]
+	fn packets_are_audited_once() {
+		let packet = transfer_packet("alice", "bob", "uatom", 100);
+		let channel = ChannelPacketFilter::new(ChannelId::new(0), PortId::transfer(), vec![deny()]);
+		let shared = SharedPacketFilter::new(&[channel.clone()]).unwrap();
+		let audit = || shared.filter.read().unwrap().evaluate(&packet).1.unwrap();
+
+		assert!(shared.first_audit(&audit()));
+		assert!(!shared.first_audit(&audit()));
+		let next = Packet { sequence: 8.into(), ..packet.clone() };
+		let next_audit = shared.filter.read().unwrap().evaluate(&next).1.unwrap();
+		assert!(shared.first_audit(&next_audit));
+
+		// the packets are audited again under the reloaded filters
+		shared.reload(&[channel]).unwrap();
+		assert!(shared.first_audit(&audit()));
+	}
+
+	#[test]
+	fn decoding_preserves_memo() {
+		// packet data as encoded by ibc-go, with the memo of a forwarding middleware
+		let memo = r#"{"forward":{"receiver":"bob","port":"transfer","channel":"channel-1"}}"#;
+		let raw = json_packet_data(memo);
+		let packet = Packet {
+			source_port: PortId::transfer(),
+			data: raw.clone().into_bytes(),
+			..Default::default()
+		};
+		assert_eq!(decode_transfer_data(&packet).unwrap().memo, memo);
+
+		// evaluating the filter leaves the relayed bytes untouched
+		let engine = filter(vec![PacketFilterRule { sender: Some("alice".into()), ..deny() }]);
+		assert_eq!(action(&engine, &packet), FilterAction::Deny);
+		assert_eq!(packet.data, raw.into_bytes());
+
+		// the memo is optional in the packets of older chains
+		let packet = Packet {
+			data: br#"{"amount":"100","denom":"uatom","receiver":"bob","sender":"alice"}"#.to_vec(),
+			..packet
+		};
+		assert_eq!(decode_transfer_data(&packet).unwrap().memo, "");
+	}
+
+	fn json_packet_data(memo: &str) -> String {
+		serde_json::json!({
+			"amount": "100",
+			"denom": "uatom",
+			"memo": memo,
+			"receiver": "bob",
+			"sender": "alice",
+		})
+		.to_string()
+	}
+}
@@ -0,0 +1,208 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The proofs of every packet of a batch are built independently, so the client state and
+//! consensus states of a path are queried many times at the same heights. The state of a chain
+//! at a given height never changes, so the responses are memoized. Queries at height zero,
+//! which the nodes answer at their latest height, are never cached.
+
+use ibc::{core::ics24_host::identifier::ClientId, Height};
+use ibc_proto::ibc::core::client::v1::{QueryClientStateResponse, QueryConsensusStateResponse};
+use lru::LruCache;
+use std::{
+	future::Future,
+	num::NonZeroUsize,
+	sync::{Arc, Mutex},
+};
+
+/// Default number of responses kept by [`QueryCache`].
+pub const DEFAULT_QUERY_CACHE_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum QueryKey {
+	ClientState { at: Height, client_id: ClientId },
+	ClientConsensus { at: Height, client_id: ClientId, consensus_height: Height },
+}
+
+#[derive(Debug, Clone)]
+enum QueryResponse {
+	ClientState(QueryClientStateResponse),
+	ClientConsensus(QueryConsensusStateResponse),
+}
+
+/// LRU cache of the client state and consensus state queries of a chain, shared between clones
+/// of the client. Disabled if its capacity is zero.
+#[derive(Debug, Clone)]
+pub struct QueryCache(Option<Arc<Mutex<LruCache<QueryKey, QueryResponse>>>>);
+
+impl Default for QueryCache {
+	fn default() -> Self {
+		Self::new(DEFAULT_QUERY_CACHE_CAPACITY)
+	}
+}
+
+impl QueryCache {
+	pub fn new(capacity: usize) -> Self {
+		Self(
+			NonZeroUsize::new(capacity)
+				.map(|capacity| Arc::new(Mutex::new(LruCache::new(capacity)))),
+		)
+	}
+
+	/// Returns the client state of `client_id` at `at`, calling `fetch` on a cache miss.
+	pub async fn client_state<F, Fut, E>(
+		&self,
+		at: Height,
+		client_id: ClientId,
+		fetch: F,
+	) -> Result<QueryClientStateResponse, E>
+	where
+		F: FnOnce(ClientId) -> Fut,
+		Fut: Future<Output = Result<QueryClientStateResponse, E>>,
+	{
+		let key = QueryKey::ClientState { at, client_id: client_id.clone() };
+		if let Some(QueryResponse::ClientState(response)) = self.get(&key) {
+			return Ok(response)
+		}
+		let response = fetch(client_id).await?;
+		self.insert(key, QueryResponse::ClientState(response.clone()));
+		Ok(response)
+	}
+
+	/// Returns the consensus state of `client_id` at `consensus_height`, queried at `at`,
+	/// calling `fetch` on a cache miss.
+	pub async fn client_consensus<F, Fut, E>(
+		&self,
+		at: Height,
+		client_id: ClientId,
+		consensus_height: Height,
+		fetch: F,
+	) -> Result<QueryConsensusStateResponse, E>
+	where
+		F: FnOnce(ClientId) -> Fut,
+		Fut: Future<Output = Result<QueryConsensusStateResponse, E>>,
+	{
+		let key = QueryKey::ClientConsensus { at, client_id: client_id.clone(), consensus_height };
+		if let Some(QueryResponse::ClientConsensus(response)) = self.get(&key) {
+			return Ok(response)
+		}
+		let response = fetch(client_id).await?;
+		self.insert(key, QueryResponse::ClientConsensus(response.clone()));
+		Ok(response)
+	}
+
+	fn get(&self, key: &QueryKey) -> Option<QueryResponse> {
+		self.0.as_ref()?.lock().unwrap().get(key).cloned()
+	}
+
+	fn insert(&self, key: QueryKey, response: QueryResponse) {
+		let at = match &key {
+			QueryKey::ClientState { at, .. } | QueryKey::ClientConsensus { at, .. } => at,
+		};
+		if at.revision_height == 0 {
+			return
+		}
+		if let Some(cache) = &self.0 {
+			cache.lock().unwrap().put(key, response);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use futures::executor::block_on;
+	use std::{collections::HashMap, convert::Infallible};
+
+	/// Number of calls to the node, by query, client and height.
+	type Calls = Arc<Mutex<HashMap<(&'static str, ClientId, Height), usize>>>;
+
+	fn clients() -> [ClientId; 2] {
+		[ClientId::new("10-grandpa", 0).unwrap(), ClientId::new("07-tendermint", 0).unwrap()]
+	}
+
+	/// Assembles the proofs of a batch of packets, querying the client state and consensus state
+	/// of both clients of the path for every packet.
+	fn assemble_batch(cache: &QueryCache, calls: &Calls, at: Height, packets: usize) {
+		for _ in 0..packets {
+			for client_id in clients() {
+				block_on(cache.client_state(at, client_id, |client_id| {
+					*calls.lock().unwrap().entry(("client_state", client_id, at)).or_default() += 1;
+					async { Ok::<_, Infallible>(QueryClientStateResponse::default()) }
+				}))
+				.unwrap();
+			}
+			for client_id in clients() {
+				block_on(cache.client_consensus(at, client_id, Height::new(1, 10), |client_id| {
+					*calls.lock().unwrap().entry(("consensus", client_id, at)).or_default() += 1;
+					async { Ok::<_, Infallible>(QueryConsensusStateResponse::default()) }
+				}))
+				.unwrap();
+			}
+		}
+	}
+
+	#[test]
+	fn batch_queries_each_client_once_per_height() {
+		let cache = QueryCache::default();
+		let calls = Calls::default();
+
+		assemble_batch(&cache, &calls, Height::new(1, 100), 100);
+		assert_eq!(calls.lock().unwrap().len(), 4);
+		assert!(calls.lock().unwrap().values().all(|count| *count == 1));
+
+		// the next height is queried once as well
+		assemble_batch(&cache, &calls, Height::new(1, 101), 100);
+		assert_eq!(calls.lock().unwrap().len(), 8);
+		assert!(calls.lock().unwrap().values().all(|count| *count == 1));
+	}
+
+	#[test]
+	fn latest_height_is_not_cached() {
+		let cache = QueryCache::default();
+		let calls = Calls::default();
+		assemble_batch(&cache, &calls, Height::new(1, 0), 10);
+		assert_eq!(calls.lock().unwrap().len(), 4);
+		assert!(calls.lock().unwrap().values().all(|count| *count == 10));
+	}
+
+	#[test]
+	fn errors_are_not_cached() {
+		let cache = QueryCache::default();
+		let [client_id, _] = clients();
+		let at = Height::new(1, 100);
+
+		let result = block_on(cache.client_state(at, client_id.clone(), |_| async { Err(()) }));
+		assert!(result.is_err());
+		let mut fetched = false;
+		block_on(cache.client_state(at, client_id, |_| {
+			fetched = true;
+			async { Ok::<_, ()>(QueryClientStateResponse::default()) }
+		}))
+		.unwrap();
+		assert!(fetched);
+	}
+
+	#[test]
+	fn disabled_and_evicted_entries_are_refetched() {
+		let calls = Calls::default();
+		assemble_batch(&QueryCache::new(0), &calls, Height::new(1, 100), 10);
+		assert!(calls.lock().unwrap().values().all(|count| *count == 10));
+
+		// every query of a batch evicts the previous one from a single entry cache
+		let calls = Calls::default();
+		assemble_batch(&QueryCache::new(1), &calls, Height::new(1, 100), 10);
+		assert!(calls.lock().unwrap().values().all(|count| *count == 10));
+	}
+}
@@ -0,0 +1,159 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Statistics of the messages the relayer submits to a chain, kept in memory so that they can be
+//! queried while the relayer runs.
+
+use ibc::{
+	core::{
+		ics02_client::msgs::update_client,
+		ics04_channel::msgs::{acknowledgement, recv_packet, timeout, timeout_on_close},
+		ics24_host::identifier::ClientId,
+	},
+	Height,
+};
+use ibc_proto::google::protobuf::Any;
+use std::{
+	collections::BTreeMap,
+	sync::{Arc, Mutex},
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Number of messages of each kind in a batch submitted to a chain.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MessageCounts {
+	pub received_packets: u64,
+	pub acknowledgements: u64,
+	pub timeouts: u64,
+	pub client_updates: u64,
+}
+
+impl MessageCounts {
+	pub fn of(msgs: &[Any]) -> Self {
+		let mut counts = Self::default();
+		for msg in msgs {
+			match msg.type_url.as_str() {
+				recv_packet::TYPE_URL => counts.received_packets += 1,
+				acknowledgement::TYPE_URL => counts.acknowledgements += 1,
+				timeout::TYPE_URL | timeout_on_close::TYPE_URL => counts.timeouts += 1,
+				update_client::TYPE_URL => counts.client_updates += 1,
+				_ => {},
+			}
+		}
+		counts
+	}
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RelayStatsSnapshot {
+	/// Batches of messages submitted to the chain
+	pub submitted_batches: u64,
+	/// Batches of messages that failed to be submitted to the chain
+	pub failed_batches: u64,
+	/// Messages of the submitted batches
+	pub submitted: MessageCounts,
+	/// Messages waiting to be submitted to the chain
+	pub pending_messages: u64,
+	/// Seconds since the unix epoch of the last successful submission
+	pub last_submission: Option<u64>,
+	/// Latest consensus heights the clients hosted on the chain were seen updated to
+	pub client_heights: BTreeMap<ClientId, Height>,
+}
+
+/// Records the statistics of the relay to a chain. Shared between clones, so that the relayer can
+/// serve them.
+#[derive(Debug, Clone, Default)]
+pub struct RelayStats(Arc<Mutex<RelayStatsSnapshot>>);
+
+impl RelayStats {
+	pub fn record_submission(&self, counts: MessageCounts) {
+		let mut stats = self.0.lock().unwrap();
+		stats.submitted_batches += 1;
+		stats.submitted.received_packets += counts.received_packets;
+		stats.submitted.acknowledgements += counts.acknowledgements;
+		stats.submitted.timeouts += counts.timeouts;
+		stats.submitted.client_updates += counts.client_updates;
+		stats.last_submission =
+			SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|since| since.as_secs());
+	}
+
+	pub fn record_failed_submission(&self) {
+		self.0.lock().unwrap().failed_batches += 1;
+	}
+
+	pub fn set_pending_messages(&self, count: usize) {
+		self.0.lock().unwrap().pending_messages = count as u64;
+	}
+
+	/// Records an `UpdateClient` event of `client_id` to `height`. Events of older heights are
+	/// ignored.
+	pub fn observe_client_update(&self, client_id: &ClientId, height: Height) {
+		let mut stats = self.0.lock().unwrap();
+		let latest = stats.client_heights.entry(client_id.clone()).or_insert(height);
+		*latest = height.max(*latest);
+	}
+
+	pub fn snapshot(&self) -> RelayStatsSnapshot {
+		self.0.lock().unwrap().clone()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn submitted_messages_are_counted_by_kind() {
+		let msg = |type_url: &str| Any { type_url: type_url.to_string(), value: vec![] };
+		let stats = RelayStats::default();
+		let shared = stats.clone();
+		stats.record_submission(MessageCounts::of(&[
+			msg(update_client::TYPE_URL),
+			msg(recv_packet::TYPE_URL),
+			msg(recv_packet::TYPE_URL),
+			msg(acknowledgement::TYPE_URL),
+		]));
+		shared.record_submission(MessageCounts::of(&[
+			msg(timeout::TYPE_URL),
+			msg(timeout_on_close::TYPE_URL),
+			msg("/ibc.core.channel.v1.MsgChannelOpenTry"),
+		]));
+		shared.record_failed_submission();
+
+		let snapshot = stats.snapshot();
+		assert_eq!(snapshot.submitted_batches, 2);
+		assert_eq!(snapshot.failed_batches, 1);
+		assert_eq!(
+			snapshot.submitted,
+			MessageCounts {
+				received_packets: 2,
+				acknowledgements: 1,
+				timeouts: 2,
+				client_updates: 1
+			}
+		);
+		assert!(snapshot.last_submission.is_some());
+	}
+
+	#[test]
+	fn latest_client_update_is_kept() {
+		let stats = RelayStats::default();
+		let client_id = ClientId::new("07-tendermint", 5).unwrap();
+		stats.observe_client_update(&client_id, Height::new(1, 10));
+		stats.observe_client_update(&client_id, Height::new(1, 8));
+		assert_eq!(stats.snapshot().client_heights[&client_id], Height::new(1, 10));
+		stats.observe_client_update(&client_id, Height::new(1, 12));
+		assert_eq!(stats.snapshot().client_heights[&client_id], Height::new(1, 12));
+	}
+}
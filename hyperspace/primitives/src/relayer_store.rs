@@ -0,0 +1,315 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Packet bookkeeping persisted across restarts of the relayer.
+//!
+//! The relay loop records, for every whitelisted channel of the source chain, the latest height
+//! whose events it processed and the packets sent on the channel that weren't acknowledged or
+//! timed out yet. On startup, the saved state is reconciled with the packet commitments of the
+//! source, so that the packets sent while the relayer was down are relayed right away.
+//!
+//! The packet messages submitted to the chain on a channel are recorded as well, by transaction,
+//! until the events of the chain confirm their delivery. The transactions still unconfirmed on
+//! startup are checked against the chain, and the packets they didn't deliver are relayed again.
+
+use crate::error::Error;
+use ibc::{
+	core::ics24_host::identifier::{ChannelId, PortId},
+	events::IbcEvent,
+	Height,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::{BTreeMap, BTreeSet, HashMap},
+	fmt::Debug,
+	sync::{Arc, Mutex},
+};
+
+/// Bookkeeping of a channel of the source chain.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelState {
+	/// Latest height of the source whose events were processed
+	pub last_processed_height: Option<Height>,
+	/// Packets sent on the channel and not acknowledged or timed out yet
+	pub pending_sequences: BTreeSet<u64>,
+	/// Transactions submitted to the chain with packet messages of the channel, by transaction
+	/// id, whose delivery wasn't confirmed by the events of the chain yet
+	#[serde(default)]
+	pub unconfirmed_txs: BTreeMap<String, UnconfirmedTx>,
+}
+
+/// Packet messages of a channel carried by a transaction submitted to the chain.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnconfirmedTx {
+	/// Sequences of the packets received on the channel by the transaction
+	pub received: BTreeSet<u64>,
+	/// Sequences of the packets sent on the channel, acknowledged or timed out by the transaction
+	pub acknowledged: BTreeSet<u64>,
+}
+
+impl UnconfirmedTx {
+	pub fn is_empty(&self) -> bool {
+		self.received.is_empty() && self.acknowledged.is_empty()
+	}
+}
+
+impl ChannelState {
+	/// Records the `events` of the channel emitted by the source at `height`. The events of the
+	/// packets received, acknowledged or timed out confirm the transactions that delivered them.
+	pub fn record_events(
+		&mut self,
+		channel_id: ChannelId,
+		port_id: &PortId,
+		height: Height,
+		events: &[IbcEvent],
+	) {
+		for event in events {
+			let (packet, sent) = match event {
+				IbcEvent::ReceivePacket(ev) => {
+					if ev.packet.destination_channel == channel_id &&
+						&ev.packet.destination_port == port_id
+					{
+						let sequence = u64::from(ev.packet.sequence);
+						self.confirm_delivery(|tx| tx.received.remove(&sequence));
+					}
+					continue
+				},
+				IbcEvent::SendPacket(ev) => (&ev.packet, true),
+				IbcEvent::AcknowledgePacket(ev) => (&ev.packet, false),
+				IbcEvent::TimeoutPacket(ev) => (&ev.packet, false),
+				IbcEvent::TimeoutOnClosePacket(ev) => (&ev.packet, false),
+				_ => continue,
+			};
+			if packet.source_channel != channel_id || &packet.source_port != port_id {
+				continue
+			}
+			let sequence = u64::from(packet.sequence);
+			if sent {
+				self.pending_sequences.insert(sequence);
+			} else {
+				self.pending_sequences.remove(&sequence);
+				self.confirm_delivery(|tx| tx.acknowledged.remove(&sequence));
+			}
+		}
+		if self.last_processed_height.map_or(true, |last| last < height) {
+			self.last_processed_height = Some(height);
+		}
+	}
+
+	/// Records the packet messages of the channel carried by the transaction `tx_id` submitted to
+	/// the chain.
+	pub fn record_submission(&mut self, tx_id: String, tx: UnconfirmedTx) {
+		if !tx.is_empty() {
+			self.unconfirmed_txs.insert(tx_id, tx);
+		}
+	}
+
+	/// Removes a delivered packet from the unconfirmed transactions, and the transactions
+	/// whose packets were all delivered.
+	fn confirm_delivery(&mut self, mut confirm: impl FnMut(&mut UnconfirmedTx) -> bool) {
+		self.unconfirmed_txs.retain(|_, tx| {
+			confirm(tx);
+			!tx.is_empty()
+		});
+	}
+
+	/// Reconciles the state saved before a restart with the packets `committed` on the source.
+	/// Packets without a commitment were acknowledged or timed out while the relayer was down.
+	/// Returns the committed packets sent while the relayer was down.
+	pub fn reconcile(&mut self, committed: impl IntoIterator<Item = u64>) -> Vec<u64> {
+		let committed = committed.into_iter().collect::<BTreeSet<_>>();
+		let sent_while_down = committed.difference(&self.pending_sequences).copied().collect();
+		self.pending_sequences = committed;
+		sent_while_down
+	}
+}
+
+/// Storage of the [`ChannelState`]s, keyed by the name of the source chain and the channel.
+pub trait RelayerStore: Debug + Send + Sync {
+	fn load(
+		&self,
+		chain: &str,
+		channel_id: ChannelId,
+		port_id: &PortId,
+	) -> Result<Option<ChannelState>, Error>;
+
+	fn save(
+		&self,
+		chain: &str,
+		channel_id: ChannelId,
+		port_id: &PortId,
+		state: &ChannelState,
+	) -> Result<(), Error>;
+
+	/// Persists the states saved since the last flush. The relay loop saves the states of all
+	/// the channels of a batch before flushing them at once.
+	fn flush(&self) -> Result<(), Error> {
+		Ok(())
+	}
+}
+
+/// Key of a channel in a [`RelayerStore`].
+pub fn channel_key(chain: &str, channel_id: ChannelId, port_id: &PortId) -> String {
+	format!("{chain}/{port_id}/{channel_id}")
+}
+
+/// [`RelayerStore`] kept in memory, which only survives restarts of the relay loop.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryRelayerStore(Arc<Mutex<HashMap<String, ChannelState>>>);
+
+impl RelayerStore for InMemoryRelayerStore {
+	fn load(
+		&self,
+		chain: &str,
+		channel_id: ChannelId,
+		port_id: &PortId,
+	) -> Result<Option<ChannelState>, Error> {
+		Ok(self.0.lock().unwrap().get(&channel_key(chain, channel_id, port_id)).cloned())
+	}
+
+	fn save(
+		&self,
+		chain: &str,
+		channel_id: ChannelId,
+		port_id: &PortId,
+		state: &ChannelState,
+	) -> Result<(), Error> {
+		self.0
+			.lock()
+			.unwrap()
+			.insert(channel_key(chain, channel_id, port_id), state.clone());
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ibc::core::ics04_channel::{
+		events::{AcknowledgePacket, ReceivePacket, SendPacket},
+		packet::{Packet, Sequence},
+	};
+
+	fn packet(sequence: u64, channel_id: u64) -> Packet {
+		Packet {
+			sequence: Sequence::from(sequence),
+			source_port: PortId::transfer(),
+			source_channel: ChannelId::new(channel_id),
+			..Default::default()
+		}
+	}
+
+	fn sent(sequence: u64, channel_id: u64) -> IbcEvent {
+		IbcEvent::SendPacket(SendPacket {
+			height: Height::new(1, 10),
+			packet: packet(sequence, channel_id),
+		})
+	}
+
+	fn acknowledged(sequence: u64) -> IbcEvent {
+		IbcEvent::AcknowledgePacket(AcknowledgePacket {
+			height: Height::new(1, 10),
+			packet: packet(sequence, 0),
+		})
+	}
+
+	fn received(sequence: u64, channel_id: u64) -> IbcEvent {
+		IbcEvent::ReceivePacket(ReceivePacket {
+			height: Height::new(1, 10),
+			packet: Packet {
+				sequence: Sequence::from(sequence),
+				destination_port: PortId::transfer(),
+				destination_channel: ChannelId::new(channel_id),
+				..Default::default()
+			},
+		})
+	}
+
+	#[test]
+	fn pending_sequences_follow_the_events_of_the_channel() {
+		let mut state = ChannelState::default();
+		let channel_id = ChannelId::new(0);
+		let events = [sent(1, 0), sent(2, 0), sent(3, 1)];
+		state.record_events(channel_id, &PortId::transfer(), Height::new(1, 10), &events);
+		state.record_events(
+			channel_id,
+			&PortId::transfer(),
+			Height::new(1, 11),
+			&[acknowledged(1)],
+		);
+		assert_eq!(state.pending_sequences, BTreeSet::from([2]));
+		assert_eq!(state.last_processed_height, Some(Height::new(1, 11)));
+
+		// heights processed out of order don't move the last processed height back
+		state.record_events(channel_id, &PortId::transfer(), Height::new(1, 9), &[]);
+		assert_eq!(state.last_processed_height, Some(Height::new(1, 11)));
+	}
+
+	#[test]
+	fn packets_sent_while_down_are_found_on_resume() {
+		let store = InMemoryRelayerStore::default();
+		let channel_id = ChannelId::new(0);
+		let mut state = ChannelState::default();
+		state.record_events(
+			channel_id,
+			&PortId::transfer(),
+			Height::new(1, 10),
+			&[sent(1, 0), sent(2, 0)],
+		);
+		store.save("chain_a", channel_id, &PortId::transfer(), &state).unwrap();
+
+		// while the relayer is down, packet 1 is acknowledged and packets 3 and 4 are sent
+		let mut state = store.load("chain_a", channel_id, &PortId::transfer()).unwrap().unwrap();
+		assert_eq!(state.reconcile([2, 3, 4]), vec![3, 4]);
+		assert_eq!(state.pending_sequences, BTreeSet::from([2, 3, 4]));
+		// the relayer resumes from where it stopped rather than from genesis
+		assert_eq!(state.last_processed_height, Some(Height::new(1, 10)));
+
+		assert!(store.load("chain_b", channel_id, &PortId::transfer()).unwrap().is_none());
+	}
+
+	#[test]
+	fn submitted_transactions_are_confirmed_by_the_events_of_the_channel() {
+		let mut state = ChannelState::default();
+		let channel_id = ChannelId::new(0);
+		state.record_submission(
+			"0x01".to_string(),
+			UnconfirmedTx { received: BTreeSet::from([1, 2]), acknowledged: BTreeSet::from([5]) },
+		);
+		state.record_submission(
+			"0x02".to_string(),
+			UnconfirmedTx { received: BTreeSet::from([3]), ..Default::default() },
+		);
+		// transactions without packet messages of the channel aren't recorded
+		state.record_submission("0x03".to_string(), UnconfirmedTx::default());
+
+		// packet 2 is received on another channel, packet 3 on this one
+		state.record_events(
+			channel_id,
+			&PortId::transfer(),
+			Height::new(1, 10),
+			&[received(1, 0), received(2, 1), received(3, 0), acknowledged(5)],
+		);
+		assert_eq!(
+			state.unconfirmed_txs,
+			BTreeMap::from([(
+				"0x01".to_string(),
+				UnconfirmedTx { received: BTreeSet::from([2]), ..Default::default() }
+			)])
+		);
+
+		state.record_events(channel_id, &PortId::transfer(), Height::new(1, 11), &[received(2, 0)]);
+		assert!(state.unconfirmed_txs.is_empty());
+	}
+}
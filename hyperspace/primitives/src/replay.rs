@@ -0,0 +1,616 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Recording and replay of the RPC calls of the providers, to reproduce a relayer run against
+//! the exact chain state it saw.
+//!
+//! The layer sits in the transport of the jsonrpsee clients the providers hold, so every call
+//! made through them is covered: the subxt calls as well as the typed RPCs and subscriptions. With
+//! `HYPERSPACE_RECORD=<dir>` set, the responses the node sends are written to `<dir>/<transport>`,
+//! one file per call keyed by the method, the hash of the params and the number of identical
+//! calls before it. The notifications of a subscription are written next to the response to the
+//! call that opened it. With `HYPERSPACE_REPLAY=<dir>`, the client doesn't connect to the node:
+//! the calls are answered from these files in the same order, a call that wasn't recorded fails,
+//! and a replayed subscription ends after its last recorded notification.
+
+use crate::error::Error;
+use futures::{channel::mpsc, StreamExt};
+use jsonrpsee_client_transport::ws::{Uri, WsTransportClientBuilder};
+use jsonrpsee_core::client::{
+	async_client::{Client, ClientBuilder},
+	ReceivedMessage, TransportReceiverT, TransportSenderT,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{
+	collections::HashMap,
+	ffi::OsString,
+	fs,
+	path::PathBuf,
+	sync::{Arc, Mutex, OnceLock},
+};
+
+/// Directory the RPC calls are recorded to.
+pub const RECORD_DIR_ENV: &str = "HYPERSPACE_RECORD";
+
+/// Directory the RPC calls are replayed from.
+pub const REPLAY_DIR_ENV: &str = "HYPERSPACE_REPLAY";
+
+/// Code of the error answered to a call that wasn't recorded.
+const NOT_RECORDED_CODE: i32 = -32099;
+
+/// Number of notifications a replayed subscription buffers, since they're all sent at once.
+const REPLAYED_NOTIFICATIONS_CAPACITY: usize = 1 << 16;
+
+/// Whether the RPC calls are recorded to, or replayed from, a directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordMode {
+	Record(PathBuf),
+	Replay(PathBuf),
+}
+
+impl RecordMode {
+	/// The mode set by [`RECORD_DIR_ENV`] or [`REPLAY_DIR_ENV`], if any.
+	pub fn from_env() -> Result<Option<Self>, Error> {
+		Self::from_vars(std::env::var_os(RECORD_DIR_ENV), std::env::var_os(REPLAY_DIR_ENV))
+	}
+
+	fn from_vars(
+		record: Option<OsString>,
+		replay: Option<OsString>,
+	) -> Result<Option<Self>, Error> {
+		match (record, replay) {
+			(Some(_), Some(_)) => Err(Error::Custom(format!(
+				"Only one of {RECORD_DIR_ENV} and {REPLAY_DIR_ENV} can be set"
+			))),
+			(Some(dir), None) => Ok(Some(Self::Record(dir.into()))),
+			(None, Some(dir)) => Ok(Some(Self::Replay(dir.into()))),
+			(None, None) => Ok(None),
+		}
+	}
+}
+
+/// A recorded answer of the node: the response to a call, or a notification of a subscription.
+#[derive(Serialize, Deserialize)]
+struct Recording {
+	method: String,
+	params: Value,
+	/// The message sent by the node, without the id of the request it answers
+	response: Value,
+}
+
+/// The recordings of the calls of a transport. The recordings of the same directory are shared,
+/// so that the calls of a transport that reconnects keep their order.
+#[derive(Debug, Clone)]
+pub struct Recordings {
+	replay: bool,
+	dir: PathBuf,
+	/// Number of calls made so far by key
+	calls: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl Recordings {
+	/// The recordings of `transport`, e.g. the name of the chain and of its client, in the
+	/// directory of `mode`.
+	pub fn open(mode: &RecordMode, transport: &str) -> Self {
+		static OPENED: OnceLock<Mutex<HashMap<(bool, PathBuf), Recordings>>> = OnceLock::new();
+		let (replay, dir) = match mode {
+			RecordMode::Record(dir) => (false, dir.join(transport)),
+			RecordMode::Replay(dir) => (true, dir.join(transport)),
+		};
+		OPENED
+			.get_or_init(Default::default)
+			.lock()
+			.unwrap()
+			.entry((replay, dir.clone()))
+			.or_insert_with(|| Self { replay, dir, calls: Default::default() })
+			.clone()
+	}
+
+	/// Whether the calls are answered from the recordings.
+	pub fn is_replay(&self) -> bool {
+		self.replay
+	}
+
+	/// Name of the recording of the next call of `method` with `params`.
+	fn next_call(&self, method: &str, params: &Value) -> String {
+		let hash = hex::encode(&Sha256::digest(format!("{method}({params})").as_bytes())[..8]);
+		let method = method.replace(|c: char| !c.is_ascii_alphanumeric() && c != '_', "_");
+		let key = format!("{method}-{hash}");
+		let mut calls = self.calls.lock().unwrap();
+		let call = calls.entry(key.clone()).or_default();
+		*call += 1;
+		format!("{key}-{}", *call - 1)
+	}
+
+	fn path(&self, name: &str) -> PathBuf {
+		self.dir.join(format!("{name}.json"))
+	}
+
+	fn write(&self, name: &str, recording: &Recording) -> Result<(), Error> {
+		let path = self.path(name);
+		let encoded = serde_json::to_vec_pretty(recording).map_err(|e| {
+			Error::Custom(format!("Failed to encode the recording of {}: {e}", recording.method))
+		})?;
+		fs::create_dir_all(&self.dir)
+			.and_then(|_| fs::write(&path, encoded))
+			.map_err(|e| Error::Custom(format!("Failed to record {}: {e}", path.display())))
+	}
+
+	/// The recording `name`, if there is one.
+	fn read(&self, name: &str) -> Result<Option<Recording>, Error> {
+		let path = self.path(name);
+		if !path.exists() {
+			return Ok(None)
+		}
+		let recording = fs::read(&path)
+			.map_err(|e| Error::Custom(format!("Failed to read {}: {e}", path.display())))?;
+		serde_json::from_slice(&recording)
+			.map(Some)
+			.map_err(|e| Error::Custom(format!("Invalid recording {}: {e}", path.display())))
+	}
+}
+
+/// Name of the recording of the `index`th notification of the subscription opened by the call
+/// recorded as `call`.
+fn notification_name(call: &str, index: usize) -> String {
+	format!("{call}.notification-{index}")
+}
+
+fn is_unsubscribe(method: &str) -> bool {
+	let method = method.to_lowercase();
+	method.contains("unsubscribe") || method.contains("unwatch")
+}
+
+fn is_subscribe(method: &str) -> bool {
+	let method = method.to_lowercase();
+	!is_unsubscribe(&method) && (method.contains("subscribe") || method.contains("watch"))
+}
+
+/// A call sent by the client.
+#[derive(Deserialize)]
+struct Call {
+	id: Value,
+	method: String,
+	#[serde(default)]
+	params: Value,
+}
+
+impl Call {
+	fn parse(message: &str) -> Result<Self, Error> {
+		if message.trim_start().starts_with('[') {
+			return Err(Error::Custom("Batch requests can't be recorded or replayed".to_string()))
+		}
+		serde_json::from_str(message)
+			.map_err(|e| Error::Custom(format!("Invalid request {message}: {e}")))
+	}
+}
+
+fn transport_error(e: impl std::error::Error) -> Error {
+	Error::Custom(format!("Transport error: {e}"))
+}
+
+/// Calls awaiting a response and live subscriptions of a recorded client.
+#[derive(Default)]
+struct RecorderState {
+	/// Recording name and call, by request id
+	pending: HashMap<String, (String, Call)>,
+	/// Recording name of the call that opened the subscription and number of notifications
+	/// recorded, by subscription id
+	subscriptions: HashMap<String, (String, usize)>,
+}
+
+/// Sends the calls of a recorded client to the node.
+struct RecordingSender<S> {
+	inner: S,
+	recordings: Recordings,
+	state: Arc<Mutex<RecorderState>>,
+}
+
+#[async_trait::async_trait]
+impl<S: TransportSenderT + Send> TransportSenderT for RecordingSender<S> {
+	type Error = Error;
+
+	async fn send(&mut self, msg: String) -> Result<(), Error> {
+		let call = Call::parse(&msg)?;
+		if is_unsubscribe(&call.method) {
+			// unsubscribing isn't recorded, since when it happens depends on the consumer
+			let subscription = call.params.get(0).map(Value::to_string).unwrap_or_default();
+			self.state.lock().unwrap().subscriptions.remove(&subscription);
+		} else {
+			let name = self.recordings.next_call(&call.method, &call.params);
+			self.state.lock().unwrap().pending.insert(call.id.to_string(), (name, call));
+		}
+		self.inner.send(msg).await.map_err(transport_error)
+	}
+
+	async fn send_ping(&mut self) -> Result<(), Error> {
+		self.inner.send_ping().await.map_err(transport_error)
+	}
+
+	async fn close(&mut self) -> Result<(), Error> {
+		self.inner.close().await.map_err(transport_error)
+	}
+}
+
+/// Receives the answers of the node to a recorded client, and records them.
+struct RecordingReceiver<R> {
+	inner: R,
+	recordings: Recordings,
+	state: Arc<Mutex<RecorderState>>,
+}
+
+impl<R> RecordingReceiver<R> {
+	fn record(&self, message: &[u8]) -> Result<(), Error> {
+		let mut message: Value = serde_json::from_slice(message)
+			.map_err(|e| Error::Custom(format!("Invalid message from the node: {e}")))?;
+		let mut state = self.state.lock().unwrap();
+		if let Some(id) = message.as_object_mut().and_then(|message| message.remove("id")) {
+			let Some((name, call)) = state.pending.remove(&id.to_string()) else { return Ok(()) };
+			if is_subscribe(&call.method) {
+				if let Some(subscription) = message.get("result") {
+					state.subscriptions.insert(subscription.to_string(), (name.clone(), 0));
+				}
+			}
+			let recording =
+				Recording { method: call.method, params: call.params, response: message };
+			return self.recordings.write(&name, &recording)
+		}
+
+		let Some(subscription) = message.pointer("/params/subscription").map(Value::to_string)
+		else {
+			return Ok(())
+		};
+		let Some((call, notifications)) = state.subscriptions.get_mut(&subscription) else {
+			return Ok(())
+		};
+		let name = notification_name(call, *notifications);
+		*notifications += 1;
+		let method = message.get("method").and_then(Value::as_str).unwrap_or_default().to_string();
+		self.recordings
+			.write(&name, &Recording { method, params: Value::Null, response: message })
+	}
+}
+
+#[async_trait::async_trait]
+impl<R: TransportReceiverT + Send> TransportReceiverT for RecordingReceiver<R> {
+	type Error = Error;
+
+	async fn receive(&mut self) -> Result<ReceivedMessage, Error> {
+		let message = self.inner.receive().await.map_err(transport_error)?;
+		let recorded = match &message {
+			ReceivedMessage::Text(text) => self.record(text.as_bytes()),
+			ReceivedMessage::Bytes(bytes) => self.record(bytes),
+			ReceivedMessage::Pong => Ok(()),
+		};
+		if let Err(e) = recorded {
+			log::error!(target: "hyperspace", "{e}");
+		}
+		Ok(message)
+	}
+}
+
+/// Answers the calls of a replayed client from the recordings.
+struct ReplaySender {
+	recordings: Recordings,
+	answers: mpsc::UnboundedSender<String>,
+	/// Number of subscriptions opened so far
+	subscriptions: usize,
+}
+
+impl ReplaySender {
+	fn answer(&self, message: Value) -> Result<(), Error> {
+		self.answers
+			.unbounded_send(message.to_string())
+			.map_err(|_| Error::Custom("The replayed client was closed".to_string()))
+	}
+
+	/// Sends the notifications recorded for the subscription opened by the call recorded as
+	/// `call`, then closes it. A subscription without notifications stays open.
+	fn replay_notifications(&self, call: &str, subscription: &Value) -> Result<(), Error> {
+		let mut method = None;
+		for index in 0.. {
+			let Some(Recording { response: mut notification, .. }) =
+				self.recordings.read(&notification_name(call, index))?
+			else {
+				break
+			};
+			notification["params"]["subscription"] = subscription.clone();
+			method = notification.get("method").cloned();
+			self.answer(notification)?;
+		}
+		if let Some(method) = method {
+			self.answer(json!({
+				"jsonrpc": "2.0",
+				"method": method,
+				"params": { "subscription": subscription, "error": "No more recorded notifications" },
+			}))?;
+		}
+		Ok(())
+	}
+}
+
+#[async_trait::async_trait]
+impl TransportSenderT for ReplaySender {
+	type Error = Error;
+
+	async fn send(&mut self, msg: String) -> Result<(), Error> {
+		let call = Call::parse(&msg)?;
+		if is_unsubscribe(&call.method) {
+			return self.answer(json!({ "jsonrpc": "2.0", "id": call.id, "result": true }))
+		}
+		let name = self.recordings.next_call(&call.method, &call.params);
+		let Some(Recording { response: mut answer, .. }) = self.recordings.read(&name)? else {
+			let message = format!(
+				"No recording of {} with params {} at {}",
+				call.method,
+				call.params,
+				self.recordings.path(&name).display()
+			);
+			log::error!(target: "hyperspace", "{message}");
+			return self.answer(json!({
+				"jsonrpc": "2.0",
+				"id": call.id,
+				"error": { "code": NOT_RECORDED_CODE, "message": message },
+			}))
+		};
+		answer["id"] = call.id;
+		if !is_subscribe(&call.method) || answer.get("result").is_none() {
+			return self.answer(answer)
+		}
+		// the subscriptions of the recording may have shared an id, replayed ones can't
+		self.subscriptions += 1;
+		let subscription = json!(format!("replayed-{}", self.subscriptions));
+		answer["result"] = subscription.clone();
+		self.answer(answer)?;
+		self.replay_notifications(&name, &subscription)
+	}
+}
+
+/// Receives the answers of a [`ReplaySender`].
+struct ReplayReceiver(mpsc::UnboundedReceiver<String>);
+
+#[async_trait::async_trait]
+impl TransportReceiverT for ReplayReceiver {
+	type Error = Error;
+
+	async fn receive(&mut self) -> Result<ReceivedMessage, Error> {
+		self.0
+			.next()
+			.await
+			.map(ReceivedMessage::Text)
+			.ok_or_else(|| Error::Custom("The replayed client was closed".to_string()))
+	}
+}
+
+async fn ws_transport(
+	url: &str,
+) -> Result<(impl TransportSenderT + Send, impl TransportReceiverT + Send), Error> {
+	let uri = url
+		.parse::<Uri>()
+		.map_err(|e| Error::Custom(format!("Invalid url {url}: {e}")))?;
+	WsTransportClientBuilder::default()
+		.build(uri)
+		.await
+		.map_err(|e| Error::Custom(format!("Failed to connect to {url}: {e}")))
+}
+
+/// Connects a jsonrpsee client to the node at `url`, whose calls are recorded or replayed as
+/// `transport` when [`RecordMode::from_env`] is set.
+pub async fn connect(url: &str, transport: &str) -> Result<Client, Error> {
+	connect_with(RecordMode::from_env()?.as_ref(), url, transport).await
+}
+
+/// Connects a jsonrpsee client to the node at `url`, whose calls are recorded or replayed as
+/// `transport` in `mode`. A replayed client doesn't connect to the node.
+pub async fn connect_with(
+	mode: Option<&RecordMode>,
+	url: &str,
+	transport: &str,
+) -> Result<Client, Error> {
+	let Some(mode) = mode else {
+		let (sender, receiver) = ws_transport(url).await?;
+		return Ok(ClientBuilder::default().build_with_tokio(sender, receiver))
+	};
+	let recordings = Recordings::open(mode, transport);
+	if recordings.is_replay() {
+		let (answers, received) = mpsc::unbounded();
+		let sender = ReplaySender { recordings, answers, subscriptions: 0 };
+		return Ok(ClientBuilder::default()
+			.max_notifs_per_subscription(REPLAYED_NOTIFICATIONS_CAPACITY)
+			.build_with_tokio(sender, ReplayReceiver(received)))
+	}
+	let (sender, receiver) = ws_transport(url).await?;
+	let state = Arc::new(Mutex::new(RecorderState::default()));
+	let sender =
+		RecordingSender { inner: sender, recordings: recordings.clone(), state: state.clone() };
+	let receiver = RecordingReceiver { inner: receiver, recordings, state };
+	Ok(ClientBuilder::default().build_with_tokio(sender, receiver))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use jsonrpsee::{
+		server::{ServerBuilder, ServerHandle},
+		RpcModule,
+	};
+	use jsonrpsee_core::{
+		client::{ClientT, SubscriptionClientT},
+		rpc_params,
+	};
+	use std::{
+		net::{SocketAddr, TcpListener},
+		sync::atomic::{AtomicU64, Ordering},
+	};
+
+	/// Serves a node on `addr` whose finalized head advances on each query, and whose
+	/// subscription of new heads yields heads 10 to 12.
+	async fn serve_node(addr: SocketAddr) -> ServerHandle {
+		let server = ServerBuilder::default().build(addr).await.unwrap();
+		let mut module = RpcModule::new(AtomicU64::new(0));
+		module
+			.register_method("chain_getFinalizedHead", |_, head| {
+				Ok(head.fetch_add(1, Ordering::SeqCst))
+			})
+			.unwrap();
+		module
+			.register_method("state_getStorage", |params, _| {
+				let key: String = params.one()?;
+				Ok(format!("value of {key}"))
+			})
+			.unwrap();
+		module
+			.register_subscription(
+				"chain_subscribeNewHeads",
+				"chain_newHead",
+				"chain_unsubscribeNewHeads",
+				|_, mut sink, _| {
+					sink.accept()?;
+					tokio::spawn(async move {
+						for head in 10..13u64 {
+							sink.send(&head).unwrap();
+						}
+						// the subscription stays open until the node stops
+						futures::future::pending::<()>().await;
+						drop(sink);
+					});
+					Ok(())
+				},
+			)
+			.unwrap();
+		server.start(module).unwrap()
+	}
+
+	/// Makes the calls of a relayer run, returning their responses.
+	async fn run(client: &Client) -> Result<Vec<u64>, jsonrpsee_core::Error> {
+		let mut responses = vec![];
+		for _ in 0..2 {
+			responses.push(client.request("chain_getFinalizedHead", rpc_params![]).await?);
+		}
+		let value: String = client.request("state_getStorage", rpc_params!["0x01"]).await?;
+		assert_eq!(value, "value of 0x01");
+		let mut heads = client
+			.subscribe::<u64, _>(
+				"chain_subscribeNewHeads",
+				rpc_params![],
+				"chain_unsubscribeNewHeads",
+			)
+			.await?;
+		for _ in 0..3 {
+			responses.push(heads.next().await.expect("The subscription ended")?);
+		}
+		Ok(responses)
+	}
+
+	#[tokio::test]
+	async fn recorded_calls_are_replayed_without_the_node() {
+		let dir = std::env::temp_dir().join(format!("hyperspace-replay-{}", std::process::id()));
+		let _ = fs::remove_dir_all(&dir);
+		let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+		let url = format!("ws://{addr}");
+
+		let node = serve_node(addr).await;
+		let record = RecordMode::Record(dir.clone());
+		let recorder = connect_with(Some(&record), &url, "chain_a").await.unwrap();
+		let recorded = run(&recorder).await.unwrap();
+		assert_eq!(recorded, [0, 1, 10, 11, 12]);
+		node.stop().unwrap();
+		node.stopped().await;
+
+		// the node is down, so the calls can only be answered from the recordings
+		let replay = RecordMode::Replay(dir.clone());
+		let replayer = connect_with(Some(&replay), &url, "chain_a").await.unwrap();
+		assert_eq!(run(&replayer).await.unwrap(), recorded);
+
+		// the calls beyond the recorded ones fail rather than waiting for the node
+		let Err(error) = replayer
+			.subscribe::<u64, _>(
+				"chain_subscribeNewHeads",
+				rpc_params![],
+				"chain_unsubscribeNewHeads",
+			)
+			.await
+		else {
+			panic!("A subscription that wasn't recorded was replayed")
+		};
+		assert!(error.to_string().contains("No recording of chain_subscribeNewHeads"), "{error}");
+		let error = replayer
+			.request::<u64, _>("chain_getFinalizedHead", rpc_params![])
+			.await
+			.unwrap_err();
+		assert!(error.to_string().contains("No recording of chain_getFinalizedHead"), "{error}");
+		let other = connect_with(Some(&replay), &url, "chain_b").await.unwrap();
+		let error = other
+			.request::<String, _>("state_getStorage", rpc_params!["0x01"])
+			.await
+			.unwrap_err();
+		assert!(error.to_string().contains("No recording of state_getStorage"), "{error}");
+
+		fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[tokio::test]
+	async fn replayed_subscriptions_end_after_the_recorded_notifications() {
+		let dir =
+			std::env::temp_dir().join(format!("hyperspace-replay-end-{}", std::process::id()));
+		let _ = fs::remove_dir_all(&dir);
+		let recordings = Recordings::open(&RecordMode::Record(dir.clone()), "chain_a");
+		let call = recordings.next_call("chain_subscribeNewHeads", &Value::Null);
+		let response = json!({ "jsonrpc": "2.0", "result": "sub" });
+		let recording =
+			Recording { method: "chain_subscribeNewHeads".into(), params: Value::Null, response };
+		recordings.write(&call, &recording).unwrap();
+		for (index, head) in [10u64, 11].into_iter().enumerate() {
+			let response = json!({
+				"jsonrpc": "2.0",
+				"method": "chain_newHead",
+				"params": { "subscription": "sub", "result": head },
+			});
+			let recording =
+				Recording { method: "chain_newHead".into(), params: Value::Null, response };
+			recordings.write(&notification_name(&call, index), &recording).unwrap();
+		}
+
+		let replay = RecordMode::Replay(dir.clone());
+		let replayer = connect_with(Some(&replay), "ws://127.0.0.1:1", "chain_a").await.unwrap();
+		let heads = replayer
+			.subscribe::<u64, _>(
+				"chain_subscribeNewHeads",
+				rpc_params![],
+				"chain_unsubscribeNewHeads",
+			)
+			.await
+			.unwrap();
+		let heads = heads.map(Result::unwrap).collect::<Vec<_>>().await;
+		assert_eq!(heads, [10, 11]);
+
+		fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn mode_is_set_by_one_variable() {
+		assert_eq!(RecordMode::from_vars(None, None).unwrap(), None);
+		assert_eq!(
+			RecordMode::from_vars(Some("rec".into()), None).unwrap(),
+			Some(RecordMode::Record("rec".into()))
+		);
+		assert_eq!(
+			RecordMode::from_vars(None, Some("rec".into())).unwrap(),
+			Some(RecordMode::Replay("rec".into()))
+		);
+		assert!(RecordMode::from_vars(Some("a".into()), Some("b".into())).is_err());
+	}
+}
@@ -0,0 +1,637 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Signers of the transactions submitted by the relayer. The keys are either held in process, or
+//! by a remote signer so that they don't need to be written in the config.
+//!
+//! The remote signer exposes a JSON API over HTTPS or HTTP, authenticated with an optional bearer
+//! token:
+//! - `POST {url}/public_key` with `{"key_id": ...}` returns `{"public_key": <hex>}`
+//! - `POST {url}/sign` with `{"key_id": ..., "payload": <hex>}` returns `{"signature": <hex>}`
+
+use crate::error::Error;
+use hyper::{client::HttpConnector, header, Body, Client, Method, Request, StatusCode};
+use hyper_rustls::HttpsConnector;
+use ibc::{
+	core::{
+		ics02_client::msgs::{
+			create_client::TYPE_URL as CREATE_CLIENT_TYPE_URL,
+			update_client::TYPE_URL as UPDATE_CLIENT_TYPE_URL,
+		},
+		ics03_connection::msgs::{
+			conn_open_ack::TYPE_URL as CONN_OPEN_ACK_TYPE_URL,
+			conn_open_confirm::TYPE_URL as CONN_OPEN_CONFIRM_TYPE_URL,
+			conn_open_init::TYPE_URL as CONN_OPEN_INIT_TYPE_URL,
+			conn_open_try::TYPE_URL as CONN_OPEN_TRY_TYPE_URL,
+		},
+		ics04_channel::msgs::{
+			acknowledgement::TYPE_URL as ACKNOWLEDGEMENT_TYPE_URL,
+			chan_close_confirm::TYPE_URL as CHAN_CLOSE_CONFIRM_TYPE_URL,
+			chan_close_init::TYPE_URL as CHAN_CLOSE_INIT_TYPE_URL,
+			chan_open_ack::TYPE_URL as CHAN_OPEN_ACK_TYPE_URL,
+			chan_open_confirm::TYPE_URL as CHAN_OPEN_CONFIRM_TYPE_URL,
+			chan_open_init::TYPE_URL as CHAN_OPEN_INIT_TYPE_URL,
+			chan_open_try::TYPE_URL as CHAN_OPEN_TRY_TYPE_URL,
+			recv_packet::TYPE_URL as RECV_PACKET_TYPE_URL, timeout::TYPE_URL as TIMEOUT_TYPE_URL,
+			timeout_on_close::TYPE_URL as TIMEOUT_ON_CLOSE_TYPE_URL,
+		},
+	},
+	signer::Signer,
+};
+use ibc_proto::{
+	google::protobuf::Any,
+	ibc::core::{
+		channel::v1::{
+			MsgAcknowledgement, MsgChannelCloseConfirm, MsgChannelCloseInit, MsgChannelOpenAck,
+			MsgChannelOpenConfirm, MsgChannelOpenInit, MsgChannelOpenTry, MsgRecvPacket,
+			MsgTimeout, MsgTimeoutOnClose,
+		},
+		client::v1::{MsgCreateClient, MsgUpdateClient},
+		connection::v1::{
+			MsgConnectionOpenAck, MsgConnectionOpenConfirm, MsgConnectionOpenInit,
+			MsgConnectionOpenTry,
+		},
+	},
+};
+use prost::Message;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+	fmt::{self, Debug},
+	path::{Path, PathBuf},
+	sync::{Arc, RwLock},
+	time::Duration,
+};
+
+/// Time after which a request to the remote signer is abandoned.
+const REMOTE_SIGNER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Signer of the transactions of a chain, set by the `signer` field of its config.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SignerConfig {
+	/// Signs with the key of the chain config
+	#[default]
+	Local,
+	/// Signs with a key held by a remote signer
+	Remote(RemoteSignerConfig),
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoteSignerConfig {
+	/// Base url of the remote signer
+	pub url: String,
+	/// Id of the key in the remote signer
+	pub key_id: String,
+	/// Token sent as a bearer token in the `authorization` header of the requests
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub auth_token: Option<String>,
+}
+
+impl Debug for RemoteSignerConfig {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("RemoteSignerConfig")
+			.field("url", &self.url)
+			.field("key_id", &self.key_id)
+			.field("auth_token", &self.auth_token.as_ref().map(|_| "<redacted>"))
+			.finish()
+	}
+}
+
+/// Signs the transactions of the relayer account.
+#[async_trait::async_trait]
+pub trait TransactionSigner: Debug + Send + Sync {
+	/// Public key of the account, in the encoding of the chain.
+	fn public_key(&self) -> Vec<u8>;
+
+	/// Signs the bytes covered by a transaction signature: the sign doc on cosmos chains, the
+	/// signer payload on substrate chains, which is hashed if longer than 256 bytes.
+	async fn sign(&self, payload: &[u8]) -> Result<Vec<u8>, Error>;
+}
+
+/// Signer of the relayer's transactions along with the account it signs for, shared between the
+/// clones of a client so that its key can be rotated while the relayer runs.
+///
+/// A transaction is signed with the signer and account it read when it was assembled, so the
+/// transactions being signed during a rotation keep the previous key.
+pub struct RotatingSigner<A>(Arc<RwLock<(Arc<dyn TransactionSigner>, A)>>);
+
+impl<A: Clone> RotatingSigner<A> {
+	pub fn new(signer: Arc<dyn TransactionSigner>, account: A) -> Self {
+		Self(Arc::new(RwLock::new((signer, account))))
+	}
+
+	/// The signer of the next transactions and the account it signs for.
+	pub fn current(&self) -> (Arc<dyn TransactionSigner>, A) {
+		self.0.read().unwrap().clone()
+	}
+
+	pub fn account(&self) -> A {
+		self.0.read().unwrap().1.clone()
+	}
+
+	/// Signs the next transactions with `signer` for `account`, returning the previous account.
+	pub fn rotate(&self, signer: Arc<dyn TransactionSigner>, account: A) -> A {
+		let mut current = self.0.write().unwrap();
+		std::mem::replace(&mut *current, (signer, account)).1
+	}
+}
+
+impl<A> Clone for RotatingSigner<A> {
+	fn clone(&self) -> Self {
+		Self(self.0.clone())
+	}
+}
+
+impl<A: Debug> Debug for RotatingSigner<A> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let current = self.0.read().unwrap();
+		f.debug_struct("RotatingSigner")
+			.field("signer", &current.0)
+			.field("account", &current.1)
+			.finish()
+	}
+}
+
+/// Key a chain is rotated to by [`crate::KeyProvider::rotate_key`], referring to a key the
+/// operator set up beforehand: a key of a remote signer, or a file of the key directory holding
+/// the secret. The secret itself is never accepted, so that it isn't sent to the relayer or
+/// passed on a command line.
+///
+/// The secret of a file is in the format of the key of the chain config, e.g. the `private_key`
+/// of parachains or the `mnemonic` of cosmos chains.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeySource {
+	File(PathBuf),
+	Remote(RemoteSignerConfig),
+}
+
+impl KeySource {
+	/// Parses `remote:<key id>@<url>` for a key of a remote signer, or the name of a file of
+	/// `key_dir` otherwise. Names that could refer to a file outside of `key_dir` are rejected.
+	///
+	/// The reference carries no auth token, which would be exposed with it, so the remote signers
+	/// requiring one can't be rotated to.
+	pub fn parse(reference: &str, key_dir: Option<&Path>) -> Result<Self, Error> {
+		if let Some(remote) = reference.strip_prefix("remote:") {
+			let (key_id, url) = remote.split_once('@').ok_or_else(|| {
+				Error::Custom(format!("Remote key {remote:?} isn't in the <key id>@<url> format"))
+			})?;
+			return Ok(Self::Remote(RemoteSignerConfig {
+				url: url.to_string(),
+				key_id: key_id.to_string(),
+				auth_token: None,
+			}))
+		}
+		let is_key_name = !reference.is_empty() &&
+			!reference.starts_with('.') &&
+			reference
+				.chars()
+				.all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+		if !is_key_name {
+			return Err(Error::Custom(format!(
+				"Key {reference:?} isn't the name of a file of the key directory"
+			)))
+		}
+		let key_dir = key_dir.ok_or_else(|| {
+			Error::Custom(format!("Key {reference:?} can't be read without a key directory"))
+		})?;
+		Ok(Self::File(key_dir.join(reference)))
+	}
+
+	/// The secret of a key file.
+	pub fn secret(&self) -> Result<String, Error> {
+		match self {
+			Self::File(path) => std::fs::read_to_string(path)
+				.map(|secret| secret.trim().to_string())
+				.map_err(|e| Error::Custom(format!("Failed to read key {}: {e}", path.display()))),
+			Self::Remote(_) => Err(Error::Custom("A remote key has no secret".to_string())),
+		}
+	}
+}
+
+/// Applies `f` to the signer named in `msg` if it's an IBC message, returning its result along
+/// with the message encoded again.
+fn with_message_signer<T>(msg: &Any, f: impl FnOnce(&mut String) -> T) -> Option<(T, Vec<u8>)> {
+	macro_rules! signed_messages {
+		($($type_url:path => $msg:ty),* $(,)?) => {
+			match msg.type_url.as_str() {
+				$($type_url => {
+					let mut decoded = <$msg>::decode(msg.value.as_slice()).ok()?;
+					let result = f(&mut decoded.signer);
+					Some((result, decoded.encode_to_vec()))
+				},)*
+				_ => None,
+			}
+		};
+	}
+	signed_messages! {
+		CREATE_CLIENT_TYPE_URL => MsgCreateClient,
+		UPDATE_CLIENT_TYPE_URL => MsgUpdateClient,
+		CONN_OPEN_INIT_TYPE_URL => MsgConnectionOpenInit,
+		CONN_OPEN_TRY_TYPE_URL => MsgConnectionOpenTry,
+		CONN_OPEN_ACK_TYPE_URL => MsgConnectionOpenAck,
+		CONN_OPEN_CONFIRM_TYPE_URL => MsgConnectionOpenConfirm,
+		CHAN_OPEN_INIT_TYPE_URL => MsgChannelOpenInit,
+		CHAN_OPEN_TRY_TYPE_URL => MsgChannelOpenTry,
+		CHAN_OPEN_ACK_TYPE_URL => MsgChannelOpenAck,
+		CHAN_OPEN_CONFIRM_TYPE_URL => MsgChannelOpenConfirm,
+		CHAN_CLOSE_INIT_TYPE_URL => MsgChannelCloseInit,
+		CHAN_CLOSE_CONFIRM_TYPE_URL => MsgChannelCloseConfirm,
+		RECV_PACKET_TYPE_URL => MsgRecvPacket,
+		ACKNOWLEDGEMENT_TYPE_URL => MsgAcknowledgement,
+		TIMEOUT_TYPE_URL => MsgTimeout,
+		TIMEOUT_ON_CLOSE_TYPE_URL => MsgTimeoutOnClose,
+	}
+}
+
+/// The signer named in `msg`, `None` if it isn't an IBC message.
+pub fn message_signer(msg: &Any) -> Option<String> {
+	with_message_signer(msg, |signer| signer.clone()).map(|(signer, _)| signer)
+}
+
+/// Names `signer` as the signer of `msg` if it's an IBC message, so that the messages assembled
+/// before a rotation of the key are signed for by the new account. Other messages are returned
+/// as is.
+pub fn set_message_signer(msg: Any, signer: &str) -> Any {
+	match with_message_signer(&msg, |named| *named = signer.to_string()) {
+		Some((_, value)) => Any { type_url: msg.type_url, value },
+		None => msg,
+	}
+}
+
+/// Accounts of a rotated key, returned by [`crate::KeyProvider::rotate_key`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyRotation {
+	pub old_account: Signer,
+	pub new_account: Signer,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PublicKeyRequest {
+	key_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PublicKeyResponse {
+	public_key: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SignRequest {
+	key_id: String,
+	payload: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SignResponse {
+	signature: String,
+}
+
+/// [`TransactionSigner`] of a key held by a remote signer.
+#[derive(Debug, Clone)]
+pub struct RemoteSigner {
+	client: Client<HttpsConnector<HttpConnector>>,
+	config: RemoteSignerConfig,
+	public_key: Vec<u8>,
+}
+
+impl RemoteSigner {
+	/// Fetches the public key of the configured key from the remote signer.
+	pub async fn connect(config: RemoteSignerConfig) -> Result<Self, Error> {
+		let connector = hyper_rustls::HttpsConnectorBuilder::new()
+			.with_native_roots()
+			.https_or_http()
+			.enable_http1()
+			.build();
+		let client = Client::builder().build(connector);
+		let request = PublicKeyRequest { key_id: config.key_id.clone() };
+		let response: PublicKeyResponse = post(&client, &config, "public_key", &request).await?;
+		let public_key = decode_hex(&response.public_key)?;
+		Ok(Self { client, config, public_key })
+	}
+}
+
+#[async_trait::async_trait]
+impl TransactionSigner for RemoteSigner {
+	fn public_key(&self) -> Vec<u8> {
+		self.public_key.clone()
+	}
+
+	async fn sign(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+		let request =
+			SignRequest { key_id: self.config.key_id.clone(), payload: hex::encode(payload) };
+		let response: SignResponse = post(&self.client, &self.config, "sign", &request).await?;
+		decode_hex(&response.signature)
+	}
+}
+
+fn decode_hex(value: &str) -> Result<Vec<u8>, Error> {
+	hex::decode(value.trim_start_matches("0x"))
+		.map_err(|e| Error::Custom(format!("Invalid remote signer response: {e}")))
+}
+
+/// Sends `request` to the `method` endpoint of the remote signer of `config`. Transport failures,
+/// timeouts and server errors are reported as [`Error::RemoteSignerUnavailable`], while rejected
+/// requests aren't retryable.
+async fn post<Req: Serialize, Res: DeserializeOwned>(
+	client: &Client<HttpsConnector<HttpConnector>>,
+	config: &RemoteSignerConfig,
+	method: &str,
+	request: &Req,
+) -> Result<Res, Error> {
+	let body = serde_json::to_vec(request).map_err(|e| Error::Custom(e.to_string()))?;
+	let mut request = Request::builder()
+		.method(Method::POST)
+		.uri(format!("{}/{method}", config.url.trim_end_matches('/')))
+		.header(header::CONTENT_TYPE, "application/json");
+	if let Some(token) = &config.auth_token {
+		request = request.header(header::AUTHORIZATION, format!("Bearer {token}"));
+	}
+	let request = request
+		.body(Body::from(body))
+		.map_err(|e| Error::Custom(format!("Invalid remote signer request: {e}")))?;
+	let unavailable = Error::RemoteSignerUnavailable;
+	let (status, body) = tokio::time::timeout(REMOTE_SIGNER_TIMEOUT, async {
+		let response = client.request(request).await?;
+		let status = response.status();
+		let body = hyper::body::to_bytes(response.into_body()).await?;
+		Ok::<_, hyper::Error>((status, body))
+	})
+	.await
+	.map_err(|_| unavailable("request timed out".to_string()))?
+	.map_err(|e| unavailable(e.to_string()))?;
+
+	let message = || format!("{status} {}", String::from_utf8_lossy(&body));
+	if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+		return Err(unavailable(message()))
+	}
+	if !status.is_success() {
+		return Err(Error::Custom(format!("Remote signer rejected the request: {}", message())))
+	}
+	serde_json::from_slice(&body)
+		.map_err(|e| Error::Custom(format!("Invalid remote signer response: {e}")))
+}
+
+/// Serves the keys of in-process signers over the remote signer API on a local port, returning
+/// its url. The requests without `auth_token`, if given, are rejected.
+#[cfg(any(feature = "testing", test))]
+pub async fn serve_mock_remote_signer(
+	keys: std::collections::HashMap<String, std::sync::Arc<dyn TransactionSigner>>,
+	auth_token: Option<String>,
+) -> Result<(String, tokio::task::JoinHandle<()>), Error> {
+	use hyper::{
+		service::{make_service_fn, service_fn},
+		Response, Server,
+	};
+	use std::{convert::Infallible, sync::Arc};
+
+	async fn handle(
+		keys: Arc<std::collections::HashMap<String, Arc<dyn TransactionSigner>>>,
+		auth_token: Option<Arc<str>>,
+		request: Request<Body>,
+	) -> Result<Response<Body>, Error> {
+		if let Some(token) = auth_token {
+			let authorization = request.headers().get(header::AUTHORIZATION);
+			if authorization.and_then(|value| value.to_str().ok()) !=
+				Some(format!("Bearer {token}").as_str())
+			{
+				return Response::builder()
+					.status(StatusCode::UNAUTHORIZED)
+					.body(Body::from("invalid auth token"))
+					.map_err(|e| Error::Custom(e.to_string()))
+			}
+		}
+		let method = request.uri().path().trim_start_matches('/').to_string();
+		let body = hyper::body::to_bytes(request.into_body())
+			.await
+			.map_err(|e| Error::Custom(e.to_string()))?;
+		let response = match method.as_str() {
+			"public_key" => serde_json::from_slice::<PublicKeyRequest>(&body)
+				.ok()
+				.and_then(|request| keys.get(&request.key_id).cloned())
+				.map(|signer| {
+					serde_json::to_vec(&PublicKeyResponse {
+						public_key: hex::encode(signer.public_key()),
+					})
+				}),
+			"sign" => match serde_json::from_slice::<SignRequest>(&body)
+				.ok()
+				.and_then(|request| Some((keys.get(&request.key_id).cloned()?, request.payload)))
+			{
+				Some((signer, payload)) => {
+					let signature = signer.sign(&hex::decode(payload)?).await?;
+					Some(serde_json::to_vec(&SignResponse { signature: hex::encode(signature) }))
+				},
+				None => None,
+			},
+			_ => None,
+		};
+		let (status, body) = match response {
+			Some(body) => (StatusCode::OK, body.map_err(|e| Error::Custom(e.to_string()))?),
+			None => (StatusCode::NOT_FOUND, b"unknown key".to_vec()),
+		};
+		Response::builder()
+			.status(status)
+			.body(Body::from(body))
+			.map_err(|e| Error::Custom(e.to_string()))
+	}
+
+	let keys = Arc::new(keys);
+	let auth_token = auth_token.map(Arc::<str>::from);
+	let service = make_service_fn(move |_| {
+		let keys = keys.clone();
+		let auth_token = auth_token.clone();
+		async move {
+			Ok::<_, Infallible>(service_fn(move |request| {
+				let keys = keys.clone();
+				let auth_token = auth_token.clone();
+				async move {
+					Ok::<_, Infallible>(handle(keys, auth_token, request).await.unwrap_or_else(
+						|e| {
+							let mut response = Response::new(Body::from(e.to_string()));
+							*response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+							response
+						},
+					))
+				}
+			}))
+		}
+	});
+	let server = Server::try_bind(&([127, 0, 0, 1], 0).into())
+		.map_err(|e| Error::Custom(e.to_string()))?
+		.serve(service);
+	let url = format!("http://{}", server.local_addr());
+	let handle = tokio::spawn(async move {
+		if let Err(e) = server.await {
+			log::error!(target: "hyperspace", "Mock remote signer failed: {e}");
+		}
+	});
+	Ok((url, handle))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{HasErrorKind, RetryPolicy};
+	use std::{collections::HashMap, sync::Arc};
+
+	/// Signs by prefixing the payload with its key.
+	#[derive(Debug)]
+	struct PrefixSigner(Vec<u8>);
+
+	#[async_trait::async_trait]
+	impl TransactionSigner for PrefixSigner {
+		fn public_key(&self) -> Vec<u8> {
+			self.0.clone()
+		}
+
+		async fn sign(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+			Ok([&self.0[..], payload].concat())
+		}
+	}
+
+	async fn serve_relayer_key() -> (RemoteSignerConfig, tokio::task::JoinHandle<()>) {
+		let signer: Arc<dyn TransactionSigner> = Arc::new(PrefixSigner(vec![1, 2, 3]));
+		let keys = HashMap::from([("relayer".to_string(), signer)]);
+		let auth_token = "secret".to_string();
+		let (url, handle) = serve_mock_remote_signer(keys, Some(auth_token.clone())).await.unwrap();
+		(
+			RemoteSignerConfig { url, key_id: "relayer".to_string(), auth_token: Some(auth_token) },
+			handle,
+		)
+	}
+
+	#[test]
+	fn signer_configs_are_tagged_by_type() {
+		let config: SignerConfig = serde_json::from_str(
+			r#"{"type": "remote", "url": "http://127.0.0.1:8000", "key_id": "relayer"}"#,
+		)
+		.unwrap();
+		assert_eq!(
+			config,
+			SignerConfig::Remote(RemoteSignerConfig {
+				url: "http://127.0.0.1:8000".to_string(),
+				key_id: "relayer".to_string(),
+				auth_token: None,
+			})
+		);
+		let config: SignerConfig = serde_json::from_str(
+			r#"{"type": "remote", "url": "https://signer", "key_id": "relayer", "auth_token": "secret"}"#,
+		)
+		.unwrap();
+		assert_eq!(
+			config,
+			SignerConfig::Remote(RemoteSignerConfig {
+				url: "https://signer".to_string(),
+				key_id: "relayer".to_string(),
+				auth_token: Some("secret".to_string()),
+			})
+		);
+		let config: SignerConfig = serde_json::from_str(r#"{"type": "local"}"#).unwrap();
+		assert_eq!(config, SignerConfig::Local);
+	}
+
+	#[test]
+	fn key_sources_are_parsed() {
+		let key_dir = Some(Path::new("/etc/hyperspace/keys"));
+		assert_eq!(
+			KeySource::parse("remote:relayer@http://127.0.0.1:8000", None).unwrap(),
+			KeySource::Remote(RemoteSignerConfig {
+				url: "http://127.0.0.1:8000".to_string(),
+				key_id: "relayer".to_string(),
+				auth_token: None,
+			})
+		);
+		assert_eq!(
+			KeySource::parse("relayer-2.key", key_dir).unwrap(),
+			KeySource::File(PathBuf::from("/etc/hyperspace/keys/relayer-2.key"))
+		);
+		assert!(KeySource::parse("remote:http://127.0.0.1:8000", None).is_err());
+		// only the files of the key directory can be named, and secrets aren't taken as is
+		assert!(KeySource::parse("relayer", None).is_err());
+		for reference in ["/etc/passwd", "../passwd", "..", "", "mnemonic:bottom drive obey"] {
+			assert!(KeySource::parse(reference, key_dir).is_err(), "{reference}");
+		}
+	}
+
+	#[test]
+	fn message_signers_are_replaced() {
+		let msg = MsgRecvPacket { signer: "old".to_string(), ..Default::default() };
+		let msg = Any { type_url: RECV_PACKET_TYPE_URL.to_string(), value: msg.encode_to_vec() };
+		assert_eq!(message_signer(&msg).as_deref(), Some("old"));
+		let msg = set_message_signer(msg, "new");
+		assert_eq!(msg.type_url, RECV_PACKET_TYPE_URL);
+		assert_eq!(message_signer(&msg).as_deref(), Some("new"));
+
+		let other = Any { type_url: "/cosmos.bank.v1beta1.MsgSend".to_string(), value: vec![1] };
+		assert_eq!(message_signer(&other), None);
+		assert_eq!(set_message_signer(other.clone(), "new"), other);
+	}
+
+	#[tokio::test]
+	async fn rotated_signers_are_shared_between_clones() {
+		let signer = RotatingSigner::new(Arc::new(PrefixSigner(vec![1])), "old".to_string());
+		let clone = signer.clone();
+		let (in_flight, _) = signer.current();
+		assert_eq!(clone.rotate(Arc::new(PrefixSigner(vec![2])), "new".to_string()), "old");
+		assert_eq!(in_flight.sign(&[0]).await.unwrap(), vec![1, 0]);
+		let (current, account) = signer.current();
+		assert_eq!(account, "new");
+		assert_eq!(current.sign(&[0]).await.unwrap(), vec![2, 0]);
+	}
+
+	#[tokio::test]
+	async fn remote_signers_sign_with_the_served_key() {
+		let (config, handle) = serve_relayer_key().await;
+		let signer = RemoteSigner::connect(config).await.unwrap();
+		assert_eq!(signer.public_key(), vec![1, 2, 3]);
+		assert_eq!(signer.sign(&[4, 5]).await.unwrap(), vec![1, 2, 3, 4, 5]);
+		handle.abort();
+	}
+
+	#[tokio::test]
+	async fn unknown_keys_are_rejected() {
+		let (mut config, handle) = serve_relayer_key().await;
+		config.key_id = "unknown".to_string();
+		let error = RemoteSigner::connect(config).await.unwrap_err();
+		assert!(error.to_string().contains("404"), "{error}");
+		assert_eq!(error.kind().retry_policy(), RetryPolicy::Fail);
+		handle.abort();
+	}
+
+	#[tokio::test]
+	async fn requests_without_the_auth_token_are_rejected() {
+		let (mut config, handle) = serve_relayer_key().await;
+		config.auth_token = Some("wrong".to_string());
+		let error = RemoteSigner::connect(config.clone()).await.unwrap_err();
+		assert!(error.to_string().contains("401"), "{error}");
+		assert_eq!(error.kind().retry_policy(), RetryPolicy::Fail);
+
+		config.auth_token = None;
+		let error = RemoteSigner::connect(config).await.unwrap_err();
+		assert!(error.to_string().contains("401"), "{error}");
+		handle.abort();
+	}
+
+	#[tokio::test]
+	async fn unreachable_remote_signers_are_retried() {
+		let (config, handle) = serve_relayer_key().await;
+		handle.abort();
+		let _ = handle.await;
+
+		let error = RemoteSigner::connect(config).await.unwrap_err();
+		assert!(matches!(error, Error::RemoteSignerUnavailable(_)), "{error}");
+		assert_eq!(error.kind().retry_policy(), RetryPolicy::Retry);
+	}
+}
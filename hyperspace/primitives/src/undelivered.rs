@@ -0,0 +1,124 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A packet that wasn't received by the counterparty is delivered by a different message
+//! depending on the state of both channel ends. Packets to a channel that was closed on the
+//! counterparty can't be received anymore and are timed out on close, timed out packets can only
+//! be timed out while the channel is open on the sending chain, and the other packets can only be
+//! received while the channel is open on the counterparty.
+
+use ibc::core::ics04_channel::channel::State;
+
+/// Message delivering an undelivered packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+	/// `MsgRecvPacket` on the counterparty
+	Recv,
+	/// `MsgTimeout` on the sending chain
+	Timeout,
+	/// `MsgTimeoutOnClose` on the sending chain
+	TimeoutOnClose,
+}
+
+impl Delivery {
+	/// Returns the message delivering a packet given the state of the channel end on the sending
+	/// chain (`source`) and on the counterparty (`sink`), or `None` if the packet can't be
+	/// delivered in these states.
+	pub fn of(source: State, sink: State, timed_out: bool) -> Option<Self> {
+		match (source, sink, timed_out) {
+			(_, State::Closed, _) => Some(Self::TimeoutOnClose),
+			(State::Open, State::Open, true) => Some(Self::Timeout),
+			(_, State::Open, false) => Some(Self::Recv),
+			_ => None,
+		}
+	}
+}
+
+/// Sequences of the packets not received by the counterparty, by the message delivering them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UndeliveredInfo {
+	/// Packets to be received on the counterparty
+	pub relayable: Vec<u64>,
+	/// Packets that timed out on the counterparty
+	pub needs_timeout: Vec<u64>,
+	/// Packets to a channel closed on the counterparty
+	pub needs_timeout_on_close: Vec<u64>,
+}
+
+impl UndeliveredInfo {
+	/// Classifies `(sequence, timed_out)` pairs with [`Delivery::of`]. Packets that can't be
+	/// delivered in the current channel states are left out.
+	pub fn classify(
+		source: State,
+		sink: State,
+		packets: impl IntoIterator<Item = (u64, bool)>,
+	) -> Self {
+		let mut info = Self::default();
+		for (sequence, timed_out) in packets {
+			match Delivery::of(source, sink, timed_out) {
+				Some(Delivery::Recv) => info.relayable.push(sequence),
+				Some(Delivery::Timeout) => info.needs_timeout.push(sequence),
+				Some(Delivery::TimeoutOnClose) => info.needs_timeout_on_close.push(sequence),
+				None => {},
+			}
+		}
+		info
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const PACKETS: [(u64, bool); 2] = [(1, false), (2, true)];
+
+	fn classify(source: State, sink: State) -> UndeliveredInfo {
+		UndeliveredInfo::classify(source, sink, PACKETS)
+	}
+
+	#[test]
+	fn open_channels() {
+		assert_eq!(
+			classify(State::Open, State::Open),
+			UndeliveredInfo {
+				relayable: vec![1],
+				needs_timeout: vec![2],
+				needs_timeout_on_close: vec![]
+			}
+		);
+	}
+
+	#[test]
+	fn channel_closed_on_the_counterparty() {
+		let timeout_on_close =
+			UndeliveredInfo { needs_timeout_on_close: vec![1, 2], ..Default::default() };
+		assert_eq!(classify(State::Open, State::Closed), timeout_on_close);
+		assert_eq!(classify(State::Closed, State::Closed), timeout_on_close);
+	}
+
+	#[test]
+	fn channel_closed_on_the_sending_chain() {
+		// packets can still be received, but not timed out
+		assert_eq!(
+			classify(State::Closed, State::Open),
+			UndeliveredInfo { relayable: vec![1], ..Default::default() }
+		);
+	}
+
+	#[test]
+	fn channel_not_yet_open_on_the_counterparty() {
+		assert_eq!(classify(State::Open, State::TryOpen), UndeliveredInfo::default());
+		assert_eq!(classify(State::Open, State::Init), UndeliveredInfo::default());
+	}
+}
@@ -14,7 +14,9 @@
 
 #[cfg(any(test, feature = "testing"))]
 use crate::TestProvider;
-use crate::{mock::LocalClientTypes, Chain};
+use crate::{
+	middleware::unsupported_middleware, mock::LocalClientTypes, Chain, ChannelVersionResolver,
+};
 use futures::{future, StreamExt};
 use ibc::{
 	core::{
@@ -24,16 +26,21 @@ use ibc::{
 		ics04_channel::{
 			channel,
 			channel::{ChannelEnd, Order, State},
-			msgs::chan_open_init::MsgChannelOpenInit,
+			msgs::{chan_close_init::MsgChannelCloseInit, chan_open_init::MsgChannelOpenInit},
 		},
+		ics23_commitment::commitment::CommitmentPrefix,
 		ics24_host::identifier::{ChannelId, ClientId, ConnectionId, PortId},
 	},
 	events::IbcEvent,
 	protobuf::Protobuf,
 	tx_msg::Msg,
+	Height,
+};
+use ibc_proto::{
+	google::protobuf::Any, ibc::applications::transfer::v1::DenomTrace as RawDenomTrace,
 };
-use ibc_proto::google::protobuf::Any;
-use std::{future::Future, time::Duration};
+use sha2::{Digest, Sha256};
+use std::{fmt::Debug, future::Future, time::Duration};
 
 pub async fn timeout_future<T: Future>(future: T, secs: u64, reason: String) -> T::Output {
 	let duration = Duration::from_secs(secs);
@@ -43,6 +50,51 @@ pub async fn timeout_future<T: Future>(future: T, secs: u64, reason: String) ->
 	}
 }
 
+/// How often [`await_height_advance`] queries the latest height.
+pub const READY_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Polls `latest_height` every `poll_interval` until the height advances past the first one
+/// observed, and returns it. Query errors are retried, since a starting node may fail them.
+/// Fails after `timeout` with a description of the last observed height or error.
+pub async fn await_height_advance<F, Fut, E>(
+	mut latest_height: F,
+	timeout: Duration,
+	poll_interval: Duration,
+) -> Result<Height, String>
+where
+	F: FnMut() -> Fut,
+	Fut: Future<Output = Result<Height, E>>,
+	E: Debug,
+{
+	let mut initial = None;
+	let mut last_error = None;
+	let poll = async {
+		loop {
+			match latest_height().await {
+				Ok(height) => match initial {
+					Some(initial) if height > initial => return height,
+					Some(_) => {},
+					None => initial = Some(height),
+				},
+				Err(e) => {
+					log::debug!(target: "hyperspace", "Failed to query the latest height: {e:?}");
+					last_error = Some(e);
+				},
+			}
+			tokio::time::sleep(poll_interval).await;
+		}
+	};
+	let result = tokio::time::timeout(timeout, poll).await;
+	match result {
+		Ok(height) => Ok(height),
+		Err(_) => Err(match (initial, last_error) {
+			(Some(height), _) => format!("height stuck at {height} for {timeout:?}"),
+			(None, Some(e)) => format!("latest height query failing for {timeout:?}: {e:?}"),
+			(None, None) => format!("latest height query didn't return within {timeout:?}"),
+		}),
+	}
+}
+
 #[cfg(any(test, feature = "testing"))]
 pub async fn timeout_after<C: TestProvider, T: Future + Send + 'static>(
 	chain: &C,
@@ -97,16 +149,18 @@ pub async fn create_clients(
 	Ok((client_id_a_on_b, client_id_b_on_a))
 }
 
-/// Completes the connection handshake process
+/// Completes the connection handshake process, with `prefix` the commitment prefix of the store
+/// of chain B holding the connection.
 /// The relayer process must be running before this function is executed
 pub async fn create_connection(
 	chain_a: &mut impl Chain,
 	chain_b: &mut impl Chain,
 	delay_period: Duration,
+	prefix: CommitmentPrefix,
 ) -> Result<(ConnectionId, ConnectionId), anyhow::Error> {
 	let msg = MsgConnectionOpenInit {
 		client_id: chain_b.client_id(),
-		counterparty: Counterparty::new(chain_a.client_id(), None, chain_b.connection_prefix()),
+		counterparty: Counterparty::new(chain_a.client_id(), None, prefix),
 		version: Some(Default::default()),
 		delay_period,
 		signer: chain_a.account_id(),
@@ -116,7 +170,8 @@ pub async fn create_connection(
 
 	let tx_id = chain_a.submit(vec![msg]).await?;
 	let connection_id_a = chain_a.query_connection_id_from_tx_hash(tx_id).await?;
-	chain_a.set_connection_id(connection_id_a.clone());
+	// the connections relayed so far keep being relayed
+	chain_a.add_connection_id(connection_id_a.clone());
 
 	log::info!(target: "hyperspace", "============= Wait till both chains have completed connection handshake =============");
 
@@ -151,6 +206,9 @@ pub async fn create_connection(
 
 /// Completes the chanel handshake process
 /// The relayer process must be running before this function is executed
+///
+/// If `version_resolver` is provided, it's called with the version chain B opened the channel with
+/// in `ChanOpenTry` and its result is used as the counterparty version in `ChanOpenAck`.
 pub async fn create_channel(
 	chain_a: &mut impl Chain,
 	chain_b: &mut impl Chain,
@@ -158,6 +216,33 @@ pub async fn create_channel(
 	port_id: PortId,
 	version: String,
 	order: Order,
+	version_resolver: Option<ChannelVersionResolver>,
+) -> Result<(ChannelId, ChannelId), anyhow::Error> {
+	// The `ChanOpenAck` is submitted to chain A by the relayer task, which shares the common
+	// state with `chain_a`. The resolver only applies to this handshake.
+	let Some(version_resolver) = version_resolver else {
+		return open_channel(chain_a, chain_b, connection_id, port_id, version, order).await
+	};
+	let state = chain_a.common_state().clone();
+	state.set_channel_version_resolver(
+		connection_id.clone(),
+		port_id.clone(),
+		Some(version_resolver),
+	);
+	let result =
+		open_channel(chain_a, chain_b, connection_id.clone(), port_id.clone(), version, order)
+			.await;
+	state.set_channel_version_resolver(connection_id, port_id, None);
+	result
+}
+
+async fn open_channel(
+	chain_a: &mut impl Chain,
+	chain_b: &mut impl Chain,
+	connection_id: ConnectionId,
+	port_id: PortId,
+	version: String,
+	order: Order,
 ) -> Result<(ChannelId, ChannelId), anyhow::Error> {
 	let channel = ChannelEnd::new(
 		State::Init,
@@ -167,6 +252,16 @@ pub async fn create_channel(
 		ics04_channel::Version::new(version),
 	);
 
+	if chain_a.common_state().strict_middleware_check {
+		let unsupported = unsupported_middleware(&channel.version().to_string());
+		if !unsupported.is_empty() {
+			return Err(anyhow::anyhow!(
+				"Refusing to create channel with unsupported middleware {unsupported:?} in version {}",
+				channel.version()
+			))
+		}
+	}
+
 	let msg = MsgChannelOpenInit::new(port_id, channel, chain_a.account_id());
 
 	let msg = Any { type_url: msg.type_url(), value: msg.encode_vec()? };
@@ -191,11 +286,179 @@ pub async fn create_channel(
 	)
 	.await;
 
-	let (channel_id_a, channel_id_b) = match events.pop() {
-		Some(IbcEvent::OpenConfirmChannel(chan)) =>
-			(chan.counterparty_channel_id.unwrap(), chan.channel_id().unwrap().clone()),
+	let (channel_id_a, channel_id_b, port_id_a, port_id_b) = match events.pop() {
+		Some(IbcEvent::OpenConfirmChannel(chan)) => (
+			chan.counterparty_channel_id.unwrap(),
+			chan.channel_id().unwrap().clone(),
+			chan.counterparty_port_id.clone(),
+			chan.port_id.clone(),
+		),
 		got => panic!("Last event should be OpenConfirmChannel: {got:?}"),
 	};
 
+	let version_a = query_channel_version(&*chain_a, channel_id_a, port_id_a).await?;
+	let version_b = query_channel_version(&*chain_b, channel_id_b, port_id_b).await?;
+	log::info!(
+		target: "hyperspace",
+		"Negotiated channel version: {version_a} on {}, {version_b} on {}",
+		chain_a.name(), chain_b.name()
+	);
+
 	Ok((channel_id_a, channel_id_b))
 }
+
+/// Closes `channel_id` on chain A and waits for the relayer task to close its counterparty on
+/// chain B. Returns the counterparty channel.
+pub async fn close_channel(
+	chain_a: &mut impl Chain,
+	chain_b: &impl Chain,
+	channel_id: ChannelId,
+	port_id: PortId,
+) -> Result<ChannelId, anyhow::Error> {
+	let (latest_height, ..) = chain_a.latest_height_and_timestamp().await?;
+	let channel = chain_a
+		.query_channel_end(latest_height, channel_id, port_id.clone())
+		.await?
+		.channel
+		.ok_or_else(|| {
+			anyhow::anyhow!("Channel {channel_id}/{port_id} not found on {}", chain_a.name())
+		})?;
+	let channel = ChannelEnd::try_from(channel)?;
+	if channel.state != State::Open {
+		return Err(anyhow::anyhow!(
+			"Refusing to close channel {channel_id}/{port_id} on {}, it is {} instead of open",
+			chain_a.name(),
+			channel.state
+		))
+	}
+	let counterparty_channel_id = channel.counterparty().channel_id.ok_or_else(|| {
+		anyhow::anyhow!("Channel {channel_id}/{port_id} on {} has no counterparty", chain_a.name())
+	})?;
+
+	let msg =
+		MsgChannelCloseInit { port_id: port_id.clone(), channel_id, signer: chain_a.account_id() };
+	let msg = Any { type_url: msg.type_url(), value: msg.encode_vec()? };
+	// the relayer task only relays the close handshake of whitelisted channels
+	chain_a.add_channel_to_whitelist((channel_id, port_id));
+	chain_a.submit(vec![msg]).await?;
+
+	log::info!(target: "hyperspace", "============= Wait till the channel is closed on both chains =============");
+
+	let future = chain_b
+		.ibc_events()
+		.await
+		.skip_while(|ev| {
+			future::ready(!matches!(
+				ev,
+				IbcEvent::CloseConfirmChannel(close)
+					if close.channel_id() == Some(&counterparty_channel_id)
+			))
+		})
+		.take(1)
+		.collect::<Vec<_>>();
+	timeout_future(
+		future,
+		30 * 60,
+		format!("Didn't see CloseConfirmChannel on {}", chain_b.name()),
+	)
+	.await;
+
+	Ok(counterparty_channel_id)
+}
+
+async fn query_channel_version(
+	chain: &impl Chain,
+	channel_id: ChannelId,
+	port_id: PortId,
+) -> Result<ics04_channel::Version, anyhow::Error> {
+	let (latest_height, ..) = chain.latest_height_and_timestamp().await?;
+	let channel = chain
+		.query_channel_end(latest_height, channel_id, port_id)
+		.await?
+		.channel
+		.ok_or_else(|| anyhow::anyhow!("Channel end not found on {}", chain.name()))?;
+	Ok(ChannelEnd::try_from(channel)?.version)
+}
+
+/// Denom of the vouchers minted by the ICS-20 module for the tokens received on `channel_id` and
+/// `port_id`, `base_denom` being their full denom on the sending chain, e.g.
+/// `transfer/channel-0/stake` for tokens that were received by the sending chain already.
+pub fn ibc_denom_for(port_id: &PortId, channel_id: &ChannelId, base_denom: &str) -> String {
+	ibc_denom(&format!("{port_id}/{channel_id}/{base_denom}"))
+}
+
+/// Denom of the vouchers of the given trace, the hash of its full path.
+pub fn ibc_denom_of(trace: &RawDenomTrace) -> String {
+	if trace.path.is_empty() {
+		return trace.base_denom.clone()
+	}
+	ibc_denom(&format!("{}/{}", trace.path, trace.base_denom))
+}
+
+fn ibc_denom(full_path: &str) -> String {
+	format!("ibc/{}", hex::encode_upper(Sha256::digest(full_path.as_bytes())))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// A provider whose latest height follows `heights`, repeating the last one.
+	fn mock_provider(
+		heights: Vec<Result<u64, &'static str>>,
+	) -> impl FnMut() -> future::Ready<Result<Height, &'static str>> {
+		let mut calls = 0;
+		move || {
+			let height = heights[calls.min(heights.len() - 1)];
+			calls += 1;
+			future::ready(height.map(|height| Height::new(1, height)))
+		}
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn advancing_height_is_ready() {
+		let provider = mock_provider(vec![Err("connecting"), Ok(10), Ok(10), Ok(11)]);
+		let height = await_height_advance(provider, Duration::from_secs(60), READY_POLL_INTERVAL)
+			.await
+			.unwrap();
+		assert_eq!(height, Height::new(1, 11));
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn stuck_height_times_out() {
+		let provider = mock_provider(vec![Ok(10), Ok(9), Ok(10)]);
+		let error = await_height_advance(provider, Duration::from_secs(60), READY_POLL_INTERVAL)
+			.await
+			.unwrap_err();
+		assert_eq!(error, "height stuck at 1-10 for 60s");
+
+		let provider = mock_provider(vec![Err("connection refused")]);
+		let error = await_height_advance(provider, Duration::from_secs(60), READY_POLL_INTERVAL)
+			.await
+			.unwrap_err();
+		assert_eq!(error, "latest height query failing for 60s: \"connection refused\"");
+	}
+
+	#[test]
+	fn ibc_denoms_hash_the_full_trace() {
+		let denom = ibc_denom_for(&PortId::transfer(), &ChannelId::new(0), "UNIT");
+		assert_eq!(denom, "ibc/47B97D8FF01DA03FCB2F4B1FFEC931645F254E21EF465FA95CBA6888CB964DC4");
+
+		let trace = RawDenomTrace {
+			path: "transfer/channel-0".to_string(),
+			base_denom: "UNIT".to_string(),
+		};
+		assert_eq!(ibc_denom_of(&trace), denom);
+		let native = RawDenomTrace { path: String::new(), base_denom: "UNIT".to_string() };
+		assert_eq!(ibc_denom_of(&native), "UNIT");
+
+		// vouchers sent on are hashed with the trace of both hops
+		let forwarded =
+			ibc_denom_for(&PortId::transfer(), &ChannelId::new(1), "transfer/channel-0/UNIT");
+		let trace = RawDenomTrace {
+			path: "transfer/channel-1/transfer/channel-0".to_string(),
+			base_denom: "UNIT".to_string(),
+		};
+		assert_eq!(ibc_denom_of(&trace), forwarded);
+	}
+}
@@ -0,0 +1,146 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Authority set changes of the dev relay chain, scheduled by rotating the session keys of its
+//! validator, to check that the grandpa clients the relayer lets fall behind are caught up.
+
+use codec::{Compact, Encode};
+use hyperspace_core::substrate::DefaultConfig;
+use hyperspace_parachain::ParachainClient;
+use hyperspace_primitives::{Chain, IbcProvider};
+use ibc::core::ics24_host::identifier::ClientId;
+use light_client_common::config::Config;
+use pallet_ibc::light_clients::AnyClientState;
+use sp_core::{sr25519, Pair};
+use std::time::Duration;
+use subxt::{error::MetadataError, tx::TxPayload, Metadata};
+use tokio::time::{sleep, timeout};
+
+/// How long a change of the authority set may take once it's scheduled, i.e. two sessions of a
+/// `fast-runtime` relay chain with some margin.
+const SET_CHANGE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Interval at which the relay chain and the client are polled.
+const POLL_INTERVAL: Duration = Duration::from_secs(6);
+
+/// `Session::set_keys` call with the SCALE encoded session keys returned by `author_rotateKeys`,
+/// which are submitted as is rather than decoded into the keys type of the relay chain runtime.
+struct SetKeys(Vec<u8>);
+
+impl TxPayload for SetKeys {
+	fn encode_call_data_to(
+		&self,
+		metadata: &Metadata,
+		out: &mut Vec<u8>,
+	) -> Result<(), subxt::Error> {
+		let pallet = metadata
+			.pallet_by_name("Session")
+			.ok_or_else(|| MetadataError::PalletNameNotFound("Session".to_string()))?;
+		let call = pallet
+			.call_variant_by_name("set_keys")
+			.ok_or_else(|| MetadataError::CallNameNotFound("set_keys".to_string()))?;
+		out.extend([pallet.index(), call.index]);
+		out.extend_from_slice(&self.0);
+		// no ownership proof
+		Compact(0u32).encode_to(out);
+		Ok(())
+	}
+}
+
+/// Id of the authority set of the grandpa client `client_id` on `host`.
+async fn client_set_id(host: &ParachainClient<DefaultConfig>, client_id: ClientId) -> u64 {
+	let latest_height = host.latest_height_and_timestamp().await.unwrap().0;
+	let response = host.query_client_state(latest_height, client_id).await.unwrap();
+	let AnyClientState::Grandpa(client_state) =
+		AnyClientState::decode_recursive(response.client_state.unwrap(), |cs| {
+			matches!(cs, AnyClientState::Grandpa(_))
+		})
+		.unwrap()
+	else {
+		panic!("The client isn't a grandpa client")
+	};
+	client_state.current_set_id
+}
+
+/// Id of the authority set finalizing the relay chain of `chain`.
+async fn relay_set_id(chain: &ParachainClient<DefaultConfig>) -> u64 {
+	let rpc = chain.relay_client.rpc();
+	let finalized = rpc.finalized_head().await.unwrap();
+	let header = rpc.header(Some(finalized)).await.unwrap().unwrap();
+	chain.grandpa_prover().current_set_id_at(header.number).await.unwrap()
+}
+
+/// Schedules a change of the authority set of the relay chain of `chain`: the node generates new
+/// session keys for its validator, which are set with the validator's stash account. The keys
+/// are queued at the next session and change the authority set at the one after.
+async fn rotate_relay_session_keys(chain: &ParachainClient<DefaultConfig>) {
+	let keys = chain.relay_client.rpc().rotate_keys().await.unwrap();
+	let stash = sr25519::Pair::from_string("//Alice//stash", None).unwrap();
+	let signer = subxt::tx::PairSigner::<DefaultConfig, _>::new(stash);
+	let params = DefaultConfig::custom_extrinsic_params(&chain.relay_client).await.unwrap();
+	chain
+		.relay_client
+		.tx()
+		.sign_and_submit_then_watch(&SetKeys(keys.0), &signer, params)
+		.await
+		.unwrap()
+		.wait_for_finalized_success()
+		.await
+		.unwrap();
+}
+
+/// Lets the client of `chain_a` on `chain_b` fall `sets` authority sets behind the relay chain
+/// while the relayer is down, then asserts that the relayer catches it up to the current set on
+/// its own.
+pub async fn grandpa_client_catches_up_with_authority_sets(
+	chain_a: &mut ParachainClient<DefaultConfig>,
+	chain_b: &mut ParachainClient<DefaultConfig>,
+	sets: u64,
+) {
+	let client_set_id_before = client_set_id(chain_b, chain_a.client_id()).await;
+	let mut set_id = relay_set_id(chain_a).await;
+	while set_id < client_set_id_before + sets {
+		log::info!(target: "hyperspace", "Rotating the session keys of the relay chain at authority set {set_id}");
+		rotate_relay_session_keys(chain_a).await;
+		timeout(SET_CHANGE_TIMEOUT, async {
+			while relay_set_id(chain_a).await == set_id {
+				sleep(POLL_INTERVAL).await;
+			}
+		})
+		.await
+		.expect("The relay chain didn't change its authority set");
+		set_id = relay_set_id(chain_a).await;
+	}
+	assert_eq!(
+		client_set_id(chain_b, chain_a.client_id()).await,
+		client_set_id_before,
+		"The client was updated while the relayer was down"
+	);
+
+	log::info!(target: "hyperspace", "The client of {} is {} authority sets behind, starting the relayer", chain_a.name(), set_id - client_set_id_before);
+	let (client_a, client_b) = (chain_a.clone(), chain_b.clone());
+	let handle = tokio::task::spawn(async move {
+		hyperspace_core::relay(client_a, client_b, None, None, None).await.unwrap()
+	});
+	timeout(SET_CHANGE_TIMEOUT, async {
+		while client_set_id(chain_b, chain_a.client_id()).await < set_id {
+			sleep(POLL_INTERVAL).await;
+		}
+	})
+	.await
+	.expect("The relayer didn't catch the client up with the authority sets of the relay chain");
+	assert_eq!(chain_a.common_state().authority_set_lag.get(), 0);
+	log::info!(target: "hyperspace", "🚀🚀 The client of {} caught up with authority set {set_id}", chain_a.name());
+	handle.abort();
+}
@@ -0,0 +1,85 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Configs of the dev parachains the parachain suites run against, whose hosts are read from the
+//! `RELAY_HOST` and `PARA_HOST` environment variables.
+
+use hyperspace_parachain::{finality_protocol::FinalityProtocol, ParachainClientConfig};
+
+/// Endpoints and ids of the two dev parachains and of their relay chain.
+#[derive(Debug, Clone)]
+pub struct DevParachains {
+	pub chain_a: String,
+	pub chain_b: String,
+	pub relay_chain: String,
+	pub para_id_a: u32,
+	pub para_id_b: u32,
+}
+
+impl Default for DevParachains {
+	fn default() -> Self {
+		let relay = std::env::var("RELAY_HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
+		let para = std::env::var("PARA_HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
+
+		DevParachains {
+			chain_a: format!("ws://{para}:9988"),
+			chain_b: format!("ws://{para}:9188"),
+			relay_chain: format!("ws://{relay}:9944"),
+			para_id_a: 2001,
+			para_id_b: 2000,
+		}
+	}
+}
+
+impl DevParachains {
+	pub fn config_a(&self) -> ParachainClientConfig {
+		parachain_config("9988", self.para_id_a, self.chain_a.clone(), self.relay_chain.clone())
+	}
+
+	pub fn config_b(&self) -> ParachainClientConfig {
+		parachain_config("9188", self.para_id_b, self.chain_b.clone(), self.relay_chain.clone())
+	}
+}
+
+/// Config of a dev parachain relayed with the `//Alice` key over grandpa, without any client,
+/// connection or channel yet.
+pub fn parachain_config(
+	name: &str,
+	para_id: u32,
+	parachain_rpc_url: String,
+	relay_chain_rpc_url: String,
+) -> ParachainClientConfig {
+	ParachainClientConfig {
+		name: name.to_string(),
+		para_id,
+		parachain_rpc_url,
+		relay_chain_rpc_url,
+		client_id: None,
+		connection_id: None,
+		connections: vec![],
+		commitment_prefix: b"ibc/".to_vec().into(),
+		ss58_version: 42,
+		channel_whitelist: vec![],
+		finality_protocol: FinalityProtocol::Grandpa,
+		private_key: "//Alice".to_string(),
+		signer: Default::default(),
+		key_type: "sr25519".to_string(),
+		wasm_code_id: None,
+		finality_proof_cache_bucket_size: None,
+		metadata_mismatch: Default::default(),
+		max_update_size: None,
+		signature_verification_threads: 0,
+		common: Default::default(),
+	}
+}
@@ -0,0 +1,344 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Timing of the packets relayed by the scenarios.
+//!
+//! The stages of the life of a packet are timed with the block timestamps of the chains they
+//! happened on: the packet is sent on the source, the client of the source on the sink is updated
+//! to a height proving it, the packet is received on the sink and its acknowledgement is seen on
+//! the source. The timelines of a scenario are printed at its end, and checked against the
+//! budgets set with [`RECV_BLOCKS_BUDGET_ENV`] and [`ROUND_TRIP_SECS_BUDGET_ENV`], so that CI can
+//! enforce them on the local chains but not on the slow public testnets.
+
+use futures::StreamExt;
+use hyperspace_primitives::TestProvider;
+use ibc::{
+	applications::transfer::{msgs::transfer::MsgTransfer, packet::PacketData, PrefixedCoin},
+	core::ics24_host::identifier::ChannelId,
+	events::IbcEvent,
+	Height,
+};
+use std::{
+	fmt::{self, Display},
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+use tokio::task::JoinHandle;
+
+/// Maximum number of blocks of the sink between the client update proving a packet and its
+/// reception.
+pub const RECV_BLOCKS_BUDGET_ENV: &str = "HYPERSPACE_LATENCY_BUDGET_RECV_BLOCKS";
+
+/// Maximum number of seconds between a packet being sent and its acknowledgement.
+pub const ROUND_TRIP_SECS_BUDGET_ENV: &str = "HYPERSPACE_LATENCY_BUDGET_ROUND_TRIP_SECS";
+
+/// Number of blocks of the source the acknowledgement is awaited for, since the events are
+/// collected apart from the scenario that saw it.
+const ACKNOWLEDGEMENT_WAIT_BLOCKS: u32 = 20;
+
+/// Block at which a stage of a packet happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StageTime {
+	/// Height of the block, on the chain the stage happened on
+	pub height: Height,
+	/// Timestamp of the block in nanoseconds
+	pub timestamp: u64,
+}
+
+/// Stages of a packet relayed from `source` to `sink`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PacketTimeline {
+	pub source: String,
+	pub sink: String,
+	pub channel_id: ChannelId,
+	pub sequence: u64,
+	pub sent: StageTime,
+	/// Update of the client of the source on the sink to a height proving the packet
+	pub client_updated: Option<StageTime>,
+	pub received: Option<StageTime>,
+	pub acknowledged: Option<StageTime>,
+}
+
+impl PacketTimeline {
+	/// Number of blocks of the sink between the client update proving the packet and its
+	/// reception.
+	pub fn recv_blocks(&self) -> Option<u64> {
+		let (updated, received) = (self.client_updated?, self.received?);
+		Some(received.height.revision_height.saturating_sub(updated.height.revision_height))
+	}
+
+	/// Time between the packet being sent and its acknowledgement, both on the source.
+	pub fn round_trip(&self) -> Option<Duration> {
+		Some(self.since_sent(self.acknowledged?))
+	}
+
+	fn since_sent(&self, stage: StageTime) -> Duration {
+		Duration::from_nanos(stage.timestamp.saturating_sub(self.sent.timestamp))
+	}
+}
+
+impl Display for PacketTimeline {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"{} -> {} {}/{}: sent at {}",
+			self.source, self.sink, self.channel_id, self.sequence, self.sent.height
+		)?;
+		let stages = [
+			("client updated", self.client_updated),
+			("received", self.received),
+			("acknowledged", self.acknowledged),
+		];
+		for (stage, time) in stages {
+			match time {
+				Some(time) => write!(
+					f,
+					", {stage} at {} (+{:.1}s)",
+					time.height,
+					self.since_sent(time).as_secs_f64()
+				)?,
+				None => write!(f, ", not {stage}")?,
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Latency the packets of the scenarios must stay within, no limit if `None`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencyBudget {
+	/// See [`RECV_BLOCKS_BUDGET_ENV`]
+	pub recv_blocks: Option<u64>,
+	/// See [`ROUND_TRIP_SECS_BUDGET_ENV`]
+	pub round_trip: Option<Duration>,
+}
+
+impl LatencyBudget {
+	/// The budget set by [`RECV_BLOCKS_BUDGET_ENV`] and [`ROUND_TRIP_SECS_BUDGET_ENV`].
+	pub fn from_env() -> Self {
+		Self::from_vars(
+			std::env::var(RECV_BLOCKS_BUDGET_ENV).ok(),
+			std::env::var(ROUND_TRIP_SECS_BUDGET_ENV).ok(),
+		)
+	}
+
+	pub fn from_vars(recv_blocks: Option<String>, round_trip_secs: Option<String>) -> Self {
+		let parse = |var: &str, value: Option<String>| {
+			value.map(|value| {
+				value.parse::<u64>().unwrap_or_else(|e| panic!("Invalid {var} {value}: {e}"))
+			})
+		};
+		Self {
+			recv_blocks: parse(RECV_BLOCKS_BUDGET_ENV, recv_blocks),
+			round_trip: parse(ROUND_TRIP_SECS_BUDGET_ENV, round_trip_secs).map(Duration::from_secs),
+		}
+	}
+
+	/// The budgets exceeded by `timeline`. A stage that wasn't reached exceeds its budget.
+	pub fn violations(&self, timeline: &PacketTimeline) -> Vec<String> {
+		let mut violations = vec![];
+		if let Some(budget) = self.recv_blocks {
+			match timeline.recv_blocks() {
+				Some(blocks) if blocks <= budget => {},
+				Some(blocks) => violations.push(format!(
+					"received {blocks} blocks after the client update proving it, over the budget of {budget}"
+				)),
+				None =>
+					violations.push("not received after a client update proving it".to_string()),
+			}
+		}
+		if let Some(budget) = self.round_trip {
+			match timeline.round_trip() {
+				Some(round_trip) if round_trip <= budget => {},
+				Some(round_trip) => violations.push(format!(
+					"acknowledged after {round_trip:?}, over the budget of {budget:?}"
+				)),
+				None => violations.push("not acknowledged".to_string()),
+			}
+		}
+		violations
+	}
+}
+
+/// Collects the events of a pair of chains while a packet is relayed between them.
+pub struct PacketTimer {
+	source_events: Arc<Mutex<Vec<IbcEvent>>>,
+	sink_events: Arc<Mutex<Vec<IbcEvent>>>,
+	collectors: Vec<JoinHandle<()>>,
+}
+
+impl Drop for PacketTimer {
+	fn drop(&mut self) {
+		self.collectors.iter().for_each(JoinHandle::abort);
+	}
+}
+
+/// Collects the events of `chain` until the returned task is aborted.
+async fn collect_events<C: TestProvider>(chain: &C) -> (Arc<Mutex<Vec<IbcEvent>>>, JoinHandle<()>) {
+	let events = Arc::new(Mutex::new(vec![]));
+	let mut stream = chain.ibc_events().await;
+	let collected = events.clone();
+	let collector = tokio::task::spawn(async move {
+		while let Some(event) = stream.next().await {
+			collected.lock().unwrap().push(event);
+		}
+	});
+	(events, collector)
+}
+
+/// First of `events` `f` returns a value for.
+fn find<T>(events: &Mutex<Vec<IbcEvent>>, f: impl FnMut(&IbcEvent) -> Option<T>) -> Option<T> {
+	events.lock().unwrap().iter().find_map(f)
+}
+
+/// Block `height` of `chain`.
+async fn stage_time<C: TestProvider>(chain: &C, height: Height) -> Option<StageTime> {
+	let timestamp = chain.query_timestamp_at(height.revision_height).await.ok()?;
+	Some(StageTime { height, timestamp })
+}
+
+impl PacketTimer {
+	/// Starts collecting the events of both chains, before the packet is sent.
+	pub async fn start<A: TestProvider, B: TestProvider>(source: &A, sink: &B) -> Self {
+		let (source_events, source_collector) = collect_events(source).await;
+		let (sink_events, sink_collector) = collect_events(sink).await;
+		Self { source_events, sink_events, collectors: vec![source_collector, sink_collector] }
+	}
+
+	/// Timeline of the packet of `transfer` sent from `source` since the timer was started,
+	/// `None` if it wasn't seen. The packets of the other scenarios relaying on the same channel
+	/// are told apart by their data.
+	pub async fn finish<A: TestProvider, B: TestProvider>(
+		self,
+		source: &A,
+		sink: &B,
+		transfer: &MsgTransfer<PrefixedCoin>,
+	) -> Option<PacketTimeline> {
+		let channel_id = transfer.source_channel;
+		let (sent_height, sequence) = find(&self.source_events, |event| match event {
+			IbcEvent::SendPacket(ev) if ev.packet.source_channel == channel_id => {
+				let data = json::from_slice::<PacketData>(&ev.packet.data).ok()?;
+				(data.token == transfer.token && data.receiver == transfer.receiver)
+					.then_some((ev.height, ev.packet.sequence))
+			},
+			_ => None,
+		})?;
+		let acknowledged = |event: &IbcEvent| match event {
+			IbcEvent::AcknowledgePacket(ev)
+				if ev.packet.source_channel == channel_id && ev.packet.sequence == sequence =>
+				Some(ev.height),
+			_ => None,
+		};
+		for _ in 0..ACKNOWLEDGEMENT_WAIT_BLOCKS {
+			if find(&self.source_events, acknowledged).is_some() {
+				break
+			}
+			tokio::time::sleep(source.expected_block_time()).await;
+		}
+
+		let client_id = source.client_id();
+		let consensus_height = find(&self.sink_events, |event| match event {
+			IbcEvent::UpdateClient(ev)
+				if *ev.client_id() == client_id && ev.consensus_height() >= sent_height =>
+				Some(ev.consensus_height()),
+			_ => None,
+		});
+		let client_updated = match consensus_height {
+			Some(consensus_height) => sink
+				.query_client_update_time_and_height(client_id, consensus_height)
+				.await
+				.ok()
+				.map(|(height, time)| StageTime { height, timestamp: time.nanoseconds() }),
+			None => None,
+		};
+		let received_height = find(&self.sink_events, |event| match event {
+			IbcEvent::ReceivePacket(ev)
+				if ev.packet.source_channel == channel_id && ev.packet.sequence == sequence =>
+				Some(ev.height),
+			_ => None,
+		});
+		let received = match received_height {
+			Some(height) => stage_time(sink, height).await,
+			None => None,
+		};
+		let acknowledged = match find(&self.source_events, acknowledged) {
+			Some(height) => stage_time(source, height).await,
+			None => None,
+		};
+		Some(PacketTimeline {
+			source: source.name().to_string(),
+			sink: sink.name().to_string(),
+			channel_id,
+			sequence: sequence.into(),
+			sent: stage_time(source, sent_height).await?,
+			client_updated,
+			received,
+			acknowledged,
+		})
+	}
+}
+
+/// Timelines of the packets of a scenario.
+#[derive(Debug, Clone)]
+pub struct LatencyReport {
+	scenario: String,
+	timelines: Vec<PacketTimeline>,
+}
+
+impl LatencyReport {
+	pub fn new(scenario: impl Into<String>) -> Self {
+		Self { scenario: scenario.into(), timelines: vec![] }
+	}
+
+	/// Adds the timeline of a packet of the scenario, see [`PacketTimer::finish`].
+	pub fn record(&mut self, timeline: Option<PacketTimeline>) {
+		match timeline {
+			Some(timeline) => self.timelines.push(timeline),
+			None => log::warn!(target: "hyperspace", "No packet was timed in {}", self.scenario),
+		}
+	}
+
+	/// Prints the timelines and asserts that they're within the budget set in the environment,
+	/// see [`LatencyBudget::from_env`].
+	pub fn finish(self) -> Vec<PacketTimeline> {
+		self.finish_with(&LatencyBudget::from_env())
+	}
+
+	/// Prints the timelines and asserts that they're within `budget`.
+	pub fn finish_with(self, budget: &LatencyBudget) -> Vec<PacketTimeline> {
+		println!("Latency of {}:", self.scenario);
+		for timeline in &self.timelines {
+			println!("  {timeline}");
+		}
+		let violations = self
+			.timelines
+			.iter()
+			.flat_map(|timeline| {
+				budget.violations(timeline).into_iter().map(move |violation| {
+					format!(
+						"{} {}/{}: {violation}",
+						timeline.source, timeline.channel_id, timeline.sequence
+					)
+				})
+			})
+			.collect::<Vec<_>>();
+		assert!(
+			violations.is_empty(),
+			"Latency budget exceeded in {}:\n{}",
+			self.scenario,
+			violations.join("\n")
+		);
+		self.timelines
+	}
+}
@@ -14,42 +14,172 @@
 
 #![allow(clippy::all)]
 
-use crate::utils::assert_timeout_packet;
+use crate::{
+	latency::{LatencyReport, PacketTimeline, PacketTimer},
+	utils::assert_timeout_packet,
+};
 use futures::{future, StreamExt};
-use hyperspace_core::send_packet_relay::set_relay_status;
+use hyperspace_core::{send_packet_relay::set_relay_status, substrate::DefaultConfig};
+use hyperspace_parachain::ParachainClient;
 use hyperspace_primitives::{
-	utils::{create_channel, create_connection, timeout_after, timeout_future},
+	relayer_store::{InMemoryRelayerStore, RelayerStore},
+	utils::{create_channel, create_connection, ibc_denom_for, timeout_after, timeout_future},
 	TestProvider,
 };
 use ibc::{
-	applications::transfer::{msgs::transfer::MsgTransfer, Amount, PrefixedCoin, VERSION},
+	applications::transfer::{
+		msgs::transfer::MsgTransfer, packet::PacketData, Amount, PrefixedCoin, PrefixedDenom,
+		VERSION,
+	},
 	core::{
+		ics03_connection::connection::ConnectionEnd,
 		ics04_channel::{
-			channel::{ChannelEnd, Order, State},
-			msgs::chan_close_init::MsgChannelCloseInit,
+			channel::{ChannelEnd, Counterparty, Order, State},
+			msgs::{chan_close_init::MsgChannelCloseInit, chan_open_init::MsgChannelOpenInit},
+			Version,
 		},
 		ics24_host::identifier::{ChannelId, ConnectionId, PortId},
 	},
 	events::IbcEvent,
+	signer::Signer,
 	tx_msg::Msg,
 };
-use ibc_proto::google::protobuf::Any;
+use ibc_proto::{
+	cosmos::base::v1beta1::Coin, google::protobuf::Any, ibc::applications::fee::v1::Fee,
+};
 use pallet_ibc::Timeout;
-use std::{str::FromStr, time::Duration};
+use std::{str::FromStr, sync::Arc, time::Duration};
 use tendermint_proto::Protobuf;
 use tokio::task::JoinHandle;
 
+pub mod authority_sets;
+pub mod dev_parachains;
+pub mod latency;
 pub mod misbehaviour;
+pub mod mock;
 pub mod ordered_channels;
+pub mod raw_packets;
 mod utils;
 
-/// This will set up a connection and ics20 channel in-between the two chains.
-/// `connection_delay` should be in seconds.
+/// Amount of the transferred asset minted to the relayer account by [`ensure_transfer_asset`].
+pub const TRANSFER_ASSET_AMOUNT: u128 = 1_000_000_000_000_000;
+
+/// Creates `asset_id` on `chain` if it doesn't exist and funds the relayer account with it, so
+/// that the transfer tests don't depend on the genesis of the chain.
+pub async fn ensure_transfer_asset<C: TestProvider>(chain: &C, asset_id: C::AssetId) {
+	chain
+		.ensure_asset(asset_id, chain.account_id(), TRANSFER_ASSET_AMOUNT)
+		.await
+		.expect("Failed to create the transferred asset");
+}
+
+/// How often the relay chain is polled by [`await_parachain_onboarding`].
+const ONBOARDING_POLL_INTERVAL: Duration = Duration::from_secs(6);
+
+/// Waits until the relay chain of `chain` finalizes two sessions, after which the parachain is
+/// onboarded and produces blocks. The finalized head is polled rather than subscribed to, since
+/// the subscription may never yield on a slow node, and the wait panics after `timeout`.
+pub async fn await_parachain_onboarding(chain: &ParachainClient<DefaultConfig>, timeout: Duration) {
+	let session_length = chain.grandpa_prover().session_length().await.unwrap();
+	let onboarded_at = session_length * 2 + 10;
+	let mut finalized = None;
+	let poll = async {
+		loop {
+			let header = match chain.relay_client.rpc().finalized_head().await {
+				Ok(hash) => chain.relay_client.rpc().header(Some(hash)).await,
+				Err(e) => Err(e),
+			};
+			match header {
+				Ok(Some(header)) if header.number >= onboarded_at => return,
+				Ok(header) => finalized = header.map(|header| header.number),
+				Err(e) =>
+					log::debug!(target: "hyperspace", "Failed to query the finalized head of the relay chain: {e}"),
+			}
+			tokio::time::sleep(ONBOARDING_POLL_INTERVAL).await;
+		}
+	};
+	if tokio::time::timeout(timeout, poll).await.is_err() {
+		panic!(
+			"{} wasn't onboarded within {timeout:?}, the relay chain at {} finalized {finalized:?} out of {onboarded_at} blocks",
+			chain.name, chain.relay_chain_rpc_url
+		)
+	}
+}
+
+/// Asserts that `chain` traces the vouchers of `base_denom` received on the transfer channel
+/// `channel_id` back to their origin under the locally computed ibc denom, and returns the asset
+/// holding them.
+pub async fn assert_denom_trace<C: TestProvider>(
+	chain: &C,
+	channel_id: ChannelId,
+	base_denom: &str,
+) -> C::AssetId {
+	let ibc_denom = ibc_denom_for(&PortId::transfer(), &channel_id, base_denom);
+	let trace = chain
+		.query_denom_trace(ibc_denom.clone())
+		.await
+		.expect("Failed to query the trace");
+	let full_path = PrefixedDenom::try_from(trace).expect("Invalid denom trace").to_string();
+	assert_eq!(full_path, format!("{}/{channel_id}/{base_denom}", PortId::transfer()));
+	chain.query_asset_id(ibc_denom).await.expect("Failed to query the asset id")
+}
+
+/// Parameters of the path set up by [`setup_path`].
+#[derive(Debug, Clone)]
+pub struct PathParams {
+	/// Delay period of the connection
+	pub connection_delay: Duration,
+	/// Port of the channel on both chains
+	pub port_id: PortId,
+	/// Version of the channel
+	pub version: String,
+	/// Order of the channel
+	pub order: Order,
+	/// Reuse an open path with the same parameters between the configured clients, if any.
+	/// Disable it for tests that need a fresh channel.
+	pub reuse_existing: bool,
+}
+
+impl PathParams {
+	/// An unordered ics20 channel reusing an existing path.
+	pub fn transfer(connection_delay: Duration) -> Self {
+		Self {
+			connection_delay,
+			port_id: PortId::transfer(),
+			version: VERSION.to_string(),
+			order: Order::Unordered,
+			reuse_existing: true,
+		}
+	}
+}
+
+/// This will set up a connection and ics20 channel in-between the two chains, reusing an open
+/// one if it exists. `connection_delay` should be in seconds.
 pub async fn setup_connection_and_channel<A, B>(
 	chain_a: &mut A,
 	chain_b: &mut B,
 	connection_delay: Duration,
-) -> (JoinHandle<()>, ChannelId, ChannelId, ConnectionId, ConnectionId)
+) -> (JoinHandle<()>, ChannelId, ChannelId, ConnectionId, ConnectionId, bool)
+where
+	A: TestProvider,
+	A::FinalityEvent: Send + Sync,
+	A::Error: From<B::Error>,
+	B: TestProvider,
+	B::FinalityEvent: Send + Sync,
+	B::Error: From<A::Error>,
+{
+	setup_path(chain_a, chain_b, PathParams::transfer(connection_delay)).await
+}
+
+/// This will set up a connection and a channel in-between the two chains. An open channel (or
+/// connection) matching `params` is reused if `params.reuse_existing` is set, so that only the
+/// missing pieces of the path are created. The returned flag is `true` if the channel was
+/// reused.
+pub async fn setup_path<A, B>(
+	chain_a: &mut A,
+	chain_b: &mut B,
+	params: PathParams,
+) -> (JoinHandle<()>, ChannelId, ChannelId, ConnectionId, ConnectionId, bool)
 where
 	A: TestProvider,
 	A::FinalityEvent: Send + Sync,
@@ -66,7 +196,83 @@ where
 			.await
 			.unwrap()
 	});
-	// check if an open transfer channel exists
+
+	let existing_connection = if params.reuse_existing {
+		match find_open_path(chain_a, chain_b, &params).await {
+			ExistingPath::Channel {
+				channel_id_a,
+				channel_id_b,
+				connection_id_a,
+				connection_id_b,
+			} => {
+				log::info!(target: "hyperspace", "============ Reusing open channel: ChannelId({channel_id_a}) ============");
+				return (handle, channel_id_a, channel_id_b, connection_id_a, connection_id_b, true)
+			},
+			ExistingPath::Connection { connection_id_a, connection_id_b } =>
+				Some((connection_id_a, connection_id_b)),
+			ExistingPath::None => None,
+		}
+	} else {
+		None
+	};
+
+	let (connection_id_a, connection_id_b) = match existing_connection {
+		Some((connection_id_a, connection_id_b)) => {
+			log::info!(target: "hyperspace", "============ Reusing open connection: ConnectionId({connection_id_a}), ConnectionId({connection_id_b}) ============");
+			(connection_id_a, connection_id_b)
+		},
+		None => {
+			let prefix = chain_b.connection_prefix();
+			let (connection_id_a, connection_id_b) =
+				create_connection(chain_a, chain_b, params.connection_delay, prefix)
+					.await
+					.unwrap();
+			log::info!(target: "hyperspace", "============ Connection handshake completed: ConnectionId({connection_id_a}), ConnectionId({connection_id_b}) ============");
+			(connection_id_a, connection_id_b)
+		},
+	};
+
+	log::info!(target: "hyperspace", "=========================== Starting channel handshake ===========================");
+
+	let (channel_id_a, channel_id_b) = create_channel(
+		chain_a,
+		chain_b,
+		connection_id_a.clone(),
+		params.port_id,
+		params.version,
+		params.order,
+		None,
+	)
+	.await
+	.unwrap();
+	// channel handshake completed
+	log::info!(target: "hyperspace", "============ Channel handshake completed: ChannelId({channel_id_a}) ============");
+
+	(handle, channel_id_a, channel_id_b, connection_id_a, connection_id_b, false)
+}
+
+/// Open parts of a path between the configured clients found by [`find_open_path`].
+enum ExistingPath {
+	Channel {
+		channel_id_a: ChannelId,
+		channel_id_b: ChannelId,
+		connection_id_a: ConnectionId,
+		connection_id_b: ConnectionId,
+	},
+	Connection {
+		connection_id_a: ConnectionId,
+		connection_id_b: ConnectionId,
+	},
+	None,
+}
+
+/// Looks for an open channel matching `params` on the open connections of `chain_a` that use the
+/// client of `chain_b`. Falls back to an open connection with the expected delay period.
+async fn find_open_path<A, B>(chain_a: &A, chain_b: &B, params: &PathParams) -> ExistingPath
+where
+	A: TestProvider,
+	B: TestProvider,
+{
 	let (latest_height, ..) = chain_a.latest_height_and_timestamp().await.unwrap();
 	let connections = chain_a
 		.query_connection_using_client(
@@ -76,6 +282,7 @@ where
 		.await
 		.unwrap();
 
+	let mut open_connection = None;
 	for connection in connections {
 		let connection_id = ConnectionId::from_str(&connection.id).unwrap();
 		let connection_end = chain_a
@@ -84,11 +291,12 @@ where
 			.unwrap()
 			.connection
 			.unwrap();
-
-		let delay_period = Duration::from_nanos(connection_end.delay_period);
-		if delay_period != connection_delay {
+		let connection_end = ConnectionEnd::try_from(connection_end).unwrap();
+		if !connection_end.is_open() || connection_end.delay_period() != params.connection_delay {
 			continue
 		}
+		let counterparty_connection_id =
+			connection_end.counterparty().connection_id().unwrap().clone();
 
 		let channels = chain_a
 			.query_connection_channels(latest_height, &connection_id)
@@ -97,48 +305,67 @@ where
 			.channels;
 
 		for channel in channels {
+			if channel.port_id != params.port_id.to_string() {
+				continue
+			}
 			let channel_id = ChannelId::from_str(&channel.channel_id).unwrap();
 			let channel_end = chain_a
-				.query_channel_end(latest_height, channel_id, PortId::transfer())
+				.query_channel_end(latest_height, channel_id, params.port_id.clone())
 				.await
 				.unwrap()
 				.channel
 				.unwrap();
 			let channel_end = ChannelEnd::try_from(channel_end).unwrap();
 
-			if channel_end.state == State::Open && channel.port_id == PortId::transfer().to_string()
+			if channel_end.is_open() &&
+				channel_end.ordering == params.order &&
+				channel_end.version.to_string() == params.version
 			{
-				return (
-					handle,
-					channel_id,
-					channel_end.counterparty().channel_id.unwrap().clone(),
-					channel_end.connection_hops[0].clone(),
-					connection_id,
-				)
+				return ExistingPath::Channel {
+					channel_id_a: channel_id,
+					channel_id_b: channel_end.counterparty().channel_id.unwrap().clone(),
+					connection_id_a: connection_id,
+					connection_id_b: counterparty_connection_id,
+				}
 			}
 		}
+		open_connection.get_or_insert((connection_id, counterparty_connection_id));
 	}
 
-	let (connection_id_a, connection_id_b) =
-		create_connection(chain_a, chain_b, connection_delay).await.unwrap();
+	match open_connection {
+		Some((connection_id_a, connection_id_b)) =>
+			ExistingPath::Connection { connection_id_a, connection_id_b },
+		None => ExistingPath::None,
+	}
+}
 
-	log::info!(target: "hyperspace", "============ Connection handshake completed: ConnectionId({connection_id_a}), ConnectionId({connection_id_b}) ============");
-	log::info!(target: "hyperspace", "=========================== Starting channel handshake ===========================");
+/// Asset, amount and receiver of a transfer sent by the testsuite helpers.
+#[derive(Debug, Clone)]
+pub struct TestTransfer<AssetId> {
+	/// Transferred asset of the sending chain
+	pub asset_id: AssetId,
+	/// Transferred amount, 20% of the sender's balance of the asset if `None`
+	pub amount: Option<u128>,
+	/// Receiver of the transfer, the relayer's account of the counterparty if `None`
+	pub receiver: Option<Signer>,
+}
 
-	let (channel_id_a, channel_id_b) = create_channel(
-		chain_a,
-		chain_b,
-		connection_id_a.clone(),
-		PortId::transfer(),
-		VERSION.to_string(),
-		Order::Unordered,
-	)
-	.await
-	.unwrap();
-	// channel handshake completed
-	log::info!(target: "hyperspace", "============ Channel handshake completed: ChannelId({channel_id_a}) ============");
+impl<AssetId> TestTransfer<AssetId> {
+	/// Transfer of 20% of the sender's balance of `asset_id` to the relayer's account of the
+	/// counterparty.
+	pub fn new(asset_id: AssetId) -> Self {
+		Self { asset_id, amount: None, receiver: None }
+	}
+
+	pub fn with_amount(mut self, amount: u128) -> Self {
+		self.amount = Some(amount);
+		self
+	}
 
-	(handle, channel_id_a, channel_id_b, connection_id_a, connection_id_b)
+	pub fn with_receiver(mut self, receiver: Signer) -> Self {
+		self.receiver = Some(receiver);
+		self
+	}
 }
 
 /// Attempts to send 20% of funds of chain_a's signer to chain b's signer.
@@ -149,6 +376,73 @@ async fn send_transfer<A, B>(
 	channel_id: ChannelId,
 	timeout: Option<Timeout>,
 ) -> (u128, MsgTransfer<PrefixedCoin>)
+where
+	A: TestProvider,
+	A::FinalityEvent: Send + Sync,
+	A::Error: From<B::Error>,
+	B: TestProvider,
+	B::FinalityEvent: Send + Sync,
+	B::Error: From<A::Error>,
+{
+	send_test_transfer(chain_a, chain_b, &TestTransfer::new(asset_a), channel_id, timeout).await
+}
+
+/// Sends `transfer` from chain_a over `channel_id`. Returns the balance of the transferred asset
+/// before the transfer along with the message.
+async fn send_test_transfer<A, B>(
+	chain_a: &A,
+	chain_b: &B,
+	transfer: &TestTransfer<A::AssetId>,
+	channel_id: ChannelId,
+	timeout: Option<Timeout>,
+) -> (u128, MsgTransfer<PrefixedCoin>)
+where
+	A: TestProvider,
+	A::FinalityEvent: Send + Sync,
+	A::Error: From<B::Error>,
+	B: TestProvider,
+	B::FinalityEvent: Send + Sync,
+	B::Error: From<A::Error>,
+{
+	let (balance, msg) =
+		transfer_msg(chain_a, chain_b, transfer, channel_id, timeout, String::new()).await;
+	chain_a.send_transfer(msg.clone()).await.expect("Failed to send transfer: ");
+	(balance, msg)
+}
+
+/// Like [`send_transfer`], with the given memo attached to the transfer.
+async fn send_transfer_with_memo<A, B>(
+	chain_a: &A,
+	chain_b: &B,
+	asset_a: A::AssetId,
+	channel_id: ChannelId,
+	timeout: Option<Timeout>,
+	memo: String,
+) -> (u128, MsgTransfer<PrefixedCoin>)
+where
+	A: TestProvider,
+	A::FinalityEvent: Send + Sync,
+	A::Error: From<B::Error>,
+	B: TestProvider,
+	B::FinalityEvent: Send + Sync,
+	B::Error: From<A::Error>,
+{
+	let transfer = TestTransfer::new(asset_a);
+	let (amount, msg) = transfer_msg(chain_a, chain_b, &transfer, channel_id, timeout, memo).await;
+	chain_a.send_transfer(msg.clone()).await.expect("Failed to send transfer: ");
+	(amount, msg)
+}
+
+/// Builds the message of `transfer` from `chain_a` to `chain_b`. Returns the balance of the
+/// transferred asset before the transfer along with the message.
+async fn transfer_msg<A, B>(
+	chain_a: &A,
+	chain_b: &B,
+	transfer: &TestTransfer<A::AssetId>,
+	channel_id: ChannelId,
+	timeout: Option<Timeout>,
+	memo: String,
+) -> (u128, MsgTransfer<PrefixedCoin>)
 where
 	A: TestProvider,
 	A::FinalityEvent: Send + Sync,
@@ -158,16 +452,18 @@ where
 	B::Error: From<A::Error>,
 {
 	let balance = chain_a
-		.query_ibc_balance(asset_a)
+		.query_ibc_balance(transfer.asset_id.clone())
 		.await
 		.expect("Can't query ibc balance")
 		.pop()
 		.expect("No Ibc balances");
 
 	let amount = balance.amount.as_u256().as_u128();
+	let transferred = transfer.amount.unwrap_or((amount * 20) / 100);
+	assert!(transferred <= amount, "Can't transfer {transferred} out of a balance of {amount}");
 	let coin = PrefixedCoin {
 		denom: balance.denom,
-		amount: Amount::from_str(&format!("{}", (amount * 20) / 100)).expect("Infallible"),
+		amount: Amount::from_str(&format!("{transferred}")).expect("Infallible"),
 	};
 
 	let (height_offset, time_offset) = if let Some(timeout) = timeout {
@@ -194,15 +490,15 @@ where
 		source_channel: channel_id,
 		token: coin,
 		sender: chain_a.account_id(),
-		receiver: chain_b.account_id(),
+		receiver: transfer.receiver.clone().unwrap_or_else(|| chain_b.account_id()),
 		timeout_height,
 		timeout_timestamp,
-		memo: "".to_string(),
+		memo,
 	};
-	chain_a.send_transfer(msg.clone()).await.expect("Failed to send transfer: ");
 	(amount, msg)
 }
 
+/// Asserts that a transfer of 20% of `previous_balance` of `asset_id` is acknowledged on `chain`.
 async fn assert_send_transfer<A>(
 	chain: &A,
 	asset_id: A::AssetId,
@@ -211,6 +507,22 @@ async fn assert_send_transfer<A>(
 ) where
 	A: TestProvider,
 	A::FinalityEvent: Send + Sync,
+{
+	let transferred = previous_balance - (previous_balance * 80) / 100;
+	assert_transfer_acknowledged(chain, asset_id, previous_balance, transferred, wait_blocks).await
+}
+
+/// Waits for a packet to be acknowledged on `chain` and asserts that at least `transferred` of
+/// `asset_id` left the relayer's account since its balance was `previous_balance`.
+async fn assert_transfer_acknowledged<A>(
+	chain: &A,
+	asset_id: A::AssetId,
+	previous_balance: u128,
+	transferred: u128,
+	wait_blocks: u64,
+) where
+	A: TestProvider,
+	A::FinalityEvent: Send + Sync,
 {
 	// wait for the acknowledgment
 	let future = chain
@@ -227,15 +539,21 @@ async fn assert_send_transfer<A>(
 	)
 	.await;
 
-	let balance = chain
+	let new_amount = relayer_balance(chain, asset_id).await;
+	assert!(new_amount <= previous_balance - transferred);
+}
+
+/// Balance of `asset_id` of the relayer's account of `chain`.
+async fn relayer_balance<A: TestProvider>(chain: &A, asset_id: A::AssetId) -> u128 {
+	chain
 		.query_ibc_balance(asset_id)
 		.await
 		.expect("Can't query ibc balance")
 		.pop()
-		.expect("No Ibc balances");
-
-	let new_amount = balance.amount.as_u256().as_u128();
-	assert!(new_amount <= (previous_balance * 80) / 100);
+		.expect("No Ibc balances")
+		.amount
+		.as_u256()
+		.as_u128()
 }
 
 /// Send a packet using a height timeout that has already passed
@@ -355,7 +673,8 @@ async fn send_packet_with_connection_delay<A, B>(
 	channel_id_b: ChannelId,
 	asset_a: A::AssetId,
 	asset_b: B::AssetId,
-) where
+) -> Vec<PacketTimeline>
+where
 	A: TestProvider,
 	A::FinalityEvent: Send + Sync,
 	A::Error: From<B::Error>,
@@ -363,16 +682,103 @@ async fn send_packet_with_connection_delay<A, B>(
 	B::FinalityEvent: Send + Sync,
 	B::Error: From<A::Error>,
 {
+	send_transfers_with_connection_delay(
+		chain_a,
+		chain_b,
+		channel_id_a,
+		channel_id_b,
+		TestTransfer::new(asset_a),
+		TestTransfer::new(asset_b),
+	)
+	.await
+}
+
+/// Sends `transfer_a` from chain_a then `transfer_b` from chain_b, and checks that each was
+/// acknowledged after the connection delay. Returns the timelines of the packets, see
+/// [`LatencyReport::finish`].
+async fn send_transfers_with_connection_delay<A, B>(
+	chain_a: &A,
+	chain_b: &B,
+	channel_id_a: ChannelId,
+	channel_id_b: ChannelId,
+	transfer_a: TestTransfer<A::AssetId>,
+	transfer_b: TestTransfer<B::AssetId>,
+) -> Vec<PacketTimeline>
+where
+	A: TestProvider,
+	A::FinalityEvent: Send + Sync,
+	A::Error: From<B::Error>,
+	B: TestProvider,
+	B::FinalityEvent: Send + Sync,
+	B::Error: From<A::Error>,
+{
+	let mut report = LatencyReport::new(format!(
+		"transfers with connection delay between {} and {}",
+		chain_a.name(),
+		chain_b.name()
+	));
 	log::info!(target: "hyperspace", "Sending transfer from {}", chain_a.name());
-	let (previous_balance, ..) =
-		send_transfer(chain_a, chain_b, asset_a.clone(), channel_id_a, None).await;
-	assert_send_transfer(chain_a, asset_a, previous_balance, 220).await;
+	let timer = PacketTimer::start(chain_a, chain_b).await;
+	let (previous_balance, msg) =
+		send_test_transfer(chain_a, chain_b, &transfer_a, channel_id_a, None).await;
+	let transferred = msg.token.amount.as_u256().as_u128();
+	assert_transfer_acknowledged(chain_a, transfer_a.asset_id, previous_balance, transferred, 220)
+		.await;
+	report.record(timer.finish(chain_a, chain_b, &msg).await);
 	log::info!(target: "hyperspace", "Sending transfer from {}", chain_b.name());
-	let (previous_balance, ..) =
-		send_transfer(chain_b, chain_a, asset_b.clone(), channel_id_b, None).await;
-	assert_send_transfer(chain_b, asset_b, previous_balance, 220).await;
+	let timer = PacketTimer::start(chain_b, chain_a).await;
+	let (previous_balance, msg) =
+		send_test_transfer(chain_b, chain_a, &transfer_b, channel_id_b, None).await;
+	let transferred = msg.token.amount.as_u256().as_u128();
+	assert_transfer_acknowledged(chain_b, transfer_b.asset_id, previous_balance, transferred, 220)
+		.await;
+	report.record(timer.finish(chain_b, chain_a, &msg).await);
 	// now send from chain b.
 	log::info!(target: "hyperspace", "🚀🚀 Token Transfer successful with connection delay");
+	report.finish()
+}
+
+/// Send a transfer with a memo and assert the memo is intact in the packet received by the
+/// counterparty.
+async fn send_packet_with_memo_and_assert_receive<A, B>(
+	chain_a: &A,
+	chain_b: &B,
+	asset_a: A::AssetId,
+	channel_id: ChannelId,
+) where
+	A: TestProvider,
+	A::FinalityEvent: Send + Sync,
+	A::Error: From<B::Error>,
+	B: TestProvider,
+	B::FinalityEvent: Send + Sync,
+	B::Error: From<A::Error>,
+{
+	let memo = format!("hyperspace memo round-trip from {}", chain_a.name());
+	log::info!(target: "hyperspace", "Sending transfer with memo from {}", chain_a.name());
+	send_transfer_with_memo(chain_a, chain_b, asset_a, channel_id, None, memo.clone()).await;
+
+	// the packet is returned through a channel, since `timeout_after` drops the future's output
+	let (sender, receiver) = tokio::sync::oneshot::channel();
+	let mut events = chain_b.ibc_events().await.filter_map(move |ev| {
+		future::ready(match ev {
+			IbcEvent::ReceivePacket(ev) if ev.packet.source_channel == channel_id =>
+				Some(ev.packet),
+			_ => None,
+		})
+	});
+	let future = async move {
+		if let Some(packet) = events.next().await {
+			let _ = sender.send(packet);
+		}
+	};
+	timeout_after(chain_b, future, 220, format!("Didn't see ReceivePacket on {}", chain_b.name()))
+		.await;
+
+	let packet = receiver.await.expect("ReceivePacket was seen");
+	let data: PacketData =
+		json::from_slice(&packet.data).expect("Received packet isn't an ics20 packet");
+	assert_eq!(data.memo, memo);
+	log::info!(target: "hyperspace", "🚀🚀 Memo successfully received on {}", chain_b.name());
 }
 
 /// Close a channel
@@ -481,6 +887,31 @@ async fn send_packet_and_assert_timeout_on_channel_close<A, B>(
 	log::info!(target: "hyperspace", "🚀🚀 Timeout packet successfully processed for channel close");
 }
 
+/// Send a transfer with a memo and assert the counterparty receives the memo intact.
+pub async fn ibc_messaging_with_memo<A, B>(
+	chain_a: &mut A,
+	chain_b: &mut B,
+	asset_a: A::AssetId,
+	channel_a: ChannelId,
+) where
+	A: TestProvider,
+	A::FinalityEvent: Send + Sync,
+	A::Error: From<B::Error>,
+	B: TestProvider,
+	B::FinalityEvent: Send + Sync,
+	B::Error: From<A::Error>,
+{
+	let client_a_clone = chain_a.clone();
+	let client_b_clone = chain_b.clone();
+	let handle = tokio::task::spawn(async move {
+		hyperspace_core::relay(client_a_clone, client_b_clone, None, None, None)
+			.await
+			.unwrap()
+	});
+	send_packet_with_memo_and_assert_receive(chain_a, chain_b, asset_a, channel_a).await;
+	handle.abort()
+}
+
 ///
 pub async fn ibc_messaging_packet_height_timeout_with_connection_delay<A, B>(
 	chain_a: &mut A,
@@ -534,7 +965,7 @@ pub async fn ibc_messaging_packet_timestamp_timeout_with_connection_delay<A, B>(
 }
 
 /// Send a packet over a connection with a connection delay and assert the sending chain only sees
-/// the packet after the delay has elapsed.
+/// the packet after the delay has elapsed. Returns the timelines of the packets.
 pub async fn ibc_messaging_with_connection_delay<A, B>(
 	chain_a: &mut A,
 	chain_b: &mut B,
@@ -542,6 +973,70 @@ pub async fn ibc_messaging_with_connection_delay<A, B>(
 	asset_b: B::AssetId,
 	channel_a: ChannelId,
 	channel_b: ChannelId,
+) -> Vec<PacketTimeline>
+where
+	A: TestProvider,
+	A::FinalityEvent: Send + Sync,
+	A::Error: From<B::Error>,
+	B: TestProvider,
+	B::FinalityEvent: Send + Sync,
+	B::Error: From<A::Error>,
+{
+	let client_a_clone = chain_a.clone();
+	let client_b_clone = chain_b.clone();
+	let handle = tokio::task::spawn(async move {
+		hyperspace_core::relay(client_a_clone, client_b_clone, None, None, None)
+			.await
+			.unwrap()
+	});
+	let timelines =
+		send_packet_with_connection_delay(chain_a, chain_b, channel_a, channel_b, asset_a, asset_b)
+			.await;
+	handle.abort();
+	timelines
+}
+
+/// Like [`ibc_messaging_with_connection_delay`], sending `transfer_a` from chain_a and
+/// `transfer_b` from chain_b, whose asset ids, amounts and receivers may differ per direction.
+pub async fn ibc_messaging_with_transfers<A, B>(
+	chain_a: &mut A,
+	chain_b: &mut B,
+	transfer_a: TestTransfer<A::AssetId>,
+	transfer_b: TestTransfer<B::AssetId>,
+	channel_a: ChannelId,
+	channel_b: ChannelId,
+) -> Vec<PacketTimeline>
+where
+	A: TestProvider,
+	A::FinalityEvent: Send + Sync,
+	A::Error: From<B::Error>,
+	B: TestProvider,
+	B::FinalityEvent: Send + Sync,
+	B::Error: From<A::Error>,
+{
+	let client_a_clone = chain_a.clone();
+	let client_b_clone = chain_b.clone();
+	let handle = tokio::task::spawn(async move {
+		hyperspace_core::relay(client_a_clone, client_b_clone, None, None, None)
+			.await
+			.unwrap()
+	});
+	let timelines = send_transfers_with_connection_delay(
+		chain_a, chain_b, channel_a, channel_b, transfer_a, transfer_b,
+	)
+	.await;
+	handle.abort();
+	timelines
+}
+
+/// Sends `count` transfers of `asset_a` from chain_a over `channel_id`, one per block of chain_a,
+/// and waits for all of them to be acknowledged while the relayer runs.
+pub async fn ibc_messaging_with_many_transfers<A, B>(
+	chain_a: &mut A,
+	chain_b: &mut B,
+	asset_a: A::AssetId,
+	channel_id: ChannelId,
+	count: u64,
 ) where
 	A: TestProvider,
 	A::FinalityEvent: Send + Sync,
@@ -557,8 +1052,261 @@ pub async fn ibc_messaging_with_connection_delay<A, B>(
 			.await
 			.unwrap()
 	});
-	send_packet_with_connection_delay(chain_a, chain_b, channel_a, channel_b, asset_a, asset_b)
+	let mut blocks = chain_a.subscribe_blocks().await;
+	let transfer = TestTransfer::new(asset_a).with_amount(1);
+	for _ in 0..count {
+		send_test_transfer(&*chain_a, &*chain_b, &transfer, channel_id, None).await;
+		blocks.next().await;
+	}
+	log::info!(target: "hyperspace", "Sent {count} transfers from {}", chain_a.name());
+
+	// the commitment of a packet is deleted once it's acknowledged
+	let mut waited_blocks = 0;
+	loop {
+		let (latest_height, _) = chain_a.latest_height_and_timestamp().await.unwrap();
+		let unacknowledged = chain_a
+			.query_packet_commitments(latest_height, channel_id, PortId::transfer())
+			.await
+			.expect("Can't query packet commitments");
+		if unacknowledged.is_empty() {
+			break
+		}
+		assert!(
+			waited_blocks < 400,
+			"Packets {unacknowledged:?} of {} weren't acknowledged",
+			chain_a.name()
+		);
+		blocks.next().await;
+		waited_blocks += 1;
+	}
+	handle.abort();
+	log::info!(target: "hyperspace", "🚀🚀 {count} transfers were acknowledged");
+}
+
+/// Sends each of `transfers` of assets native to `chain_a` over the same channel, and asserts
+/// that each is acknowledged and that `chain_b` traces the vouchers of each denom back to
+/// `chain_a`.
+pub async fn ibc_messaging_with_mixed_assets<A, B>(
+	chain_a: &mut A,
+	chain_b: &mut B,
+	transfers: Vec<TestTransfer<A::AssetId>>,
+	channel_a: ChannelId,
+	channel_b: ChannelId,
+) where
+	A: TestProvider,
+	A::FinalityEvent: Send + Sync,
+	A::Error: From<B::Error>,
+	B: TestProvider,
+	B::FinalityEvent: Send + Sync,
+	B::Error: From<A::Error>,
+{
+	let client_a_clone = chain_a.clone();
+	let client_b_clone = chain_b.clone();
+	let handle = tokio::task::spawn(async move {
+		hyperspace_core::relay(client_a_clone, client_b_clone, None, None, None)
+			.await
+			.unwrap()
+	});
+	let mut denoms = vec![];
+	for transfer in transfers {
+		let (previous_balance, msg) =
+			send_test_transfer(&*chain_a, &*chain_b, &transfer, channel_a, None).await;
+		let denom = msg.token.denom.to_string();
+		log::info!(target: "hyperspace", "Sent {} {denom} from {}", msg.token.amount, chain_a.name());
+		let transferred = msg.token.amount.as_u256().as_u128();
+		assert_transfer_acknowledged(
+			&*chain_a,
+			transfer.asset_id,
+			previous_balance,
+			transferred,
+			220,
+		)
 		.await;
+		assert_denom_trace(&*chain_b, channel_b, &denom).await;
+		denoms.push(denom);
+	}
+	denoms.sort();
+	denoms.dedup();
+	assert!(denoms.len() > 1, "The transfers should be of different denoms: {denoms:?}");
+	log::info!(target: "hyperspace", "🚀🚀 Token Transfers of {denoms:?} successful over the same channel");
+	handle.abort()
+}
+
+/// Relays transfers over two connections between the same clients, one without delay and one
+/// with a 120 seconds delay, from a single relay loop, and asserts that packets are relayed on
+/// both connections.
+pub async fn ibc_messaging_on_multiple_connections<A, B>(
+	chain_a: &mut A,
+	chain_b: &mut B,
+	asset_a: A::AssetId,
+	asset_b: B::AssetId,
+) where
+	A: TestProvider,
+	A::FinalityEvent: Send + Sync,
+	A::Error: From<B::Error>,
+	B: TestProvider,
+	B::FinalityEvent: Send + Sync,
+	B::Error: From<A::Error>,
+{
+	let mut paths = vec![];
+	for delay in [Duration::ZERO, Duration::from_secs(120)] {
+		let (handle, channel_a, channel_b, connection_id_a, connection_id_b, _) =
+			setup_path(chain_a, chain_b, PathParams::transfer(delay)).await;
+		handle.abort();
+		log::info!(target: "hyperspace", "============ Connection with a {delay:?} delay: ConnectionId({connection_id_a}), ChannelId({channel_a}) ============");
+		chain_a.add_connection_id(connection_id_a.clone());
+		chain_b.add_connection_id(connection_id_b.clone());
+		chain_a
+			.add_channel_to_connection_whitelist(connection_id_a, (channel_a, PortId::transfer()));
+		chain_b
+			.add_channel_to_connection_whitelist(connection_id_b, (channel_b, PortId::transfer()));
+		paths.push((channel_a, channel_b));
+	}
+	assert_eq!(chain_a.connection_ids().len(), 2);
+	assert_eq!(chain_b.connection_ids().len(), 2);
+
+	let client_a_clone = chain_a.clone();
+	let client_b_clone = chain_b.clone();
+	let handle = tokio::task::spawn(async move {
+		hyperspace_core::relay(client_a_clone, client_b_clone, None, None, None)
+			.await
+			.unwrap()
+	});
+	for (channel_a, channel_b) in paths {
+		send_packet_with_connection_delay(
+			&*chain_a,
+			&*chain_b,
+			channel_a,
+			channel_b,
+			asset_a.clone(),
+			asset_b.clone(),
+		)
+		.await;
+	}
+	log::info!(target: "hyperspace", "🚀🚀 Token Transfers successful on both connections");
+	handle.abort()
+}
+
+/// Send a packet over a connection with a connection delay while both chains skip optional
+/// client updates, and assert the packet is still received before it times out. The timeout
+/// leaves little more than the delay to relay the packet, so the update proving it has to be
+/// sent as soon as it is due.
+pub async fn ibc_messaging_with_connection_delay_skipping_optional_updates<A, B>(
+	chain_a: &mut A,
+	chain_b: &mut B,
+	asset_a: A::AssetId,
+	channel_a: ChannelId,
+	connection_delay: Duration,
+) where
+	A: TestProvider,
+	A::FinalityEvent: Send + Sync,
+	A::Error: From<B::Error>,
+	B: TestProvider,
+	B::FinalityEvent: Send + Sync,
+	B::Error: From<A::Error>,
+{
+	let mut client_a_clone = chain_a.clone();
+	let mut client_b_clone = chain_b.clone();
+	client_a_clone.common_state_mut().skip_optional_client_updates = true;
+	client_b_clone.common_state_mut().skip_optional_client_updates = true;
+	let handle = tokio::task::spawn(async move {
+		hyperspace_core::relay(client_a_clone, client_b_clone, None, None, None)
+			.await
+			.unwrap()
+	});
+	let timeout =
+		Timeout::Offset { timestamp: Some(connection_delay.as_secs() * 2), height: Some(1000) };
+	let (previous_balance, ..) =
+		send_transfer(chain_a, chain_b, asset_a.clone(), channel_a, Some(timeout)).await;
+	assert_send_transfer(chain_a, asset_a, previous_balance, 220).await;
+	log::info!(target: "hyperspace", "🚀🚀 Token Transfer successful with connection delay and skipped optional updates");
+	handle.abort()
+}
+
+/// Send an incentivized transfer from `sender_a`, an account of `chain_a` other than the
+/// relayer's, over a fee-enabled channel, while the relayer runs with `payee_a`, a third account of
+/// `chain_a`, as its counterparty payee. Once the packet is acknowledged, assert that the payee
+/// received the receive fee, that the relayer's balance increased with the acknowledgement fee,
+/// and that the timeout fee was refunded to the sender.
+pub async fn ibc_messaging_with_relayer_fees<A, B>(
+	chain_a: &mut A,
+	chain_b: &mut B,
+	sender_a: &A,
+	payee_a: &A,
+	asset_a: A::AssetId,
+	channel_a: ChannelId,
+) where
+	A: TestProvider,
+	A::FinalityEvent: Send + Sync,
+	A::Error: From<B::Error>,
+	B: TestProvider,
+	B::FinalityEvent: Send + Sync,
+	B::Error: From<A::Error>,
+{
+	// larger than the transaction fees paid by the relayer while relaying the packet
+	const RELAYER_FEE: u128 = 1_000_000;
+
+	let relayer = chain_a.account_id();
+	assert_ne!(sender_a.account_id(), relayer, "The sender must not be the relayer");
+	assert_ne!(payee_a.account_id(), relayer, "The payee must not be the relayer");
+	assert_ne!(payee_a.account_id(), sender_a.account_id(), "The payee must not be the sender");
+
+	let mut client_a = chain_a.clone();
+	client_a.common_state_mut().fee_payee_address = Some(payee_a.account_id().to_string());
+	let client_b = chain_b.clone();
+	let handle = tokio::task::spawn(async move {
+		hyperspace_core::relay(client_a, client_b, None, None, None).await.unwrap()
+	});
+
+	let transfer = TestTransfer::new(asset_a.clone());
+	let (_, msg) = transfer_msg(sender_a, chain_b, &transfer, channel_a, None, String::new()).await;
+	let coin =
+		|amount: u128| Coin { denom: msg.token.denom.to_string(), amount: amount.to_string() };
+	let fee = Fee {
+		recv_fee: vec![coin(RELAYER_FEE)],
+		ack_fee: vec![coin(RELAYER_FEE)],
+		timeout_fee: vec![coin(RELAYER_FEE)],
+	};
+	let payee_balance_before = relayer_balance(payee_a, asset_a.clone()).await;
+	log::info!(target: "hyperspace", "Sending incentivized transfer from {} on {}", sender_a.account_id(), chain_a.name());
+	sender_a
+		.send_transfer_with_fee(msg.clone(), fee)
+		.await
+		.expect("Failed to send incentivized transfer");
+	let sender_balance_after_send = relayer_balance(sender_a, asset_a.clone()).await;
+	let relayer_balance_after_send = relayer_balance(chain_a, asset_a.clone()).await;
+
+	let future = chain_a
+		.ibc_events()
+		.await
+		.skip_while(|ev| future::ready(!matches!(ev, IbcEvent::AcknowledgePacket(_))))
+		.take(1)
+		.collect::<Vec<_>>();
+	timeout_after(
+		chain_a,
+		future,
+		220,
+		format!("Didn't see AcknowledgePacket on {}", chain_a.name()),
+	)
+	.await;
+	let payee_balance_after_ack = relayer_balance(payee_a, asset_a.clone()).await;
+	assert_eq!(
+		payee_balance_after_ack - payee_balance_before,
+		RELAYER_FEE,
+		"The payee wasn't paid the receive fee"
+	);
+	let relayer_balance_after_ack = relayer_balance(chain_a, asset_a.clone()).await;
+	assert!(
+		relayer_balance_after_ack > relayer_balance_after_send,
+		"The relayer wasn't paid the acknowledgement fee: {relayer_balance_after_send} before the acknowledgement, {relayer_balance_after_ack} after"
+	);
+	let sender_balance_after_ack = relayer_balance(sender_a, asset_a).await;
+	assert_eq!(
+		sender_balance_after_ack - sender_balance_after_send,
+		RELAYER_FEE,
+		"The timeout fee wasn't refunded to the sender"
+	);
+	log::info!(target: "hyperspace", "🚀🚀 Relayer paid {} and payee paid {RELAYER_FEE} for relaying an incentivized transfer", relayer_balance_after_ack - relayer_balance_after_send);
 	handle.abort()
 }
 
@@ -572,7 +1320,7 @@ where
 	B::FinalityEvent: Send + Sync,
 	B::Error: From<A::Error>,
 {
-	let (handle, channel_id, channel_b, connection_id_a, connection_id_b) =
+	let (handle, channel_id, channel_b, connection_id_a, connection_id_b, _) =
 		setup_connection_and_channel(chain_a, chain_b, Duration::from_secs(60 * 2)).await;
 	handle.abort();
 
@@ -618,6 +1366,116 @@ pub async fn ibc_messaging_packet_timeout_on_channel_close<A, B>(
 	handle.abort()
 }
 
+/// Stops the relayer, sends a transfer while it is down and asserts that the transfer is relayed
+/// once the relayer resumes with the state it saved before stopping.
+pub async fn ibc_messaging_after_downtime<A, B>(
+	chain_a: &mut A,
+	chain_b: &mut B,
+	asset_a: A::AssetId,
+	channel_a: ChannelId,
+) where
+	A: TestProvider,
+	A::FinalityEvent: Send + Sync,
+	A::Error: From<B::Error>,
+	B: TestProvider,
+	B::FinalityEvent: Send + Sync,
+	B::Error: From<A::Error>,
+{
+	let store: Arc<dyn RelayerStore> = Arc::new(InMemoryRelayerStore::default());
+	let mut client_a = chain_a.clone();
+	let mut client_b = chain_b.clone();
+	client_a.common_state_mut().relayer_store = Some(store.clone());
+	client_b.common_state_mut().relayer_store = Some(store.clone());
+
+	let (client_a_clone, client_b_clone) = (client_a.clone(), client_b.clone());
+	let handle = tokio::task::spawn(async move {
+		hyperspace_core::relay(client_a_clone, client_b_clone, None, None, None)
+			.await
+			.unwrap()
+	});
+	// wait for the relayer to save the state of the channel
+	let mut blocks = chain_a.subscribe_blocks().await.take(50);
+	while store.load(chain_a.name(), channel_a, &PortId::transfer()).unwrap().is_none() {
+		blocks.next().await.expect("The relayer didn't save the state of the channel");
+	}
+	handle.abort();
+
+	log::info!(target: "hyperspace", "Sending transfer from {} while the relayer is down", chain_a.name());
+	let (previous_balance, ..) =
+		send_transfer(chain_a, chain_b, asset_a.clone(), channel_a, None).await;
+
+	let handle = tokio::task::spawn(async move {
+		hyperspace_core::relay(client_a, client_b, None, None, None).await.unwrap()
+	});
+	assert_send_transfer(chain_a, asset_a, previous_balance, 220).await;
+	log::info!(target: "hyperspace", "🚀🚀 Transfer sent while the relayer was down was relayed on resume");
+	handle.abort()
+}
+
+/// Opens a channel on the relayed connection without whitelisting it, the way a third party
+/// would, while the relayer runs with `auto_whitelist_channels`, and asserts that a transfer
+/// over the new channel is relayed without restarting the relayer.
+pub async fn ibc_messaging_on_auto_whitelisted_channel<A, B>(
+	chain_a: &mut A,
+	chain_b: &mut B,
+	asset_a: A::AssetId,
+) where
+	A: TestProvider,
+	A::FinalityEvent: Send + Sync,
+	A::Error: From<B::Error>,
+	B: TestProvider,
+	B::FinalityEvent: Send + Sync,
+	B::Error: From<A::Error>,
+{
+	let connection_id = chain_a.connection_id().expect("Connection id should be set");
+	let mut client_a_clone = chain_a.clone();
+	let mut client_b_clone = chain_b.clone();
+	client_a_clone.common_state_mut().auto_whitelist_channels = true;
+	client_b_clone.common_state_mut().auto_whitelist_channels = true;
+	let handle = tokio::task::spawn(async move {
+		hyperspace_core::relay(client_a_clone, client_b_clone, None, None, None)
+			.await
+			.unwrap()
+	});
+
+	// subscribe before the handshake starts, so that its end can't be missed
+	let future = chain_b
+		.ibc_events()
+		.await
+		.skip_while(|ev| future::ready(!matches!(ev, IbcEvent::OpenConfirmChannel(_))))
+		.take(1)
+		.collect::<Vec<_>>();
+
+	let channel = ChannelEnd::new(
+		State::Init,
+		Order::Unordered,
+		Counterparty::new(PortId::transfer(), None),
+		vec![connection_id],
+		Version::new(VERSION.to_string()),
+	);
+	let msg = MsgChannelOpenInit::new(PortId::transfer(), channel, chain_a.account_id());
+	let msg = Any { type_url: msg.type_url(), value: msg.encode_vec().unwrap() };
+	chain_a.submit(vec![msg]).await.unwrap();
+
+	let mut events = timeout_future(
+		future,
+		30 * 60,
+		format!("Didn't see OpenConfirmChannel on {}", chain_b.name()),
+	)
+	.await;
+	let channel_id = match events.pop() {
+		Some(IbcEvent::OpenConfirmChannel(chan)) => chan.counterparty_channel_id.unwrap(),
+		got => panic!("Last event should be OpenConfirmChannel: {got:?}"),
+	};
+	log::info!(target: "hyperspace", "============ Channel opened by a third party: ChannelId({channel_id}) ============");
+
+	let (previous_balance, ..) =
+		send_transfer(chain_a, chain_b, asset_a.clone(), channel_id, None).await;
+	assert_send_transfer(chain_a, asset_a, previous_balance, 220).await;
+	log::info!(target: "hyperspace", "🚀🚀 Token Transfer successful over an auto-whitelisted channel");
+	handle.abort()
+}
+
 pub async fn client_synchronization_test<A, B>(chain_a: &mut A, chain_b: &mut B)
 where
 	A: TestProvider,
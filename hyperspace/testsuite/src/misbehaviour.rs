@@ -4,6 +4,7 @@ use grandpa_client_primitives::{
 	justification::GrandpaJustification, parachain_header_storage_key, Commit, FinalityProof,
 	ParachainHeaderProofs,
 };
+use hyperspace_core::archive::MisbehaviourArchive;
 use hyperspace_primitives::{mock::LocalClientTypes, TestProvider};
 use ibc::{
 	core::ics02_client::{height::Height, msgs::update_client::MsgUpdateAnyClient},
@@ -38,10 +39,17 @@ where
 	B::FinalityEvent: Send + Sync,
 	B::Error: From<A::Error>,
 {
+	let archive_dir = std::env::temp_dir()
+		.join(format!("hyperspace-misbehaviour-archive-{}", std::process::id()));
+	let _ = std::fs::remove_dir_all(&archive_dir);
+	let archive = MisbehaviourArchive::open(&archive_dir).unwrap();
 	let client_a_clone = chain_a.clone();
 	let client_b_clone = chain_b.clone();
+	let fisherman_archive = archive.clone();
 	let handle = tokio::task::spawn(async move {
-		hyperspace_core::fish(client_a_clone, client_b_clone).await.unwrap()
+		hyperspace_core::fish(client_a_clone, client_b_clone, None, None, Some(fisherman_archive))
+			.await
+			.unwrap()
 	});
 	info!("Waiting for the next block...");
 
@@ -194,7 +202,7 @@ where
 	}
 
 	let grandpa_header = GrandpaHeader {
-		finality_proof,
+		finality_proof: finality_proof.clone(),
 		parachain_headers,
 		height: Height::new(client_state.para_id as u64, parachain_header.number as u64),
 	};
@@ -224,5 +232,28 @@ where
 		.expect("timeout")
 		.expect("failed to receive misbehaviour event");
 
+	// the evidence is archived before it's submitted, and again once it's submitted
+	let report = timeout(Duration::from_secs(60), async {
+		loop {
+			if let Some(report) =
+				archive.reports_since(0).unwrap().pop().filter(|report| report.tx_id.is_some())
+			{
+				return report
+			}
+			tokio::time::sleep(Duration::from_secs(1)).await;
+		}
+	})
+	.await
+	.expect("misbehaviour wasn't archived");
 	handle.abort();
+
+	assert_eq!(report.client_id, chain_b.client_id());
+	assert_eq!(report.chain, chain_a.name());
+	assert_eq!(report.first_height, u64::from(header.number));
+	let first_proof = FinalityProof::<RelayChainHeader>::decode(&mut &*report.first_proof).unwrap();
+	assert_eq!(first_proof, finality_proof);
+	let second_proof =
+		FinalityProof::<RelayChainHeader>::decode(&mut &*report.second_proof).unwrap();
+	assert_ne!(second_proof.block, finality_proof.block);
+	let _ = std::fs::remove_dir_all(&archive_dir);
 }
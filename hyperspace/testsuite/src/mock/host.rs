@@ -0,0 +1,923 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The host context of a mock chain, through which the handlers of `ibc` and its ICS-20 module
+//! read and write the store of the chain.
+
+use super::{
+	ledger::{block_timestamp, height, REVISION_NUMBER},
+	store::{
+		balance_path, connection_channels_path, consensus_state_path, denom_trace_path,
+		processed_height_path, processed_time_path, raw_ack_path, received_packet_path,
+		sent_packet_path, State, Store, CHANNEL_COUNTER, CLIENT_COUNTER, CONNECTION_COUNTER,
+	},
+};
+use hyperspace_primitives::utils::ibc_denom_of;
+use ibc::{
+	applications::transfer::{
+		context::{
+			on_acknowledgement_packet, on_recv_packet, on_timeout_packet, BankKeeper, Ics20Context,
+			Ics20Keeper, Ics20Reader,
+		},
+		error::Error as Ics20Error,
+		PrefixedCoin, MODULE_ID_STR,
+	},
+	core::{
+		ics02_client::{
+			client_state::{ClientState as _, ClientType},
+			context::{ClientKeeper, ClientReader, ClientTypes},
+			error::Error as Ics02Error,
+		},
+		ics03_connection::{
+			connection::ConnectionEnd,
+			context::{ConnectionKeeper, ConnectionReader},
+			error::Error as Ics03Error,
+		},
+		ics04_channel::{
+			channel::{ChannelEnd, Counterparty, Order},
+			commitment::{AcknowledgementCommitment, PacketCommitment},
+			context::{ChannelKeeper, ChannelReader},
+			error::Error as Ics04Error,
+			msgs::acknowledgement::Acknowledgement,
+			packet::{Packet, Receipt, Sequence},
+			Version,
+		},
+		ics05_port::{context::PortReader, error::Error as Ics05Error},
+		ics23_commitment::commitment::CommitmentPrefix,
+		ics24_host::{
+			identifier::{ChannelId, ClientId, ConnectionId, PortId},
+			path::{
+				AcksPath, ChannelEndsPath, ClientConnectionsPath, ClientStatePath, ClientTypePath,
+				CommitmentsPath, ConnectionsPath, ReceiptsPath, SeqAcksPath, SeqRecvsPath,
+				SeqSendsPath,
+			},
+		},
+		ics26_routing::context::{
+			Ics26Context, Module, ModuleCallbackContext, ModuleId, ModuleOutputBuilder,
+			ReaderContext, Router,
+		},
+	},
+	mock::{
+		client_state::{MockClientState, MockConsensusState},
+		header::MockHeader,
+	},
+	signer::Signer,
+	timestamp::Timestamp,
+	Height,
+};
+use ibc_rpc::PacketInfo;
+use pallet_ibc::light_clients::{AnyClient, AnyClientMessage, AnyClientState, AnyConsensusState};
+use sha2::{Digest, Sha256};
+use std::{
+	fmt::Display,
+	sync::{Arc, Mutex, MutexGuard},
+	time::Duration,
+};
+use tendermint_proto::Protobuf;
+
+/// The store of a mock chain and the block it's executing transactions in.
+#[derive(Debug)]
+pub struct HostState {
+	pub store: Store,
+	/// Height of the pending block
+	pub height: u64,
+	pub block_time: Duration,
+	pub prefix: CommitmentPrefix,
+	/// Version the transfer module opens the channels proposed by the counterparty with, instead
+	/// of the counterparty version
+	pub transfer_version: Option<Version>,
+}
+
+/// Context of the `ibc` handlers. Its clones, including the ones held by the modules of its
+/// router, share the same state.
+#[derive(Clone, Debug)]
+pub struct Host {
+	state: Arc<Mutex<HostState>>,
+	router: HostRouter,
+}
+
+impl PartialEq for Host {
+	fn eq(&self, other: &Self) -> bool {
+		Arc::ptr_eq(&self.state, &other.state)
+	}
+}
+
+impl Eq for Host {}
+
+impl Host {
+	pub fn new(state: HostState) -> Self {
+		Self::with_state(Arc::new(Mutex::new(state)))
+	}
+
+	fn with_state(state: Arc<Mutex<HostState>>) -> Self {
+		let router = HostRouter { transfer: TransferModule { state: state.clone() } };
+		Self { state, router }
+	}
+
+	/// Returns the store, leaving the context with an empty one.
+	pub fn take_store(&self) -> Store {
+		std::mem::take(&mut self.lock().store)
+	}
+
+	fn lock(&self) -> MutexGuard<'_, HostState> {
+		self.state.lock().unwrap()
+	}
+
+	/// Runs `f` on the store of the pending block. `f` mustn't use the context itself.
+	pub fn view<T>(&self, f: impl FnOnce(State<'_>) -> T) -> T {
+		let state = self.lock();
+		f(State { store: &state.store, at: None })
+	}
+
+	fn get(&self, path: impl Display) -> Option<Vec<u8>> {
+		self.view(|state| state.get(path))
+	}
+
+	fn set(&self, path: impl Display, value: Vec<u8>) {
+		self.lock().store.set(path.to_string(), Some(value));
+	}
+
+	fn remove(&self, path: impl Display) {
+		self.lock().store.set(path.to_string(), None);
+	}
+
+	fn sequence(&self, path: impl Display) -> Option<u64> {
+		let value = self.get(path)?;
+		<[u8; 8]>::try_from(value.as_slice()).ok().map(u64::from_be_bytes)
+	}
+
+	fn set_sequence(&self, path: impl Display, sequence: u64) {
+		self.set(path, sequence.to_be_bytes().to_vec());
+	}
+
+	fn increase_counter(&self, path: &str) {
+		let counter = self.sequence(path).unwrap_or_default();
+		self.set_sequence(path, counter + 1);
+	}
+
+	fn timestamp_at(&self, height: u64) -> Timestamp {
+		let block_time = self.lock().block_time;
+		Timestamp::from_nanoseconds(block_timestamp(block_time, height))
+			.expect("The timestamps of the mock chains are after the epoch")
+	}
+
+	fn balance(&self, account: &str, denom: &str) -> u128 {
+		self.view(|state| state.balance(account, denom))
+	}
+
+	fn set_balance(&self, account: &str, denom: &str, balance: u128) {
+		self.set(balance_path(account, denom), balance.to_be_bytes().to_vec());
+	}
+
+	fn packet_info(
+		&self,
+		packet: &Packet,
+		ack: Option<Vec<u8>>,
+		ordering: Order,
+	) -> Result<Vec<u8>, Ics04Error> {
+		let info = PacketInfo {
+			height: Some(self.lock().height),
+			sequence: packet.sequence.into(),
+			source_port: packet.source_port.to_string(),
+			source_channel: packet.source_channel.to_string(),
+			destination_port: packet.destination_port.to_string(),
+			destination_channel: packet.destination_channel.to_string(),
+			channel_order: ordering.as_str().to_string(),
+			data: packet.data.clone(),
+			timeout_height: packet.timeout_height.into(),
+			timeout_timestamp: packet.timeout_timestamp.nanoseconds(),
+			ack,
+		};
+		json::to_vec(&info).map_err(|e| Ics04Error::implementation_specific(e.to_string()))
+	}
+
+	fn json_list<T: json_list::Item>(&self, path: impl Display) -> Vec<T> {
+		self.get(path)
+			.and_then(|value| json::from_slice::<Vec<String>>(&value).ok())
+			.unwrap_or_default()
+			.iter()
+			.filter_map(|item| T::parse(item))
+			.collect()
+	}
+
+	fn push_json_list<T: json_list::Item>(&self, path: impl Display, item: &T) {
+		let path = path.to_string();
+		let mut items = self
+			.get(&path)
+			.and_then(|value| json::from_slice::<Vec<String>>(&value).ok())
+			.unwrap_or_default();
+		items.push(item.format());
+		self.set(path, json::to_vec(&items).expect("A list of strings can be encoded"));
+	}
+}
+
+/// The lists stored by the context, encoded as JSON lists of strings.
+mod json_list {
+	use ibc::core::ics24_host::identifier::{ChannelId, ConnectionId, PortId};
+	use std::str::FromStr;
+
+	pub trait Item: Sized {
+		fn format(&self) -> String;
+		fn parse(item: &str) -> Option<Self>;
+	}
+
+	impl Item for ConnectionId {
+		fn format(&self) -> String {
+			self.to_string()
+		}
+
+		fn parse(item: &str) -> Option<Self> {
+			ConnectionId::from_str(item).ok()
+		}
+	}
+
+	impl Item for (PortId, ChannelId) {
+		fn format(&self) -> String {
+			format!("{}/{}", self.0, self.1)
+		}
+
+		fn parse(item: &str) -> Option<Self> {
+			let (port_id, channel_id) = item.split_once('/')?;
+			Some((PortId::from_str(port_id).ok()?, ChannelId::from_str(channel_id).ok()?))
+		}
+	}
+}
+
+impl ClientTypes for Host {
+	type AnyClientMessage = AnyClientMessage;
+	type AnyClientState = AnyClientState;
+	type AnyConsensusState = AnyConsensusState;
+	type ClientDef = AnyClient;
+}
+
+impl ClientReader for Host {
+	fn client_type(&self, client_id: &ClientId) -> Result<ClientType, Ics02Error> {
+		let value = self
+			.get(ClientTypePath(client_id.clone()))
+			.ok_or_else(|| Ics02Error::client_not_found(client_id.clone()))?;
+		String::from_utf8(value).map_err(|e| Ics02Error::implementation_specific(e.to_string()))
+	}
+
+	fn client_state(&self, client_id: &ClientId) -> Result<AnyClientState, Ics02Error> {
+		let value = self
+			.get(ClientStatePath(client_id.clone()))
+			.ok_or_else(|| Ics02Error::client_not_found(client_id.clone()))?;
+		AnyClientState::decode_vec(&value)
+			.map_err(|e| Ics02Error::implementation_specific(e.to_string()))
+	}
+
+	fn consensus_state(
+		&self,
+		client_id: &ClientId,
+		height: Height,
+	) -> Result<AnyConsensusState, Ics02Error> {
+		let value = self
+			.get(consensus_state_path(client_id, height))
+			.ok_or_else(|| Ics02Error::consensus_state_not_found(client_id.clone(), height))?;
+		AnyConsensusState::decode_vec(&value)
+			.map_err(|e| Ics02Error::implementation_specific(e.to_string()))
+	}
+
+	fn host_client_type(&self) -> String {
+		MockClientState::client_type()
+	}
+
+	fn next_consensus_state(
+		&self,
+		client_id: &ClientId,
+		height: Height,
+	) -> Result<Option<AnyConsensusState>, Ics02Error> {
+		let heights = self.view(|state| state.consensus_heights(client_id));
+		match heights.into_iter().find(|next| *next > height) {
+			Some(next) => self.consensus_state(client_id, next).map(Some),
+			None => Ok(None),
+		}
+	}
+
+	fn prev_consensus_state(
+		&self,
+		client_id: &ClientId,
+		height: Height,
+	) -> Result<Option<AnyConsensusState>, Ics02Error> {
+		let heights = self.view(|state| state.consensus_heights(client_id));
+		match heights.into_iter().rev().find(|prev| *prev < height) {
+			Some(prev) => self.consensus_state(client_id, prev).map(Some),
+			None => Ok(None),
+		}
+	}
+
+	fn host_height(&self) -> Height {
+		height(self.lock().height)
+	}
+
+	fn host_timestamp(&self) -> Timestamp {
+		let height = self.lock().height;
+		self.timestamp_at(height)
+	}
+
+	/// The consensus state of the mock clients of the chain at `height`, which the mock headers
+	/// of its blocks determine.
+	fn host_consensus_state(
+		&self,
+		height: Height,
+		_proof: Option<Vec<u8>>,
+		_client_state: &AnyClientState,
+	) -> Result<AnyConsensusState, Ics02Error> {
+		if height.revision_number != REVISION_NUMBER || height > self.host_height() {
+			return Err(Ics02Error::implementation_specific(format!(
+				"no block at {height}, the chain is at {}",
+				self.host_height()
+			)))
+		}
+		let header = MockHeader { height, timestamp: self.timestamp_at(height.revision_height) };
+		Ok(AnyConsensusState::Mock(MockConsensusState::new(header)))
+	}
+
+	fn client_counter(&self) -> Result<u64, Ics02Error> {
+		Ok(self.sequence(CLIENT_COUNTER).unwrap_or_default())
+	}
+}
+
+impl ClientKeeper for Host {
+	fn store_client_type(
+		&mut self,
+		client_id: ClientId,
+		client_type: ClientType,
+	) -> Result<(), Ics02Error> {
+		self.set(ClientTypePath(client_id), client_type.into_bytes());
+		Ok(())
+	}
+
+	fn store_client_state(
+		&mut self,
+		client_id: ClientId,
+		client_state: AnyClientState,
+	) -> Result<(), Ics02Error> {
+		self.set(
+			ClientStatePath(client_id),
+			client_state.encode_vec().map_err(Ics02Error::encode)?,
+		);
+		Ok(())
+	}
+
+	fn store_consensus_state(
+		&mut self,
+		client_id: ClientId,
+		height: Height,
+		consensus_state: AnyConsensusState,
+	) -> Result<(), Ics02Error> {
+		let value = consensus_state.encode_vec().map_err(Ics02Error::encode)?;
+		self.set(consensus_state_path(&client_id, height), value);
+		Ok(())
+	}
+
+	fn increase_client_counter(&mut self) {
+		self.increase_counter(CLIENT_COUNTER)
+	}
+
+	fn store_update_time(
+		&mut self,
+		client_id: ClientId,
+		height: Height,
+		timestamp: Timestamp,
+	) -> Result<(), Ics02Error> {
+		self.set_sequence(processed_time_path(&client_id, height), timestamp.nanoseconds());
+		Ok(())
+	}
+
+	fn store_update_height(
+		&mut self,
+		client_id: ClientId,
+		height: Height,
+		host_height: Height,
+	) -> Result<(), Ics02Error> {
+		self.set_sequence(processed_height_path(&client_id, height), host_height.revision_height);
+		Ok(())
+	}
+
+	/// The mock chains are only tracked by mock clients.
+	fn validate_self_client(&self, client_state: &AnyClientState) -> Result<(), Ics02Error> {
+		match client_state {
+			AnyClientState::Mock(_) => Ok(()),
+			client_state => Err(Ics02Error::implementation_specific(format!(
+				"the mock chains can't be tracked by {} clients",
+				client_state.client_type()
+			))),
+		}
+	}
+}
+
+impl ConnectionReader for Host {
+	fn minimum_delay_period(&self) -> Duration {
+		Duration::ZERO
+	}
+
+	fn connection_end(&self, connection_id: &ConnectionId) -> Result<ConnectionEnd, Ics03Error> {
+		let value = self
+			.get(ConnectionsPath(connection_id.clone()))
+			.ok_or_else(|| Ics03Error::connection_not_found(connection_id.clone()))?;
+		ConnectionEnd::decode_vec(&value)
+			.map_err(|e| Ics03Error::implementation_specific(e.to_string()))
+	}
+
+	fn host_oldest_height(&self) -> Height {
+		height(1)
+	}
+
+	fn commitment_prefix(&self) -> CommitmentPrefix {
+		self.lock().prefix.clone()
+	}
+
+	fn connection_counter(&self) -> Result<u64, Ics03Error> {
+		Ok(self.sequence(CONNECTION_COUNTER).unwrap_or_default())
+	}
+}
+
+impl ConnectionKeeper for Host {
+	fn store_connection(
+		&mut self,
+		connection_id: ConnectionId,
+		connection_end: &ConnectionEnd,
+	) -> Result<(), Ics03Error> {
+		let value = connection_end
+			.encode_vec()
+			.map_err(|e| Ics03Error::implementation_specific(e.to_string()))?;
+		self.set(ConnectionsPath(connection_id), value);
+		Ok(())
+	}
+
+	fn store_connection_to_client(
+		&mut self,
+		connection_id: ConnectionId,
+		client_id: &ClientId,
+	) -> Result<(), Ics03Error> {
+		self.push_json_list(ClientConnectionsPath(client_id.clone()), &connection_id);
+		Ok(())
+	}
+
+	fn increase_connection_counter(&mut self) {
+		self.increase_counter(CONNECTION_COUNTER)
+	}
+}
+
+impl ChannelReader for Host {
+	fn channel_end(
+		&self,
+		(port_id, channel_id): &(PortId, ChannelId),
+	) -> Result<ChannelEnd, Ics04Error> {
+		let value = self
+			.get(ChannelEndsPath(port_id.clone(), *channel_id))
+			.ok_or_else(|| Ics04Error::channel_not_found(port_id.clone(), *channel_id))?;
+		ChannelEnd::decode_vec(&value)
+			.map_err(|e| Ics04Error::implementation_specific(e.to_string()))
+	}
+
+	fn connection_channels(
+		&self,
+		connection_id: &ConnectionId,
+	) -> Result<Vec<(PortId, ChannelId)>, Ics04Error> {
+		Ok(self.json_list(connection_channels_path(connection_id)))
+	}
+
+	fn get_next_sequence_send(
+		&self,
+		(port_id, channel_id): &(PortId, ChannelId),
+	) -> Result<Sequence, Ics04Error> {
+		self.sequence(SeqSendsPath(port_id.clone(), *channel_id))
+			.map(Sequence::from)
+			.ok_or_else(|| Ics04Error::missing_next_send_seq((port_id.clone(), *channel_id)))
+	}
+
+	fn get_next_sequence_recv(
+		&self,
+		(port_id, channel_id): &(PortId, ChannelId),
+	) -> Result<Sequence, Ics04Error> {
+		self.sequence(SeqRecvsPath(port_id.clone(), *channel_id))
+			.map(Sequence::from)
+			.ok_or_else(|| Ics04Error::missing_next_recv_seq((port_id.clone(), *channel_id)))
+	}
+
+	fn get_next_sequence_ack(
+		&self,
+		(port_id, channel_id): &(PortId, ChannelId),
+	) -> Result<Sequence, Ics04Error> {
+		self.sequence(SeqAcksPath(port_id.clone(), *channel_id))
+			.map(Sequence::from)
+			.ok_or_else(|| Ics04Error::missing_next_ack_seq((port_id.clone(), *channel_id)))
+	}
+
+	fn get_packet_commitment(
+		&self,
+		(port_id, channel_id, sequence): &(PortId, ChannelId, Sequence),
+	) -> Result<PacketCommitment, Ics04Error> {
+		let path = CommitmentsPath {
+			port_id: port_id.clone(),
+			channel_id: *channel_id,
+			sequence: *sequence,
+		};
+		self.get(path)
+			.map(PacketCommitment::from)
+			.ok_or_else(|| Ics04Error::packet_commitment_not_found(*sequence))
+	}
+
+	fn get_packet_receipt(
+		&self,
+		(port_id, channel_id, sequence): &(PortId, ChannelId, Sequence),
+	) -> Result<Receipt, Ics04Error> {
+		let path =
+			ReceiptsPath { port_id: port_id.clone(), channel_id: *channel_id, sequence: *sequence };
+		self.get(path)
+			.map(|_| Receipt::Ok)
+			.ok_or_else(|| Ics04Error::packet_receipt_not_found(*sequence))
+	}
+
+	fn get_packet_acknowledgement(
+		&self,
+		(port_id, channel_id, sequence): &(PortId, ChannelId, Sequence),
+	) -> Result<AcknowledgementCommitment, Ics04Error> {
+		let path =
+			AcksPath { port_id: port_id.clone(), channel_id: *channel_id, sequence: *sequence };
+		self.get(path)
+			.map(AcknowledgementCommitment::from)
+			.ok_or_else(|| Ics04Error::packet_acknowledgement_not_found(*sequence))
+	}
+
+	fn hash(&self, value: Vec<u8>) -> Vec<u8> {
+		Sha256::digest(value).to_vec()
+	}
+
+	fn client_update_time(
+		&self,
+		client_id: &ClientId,
+		height: Height,
+	) -> Result<Timestamp, Ics04Error> {
+		let time = self
+			.sequence(processed_time_path(client_id, height))
+			.ok_or_else(|| Ics04Error::processed_time_not_found(client_id.clone(), height))?;
+		Timestamp::from_nanoseconds(time)
+			.map_err(|e| Ics04Error::implementation_specific(e.to_string()))
+	}
+
+	fn client_update_height(
+		&self,
+		client_id: &ClientId,
+		height: Height,
+	) -> Result<Height, Ics04Error> {
+		self.sequence(processed_height_path(client_id, height))
+			.map(self::height)
+			.ok_or_else(|| Ics04Error::processed_height_not_found(client_id.clone(), height))
+	}
+
+	fn channel_counter(&self) -> Result<u64, Ics04Error> {
+		Ok(self.sequence(CHANNEL_COUNTER).unwrap_or_default())
+	}
+
+	fn max_expected_time_per_block(&self) -> Duration {
+		self.lock().block_time
+	}
+}
+
+impl ChannelKeeper for Host {
+	fn store_packet_commitment(
+		&mut self,
+		(port_id, channel_id, sequence): (PortId, ChannelId, Sequence),
+		commitment: PacketCommitment,
+	) -> Result<(), Ics04Error> {
+		self.set(CommitmentsPath { port_id, channel_id, sequence }, commitment.into_vec());
+		Ok(())
+	}
+
+	fn store_send_packet(
+		&mut self,
+		(port_id, channel_id, sequence): (PortId, ChannelId, Sequence),
+		packet: Packet,
+	) -> Result<(), Ics04Error> {
+		let ordering = self.channel_end(&(port_id.clone(), channel_id))?.ordering;
+		let info = self.packet_info(&packet, None, ordering)?;
+		self.set(sent_packet_path(&port_id, &channel_id, sequence.into()), info);
+		Ok(())
+	}
+
+	/// Called once the acknowledgement of the packet is written, which the packet info holds.
+	fn store_recv_packet(
+		&mut self,
+		(port_id, channel_id, sequence): (PortId, ChannelId, Sequence),
+		packet: Packet,
+	) -> Result<(), Ics04Error> {
+		let ordering = self.channel_end(&(port_id.clone(), channel_id))?.ordering;
+		let ack = self.get(raw_ack_path(&port_id, &channel_id, sequence.into()));
+		let info = self.packet_info(&packet, ack, ordering)?;
+		self.set(received_packet_path(&port_id, &channel_id, sequence.into()), info);
+		Ok(())
+	}
+
+	fn delete_packet_commitment(
+		&mut self,
+		(port_id, channel_id, sequence): (PortId, ChannelId, Sequence),
+	) -> Result<(), Ics04Error> {
+		self.remove(CommitmentsPath { port_id, channel_id, sequence });
+		Ok(())
+	}
+
+	fn store_packet_receipt(
+		&mut self,
+		(port_id, channel_id, sequence): (PortId, ChannelId, Sequence),
+		_receipt: Receipt,
+	) -> Result<(), Ics04Error> {
+		self.set(ReceiptsPath { port_id, channel_id, sequence }, vec![1]);
+		Ok(())
+	}
+
+	fn store_packet_acknowledgement(
+		&mut self,
+		(port_id, channel_id, sequence): (PortId, ChannelId, Sequence),
+		ack_commitment: AcknowledgementCommitment,
+	) -> Result<(), Ics04Error> {
+		self.set(AcksPath { port_id, channel_id, sequence }, ack_commitment.into_vec());
+		Ok(())
+	}
+
+	fn store_raw_acknowledgement(
+		&mut self,
+		(port_id, channel_id, sequence): (PortId, ChannelId, Sequence),
+		ack: Acknowledgement,
+	) -> Result<(), Ics04Error> {
+		self.set(raw_ack_path(&port_id, &channel_id, sequence.into()), ack.into_bytes());
+		Ok(())
+	}
+
+	fn delete_packet_acknowledgement(
+		&mut self,
+		(port_id, channel_id, sequence): (PortId, ChannelId, Sequence),
+	) -> Result<(), Ics04Error> {
+		self.remove(AcksPath { port_id, channel_id, sequence });
+		Ok(())
+	}
+
+	fn store_connection_channels(
+		&mut self,
+		connection_id: ConnectionId,
+		port_channel_id: &(PortId, ChannelId),
+	) -> Result<(), Ics04Error> {
+		self.push_json_list(connection_channels_path(&connection_id), port_channel_id);
+		Ok(())
+	}
+
+	fn store_channel(
+		&mut self,
+		(port_id, channel_id): (PortId, ChannelId),
+		channel_end: &ChannelEnd,
+	) -> Result<(), Ics04Error> {
+		let value = channel_end
+			.encode_vec()
+			.map_err(|e| Ics04Error::implementation_specific(e.to_string()))?;
+		self.set(ChannelEndsPath(port_id, channel_id), value);
+		Ok(())
+	}
+
+	fn store_next_sequence_send(
+		&mut self,
+		(port_id, channel_id): (PortId, ChannelId),
+		seq: Sequence,
+	) -> Result<(), Ics04Error> {
+		self.set_sequence(SeqSendsPath(port_id, channel_id), seq.into());
+		Ok(())
+	}
+
+	fn store_next_sequence_recv(
+		&mut self,
+		(port_id, channel_id): (PortId, ChannelId),
+		seq: Sequence,
+	) -> Result<(), Ics04Error> {
+		self.set_sequence(SeqRecvsPath(port_id, channel_id), seq.into());
+		Ok(())
+	}
+
+	fn store_next_sequence_ack(
+		&mut self,
+		(port_id, channel_id): (PortId, ChannelId),
+		seq: Sequence,
+	) -> Result<(), Ics04Error> {
+		self.set_sequence(SeqAcksPath(port_id, channel_id), seq.into());
+		Ok(())
+	}
+
+	fn increase_channel_counter(&mut self) {
+		self.increase_counter(CHANNEL_COUNTER)
+	}
+}
+
+impl PortReader for Host {
+	fn lookup_module_by_port(&self, port_id: &PortId) -> Result<ModuleId, Ics05Error> {
+		match port_id == &PortId::transfer() {
+			true =>
+				MODULE_ID_STR.parse().map_err(|_| Ics05Error::module_not_found(port_id.clone())),
+			false => Err(Ics05Error::unknown_port(port_id.clone())),
+		}
+	}
+}
+
+impl ReaderContext for Host {}
+
+impl ModuleCallbackContext for Host {}
+
+impl Ics26Context for Host {
+	type Router = HostRouter;
+
+	fn router(&self) -> &Self::Router {
+		&self.router
+	}
+
+	fn router_mut(&mut self) -> &mut Self::Router {
+		&mut self.router
+	}
+}
+
+impl Ics20Reader for Host {
+	type AccountId = Signer;
+
+	fn get_port(&self) -> Result<PortId, Ics20Error> {
+		Ok(PortId::transfer())
+	}
+
+	fn is_send_enabled(&self) -> bool {
+		true
+	}
+
+	fn is_receive_enabled(&self) -> bool {
+		true
+	}
+}
+
+/// Balances are kept by full denom, and the traces of the minted vouchers by `ibc/` denom.
+impl BankKeeper for Host {
+	type AccountId = Signer;
+
+	fn send_coins(
+		&mut self,
+		from: &Signer,
+		to: &Signer,
+		amt: &PrefixedCoin,
+	) -> Result<(), Ics20Error> {
+		self.burn_coins(from, amt)?;
+		self.mint_coins(to, amt)
+	}
+
+	fn mint_coins(&mut self, account: &Signer, amt: &PrefixedCoin) -> Result<(), Ics20Error> {
+		let amount =
+			u128::try_from(amt.amount.as_u256()).map_err(|_| Ics20Error::invalid_token())?;
+		let (account, denom) = (account.to_string(), amt.denom.to_string());
+		let balance = self.balance(&account, &denom);
+		let balance = balance.checked_add(amount).ok_or_else(Ics20Error::invalid_token)?;
+		self.set_balance(&account, &denom, balance);
+		if !amt.denom.trace_path().is_empty() {
+			self.set(
+				denom_trace_path(&ibc_denom_of(&amt.denom.clone().into())),
+				denom.into_bytes(),
+			);
+		}
+		Ok(())
+	}
+
+	fn burn_coins(&mut self, account: &Signer, amt: &PrefixedCoin) -> Result<(), Ics20Error> {
+		let amount =
+			u128::try_from(amt.amount.as_u256()).map_err(|_| Ics20Error::invalid_token())?;
+		let (account, denom) = (account.to_string(), amt.denom.to_string());
+		let balance = self.balance(&account, &denom);
+		let remaining = balance.checked_sub(amount).ok_or_else(|| {
+			Ics20Error::implementation_specific(format!(
+				"insufficient balance of {denom} of {account}: {balance} < {amount}"
+			))
+		})?;
+		self.set_balance(&account, &denom, remaining);
+		Ok(())
+	}
+}
+
+impl Ics20Keeper for Host {
+	type AccountId = Signer;
+}
+
+impl Ics20Context for Host {
+	type AccountId = Signer;
+}
+
+/// Routes the callbacks of the transfer port to the transfer module.
+#[derive(Clone, Debug)]
+pub struct HostRouter {
+	transfer: TransferModule,
+}
+
+impl Router for HostRouter {
+	fn get_route_mut(&mut self, module_id: &ModuleId) -> Option<&mut dyn Module> {
+		match module_id.as_ref() {
+			MODULE_ID_STR => Some(&mut self.transfer),
+			_ => None,
+		}
+	}
+
+	fn has_route(&self, module_id: &ModuleId) -> bool {
+		module_id.as_ref() == MODULE_ID_STR
+	}
+}
+
+/// The ICS-20 module of `ibc`, except that it opens unordered channels with any version: the
+/// transfer module opens with the counterparty version, or rewrites it into the
+/// [`HostState::transfer_version`] like an app running another version of ICS-20 would.
+#[derive(Clone, Debug)]
+pub struct TransferModule {
+	state: Arc<Mutex<HostState>>,
+}
+
+impl TransferModule {
+	fn host(&self) -> Host {
+		Host::with_state(self.state.clone())
+	}
+}
+
+fn unordered(order: Order) -> Result<(), Ics04Error> {
+	match order {
+		Order::Unordered => Ok(()),
+		order => Err(Ics04Error::app_module(Ics20Error::channel_not_unordered(order).to_string())),
+	}
+}
+
+impl Module for TransferModule {
+	fn on_chan_open_init(
+		&mut self,
+		_ctx: &dyn ModuleCallbackContext,
+		_output: &mut ModuleOutputBuilder,
+		order: Order,
+		_connection_hops: &[ConnectionId],
+		_port_id: &PortId,
+		_channel_id: &ChannelId,
+		_counterparty: &Counterparty,
+		_version: &Version,
+		_relayer: &Signer,
+	) -> Result<(), Ics04Error> {
+		unordered(order)
+	}
+
+	fn on_chan_open_try(
+		&mut self,
+		_ctx: &dyn ModuleCallbackContext,
+		_output: &mut ModuleOutputBuilder,
+		order: Order,
+		_connection_hops: &[ConnectionId],
+		_port_id: &PortId,
+		_channel_id: &ChannelId,
+		_counterparty: &Counterparty,
+		_version: &Version,
+		counterparty_version: &Version,
+		_relayer: &Signer,
+	) -> Result<Version, Ics04Error> {
+		unordered(order)?;
+		let transfer_version = self.state.lock().unwrap().transfer_version.clone();
+		Ok(transfer_version.unwrap_or_else(|| counterparty_version.clone()))
+	}
+
+	fn on_recv_packet(
+		&self,
+		_ctx: &dyn ModuleCallbackContext,
+		output: &mut ModuleOutputBuilder,
+		packet: &mut Packet,
+		relayer: &Signer,
+	) -> Result<Acknowledgement, Ics04Error> {
+		let ack = on_recv_packet(&mut self.host(), output, packet, relayer);
+		Ok(Acknowledgement::from_bytes(ack.to_string().into_bytes()))
+	}
+
+	fn on_acknowledgement_packet(
+		&mut self,
+		_ctx: &dyn ModuleCallbackContext,
+		output: &mut ModuleOutputBuilder,
+		packet: &mut Packet,
+		acknowledgement: &Acknowledgement,
+		relayer: &Signer,
+	) -> Result<(), Ics04Error> {
+		on_acknowledgement_packet(&mut self.host(), output, packet, acknowledgement, relayer)
+			.map_err(|e| Ics04Error::app_module(e.to_string()))
+	}
+
+	fn on_timeout_packet(
+		&mut self,
+		_ctx: &dyn ModuleCallbackContext,
+		output: &mut ModuleOutputBuilder,
+		packet: &mut Packet,
+		relayer: &Signer,
+	) -> Result<(), Ics04Error> {
+		on_timeout_packet(&mut self.host(), output, packet, relayer)
+			.map_err(|e| Ics04Error::app_module(e.to_string()))
+	}
+}
@@ -0,0 +1,1011 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The IBC state machine of a mock chain. Messages are executed by the handlers of `ibc` on the
+//! [`Host`] context, and every block records the writes of its transactions to the [`Store`],
+//! which the queries at its height read and the proofs of its height commit to.
+
+use super::{
+	host::{Host, HostState},
+	store::{balance_path, consensus_state_path, State, Store},
+	MockChainConfig,
+};
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use hyperspace_primitives::{error::Error, signer::message_signer};
+use ibc::{
+	applications::transfer::{
+		msgs::transfer::MsgTransfer, relay::send_transfer::send_transfer, PrefixedCoin,
+	},
+	core::{
+		ics02_client::{client_state::ClientState as _, context::ClientReader, msgs::ClientMsg},
+		ics03_connection::{
+			connection::{ConnectionEnd, State as ConnectionState},
+			msgs::ConnectionMsg,
+		},
+		ics04_channel::{
+			channel::{ChannelEnd, State as ChannelState},
+			context::{calculate_block_delay, ChannelReader},
+			msgs::{ChannelMsg, PacketMsg},
+			packet::Packet,
+			Version,
+		},
+		ics23_commitment::commitment::{CommitmentPrefix, CommitmentProofBytes},
+		ics24_host::{
+			identifier::{ChannelId, ConnectionId, PortId},
+			path::{
+				AcksPath, ChannelEndsPath, ClientStatePath, CommitmentsPath, ConnectionsPath,
+				ReceiptsPath,
+			},
+		},
+		ics26_routing::{
+			handler::{decode, dispatch},
+			msgs::Ics26Envelope,
+		},
+	},
+	events::IbcEvent,
+	handler::HandlerOutput,
+	mock::header::{MockClientMessage, MockHeader},
+	proofs::Proofs,
+	timestamp::Timestamp,
+	Height,
+};
+use ibc_proto::google::protobuf::Any;
+use pallet_ibc::light_clients::{AnyClientMessage, AnyClientState, AnyConsensusState};
+use sha2::{Digest, Sha256};
+use std::{collections::BTreeMap, fmt::Display, time::Duration};
+use tendermint_proto::Protobuf;
+
+/// Revision of the heights of the mock chains.
+pub const REVISION_NUMBER: u64 = 1;
+
+/// Timestamp of the genesis of the mock chains in nanoseconds, block `n` is `n` block times later.
+const GENESIS_TIMESTAMP: u64 = 1_600_000_000_000_000_000;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Height of a mock chain.
+pub fn height(revision_height: u64) -> Height {
+	Height::new(REVISION_NUMBER, revision_height)
+}
+
+/// Timestamp of block `height` of a mock chain producing a block every `block_time`, in
+/// nanoseconds.
+pub fn block_timestamp(block_time: Duration, height: u64) -> u64 {
+	GENESIS_TIMESTAMP + height * block_time.as_nanos() as u64
+}
+
+pub fn not_found(what: impl Display) -> Error {
+	Error::Custom(format!("{what} not found"))
+}
+
+pub fn unsupported(what: impl Display) -> Error {
+	Error::Custom(format!("{what} not supported by the mock chains"))
+}
+
+pub fn codec_error(e: impl Display) -> Error {
+	Error::Custom(format!("failed to encode or decode a value: {e}"))
+}
+
+/// Proof of the value at `path`, or of its absence, in the store of `chain` under `prefix` at
+/// `height`.
+fn store_proof(
+	chain: &str,
+	height: u64,
+	prefix: &[u8],
+	path: &str,
+	value: Option<&[u8]>,
+) -> Vec<u8> {
+	let mut hasher = Sha256::new();
+	for part in [chain.as_bytes(), &height.to_be_bytes(), prefix, path.as_bytes()] {
+		hasher.update((part.len() as u64).to_be_bytes());
+		hasher.update(part);
+	}
+	match value {
+		Some(value) => {
+			hasher.update([1]);
+			hasher.update(value);
+		},
+		None => hasher.update([0]),
+	}
+	hasher.finalize().to_vec()
+}
+
+/// A sealed block: its timestamp and the events of its transactions. Its writes are recorded by
+/// the store.
+#[derive(Clone, Debug)]
+pub struct Block {
+	pub timestamp: u64,
+	pub txs: Vec<Vec<IbcEvent>>,
+}
+
+impl Block {
+	pub fn events(&self) -> impl Iterator<Item = &IbcEvent> {
+		self.txs.iter().flatten()
+	}
+}
+
+/// Faults injected into a mock chain.
+#[derive(Clone, Debug, Default)]
+pub struct Faults {
+	/// Number of the next submissions that time out without being included
+	pub dropped_submissions: usize,
+	/// Whether the proofs queried from the chain are corrupted
+	pub corrupt_proofs: bool,
+	/// Whether the headers of the client updates built from the chain are corrupted
+	pub corrupt_headers: bool,
+	/// Time between the signing of a transaction and its submission
+	pub submission_delay: Duration,
+	/// Whether the chain stopped finalizing blocks, which it still produces
+	pub finality_paused: bool,
+}
+
+/// The blocks of a mock chain and its store.
+pub struct Ledger {
+	pub name: String,
+	pub prefix: CommitmentPrefix,
+	/// Prefixes of the stores of the connections that aren't in the store under `prefix`
+	pub connection_prefixes: BTreeMap<ConnectionId, CommitmentPrefix>,
+	/// Prefix and path of each proof queried from the chain by the relayer
+	pub proof_requests: Vec<(CommitmentPrefix, String)>,
+	pub block_time: Duration,
+	pub finality_lag: u64,
+	/// See [`HostState::transfer_version`]
+	pub transfer_version: Option<Version>,
+	/// Sealed blocks, block `n` at index `n - 1`
+	blocks: Vec<Block>,
+	/// Writes of the sealed blocks and of the pending block
+	store: Store,
+	pending_txs: Vec<Vec<IbcEvent>>,
+	/// Latest finalized height
+	pub finalized: u64,
+	pub faults: Faults,
+	/// Sender and type url of each message included in the chain
+	pub message_senders: Vec<(String, String)>,
+	/// Sequence of the next transaction of each account
+	account_sequences: BTreeMap<String, u64>,
+	/// Number of transactions rejected because their sequence was already used
+	pub sequence_mismatches: usize,
+	block_subscribers: Vec<UnboundedSender<u64>>,
+	finality_subscribers: Vec<UnboundedSender<u64>>,
+	event_subscribers: Vec<UnboundedSender<IbcEvent>>,
+}
+
+impl Ledger {
+	/// A chain whose genesis block, block 1, is sealed and finalized.
+	pub fn new(config: &MockChainConfig) -> Self {
+		let mut ledger = Self {
+			name: config.name.clone(),
+			prefix: CommitmentPrefix::try_from(config.commitment_prefix.clone())
+				.expect("The commitment prefix of a mock chain can't be empty"),
+			connection_prefixes: BTreeMap::new(),
+			proof_requests: vec![],
+			block_time: config.block_time,
+			finality_lag: config.finality_lag,
+			transfer_version: None,
+			blocks: vec![],
+			store: Store::default(),
+			pending_txs: vec![],
+			finalized: 0,
+			faults: Faults::default(),
+			message_senders: vec![],
+			account_sequences: BTreeMap::new(),
+			sequence_mismatches: 0,
+			block_subscribers: vec![],
+			finality_subscribers: vec![],
+			event_subscribers: vec![],
+		};
+		ledger.store.seal(1);
+		ledger.blocks.push(Block { timestamp: ledger.timestamp_at(1), txs: vec![] });
+		ledger.finalized = 1;
+		ledger
+	}
+
+	pub fn latest_height(&self) -> u64 {
+		self.blocks.len() as u64
+	}
+
+	/// Height of the block being built.
+	pub fn pending_height(&self) -> u64 {
+		self.latest_height() + 1
+	}
+
+	/// Timestamp of block `height` in nanoseconds.
+	pub fn timestamp_at(&self, height: u64) -> u64 {
+		block_timestamp(self.block_time, height)
+	}
+
+	pub fn timestamp(&self, height: u64) -> Result<Timestamp> {
+		Timestamp::from_nanoseconds(self.timestamp_at(height)).map_err(codec_error)
+	}
+
+	pub fn block(&self, height: u64) -> Result<&Block> {
+		height
+			.checked_sub(1)
+			.and_then(|index| self.blocks.get(index as usize))
+			.ok_or_else(|| not_found(format!("block {height} of {}", self.name)))
+	}
+
+	/// Store of the pending block.
+	pub fn state(&self) -> State<'_> {
+		State { store: &self.store, at: None }
+	}
+
+	/// Store at `height`, after the transactions of its block.
+	pub fn state_at(&self, height: Height) -> Result<State<'_>> {
+		self.block(height.revision_height)?;
+		Ok(State { store: &self.store, at: Some(height.revision_height) })
+	}
+
+	/// Credits `amount` of `denom` to `account` in the pending block.
+	pub fn mint(&mut self, account: &str, denom: &str, amount: u128) {
+		let balance = self.state().balance(account, denom) + amount;
+		self.store
+			.set(balance_path(account, denom), Some(balance.to_be_bytes().to_vec()));
+	}
+
+	/// Header of block `height`, as tracked by the mock clients of the chain.
+	pub fn header(&self, height: u64) -> Result<MockHeader> {
+		let block = self.block(height)?;
+		Ok(MockHeader {
+			height: self::height(height),
+			timestamp: Timestamp::from_nanoseconds(block.timestamp).map_err(codec_error)?,
+		})
+	}
+
+	/// Header of block `height` for the client updates built from the chain, whose timestamp is
+	/// off by a block time if the headers are corrupted.
+	pub fn update_header(&self, height: u64) -> Result<MockHeader> {
+		let mut header = self.header(height)?;
+		if self.faults.corrupt_headers {
+			header.timestamp = (header.timestamp + self.block_time).map_err(codec_error)?;
+		}
+		Ok(header)
+	}
+
+	/// Seals the pending block and finalizes the blocks that are `finality_lag` blocks deep.
+	/// Returns the height of the sealed block.
+	pub fn produce_block(&mut self) -> u64 {
+		let height = self.pending_height();
+		self.store.seal(height);
+		let block = Block {
+			timestamp: self.timestamp_at(height),
+			txs: std::mem::take(&mut self.pending_txs),
+		};
+		self.blocks.push(block);
+		self.block_subscribers
+			.retain(|subscriber| subscriber.unbounded_send(height).is_ok());
+		self.finalize(height.saturating_sub(self.finality_lag));
+		height
+	}
+	/// Finalizes the blocks up to `height`, publishing their events.
+	fn finalize(&mut self, height: u64) {
+		let height = height.min(self.latest_height());
+		if height <= self.finalized || self.faults.finality_paused {
+			return
+		}
+		for finalized in self.finalized + 1..=height {
+			let events = self.blocks[finalized as usize - 1].events().cloned().collect::<Vec<_>>();
+			for event in events {
+				self.event_subscribers
+					.retain(|subscriber| subscriber.unbounded_send(event.clone()).is_ok());
+			}
+		}
+		self.finalized = height;
+		self.finality_subscribers
+			.retain(|subscriber| subscriber.unbounded_send(height).is_ok());
+	}
+
+	pub fn subscribe_blocks(&mut self) -> UnboundedReceiver<u64> {
+		let (sender, receiver) = unbounded();
+		self.block_subscribers.push(sender);
+		receiver
+	}
+
+	pub fn subscribe_finality(&mut self) -> UnboundedReceiver<u64> {
+		let (sender, receiver) = unbounded();
+		self.finality_subscribers.push(sender);
+		receiver
+	}
+
+	pub fn subscribe_events(&mut self) -> UnboundedReceiver<IbcEvent> {
+		let (sender, receiver) = unbounded();
+		self.event_subscribers.push(sender);
+		receiver
+	}
+
+	/// Proof of the value at `path` in the store under `prefix` at `height`. The stores of the
+	/// connections share the state of the chain, only their proofs differ.
+	pub fn proof(&self, height: u64, prefix: &CommitmentPrefix, path: &str) -> Result<Vec<u8>> {
+		if prefix != &self.prefix && !self.connection_prefixes.values().any(|p| p == prefix) {
+			return Err(not_found(format!("store of {} under prefix {prefix:?}", self.name)))
+		}
+		let value = self.state_at(self::height(height))?.get(path);
+		let mut proof = store_proof(&self.name, height, prefix.as_bytes(), path, value.as_deref());
+		if self.faults.corrupt_proofs {
+			proof[0] ^= 0xff;
+		}
+		Ok(proof)
+	}
+
+	/// Sequence the next transaction of `account` must be signed with.
+	pub fn account_sequence(&self, account: &str) -> u64 {
+		self.account_sequences.get(account).copied().unwrap_or_default()
+	}
+
+	/// Includes a transaction of `sender`, signed with `sequence`, in the pending block. The
+	/// messages are applied all or none, and the id of the transaction is returned.
+	pub fn submit(
+		&mut self,
+		counterparty: &Ledger,
+		sender: &str,
+		sequence: u64,
+		messages: Vec<Any>,
+	) -> Result<(u64, usize)> {
+		if sequence != self.account_sequence(sender) {
+			self.sequence_mismatches += 1;
+			return Err(Error::Custom(format!(
+				"account sequence mismatch on {}, expected {}, got {sequence}",
+				self.name,
+				self.account_sequence(sender)
+			)))
+		}
+		if self.faults.dropped_submissions > 0 {
+			self.faults.dropped_submissions -= 1;
+			return Err(Error::Custom(format!("submission to {} timed out", self.name)))
+		}
+		if let Some(signer) = messages
+			.iter()
+			.filter_map(message_signer)
+			.find(|signer| signer.as_str() != sender)
+		{
+			return Err(Error::Custom(format!(
+				"message signed for by {signer} in a transaction of {sender} on {}",
+				self.name
+			)))
+		}
+		let type_urls = messages.iter().map(|message| message.type_url.clone()).collect::<Vec<_>>();
+		self.store.begin();
+		let events = messages
+			.into_iter()
+			.map(|message| self.deliver(counterparty, message))
+			.collect::<Result<Vec<_>>>();
+		let events = match events {
+			Ok(events) => {
+				self.store.commit();
+				events
+			},
+			Err(e) => {
+				self.store.rollback();
+				return Err(e)
+			},
+		};
+		*self.account_sequences.entry(sender.to_string()).or_default() += 1;
+		self.message_senders
+			.extend(type_urls.into_iter().map(|type_url| (sender.to_string(), type_url)));
+		Ok(self.record_tx(events.concat()))
+	}
+
+	/// Whether each of `messages` would succeed if submitted alone.
+	pub fn simulate(&mut self, counterparty: &Ledger, messages: Vec<Any>) -> Vec<bool> {
+		messages
+			.into_iter()
+			.map(|message| {
+				self.store.begin();
+				let result = self.deliver(counterparty, message).is_ok();
+				self.store.rollback();
+				result
+			})
+			.collect()
+	}
+
+	/// Sends an ICS-20 transfer from the pending block.
+	pub fn send_transfer(&mut self, msg: MsgTransfer<PrefixedCoin>) -> Result<(u64, usize)> {
+		self.store.begin();
+		let mut output = HandlerOutput::builder();
+		match self.execute(|host| send_transfer(host, &mut output, msg)) {
+			Ok(()) => self.store.commit(),
+			Err(e) => {
+				self.store.rollback();
+				return Err(Error::Custom(format!("failed to send the transfer: {e}")))
+			},
+		}
+		Ok(self.record_tx(output.with_result(()).events))
+	}
+
+	/// Events of the transaction `tx_id`.
+	pub fn tx_events(&self, (height, index): (u64, usize)) -> Result<&[IbcEvent]> {
+		let txs = if height == self.pending_height() {
+			&self.pending_txs
+		} else {
+			&self.block(height)?.txs
+		};
+		txs.get(index)
+			.map(Vec::as_slice)
+			.ok_or_else(|| not_found(format!("transaction {index} of block {height}")))
+	}
+
+	fn record_tx(&mut self, events: Vec<IbcEvent>) -> (u64, usize) {
+		self.pending_txs.push(events);
+		(self.pending_height(), self.pending_txs.len() - 1)
+	}
+
+	/// Runs `f` on the context of the handlers in the pending block.
+	fn execute<T>(&mut self, f: impl FnOnce(&mut Host) -> T) -> T {
+		let mut host = Host::new(HostState {
+			store: std::mem::take(&mut self.store),
+			height: self.pending_height(),
+			block_time: self.block_time,
+			prefix: self.prefix.clone(),
+			transfer_version: self.transfer_version.clone(),
+		});
+		let result = f(&mut host);
+		self.store = host.take_store();
+		result
+	}
+
+	/// Executes `message`, once its proofs are verified against `counterparty`.
+	fn deliver(&mut self, counterparty: &Ledger, message: Any) -> Result<Vec<IbcEvent>> {
+		let type_url = message.type_url.clone();
+		let envelope = decode::<Host>(message)
+			.map_err(|e| Error::Custom(format!("failed to decode message {type_url}: {e}")))?;
+		// the events of the client updates carry their header, like the ones of the chains do
+		let header = match &envelope {
+			Ics26Envelope::Ics2Msg(ClientMsg::UpdateClient(msg)) =>
+				Some(msg.client_message.encode_vec().map_err(codec_error)?),
+			_ => None,
+		};
+		let output = self.execute(|host| {
+			if !verify_message(host, counterparty, &envelope)? {
+				return Ok(None)
+			}
+			dispatch(host, envelope)
+				.map(Some)
+				.map_err(|e| Error::Custom(format!("failed to execute message {type_url}: {e}")))
+		})?;
+		let events = output.map(|output| output.events).unwrap_or_default();
+		Ok(events
+			.into_iter()
+			.map(|event| match event {
+				IbcEvent::UpdateClient(mut event) => {
+					event.header = header.clone();
+					IbcEvent::UpdateClient(event)
+				},
+				event => event,
+			})
+			.collect())
+	}
+}
+
+/// Verifies the headers and the proofs carried by `envelope` against `counterparty`, as the mock
+/// clients of the chain would: the mock light client of `ibc` accepts any of them. Returns
+/// `false` if the message is redundant, in which case it succeeds without being executed.
+fn verify_message(
+	host: &Host,
+	counterparty: &Ledger,
+	envelope: &Ics26Envelope<Host>,
+) -> Result<bool> {
+	match envelope {
+		Ics26Envelope::Ics2Msg(ClientMsg::CreateClient(msg)) =>
+			match (&msg.client_state, &msg.consensus_state) {
+				(AnyClientState::Mock(_), AnyConsensusState::Mock(_)) => Ok(true),
+				(client_state, _) =>
+					Err(unsupported(format!("client type {}", client_state.client_type()))),
+			},
+		Ics26Envelope::Ics2Msg(ClientMsg::UpdateClient(msg)) => {
+			let header = match &msg.client_message {
+				AnyClientMessage::Mock(MockClientMessage::Header(header)) => header,
+				AnyClientMessage::Mock(MockClientMessage::Misbehaviour(_)) =>
+					return Err(unsupported("misbehaviour")),
+				_ => return Err(unsupported("client messages of other clients than mock")),
+			};
+			let header_height = header.height.revision_height;
+			if header_height > counterparty.finalized {
+				return Err(Error::Custom(format!(
+					"block {header_height} of {} isn't finalized",
+					counterparty.name
+				)))
+			}
+			if *header != counterparty.header(header_height)? {
+				return Err(Error::Custom(format!(
+					"header doesn't match block {header_height} of {}",
+					counterparty.name
+				)))
+			}
+			Ok(host.consensus_state(&msg.client_id, header.height).is_err())
+		},
+		Ics26Envelope::Ics2Msg(ClientMsg::UpgradeClient(_)) => Err(unsupported("client upgrades")),
+		Ics26Envelope::Ics3Msg(ConnectionMsg::ConnectionOpenInit(_)) => Ok(true),
+		Ics26Envelope::Ics3Msg(ConnectionMsg::ConnectionOpenTry(msg)) => {
+			let counterparty_connection_id = msg
+				.counterparty
+				.connection_id()
+				.ok_or_else(|| not_found("counterparty connection id"))?;
+			let connection = ConnectionEnd::new(
+				ConnectionState::TryOpen,
+				msg.client_id.clone(),
+				msg.counterparty.clone(),
+				msg.counterparty_versions.clone(),
+				msg.delay_period,
+			);
+			let proven = verify_counterparty_connection(
+				host,
+				counterparty,
+				&connection,
+				counterparty_connection_id,
+				&msg.proofs,
+			)?;
+			if proven.state != ConnectionState::Init ||
+				proven.counterparty().client_id() != &msg.client_id
+			{
+				return Err(Error::Custom(format!(
+					"connection {counterparty_connection_id} of {} doesn't match",
+					counterparty.name
+				)))
+			}
+			verify_client_proofs(
+				host,
+				counterparty,
+				&connection,
+				msg.client_state.as_ref(),
+				&msg.proofs,
+			)?;
+			Ok(true)
+		},
+		Ics26Envelope::Ics3Msg(ConnectionMsg::ConnectionOpenAck(msg)) => {
+			let connection = host.view(|state| state.connection(&msg.connection_id))?;
+			let proven = verify_counterparty_connection(
+				host,
+				counterparty,
+				&connection,
+				&msg.counterparty_connection_id,
+				&msg.proofs,
+			)?;
+			if proven.state != ConnectionState::TryOpen ||
+				proven.counterparty().connection_id() != Some(&msg.connection_id)
+			{
+				return Err(Error::Custom(format!(
+					"connection {} of {} doesn't match",
+					msg.counterparty_connection_id, counterparty.name
+				)))
+			}
+			verify_client_proofs(
+				host,
+				counterparty,
+				&connection,
+				msg.client_state.as_ref(),
+				&msg.proofs,
+			)?;
+			Ok(true)
+		},
+		Ics26Envelope::Ics3Msg(ConnectionMsg::ConnectionOpenConfirm(msg)) => {
+			let connection = host.view(|state| state.connection(&msg.connection_id))?;
+			let counterparty_connection_id = connection
+				.counterparty()
+				.connection_id()
+				.ok_or_else(|| not_found("counterparty connection id"))?;
+			let proven = verify_counterparty_connection(
+				host,
+				counterparty,
+				&connection,
+				counterparty_connection_id,
+				&msg.proofs,
+			)?;
+			if !proven.is_open() ||
+				proven.counterparty().connection_id() != Some(&msg.connection_id)
+			{
+				return Err(Error::Custom(format!(
+					"connection {counterparty_connection_id} of {} isn't open with {}",
+					counterparty.name, msg.connection_id
+				)))
+			}
+			Ok(true)
+		},
+		Ics26Envelope::Ics4ChannelMsg(ChannelMsg::ChannelOpenInit(_)) |
+		Ics26Envelope::Ics4ChannelMsg(ChannelMsg::ChannelCloseInit(_)) => Ok(true),
+		Ics26Envelope::Ics4ChannelMsg(ChannelMsg::ChannelOpenTry(msg)) => {
+			let connection = connection_of(host, &msg.channel)?;
+			let counterparty_port_id = msg.channel.counterparty().port_id();
+			let counterparty_channel_id = msg
+				.channel
+				.counterparty()
+				.channel_id()
+				.ok_or_else(|| not_found("counterparty channel id"))?;
+			let proven = verify_counterparty_channel(
+				host,
+				counterparty,
+				&connection,
+				counterparty_port_id,
+				counterparty_channel_id,
+				&msg.proofs,
+			)?;
+			if proven.state != ChannelState::Init ||
+				proven.ordering != msg.channel.ordering ||
+				proven.counterparty().port_id() != &msg.port_id ||
+				proven.connection_hops().first() != connection.counterparty().connection_id() ||
+				proven.version != msg.counterparty_version
+			{
+				return Err(Error::Custom(format!(
+					"channel {counterparty_channel_id}/{counterparty_port_id} of {} doesn't match",
+					counterparty.name
+				)))
+			}
+			Ok(true)
+		},
+		Ics26Envelope::Ics4ChannelMsg(ChannelMsg::ChannelOpenAck(msg)) => {
+			let channel = host.view(|state| state.channel(&msg.port_id, &msg.channel_id))?;
+			let connection = connection_of(host, &channel)?;
+			let counterparty_port_id = channel.counterparty().port_id();
+			let proven = verify_counterparty_channel(
+				host,
+				counterparty,
+				&connection,
+				counterparty_port_id,
+				&msg.counterparty_channel_id,
+				&msg.proofs,
+			)?;
+			if proven.state != ChannelState::TryOpen ||
+				proven.counterparty().channel_id() != Some(&msg.channel_id) ||
+				proven.counterparty().port_id() != &msg.port_id ||
+				proven.version != msg.counterparty_version
+			{
+				return Err(Error::Custom(format!(
+					"channel {}/{counterparty_port_id} of {} doesn't match",
+					msg.counterparty_channel_id, counterparty.name
+				)))
+			}
+			Ok(true)
+		},
+		Ics26Envelope::Ics4ChannelMsg(ChannelMsg::ChannelOpenConfirm(msg)) => {
+			let channel = host.view(|state| state.channel(&msg.port_id, &msg.channel_id))?;
+			let (port_id, channel_id) = counterparty_channel(&channel)?;
+			let proven = verify_counterparty_channel(
+				host,
+				counterparty,
+				&connection_of(host, &channel)?,
+				port_id,
+				channel_id,
+				&msg.proofs,
+			)?;
+			if !proven.is_open() || proven.counterparty().channel_id() != Some(&msg.channel_id) {
+				return Err(Error::Custom(format!(
+					"channel {channel_id}/{port_id} of {} isn't open",
+					counterparty.name
+				)))
+			}
+			Ok(true)
+		},
+		Ics26Envelope::Ics4ChannelMsg(ChannelMsg::ChannelCloseConfirm(msg)) => {
+			let channel = host.view(|state| state.channel(&msg.port_id, &msg.channel_id))?;
+			let (port_id, channel_id) = counterparty_channel(&channel)?;
+			let proven = verify_counterparty_channel(
+				host,
+				counterparty,
+				&connection_of(host, &channel)?,
+				port_id,
+				channel_id,
+				&msg.proofs,
+			)?;
+			if proven.state != ChannelState::Closed ||
+				proven.counterparty().channel_id() != Some(&msg.channel_id)
+			{
+				return Err(Error::Custom(format!(
+					"channel {channel_id}/{port_id} of {} isn't closed",
+					counterparty.name
+				)))
+			}
+			Ok(true)
+		},
+		Ics26Envelope::Ics4PacketMsg(PacketMsg::RecvPacket(msg)) => {
+			let packet = &msg.packet;
+			let receipt =
+				(packet.destination_port.clone(), packet.destination_channel, packet.sequence);
+			if host.get_packet_receipt(&receipt).is_ok() {
+				return Ok(false)
+			}
+			let channel = host.view(|state| {
+				state.channel(&packet.destination_port, &packet.destination_channel)
+			})?;
+			let connection = connection_of(host, &channel)?;
+			let commitment = host.packet_commitment(
+				packet.data.clone(),
+				packet.timeout_height,
+				packet.timeout_timestamp,
+			);
+			verify_value(
+				host,
+				counterparty,
+				&connection,
+				msg.proofs.object_proof(),
+				msg.proofs.height(),
+				CommitmentsPath {
+					port_id: packet.source_port.clone(),
+					channel_id: packet.source_channel,
+					sequence: packet.sequence,
+				},
+				&commitment.into_vec(),
+			)?;
+			verify_delay_passed(host, &connection, msg.proofs.height())?;
+			Ok(true)
+		},
+		Ics26Envelope::Ics4PacketMsg(PacketMsg::AckPacket(msg)) => {
+			let packet = &msg.packet;
+			let Some(connection) = unsettled_packet_connection(host, packet)? else {
+				return Ok(false)
+			};
+			let commitment = host.ack_commitment(msg.acknowledgement.clone());
+			verify_value(
+				host,
+				counterparty,
+				&connection,
+				msg.proofs.object_proof(),
+				msg.proofs.height(),
+				AcksPath {
+					port_id: packet.destination_port.clone(),
+					channel_id: packet.destination_channel,
+					sequence: packet.sequence,
+				},
+				&commitment.into_vec(),
+			)?;
+			verify_delay_passed(host, &connection, msg.proofs.height())?;
+			Ok(true)
+		},
+		Ics26Envelope::Ics4PacketMsg(PacketMsg::ToPacket(msg)) =>
+			verify_timeout(host, counterparty, &msg.packet, &msg.proofs, false),
+		Ics26Envelope::Ics4PacketMsg(PacketMsg::ToClosePacket(msg)) =>
+			verify_timeout(host, counterparty, &msg.packet, &msg.proofs, true),
+	}
+}
+
+/// Verifies the proof of the absence of the receipt of `packet` on `counterparty`, and the one
+/// of its closed channel end if `on_close`. Returns `false` if the packet is already settled.
+fn verify_timeout(
+	host: &Host,
+	counterparty: &Ledger,
+	packet: &Packet,
+	proofs: &Proofs,
+	on_close: bool,
+) -> Result<bool> {
+	let Some(connection) = unsettled_packet_connection(host, packet)? else { return Ok(false) };
+	if on_close {
+		let proof_closed = proofs
+			.other_proof()
+			.as_ref()
+			.ok_or_else(|| Error::Custom("missing proof of the closed channel".to_string()))?;
+		let proven = verify(
+			host,
+			counterparty,
+			&connection,
+			proof_closed,
+			proofs.height(),
+			ChannelEndsPath(packet.destination_port.clone(), packet.destination_channel),
+		)?
+		.ok_or_else(|| not_found(format!("channel {}", packet.destination_channel)))?;
+		let proven = ChannelEnd::decode_vec(&proven).map_err(codec_error)?;
+		if proven.state != ChannelState::Closed {
+			return Err(Error::Custom(format!(
+				"channel {} of {} isn't closed",
+				packet.destination_channel, counterparty.name
+			)))
+		}
+	}
+	let receipt = ReceiptsPath {
+		port_id: packet.destination_port.clone(),
+		channel_id: packet.destination_channel,
+		sequence: packet.sequence,
+	};
+	if verify(host, counterparty, &connection, proofs.object_proof(), proofs.height(), receipt)?
+		.is_some()
+	{
+		return Err(Error::Custom(format!("packet {} was received", packet.sequence)))
+	}
+	verify_delay_passed(host, &connection, proofs.height())?;
+	Ok(true)
+}
+
+/// Connection of the channel `packet` was sent on, `None` if the packet was already acknowledged
+/// or timed out: relaying it again is a no-op, as it is on the chains.
+fn unsettled_packet_connection(host: &Host, packet: &Packet) -> Result<Option<ConnectionEnd>> {
+	let commitment = (packet.source_port.clone(), packet.source_channel, packet.sequence);
+	if host.get_packet_commitment(&commitment).is_err() {
+		return Ok(None)
+	}
+	let channel = host.view(|state| state.channel(&packet.source_port, &packet.source_channel))?;
+	connection_of(host, &channel).map(Some)
+}
+
+/// Connection of `channel`.
+fn connection_of(host: &Host, channel: &ChannelEnd) -> Result<ConnectionEnd> {
+	let connection_id =
+		channel.connection_hops().first().ok_or_else(|| not_found("connection hop"))?;
+	host.view(|state| state.connection(connection_id))
+}
+
+/// Port and channel of the counterparty of `channel`.
+fn counterparty_channel(channel: &ChannelEnd) -> Result<(&PortId, &ChannelId)> {
+	let channel_id = channel
+		.counterparty()
+		.channel_id()
+		.ok_or_else(|| not_found("counterparty channel id"))?;
+	Ok((channel.counterparty().port_id(), channel_id))
+}
+
+/// Verifies `proof` of the value at `path` in the store of `counterparty` at `height`, against
+/// the consensus state of the client of `connection`. Returns the proven value, `None` if the
+/// proof is a proof of absence.
+fn verify(
+	host: &Host,
+	counterparty: &Ledger,
+	connection: &ConnectionEnd,
+	proof: &CommitmentProofBytes,
+	height: Height,
+	path: impl Display,
+) -> Result<Option<Vec<u8>>> {
+	let client_id = connection.client_id();
+	let consensus_state = host.view(|state| {
+		if state.client_state(client_id)?.frozen_height.is_some() {
+			return Err(Error::Custom(format!("client {client_id} is frozen")))
+		}
+		state.consensus_state(client_id, height)
+	})?;
+	let block = counterparty.block(height.revision_height)?;
+	if consensus_state.timestamp().nanoseconds() != block.timestamp {
+		return Err(Error::Custom(format!(
+			"consensus state of client {client_id} at {height} doesn't match block {} of {}",
+			height.revision_height, counterparty.name
+		)))
+	}
+	let path = path.to_string();
+	let value = counterparty.state_at(height)?.get(&path);
+	let expected = store_proof(
+		&counterparty.name,
+		height.revision_height,
+		connection.counterparty().prefix().as_bytes(),
+		&path,
+		value.as_deref(),
+	);
+	if proof.as_bytes() != expected.as_slice() {
+		return Err(Error::Custom(format!("invalid proof of {path} at {height}")))
+	}
+	Ok(value)
+}
+
+/// Like [`verify`], checking that the proven value is `expected`.
+fn verify_value(
+	host: &Host,
+	counterparty: &Ledger,
+	connection: &ConnectionEnd,
+	proof: &CommitmentProofBytes,
+	height: Height,
+	path: impl Display,
+	expected: &[u8],
+) -> Result<()> {
+	let path = path.to_string();
+	match verify(host, counterparty, connection, proof, height, &path)? {
+		Some(value) if value == expected => Ok(()),
+		_ => Err(Error::Custom(format!("proven value of {path} at {height} doesn't match"))),
+	}
+}
+
+/// Verifies the proof of the end of the counterparty `counterparty_connection_id` of
+/// `connection` and returns it, checking that it uses the clients of `connection`.
+fn verify_counterparty_connection(
+	host: &Host,
+	counterparty: &Ledger,
+	connection: &ConnectionEnd,
+	counterparty_connection_id: &ConnectionId,
+	proofs: &Proofs,
+) -> Result<ConnectionEnd> {
+	let proven = verify(
+		host,
+		counterparty,
+		connection,
+		proofs.object_proof(),
+		proofs.height(),
+		ConnectionsPath(counterparty_connection_id.clone()),
+	)?
+	.ok_or_else(|| not_found(format!("connection {counterparty_connection_id}")))?;
+	let proven = ConnectionEnd::decode_vec(&proven).map_err(codec_error)?;
+	if proven.client_id() != connection.counterparty().client_id() ||
+		proven.delay_period() != connection.delay_period()
+	{
+		return Err(Error::Custom(format!(
+			"connection {counterparty_connection_id} of {} doesn't match",
+			counterparty.name
+		)))
+	}
+	Ok(proven)
+}
+
+/// Verifies the proofs of the client of the chain on the counterparty, carried by the
+/// `ConnOpenTry` and `ConnOpenAck` messages.
+fn verify_client_proofs(
+	host: &Host,
+	counterparty: &Ledger,
+	connection: &ConnectionEnd,
+	client_state: Option<&AnyClientState>,
+	proofs: &Proofs,
+) -> Result<()> {
+	let counterparty_client_id = connection.counterparty().client_id();
+	if let (Some(client_state), Some(proof)) = (client_state, proofs.client_proof()) {
+		let expected = client_state.encode_vec().map_err(codec_error)?;
+		verify_value(
+			host,
+			counterparty,
+			connection,
+			proof,
+			proofs.height(),
+			ClientStatePath(counterparty_client_id.clone()),
+			&expected,
+		)?;
+	}
+	let consensus_proof = proofs
+		.consensus_proof()
+		.ok_or_else(|| Error::Custom("missing consensus state proof".to_string()))?;
+	verify(
+		host,
+		counterparty,
+		connection,
+		consensus_proof.proof(),
+		proofs.height(),
+		consensus_state_path(counterparty_client_id, consensus_proof.height()),
+	)?
+	.ok_or_else(|| not_found(format!("consensus state of {counterparty_client_id}")))?;
+	Ok(())
+}
+
+/// Verifies the proof of the channel end `port_id`/`channel_id` of the counterparty and returns
+/// it.
+fn verify_counterparty_channel(
+	host: &Host,
+	counterparty: &Ledger,
+	connection: &ConnectionEnd,
+	port_id: &PortId,
+	channel_id: &ChannelId,
+	proofs: &Proofs,
+) -> Result<ChannelEnd> {
+	let proven = verify(
+		host,
+		counterparty,
+		connection,
+		proofs.object_proof(),
+		proofs.height(),
+		ChannelEndsPath(port_id.clone(), *channel_id),
+	)?
+	.ok_or_else(|| not_found(format!("channel {channel_id}/{port_id}")))?;
+	ChannelEnd::decode_vec(&proven).map_err(codec_error)
+}
+
+/// Checks that the packets proven at `proof_height` by `connection` may be processed, the delay
+/// period of the connection having passed since its client was updated to it.
+fn verify_delay_passed(
+	host: &Host,
+	connection: &ConnectionEnd,
+	proof_height: Height,
+) -> Result<()> {
+	let delay = connection.delay_period();
+	if delay.is_zero() {
+		return Ok(())
+	}
+	let client_id = connection.client_id();
+	let (update_height, update_time) =
+		host.view(|state| state.client_update(client_id, proof_height))?;
+	let blocks = calculate_block_delay(delay, host.max_expected_time_per_block());
+	let (height, time) = (host.host_height().revision_height, host.host_timestamp().nanoseconds());
+	if time < update_time + delay.as_nanos() as u64 || height < update_height + blocks {
+		return Err(Error::Custom(format!(
+			"delay period of {delay:?} hasn't passed since client {client_id} was updated to {proof_height}"
+		)))
+	}
+	Ok(())
+}
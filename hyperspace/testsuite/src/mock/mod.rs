@@ -0,0 +1,237 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! In-process chains for running the relayer and the testsuite scenarios without any node.
+//!
+//! A pair of [`MockChain`]s shares an in-memory IBC state machine tracking each other with the
+//! mock light client of `ibc`, whose messages are executed by the handlers of `ibc`. Blocks are
+//! produced on demand or by a background task, and their heights and timestamps only depend on the
+//! number of produced blocks, so that the scenarios are deterministic. Faults can be injected to
+//! exercise the recovery paths of the relayer: dropped submissions, lagging finality and corrupted
+//! proofs or headers.
+
+mod host;
+mod ledger;
+mod provider;
+mod store;
+
+pub use ledger::REVISION_NUMBER;
+
+/// Denom of the balance the relayer account of a mock chain needs to hold to be rotated to.
+pub const FEE_DENOM: &str = "fee";
+
+use hyperspace_primitives::{ChannelWhitelist, CommonClientState};
+use ibc::{
+	core::{
+		ics04_channel::Version,
+		ics23_commitment::commitment::CommitmentPrefix,
+		ics24_host::identifier::{ClientId, ConnectionId},
+	},
+	signer::Signer,
+};
+use ledger::Ledger;
+use std::{
+	str::FromStr,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+use tokio::task::JoinHandle;
+
+/// Configuration of a [`MockChain`].
+#[derive(Debug, Clone)]
+pub struct MockChainConfig {
+	/// Name of the chain
+	pub name: String,
+	/// Time between the timestamps of consecutive blocks
+	pub block_time: Duration,
+	/// Number of blocks a block is finalized after
+	pub finality_lag: u64,
+	/// Number of blocks after which an update of the client of the chain is mandatory
+	pub mandatory_update_interval: u64,
+	/// Prefix of the IBC store of the chain
+	pub commitment_prefix: Vec<u8>,
+}
+
+impl MockChainConfig {
+	pub fn new(name: impl Into<String>) -> Self {
+		Self {
+			name: name.into(),
+			block_time: Duration::from_secs(6),
+			finality_lag: 0,
+			mandatory_update_interval: 10,
+			commitment_prefix: b"ibc/".to_vec(),
+		}
+	}
+}
+
+/// A chain of a pair of in-process chains, see the [module documentation](self).
+///
+/// Clones share the state of the chain, as well as the relayed client, connections and channels
+/// like the clients of the other chains do.
+#[derive(Clone)]
+pub struct MockChain {
+	name: String,
+	/// Index of the ledger of the chain in `network`
+	index: usize,
+	network: Arc<Mutex<[Ledger; 2]>>,
+	mandatory_update_interval: u64,
+	client_id: Arc<Mutex<Option<ClientId>>>,
+	connection_ids: Arc<Mutex<Vec<ConnectionId>>>,
+	channel_whitelist: Arc<Mutex<ChannelWhitelist>>,
+	common_state: CommonClientState,
+	/// Relayer account, which is the secret of its key on mock chains
+	account: Arc<Mutex<Signer>>,
+}
+
+impl MockChain {
+	/// Two chains tracking each other, each at its genesis block.
+	pub fn pair(config_a: MockChainConfig, config_b: MockChainConfig) -> (Self, Self) {
+		let network = Arc::new(Mutex::new([Ledger::new(&config_a), Ledger::new(&config_b)]));
+		let chain_a = Self::new(&config_a, 0, network.clone());
+		let chain_b = Self::new(&config_b, 1, network);
+		(chain_a, chain_b)
+	}
+
+	fn new(config: &MockChainConfig, index: usize, network: Arc<Mutex<[Ledger; 2]>>) -> Self {
+		let rpc_call_delay = Duration::from_millis(10);
+		Self {
+			name: config.name.clone(),
+			index,
+			network,
+			mandatory_update_interval: config.mandatory_update_interval,
+			client_id: Default::default(),
+			connection_ids: Default::default(),
+			channel_whitelist: Default::default(),
+			common_state: CommonClientState {
+				rpc_call_delay,
+				initial_rpc_call_delay: rpc_call_delay,
+				..Default::default()
+			},
+			account: Arc::new(Mutex::new(
+				Signer::from_str(&format!("{}-relayer", config.name))
+					.expect("Relayer account can't be empty"),
+			)),
+		}
+	}
+
+	/// Runs `f` with the ledgers of this chain and of its counterparty.
+	fn with_ledgers<T>(&self, f: impl FnOnce(&mut Ledger, &mut Ledger) -> T) -> T {
+		let mut network = self.network.lock().unwrap();
+		let [a, b] = &mut *network;
+		match self.index {
+			0 => f(a, b),
+			_ => f(b, a),
+		}
+	}
+
+	fn with_ledger<T>(&self, f: impl FnOnce(&mut Ledger) -> T) -> T {
+		self.with_ledgers(|host, _| f(host))
+	}
+
+	/// Produces a block including the pending transactions and returns its height.
+	pub fn produce_block(&self) -> u64 {
+		self.with_ledger(Ledger::produce_block)
+	}
+
+	/// Produces a block on this chain every `interval` until the returned task is aborted.
+	pub fn start_block_production(&self, interval: Duration) -> JoinHandle<()> {
+		let chain = self.clone();
+		tokio::spawn(async move {
+			let mut interval = tokio::time::interval(interval);
+			loop {
+				interval.tick().await;
+				chain.produce_block();
+			}
+		})
+	}
+
+	/// Drops the next `count` submissions to the chain, which fail as if they timed out.
+	pub fn drop_submissions(&self, count: usize) {
+		self.with_ledger(|ledger| ledger.faults.dropped_submissions = count)
+	}
+
+	/// Number of submissions left to drop.
+	pub fn dropped_submissions(&self) -> usize {
+		self.with_ledger(|ledger| ledger.faults.dropped_submissions)
+	}
+
+	/// Finalizes blocks `lag` blocks after they are produced. Blocks that are already finalized
+	/// stay so.
+	pub fn set_finality_lag(&self, lag: u64) {
+		self.with_ledger(|ledger| ledger.finality_lag = lag)
+	}
+
+	/// Delays the submissions to the chain by `delay` after their transactions are signed, until
+	/// reset to zero.
+	pub fn set_submission_delay(&self, delay: Duration) {
+		self.with_ledger(|ledger| ledger.faults.submission_delay = delay)
+	}
+
+	/// Number of transactions rejected because another transaction of their account was included
+	/// after they were signed.
+	pub fn sequence_mismatches(&self) -> usize {
+		self.with_ledger(|ledger| ledger.sequence_mismatches)
+	}
+
+	/// Stops finalizing the blocks of the chain, and its finality notifications, until resumed.
+	/// The blocks produced meanwhile are finalized with the next block once resumed.
+	pub fn set_finality_paused(&self, paused: bool) {
+		self.with_ledger(|ledger| ledger.faults.finality_paused = paused)
+	}
+
+	/// Corrupts the proofs queried from the chain until disabled.
+	pub fn set_corrupt_proofs(&self, corrupt: bool) {
+		self.with_ledger(|ledger| ledger.faults.corrupt_proofs = corrupt)
+	}
+
+	/// Corrupts the headers of the client updates built from the chain until disabled.
+	pub fn set_corrupt_headers(&self, corrupt: bool) {
+		self.with_ledger(|ledger| ledger.faults.corrupt_headers = corrupt)
+	}
+
+	/// Keeps `connection_id`, its channels and packets in a store of its own under `prefix`.
+	pub fn set_connection_prefix(&self, connection_id: ConnectionId, prefix: &[u8]) {
+		let prefix = CommitmentPrefix::try_from(prefix.to_vec())
+			.expect("The commitment prefix of a connection can't be empty");
+		self.with_ledger(|ledger| ledger.connection_prefixes.insert(connection_id, prefix));
+	}
+
+	/// Opens the transfer channels proposed by the counterparty with `version`, or with the
+	/// counterparty version if unset.
+	pub fn set_transfer_version(&self, version: Option<&str>) {
+		let version = version.map(|version| Version::new(version.to_string()));
+		self.with_ledger(|ledger| ledger.transfer_version = version);
+	}
+
+	/// Takes the prefix and path of each proof queried from the chain with
+	/// [`IbcProvider::query_proof`](hyperspace_primitives::IbcProvider::query_proof) so far.
+	pub fn take_proof_requests(&self) -> Vec<(CommitmentPrefix, String)> {
+		self.with_ledger(|ledger| std::mem::take(&mut ledger.proof_requests))
+	}
+
+	/// Height of the latest produced block.
+	pub fn latest_height(&self) -> u64 {
+		self.with_ledger(|ledger| ledger.latest_height())
+	}
+
+	/// Height of the latest finalized block.
+	pub fn finalized_height(&self) -> u64 {
+		self.with_ledger(|ledger| ledger.finalized)
+	}
+
+	/// Sender and type url of each message submitted to the chain.
+	pub fn message_senders(&self) -> Vec<(String, String)> {
+		self.with_ledger(|ledger| ledger.message_senders.clone())
+	}
+}
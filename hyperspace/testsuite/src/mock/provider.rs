@@ -0,0 +1,957 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{
+	ledger::{codec_error, height, not_found, unsupported, Ledger},
+	store::{consensus_state_path, State},
+	MockChain, FEE_DENOM,
+};
+use futures::Stream;
+use hyperspace_primitives::{
+	error::Error,
+	filter_events_by_ids,
+	misbehaviour::DetectedMisbehaviour,
+	mock::LocalClientTypes,
+	query_maximum_height_for_timeout_proofs, set_primary_connection_id,
+	signer::{set_message_signer, KeyRotation, KeySource},
+	Chain, ClientStatus, CommonClientState, IbcProvider, KeyProvider, LightClientSync,
+	MisbehaviourHandler, SimulationResult, TestProvider, UpdateType, WasmUploadStatus,
+};
+use ibc::{
+	applications::transfer::{msgs::transfer::MsgTransfer, Amount, PrefixedCoin, PrefixedDenom},
+	core::{
+		ics02_client::{
+			client_state::{ClientState as _, ClientType},
+			events::UpdateClient,
+			msgs::update_client::MsgUpdateAnyClient,
+		},
+		ics03_connection::connection::IdentifiedConnectionEnd,
+		ics04_channel::channel::IdentifiedChannelEnd,
+		ics23_commitment::commitment::CommitmentPrefix,
+		ics24_host::{
+			identifier::{ChannelId, ClientId, ConnectionId, PortId},
+			path::{
+				AcksPath, ChannelEndsPath, ClientStatePath, CommitmentsPath, ConnectionsPath,
+				ReceiptsPath, SeqRecvsPath,
+			},
+		},
+	},
+	events::IbcEvent,
+	mock::{
+		client_state::{MockClientState, MockConsensusState},
+		header::MockClientMessage,
+	},
+	signer::Signer,
+	timestamp::Timestamp,
+	tx_msg::Msg,
+	Height,
+};
+use ibc_proto::{
+	google::protobuf::Any,
+	ibc::{
+		applications::{fee::v1::Fee, transfer::v1::DenomTrace},
+		core::{
+			channel::v1::{
+				QueryChannelResponse, QueryChannelsResponse, QueryNextSequenceReceiveResponse,
+				QueryPacketAcknowledgementResponse, QueryPacketCommitmentResponse,
+				QueryPacketReceiptResponse,
+			},
+			client::v1::{QueryClientStateResponse, QueryConsensusStateResponse},
+			connection::v1::{IdentifiedConnection, QueryConnectionResponse},
+		},
+	},
+};
+use ibc_rpc::PacketInfo;
+use pallet_ibc::light_clients::{AnyClientMessage, AnyClientState, AnyConsensusState};
+use std::{collections::HashSet, fmt::Display, pin::Pin, str::FromStr, time::Duration};
+use tendermint_proto::Protobuf;
+
+impl MockChain {
+	/// Reads the state at `at` with `f`, along with the proof of the value at `path`.
+	fn query_proven<T>(
+		&self,
+		at: Height,
+		path: impl Display,
+		f: impl FnOnce(State) -> Result<T, Error>,
+	) -> Result<(T, Vec<u8>), Error> {
+		self.with_ledger(|ledger| {
+			let value = f(ledger.state_at(at)?)?;
+			let proof = ledger.proof(at.revision_height, &ledger.prefix, &path.to_string())?;
+			Ok((value, proof))
+		})
+	}
+
+	/// Events of the transaction `tx_id` matching `f`.
+	fn find_in_tx<T>(
+		&self,
+		tx_id: (u64, usize),
+		f: impl Fn(&IbcEvent) -> Option<T>,
+	) -> Result<T, Error> {
+		self.with_ledger(|ledger| {
+			ledger
+				.tx_events(tx_id)?
+				.iter()
+				.find_map(f)
+				.ok_or_else(|| not_found(format!("event in transaction {tx_id:?}")))
+		})
+	}
+}
+
+#[async_trait::async_trait]
+impl IbcProvider for MockChain {
+	/// Height of the latest finalized block
+	type FinalityEvent = u64;
+	/// Height of the block including the transaction and index of the transaction in the block
+	type TransactionId = (u64, usize);
+	/// Base denom of a native asset or `ibc/` denom of vouchers
+	type AssetId = String;
+	type Error = Error;
+
+	async fn query_latest_ibc_events<T>(
+		&mut self,
+		finality_event: Self::FinalityEvent,
+		counterparty: &T,
+	) -> Result<Vec<(Any, Height, Vec<IbcEvent>, UpdateType)>, anyhow::Error>
+	where
+		T: Chain,
+	{
+		let client_id = self.client_id();
+		let (latest_cp_height, _) = counterparty.latest_height_and_timestamp().await?;
+		let client_state = counterparty
+			.query_client_state(latest_cp_height, client_id.clone())
+			.await?
+			.client_state
+			.ok_or_else(|| Error::Custom("counterparty returned empty client state".to_string()))?;
+		let client_state = AnyClientState::try_from(client_state)
+			.map_err(|e| Error::Custom(format!("failed to decode client state: {e}")))?;
+
+		// updates submitted to the counterparty aren't part of its client state until they're
+		// included
+		let from = self
+			.common_state()
+			.submitted_updates
+			.expected_height(counterparty.name(), &client_id, client_state.latest_height())
+			.revision_height;
+		let to = finality_event.min(self.finalized_height());
+		if to <= from {
+			return Ok(vec![])
+		}
+
+		// the counterparty may need a consensus state of this chain to prove the timeouts of its
+		// packets
+		let timeout_height = query_maximum_height_for_timeout_proofs(counterparty, self)
+			.await
+			.filter(|height| (from + 1..=to).contains(height));
+		let is_update_required = self.is_update_required(to, from).await?;
+
+		let mut channel_whitelist = self.channel_whitelist();
+		channel_whitelist.extend(counterparty.channel_whitelist());
+		let client_ids = [self.client_id(), counterparty.client_id()];
+		let connection_ids = [self.connection_ids(), counterparty.connection_ids()].concat();
+		let auto_whitelist_channels = self.common_state().auto_whitelist_channels ||
+			counterparty.common_state().auto_whitelist_channels;
+		let blocks = self.with_ledger(|ledger| {
+			(from + 1..=to)
+				.map(|height| {
+					let events = ledger
+						.block(height)?
+						.events()
+						.filter(|event| {
+							filter_events_by_ids(
+								event,
+								&client_ids,
+								&connection_ids,
+								&channel_whitelist,
+								auto_whitelist_channels,
+							)
+						})
+						.cloned()
+						.collect::<Vec<_>>();
+					Ok((ledger.update_header(height)?, events))
+				})
+				.collect::<Result<Vec<_>, Error>>()
+		})?;
+
+		let mut updates = vec![];
+		for (header, events) in blocks {
+			let height = header.height.revision_height;
+			let mandatory = (is_update_required && height == to) || timeout_height == Some(height);
+			// only update the client to the heights that have events or are mandatory
+			if events.is_empty() && !mandatory {
+				continue
+			}
+			let msg = MsgUpdateAnyClient::<LocalClientTypes> {
+				client_id: client_id.clone(),
+				client_message: AnyClientMessage::Mock(MockClientMessage::Header(header)),
+				signer: counterparty.account_id(),
+			};
+			let value = msg.encode_vec()?;
+			let update_type = match mandatory {
+				true => UpdateType::Mandatory,
+				false => UpdateType::Optional,
+			};
+			updates.push((
+				Any { value, type_url: msg.type_url() },
+				header.height,
+				events,
+				update_type,
+			));
+		}
+		Ok(updates)
+	}
+
+	async fn ibc_events(&self) -> Pin<Box<dyn Stream<Item = IbcEvent> + Send + 'static>> {
+		Box::pin(self.with_ledger(Ledger::subscribe_events))
+	}
+
+	async fn query_client_consensus(
+		&self,
+		at: Height,
+		client_id: ClientId,
+		consensus_height: Height,
+	) -> Result<QueryConsensusStateResponse, Self::Error> {
+		let path = consensus_state_path(&client_id, consensus_height);
+		let (consensus_state, proof) = self
+			.query_proven(at, path, |state| state.consensus_state(&client_id, consensus_height))?;
+		Ok(QueryConsensusStateResponse {
+			consensus_state: Some(AnyConsensusState::Mock(consensus_state).into()),
+			proof,
+			proof_height: Some(at.into()),
+		})
+	}
+
+	async fn query_client_state(
+		&self,
+		at: Height,
+		client_id: ClientId,
+	) -> Result<QueryClientStateResponse, Self::Error> {
+		let path = ClientStatePath(client_id.clone());
+		let (client_state, proof) =
+			self.query_proven(at, path, |state| state.client_state(&client_id))?;
+		Ok(QueryClientStateResponse {
+			client_state: Some(AnyClientState::Mock(client_state).into()),
+			proof,
+			proof_height: Some(at.into()),
+		})
+	}
+
+	async fn query_client_status(
+		&self,
+		at: Height,
+		client_id: ClientId,
+	) -> Result<ClientStatus, Self::Error> {
+		let client_state =
+			self.with_ledger(|ledger| ledger.state_at(at)?.client_state(&client_id))?;
+		Ok(match client_state.frozen_height {
+			Some(_) => ClientStatus::Frozen,
+			None => ClientStatus::Active,
+		})
+	}
+
+	async fn query_connection_end(
+		&self,
+		at: Height,
+		connection_id: ConnectionId,
+	) -> Result<QueryConnectionResponse, Self::Error> {
+		let path = ConnectionsPath(connection_id.clone());
+		let (connection, proof) =
+			self.query_proven(at, path, |state| state.connection(&connection_id))?;
+		Ok(QueryConnectionResponse {
+			connection: Some(connection.into()),
+			proof,
+			proof_height: Some(at.into()),
+		})
+	}
+
+	async fn query_channel_end(
+		&self,
+		at: Height,
+		channel_id: ChannelId,
+		port_id: PortId,
+	) -> Result<QueryChannelResponse, Self::Error> {
+		let path = ChannelEndsPath(port_id.clone(), channel_id);
+		let (channel, proof) =
+			self.query_proven(at, path, |state| state.channel(&port_id, &channel_id))?;
+		Ok(QueryChannelResponse {
+			channel: Some(channel.into()),
+			proof,
+			proof_height: Some(at.into()),
+		})
+	}
+
+	async fn query_proof(
+		&self,
+		at: Height,
+		prefix: &CommitmentPrefix,
+		keys: Vec<Vec<u8>>,
+	) -> Result<Vec<u8>, Self::Error> {
+		let [key] = <[Vec<u8>; 1]>::try_from(keys)
+			.map_err(|keys| unsupported(format!("proofs of {} keys", keys.len())))?;
+		let path = String::from_utf8(key).map_err(codec_error)?;
+		self.with_ledger(|ledger| {
+			ledger.proof_requests.push((prefix.clone(), path.clone()));
+			ledger.proof(at.revision_height, prefix, &path)
+		})
+	}
+
+	async fn query_packet_commitment(
+		&self,
+		at: Height,
+		port_id: &PortId,
+		channel_id: &ChannelId,
+		seq: u64,
+	) -> Result<QueryPacketCommitmentResponse, Self::Error> {
+		let path = CommitmentsPath {
+			port_id: port_id.clone(),
+			channel_id: *channel_id,
+			sequence: seq.into(),
+		};
+		let (commitment, proof) =
+			self.query_proven(at, &path, |state| Ok(state.get(&path).unwrap_or_default()))?;
+		Ok(QueryPacketCommitmentResponse { commitment, proof, proof_height: Some(at.into()) })
+	}
+
+	async fn query_packet_acknowledgement(
+		&self,
+		at: Height,
+		port_id: &PortId,
+		channel_id: &ChannelId,
+		seq: u64,
+	) -> Result<QueryPacketAcknowledgementResponse, Self::Error> {
+		let path =
+			AcksPath { port_id: port_id.clone(), channel_id: *channel_id, sequence: seq.into() };
+		let (acknowledgement, proof) =
+			self.query_proven(at, &path, |state| Ok(state.get(&path).unwrap_or_default()))?;
+		Ok(QueryPacketAcknowledgementResponse {
+			acknowledgement,
+			proof,
+			proof_height: Some(at.into()),
+		})
+	}
+
+	async fn query_next_sequence_recv(
+		&self,
+		at: Height,
+		port_id: &PortId,
+		channel_id: &ChannelId,
+	) -> Result<QueryNextSequenceReceiveResponse, Self::Error> {
+		let path = SeqRecvsPath(port_id.clone(), *channel_id);
+		let (next_sequence_receive, proof) =
+			self.query_proven(at, &path, |state| state.sequence(&path))?;
+		Ok(QueryNextSequenceReceiveResponse {
+			next_sequence_receive,
+			proof,
+			proof_height: Some(at.into()),
+		})
+	}
+
+	async fn query_packet_receipt(
+		&self,
+		at: Height,
+		port_id: &PortId,
+		channel_id: &ChannelId,
+		seq: u64,
+	) -> Result<QueryPacketReceiptResponse, Self::Error> {
+		let path = ReceiptsPath {
+			port_id: port_id.clone(),
+			channel_id: *channel_id,
+			sequence: seq.into(),
+		};
+		let (received, proof) =
+			self.query_proven(at, &path, |state| Ok(state.get(&path).is_some()))?;
+		Ok(QueryPacketReceiptResponse { received, proof, proof_height: Some(at.into()) })
+	}
+
+	async fn latest_height_and_timestamp(&self) -> Result<(Height, Timestamp), Self::Error> {
+		self.with_ledger(|ledger| {
+			Ok((height(ledger.finalized), ledger.timestamp(ledger.finalized)?))
+		})
+	}
+
+	async fn query_packet_commitments(
+		&self,
+		at: Height,
+		channel_id: ChannelId,
+		port_id: PortId,
+	) -> Result<Vec<u64>, Self::Error> {
+		self.with_ledger(|ledger| {
+			Ok(ledger.state_at(at)?.packet_sequences("commitments", &port_id, &channel_id))
+		})
+	}
+
+	async fn query_packet_acknowledgements(
+		&self,
+		at: Height,
+		channel_id: ChannelId,
+		port_id: PortId,
+	) -> Result<Vec<u64>, Self::Error> {
+		self.with_ledger(|ledger| {
+			Ok(ledger.state_at(at)?.packet_sequences("acks", &port_id, &channel_id))
+		})
+	}
+
+	async fn query_unreceived_packets(
+		&self,
+		at: Height,
+		channel_id: ChannelId,
+		port_id: PortId,
+		seqs: Vec<u64>,
+	) -> Result<Vec<u64>, Self::Error> {
+		self.with_ledger(|ledger| {
+			let state = ledger.state_at(at)?;
+			Ok(seqs
+				.into_iter()
+				.filter(|seq| {
+					let path = ReceiptsPath {
+						port_id: port_id.clone(),
+						channel_id,
+						sequence: (*seq).into(),
+					};
+					state.get(path).is_none()
+				})
+				.collect())
+		})
+	}
+
+	async fn query_unreceived_acknowledgements(
+		&self,
+		at: Height,
+		channel_id: ChannelId,
+		port_id: PortId,
+		seqs: Vec<u64>,
+	) -> Result<Vec<u64>, Self::Error> {
+		self.with_ledger(|ledger| {
+			let state = ledger.state_at(at)?;
+			Ok(seqs
+				.into_iter()
+				.filter(|seq| {
+					let path = CommitmentsPath {
+						port_id: port_id.clone(),
+						channel_id,
+						sequence: (*seq).into(),
+					};
+					state.get(path).is_some()
+				})
+				.collect())
+		})
+	}
+
+	fn channel_whitelist(&self) -> HashSet<(ChannelId, PortId)> {
+		self.channel_whitelist.lock().unwrap().all()
+	}
+
+	fn is_channel_whitelisted_on(
+		&self,
+		channel: &(ChannelId, PortId),
+		connection_id: &ConnectionId,
+	) -> bool {
+		self.channel_whitelist.lock().unwrap().allows(channel, connection_id)
+	}
+
+	async fn query_connection_channels(
+		&self,
+		at: Height,
+		connection_id: &ConnectionId,
+	) -> Result<QueryChannelsResponse, Self::Error> {
+		let channels = self.with_ledger(|ledger| {
+			let state = ledger.state_at(at)?;
+			let mut channels = vec![];
+			for (port_id, channel_id) in state.channels() {
+				let channel = state.channel(&port_id, &channel_id)?;
+				if channel.connection_hops().first() == Some(connection_id) {
+					channels.push(IdentifiedChannelEnd::new(port_id, channel_id, channel).into());
+				}
+			}
+			Ok::<_, Error>(channels)
+		})?;
+		Ok(QueryChannelsResponse { channels, pagination: None, height: Some(at.into()) })
+	}
+
+	async fn query_send_packets(
+		&self,
+		channel_id: ChannelId,
+		port_id: PortId,
+		seqs: Vec<u64>,
+	) -> Result<Vec<PacketInfo>, Self::Error> {
+		self.with_ledger(|ledger| {
+			let state = ledger.state();
+			seqs.into_iter()
+				.filter_map(|seq| state.sent_packet(&port_id, &channel_id, seq).transpose())
+				.collect()
+		})
+	}
+
+	async fn query_received_packets(
+		&self,
+		channel_id: ChannelId,
+		port_id: PortId,
+		seqs: Vec<u64>,
+	) -> Result<Vec<PacketInfo>, Self::Error> {
+		self.with_ledger(|ledger| {
+			let state = ledger.state();
+			seqs.into_iter()
+				.filter_map(|seq| state.received_packet(&port_id, &channel_id, seq).transpose())
+				.collect()
+		})
+	}
+
+	fn expected_block_time(&self) -> Duration {
+		self.with_ledger(|ledger| ledger.block_time)
+	}
+
+	async fn query_client_update_time_and_height(
+		&self,
+		client_id: ClientId,
+		client_height: Height,
+	) -> Result<(Height, Timestamp), Self::Error> {
+		self.with_ledger(|ledger| {
+			let (update_height, update_time) =
+				ledger.state().client_update(&client_id, client_height)?;
+			Ok((
+				height(update_height),
+				Timestamp::from_nanoseconds(update_time).map_err(codec_error)?,
+			))
+		})
+	}
+
+	async fn query_host_consensus_state_proof(
+		&self,
+		_client_state: &AnyClientState,
+	) -> Result<Option<Vec<u8>>, Self::Error> {
+		// the consensus states of the mock clients aren't checked against the host
+		Ok(Some(vec![0]))
+	}
+
+	async fn query_ibc_balance(
+		&self,
+		asset_id: Self::AssetId,
+	) -> Result<Vec<PrefixedCoin>, Self::Error> {
+		let (denom, amount) = self.with_ledger(|ledger| {
+			let denom = ledger.state().full_denom(&asset_id)?;
+			let amount = ledger.state().balance(&self.account_id().to_string(), &denom);
+			Ok::<_, Error>((denom, amount))
+		})?;
+		Ok(vec![PrefixedCoin {
+			denom: PrefixedDenom::from_str(&denom).map_err(codec_error)?,
+			amount: Amount::from_str(&amount.to_string()).map_err(codec_error)?,
+		}])
+	}
+
+	async fn query_relayer_balance(&self) -> Result<u128, Self::Error> {
+		Err(unsupported("fees"))
+	}
+
+	async fn query_denom_trace(&self, hash: String) -> Result<DenomTrace, Self::Error> {
+		let ibc_denom = match hash.starts_with("ibc/") {
+			true => hash,
+			false => format!("ibc/{hash}"),
+		};
+		self.with_ledger(|ledger| ledger.state().denom_trace(&ibc_denom).map(DenomTrace::from))
+	}
+
+	fn connection_prefix(&self) -> CommitmentPrefix {
+		self.with_ledger(|ledger| ledger.prefix.clone())
+	}
+
+	fn connection_prefix_of(&self, connection_id: &ConnectionId) -> CommitmentPrefix {
+		self.with_ledger(|ledger| {
+			ledger.connection_prefixes.get(connection_id).unwrap_or(&ledger.prefix).clone()
+		})
+	}
+
+	fn client_id(&self) -> ClientId {
+		self.client_id
+			.lock()
+			.unwrap()
+			.as_ref()
+			.expect("Client Id should be defined")
+			.clone()
+	}
+
+	fn set_client_id(&mut self, client_id: ClientId) {
+		*self.client_id.lock().unwrap() = Some(client_id);
+	}
+
+	fn connection_id(&self) -> Option<ConnectionId> {
+		self.connection_ids.lock().unwrap().first().cloned()
+	}
+
+	fn connection_ids(&self) -> Vec<ConnectionId> {
+		self.connection_ids.lock().unwrap().clone()
+	}
+
+	fn set_channel_whitelist(&mut self, channel_whitelist: HashSet<(ChannelId, PortId)>) {
+		self.channel_whitelist.lock().unwrap().set(channel_whitelist);
+	}
+
+	fn add_channel_to_whitelist(&mut self, channel: (ChannelId, PortId)) {
+		self.channel_whitelist.lock().unwrap().insert(channel);
+	}
+
+	fn add_channel_to_connection_whitelist(
+		&mut self,
+		connection_id: ConnectionId,
+		channel: (ChannelId, PortId),
+	) {
+		self.channel_whitelist.lock().unwrap().insert_on(connection_id, channel);
+	}
+
+	fn set_connection_id(&mut self, connection_id: ConnectionId) {
+		set_primary_connection_id(&mut self.connection_ids.lock().unwrap(), connection_id);
+	}
+
+	fn add_connection_id(&mut self, connection_id: ConnectionId) {
+		let mut connection_ids = self.connection_ids.lock().unwrap();
+		if !connection_ids.contains(&connection_id) {
+			connection_ids.push(connection_id);
+		}
+	}
+
+	fn client_type(&self) -> ClientType {
+		MockClientState::client_type()
+	}
+
+	async fn query_timestamp_at(&self, block_number: u64) -> Result<u64, Self::Error> {
+		self.with_ledger(|ledger| Ok(ledger.block(block_number)?.timestamp))
+	}
+
+	async fn query_clients(&self) -> Result<Vec<ClientId>, Self::Error> {
+		Ok(self.with_ledger(|ledger| ledger.state().clients()))
+	}
+
+	async fn query_identified_channels(&self) -> Result<Vec<IdentifiedChannelEnd>, Self::Error> {
+		self.with_ledger(|ledger| {
+			let state = ledger.state();
+			state
+				.channels()
+				.into_iter()
+				.map(|(port_id, channel_id)| {
+					let channel = state.channel(&port_id, &channel_id)?;
+					Ok(IdentifiedChannelEnd::new(port_id, channel_id, channel))
+				})
+				.collect()
+		})
+	}
+
+	async fn query_connection_using_client(
+		&self,
+		height: u32,
+		client_id: String,
+	) -> Result<Vec<IdentifiedConnection>, Self::Error> {
+		self.with_ledger(|ledger| {
+			let state = ledger.state_at(self::height(height as u64))?;
+			let mut connections = vec![];
+			for connection_id in state.connections() {
+				let connection = state.connection(&connection_id)?;
+				if connection.client_id().as_str() == client_id {
+					connections
+						.push(IdentifiedConnectionEnd::new(connection_id, connection).into());
+				}
+			}
+			Ok(connections)
+		})
+	}
+
+	async fn is_update_required(
+		&self,
+		latest_height: u64,
+		latest_client_height_on_counterparty: u64,
+	) -> Result<bool, Self::Error> {
+		let diff = latest_height.saturating_sub(latest_client_height_on_counterparty);
+		Ok(diff >= self.mandatory_update_interval)
+	}
+
+	async fn initialize_client_state(
+		&self,
+	) -> Result<(AnyClientState, AnyConsensusState), Self::Error> {
+		let header = self.with_ledger(|ledger| ledger.header(ledger.finalized))?;
+		Ok((
+			AnyClientState::Mock(MockClientState::new(MockClientMessage::Header(header))),
+			AnyConsensusState::Mock(MockConsensusState::new(header)),
+		))
+	}
+
+	async fn query_client_id_from_tx_hash(
+		&self,
+		tx_id: Self::TransactionId,
+	) -> Result<ClientId, Self::Error> {
+		self.find_in_tx(tx_id, |event| match event {
+			IbcEvent::CreateClient(event) => Some(event.client_id().clone()),
+			_ => None,
+		})
+	}
+
+	async fn query_connection_id_from_tx_hash(
+		&self,
+		tx_id: Self::TransactionId,
+	) -> Result<ConnectionId, Self::Error> {
+		self.find_in_tx(tx_id, |event| match event {
+			IbcEvent::OpenInitConnection(event) => event.connection_id().cloned(),
+			IbcEvent::OpenTryConnection(event) => event.connection_id().cloned(),
+			_ => None,
+		})
+	}
+
+	async fn query_channel_id_from_tx_hash(
+		&self,
+		tx_id: Self::TransactionId,
+	) -> Result<(ChannelId, PortId), Self::Error> {
+		self.find_in_tx(tx_id, |event| match event {
+			IbcEvent::OpenInitChannel(event) => Some((event.channel_id?, event.port_id.clone())),
+			IbcEvent::OpenTryChannel(event) => Some((event.channel_id?, event.port_id.clone())),
+			_ => None,
+		})
+	}
+
+	async fn upload_wasm(
+		&self,
+		_wasm: Vec<u8>,
+	) -> Result<(Vec<u8>, WasmUploadStatus), Self::Error> {
+		Err(unsupported("wasm clients"))
+	}
+
+	async fn query_wasm_code_stored(&self, _code_id: &[u8]) -> Result<bool, Self::Error> {
+		Err(unsupported("wasm clients"))
+	}
+
+	async fn migrate_wasm_client(
+		&mut self,
+		_client_id: ClientId,
+		_code_id: Vec<u8>,
+		_migrate_msg: Vec<u8>,
+	) -> Result<(), Self::Error> {
+		Err(unsupported("wasm clients"))
+	}
+
+	fn register_counterparty_payee_msg(
+		&self,
+		_channel_id: ChannelId,
+		_port_id: PortId,
+		_counterparty_payee: String,
+	) -> Result<Any, Self::Error> {
+		Err(unsupported("fees"))
+	}
+}
+
+#[async_trait::async_trait]
+impl KeyProvider for MockChain {
+	fn account_id(&self) -> Signer {
+		self.account.lock().unwrap().clone()
+	}
+
+	/// The account of a key is the secret of its file, which needs to hold `min_balance` of
+	/// [`FEE_DENOM`].
+	async fn rotate_key(
+		&self,
+		key_source: &KeySource,
+		min_balance: u128,
+	) -> Result<KeyRotation, Error> {
+		if let KeySource::Remote(_) = key_source {
+			return Err(unsupported("remote keys"))
+		}
+		let new_account = Signer::from_str(&key_source.secret()?).map_err(codec_error)?;
+		let balance =
+			self.with_ledger(|ledger| ledger.state().balance(new_account.as_ref(), FEE_DENOM));
+		if balance < min_balance {
+			return Err(Error::Custom(format!(
+				"Account {new_account} holds {balance}{FEE_DENOM}, less than the minimum of {min_balance}"
+			)))
+		}
+		let old_account =
+			std::mem::replace(&mut *self.account.lock().unwrap(), new_account.clone());
+		Ok(KeyRotation { old_account, new_account })
+	}
+}
+
+#[async_trait::async_trait]
+impl MisbehaviourHandler for MockChain {
+	async fn check_for_misbehaviour<C: Chain>(
+		&self,
+		_counterparty: &C,
+		_client_message: AnyClientMessage,
+	) -> Result<Option<DetectedMisbehaviour>, anyhow::Error> {
+		// the headers of the mock clients are checked against the blocks of the chain when
+		// they're submitted
+		Ok(None)
+	}
+}
+
+#[async_trait::async_trait]
+impl LightClientSync for MockChain {
+	async fn is_synced<C: Chain>(&self, _counterparty: &C) -> Result<bool, anyhow::Error> {
+		Ok(true)
+	}
+
+	async fn fetch_mandatory_updates<C: Chain>(
+		&self,
+		_counterparty: &C,
+	) -> Result<(Vec<Any>, Vec<IbcEvent>), anyhow::Error> {
+		Ok((vec![], vec![]))
+	}
+}
+
+#[async_trait::async_trait]
+impl Chain for MockChain {
+	fn name(&self) -> &str {
+		&self.name
+	}
+
+	fn block_max_weight(&self) -> u64 {
+		u64::MAX
+	}
+
+	async fn estimate_weight(&self, msg: Vec<Any>) -> Result<u64, Self::Error> {
+		Ok(msg.len() as u64)
+	}
+
+	async fn simulate(&self, messages: Vec<Any>) -> Result<SimulationResult, Self::Error> {
+		let estimated_weight = messages.len() as u64;
+		let message_results =
+			self.with_ledgers(|ledger, counterparty| ledger.simulate(counterparty, messages));
+		Ok(SimulationResult { estimated_weight, message_results })
+	}
+
+	async fn finality_notifications(
+		&self,
+	) -> Result<Pin<Box<dyn Stream<Item = Self::FinalityEvent> + Send + Sync>>, Self::Error> {
+		Ok(Box::pin(self.with_ledger(Ledger::subscribe_finality)))
+	}
+
+	async fn submit(&self, messages: Vec<Any>) -> Result<Self::TransactionId, Self::Error> {
+		let sender = self.account_id().to_string();
+		// the messages assembled before a rotation of the key still name the previous account
+		let messages = messages
+			.into_iter()
+			.map(|msg| set_message_signer(msg, &sender))
+			.collect::<Vec<_>>();
+		// the transaction is signed with the sequence of the account before its submission
+		let (sequence, delay) = self.with_ledger(|ledger| {
+			(ledger.account_sequence(&sender), ledger.faults.submission_delay)
+		});
+		if !delay.is_zero() {
+			tokio::time::sleep(delay).await;
+		}
+		let tx_id = self.with_ledgers(|ledger, counterparty| {
+			ledger.submit(counterparty, &sender, sequence, messages)
+		})?;
+		log::debug!(target: "hyperspace", "Submitted transaction {tx_id:?} to {}", self.name);
+		Ok(tx_id)
+	}
+
+	async fn query_client_message(
+		&self,
+		update: UpdateClient,
+	) -> Result<AnyClientMessage, Self::Error> {
+		let header = update
+			.header
+			.ok_or_else(|| not_found(format!("header of the update of {}", update.client_id())))?;
+		AnyClientMessage::decode_vec(&header).map_err(codec_error)
+	}
+
+	async fn get_proof_height(&self, block_height: Height) -> Height {
+		block_height
+	}
+
+	/// Mock headers are verified against the blocks of the chain they're built from.
+	async fn verify_client_update<C: Chain>(
+		&self,
+		_counterparty: &C,
+		client_message: AnyClientMessage,
+	) -> Result<(), anyhow::Error> {
+		let AnyClientMessage::Mock(MockClientMessage::Header(header)) = client_message else {
+			return Ok(())
+		};
+		let block_header =
+			self.with_ledger(|ledger| ledger.header(header.height.revision_height))?;
+		if header != block_header {
+			anyhow::bail!("header {header:?} doesn't match block {block_header:?} of {}", self.name)
+		}
+		Ok(())
+	}
+
+	async fn handle_error(&mut self, _error: &anyhow::Error) -> Result<(), anyhow::Error> {
+		Ok(())
+	}
+
+	fn common_state(&self) -> &CommonClientState {
+		&self.common_state
+	}
+
+	fn common_state_mut(&mut self) -> &mut CommonClientState {
+		&mut self.common_state
+	}
+
+	async fn reconnect(&mut self) -> anyhow::Result<()> {
+		Ok(())
+	}
+}
+
+#[async_trait::async_trait]
+impl TestProvider for MockChain {
+	async fn send_transfer(&self, params: MsgTransfer<PrefixedCoin>) -> Result<(), Self::Error> {
+		self.with_ledger(|ledger| ledger.send_transfer(params)).map(|_| ())
+	}
+
+	async fn send_transfer_with_fee(
+		&self,
+		_params: MsgTransfer<PrefixedCoin>,
+		_fee: Fee,
+	) -> Result<(), Self::Error> {
+		Err(unsupported("fees"))
+	}
+
+	async fn send_ordered_packet(
+		&self,
+		_channel_id: ChannelId,
+		_timeout: pallet_ibc::Timeout,
+	) -> Result<(), Self::Error> {
+		Err(unsupported("ordered channels"))
+	}
+
+	async fn send_raw_packet(
+		&self,
+		_port_id: PortId,
+		_channel_id: ChannelId,
+		_data: Vec<u8>,
+		_timeout: pallet_ibc::Timeout,
+	) -> Result<(), Self::Error> {
+		Err(unsupported("raw packets"))
+	}
+
+	async fn subscribe_blocks(&self) -> Pin<Box<dyn Stream<Item = u64> + Send + Sync>> {
+		Box::pin(self.with_ledger(Ledger::subscribe_blocks))
+	}
+
+	async fn increase_counters(&mut self) -> Result<(), Self::Error> {
+		Err(unsupported("increasing the counters"))
+	}
+
+	async fn ensure_asset(
+		&self,
+		asset_id: Self::AssetId,
+		mint_to: Signer,
+		amount: u128,
+	) -> Result<(), Self::Error> {
+		let account = mint_to.to_string();
+		self.with_ledger(|ledger| {
+			let balance = ledger.state().balance(&account, &asset_id);
+			ledger.mint(&account, &asset_id, amount.saturating_sub(balance))
+		});
+		Ok(())
+	}
+
+	async fn query_asset_id(&self, ibc_denom: String) -> Result<Self::AssetId, Self::Error> {
+		Ok(ibc_denom)
+	}
+}
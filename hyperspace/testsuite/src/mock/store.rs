@@ -0,0 +1,356 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The versioned store of a mock chain. Sealed blocks only record the values their transactions
+//! wrote, and the value of a path at a height is the last one written at or below it.
+
+use super::ledger::{codec_error, not_found, unsupported, Result};
+use ibc::{
+	applications::transfer::PrefixedDenom,
+	core::{
+		ics02_client::client_state::ClientState as _,
+		ics03_connection::connection::ConnectionEnd,
+		ics04_channel::channel::ChannelEnd,
+		ics24_host::{
+			identifier::{ChannelId, ClientId, ConnectionId, PortId},
+			path::{ChannelEndsPath, ClientConsensusStatePath, ClientStatePath, ConnectionsPath},
+		},
+	},
+	mock::client_state::{MockClientState, MockConsensusState},
+	Height,
+};
+use ibc_rpc::PacketInfo;
+use pallet_ibc::light_clients::{AnyClientState, AnyConsensusState};
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	fmt::Display,
+	str::FromStr,
+};
+use tendermint_proto::Protobuf;
+
+/// Paths of the counters of the identifiers generated by the chain.
+pub const CLIENT_COUNTER: &str = "nextClientSequence";
+pub const CONNECTION_COUNTER: &str = "nextConnectionSequence";
+pub const CHANNEL_COUNTER: &str = "nextChannelSequence";
+
+/// Writes to the store, `None` deleting the value at a path.
+type Writes = BTreeMap<String, Option<Vec<u8>>>;
+
+pub fn consensus_state_path(client_id: &ClientId, height: Height) -> ClientConsensusStatePath {
+	ClientConsensusStatePath {
+		client_id: client_id.clone(),
+		epoch: height.revision_number,
+		height: height.revision_height,
+	}
+}
+
+/// Host timestamp at which the consensus state of `client_id` at `height` was stored.
+pub fn processed_time_path(client_id: &ClientId, height: Height) -> String {
+	format!("{}/processedTime", consensus_state_path(client_id, height))
+}
+
+/// Host height at which the consensus state of `client_id` at `height` was stored.
+pub fn processed_height_path(client_id: &ClientId, height: Height) -> String {
+	format!("{}/processedHeight", consensus_state_path(client_id, height))
+}
+
+pub fn connection_channels_path(connection_id: &ConnectionId) -> String {
+	format!("connectionChannels/{connection_id}")
+}
+
+pub fn balance_path(account: &str, denom: &str) -> String {
+	format!("balances/{account}/{denom}")
+}
+
+/// Full denom of the vouchers whose `ibc/` denom is `ibc_denom`.
+pub fn denom_trace_path(ibc_denom: &str) -> String {
+	format!("denomTraces/{ibc_denom}")
+}
+
+pub fn sent_packet_path(port_id: &PortId, channel_id: &ChannelId, sequence: u64) -> String {
+	format!("sentPackets/ports/{port_id}/channels/{channel_id}/sequences/{sequence}")
+}
+
+pub fn received_packet_path(port_id: &PortId, channel_id: &ChannelId, sequence: u64) -> String {
+	format!("receivedPackets/ports/{port_id}/channels/{channel_id}/sequences/{sequence}")
+}
+
+/// Acknowledgement written for a received packet, whose commitment is under its `AcksPath`.
+pub fn raw_ack_path(port_id: &PortId, channel_id: &ChannelId, sequence: u64) -> String {
+	format!("rawAcks/ports/{port_id}/channels/{channel_id}/sequences/{sequence}")
+}
+
+/// The values written by the sealed blocks, the pending block and the transaction being executed.
+#[derive(Debug, Default)]
+pub struct Store {
+	/// Values written by the sealed blocks, keyed by path and by the height of the block
+	history: BTreeMap<String, BTreeMap<u64, Option<Vec<u8>>>>,
+	/// Values written by the pending block
+	pending: Writes,
+	/// Values written by the transaction being executed, applied to the pending block once it
+	/// succeeds
+	tx: Option<Writes>,
+}
+
+impl Store {
+	/// Value at `path` after block `at`, or in the pending block if `at` is `None`.
+	pub fn get(&self, at: Option<u64>, path: &str) -> Option<&[u8]> {
+		if at.is_none() {
+			let overlay = self.tx.as_ref().and_then(|tx| tx.get(path));
+			if let Some(value) = overlay.or_else(|| self.pending.get(path)) {
+				return value.as_deref()
+			}
+		}
+		let versions = self.history.get(path)?;
+		let (_, value) = match at {
+			Some(height) => versions.range(..=height).next_back()?,
+			None => versions.iter().next_back()?,
+		};
+		value.as_deref()
+	}
+
+	/// Paths starting with `prefix` that hold a value after block `at`, or in the pending block
+	/// if `at` is `None`.
+	pub fn paths(&self, at: Option<u64>, prefix: &str) -> Vec<String> {
+		let in_range = |writes: &Writes| {
+			writes
+				.range(prefix.to_string()..)
+				.take_while(|(path, _)| path.starts_with(prefix))
+				.map(|(path, _)| path.clone())
+				.collect::<Vec<_>>()
+		};
+		let mut paths = self
+			.history
+			.range(prefix.to_string()..)
+			.take_while(|(path, _)| path.starts_with(prefix))
+			.map(|(path, _)| path.clone())
+			.collect::<BTreeSet<_>>();
+		if at.is_none() {
+			paths.extend(in_range(&self.pending));
+			paths.extend(self.tx.as_ref().map(in_range).unwrap_or_default());
+		}
+		paths.into_iter().filter(|path| self.get(at, path).is_some()).collect()
+	}
+
+	pub fn set(&mut self, path: String, value: Option<Vec<u8>>) {
+		self.tx.as_mut().unwrap_or(&mut self.pending).insert(path, value);
+	}
+
+	/// Starts buffering the writes of a transaction.
+	pub fn begin(&mut self) {
+		self.tx = Some(Writes::new());
+	}
+
+	/// Applies the writes of the transaction to the pending block.
+	pub fn commit(&mut self) {
+		if let Some(tx) = self.tx.take() {
+			self.pending.extend(tx);
+		}
+	}
+
+	/// Discards the writes of the transaction.
+	pub fn rollback(&mut self) {
+		self.tx = None;
+	}
+
+	/// Records the writes of the pending block as the ones of block `height`.
+	pub fn seal(&mut self, height: u64) {
+		for (path, value) in std::mem::take(&mut self.pending) {
+			self.history.entry(path).or_default().insert(height, value);
+		}
+	}
+}
+
+/// The store of a mock chain after a block, or in the pending block.
+#[derive(Clone, Copy)]
+pub struct State<'a> {
+	pub store: &'a Store,
+	pub at: Option<u64>,
+}
+
+impl<'a> State<'a> {
+	pub fn get(&self, path: impl Display) -> Option<Vec<u8>> {
+		self.store.get(self.at, &path.to_string()).map(<[u8]>::to_vec)
+	}
+
+	fn packet_info(&self, path: String) -> Result<Option<PacketInfo>> {
+		self.get(path)
+			.map(|value| json::from_slice(&value).map_err(codec_error))
+			.transpose()
+	}
+
+	pub fn client_state(&self, client_id: &ClientId) -> Result<MockClientState> {
+		let value = self
+			.get(ClientStatePath(client_id.clone()))
+			.ok_or_else(|| not_found(format!("client {client_id}")))?;
+		match AnyClientState::decode_vec(&value).map_err(codec_error)? {
+			AnyClientState::Mock(client_state) => Ok(client_state),
+			client_state => Err(unsupported(format!("client type {}", client_state.client_type()))),
+		}
+	}
+
+	pub fn consensus_state(
+		&self,
+		client_id: &ClientId,
+		height: Height,
+	) -> Result<MockConsensusState> {
+		let value = self.get(consensus_state_path(client_id, height)).ok_or_else(|| {
+			not_found(format!("consensus state of client {client_id} at {height}"))
+		})?;
+		match AnyConsensusState::decode_vec(&value).map_err(codec_error)? {
+			AnyConsensusState::Mock(consensus_state) => Ok(consensus_state),
+			_ => Err(unsupported("consensus states of other clients than mock")),
+		}
+	}
+
+	/// Heights of the consensus states of `client_id`, in ascending order.
+	pub fn consensus_heights(&self, client_id: &ClientId) -> Vec<Height> {
+		let prefix = format!("clients/{client_id}/consensusStates/");
+		let mut heights = self
+			.store
+			.paths(self.at, &prefix)
+			.into_iter()
+			.filter_map(|path| {
+				let (revision_number, revision_height) =
+					path.strip_prefix(&prefix)?.split_once('-')?;
+				Some(Height::new(revision_number.parse().ok()?, revision_height.parse().ok()?))
+			})
+			.collect::<Vec<_>>();
+		heights.sort();
+		heights
+	}
+
+	pub fn connection(&self, connection_id: &ConnectionId) -> Result<ConnectionEnd> {
+		let value = self
+			.get(ConnectionsPath(connection_id.clone()))
+			.ok_or_else(|| not_found(format!("connection {connection_id}")))?;
+		ConnectionEnd::decode_vec(&value).map_err(codec_error)
+	}
+
+	pub fn channel(&self, port_id: &PortId, channel_id: &ChannelId) -> Result<ChannelEnd> {
+		let value = self
+			.get(ChannelEndsPath(port_id.clone(), *channel_id))
+			.ok_or_else(|| not_found(format!("channel {channel_id}/{port_id}")))?;
+		ChannelEnd::decode_vec(&value).map_err(codec_error)
+	}
+
+	/// Sequence stored at `path`, big-endian encoded.
+	pub fn sequence(&self, path: impl Display) -> Result<u64> {
+		let path = path.to_string();
+		let value = self.get(&path).ok_or_else(|| not_found(format!("sequence {path}")))?;
+		let bytes = <[u8; 8]>::try_from(value.as_slice()).map_err(codec_error)?;
+		Ok(u64::from_be_bytes(bytes))
+	}
+
+	pub fn clients(&self) -> Vec<ClientId> {
+		self.store
+			.paths(self.at, "clients/")
+			.into_iter()
+			.filter_map(|path| {
+				let client_id = path.strip_prefix("clients/")?.strip_suffix("/clientType")?;
+				ClientId::from_str(client_id).ok()
+			})
+			.collect()
+	}
+
+	pub fn connections(&self) -> Vec<ConnectionId> {
+		self.store
+			.paths(self.at, "connections/")
+			.into_iter()
+			.filter_map(|path| ConnectionId::from_str(path.strip_prefix("connections/")?).ok())
+			.collect()
+	}
+
+	pub fn channels(&self) -> Vec<(PortId, ChannelId)> {
+		self.store
+			.paths(self.at, "channelEnds/ports/")
+			.into_iter()
+			.filter_map(|path| {
+				let (port_id, channel_id) =
+					path.strip_prefix("channelEnds/ports/")?.split_once("/channels/")?;
+				Some((PortId::from_str(port_id).ok()?, ChannelId::from_str(channel_id).ok()?))
+			})
+			.collect()
+	}
+
+	/// Sequences of the packets of `port_id`/`channel_id` with a value under `kind`, e.g. the
+	/// packets with a commitment under `commitments`.
+	pub fn packet_sequences(
+		&self,
+		kind: &str,
+		port_id: &PortId,
+		channel_id: &ChannelId,
+	) -> Vec<u64> {
+		let prefix = format!("{kind}/ports/{port_id}/channels/{channel_id}/sequences/");
+		let mut sequences = self
+			.store
+			.paths(self.at, &prefix)
+			.into_iter()
+			.filter_map(|path| path.strip_prefix(&prefix)?.parse().ok())
+			.collect::<Vec<_>>();
+		sequences.sort();
+		sequences
+	}
+
+	/// Host height and timestamp at which the consensus state of `client_id` at `height` was
+	/// stored.
+	pub fn client_update(&self, client_id: &ClientId, height: Height) -> Result<(u64, u64)> {
+		let not_updated = || not_found(format!("update of client {client_id} to {height}"));
+		let update_height = self.sequence(processed_height_path(client_id, height));
+		let update_time = self.sequence(processed_time_path(client_id, height));
+		Ok((update_height.map_err(|_| not_updated())?, update_time.map_err(|_| not_updated())?))
+	}
+
+	pub fn balance(&self, account: &str, denom: &str) -> u128 {
+		self.get(balance_path(account, denom))
+			.and_then(|value| <[u8; 16]>::try_from(value.as_slice()).ok())
+			.map(u128::from_be_bytes)
+			.unwrap_or_default()
+	}
+
+	pub fn denom_trace(&self, ibc_denom: &str) -> Result<PrefixedDenom> {
+		let value = self
+			.get(denom_trace_path(ibc_denom))
+			.ok_or_else(|| not_found(format!("denom trace of {ibc_denom}")))?;
+		let denom = String::from_utf8(value).map_err(codec_error)?;
+		PrefixedDenom::from_str(&denom).map_err(codec_error)
+	}
+
+	/// Full denom of the asset `asset_id`, a base denom or the `ibc/` denom of vouchers.
+	pub fn full_denom(&self, asset_id: &str) -> Result<String> {
+		if asset_id.starts_with("ibc/") {
+			self.denom_trace(asset_id).map(|denom| denom.to_string())
+		} else {
+			Ok(asset_id.to_string())
+		}
+	}
+
+	pub fn sent_packet(
+		&self,
+		port_id: &PortId,
+		channel_id: &ChannelId,
+		sequence: u64,
+	) -> Result<Option<PacketInfo>> {
+		self.packet_info(sent_packet_path(port_id, channel_id, sequence))
+	}
+
+	pub fn received_packet(
+		&self,
+		port_id: &PortId,
+		channel_id: &ChannelId,
+		sequence: u64,
+	) -> Result<Option<PacketInfo>> {
+		self.packet_info(received_packet_path(port_id, channel_id, sequence))
+	}
+}
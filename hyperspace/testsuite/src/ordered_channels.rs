@@ -12,19 +12,19 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::{assert_timeout_packet, timeout_future, StreamExt};
+use crate::{assert_timeout_packet, setup_path, timeout_future, PathParams, StreamExt};
 use futures::future;
-use hyperspace_core::send_packet_relay::set_relay_status;
-use hyperspace_primitives::{
-	utils::{create_channel, create_connection},
-	TestProvider,
+use hyperspace_core::{
+	packets::utils::construct_recv_message, send_packet_relay::set_relay_status,
 };
+use hyperspace_primitives::{packet_info_to_packet, TestProvider};
 use ibc::{
 	core::{
 		ics04_channel::channel::{ChannelEnd, Order, State},
 		ics24_host::identifier::{ChannelId, ConnectionId, PortId},
 	},
 	events::IbcEvent,
+	Height,
 };
 use pallet_ibc::Timeout;
 use std::{str::FromStr, time::Duration};
@@ -47,79 +47,18 @@ where
 	B::FinalityEvent: Send + Sync,
 	B::Error: From<A::Error>,
 {
-	let client_a_clone = chain_a.clone();
-	let client_b_clone = chain_b.clone();
-	// Start relayer loop
-	let handle = tokio::task::spawn(async move {
-		hyperspace_core::relay(client_a_clone, client_b_clone, None, None, None)
-			.await
-			.unwrap()
-	});
-	// check if an open ping channel exists
-	let (latest_height, ..) = chain_a.latest_height_and_timestamp().await.unwrap();
-	let connections = chain_a
-		.query_connection_using_client(
-			latest_height.revision_height as u32,
-			chain_b.client_id().to_string(),
-		)
-		.await
-		.unwrap();
-
-	for connection in connections {
-		let connection_id = ConnectionId::from_str(&connection.id).unwrap();
-		let connection_end = chain_a
-			.query_connection_end(latest_height, connection_id.clone())
-			.await
-			.unwrap()
-			.connection
-			.unwrap();
-
-		let delay_period = Duration::from_nanos(connection_end.delay_period);
-
-		if delay_period != connection_delay {
-			continue
-		}
-
-		let channels = chain_a
-			.query_connection_channels(latest_height, &connection_id)
-			.await
-			.unwrap()
-			.channels;
-
-		for channel in channels {
-			let channel_id = ChannelId::from_str(&channel.channel_id).unwrap();
-			let channel_end = chain_a
-				.query_channel_end(latest_height, channel_id, port_id.clone())
-				.await
-				.unwrap()
-				.channel
-				.unwrap();
-			let channel_end = ChannelEnd::try_from(channel_end).unwrap();
-
-			if channel_end.state == State::Open && channel.port_id == port_id.to_string() {
-				return (
-					handle,
-					channel_id,
-					channel_end.counterparty().channel_id.unwrap().clone(),
-					channel_end.connection_hops[0].clone(),
-				)
-			}
-		}
-	}
-
-	let (connection_id_a, connection_id_b) =
-		create_connection(chain_a, chain_b, connection_delay).await.unwrap();
-
-	log::info!(target: "hyperspace", "============ Connection handshake completed: ConnectionId({connection_id_a}), ConnectionId({connection_id_b}) ============");
-	log::info!(target: "hyperspace", "=========================== Starting channel handshake ===========================");
-
-	let (channel_id_a, channel_id_b) =
-		create_channel(chain_a, chain_b, connection_id_a.clone(), port_id, version, Order::Ordered)
-			.await
-			.unwrap();
-	// channel handshake completed
-	log::info!(target: "hyperspace", "============ Channel handshake completed: ChannelId({channel_id_a}) ============");
-
+	let (handle, channel_id_a, channel_id_b, connection_id_a, ..) = setup_path(
+		chain_a,
+		chain_b,
+		PathParams {
+			connection_delay,
+			port_id,
+			version,
+			order: Order::Ordered,
+			reuse_existing: true,
+		},
+	)
+	.await;
 	(handle, channel_id_a, channel_id_b, connection_id_a)
 }
 
@@ -294,3 +233,167 @@ pub async fn ibc_messaging_ordered_packet_timeout<A, B>(
 	send_ordered_packet_and_assert_timeout(chain_a, chain_b, channel_id).await;
 	handle.abort()
 }
+
+/// Starts the relayer loop between the two chains.
+fn spawn_relayer<A, B>(chain_a: &A, chain_b: &B) -> JoinHandle<()>
+where
+	A: TestProvider,
+	A::FinalityEvent: Send + Sync,
+	A::Error: From<B::Error>,
+	B: TestProvider,
+	B::FinalityEvent: Send + Sync,
+	B::Error: From<A::Error>,
+{
+	let client_a_clone = chain_a.clone();
+	let client_b_clone = chain_b.clone();
+	tokio::task::spawn(async move {
+		hyperspace_core::relay(client_a_clone, client_b_clone, None, None, None)
+			.await
+			.unwrap()
+	})
+}
+
+/// Next sequence `chain` expects to receive on the channel.
+async fn next_sequence_recv<C: TestProvider>(
+	chain: &C,
+	port_id: &PortId,
+	channel_id: &ChannelId,
+) -> u64 {
+	let (latest_height, ..) = chain.latest_height_and_timestamp().await.unwrap();
+	chain
+		.query_next_sequence_recv(latest_height, port_id, channel_id)
+		.await
+		.unwrap()
+		.next_sequence_receive
+}
+
+/// Updates the client of `chain_a` on `chain_b` to a finalized height of chain A at or above
+/// `min_height`, and returns that height.
+async fn update_client_to<A, B>(chain_a: &mut A, chain_b: &B, min_height: u64) -> Height
+where
+	A: TestProvider,
+	B: TestProvider,
+{
+	let mut finality = chain_a.finality_notifications().await.unwrap();
+	loop {
+		let finality_event = finality.next().await.expect("The finality stream ended");
+		let updates = chain_a.query_latest_ibc_events(finality_event, chain_b).await.unwrap();
+		let Some(height) = updates.iter().map(|(_, height, ..)| *height).max() else { continue };
+		if height.revision_height < min_height {
+			continue
+		}
+		let msgs = updates
+			.into_iter()
+			.filter(|(_, update_height, _, update_type)| {
+				*update_height == height || !update_type.is_optional()
+			})
+			.map(|(msg, ..)| msg)
+			.collect();
+		chain_b.submit(msgs).await.unwrap();
+		return height
+	}
+}
+
+/// Opens a fresh ordered channel on the `ping` port and sends four pings. The relayer is stopped
+/// once the first two are received, and the fourth ping is refused by chain B while the third
+/// isn't received. Asserts that chain B receives the pings in the order they were sent once the
+/// relayer restarts, then that a timed out ping closes the ordered channel on chain A.
+pub async fn ordered_ping_messaging<A, B>(chain_a: &mut A, chain_b: &mut B)
+where
+	A: TestProvider,
+	A::FinalityEvent: Send + Sync,
+	A::Error: From<B::Error>,
+	B: TestProvider,
+	B::FinalityEvent: Send + Sync,
+	B::Error: From<A::Error>,
+{
+	let port_id = PortId::from_str("ping").unwrap();
+	let (handle, channel_a, channel_b, connection_a, ..) = setup_path(
+		chain_a,
+		chain_b,
+		PathParams {
+			connection_delay: Duration::ZERO,
+			port_id: port_id.clone(),
+			version: "ping-1".to_string(),
+			order: Order::Ordered,
+			reuse_existing: false,
+		},
+	)
+	.await;
+	handle.abort();
+	chain_a.set_channel_whitelist(vec![(channel_a, port_id.clone())].into_iter().collect());
+	chain_b.set_channel_whitelist(vec![(channel_b, port_id.clone())].into_iter().collect());
+	assert_eq!(next_sequence_recv(chain_b, &port_id, &channel_b).await, 1);
+
+	let mut received = Box::pin(chain_b.ibc_events().await.filter_map(move |ev| {
+		future::ready(match ev {
+			IbcEvent::ReceivePacket(recv) if recv.packet.destination_channel == channel_b =>
+				Some(u64::from(recv.packet.sequence)),
+			_ => None,
+		})
+	}));
+	let timeout = Timeout::Offset { height: Some(100), timestamp: Some(60 * 60) };
+
+	let handle = spawn_relayer(chain_a, chain_b);
+	chain_a.send_ordered_packet(channel_a, timeout.clone()).await.unwrap();
+	chain_a.send_ordered_packet(channel_a, timeout.clone()).await.unwrap();
+	let first_pings = timeout_future(
+		received.by_ref().take(2).collect::<Vec<_>>(),
+		20 * 60,
+		format!("Didn't see the first pings received on {}", chain_b.name()),
+	)
+	.await;
+	assert_eq!(first_pings, vec![1, 2], "the pings were received out of order");
+	handle.abort();
+	log::info!(target: "hyperspace", "Relayer stopped, sending the third and fourth pings");
+	chain_a.send_ordered_packet(channel_a, timeout.clone()).await.unwrap();
+	chain_a.send_ordered_packet(channel_a, timeout).await.unwrap();
+
+	// the fourth ping can't be received before the third
+	let packet_info = chain_a
+		.query_send_packets(channel_a, port_id.clone(), vec![4])
+		.await
+		.unwrap()
+		.pop()
+		.expect("The fourth ping wasn't found");
+	let send_height = packet_info.height.expect("The fourth ping has no height");
+	let proof_height = update_client_to(chain_a, chain_b, send_height).await;
+	let msg = construct_recv_message(
+		chain_a,
+		chain_b,
+		&chain_a.connection_prefix_of(&connection_a),
+		packet_info_to_packet(&packet_info),
+		proof_height,
+	)
+	.await
+	.unwrap();
+	assert!(chain_b.submit(vec![msg]).await.is_err(), "the fourth ping was received out of order");
+	assert_eq!(next_sequence_recv(chain_b, &port_id, &channel_b).await, 3);
+
+	let handle = spawn_relayer(chain_a, chain_b);
+	let last_pings = timeout_future(
+		received.take(2).collect::<Vec<_>>(),
+		20 * 60,
+		format!("Didn't see the last pings received on {}", chain_b.name()),
+	)
+	.await;
+	assert_eq!(last_pings, vec![3, 4], "the pings were received out of order");
+	assert_eq!(next_sequence_recv(chain_b, &port_id, &channel_b).await, 5);
+	log::info!(target: "hyperspace", "🚀🚀 Pings received in order on {}", chain_b.name());
+
+	send_ordered_packet_and_assert_timeout(chain_a, chain_b, channel_a).await;
+	handle.abort();
+
+	let (latest_height, ..) = chain_a.latest_height_and_timestamp().await.unwrap();
+	let channel_end = chain_a
+		.query_channel_end(latest_height, channel_a, port_id.clone())
+		.await
+		.unwrap()
+		.channel
+		.unwrap();
+	let channel_end = ChannelEnd::try_from(channel_end).unwrap();
+	assert_eq!(channel_end.state, State::Closed, "the timeout didn't close the ordered channel");
+	// the timed out ping was never received
+	assert_eq!(next_sequence_recv(chain_b, &port_id, &channel_b).await, 5);
+	log::info!(target: "hyperspace", "🚀🚀 Ordered channel closed by the timed out ping");
+}
@@ -0,0 +1,106 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Relay of the packets of applications other than ICS-20 transfers, whose data the relayer
+//! can't decode.
+
+use crate::{setup_path, timeout_future, PathParams, StreamExt};
+use futures::future;
+use hyperspace_primitives::TestProvider;
+use ibc::{
+	core::{ics04_channel::channel::Order, ics24_host::identifier::PortId},
+	events::IbcEvent,
+};
+use pallet_ibc::Timeout;
+use std::time::Duration;
+
+/// Opens a channel with the given `order` on `port_id`, sends a packet with `data` from the
+/// application bound to it on chain A and asserts that it's received on chain B and acknowledged
+/// on chain A.
+pub async fn ibc_messaging_raw_packet<A, B>(
+	chain_a: &mut A,
+	chain_b: &mut B,
+	port_id: PortId,
+	version: String,
+	order: Order,
+	data: Vec<u8>,
+) where
+	A: TestProvider,
+	A::FinalityEvent: Send + Sync,
+	A::Error: From<B::Error>,
+	B: TestProvider,
+	B::FinalityEvent: Send + Sync,
+	B::Error: From<A::Error>,
+{
+	let (handle, channel_a, channel_b, ..) = setup_path(
+		chain_a,
+		chain_b,
+		PathParams {
+			connection_delay: Duration::ZERO,
+			port_id: port_id.clone(),
+			version,
+			order,
+			reuse_existing: true,
+		},
+	)
+	.await;
+	handle.abort();
+	chain_a.set_channel_whitelist(vec![(channel_a, port_id.clone())].into_iter().collect());
+	chain_b.set_channel_whitelist(vec![(channel_b, port_id.clone())].into_iter().collect());
+	let client_a_clone = chain_a.clone();
+	let client_b_clone = chain_b.clone();
+	let handle = tokio::task::spawn(async move {
+		hyperspace_core::relay(client_a_clone, client_b_clone, None, None, None)
+			.await
+			.unwrap()
+	});
+
+	let received = chain_b.ibc_events().await.skip_while(|ev| {
+		future::ready(
+			!matches!(ev, IbcEvent::ReceivePacket(recv) if recv.packet.destination_channel == channel_b),
+		)
+	});
+	let acknowledged = chain_a.ibc_events().await.skip_while(|ev| {
+		future::ready(
+			!matches!(ev, IbcEvent::AcknowledgePacket(ack) if ack.packet.source_channel == channel_a),
+		)
+	});
+	chain_a
+		.send_raw_packet(
+			port_id,
+			channel_a,
+			data.clone(),
+			Timeout::Offset { height: Some(100), timestamp: Some(60 * 60) },
+		)
+		.await
+		.unwrap();
+
+	let received = timeout_future(
+		received.take(1).collect::<Vec<_>>(),
+		20 * 60,
+		format!("Didn't see the raw packet received on {}", chain_b.name()),
+	)
+	.await;
+	let Some(IbcEvent::ReceivePacket(recv)) = received.into_iter().next() else { unreachable!() };
+	assert_eq!(recv.packet.data, data, "the relayer altered the data of the raw packet");
+
+	timeout_future(
+		acknowledged.take(1).collect::<Vec<_>>(),
+		20 * 60,
+		format!("Didn't see the raw packet acknowledged on {}", chain_a.name()),
+	)
+	.await;
+	log::info!(target: "hyperspace", "🚀🚀 Raw packet successfully relayed and acknowledged");
+	handle.abort()
+}
@@ -0,0 +1,152 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The packets sent while no relayer was running are relayed by the backfill, from its starting
+//! height.
+
+use core::time::Duration;
+use hyperspace_core::{backfill::backfill, logging};
+use hyperspace_primitives::{
+	query_undelivered_sequences, utils::create_clients, IbcProvider, TestProvider,
+};
+use hyperspace_testsuite::{
+	ensure_transfer_asset,
+	mock::{MockChain, MockChainConfig},
+	setup_connection_and_channel,
+};
+use ibc::{
+	applications::transfer::{msgs::transfer::MsgTransfer, Amount, PrefixedCoin, PrefixedDenom},
+	core::ics24_host::identifier::{ChannelId, PortId},
+};
+use std::str::FromStr;
+use tokio::task::JoinHandle;
+
+const ASSET: &str = "UNIT";
+
+async fn send_transfer(chain_a: &MockChain, chain_b: &MockChain, channel_a: ChannelId) {
+	let (latest_height, timestamp) = chain_b.latest_height_and_timestamp().await.unwrap();
+	let msg = MsgTransfer {
+		source_port: PortId::transfer(),
+		source_channel: channel_a,
+		token: PrefixedCoin {
+			denom: PrefixedDenom::from_str(ASSET).unwrap(),
+			amount: Amount::from_str("1000").unwrap(),
+		},
+		sender: chain_a.account_id(),
+		receiver: chain_b.account_id(),
+		timeout_height: latest_height.add(1000),
+		timeout_timestamp: (timestamp + Duration::from_secs(60 * 60)).unwrap(),
+		memo: String::new(),
+	};
+	chain_a.send_transfer(msg).await.unwrap();
+}
+
+/// Opens a transfer channel between two mock chains producing blocks, without a relayer
+/// running.
+async fn setup() -> (MockChain, MockChain, ChannelId, [JoinHandle<()>; 2]) {
+	let (mut chain_a, mut chain_b) =
+		MockChain::pair(MockChainConfig::new("mock-a"), MockChainConfig::new("mock-b"));
+	let block_production = [
+		chain_a.start_block_production(Duration::from_millis(50)),
+		chain_b.start_block_production(Duration::from_millis(50)),
+	];
+	let (client_a, client_b) = create_clients(&mut chain_a, &mut chain_b).await.unwrap();
+	chain_a.set_client_id(client_a);
+	chain_b.set_client_id(client_b);
+	ensure_transfer_asset(&chain_a, ASSET.to_string()).await;
+	ensure_transfer_asset(&chain_b, ASSET.to_string()).await;
+
+	let (handle, channel_a, channel_b, connection_id_a, connection_id_b, _) =
+		setup_connection_and_channel(&mut chain_a, &mut chain_b, Duration::from_secs(0)).await;
+	handle.abort();
+	chain_a.set_connection_id(connection_id_a);
+	chain_b.set_connection_id(connection_id_b);
+	chain_a.set_channel_whitelist(vec![(channel_a, PortId::transfer())].into_iter().collect());
+	chain_b.set_channel_whitelist(vec![(channel_b, PortId::transfer())].into_iter().collect());
+	(chain_a, chain_b, channel_a, block_production)
+}
+
+/// Sequences of the packets sent by `chain_a` on `channel_a` that `chain_b` didn't receive.
+async fn undelivered(chain_a: &MockChain, chain_b: &MockChain, channel_a: ChannelId) -> Vec<u64> {
+	let (height_a, _) = chain_a.latest_height_and_timestamp().await.unwrap();
+	let (height_b, _) = chain_b.latest_height_and_timestamp().await.unwrap();
+	query_undelivered_sequences(height_a, height_b, channel_a, PortId::transfer(), chain_a, chain_b)
+		.await
+		.unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn backfill_relays_the_packets_sent_from_its_height() {
+	logging::setup_logging();
+	let (mut chain_a, chain_b, channel_a, block_production) = setup().await;
+
+	// the first packet is sent before the height the backfill starts from
+	send_transfer(&chain_a, &chain_b, channel_a).await;
+	let from_height = chain_a.produce_block() + 1;
+	for _ in 0..3 {
+		send_transfer(&chain_a, &chain_b, channel_a).await;
+	}
+	chain_a.produce_block();
+
+	tokio::time::timeout(
+		Duration::from_secs(60),
+		backfill(&mut chain_a, &chain_b, from_height, None),
+	)
+	.await
+	.expect("The backfill didn't complete")
+	.unwrap();
+
+	chain_b.produce_block();
+	assert_eq!(undelivered(&chain_a, &chain_b, channel_a).await, vec![1]);
+
+	block_production.iter().for_each(|task| task.abort());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn failed_backfill_batches_are_left_to_the_relay_loop() {
+	logging::setup_logging();
+	let (mut chain_a, chain_b, channel_a, block_production) = setup().await;
+
+	let from_height = chain_a.produce_block() + 1;
+	for _ in 0..3 {
+		send_transfer(&chain_a, &chain_b, channel_a).await;
+	}
+	chain_a.produce_block();
+
+	// the packets can't be received with the proofs of chain A
+	chain_a.set_corrupt_proofs(true);
+	tokio::time::timeout(
+		Duration::from_secs(60),
+		backfill(&mut chain_a, &chain_b, from_height, None),
+	)
+	.await
+	.expect("The backfill didn't complete")
+	.expect("The backfill failed on a batch it should have skipped");
+	chain_b.produce_block();
+	assert_eq!(undelivered(&chain_a, &chain_b, channel_a).await, vec![1, 2, 3]);
+
+	chain_a.set_corrupt_proofs(false);
+	let relayer =
+		tokio::spawn(hyperspace_core::relay(chain_a.clone(), chain_b.clone(), None, None, None));
+	tokio::time::timeout(Duration::from_secs(60), async {
+		while !undelivered(&chain_a, &chain_b, channel_a).await.is_empty() {
+			tokio::time::sleep(Duration::from_millis(100)).await;
+		}
+	})
+	.await
+	.expect("The relay loop didn't relay the packets the backfill skipped");
+
+	relayer.abort();
+	block_production.iter().for_each(|task| task.abort());
+}
@@ -0,0 +1,129 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use clap::Parser;
+use hyperspace_core::{chain::AnyConfig, command::Cli, logging, substrate::DefaultConfig};
+use hyperspace_parachain::ParachainClient;
+use hyperspace_primitives::{Chain, IbcProvider};
+use hyperspace_testsuite::{await_parachain_onboarding, dev_parachains::DevParachains};
+use ibc::core::ics24_host::identifier::PortId;
+use std::{
+	path::{Path, PathBuf},
+	time::Duration,
+};
+
+/// How long the relay chain may take to onboard the parachains.
+const READY_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Writes the configs of both chains and of the relayer core into `dir`.
+fn write_configs(dir: &Path, chains: &DevParachains) -> [PathBuf; 3] {
+	let config_a = AnyConfig::Parachain(chains.config_a());
+	let config_b = AnyConfig::Parachain(chains.config_b());
+	let paths = [dir.join("chain_a.toml"), dir.join("chain_b.toml"), dir.join("core.toml")];
+	std::fs::write(&paths[0], toml::to_string(&config_a).unwrap()).unwrap();
+	std::fs::write(&paths[1], toml::to_string(&config_b).unwrap()).unwrap();
+	std::fs::write(&paths[2], "").unwrap();
+	paths
+}
+
+async fn run_cli(args: &[&str]) {
+	let cli = Cli::parse_from(std::iter::once("hyperspace").chain(args.iter().copied()));
+	cli.run()
+		.await
+		.unwrap_or_else(|e| panic!("`hyperspace {}` failed: {e:?}", args.join(" ")));
+}
+
+async fn read_config(path: &Path) -> AnyConfig {
+	toml::from_str(&tokio::fs::read_to_string(path).await.unwrap()).unwrap()
+}
+
+/// Bootstraps a path between the dev parachains with the CLI alone.
+#[tokio::test]
+async fn cli_bootstraps_a_path_between_parachains() {
+	logging::setup_logging();
+	let chains = DevParachains::default();
+	let dir = std::env::temp_dir().join(format!("hyperspace-cli-{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	let [path_a, path_b, path_core] = write_configs(&dir, &chains);
+
+	// Wait until the relay chain finalized enough blocks for the grandpa clients
+	let chain_a = ParachainClient::<DefaultConfig>::new(chains.config_a()).await.unwrap();
+	await_parachain_onboarding(&chain_a, READY_TIMEOUT).await;
+
+	let configs = [
+		"--config-a",
+		path_a.to_str().unwrap(),
+		"--config-b",
+		path_b.to_str().unwrap(),
+		"--config-core",
+		path_core.to_str().unwrap(),
+	];
+	run_cli(&[&["create-clients"], &configs[..]].concat()).await;
+	run_cli(&[&["create-connection", "--delay-period", "1"], &configs[..]].concat()).await;
+	run_cli(
+		&[
+			&[
+				"create-channel",
+				"--port",
+				"transfer",
+				"--version",
+				"ics20-1",
+				"--order",
+				"unordered",
+			],
+			&configs[..],
+		]
+		.concat(),
+	)
+	.await;
+
+	let chain_a = read_config(&path_a).await.into_client().await.unwrap();
+	let chain_b = read_config(&path_b).await.into_client().await.unwrap();
+	let connection_id_a = chain_a.connection_id().expect("connection id was written to config A");
+	let connection_id_b = chain_b.connection_id().expect("connection id was written to config B");
+	let (channel_id_a, port_id) = chain_a
+		.channel_whitelist()
+		.into_iter()
+		.next()
+		.expect("channel was written to config A");
+	let (channel_id_b, _) = chain_b
+		.channel_whitelist()
+		.into_iter()
+		.next()
+		.expect("channel was written to config B");
+	assert_eq!(port_id, PortId::transfer());
+
+	// the written ids identify an open path
+	let (height, _) = chain_a.latest_height_and_timestamp().await.unwrap();
+	let channel = chain_a
+		.query_channel_end(height, channel_id_a, port_id.clone())
+		.await
+		.unwrap()
+		.channel
+		.expect("channel exists on chain A");
+	assert_eq!(channel.connection_hops, vec![connection_id_a.to_string()]);
+	assert_eq!(channel.counterparty.map(|c| c.channel_id), Some(channel_id_b.to_string()));
+	let connection = chain_b
+		.query_connection_end(
+			chain_b.latest_height_and_timestamp().await.unwrap().0,
+			connection_id_b,
+		)
+		.await
+		.unwrap()
+		.connection
+		.expect("connection exists on chain B");
+	assert_eq!(connection.client_id, chain_a.client_id().to_string());
+
+	std::fs::remove_dir_all(dir).unwrap();
+}
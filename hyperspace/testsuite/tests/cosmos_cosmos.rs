@@ -0,0 +1,221 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::time::Duration;
+use hyperspace_core::{
+	chain::{AnyAssetId, AnyChain, AnyConfig},
+	logging,
+};
+use hyperspace_cosmos::client::CosmosClientConfig;
+use hyperspace_primitives::{
+	utils::create_clients, Chain, CommonClientConfig, IbcProvider, TestProvider,
+};
+use hyperspace_testsuite::{ibc_messaging_with_relayer_fees, setup_path, PathParams};
+use ibc::{applications::transfer::VERSION, core::ics24_host::identifier::PortId};
+use ibc_proto::{
+	cosmos::{bank::v1beta1::MsgSend, base::v1beta1::Coin},
+	google::protobuf::Any,
+};
+use prost::Message;
+
+/// Endpoints of a cosmos node.
+#[derive(Debug, Clone)]
+pub struct Endpoints {
+	pub host: String,
+	pub rpc_port: u16,
+	pub grpc_port: u16,
+}
+
+impl Endpoints {
+	/// Reads the endpoints of chain `name` from `COSMOS_<NAME>_HOST`, `COSMOS_<NAME>_RPC_PORT`
+	/// and `COSMOS_<NAME>_GRPC_PORT`, defaulting to a node on localhost at `rpc_port` and
+	/// `grpc_port`.
+	fn from_env(name: &str, rpc_port: u16, grpc_port: u16) -> Self {
+		let var = |suffix: &str| std::env::var(format!("COSMOS_{name}_{suffix}")).ok();
+		let port = |suffix: &str, default: u16| {
+			var(suffix).map(|port| port.parse().expect("Invalid port")).unwrap_or(default)
+		};
+		Endpoints {
+			host: var("HOST").unwrap_or_else(|| "127.0.0.1".to_string()),
+			rpc_port: port("RPC_PORT", rpc_port),
+			grpc_port: port("GRPC_PORT", grpc_port),
+		}
+	}
+}
+
+/// Both chains run the ICS-29 fee middleware on their transfer stack. They run on distinct nodes,
+/// by default both on localhost with the ports of chain B offset by 100.
+#[derive(Debug, Clone)]
+pub struct Args {
+	pub chain_a: Endpoints,
+	pub chain_b: Endpoints,
+}
+
+impl Default for Args {
+	fn default() -> Self {
+		Args {
+			chain_a: Endpoints::from_env("A", 26657, 9090),
+			chain_b: Endpoints::from_env("B", 26757, 9190),
+		}
+	}
+}
+
+/// Key of the relayer, funded at genesis.
+const RELAYER_MNEMONIC: &str =
+	"oxygen fall sure lava energy veteran enroll frown question detail include maximum";
+
+/// Key of the account sending the incentivized transfers, funded by the relayer.
+const SENDER_MNEMONIC: &str =
+	"legal winner thank year wave sausage worth useful legal winner thank yellow";
+
+/// Key of the counterparty payee of the relayer.
+const PAYEE_MNEMONIC: &str =
+	"abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+fn config(name: &str, chain_id: &str, endpoints: &Endpoints, mnemonic: &str) -> CosmosClientConfig {
+	let Endpoints { host, rpc_port, grpc_port } = endpoints;
+	CosmosClientConfig {
+		name: name.to_string(),
+		rpc_url: format!("http://{host}:{rpc_port}").parse().unwrap(),
+		grpc_url: format!("http://{host}:{grpc_port}").parse().unwrap(),
+		rest_url: None,
+		websocket_url: format!("ws://{host}:{rpc_port}/websocket").parse().unwrap(),
+		chain_id: chain_id.to_string(),
+		client_id: None,
+		connection_id: None,
+		connections: vec![],
+		account_prefix: "cosmos".to_string(),
+		fee_denom: "stake".to_string(),
+		fee_amount: "4000".to_string(),
+		gas_limit: (i64::MAX - 1) as u64,
+		gas_price: None,
+		fallback_fees: vec![],
+		store_prefix: "ibc".to_string(),
+		max_tx_size: 200000,
+		mnemonic: mnemonic.to_string(),
+		signer: Default::default(),
+		wasm_code_id: None,
+		latest_height_max_staleness_ms: 10_000,
+		channel_whitelist: vec![],
+		common: CommonClientConfig {
+			skip_optional_client_updates: true,
+			max_packets_to_process: 200,
+			max_submit_retries: 5,
+			strict_middleware_check: false,
+			simulate_messages: false,
+			skip_invalid_wasm_messages: false,
+			stop_packets_on_inactive_client: false,
+			packet_filters: vec![],
+			query_cache_capacity: 1024,
+			timeout_safety_margin_blocks: 2,
+			auto_whitelist_channels: false,
+			fee_payee_address: None,
+			min_balance: None,
+			stall_factor: 20,
+			stall_alert_command: None,
+			proof_query_concurrency: None,
+			max_pending_messages: 1000,
+			pre_validate_updates: false,
+		},
+		skip_tokens_list: None,
+		packet_sender_contract: None,
+	}
+}
+
+async fn setup_clients() -> (AnyChain, AnyChain) {
+	log::info!(target: "hyperspace", "=========================== Starting Test ===========================");
+	let args = Args::default();
+
+	let mut chain_a =
+		AnyConfig::Cosmos(config("cosmos-a", "ibcgo-1", &args.chain_a, RELAYER_MNEMONIC))
+			.into_live_client()
+			.await
+			.unwrap();
+	let mut chain_b =
+		AnyConfig::Cosmos(config("cosmos-b", "ibcgo-2", &args.chain_b, RELAYER_MNEMONIC))
+			.into_live_client()
+			.await
+			.unwrap();
+
+	let client_a_on_b = chain_b.find_suitable_client(&chain_a).await.unwrap();
+	let client_b_on_a = chain_a.find_suitable_client(&chain_b).await.unwrap();
+
+	if let (Some(client_a), Some(client_b)) = (client_a_on_b, client_b_on_a) {
+		chain_a.set_client_id(client_a);
+		chain_b.set_client_id(client_b);
+		return (chain_a, chain_b)
+	}
+
+	let (client_b, client_a) = create_clients(&mut chain_b, &mut chain_a).await.unwrap();
+	chain_a.set_client_id(client_a);
+	chain_b.set_client_id(client_b);
+	(chain_a, chain_b)
+}
+
+/// Client of chain A signing with `mnemonic`.
+async fn account_of_chain_a(name: &str, mnemonic: &str) -> AnyChain {
+	let args = Args::default();
+	AnyConfig::Cosmos(config(name, "ibcgo-1", &args.chain_a, mnemonic))
+		.into_live_client()
+		.await
+		.unwrap()
+}
+
+/// Sends `amount` of `denom` from the relayer's account of `chain` to `to`.
+async fn fund(chain: &AnyChain, to: &AnyChain, denom: &str, amount: u128) {
+	let msg = MsgSend {
+		from_address: chain.account_id().to_string(),
+		to_address: to.account_id().to_string(),
+		amount: vec![Coin { denom: denom.to_string(), amount: amount.to_string() }],
+	};
+	let msg =
+		Any { type_url: "/cosmos.bank.v1beta1.MsgSend".to_string(), value: msg.encode_to_vec() };
+	chain.submit(vec![msg]).await.unwrap();
+}
+
+#[tokio::test]
+#[ignore]
+async fn cosmos_to_cosmos_relayer_fees_integration_test() {
+	logging::setup_logging();
+
+	let (mut chain_a, mut chain_b) = setup_clients().await;
+	let params = PathParams {
+		version: format!(r#"{{"fee_version":"ics29-1","app_version":"{VERSION}"}}"#),
+		..PathParams::transfer(Duration::from_secs(0))
+	};
+	let (handle, channel_a, channel_b, connection_id_a, connection_id_b, _) =
+		setup_path(&mut chain_a, &mut chain_b, params).await;
+	handle.abort();
+
+	chain_a.set_connection_id(connection_id_a);
+	chain_b.set_connection_id(connection_id_b);
+
+	chain_a.set_channel_whitelist(vec![(channel_a, PortId::transfer())].into_iter().collect());
+	chain_b.set_channel_whitelist(vec![(channel_b, PortId::transfer())].into_iter().collect());
+
+	let sender_a = account_of_chain_a("cosmos-a-sender", SENDER_MNEMONIC).await;
+	let payee_a = account_of_chain_a("cosmos-a-payee", PAYEE_MNEMONIC).await;
+	fund(&chain_a, &sender_a, "stake", 100_000_000).await;
+
+	let asset_id_a = AnyAssetId::Cosmos("stake".to_string());
+	ibc_messaging_with_relayer_fees(
+		&mut chain_a,
+		&mut chain_b,
+		&sender_a,
+		&payee_a,
+		asset_id_a,
+		channel_a,
+	)
+	.await;
+}
@@ -0,0 +1,227 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::time::Duration;
+use hyperspace_core::{
+	chain::{AnyAssetId, AnyChain, AnyConfig},
+	forwarding::{relay_paths, ForwardingConfig, Hop, RelayPath},
+	logging,
+};
+use hyperspace_cosmos::client::CosmosClientConfig;
+use hyperspace_primitives::{
+	utils::{create_clients, ibc_denom_for},
+	Chain, CommonClientConfig, IbcProvider, KeyProvider, TestProvider,
+};
+use hyperspace_testsuite::{assert_denom_trace, setup_path, PathParams};
+use ibc::{
+	applications::transfer::{msgs::transfer::MsgTransfer, Amount, PrefixedCoin},
+	core::ics24_host::identifier::{ChannelId, PortId},
+};
+use std::str::FromStr;
+
+/// Chain B runs the packet forward middleware, forwarding the transfers of chain A to chain C.
+#[derive(Debug, Clone)]
+pub struct Args {
+	pub chain_a: String,
+	pub chain_b: String,
+	pub chain_c: String,
+}
+
+impl Default for Args {
+	fn default() -> Self {
+		let host = |var: &str| std::env::var(var).unwrap_or_else(|_| "127.0.0.1".to_string());
+		Args {
+			chain_a: host("COSMOS_A_HOST"),
+			chain_b: host("COSMOS_B_HOST"),
+			chain_c: host("COSMOS_C_HOST"),
+		}
+	}
+}
+
+fn config(name: &str, chain_id: &str, host: &str) -> CosmosClientConfig {
+	CosmosClientConfig {
+		name: name.to_string(),
+		rpc_url: format!("http://{host}:26657").parse().unwrap(),
+		grpc_url: format!("http://{host}:9090").parse().unwrap(),
+		rest_url: None,
+		websocket_url: format!("ws://{host}:26657/websocket").parse().unwrap(),
+		chain_id: chain_id.to_string(),
+		client_id: None,
+		connection_id: None,
+		connections: vec![],
+		account_prefix: "cosmos".to_string(),
+		fee_denom: "stake".to_string(),
+		fee_amount: "4000".to_string(),
+		gas_limit: (i64::MAX - 1) as u64,
+		gas_price: None,
+		fallback_fees: vec![],
+		store_prefix: "ibc".to_string(),
+		max_tx_size: 200000,
+		mnemonic:
+			"oxygen fall sure lava energy veteran enroll frown question detail include maximum"
+				.to_string(),
+		signer: Default::default(),
+		wasm_code_id: None,
+		latest_height_max_staleness_ms: 10_000,
+		channel_whitelist: vec![],
+		common: CommonClientConfig {
+			skip_optional_client_updates: true,
+			max_packets_to_process: 200,
+			max_submit_retries: 5,
+			strict_middleware_check: false,
+			simulate_messages: false,
+			skip_invalid_wasm_messages: false,
+			stop_packets_on_inactive_client: false,
+			packet_filters: vec![],
+			query_cache_capacity: 1024,
+			timeout_safety_margin_blocks: 2,
+			auto_whitelist_channels: false,
+			fee_payee_address: None,
+			min_balance: None,
+			stall_factor: 20,
+			stall_alert_command: None,
+			proof_query_concurrency: None,
+			max_pending_messages: 1000,
+			pre_validate_updates: false,
+		},
+		skip_tokens_list: None,
+		packet_sender_contract: None,
+	}
+}
+
+async fn setup_clients(
+	config_a: CosmosClientConfig,
+	config_b: CosmosClientConfig,
+) -> (AnyChain, AnyChain) {
+	let mut chain_a = AnyConfig::Cosmos(config_a).into_live_client().await.unwrap();
+	let mut chain_b = AnyConfig::Cosmos(config_b).into_live_client().await.unwrap();
+
+	let client_a_on_b = chain_b.find_suitable_client(&chain_a).await.unwrap();
+	let client_b_on_a = chain_a.find_suitable_client(&chain_b).await.unwrap();
+
+	if let (Some(client_a), Some(client_b)) = (client_a_on_b, client_b_on_a) {
+		chain_a.set_client_id(client_a);
+		chain_b.set_client_id(client_b);
+		return (chain_a, chain_b)
+	}
+
+	let (client_b, client_a) = create_clients(&mut chain_b, &mut chain_a).await.unwrap();
+	chain_a.set_client_id(client_a);
+	chain_b.set_client_id(client_b);
+	(chain_a, chain_b)
+}
+
+/// Opens a transfer channel between both chains and whitelists it on both sides.
+async fn setup_transfer_path(
+	chain_a: &mut AnyChain,
+	chain_b: &mut AnyChain,
+) -> (ChannelId, ChannelId) {
+	let (handle, channel_a, channel_b, connection_id_a, connection_id_b, _) =
+		setup_path(chain_a, chain_b, PathParams::transfer(Duration::from_secs(0))).await;
+	handle.abort();
+
+	chain_a.set_connection_id(connection_id_a);
+	chain_b.set_connection_id(connection_id_b);
+	chain_a.set_channel_whitelist(vec![(channel_a, PortId::transfer())].into_iter().collect());
+	chain_b.set_channel_whitelist(vec![(channel_b, PortId::transfer())].into_iter().collect());
+	(channel_a, channel_b)
+}
+
+#[tokio::test]
+#[ignore]
+async fn cosmos_to_cosmos_forwarded_transfer_integration_test() {
+	logging::setup_logging();
+	log::info!(target: "hyperspace", "=========================== Starting Test ===========================");
+	let args = Args::default();
+
+	// chain b is relayed on both paths, with a client of each counterparty
+	let (mut chain_a, mut chain_b_of_ab) = setup_clients(
+		config("cosmos-a", "ibcgo-1", &args.chain_a),
+		config("cosmos-b", "ibcgo-2", &args.chain_b),
+	)
+	.await;
+	let (mut chain_b_of_bc, mut chain_c) = setup_clients(
+		config("cosmos-b", "ibcgo-2", &args.chain_b),
+		config("cosmos-c", "ibcgo-3", &args.chain_c),
+	)
+	.await;
+	let (channel_a, channel_b_of_ab) = setup_transfer_path(&mut chain_a, &mut chain_b_of_ab).await;
+	let (channel_b_of_bc, channel_c) = setup_transfer_path(&mut chain_b_of_bc, &mut chain_c).await;
+
+	let forwarding = ForwardingConfig {
+		hops: vec![
+			Hop {
+				chain: chain_a.name().to_string(),
+				channel_id: channel_a,
+				port_id: PortId::transfer(),
+			},
+			Hop {
+				chain: chain_b_of_bc.name().to_string(),
+				channel_id: channel_b_of_bc,
+				port_id: PortId::transfer(),
+			},
+		],
+		paths: vec![],
+	};
+	let receiver = chain_c.account_id();
+	let (height, timestamp) = chain_b_of_ab.latest_height_and_timestamp().await.unwrap();
+	let transfer = MsgTransfer {
+		source_port: PortId::transfer(),
+		source_channel: channel_a,
+		token: PrefixedCoin {
+			denom: "stake".parse().unwrap(),
+			amount: Amount::from_str("1000").unwrap(),
+		},
+		sender: chain_a.account_id(),
+		receiver: receiver.clone(),
+		timeout_height: height.add(200),
+		timeout_timestamp: (timestamp + Duration::from_secs(60 * 60)).unwrap(),
+		memo: String::new(),
+	};
+	let transfer = forwarding.forward_transfer(transfer, &receiver);
+
+	let paths = vec![
+		RelayPath {
+			chain_a: chain_a.clone(),
+			chain_b: chain_b_of_ab,
+			metrics_a: None,
+			metrics_b: None,
+		},
+		RelayPath {
+			chain_a: chain_b_of_bc,
+			chain_b: chain_c.clone(),
+			metrics_a: None,
+			metrics_b: None,
+		},
+	];
+	let handle = tokio::task::spawn(async move { relay_paths(paths, None).await.unwrap() });
+
+	chain_a.send_transfer(transfer).await.unwrap();
+
+	// the denom of the tokens of chain a received on chain c through chain b
+	let denom_on_b = format!("{}/{channel_b_of_ab}/stake", PortId::transfer());
+	let denom = ibc_denom_for(&PortId::transfer(), &channel_c, &denom_on_b);
+	let mut received = false;
+	for _ in 0..60 {
+		let balances = chain_c.query_ibc_balance(AnyAssetId::Cosmos(denom.clone())).await.unwrap();
+		if balances.iter().any(|coin| coin.amount > Amount::from(0u64)) {
+			received = true;
+			break
+		}
+		tokio::time::sleep(Duration::from_secs(10)).await;
+	}
+	handle.abort();
+	assert!(received, "{denom} wasn't received on {}", chain_c.name());
+	assert_denom_trace(&chain_c, channel_c, &denom_on_b).await;
+}
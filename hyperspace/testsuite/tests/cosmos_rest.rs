@@ -0,0 +1,276 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::time::Duration;
+use hyperspace_core::{
+	chain::{AnyAssetId, AnyChain, AnyConfig},
+	logging,
+};
+use hyperspace_cosmos::client::CosmosClientConfig;
+use hyperspace_primitives::{
+	utils::{create_clients, ibc_denom_for},
+	Chain, CommonClientConfig, IbcProvider,
+};
+use hyperspace_testsuite::{ibc_messaging_with_connection_delay, setup_connection_and_channel};
+use ibc::core::{
+	ics02_client::client_state::ClientState as _,
+	ics24_host::identifier::{ChannelId, ClientId, ConnectionId, PortId},
+};
+use pallet_ibc::light_clients::AnyClientState;
+
+/// Chain B is also served by a node with gRPC disabled, whose REST gateway is at `rest_b`.
+#[derive(Debug, Clone)]
+pub struct Args {
+	pub chain_a: String,
+	pub chain_b: String,
+	pub rest_b: String,
+}
+
+impl Default for Args {
+	fn default() -> Self {
+		let cosmos_a = std::env::var("COSMOS_A_HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
+		let cosmos_b = std::env::var("COSMOS_B_HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
+		let rest_b = std::env::var("COSMOS_B_REST_URL")
+			.unwrap_or_else(|_| format!("http://{cosmos_b}:1317"));
+		Args { chain_a: cosmos_a, chain_b: cosmos_b, rest_b }
+	}
+}
+
+fn config(name: &str, chain_id: &str, host: &str) -> CosmosClientConfig {
+	CosmosClientConfig {
+		name: name.to_string(),
+		rpc_url: format!("http://{host}:26657").parse().unwrap(),
+		grpc_url: format!("http://{host}:9090").parse().unwrap(),
+		rest_url: None,
+		websocket_url: format!("ws://{host}:26657/websocket").parse().unwrap(),
+		chain_id: chain_id.to_string(),
+		client_id: None,
+		connection_id: None,
+		connections: vec![],
+		account_prefix: "cosmos".to_string(),
+		fee_denom: "stake".to_string(),
+		fee_amount: "4000".to_string(),
+		gas_limit: (i64::MAX - 1) as u64,
+		gas_price: None,
+		fallback_fees: vec![],
+		store_prefix: "ibc".to_string(),
+		max_tx_size: 200000,
+		mnemonic:
+			"oxygen fall sure lava energy veteran enroll frown question detail include maximum"
+				.to_string(),
+		signer: Default::default(),
+		wasm_code_id: None,
+		latest_height_max_staleness_ms: 10_000,
+		channel_whitelist: vec![],
+		common: CommonClientConfig {
+			skip_optional_client_updates: true,
+			max_packets_to_process: 200,
+			max_submit_retries: 5,
+			strict_middleware_check: false,
+			simulate_messages: false,
+			skip_invalid_wasm_messages: false,
+			stop_packets_on_inactive_client: false,
+			packet_filters: vec![],
+			query_cache_capacity: 1024,
+			timeout_safety_margin_blocks: 2,
+			auto_whitelist_channels: false,
+			fee_payee_address: None,
+			min_balance: None,
+			stall_factor: 20,
+			stall_alert_command: None,
+			proof_query_concurrency: None,
+			max_pending_messages: 1000,
+			pre_validate_updates: false,
+		},
+		skip_tokens_list: None,
+		packet_sender_contract: None,
+	}
+}
+
+async fn setup_clients() -> (AnyChain, AnyChain) {
+	log::info!(target: "hyperspace", "=========================== Starting Test ===========================");
+	let args = Args::default();
+
+	let mut chain_a = AnyConfig::Cosmos(config("cosmos-a", "ibcgo-1", &args.chain_a))
+		.into_live_client()
+		.await
+		.unwrap();
+	let mut chain_b = AnyConfig::Cosmos(config("cosmos-b", "ibcgo-2", &args.chain_b))
+		.into_live_client()
+		.await
+		.unwrap();
+
+	let (client_b, client_a) = create_clients(&mut chain_b, &mut chain_a).await.unwrap();
+	chain_a.set_client_id(client_a);
+	chain_b.set_client_id(client_b);
+	(chain_a, chain_b)
+}
+
+/// Asserts that the queries used by the relay loop are answered the same by a node serving
+/// gRPC and by one that only serves REST, for the client, connection and transfer channel of
+/// the path on chain B.
+async fn assert_queries_match<A: Chain, B: Chain>(
+	grpc: &A,
+	rest: &B,
+	client_id: ClientId,
+	connection_id: ConnectionId,
+	channel_id: ChannelId,
+) {
+	let port_id = PortId::transfer();
+	let (at, _) = grpc.latest_height_and_timestamp().await.unwrap();
+
+	let client_state = grpc.query_client_state(at, client_id.clone()).await.unwrap();
+	assert_eq!(client_state, rest.query_client_state(at, client_id.clone()).await.unwrap());
+	let consensus_height = AnyClientState::try_from(client_state.client_state.unwrap())
+		.unwrap()
+		.latest_height();
+	assert_eq!(
+		grpc.query_client_consensus(at, client_id.clone(), consensus_height)
+			.await
+			.unwrap(),
+		rest.query_client_consensus(at, client_id.clone(), consensus_height)
+			.await
+			.unwrap()
+	);
+	assert_eq!(
+		grpc.query_client_status(at, client_id.clone()).await.unwrap(),
+		rest.query_client_status(at, client_id).await.unwrap()
+	);
+	assert_eq!(
+		grpc.query_connection_end(at, connection_id.clone()).await.unwrap(),
+		rest.query_connection_end(at, connection_id.clone()).await.unwrap()
+	);
+	assert_eq!(
+		grpc.query_channel_end(at, channel_id, port_id.clone()).await.unwrap(),
+		rest.query_channel_end(at, channel_id, port_id.clone()).await.unwrap()
+	);
+	assert_eq!(
+		grpc.query_connection_channels(at, &connection_id).await.unwrap().channels,
+		rest.query_connection_channels(at, &connection_id).await.unwrap().channels
+	);
+
+	let commitments = grpc.query_packet_commitments(at, channel_id, port_id.clone()).await.unwrap();
+	assert_eq!(
+		commitments,
+		rest.query_packet_commitments(at, channel_id, port_id.clone()).await.unwrap()
+	);
+	let acknowledgements = grpc
+		.query_packet_acknowledgements(at, channel_id, port_id.clone())
+		.await
+		.unwrap();
+	assert!(!acknowledgements.is_empty(), "No packet received on {channel_id}");
+	assert_eq!(
+		acknowledgements,
+		rest.query_packet_acknowledgements(at, channel_id, port_id.clone())
+			.await
+			.unwrap()
+	);
+	for seq in acknowledgements.iter().copied() {
+		assert_eq!(
+			grpc.query_packet_acknowledgement(at, &port_id, &channel_id, seq).await.unwrap(),
+			rest.query_packet_acknowledgement(at, &port_id, &channel_id, seq).await.unwrap()
+		);
+		assert_eq!(
+			grpc.query_packet_receipt(at, &port_id, &channel_id, seq).await.unwrap(),
+			rest.query_packet_receipt(at, &port_id, &channel_id, seq).await.unwrap()
+		);
+	}
+	let seqs = (1..=acknowledgements.len() as u64 + 1).collect::<Vec<_>>();
+	assert_eq!(
+		grpc.query_unreceived_packets(at, channel_id, port_id.clone(), seqs.clone())
+			.await
+			.unwrap(),
+		rest.query_unreceived_packets(at, channel_id, port_id.clone(), seqs.clone())
+			.await
+			.unwrap()
+	);
+	assert_eq!(
+		grpc.query_unreceived_acknowledgements(at, channel_id, port_id.clone(), seqs.clone())
+			.await
+			.unwrap(),
+		rest.query_unreceived_acknowledgements(at, channel_id, port_id.clone(), seqs)
+			.await
+			.unwrap()
+	);
+
+	let ibc_denom = ibc_denom_for(&port_id, &channel_id, "stake");
+	assert_eq!(
+		grpc.query_denom_trace(ibc_denom.clone()).await.unwrap(),
+		rest.query_denom_trace(ibc_denom).await.unwrap()
+	);
+}
+
+#[tokio::test]
+#[ignore]
+async fn cosmos_queries_without_grpc_integration_test() {
+	logging::setup_logging();
+	let args = Args::default();
+
+	let (mut chain_a, mut chain_b) = setup_clients().await;
+	let (handle, channel_a, channel_b, connection_id_a, connection_id_b, _) =
+		setup_connection_and_channel(&mut chain_a, &mut chain_b, Duration::from_secs(0)).await;
+	handle.abort();
+
+	chain_a.set_connection_id(connection_id_a);
+	chain_b.set_connection_id(connection_id_b.clone());
+	chain_a.set_channel_whitelist(vec![(channel_a, PortId::transfer())].into_iter().collect());
+	chain_b.set_channel_whitelist(vec![(channel_b, PortId::transfer())].into_iter().collect());
+
+	// leaves vouchers, commitments and acknowledgements on both chains
+	let asset_id_a = AnyAssetId::Cosmos("stake".to_string());
+	let asset_id_b = AnyAssetId::Cosmos(ibc_denom_for(&PortId::transfer(), &channel_b, "stake"));
+	ibc_messaging_with_connection_delay(
+		&mut chain_a,
+		&mut chain_b,
+		asset_id_a,
+		asset_id_b,
+		channel_a,
+		channel_b,
+	)
+	.await;
+
+	let mut rest_only = AnyConfig::Cosmos(CosmosClientConfig {
+		grpc_url: None,
+		rest_url: Some(args.rest_b.parse().unwrap()),
+		..config("cosmos-b-rest", "ibcgo-2", &args.chain_b)
+	})
+	.into_live_client()
+	.await
+	.unwrap();
+	assert_queries_match(
+		&chain_b,
+		&rest_only,
+		chain_a.client_id(),
+		connection_id_b.clone(),
+		channel_b,
+	)
+	.await;
+
+	// the node without gRPC relays too: its account, balances, simulations and the client
+	// updates checked for misbehaviour are all queried over REST
+	rest_only.set_client_id(chain_b.client_id());
+	rest_only.set_connection_id(connection_id_b);
+	rest_only.set_channel_whitelist(vec![(channel_b, PortId::transfer())].into_iter().collect());
+	let asset_id_a = AnyAssetId::Cosmos("stake".to_string());
+	let asset_id_b = AnyAssetId::Cosmos(ibc_denom_for(&PortId::transfer(), &channel_b, "stake"));
+	ibc_messaging_with_connection_delay(
+		&mut chain_a,
+		&mut rest_only,
+		asset_id_a,
+		asset_id_b,
+		channel_a,
+		channel_b,
+	)
+	.await;
+}
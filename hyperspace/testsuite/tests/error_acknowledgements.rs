@@ -0,0 +1,154 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The error acknowledgement of a transfer that fails on its destination is surfaced by the
+//! relayer, in its logs and metrics.
+
+use core::time::Duration;
+use hyperspace_metrics::{
+	data::{Metrics, ACKS_ERROR_TOTAL_METRIC},
+	handler::MetricsHandler,
+	Registry,
+};
+use hyperspace_primitives::{utils::create_clients, IbcProvider, TestProvider};
+use hyperspace_testsuite::{
+	ensure_transfer_asset,
+	mock::{MockChain, MockChainConfig},
+	setup_connection_and_channel,
+};
+use ibc::{
+	applications::transfer::{msgs::transfer::MsgTransfer, Amount, PrefixedCoin, PrefixedDenom},
+	core::ics24_host::identifier::PortId,
+};
+use log::{Level, Log, Metadata, Record};
+use std::{str::FromStr, sync::Mutex};
+
+const ASSET: &str = "UNIT";
+
+/// The warnings logged by the relayer.
+static WARNINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+struct WarningLogger;
+
+impl Log for WarningLogger {
+	fn enabled(&self, metadata: &Metadata) -> bool {
+		metadata.level() <= Level::Warn
+	}
+
+	fn log(&self, record: &Record) {
+		if self.enabled(record.metadata()) {
+			WARNINGS.lock().unwrap().push(record.args().to_string());
+		}
+	}
+
+	fn flush(&self) {}
+}
+
+/// Total of the `acks_error_total` counters of the chain `name` on `channel`.
+fn acks_error_total(registry: &Registry, name: &str, channel: &str) -> u64 {
+	registry
+		.gather()
+		.iter()
+		.filter(|family| family.get_name() == ACKS_ERROR_TOTAL_METRIC)
+		.flat_map(|family| family.get_metric())
+		.filter(|metric| {
+			let label = |key: &str| {
+				metric
+					.get_label()
+					.iter()
+					.find(|label| label.get_name() == key)
+					.map(|l| l.get_value())
+			};
+			label("name") == Some(name) && label("channel") == Some(channel)
+		})
+		.map(|metric| metric.get_counter().get_value() as u64)
+		.sum()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn failed_transfers_surface_their_error_acknowledgement() {
+	log::set_logger(&WarningLogger).unwrap();
+	log::set_max_level(log::LevelFilter::Warn);
+	let (mut chain_a, mut chain_b) =
+		MockChain::pair(MockChainConfig::new("mock-a"), MockChainConfig::new("mock-b"));
+	let block_production = [
+		chain_a.start_block_production(Duration::from_millis(50)),
+		chain_b.start_block_production(Duration::from_millis(50)),
+	];
+	let (client_a, client_b) = create_clients(&mut chain_a, &mut chain_b).await.unwrap();
+	chain_a.set_client_id(client_a);
+	chain_b.set_client_id(client_b);
+	ensure_transfer_asset(&chain_a, ASSET.to_string()).await;
+	ensure_transfer_asset(&chain_b, ASSET.to_string()).await;
+
+	let (handle, channel_a, channel_b, connection_id_a, connection_id_b, _) =
+		setup_connection_and_channel(&mut chain_a, &mut chain_b, Duration::from_secs(0)).await;
+	handle.abort();
+	chain_a.set_connection_id(connection_id_a);
+	chain_b.set_connection_id(connection_id_b);
+	chain_a.set_channel_whitelist(vec![(channel_a, PortId::transfer())].into_iter().collect());
+	chain_b.set_channel_whitelist(vec![(channel_b, PortId::transfer())].into_iter().collect());
+
+	let registry = Registry::new();
+	let mut metrics_a =
+		MetricsHandler::new(registry.clone(), Metrics::register("mock-a", &registry).unwrap());
+	let mut metrics_b =
+		MetricsHandler::new(registry.clone(), Metrics::register("mock-b", &registry).unwrap());
+	metrics_a.link_with_counterparty(&mut metrics_b);
+	let relay = tokio::spawn(hyperspace_core::relay(
+		chain_a.clone(),
+		chain_b.clone(),
+		Some(metrics_a),
+		Some(metrics_b),
+		None,
+	));
+
+	// vouchers of chain B that it never escrowed, whose transfer back to it fails
+	let denom = format!("{}/{channel_a}/{ASSET}", PortId::transfer());
+	chain_a.ensure_asset(denom.clone(), chain_a.account_id(), 1000).await.unwrap();
+	let (latest_height, timestamp) = chain_b.latest_height_and_timestamp().await.unwrap();
+	let msg = MsgTransfer {
+		source_port: PortId::transfer(),
+		source_channel: channel_a,
+		token: PrefixedCoin {
+			denom: PrefixedDenom::from_str(&denom).unwrap(),
+			amount: Amount::from_str("1000").unwrap(),
+		},
+		sender: chain_a.account_id(),
+		receiver: chain_b.account_id(),
+		timeout_height: latest_height.add(1000),
+		timeout_timestamp: (timestamp + Duration::from_secs(60 * 60)).unwrap(),
+		memo: String::new(),
+	};
+	chain_a.send_transfer(msg).await.unwrap();
+
+	let surfaced = async {
+		loop {
+			let logged = WARNINGS.lock().unwrap().iter().any(|warning| {
+				warning.contains("error acknowledgement of packet 1") &&
+					warning.contains(&format!("1000 {denom}"))
+			});
+			if logged && acks_error_total(&registry, "mock-b", &channel_b.to_string()) == 1 {
+				break
+			}
+			tokio::time::sleep(Duration::from_millis(100)).await;
+		}
+	};
+	tokio::time::timeout(Duration::from_secs(60), surfaced)
+		.await
+		.expect("The error acknowledgement wasn't surfaced");
+
+	relay.abort();
+	block_production.iter().for_each(|task| task.abort());
+}
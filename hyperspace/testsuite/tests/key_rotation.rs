@@ -0,0 +1,178 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The key of a chain is rotated through the admin service while the relayer runs, and the
+//! messages submitted before and after the rotation land.
+
+use core::time::Duration;
+use hyperspace_core::{
+	logging,
+	query_server::{self, AdminSettings},
+};
+use hyperspace_primitives::{utils::create_clients, Chain, IbcProvider, KeyProvider, TestProvider};
+use hyperspace_testsuite::{
+	ensure_transfer_asset,
+	mock::{MockChain, MockChainConfig, FEE_DENOM},
+	setup_connection_and_channel,
+};
+use ibc::{
+	applications::transfer::{msgs::transfer::MsgTransfer, Amount, PrefixedCoin, PrefixedDenom},
+	core::{
+		ics04_channel::msgs::recv_packet,
+		ics24_host::identifier::{ChannelId, PortId},
+	},
+	signer::Signer,
+};
+use std::{net::TcpListener, str::FromStr};
+
+const ASSET: &str = "UNIT";
+
+/// Sends a transfer from `chain_a` to `chain_b` on `channel`.
+async fn send_transfer(chain_a: &MockChain, chain_b: &MockChain, channel: ChannelId) {
+	let (latest_height, timestamp) = chain_b.latest_height_and_timestamp().await.unwrap();
+	let msg = MsgTransfer {
+		source_port: PortId::transfer(),
+		source_channel: channel,
+		token: PrefixedCoin {
+			denom: PrefixedDenom::from_str(ASSET).unwrap(),
+			amount: Amount::from_str("1000").unwrap(),
+		},
+		sender: chain_a.account_id(),
+		receiver: chain_b.account_id(),
+		timeout_height: latest_height.add(1000),
+		timeout_timestamp: (timestamp + Duration::from_secs(60 * 60)).unwrap(),
+		memo: String::new(),
+	};
+	chain_a.send_transfer(msg).await.unwrap();
+}
+
+/// Waits until `chain_b` received `count` packets from `chain_a`.
+async fn wait_for_packets(chain_a: &MockChain, chain_b: &MockChain, count: usize) {
+	let received = async {
+		while chain_b
+			.message_senders()
+			.iter()
+			.filter(|(_, type_url)| type_url == recv_packet::TYPE_URL)
+			.count() < count
+		{
+			tokio::time::sleep(Duration::from_millis(100)).await;
+		}
+	};
+	tokio::time::timeout(Duration::from_secs(60), received)
+		.await
+		.unwrap_or_else(|_| {
+			panic!("{} didn't receive {count} packets from {}", chain_b.name(), chain_a.name())
+		});
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn keys_are_rotated_while_relaying() {
+	logging::setup_logging();
+	let (mut chain_a, mut chain_b) =
+		MockChain::pair(MockChainConfig::new("mock-a"), MockChainConfig::new("mock-b"));
+	let mut block_production = [
+		chain_a.start_block_production(Duration::from_millis(50)),
+		chain_b.start_block_production(Duration::from_millis(50)),
+	];
+	let (client_a, client_b) = create_clients(&mut chain_a, &mut chain_b).await.unwrap();
+	chain_a.set_client_id(client_a);
+	chain_b.set_client_id(client_b);
+	ensure_transfer_asset(&chain_a, ASSET.to_string()).await;
+	ensure_transfer_asset(&chain_b, ASSET.to_string()).await;
+
+	let (handle, channel_a, channel_b, connection_id_a, connection_id_b, _) =
+		setup_connection_and_channel(&mut chain_a, &mut chain_b, Duration::from_secs(0)).await;
+	handle.abort();
+	chain_a.set_connection_id(connection_id_a);
+	chain_b.set_connection_id(connection_id_b);
+	chain_a.set_channel_whitelist(vec![(channel_a, PortId::transfer())].into_iter().collect());
+	chain_b.set_channel_whitelist(vec![(channel_b, PortId::transfer())].into_iter().collect());
+
+	// the keys are read from files of the key directory, named in the requests
+	let key_dir = std::env::temp_dir().join(format!("hyperspace-keys-{}", std::process::id()));
+	std::fs::create_dir_all(&key_dir).unwrap();
+	let new_account = Signer::from_str("mock-b-relayer-2").unwrap();
+	std::fs::write(key_dir.join("relayer-2"), new_account.to_string()).unwrap();
+	let admin = AdminSettings { key_dir: Some(key_dir.clone()) };
+
+	// the admin service isn't authenticated, so it's only served on loopback addresses
+	let public_addr = TcpListener::bind("0.0.0.0:0").unwrap().local_addr().unwrap();
+	query_server::serve(public_addr, chain_a.clone(), chain_b.clone(), Some(admin.clone()))
+		.await
+		.unwrap_err();
+	let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+	let server =
+		tokio::spawn(query_server::serve(addr, chain_a.clone(), chain_b.clone(), Some(admin)));
+	let relay =
+		tokio::spawn(hyperspace_core::relay(chain_a.clone(), chain_b.clone(), None, None, None));
+
+	let old_account = chain_b.account_id();
+	send_transfer(&chain_a, &chain_b, channel_a).await;
+	wait_for_packets(&chain_a, &chain_b, 1).await;
+
+	// secrets and files outside of the key directory are refused
+	for key_source in [format!("mnemonic:{new_account}"), "../relayer-2".to_string()] {
+		query_server::request_key_rotation(addr, "b".to_string(), key_source, None)
+			.await
+			.unwrap_err();
+	}
+	// an account without fees is refused
+	let key_source = "relayer-2".to_string();
+	query_server::request_key_rotation(addr, "b".to_string(), key_source.clone(), Some(1))
+		.await
+		.unwrap_err();
+	assert_eq!(chain_b.account_id(), old_account);
+	chain_b
+		.ensure_asset(FEE_DENOM.to_string(), new_account.clone(), 1000)
+		.await
+		.unwrap();
+
+	// the batch relaying the second packet is dropped, and without new blocks it's only submitted
+	// again after the rotation, naming the new account as the signer of its messages
+	block_production.iter().for_each(|task| task.abort());
+	chain_b.drop_submissions(1);
+	send_transfer(&chain_a, &chain_b, channel_a).await;
+	chain_a.produce_block();
+	let dropped = async {
+		while chain_b.dropped_submissions() != 0 {
+			tokio::time::sleep(Duration::from_millis(50)).await;
+		}
+	};
+	tokio::time::timeout(Duration::from_secs(60), dropped)
+		.await
+		.expect("The batch of the second packet wasn't submitted");
+	let rotation = query_server::request_key_rotation(addr, "b".to_string(), key_source, None)
+		.await
+		.unwrap();
+	assert_eq!(rotation.old_account, old_account);
+	assert_eq!(rotation.new_account, new_account);
+	block_production = [
+		chain_a.start_block_production(Duration::from_millis(50)),
+		chain_b.start_block_production(Duration::from_millis(50)),
+	];
+	wait_for_packets(&chain_a, &chain_b, 2).await;
+
+	let senders = chain_b
+		.message_senders()
+		.into_iter()
+		.filter(|(_, type_url)| type_url == recv_packet::TYPE_URL)
+		.map(|(sender, _)| sender)
+		.collect::<Vec<_>>();
+	assert_eq!(senders, vec![old_account.to_string(), new_account.to_string()]);
+
+	relay.abort();
+	server.abort();
+	block_production.iter().for_each(|task| task.abort());
+	std::fs::remove_dir_all(key_dir).unwrap();
+}
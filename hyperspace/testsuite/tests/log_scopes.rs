@@ -0,0 +1,95 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The lines logged by the relay loops of concurrently relayed paths are attributed to their
+//! path.
+
+use core::time::Duration;
+use hyperspace_core::logging::{self, LogScope, RelayPathId};
+use hyperspace_primitives::{utils::create_clients, IbcProvider};
+use hyperspace_testsuite::{
+	mock::{MockChain, MockChainConfig},
+	setup_connection_and_channel,
+};
+use log::{LevelFilter, Log, Metadata, Record};
+use std::sync::Mutex;
+
+/// Lines logged during the test, with the scope they were logged in.
+static LINES: Mutex<Vec<(Option<LogScope>, String)>> = Mutex::new(Vec::new());
+
+struct CapturingLogger;
+
+impl Log for CapturingLogger {
+	fn enabled(&self, _: &Metadata) -> bool {
+		true
+	}
+
+	fn log(&self, record: &Record) {
+		LINES
+			.lock()
+			.unwrap()
+			.push((logging::current_scope(), record.args().to_string()));
+	}
+
+	fn flush(&self) {}
+}
+
+/// Opens a channel between two mock chains prefixed with `name`, which runs the relay loop.
+async fn relay_path(name: &str) -> RelayPathId {
+	let (mut chain_a, mut chain_b) = MockChain::pair(
+		MockChainConfig::new(format!("{name}-a")),
+		MockChainConfig::new(format!("{name}-b")),
+	);
+	let block_production = [
+		chain_a.start_block_production(Duration::from_millis(50)),
+		chain_b.start_block_production(Duration::from_millis(50)),
+	];
+	let (client_a, client_b) = create_clients(&mut chain_a, &mut chain_b).await.unwrap();
+	chain_a.set_client_id(client_a);
+	chain_b.set_client_id(client_b);
+	let path = RelayPathId::new(&chain_a, &chain_b);
+
+	let (handle, ..) =
+		setup_connection_and_channel(&mut chain_a, &mut chain_b, Duration::from_secs(0)).await;
+	handle.abort();
+	block_production.iter().for_each(|task| task.abort());
+	path
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn log_lines_are_attributed_to_their_path() {
+	log::set_logger(&CapturingLogger).unwrap();
+	log::set_max_level(LevelFilter::Debug);
+
+	let (path_1, path_2) = tokio::join!(relay_path("path1"), relay_path("path2"));
+	assert_ne!(path_1, path_2);
+
+	let lines = LINES.lock().unwrap();
+	for (path, name, other) in [(&path_1, "path1", "path2"), (&path_2, "path2", "path1")] {
+		let scoped = lines
+			.iter()
+			.filter_map(|(scope, line)| Some((scope.as_ref()?, line)))
+			.filter(|(scope, _)| scope.path == *path)
+			.collect::<Vec<_>>();
+		assert!(!scoped.is_empty(), "Nothing was logged for {path}");
+		for (scope, line) in scoped {
+			let chain = scope.chain.as_deref().expect("the relay loop logs as one of the chains");
+			assert!(chain.starts_with(name), "{path} logged as {chain}");
+			assert!(
+				!line.contains(&format!("{other}-")),
+				"{path} logged a line of {other}: {line}"
+			);
+		}
+	}
+}
@@ -0,0 +1,579 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The relayer scenarios against in-process mock chains, which run as part of `cargo test`.
+
+use core::time::Duration;
+use hyperspace_core::logging;
+use hyperspace_primitives::{
+	utils::{create_channel, create_clients, create_connection},
+	Chain, IbcProvider,
+};
+use hyperspace_testsuite::{
+	assert_denom_trace, ensure_transfer_asset, ibc_channel_close,
+	ibc_messaging_on_auto_whitelisted_channel,
+	ibc_messaging_packet_height_timeout_with_connection_delay, ibc_messaging_with_connection_delay,
+	ibc_messaging_with_many_transfers,
+	latency::LatencyBudget,
+	mock::{MockChain, MockChainConfig},
+	setup_connection_and_channel,
+};
+use ibc::{
+	applications::transfer::VERSION,
+	core::{
+		ics04_channel::channel::{ChannelEnd, Order},
+		ics24_host::identifier::{ChannelId, ConnectionId, PortId},
+	},
+};
+use std::sync::{
+	atomic::{AtomicU64, Ordering},
+	Arc, Once, OnceLock,
+};
+use tokio::{sync::Mutex, task::JoinHandle};
+
+/// Asset minted on both chains for the transfers.
+const ASSET: &str = "UNIT";
+
+/// Real time between two blocks of the mock chains.
+const BLOCK_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The scenarios toggle the global packet relay status, so they must not run concurrently.
+async fn serial() -> tokio::sync::MutexGuard<'static, ()> {
+	static SERIAL: OnceLock<Mutex<()>> = OnceLock::new();
+	SERIAL.get_or_init(|| Mutex::new(())).lock().await
+}
+
+fn setup_logging() {
+	static LOGGING: Once = Once::new();
+	LOGGING.call_once(logging::setup_logging);
+}
+
+/// A pair of chains producing blocks, with a client of each other.
+struct Setup {
+	chain_a: MockChain,
+	chain_b: MockChain,
+	block_production: Vec<JoinHandle<()>>,
+}
+
+impl Drop for Setup {
+	fn drop(&mut self) {
+		self.block_production.iter().for_each(JoinHandle::abort);
+	}
+}
+
+async fn setup_clients(configure: impl FnOnce(&MockChain, &MockChain)) -> Setup {
+	log::info!(target: "hyperspace", "=========================== Starting Test ===========================");
+	let (mut chain_a, mut chain_b) =
+		MockChain::pair(MockChainConfig::new("mock-a"), MockChainConfig::new("mock-b"));
+	configure(&chain_a, &chain_b);
+	let block_production = vec![
+		chain_a.start_block_production(BLOCK_INTERVAL),
+		chain_b.start_block_production(BLOCK_INTERVAL),
+	];
+
+	let (client_a, client_b) = create_clients(&mut chain_a, &mut chain_b).await.unwrap();
+	chain_a.set_client_id(client_a);
+	chain_b.set_client_id(client_b);
+	ensure_transfer_asset(&chain_a, ASSET.to_string()).await;
+	ensure_transfer_asset(&chain_b, ASSET.to_string()).await;
+	Setup { chain_a, chain_b, block_production }
+}
+
+/// Opens a transfer channel with a connection delay between the chains, which then only relay
+/// on it.
+async fn setup_channel(setup: &mut Setup) -> (ChannelId, ChannelId) {
+	let Setup { chain_a, chain_b, .. } = setup;
+	let (handle, channel_a, channel_b, connection_id_a, connection_id_b, _) =
+		setup_connection_and_channel(chain_a, chain_b, Duration::from_secs(60 * 2)).await;
+	handle.abort();
+
+	chain_a.set_connection_id(connection_id_a);
+	chain_b.set_connection_id(connection_id_b);
+	chain_a.set_channel_whitelist(vec![(channel_a, PortId::transfer())].into_iter().collect());
+	chain_b.set_channel_whitelist(vec![(channel_b, PortId::transfer())].into_iter().collect());
+	(channel_a, channel_b)
+}
+
+async fn assert_transfer(setup: &mut Setup, channel_a: ChannelId, channel_b: ChannelId) {
+	let Setup { chain_a, chain_b, .. } = setup;
+	ibc_messaging_with_connection_delay(
+		chain_a,
+		chain_b,
+		ASSET.to_string(),
+		ASSET.to_string(),
+		channel_a,
+		channel_b,
+	)
+	.await;
+	assert_denom_trace(chain_b, channel_b, ASSET).await;
+	assert_denom_trace(chain_a, channel_a, ASSET).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn mock_token_transfer() {
+	let _serial = serial().await;
+	setup_logging();
+	let mut setup = setup_clients(|_, _| ()).await;
+	let (channel_a, channel_b) = setup_channel(&mut setup).await;
+	assert_transfer(&mut setup, channel_a, channel_b).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn mock_packet_latency_is_timed() {
+	let _serial = serial().await;
+	setup_logging();
+	let mut setup = setup_clients(|_, _| ()).await;
+	let (channel_a, channel_b) = setup_channel(&mut setup).await;
+	let Setup { chain_a, chain_b, .. } = &mut setup;
+	let timelines = ibc_messaging_with_connection_delay(
+		chain_a,
+		chain_b,
+		ASSET.to_string(),
+		ASSET.to_string(),
+		channel_a,
+		channel_b,
+	)
+	.await;
+
+	assert_eq!(timelines.len(), 2);
+	let recv_budget = |blocks| LatencyBudget { recv_blocks: Some(blocks), round_trip: None };
+	for timeline in &timelines {
+		let round_trip = timeline.round_trip().unwrap_or_else(|| panic!("{timeline}"));
+		let recv_blocks = timeline.recv_blocks().unwrap_or_else(|| panic!("{timeline}"));
+		assert!(recv_budget(recv_blocks).violations(timeline).is_empty());
+		let round_trip_budget = LatencyBudget { recv_blocks: None, round_trip: Some(round_trip) };
+		assert!(round_trip_budget.violations(timeline).is_empty());
+		if recv_blocks > 0 {
+			assert_eq!(recv_budget(recv_blocks - 1).violations(timeline).len(), 1);
+		}
+	}
+	let budget = LatencyBudget::from_vars(Some("3".to_string()), None);
+	assert_eq!(budget, recv_budget(3));
+	// a packet that wasn't relayed exceeds the budget
+	let mut unrelayed = timelines[0].clone();
+	unrelayed.received = None;
+	unrelayed.acknowledged = None;
+	let budget = LatencyBudget::from_vars(Some("3".to_string()), Some("60".to_string()));
+	assert_eq!(budget.violations(&unrelayed).len(), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn mock_packet_height_timeout() {
+	let _serial = serial().await;
+	setup_logging();
+	let mut setup = setup_clients(|_, _| ()).await;
+	let (channel_a, channel_b) = setup_channel(&mut setup).await;
+	let Setup { chain_a, chain_b, .. } = &mut setup;
+	ibc_messaging_packet_height_timeout_with_connection_delay(
+		chain_a,
+		chain_b,
+		ASSET.to_string(),
+		channel_a,
+		channel_b,
+	)
+	.await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn mock_channel_close() {
+	let _serial = serial().await;
+	setup_logging();
+	let mut setup = setup_clients(|_, _| ()).await;
+	let Setup { chain_a, chain_b, .. } = &mut setup;
+	ibc_channel_close(chain_a, chain_b).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn mock_token_transfer_with_dropped_submissions() {
+	let _serial = serial().await;
+	setup_logging();
+	let mut setup = setup_clients(|_, _| ()).await;
+	let (channel_a, channel_b) = setup_channel(&mut setup).await;
+	setup.chain_a.drop_submissions(2);
+	setup.chain_b.drop_submissions(2);
+	assert_transfer(&mut setup, channel_a, channel_b).await;
+	assert_eq!(setup.chain_a.dropped_submissions(), 0);
+	assert_eq!(setup.chain_b.dropped_submissions(), 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn mock_token_transfer_with_delayed_finality() {
+	let _serial = serial().await;
+	setup_logging();
+	let mut setup = setup_clients(|chain_a, chain_b| {
+		chain_a.set_finality_lag(10);
+		chain_b.set_finality_lag(3);
+	})
+	.await;
+	let (channel_a, channel_b) = setup_channel(&mut setup).await;
+	assert_transfer(&mut setup, channel_a, channel_b).await;
+	assert!(setup.chain_a.finalized_height() + 10 <= setup.chain_a.latest_height());
+}
+
+/// A chain whose finality stream pauses is reported stalled by the alert command, and the
+/// packets sent meanwhile are relayed once it resumes.
+#[tokio::test(flavor = "multi_thread")]
+async fn mock_relay_after_stalled_finality() {
+	let _serial = serial().await;
+	setup_logging();
+	let mut setup = setup_clients(|_, _| ()).await;
+	let (channel_a, channel_b) = setup_channel(&mut setup).await;
+	let alerts = std::env::temp_dir().join(format!("hyperspace-mock-stall-{}", std::process::id()));
+	let _ = std::fs::remove_file(&alerts);
+	// stalled after a single expected block time without finality
+	let common_state = setup.chain_a.common_state_mut();
+	common_state.stall_factor = 1;
+	common_state.stall_alert_command = Some(format!(
+		"echo \"$HYPERSPACE_CHAIN $HYPERSPACE_CHAIN_STATUS\" >> {}",
+		alerts.display()
+	));
+	setup.chain_a.set_finality_paused(true);
+
+	let chain_a = setup.chain_a.clone();
+	let read_alerts = {
+		let alerts = alerts.clone();
+		move || std::fs::read_to_string(&alerts).unwrap_or_default()
+	};
+	let resume = tokio::spawn({
+		let read_alerts = read_alerts.clone();
+		async move {
+			while !read_alerts().contains("mock-a stalled") {
+				tokio::time::sleep(BLOCK_INTERVAL).await;
+			}
+			chain_a.set_finality_paused(false);
+		}
+	});
+	// the transfer sent while mock-a doesn't finalize is relayed once it resumes
+	assert_transfer(&mut setup, channel_a, channel_b).await;
+	resume.await.unwrap();
+	while !read_alerts().contains("mock-a resumed") {
+		tokio::time::sleep(BLOCK_INTERVAL).await;
+	}
+	assert_eq!(read_alerts().lines().collect::<Vec<_>>(), ["mock-a stalled", "mock-a resumed"]);
+	let _ = std::fs::remove_file(alerts);
+}
+
+/// Both directions of the path submit to each chain, the messages of one and the timeouts of
+/// the other, which must not race on the sequence of the relayer account while submissions are
+/// slower than the blocks.
+#[tokio::test(flavor = "multi_thread")]
+async fn mock_relay_with_slow_submissions() {
+	let _serial = serial().await;
+	setup_logging();
+	let mut setup = setup_clients(|_, _| ()).await;
+	let (channel_a, channel_b) = setup_channel(&mut setup).await;
+	setup.chain_a.set_submission_delay(BLOCK_INTERVAL * 4);
+	setup.chain_b.set_submission_delay(BLOCK_INTERVAL * 4);
+
+	let Setup { chain_a, chain_b, .. } = &mut setup;
+	ibc_messaging_packet_height_timeout_with_connection_delay(
+		chain_a,
+		chain_b,
+		ASSET.to_string(),
+		channel_a,
+		channel_b,
+	)
+	.await;
+	assert_transfer(&mut setup, channel_a, channel_b).await;
+	assert_eq!(setup.chain_a.sequence_mismatches(), 0);
+	assert_eq!(setup.chain_b.sequence_mismatches(), 0);
+}
+
+/// Transfers sent faster than the submissions to the counterparty go through a small backlog,
+/// which stays bounded while the relay loop keeps going. The batches that fail to be submitted
+/// are resubmitted.
+#[tokio::test(flavor = "multi_thread")]
+async fn mock_relay_with_a_full_backlog() {
+	const MAX_PENDING_MESSAGES: usize = 4;
+	const TRANSFERS: u64 = 20;
+	let _serial = serial().await;
+	setup_logging();
+	let mut setup = setup_clients(|_, _| ()).await;
+	let (channel_a, _) = setup_channel(&mut setup).await;
+	setup.chain_b.set_submission_delay(BLOCK_INTERVAL * 4);
+	setup.chain_b.drop_submissions(2);
+	let common_state = setup.chain_b.common_state_mut();
+	common_state.max_pending_messages = MAX_PENDING_MESSAGES;
+	// the dropped submissions aren't transport errors, each of them fails its batch
+	common_state.max_submit_retries = 2;
+
+	let relay_stats = setup.chain_b.common_state().relay_stats.clone();
+	let max_pending = Arc::new(AtomicU64::new(0));
+	let monitor = tokio::spawn({
+		let (relay_stats, max_pending) = (relay_stats.clone(), max_pending.clone());
+		async move {
+			loop {
+				max_pending.fetch_max(relay_stats.snapshot().pending_messages, Ordering::Relaxed);
+				tokio::time::sleep(BLOCK_INTERVAL / 5).await;
+			}
+		}
+	});
+	let Setup { chain_a, chain_b, .. } = &mut setup;
+	ibc_messaging_with_many_transfers(chain_a, chain_b, ASSET.to_string(), channel_a, TRANSFERS)
+		.await;
+	monitor.abort();
+
+	// packets are only assembled while the backlog has room, the client updates aside
+	let max_pending = max_pending.load(Ordering::Relaxed);
+	assert!(max_pending < 3 * MAX_PENDING_MESSAGES as u64, "{max_pending} pending messages");
+
+	assert_eq!(setup.chain_b.dropped_submissions(), 0);
+	let stats = relay_stats.snapshot();
+	assert_eq!(stats.failed_batches, 2);
+	assert_eq!(stats.submitted.received_packets, TRANSFERS);
+	assert_eq!(setup.chain_b.sequence_mismatches(), 0);
+}
+
+/// A channel opened by a third party on the relayed connection is relayed and whitelisted
+/// without restarting the relayer.
+#[tokio::test(flavor = "multi_thread")]
+async fn mock_auto_whitelisted_channel() {
+	let _serial = serial().await;
+	setup_logging();
+	let mut setup = setup_clients(|_, _| ()).await;
+	let (channel_a, _) = setup_channel(&mut setup).await;
+	let Setup { chain_a, chain_b, .. } = &mut setup;
+	ibc_messaging_on_auto_whitelisted_channel(chain_a, chain_b, ASSET.to_string()).await;
+	assert!(chain_a.channel_whitelist().len() > 1);
+	assert!(chain_a.channel_whitelist().contains(&(channel_a, PortId::transfer())));
+	assert_eq!(chain_a.channel_whitelist().len(), chain_b.channel_whitelist().len());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn mock_token_transfer_with_corrupt_proofs() {
+	let _serial = serial().await;
+	setup_logging();
+	let mut setup = setup_clients(|_, _| ()).await;
+	let (channel_a, channel_b) = setup_channel(&mut setup).await;
+
+	// the packets can only be relayed once the proofs are valid again
+	setup.chain_a.set_corrupt_proofs(true);
+	let chain_a = setup.chain_a.clone();
+	let recover_at = chain_a.latest_height() + 60;
+	let recovery = tokio::spawn(async move {
+		while chain_a.latest_height() < recover_at {
+			tokio::time::sleep(BLOCK_INTERVAL).await;
+		}
+		chain_a.set_corrupt_proofs(false);
+	});
+	assert_transfer(&mut setup, channel_a, channel_b).await;
+	recovery.await.unwrap();
+}
+
+/// The client updates of a chain whose updates are pre-validated are dropped by the relay loop
+/// while its headers are corrupted, instead of being submitted to the counterparty.
+#[tokio::test(flavor = "multi_thread")]
+async fn mock_relay_rejects_invalid_client_updates() {
+	const UPDATE_CLIENT_TYPE_URL: &str = "/ibc.core.client.v1.MsgUpdateClient";
+	let _serial = serial().await;
+	setup_logging();
+	let mut setup = setup_clients(|_, _| ()).await;
+	let (channel_a, channel_b) = setup_channel(&mut setup).await;
+	setup.chain_a.common_state_mut().pre_validate_updates = true;
+
+	let client_updates = |chain: &MockChain| {
+		chain
+			.message_senders()
+			.into_iter()
+			.filter(|(_, type_url)| type_url == UPDATE_CLIENT_TYPE_URL)
+			.count()
+	};
+	setup.chain_a.set_corrupt_headers(true);
+	let (chain_a, chain_b) = (setup.chain_a.clone(), setup.chain_b.clone());
+	let updates_before = client_updates(&chain_b);
+	let recover_at = chain_a.latest_height() + 60;
+	let recovery = tokio::spawn(async move {
+		while chain_a.latest_height() < recover_at {
+			tokio::time::sleep(BLOCK_INTERVAL).await;
+		}
+		let submitted_updates = client_updates(&chain_b) - updates_before;
+		chain_a.set_corrupt_headers(false);
+		submitted_updates
+	});
+	assert_transfer(&mut setup, channel_a, channel_b).await;
+	assert_eq!(recovery.await.unwrap(), 0, "corrupted client updates were submitted");
+}
+
+/// The handshake of a second connection kept in another store of chain B is proven in that
+/// store, while the first connection stays in the default one.
+#[tokio::test(flavor = "multi_thread")]
+async fn mock_handshake_on_a_second_store() {
+	const SECOND_STORE: &[u8] = b"ibc-v2/";
+	let _serial = serial().await;
+	setup_logging();
+	let mut setup = setup_clients(|_, _| ()).await;
+	setup_channel(&mut setup).await;
+	let Setup { chain_a, chain_b, .. } = &mut setup;
+
+	let second_connection = ConnectionId::new(1);
+	chain_b.set_connection_prefix(second_connection.clone(), SECOND_STORE);
+	chain_a.add_connection_id(second_connection.clone());
+	chain_b.add_connection_id(second_connection.clone());
+	chain_a.common_state_mut().auto_whitelist_channels = true;
+	chain_a.take_proof_requests();
+	chain_b.take_proof_requests();
+
+	let relayer =
+		tokio::spawn(hyperspace_core::relay(chain_a.clone(), chain_b.clone(), None, None, None));
+	let prefix = chain_b.connection_prefix_of(&second_connection);
+	let (connection_a, connection_b) =
+		create_connection(chain_a, chain_b, Duration::from_secs(0), prefix)
+			.await
+			.unwrap();
+	assert_eq!((&connection_a, &connection_b), (&second_connection, &second_connection));
+	let (_, channel_b) = create_channel(
+		chain_a,
+		chain_b,
+		connection_a,
+		PortId::transfer(),
+		VERSION.to_string(),
+		Order::Unordered,
+		None,
+	)
+	.await
+	.unwrap();
+	relayer.abort();
+
+	// the connection, client and channel proofs of chain B are all in its second store
+	let requests_b = chain_b.take_proof_requests();
+	let requested = |path: String| requests_b.iter().any(|(_, requested)| *requested == path);
+	assert!(requested(format!("connections/{connection_b}")), "{requests_b:?}");
+	assert!(requested(format!("clients/{}/clientState", chain_a.client_id())), "{requests_b:?}");
+	assert!(
+		requested(format!("channelEnds/ports/transfer/channels/{channel_b}")),
+		"{requests_b:?}"
+	);
+	assert!(requests_b.iter().all(|(prefix, _)| prefix.as_bytes() == SECOND_STORE));
+	// chain A proves its end of the connection in its default store
+	assert!(chain_a.take_proof_requests().is_empty());
+}
+
+/// A channel opened on the relayed connection with a middleware stack the relayer can't relay
+/// isn't whitelisted when the middleware check is strict, unlike the channels opened after it.
+#[tokio::test(flavor = "multi_thread")]
+async fn mock_unsupported_middleware_is_not_auto_whitelisted() {
+	let _serial = serial().await;
+	setup_logging();
+	let mut setup = setup_clients(|_, _| ()).await;
+	let Setup { chain_a, chain_b, .. } = &mut setup;
+	for common_state in [chain_a.common_state_mut(), chain_b.common_state_mut()] {
+		common_state.auto_whitelist_channels = true;
+		common_state.strict_middleware_check = true;
+	}
+	let relayer =
+		tokio::spawn(hyperspace_core::relay(chain_a.clone(), chain_b.clone(), None, None, None));
+	let prefix = chain_b.connection_prefix();
+	let (connection_id, _) = create_connection(chain_a, chain_b, Duration::from_secs(0), prefix)
+		.await
+		.unwrap();
+
+	let mut channels = vec![];
+	for version in [r#"{"app_version":"ics20-1","foo_version":"foo-1"}"#, VERSION] {
+		channels.push(
+			create_channel(
+				chain_a,
+				chain_b,
+				connection_id.clone(),
+				PortId::transfer(),
+				version.to_string(),
+				Order::Unordered,
+				None,
+			)
+			.await
+			.unwrap(),
+		);
+	}
+	let [(unsupported_a, unsupported_b), (supported_a, supported_b)] = channels[..] else {
+		unreachable!()
+	};
+
+	// the whitelists are updated in the order the channels were opened
+	let whitelisted = |chain: &MockChain, channel_id| {
+		chain.channel_whitelist().contains(&(channel_id, PortId::transfer()))
+	};
+	while !whitelisted(chain_a, supported_a) || !whitelisted(chain_b, supported_b) {
+		tokio::time::sleep(BLOCK_INTERVAL).await;
+	}
+	assert!(!whitelisted(chain_a, unsupported_a));
+	assert!(!whitelisted(chain_b, unsupported_b));
+	relayer.abort();
+}
+
+/// Acknowledges the channels chain B opens with ICS-20 v2 only.
+fn ics20_v2_resolver(proposed: &str) -> Result<String, anyhow::Error> {
+	match proposed {
+		"ics20-2" => Ok(proposed.to_string()),
+		_ => Err(anyhow::anyhow!("unsupported version {proposed}")),
+	}
+}
+
+async fn transfer_channel_version(chain: &MockChain, channel_id: ChannelId) -> String {
+	let (latest_height, ..) = chain.latest_height_and_timestamp().await.unwrap();
+	let channel = chain
+		.query_channel_end(latest_height, channel_id, PortId::transfer())
+		.await
+		.unwrap()
+		.channel
+		.unwrap();
+	ChannelEnd::try_from(channel).unwrap().version.to_string()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn mock_channel_version_negotiation() {
+	let _serial = serial().await;
+	setup_logging();
+	let mut setup = setup_clients(|_, _| ()).await;
+	let Setup { chain_a, chain_b, .. } = &mut setup;
+	let relayer =
+		tokio::spawn(hyperspace_core::relay(chain_a.clone(), chain_b.clone(), None, None, None));
+	let prefix = chain_b.connection_prefix();
+	let (connection_id, _) = create_connection(chain_a, chain_b, Duration::from_secs(0), prefix)
+		.await
+		.unwrap();
+
+	// the app of chain B answers the proposed version with another one, which chain A
+	// acknowledges and both ends open with
+	chain_b.set_transfer_version(Some("ics20-2"));
+	let (channel_a, channel_b) = create_channel(
+		chain_a,
+		chain_b,
+		connection_id.clone(),
+		PortId::transfer(),
+		VERSION.to_string(),
+		Order::Unordered,
+		Some(ics20_v2_resolver),
+	)
+	.await
+	.unwrap();
+	assert_eq!(transfer_channel_version(chain_a, channel_a).await, "ics20-2");
+	assert_eq!(transfer_channel_version(chain_b, channel_b).await, "ics20-2");
+
+	// the resolver only applied to that handshake
+	chain_b.set_transfer_version(None);
+	let (channel_a, channel_b) = create_channel(
+		chain_a,
+		chain_b,
+		connection_id,
+		PortId::transfer(),
+		VERSION.to_string(),
+		Order::Unordered,
+		None,
+	)
+	.await
+	.unwrap();
+	assert_eq!(transfer_channel_version(chain_a, channel_a).await, VERSION);
+	assert_eq!(transfer_channel_version(chain_b, channel_b).await, VERSION);
+	assert!(chain_a.common_state().channel_version_resolvers.lock().unwrap().is_empty());
+	relayer.abort();
+}
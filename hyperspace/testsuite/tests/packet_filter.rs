@@ -0,0 +1,211 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The packets exceeding the spam limits of their channel aren't relayed, while the other
+//! packets of the channel are, whether they are found by the packet queries or through their
+//! events.
+
+use core::time::Duration;
+use hyperspace_core::{events::parse_events, logging};
+use hyperspace_primitives::{
+	packet_filter::ChannelPacketFilter, packet_info_to_packet, query_undelivered_sequences,
+	utils::create_clients, Chain, IbcProvider, TestProvider,
+};
+use hyperspace_testsuite::{
+	ensure_transfer_asset,
+	mock::{MockChain, MockChainConfig},
+	setup_connection_and_channel,
+};
+use ibc::{
+	applications::transfer::{msgs::transfer::MsgTransfer, Amount, PrefixedCoin, PrefixedDenom},
+	core::{
+		ics04_channel::events::SendPacket,
+		ics24_host::identifier::{ChannelId, PortId},
+	},
+	events::IbcEvent,
+};
+use std::str::FromStr;
+use tokio::task::JoinHandle;
+
+const ASSET: &str = "UNIT";
+
+/// Largest packet data relayed on the channel.
+const MAX_DATA_SIZE: usize = 1024;
+
+async fn send_transfer(
+	chain_a: &MockChain,
+	chain_b: &MockChain,
+	channel_a: ChannelId,
+	memo: String,
+) {
+	let (latest_height, timestamp) = chain_b.latest_height_and_timestamp().await.unwrap();
+	let msg = MsgTransfer {
+		source_port: PortId::transfer(),
+		source_channel: channel_a,
+		token: PrefixedCoin {
+			denom: PrefixedDenom::from_str(ASSET).unwrap(),
+			amount: Amount::from_str("1000").unwrap(),
+		},
+		sender: chain_a.account_id(),
+		receiver: chain_b.account_id(),
+		timeout_height: latest_height.add(1000),
+		timeout_timestamp: (timestamp + Duration::from_secs(60 * 60)).unwrap(),
+		memo,
+	};
+	chain_a.send_transfer(msg).await.unwrap();
+}
+
+async fn undelivered_sequences(
+	chain_a: &MockChain,
+	chain_b: &MockChain,
+	channel_a: ChannelId,
+) -> Vec<u64> {
+	let (height_a, _) = chain_a.latest_height_and_timestamp().await.unwrap();
+	let (height_b, _) = chain_b.latest_height_and_timestamp().await.unwrap();
+	query_undelivered_sequences(height_a, height_b, channel_a, PortId::transfer(), chain_a, chain_b)
+		.await
+		.unwrap()
+}
+
+/// Two mock chains producing blocks, with a transfer channel open between them.
+async fn setup() -> (MockChain, MockChain, ChannelId, [JoinHandle<()>; 2]) {
+	logging::setup_logging();
+	let (mut chain_a, mut chain_b) =
+		MockChain::pair(MockChainConfig::new("mock-a"), MockChainConfig::new("mock-b"));
+	let block_production = [
+		chain_a.start_block_production(Duration::from_millis(50)),
+		chain_b.start_block_production(Duration::from_millis(50)),
+	];
+	let (client_a, client_b) = create_clients(&mut chain_a, &mut chain_b).await.unwrap();
+	chain_a.set_client_id(client_a);
+	chain_b.set_client_id(client_b);
+	ensure_transfer_asset(&chain_a, ASSET.to_string()).await;
+	ensure_transfer_asset(&chain_b, ASSET.to_string()).await;
+
+	let (handle, channel_a, channel_b, connection_id_a, connection_id_b, _) =
+		setup_connection_and_channel(&mut chain_a, &mut chain_b, Duration::from_secs(0)).await;
+	handle.abort();
+	chain_a.set_connection_id(connection_id_a);
+	chain_b.set_connection_id(connection_id_b);
+	chain_a.set_channel_whitelist(vec![(channel_a, PortId::transfer())].into_iter().collect());
+	chain_b.set_channel_whitelist(vec![(channel_b, PortId::transfer())].into_iter().collect());
+	(chain_a, chain_b, channel_a, block_production)
+}
+
+/// The `SendPacket` event of the packet `sequence` sent on `channel_a`.
+async fn send_packet_event(chain_a: &MockChain, channel_a: ChannelId, sequence: u64) -> IbcEvent {
+	let packet_info = chain_a
+		.query_send_packets(channel_a, PortId::transfer(), vec![sequence])
+		.await
+		.unwrap()
+		.pop()
+		.expect("The packet was sent");
+	// the packet is proven once its block is produced
+	let height = loop {
+		let (height, _) = chain_a.latest_height_and_timestamp().await.unwrap();
+		let commitment = chain_a
+			.query_packet_commitment(height, &PortId::transfer(), &channel_a, sequence)
+			.await
+			.unwrap()
+			.commitment;
+		if !commitment.is_empty() {
+			break height
+		}
+		tokio::time::sleep(Duration::from_millis(50)).await;
+	};
+	IbcEvent::SendPacket(SendPacket { height, packet: packet_info_to_packet(&packet_info) })
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn oversized_packets_are_skipped() {
+	let (chain_a, chain_b, channel_a, block_production) = setup().await;
+
+	let mut filter = ChannelPacketFilter::new(channel_a, PortId::transfer(), vec![]);
+	filter.max_data_size = Some(MAX_DATA_SIZE);
+	chain_a.common_state().packet_filter.reload(&[filter]).unwrap();
+	let relay =
+		tokio::spawn(hyperspace_core::relay(chain_a.clone(), chain_b.clone(), None, None, None));
+
+	// the memo of the first transfer pads its data past the limit
+	send_transfer(&chain_a, &chain_b, channel_a, "x".repeat(MAX_DATA_SIZE)).await;
+	send_transfer(&chain_a, &chain_b, channel_a, String::new()).await;
+
+	let relayed = async {
+		while undelivered_sequences(&chain_a, &chain_b, channel_a).await != vec![1] {
+			tokio::time::sleep(Duration::from_millis(100)).await;
+		}
+	};
+	tokio::time::timeout(Duration::from_secs(60), relayed)
+		.await
+		.expect("The packet within the limit wasn't relayed");
+
+	// the oversized packet stays undelivered over the next rounds of the relay loop
+	tokio::time::sleep(Duration::from_secs(2)).await;
+	assert_eq!(undelivered_sequences(&chain_a, &chain_b, channel_a).await, vec![1]);
+
+	relay.abort();
+	block_production.iter().for_each(|task| task.abort());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn denied_packets_seen_through_events_are_not_received() {
+	let (mut chain_a, mut chain_b, channel_a, block_production) = setup().await;
+	let mut filter = ChannelPacketFilter::new(channel_a, PortId::transfer(), vec![]);
+	filter.deny_senders = vec![chain_a.account_id().to_string()];
+	chain_a.common_state().packet_filter.reload(&[filter]).unwrap();
+
+	send_transfer(&chain_a, &chain_b, channel_a, String::new()).await;
+	let event = send_packet_event(&chain_a, channel_a, 1).await;
+	let msgs = parse_events(&mut chain_a, &mut chain_b, vec![event.clone()], None)
+		.await
+		.unwrap();
+	assert!(msgs.is_empty());
+
+	// the packet is received once the sender is no longer denied
+	chain_a.common_state().packet_filter.reload(&[]).unwrap();
+	let msgs = parse_events(&mut chain_a, &mut chain_b, vec![event], None).await.unwrap();
+	assert_eq!(msgs.len(), 1);
+
+	block_production.iter().for_each(|task| task.abort());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn spam_limits_apply_to_packets_seen_through_events() {
+	let (mut chain_a, mut chain_b, channel_a, block_production) = setup().await;
+	let mut filter = ChannelPacketFilter::new(channel_a, PortId::transfer(), vec![]);
+	filter.max_data_size = Some(MAX_DATA_SIZE);
+	filter.min_amounts = [(ASSET.to_string(), "1000".to_string())].into_iter().collect();
+	chain_a.common_state().packet_filter.reload(&[filter.clone()]).unwrap();
+
+	// the memo of the first transfer pads its data past the limit
+	send_transfer(&chain_a, &chain_b, channel_a, "x".repeat(MAX_DATA_SIZE)).await;
+	send_transfer(&chain_a, &chain_b, channel_a, String::new()).await;
+	let oversized = send_packet_event(&chain_a, channel_a, 1).await;
+	let within_limits = send_packet_event(&chain_a, channel_a, 2).await;
+	let msgs =
+		parse_events(&mut chain_a, &mut chain_b, vec![oversized, within_limits.clone()], None)
+			.await
+			.unwrap();
+	assert_eq!(msgs.len(), 1);
+
+	// the transfers of 1000 are denied under a higher minimum amount
+	filter.min_amounts = [(ASSET.to_string(), "1001".to_string())].into_iter().collect();
+	chain_a.common_state().packet_filter.reload(&[filter]).unwrap();
+	let msgs = parse_events(&mut chain_a, &mut chain_b, vec![within_limits], None)
+		.await
+		.unwrap();
+	assert!(msgs.is_empty());
+
+	block_production.iter().for_each(|task| task.abort());
+}
@@ -13,24 +13,37 @@
 // limitations under the License.
 
 use core::time::Duration;
-use futures::StreamExt;
 use hyperspace_core::{
-	chain::{AnyAssetId, AnyChain, AnyConfig},
+	chain::{AnyAssetId, AnyChain, AnyConfig, CodeId},
 	logging,
 	substrate::DefaultConfig,
 };
 use hyperspace_cosmos::client::{CosmosClient, CosmosClientConfig};
-use hyperspace_parachain::{finality_protocol::FinalityProtocol, ParachainClientConfig};
-use hyperspace_primitives::{utils::create_clients, CommonClientConfig, IbcProvider};
+use hyperspace_parachain::{
+	finality_protocol::FinalityProtocol, ParachainClient, ParachainClientConfig,
+};
+use hyperspace_primitives::{
+	utils::{create_clients, ibc_denom_for},
+	Chain, CommonClientConfig, IbcProvider,
+};
 use hyperspace_testsuite::{
-	ibc_channel_close, ibc_messaging_packet_height_timeout_with_connection_delay,
+	assert_denom_trace, await_parachain_onboarding, ensure_transfer_asset, ibc_channel_close,
+	ibc_messaging_packet_height_timeout_with_connection_delay,
 	ibc_messaging_packet_timeout_on_channel_close,
 	ibc_messaging_packet_timestamp_timeout_with_connection_delay,
-	ibc_messaging_with_connection_delay, misbehaviour::ibc_messaging_submit_misbehaviour,
-	setup_connection_and_channel,
+	ibc_messaging_with_connection_delay,
+	ibc_messaging_with_connection_delay_skipping_optional_updates, ibc_messaging_with_memo,
+	ibc_messaging_with_transfers, misbehaviour::ibc_messaging_submit_misbehaviour,
+	setup_connection_and_channel, TestTransfer,
 };
-use ibc::core::ics24_host::identifier::PortId;
-use sp_core::hashing::sha2_256;
+use ibc::core::{ics02_client::client_state::ClientState as _, ics24_host::identifier::PortId};
+use pallet_ibc::light_clients::AnyClientState;
+
+/// How long the chains may take to advance their height once they produce blocks.
+const READY_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Denom of the parachain's asset 1, transferred to the cosmos chain.
+const PARACHAIN_DENOM: &str = "UNIT";
 
 #[derive(Debug, Clone)]
 pub struct Args {
@@ -43,6 +56,8 @@ pub struct Args {
 	pub cosmos_grpc: String,
 	pub cosmos_ws: String,
 	pub wasm_path: String,
+	/// Another build of the wasm client, which the client is migrated to.
+	pub wasm_v2_path: String,
 }
 
 impl Default for Args {
@@ -53,6 +68,9 @@ impl Default for Args {
 		let wasm_path = std::env::var("WASM_PATH").unwrap_or_else(|_| {
 			"../../target/wasm32-unknown-unknown/release/ics10_grandpa_cw.wasm".to_string()
 		});
+		let wasm_v2_path = std::env::var("WASM_V2_PATH").unwrap_or_else(|_| {
+			"../../target/wasm32-unknown-unknown/release/ics10_grandpa_cw_v2.wasm".to_string()
+		});
 
 		Args {
 			chain_a: format!("ws://{para}:9188"),
@@ -64,6 +82,7 @@ impl Default for Args {
 			cosmos_grpc: format!("http://{cosmos}:9090"),
 			cosmos_ws: format!("ws://{cosmos}:26657/websocket"),
 			wasm_path,
+			wasm_v2_path,
 		}
 	}
 }
@@ -80,84 +99,97 @@ async fn setup_clients() -> (AnyChain, AnyChain) {
 		relay_chain_rpc_url: args.relay_chain.clone(),
 		client_id: None,
 		connection_id: None,
+		connections: vec![],
 		commitment_prefix: args.connection_prefix_a.as_bytes().to_vec().into(),
 		ss58_version: 42,
 		channel_whitelist: vec![],
 		finality_protocol: FinalityProtocol::Grandpa,
 		private_key: "//Alice".to_string(),
+		signer: Default::default(),
 		key_type: "sr25519".to_string(),
 		wasm_code_id: None,
+		finality_proof_cache_bucket_size: None,
+		metadata_mismatch: Default::default(),
+		max_update_size: None,
+		signature_verification_threads: 0,
+		common: Default::default(),
 	};
 
 	let mut config_b = CosmosClientConfig {
 		name: "cosmos".to_string(),
 		rpc_url: args.chain_b.clone().parse().unwrap(),
 		grpc_url: args.cosmos_grpc.clone().parse().unwrap(),
+		rest_url: None,
 		websocket_url: args.cosmos_ws.clone().parse().unwrap(),
 		chain_id: "ibcgo-1".to_string(),
 		client_id: None,
 		connection_id: None,
+		connections: vec![],
 		account_prefix: "cosmos".to_string(),
 		fee_denom: "stake".to_string(),
 		fee_amount: "4000".to_string(),
 		gas_limit: (i64::MAX - 1) as u64,
+		gas_price: None,
+		fallback_fees: vec![],
 		store_prefix: args.connection_prefix_b,
 		max_tx_size: 200000,
 		mnemonic:
 			"oxygen fall sure lava energy veteran enroll frown question detail include maximum"
 				.to_string(),
+		signer: Default::default(),
 		wasm_code_id: None,
+		latest_height_max_staleness_ms: 10_000,
 		channel_whitelist: vec![],
 		common: CommonClientConfig {
 			skip_optional_client_updates: true,
 			max_packets_to_process: 200,
+			max_submit_retries: 5,
+			strict_middleware_check: false,
+			simulate_messages: false,
+			skip_invalid_wasm_messages: false,
+			stop_packets_on_inactive_client: false,
+			packet_filters: vec![],
+			query_cache_capacity: 1024,
+			timeout_safety_margin_blocks: 2,
+			auto_whitelist_channels: false,
+			fee_payee_address: None,
+			min_balance: None,
+			stall_factor: 20,
+			stall_alert_command: None,
+			proof_query_concurrency: None,
+			max_pending_messages: 1000,
+			pre_validate_updates: false,
 		},
 		skip_tokens_list: None,
+		packet_sender_contract: None,
 	};
 
 	let chain_b = CosmosClient::<DefaultConfig>::new(config_b.clone()).await.unwrap();
 
 	let wasm_data = tokio::fs::read(&args.wasm_path).await.expect("Failed to read wasm file");
-	let code_id = match chain_b.upload_wasm(wasm_data.clone()).await {
-		Ok(code_id) => code_id,
-		Err(e) => {
-			let e_str = format!("{e:?}");
-			if !e_str.contains("wasm code already exists") {
-				panic!("Failed to upload wasm: {e_str}");
-			}
-			sha2_256(&wasm_data).to_vec()
-		},
-	};
+	let (code_id, _) = chain_b.upload_wasm(wasm_data).await.expect("Failed to upload wasm");
 	let code_id_str = hex::encode(code_id);
 	config_b.wasm_code_id = Some(code_id_str);
 
-	let mut chain_a_wrapped = AnyConfig::Parachain(config_a).into_client().await.unwrap();
-	let mut chain_b_wrapped = AnyConfig::Cosmos(config_b).into_client().await.unwrap();
+	// the parachain only produces blocks once onboarded, which is awaited below
+	let mut chain_a_wrapped =
+		AnyChain::Parachain(ParachainClient::<DefaultConfig>::new(config_a).await.unwrap());
+	let mut chain_b_wrapped = AnyConfig::Cosmos(config_b).into_live_client().await.unwrap();
 
 	let AnyChain::Parachain(chain_a) = &mut chain_a_wrapped else { unreachable!() };
 
 	// Wait until for parachains to start producing blocks
 	log::info!(target: "hyperspace", "Waiting for block production from parachain");
-	let session_length = chain_a.grandpa_prover().session_length().await.unwrap();
-	let _ = chain_a
-		.relay_client
-		.rpc()
-		.subscribe_finalized_block_headers()
-		.await
-		.unwrap()
-		.filter_map(|result| futures::future::ready(result.ok()))
-		.skip_while(|h| futures::future::ready(h.number < (session_length * 2) + 10))
-		.take(1)
-		.collect::<Vec<_>>()
-		.await;
+	await_parachain_onboarding(&chain_a, READY_TIMEOUT).await;
+	chain_a_wrapped.await_ready(READY_TIMEOUT).await.unwrap();
 	log::info!(target: "hyperspace", "Parachain have started block production");
 
-	let clients_on_a = chain_a_wrapped.query_clients().await.unwrap();
-	let clients_on_b = chain_b_wrapped.query_clients().await.unwrap();
+	let client_a_on_b = chain_b_wrapped.find_suitable_client(&chain_a_wrapped).await.unwrap();
+	let client_b_on_a = chain_a_wrapped.find_suitable_client(&chain_b_wrapped).await.unwrap();
 
-	if !clients_on_a.is_empty() && !clients_on_b.is_empty() {
-		chain_a_wrapped.set_client_id(clients_on_b[0].clone());
-		chain_b_wrapped.set_client_id(clients_on_a[0].clone());
+	if let (Some(client_a), Some(client_b)) = (client_a_on_b, client_b_on_a) {
+		chain_a_wrapped.set_client_id(client_a);
+		chain_b_wrapped.set_client_id(client_b);
 		return (chain_a_wrapped, chain_b_wrapped)
 	}
 
@@ -174,13 +206,13 @@ async fn parachain_to_cosmos_ibc_messaging_full_integration_test() {
 	logging::setup_logging();
 
 	let asset_id_a = AnyAssetId::Parachain(1);
-	let asset_id_b = AnyAssetId::Cosmos(
-		"ibc/47B97D8FF01DA03FCB2F4B1FFEC931645F254E21EF465FA95CBA6888CB964DC4".to_string(),
-	);
 	let (mut chain_a, mut chain_b) = setup_clients().await;
-	let (handle, channel_a, channel_b, connection_id_a, connection_id_b) =
+	ensure_transfer_asset(&chain_a, asset_id_a.clone()).await;
+	let (handle, channel_a, channel_b, connection_id_a, connection_id_b, _) =
 		setup_connection_and_channel(&mut chain_a, &mut chain_b, Duration::from_secs(60 * 2)).await;
 	handle.abort();
+	let asset_id_b =
+		AnyAssetId::Cosmos(ibc_denom_for(&PortId::transfer(), &channel_b, PARACHAIN_DENOM));
 
 	// Set connections and channel whitelist
 	chain_a.set_connection_id(connection_id_a);
@@ -202,6 +234,29 @@ async fn parachain_to_cosmos_ibc_messaging_full_integration_test() {
 		channel_b,
 	)
 	.await;
+	assert_denom_trace(&chain_b, channel_b, PARACHAIN_DENOM).await;
+	// the vouchers received by chain_b are sent back by a different asset id and amount
+	ibc_messaging_with_transfers(
+		&mut chain_a,
+		&mut chain_b,
+		TestTransfer::new(asset_id_a.clone()).with_amount(1_000_000),
+		TestTransfer::new(asset_id_b.clone()).with_amount(500_000),
+		channel_a,
+		channel_b,
+	)
+	.await;
+	ibc_messaging_with_connection_delay_skipping_optional_updates(
+		&mut chain_a,
+		&mut chain_b,
+		asset_id_a.clone(),
+		channel_a,
+		Duration::from_secs(60 * 2),
+	)
+	.await;
+
+	// memo in both directions
+	ibc_messaging_with_memo(&mut chain_a, &mut chain_b, asset_id_a.clone(), channel_a).await;
+	ibc_messaging_with_memo(&mut chain_b, &mut chain_a, asset_id_b.clone(), channel_b).await;
 
 	// timeouts + connection delay
 	ibc_messaging_packet_height_timeout_with_connection_delay(
@@ -243,7 +298,7 @@ async fn cosmos_to_parachain_ibc_messaging_full_integration_test() {
 	let (chain_a, chain_b) = setup_clients().await;
 	let (mut chain_b, mut chain_a) = (chain_a, chain_b);
 
-	let (handle, channel_a, channel_b, connection_id_a, connection_id_b) =
+	let (handle, channel_a, channel_b, connection_id_a, connection_id_b, _) =
 		setup_connection_and_channel(&mut chain_a, &mut chain_b, Duration::from_secs(60 * 2)).await;
 	handle.abort();
 
@@ -256,6 +311,7 @@ async fn cosmos_to_parachain_ibc_messaging_full_integration_test() {
 
 	let asset_id_a = AnyAssetId::Cosmos("stake".to_string());
 	let asset_id_b = AnyAssetId::Parachain(2);
+	ensure_transfer_asset(&chain_a, asset_id_a.clone()).await;
 
 	// Run tests sequentially
 
@@ -269,6 +325,7 @@ async fn cosmos_to_parachain_ibc_messaging_full_integration_test() {
 		channel_b,
 	)
 	.await;
+	assert_denom_trace(&chain_b, channel_b, "stake").await;
 
 	// timeouts + connection delay
 	ibc_messaging_packet_height_timeout_with_connection_delay(
@@ -295,3 +352,57 @@ async fn cosmos_to_parachain_ibc_messaging_full_integration_test() {
 
 	ibc_messaging_submit_misbehaviour(&mut chain_a, &mut chain_b).await;
 }
+
+/// Latest height of the client of the parachain on the cosmos chain.
+async fn parachain_client_height(chain_a: &AnyChain, chain_b: &AnyChain) -> ibc::Height {
+	let (latest_height, _) = chain_b.latest_height_and_timestamp().await.unwrap();
+	let response = chain_b.query_client_state(latest_height, chain_a.client_id()).await.unwrap();
+	AnyClientState::decode_recursive(response.client_state.unwrap(), |cs| {
+		matches!(cs, AnyClientState::Grandpa(_))
+	})
+	.unwrap()
+	.latest_height()
+}
+
+#[tokio::test]
+#[ignore]
+async fn parachain_client_on_cosmos_is_migrated_to_new_wasm_code() {
+	logging::setup_logging();
+
+	let args = Args::default();
+	let (chain_a, mut chain_b) = setup_clients().await;
+
+	let wasm_data = tokio::fs::read(&args.wasm_v2_path).await.expect("Failed to read wasm file");
+	let (code_id, _) = chain_b.upload_wasm(wasm_data).await.expect("Failed to upload wasm");
+	let AnyChain::Wasm(wasm_chain) = &chain_b else { panic!("Expected a wasm chain") };
+	assert_ne!(wasm_chain.code_id.to_vec(), code_id, "The v2 code should be another build");
+
+	chain_b
+		.migrate_wasm_client(chain_a.client_id(), code_id.clone(), b"{}".to_vec())
+		.await
+		.expect("Failed to migrate the client");
+	let AnyChain::Wasm(wasm_chain) = &chain_b else { unreachable!() };
+	assert_eq!(wasm_chain.code_id, CodeId::try_from(code_id.as_slice()).unwrap());
+
+	// the updates of the client are verified by the new code
+	let migrated_height = parachain_client_height(&chain_a, &chain_b).await;
+	let (chain_a_clone, chain_b_clone) = (chain_a.clone(), chain_b.clone());
+	let handle = tokio::task::spawn(async move {
+		hyperspace_core::relay(chain_a_clone, chain_b_clone, None, None, None)
+			.await
+			.unwrap()
+	});
+	let updated = tokio::time::timeout(READY_TIMEOUT, async {
+		loop {
+			tokio::time::sleep(Duration::from_secs(10)).await;
+			let height = parachain_client_height(&chain_a, &chain_b).await;
+			if height > migrated_height {
+				break height
+			}
+		}
+	})
+	.await;
+	handle.abort();
+	let updated_height = updated.expect("The migrated client wasn't updated");
+	log::info!(target: "hyperspace", "The migrated client was updated from {migrated_height} to {updated_height}");
+}
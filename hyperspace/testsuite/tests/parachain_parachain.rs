@@ -12,115 +12,63 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use futures::StreamExt;
 use hyperspace_core::{logging, substrate::DefaultConfig};
-use hyperspace_parachain::{
-	finality_protocol::FinalityProtocol, ParachainClient, ParachainClientConfig,
-};
-use hyperspace_primitives::{utils::create_clients, IbcProvider, TestProvider};
+use hyperspace_parachain::{error::Error, ParachainClient};
+use hyperspace_primitives::{utils::create_clients, Chain, IbcProvider, TestProvider};
 use hyperspace_testsuite::{
-	client_synchronization_test, ibc_channel_close,
+	authority_sets::grandpa_client_catches_up_with_authority_sets, await_parachain_onboarding,
+	client_synchronization_test, dev_parachains::DevParachains, ensure_transfer_asset,
+	ibc_channel_close, ibc_messaging_after_downtime, ibc_messaging_on_auto_whitelisted_channel,
+	ibc_messaging_on_multiple_connections,
 	ibc_messaging_packet_height_timeout_with_connection_delay,
 	ibc_messaging_packet_timeout_on_channel_close,
 	ibc_messaging_packet_timestamp_timeout_with_connection_delay,
-	ibc_messaging_with_connection_delay, misbehaviour::ibc_messaging_submit_misbehaviour,
+	ibc_messaging_with_connection_delay, ibc_messaging_with_memo, ibc_messaging_with_mixed_assets,
+	misbehaviour::ibc_messaging_submit_misbehaviour, ordered_channels::ordered_ping_messaging,
+	raw_packets::ibc_messaging_raw_packet, setup_connection_and_channel, TestTransfer,
+};
+use ibc::{
+	core::{
+		ics03_connection::{connection::Counterparty, msgs::conn_open_init::MsgConnectionOpenInit},
+		ics24_host::identifier::ClientId,
+	},
+	protobuf::Protobuf,
+	tx_msg::Msg,
 };
+use ibc_proto::google::protobuf::Any;
+use pallet_ibc::{MultiAddress, Timeout, TransferParams};
+use sp_core::crypto::AccountId32;
 use std::time::Duration;
+use subxt::error::DispatchError;
 
-#[derive(Debug, Clone)]
-pub struct Args {
-	pub chain_a: String,
-	pub chain_b: String,
-	pub relay_chain: String,
-	pub para_id_a: u32,
-	pub para_id_b: u32,
-	pub connection_prefix_a: String,
-	pub connection_prefix_b: String,
-}
-
-impl Default for Args {
-	fn default() -> Self {
-		let relay = std::env::var("RELAY_HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
-		let para = std::env::var("PARA_HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
-
-		Args {
-			chain_a: format!("ws://{para}:9988"),
-			chain_b: format!("ws://{para}:9188"),
-			relay_chain: format!("ws://{relay}:9944"),
-			para_id_a: 2001,
-			para_id_b: 2000,
-			connection_prefix_a: "ibc/".to_string(),
-			connection_prefix_b: "ibc/".to_string(),
-		}
-	}
-}
+/// How long the chains may take to advance their height once they produce blocks.
+const READY_TIMEOUT: Duration = Duration::from_secs(5 * 60);
 
 async fn setup_clients() -> (ParachainClient<DefaultConfig>, ParachainClient<DefaultConfig>) {
 	log::info!(target: "hyperspace", "=========================== Starting Test ===========================");
-	let args = Args::default();
-
-	// Create client configurations
-	let config_a = ParachainClientConfig {
-		name: "9988".to_string(),
-		para_id: args.para_id_a,
-		parachain_rpc_url: args.chain_a,
-		relay_chain_rpc_url: args.relay_chain.clone(),
-		client_id: None,
-		connection_id: None,
-		commitment_prefix: args.connection_prefix_b.as_bytes().to_vec().into(),
-		ss58_version: 42,
-		channel_whitelist: vec![],
-		finality_protocol: FinalityProtocol::Grandpa,
-		private_key: "//Alice".to_string(),
-		key_type: "sr25519".to_string(),
-		wasm_code_id: None,
-	};
-	let config_b = ParachainClientConfig {
-		name: "9188".to_string(),
-		para_id: args.para_id_b,
-		parachain_rpc_url: args.chain_b,
-		relay_chain_rpc_url: args.relay_chain,
-		client_id: None,
-		connection_id: None,
-		commitment_prefix: args.connection_prefix_b.as_bytes().to_vec().into(),
-		private_key: "//Alice".to_string(),
-		ss58_version: 42,
-		channel_whitelist: vec![],
-		finality_protocol: FinalityProtocol::Grandpa,
-		key_type: "sr25519".to_string(),
-		wasm_code_id: None,
-	};
+	let chains = DevParachains::default();
+	let (config_a, config_b) = (chains.config_a(), chains.config_b());
 
 	let mut chain_a = ParachainClient::<DefaultConfig>::new(config_a).await.unwrap();
 	let mut chain_b = ParachainClient::<DefaultConfig>::new(config_b).await.unwrap();
 
 	// Wait until for parachains to start producing blocks
 	log::info!(target: "hyperspace", "Waiting for  block production from parachains");
-	let session_length = chain_a.grandpa_prover().session_length().await.unwrap();
-	let _ = chain_a
-		.relay_client
-		.rpc()
-		.subscribe_finalized_block_headers()
-		.await
-		.unwrap()
-		.filter_map(|result| futures::future::ready(result.ok()))
-		.skip_while(|h| futures::future::ready(h.number < (session_length * 2) + 10))
-		.take(1)
-		.collect::<Vec<_>>()
-		.await;
+	await_parachain_onboarding(&chain_a, READY_TIMEOUT).await;
+	chain_a.await_ready(READY_TIMEOUT).await.unwrap();
+	chain_b.await_ready(READY_TIMEOUT).await.unwrap();
 	log::info!(target: "hyperspace", "Parachains have started block production");
 
 	// We need to make difference between the chains' counters to ensure that
 	// proper values are used for source/sink client, connection, channel (etc.) ids.
 	chain_a.increase_counters().await.unwrap();
 
-	let clients_on_a = chain_a.query_clients().await.unwrap();
-	let clients_on_b = chain_b.query_clients().await.unwrap();
+	let client_a_on_b = chain_b.find_suitable_client(&chain_a).await.unwrap();
+	let client_b_on_a = chain_a.find_suitable_client(&chain_b).await.unwrap();
 
-	let (client_a, client_b) = if !clients_on_a.is_empty() && !clients_on_b.is_empty() {
-		(clients_on_b[0].clone(), clients_on_b[0].clone())
-	} else {
-		create_clients(&mut chain_a, &mut chain_b).await.unwrap()
+	let (client_a, client_b) = match (client_a_on_b, client_b_on_a) {
+		(Some(client_a), Some(client_b)) => (client_a, client_b),
+		_ => create_clients(&mut chain_a, &mut chain_b).await.unwrap(),
 	};
 
 	log::info!(target: "hyperspace_parachain", "Client IDs: {client_a}, {client_b}");
@@ -129,16 +77,26 @@ async fn setup_clients() -> (ParachainClient<DefaultConfig>, ParachainClient<Def
 	(chain_a, chain_b)
 }
 
+#[tokio::test]
+async fn parachain_client_catches_up_with_relay_chain_authority_sets() {
+	logging::setup_logging();
+	let (mut chain_a, mut chain_b) = setup_clients().await;
+	// clients of their own, which the relayers of the other tests don't keep updated
+	let (client_a, client_b) = create_clients(&mut chain_a, &mut chain_b).await.unwrap();
+	chain_a.set_client_id(client_a);
+	chain_b.set_client_id(client_b);
+	grandpa_client_catches_up_with_authority_sets(&mut chain_a, &mut chain_b, 2).await;
+}
+
 #[tokio::test]
 async fn parachain_to_parachain_ibc_messaging_full_integration_test() {
 	logging::setup_logging();
-	use hyperspace_testsuite::setup_connection_and_channel;
 	use ibc::core::ics24_host::identifier::PortId;
 	let (mut chain_a, mut chain_b) = setup_clients().await;
 	let mut chain_aa = chain_a.clone();
 	let mut chain_bb = chain_b.clone();
 	//set up connection only once!!!
-	let (handle, channel_a, channel_b, connection_id_a, connection_id_b) =
+	let (handle, channel_a, channel_b, connection_id_a, connection_id_b, _) =
 		setup_connection_and_channel(&mut chain_a, &mut chain_b, Duration::from_secs(60 * 2)).await;
 	handle.abort();
 
@@ -150,6 +108,7 @@ async fn parachain_to_parachain_ibc_messaging_full_integration_test() {
 	chain_b.set_channel_whitelist(vec![(channel_b, PortId::transfer())].into_iter().collect());
 
 	let asset_id = 1;
+	ensure_transfer_asset(&chain_a, asset_id).await;
 
 	let mut join_set = tokio::task::JoinSet::new();
 
@@ -162,6 +121,9 @@ async fn parachain_to_parachain_ibc_messaging_full_integration_test() {
 		)
 		.await;
 		log::info!(target: "hyperspace", "🚀🚀 finished connection delay");
+
+		ibc_messaging_with_memo(&mut c1, &mut c2, asset_id, channel_a).await;
+		log::info!(target: "hyperspace", "🚀🚀 finished memo");
 	});
 
 	// timeouts + connection delay
@@ -186,6 +148,14 @@ async fn parachain_to_parachain_ibc_messaging_full_integration_test() {
 		res.unwrap();
 	}
 
+	// packets sent while the relayer is down
+	ibc_messaging_after_downtime(&mut chain_a, &mut chain_b, asset_id, channel_a).await;
+	log::info!(target: "hyperspace", "🚀🚀 finished relayer downtime");
+
+	// channels opened while the relayer is running
+	ibc_messaging_on_auto_whitelisted_channel(&mut chain_a, &mut chain_b, asset_id).await;
+	log::info!(target: "hyperspace", "🚀🚀 finished auto-whitelisted channel");
+
 	// channel closing semantics
 	let mut join_set = tokio::task::JoinSet::new();
 	let mut c1 = chain_a.clone();
@@ -211,3 +181,109 @@ async fn parachain_to_parachain_ibc_messaging_full_integration_test() {
 	ibc_messaging_submit_misbehaviour(&mut chain_a, &mut chain_b).await;
 	log::info!(target: "hyperspace", "🚀🚀 Waiting for misbehaviour to be submitted");
 }
+
+#[tokio::test]
+async fn parachain_to_parachain_multiple_connections_test() {
+	logging::setup_logging();
+	let (mut chain_a, mut chain_b) = setup_clients().await;
+	let asset_id = 1;
+	ensure_transfer_asset(&chain_a, asset_id).await;
+
+	ibc_messaging_on_multiple_connections(&mut chain_a, &mut chain_b, asset_id, asset_id).await;
+}
+
+#[tokio::test]
+async fn parachain_to_parachain_mixed_assets_test() {
+	logging::setup_logging();
+	use ibc::core::ics24_host::identifier::PortId;
+	let (mut chain_a, mut chain_b) = setup_clients().await;
+	ensure_transfer_asset(&chain_a, 1).await;
+	ensure_transfer_asset(&chain_a, 2).await;
+	let (handle, channel_a, channel_b, connection_id_a, connection_id_b, _) =
+		setup_connection_and_channel(&mut chain_a, &mut chain_b, Duration::ZERO).await;
+	handle.abort();
+	chain_a.set_connection_id(connection_id_a);
+	chain_b.set_connection_id(connection_id_b);
+	chain_a.set_channel_whitelist(vec![(channel_a, PortId::transfer())].into_iter().collect());
+	chain_b.set_channel_whitelist(vec![(channel_b, PortId::transfer())].into_iter().collect());
+
+	let transfers = vec![
+		TestTransfer::new(1).with_amount(1_000_000),
+		TestTransfer::new(2).with_amount(2_000_000),
+	];
+	ibc_messaging_with_mixed_assets(&mut chain_a, &mut chain_b, transfers, channel_a, channel_b)
+		.await;
+}
+
+#[tokio::test]
+async fn parachain_to_parachain_raw_packet_test() {
+	logging::setup_logging();
+	use ibc::core::{ics04_channel::channel::Order, ics24_host::identifier::PortId};
+	use std::str::FromStr;
+	let (mut chain_a, mut chain_b) = setup_clients().await;
+	// the ping pallet sends packets with arbitrary data on its ordered channels
+	let data = vec![0, 159, 146, 150, 255];
+	ibc_messaging_raw_packet(
+		&mut chain_a,
+		&mut chain_b,
+		PortId::from_str("ping").unwrap(),
+		"ping-1".to_string(),
+		Order::Ordered,
+		data,
+	)
+	.await;
+}
+
+#[tokio::test]
+async fn parachain_to_parachain_ordered_ping_test() {
+	logging::setup_logging();
+	let (mut chain_a, mut chain_b) = setup_clients().await;
+	ordered_ping_messaging(&mut chain_a, &mut chain_b).await;
+}
+
+#[tokio::test]
+async fn parachain_submit_reports_failed_dispatch() {
+	logging::setup_logging();
+	let (chain_a, _) = setup_clients().await;
+	let asset_id = 1;
+	ensure_transfer_asset(&chain_a, asset_id).await;
+
+	// there's no channel to send the transfer on, so the extrinsic is included but fails
+	let params = TransferParams {
+		to: MultiAddress::Id(AccountId32::from([1; 32])),
+		source_channel: u64::MAX,
+		timeout: Timeout::Offset { timestamp: Some(60 * 60), height: None },
+	};
+	match chain_a.transfer_tokens(params, asset_id, 1, None).await {
+		Err(Error::ExtrinsicFailed(DispatchError::Module(e))) => {
+			log::info!(target: "hyperspace", "Transfer failed as expected: {e:?}");
+		},
+		other => panic!("Expected the dispatch of the transfer to fail, got {other:?}"),
+	}
+}
+
+#[tokio::test]
+async fn parachain_submit_reports_failed_ibc_message() {
+	logging::setup_logging();
+	let (chain_a, _) = setup_clients().await;
+
+	// the client doesn't exist, so the extrinsic succeeds but pallet-ibc rejects the message
+	let msg = MsgConnectionOpenInit {
+		client_id: ClientId::new("07-tendermint", u64::MAX).unwrap(),
+		counterparty: Counterparty::new(
+			ClientId::new("07-tendermint", 0).unwrap(),
+			None,
+			chain_a.connection_prefix(),
+		),
+		version: Some(Default::default()),
+		delay_period: Duration::ZERO,
+		signer: chain_a.account_id(),
+	};
+	let msg = Any { type_url: msg.type_url(), value: msg.encode_vec().unwrap() };
+	match chain_a.submit(vec![msg]).await {
+		Err(Error::IbcMessageFailed(e)) => {
+			log::info!(target: "hyperspace", "Message failed as expected: {e:?}");
+		},
+		other => panic!("Expected pallet-ibc to reject the message, got {other:?}"),
+	}
+}
@@ -0,0 +1,170 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The gRPC query server of the relayer, serving a path between mock chains.
+
+use core::time::Duration;
+use hyperspace_core::{
+	logging,
+	query_server::{
+		self,
+		proto::{
+			query_client::QueryClient, GetChainsRequest, GetClientInfoRequest,
+			GetPendingPacketsRequest, GetRelayStatsRequest,
+		},
+	},
+};
+use hyperspace_primitives::{utils::create_clients, IbcProvider, TestProvider};
+use hyperspace_testsuite::{
+	ensure_transfer_asset, ibc_messaging_with_connection_delay,
+	mock::{MockChain, MockChainConfig},
+	setup_connection_and_channel,
+};
+use ibc::{
+	applications::transfer::{msgs::transfer::MsgTransfer, Amount, PrefixedCoin, PrefixedDenom},
+	core::ics24_host::identifier::PortId,
+};
+use std::{net::TcpListener, str::FromStr};
+use tonic::{transport::Channel, Code};
+
+const ASSET: &str = "UNIT";
+
+/// Connects to the server, which may still be starting.
+async fn connect(endpoint: String) -> QueryClient<Channel> {
+	for _ in 0..50 {
+		match QueryClient::connect(endpoint.clone()).await {
+			Ok(client) => return client,
+			Err(_) => tokio::time::sleep(Duration::from_millis(100)).await,
+		}
+	}
+	panic!("Query server didn't start on {endpoint}")
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn query_server_answers_about_mock_chains() {
+	logging::setup_logging();
+	let (mut chain_a, mut chain_b) =
+		MockChain::pair(MockChainConfig::new("mock-a"), MockChainConfig::new("mock-b"));
+	let block_production = [
+		chain_a.start_block_production(Duration::from_millis(50)),
+		chain_b.start_block_production(Duration::from_millis(50)),
+	];
+	let (client_a, client_b) = create_clients(&mut chain_a, &mut chain_b).await.unwrap();
+	chain_a.set_client_id(client_a.clone());
+	chain_b.set_client_id(client_b);
+	ensure_transfer_asset(&chain_a, ASSET.to_string()).await;
+	ensure_transfer_asset(&chain_b, ASSET.to_string()).await;
+
+	let (handle, channel_a, channel_b, connection_id_a, connection_id_b, _) =
+		setup_connection_and_channel(&mut chain_a, &mut chain_b, Duration::from_secs(0)).await;
+	handle.abort();
+	chain_a.set_connection_id(connection_id_a.clone());
+	chain_b.set_connection_id(connection_id_b);
+	chain_a.set_channel_whitelist(vec![(channel_a, PortId::transfer())].into_iter().collect());
+	chain_b.set_channel_whitelist(vec![(channel_b, PortId::transfer())].into_iter().collect());
+
+	// the server shares the state of the chains with the relay loop
+	let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+	let server = tokio::spawn(query_server::serve(addr, chain_a.clone(), chain_b.clone(), None));
+	let mut client = connect(format!("http://{addr}")).await;
+
+	ibc_messaging_with_connection_delay(
+		&mut chain_a,
+		&mut chain_b,
+		ASSET.to_string(),
+		ASSET.to_string(),
+		channel_a,
+		channel_b,
+	)
+	.await;
+
+	let chains = client.get_chains(GetChainsRequest {}).await.unwrap().into_inner().chains;
+	assert_eq!(chains.len(), 2);
+	assert_eq!(chains[0].name, "mock-a");
+	assert_eq!(chains[0].client_id, client_a.to_string());
+	assert_eq!(chains[0].connection_ids, vec![connection_id_a.to_string()]);
+	assert_eq!(chains[0].channels.len(), 1);
+	assert_eq!(chains[0].channels[0].channel_id, channel_a.to_string());
+	assert_eq!(chains[1].name, "mock-b");
+	assert!(chains.iter().all(|chain| chain.latest_height.is_some()));
+
+	let info = client
+		.get_client_info(GetClientInfoRequest {
+			client_id: client_a.to_string(),
+			chain: String::new(),
+		})
+		.await
+		.unwrap()
+		.into_inner();
+	assert_eq!(info.chain, "mock-a");
+	assert_eq!(info.status, "Active");
+	let latest_height = info.latest_height.unwrap();
+	let observed_height = info.latest_observed_height.expect("No update of the client was seen");
+	assert!(observed_height.revision_height <= latest_height.revision_height);
+	let missing = client
+		.get_client_info(GetClientInfoRequest {
+			client_id: client_a.to_string(),
+			chain: "mock-b".to_string(),
+		})
+		.await
+		.unwrap_err();
+	assert_eq!(missing.code(), Code::NotFound);
+
+	let stats = client
+		.get_relay_stats(GetRelayStatsRequest {})
+		.await
+		.unwrap()
+		.into_inner()
+		.chains;
+	assert_eq!(stats.len(), 2);
+	for chain in &stats {
+		assert!(chain.received_packets >= 1, "No packet received on {}", chain.chain);
+		assert!(chain.acknowledgements >= 1, "No acknowledgement received on {}", chain.chain);
+		assert!(chain.client_updates >= 1, "No client updated on {}", chain.chain);
+		assert_ne!(chain.last_submission, 0);
+	}
+
+	// without the relay loop, a transfer stays pending
+	let (latest_height, timestamp) = chain_b.latest_height_and_timestamp().await.unwrap();
+	let msg = MsgTransfer {
+		source_port: PortId::transfer(),
+		source_channel: channel_a,
+		token: PrefixedCoin {
+			denom: PrefixedDenom::from_str(ASSET).unwrap(),
+			amount: Amount::from_str("1000").unwrap(),
+		},
+		sender: chain_a.account_id(),
+		receiver: chain_b.account_id(),
+		timeout_height: latest_height.add(1000),
+		timeout_timestamp: (timestamp + Duration::from_secs(60 * 60)).unwrap(),
+		memo: String::new(),
+	};
+	chain_a.send_transfer(msg).await.unwrap();
+	chain_a.produce_block();
+	let pending = client
+		.get_pending_packets(GetPendingPacketsRequest {
+			channel_id: channel_a.to_string(),
+			port_id: PortId::transfer().to_string(),
+			chain: String::new(),
+		})
+		.await
+		.unwrap()
+		.into_inner();
+	assert_eq!(pending.chain, "mock-a");
+	assert_eq!(pending.undelivered_packets.len(), 1);
+	assert!(pending.undelivered_acknowledgements.is_empty());
+
+	server.abort();
+	block_production.iter().for_each(|task| task.abort());
+}
@@ -0,0 +1,252 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::time::Duration;
+use hyperspace_core::{
+	chain::{AnyAssetId, AnyChain, AnyConfig},
+	logging,
+	substrate::DefaultConfig,
+};
+use hyperspace_cosmos::{
+	client::{CosmosClient, CosmosClientConfig, MnemonicEntry},
+	key_provider::KeyEntry,
+};
+use hyperspace_parachain::{
+	finality_protocol::FinalityProtocol, signer::KeystoreSigner, KeyType, ParachainClient,
+	ParachainClientConfig,
+};
+use hyperspace_primitives::{
+	signer::{serve_mock_remote_signer, RemoteSignerConfig, SignerConfig, TransactionSigner},
+	utils::{create_clients, ibc_denom_for},
+	Chain, CommonClientConfig, IbcProvider, KeyProvider,
+};
+use hyperspace_testsuite::{
+	await_parachain_onboarding, ensure_transfer_asset, ibc_messaging_with_connection_delay,
+	setup_connection_and_channel,
+};
+use ibc::core::ics24_host::identifier::PortId;
+use std::{collections::HashMap, sync::Arc};
+
+/// How long the chains may take to advance their height once they produce blocks.
+const READY_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Denom of the parachain's asset 1, transferred to the cosmos chain.
+const PARACHAIN_DENOM: &str = "UNIT";
+
+const PARACHAIN_KEY: &str = "//Alice";
+
+const COSMOS_MNEMONIC: &str =
+	"oxygen fall sure lava energy veteran enroll frown question detail include maximum";
+
+#[derive(Debug, Clone)]
+pub struct Args {
+	pub chain_a: String,
+	pub chain_b: String,
+	pub relay_chain: String,
+	pub para_id: u32,
+	pub cosmos_grpc: String,
+	pub cosmos_ws: String,
+	pub wasm_path: String,
+}
+
+impl Default for Args {
+	fn default() -> Self {
+		let relay = std::env::var("RELAY_HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
+		let para = std::env::var("PARA_HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
+		let cosmos = std::env::var("COSMOS_HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
+		let wasm_path = std::env::var("WASM_PATH").unwrap_or_else(|_| {
+			"../../target/wasm32-unknown-unknown/release/ics10_grandpa_cw.wasm".to_string()
+		});
+
+		Args {
+			chain_a: format!("ws://{para}:9188"),
+			chain_b: format!("http://{cosmos}:26657"),
+			relay_chain: format!("ws://{relay}:9944"),
+			para_id: 2000,
+			cosmos_grpc: format!("http://{cosmos}:9090"),
+			cosmos_ws: format!("ws://{cosmos}:26657/websocket"),
+			wasm_path,
+		}
+	}
+}
+
+/// Serves the keys of the dev accounts of both chains from a mock remote signer.
+async fn serve_dev_keys() -> (String, tokio::task::JoinHandle<()>) {
+	let parachain_key: Arc<dyn TransactionSigner> =
+		Arc::new(KeystoreSigner::new(&KeyType::Sr25519, PARACHAIN_KEY).unwrap());
+	let cosmos_key: Arc<dyn TransactionSigner> = Arc::new(
+		KeyEntry::try_from(MnemonicEntry {
+			mnemonic: COSMOS_MNEMONIC.to_string(),
+			prefix: "cosmos".to_string(),
+		})
+		.unwrap(),
+	);
+	let keys = HashMap::from([
+		("parachain".to_string(), parachain_key),
+		("cosmos".to_string(), cosmos_key),
+	]);
+	serve_mock_remote_signer(keys, None).await.unwrap()
+}
+
+fn remote_signer(url: &str, key_id: &str) -> SignerConfig {
+	SignerConfig::Remote(RemoteSignerConfig {
+		url: url.to_string(),
+		key_id: key_id.to_string(),
+		auth_token: None,
+	})
+}
+
+async fn setup_clients(signer_url: &str) -> (AnyChain, AnyChain) {
+	log::info!(target: "hyperspace", "=========================== Starting Test ===========================");
+	let args = Args::default();
+
+	// neither config holds a key
+	let config_a = ParachainClientConfig {
+		name: "parachain".to_string(),
+		para_id: args.para_id,
+		parachain_rpc_url: args.chain_a,
+		relay_chain_rpc_url: args.relay_chain.clone(),
+		client_id: None,
+		connection_id: None,
+		connections: vec![],
+		commitment_prefix: b"ibc/".to_vec().into(),
+		ss58_version: 42,
+		channel_whitelist: vec![],
+		finality_protocol: FinalityProtocol::Grandpa,
+		private_key: String::new(),
+		signer: remote_signer(signer_url, "parachain"),
+		key_type: "sr25519".to_string(),
+		wasm_code_id: None,
+		finality_proof_cache_bucket_size: None,
+		metadata_mismatch: Default::default(),
+		max_update_size: None,
+		signature_verification_threads: 0,
+		common: Default::default(),
+	};
+
+	let mut config_b = CosmosClientConfig {
+		name: "cosmos".to_string(),
+		rpc_url: args.chain_b.clone().parse().unwrap(),
+		grpc_url: args.cosmos_grpc.clone().parse().unwrap(),
+		rest_url: None,
+		websocket_url: args.cosmos_ws.clone().parse().unwrap(),
+		chain_id: "ibcgo-1".to_string(),
+		client_id: None,
+		connection_id: None,
+		connections: vec![],
+		account_prefix: "cosmos".to_string(),
+		fee_denom: "stake".to_string(),
+		fee_amount: "4000".to_string(),
+		gas_limit: (i64::MAX - 1) as u64,
+		gas_price: None,
+		fallback_fees: vec![],
+		store_prefix: "ibc".to_string(),
+		max_tx_size: 200000,
+		mnemonic: String::new(),
+		signer: remote_signer(signer_url, "cosmos"),
+		wasm_code_id: None,
+		latest_height_max_staleness_ms: 10_000,
+		channel_whitelist: vec![],
+		common: CommonClientConfig {
+			skip_optional_client_updates: true,
+			max_packets_to_process: 200,
+			max_submit_retries: 5,
+			strict_middleware_check: false,
+			simulate_messages: false,
+			skip_invalid_wasm_messages: false,
+			stop_packets_on_inactive_client: false,
+			packet_filters: vec![],
+			query_cache_capacity: 1024,
+			timeout_safety_margin_blocks: 2,
+			auto_whitelist_channels: false,
+			fee_payee_address: None,
+			min_balance: None,
+			stall_factor: 20,
+			stall_alert_command: None,
+			proof_query_concurrency: None,
+			max_pending_messages: 1000,
+			pre_validate_updates: false,
+		},
+		skip_tokens_list: None,
+		packet_sender_contract: None,
+	};
+
+	// uploading the wasm client is the first cosmos transaction signed remotely
+	let chain_b = CosmosClient::<DefaultConfig>::new(config_b.clone()).await.unwrap();
+	let wasm_data = tokio::fs::read(&args.wasm_path).await.expect("Failed to read wasm file");
+	let (code_id, _) = chain_b.upload_wasm(wasm_data).await.expect("Failed to upload wasm");
+	config_b.wasm_code_id = Some(hex::encode(code_id));
+
+	let mut chain_a_wrapped =
+		AnyChain::Parachain(ParachainClient::<DefaultConfig>::new(config_a).await.unwrap());
+	let mut chain_b_wrapped = AnyConfig::Cosmos(config_b).into_live_client().await.unwrap();
+
+	let AnyChain::Parachain(chain_a) = &mut chain_a_wrapped else { unreachable!() };
+
+	log::info!(target: "hyperspace", "Waiting for block production from parachain");
+	await_parachain_onboarding(&chain_a, READY_TIMEOUT).await;
+	chain_a_wrapped.await_ready(READY_TIMEOUT).await.unwrap();
+	log::info!(target: "hyperspace", "Parachain have started block production");
+
+	let (client_b, client_a) =
+		create_clients(&mut chain_b_wrapped, &mut chain_a_wrapped).await.unwrap();
+	chain_a_wrapped.set_client_id(client_a);
+	chain_b_wrapped.set_client_id(client_b);
+	(chain_a_wrapped, chain_b_wrapped)
+}
+
+#[tokio::test]
+#[ignore]
+async fn parachain_to_cosmos_remote_signer_integration_test() {
+	logging::setup_logging();
+
+	let (signer_url, signer_handle) = serve_dev_keys().await;
+	let asset_id_a = AnyAssetId::Parachain(1);
+	let (mut chain_a, mut chain_b) = setup_clients(&signer_url).await;
+
+	// the relayer accounts are those of the keys held by the remote signer
+	assert_eq!(
+		chain_a.account_id().to_string(),
+		"5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY"
+	);
+	let cosmos_key = KeyEntry::try_from(MnemonicEntry {
+		mnemonic: COSMOS_MNEMONIC.to_string(),
+		prefix: "cosmos".to_string(),
+	})
+	.unwrap();
+	assert_eq!(chain_b.account_id().to_string(), cosmos_key.account);
+
+	ensure_transfer_asset(&chain_a, asset_id_a.clone()).await;
+	let (handle, channel_a, channel_b, connection_id_a, connection_id_b, _) =
+		setup_connection_and_channel(&mut chain_a, &mut chain_b, Duration::from_secs(60 * 2)).await;
+	handle.abort();
+	let asset_id_b =
+		AnyAssetId::Cosmos(ibc_denom_for(&PortId::transfer(), &channel_b, PARACHAIN_DENOM));
+
+	chain_a.set_connection_id(connection_id_a);
+	chain_b.set_connection_id(connection_id_b);
+	chain_a.set_channel_whitelist(vec![(channel_a, PortId::transfer())].into_iter().collect());
+	chain_b.set_channel_whitelist(vec![(channel_b, PortId::transfer())].into_iter().collect());
+
+	ibc_messaging_with_connection_delay(
+		&mut chain_a,
+		&mut chain_b,
+		asset_id_a,
+		asset_id_b,
+		channel_a,
+		channel_b,
+	)
+	.await;
+	signer_handle.abort();
+}
@@ -0,0 +1,117 @@
+// Copyright 2022 ComposableFi
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Replays the recorded fixture of a token transfer between the dev parachains through the relay
+//! loop, without reaching the parachains. The fixture is recorded by running the test against the
+//! dev parachains with `HYPERSPACE_RECORD` set to the fixture directory.
+
+use hyperspace_core::{logging, substrate::DefaultConfig};
+use hyperspace_parachain::{ParachainClient, ParachainClientConfig};
+use hyperspace_primitives::{
+	replay::{RecordMode, REPLAY_DIR_ENV},
+	utils::create_clients,
+	IbcProvider,
+};
+use hyperspace_testsuite::{
+	dev_parachains::DevParachains, ensure_transfer_asset, ibc_messaging_with_connection_delay,
+	setup_connection_and_channel,
+};
+use ibc::core::ics24_host::identifier::PortId;
+use light_client_common::config::Config;
+use sp_core::{ed25519, sr25519, Pair};
+use std::time::Duration;
+use subxt::{dynamic::Value, tx::PairSigner};
+
+/// Directory of the recorded fixture.
+const FIXTURE_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/parachain-transfer");
+
+/// Asset transferred, which the other parachain suites don't use so that the relayer owns it.
+const ASSET_ID: u128 = 3;
+
+/// Funds the ed25519 `//Alice` account with the sr25519 `//Alice` one, over a connection that
+/// isn't recorded.
+async fn fund_relayer(url: &str) {
+	let client = subxt::OnlineClient::<DefaultConfig>::from_url(url).await.unwrap();
+	let relayer = ed25519::Pair::from_string("//Alice", None).unwrap().public();
+	let transfer = subxt::dynamic::tx(
+		"Balances",
+		"transfer",
+		vec![
+			Value::unnamed_variant("Id", [Value::from_bytes(relayer.0)]),
+			Value::u128(10u128.pow(18)),
+		],
+	);
+	let alice = sr25519::Pair::from_string("//Alice", None).unwrap();
+	let params = DefaultConfig::custom_extrinsic_params(&client).await.unwrap();
+	client
+		.tx()
+		.sign_and_submit_then_watch(&transfer, &PairSigner::<DefaultConfig, _>::new(alice), params)
+		.await
+		.unwrap()
+		.wait_for_finalized_success()
+		.await
+		.unwrap();
+}
+
+/// Config of a dev parachain relayed with the ed25519 `//Alice` key, whose signatures are
+/// deterministic so that the replayed extrinsics are the recorded ones.
+fn relayer_config(mut config: ParachainClientConfig) -> ParachainClientConfig {
+	config.key_type = "ed25519".to_string();
+	config
+}
+
+#[tokio::test]
+#[ignore = "replays fixtures/parachain-transfer, which is recorded against the dev parachains"]
+async fn recorded_token_transfer_is_replayed_through_the_relay_loop() {
+	logging::setup_logging();
+	let mode = RecordMode::from_env().unwrap();
+	if mode.is_none() {
+		std::env::set_var(REPLAY_DIR_ENV, FIXTURE_DIR);
+	}
+	let chains = DevParachains::default();
+	if matches!(mode, Some(RecordMode::Record(_))) {
+		fund_relayer(&chains.chain_a).await;
+		fund_relayer(&chains.chain_b).await;
+	}
+
+	let mut chain_a = ParachainClient::<DefaultConfig>::new(relayer_config(chains.config_a()))
+		.await
+		.unwrap();
+	let mut chain_b = ParachainClient::<DefaultConfig>::new(relayer_config(chains.config_b()))
+		.await
+		.unwrap();
+	let (client_a, client_b) = create_clients(&mut chain_a, &mut chain_b).await.unwrap();
+	chain_a.set_client_id(client_a);
+	chain_b.set_client_id(client_b);
+
+	let (handle, channel_a, channel_b, connection_a, connection_b, _) =
+		setup_connection_and_channel(&mut chain_a, &mut chain_b, Duration::from_secs(60 * 2)).await;
+	handle.abort();
+	chain_a.set_connection_id(connection_a);
+	chain_b.set_connection_id(connection_b);
+	chain_a.set_channel_whitelist(vec![(channel_a, PortId::transfer())].into_iter().collect());
+	chain_b.set_channel_whitelist(vec![(channel_b, PortId::transfer())].into_iter().collect());
+
+	ensure_transfer_asset(&chain_a, ASSET_ID).await;
+	ibc_messaging_with_connection_delay(
+		&mut chain_a,
+		&mut chain_b,
+		ASSET_ID,
+		ASSET_ID,
+		channel_a,
+		channel_b,
+	)
+	.await;
+	log::info!(target: "hyperspace", "🚀🚀 Token transfer replayed");
+}
@@ -95,6 +95,27 @@ impl State {
 		}
 	}
 
+	pub fn impl_type_urls(&self) -> proc_macro2::TokenStream {
+		let this = &self.self_ident;
+		let (impl_generics, ty_generics, where_clause) = self.generics.split_for_impl();
+
+		let type_urls = self.clients.iter().filter_map(|client| {
+			let attrs = &client.attrs;
+			let type_url = client.proto_ty_url.as_ref()?;
+			Some(quote! {
+				#(#attrs)*
+				#type_url,
+			})
+		});
+
+		quote! {
+			impl #impl_generics #this #ty_generics #where_clause {
+				/// Type urls of the variants, as decoded from an `Any`.
+				pub const TYPE_URLS: &'static [&'static str] = &[#(#type_urls)*];
+			}
+		}
+	}
+
 	pub fn impl_protobuf(&self) -> proc_macro2::TokenStream {
 		let crate_ = &self.crate_ident;
 		let this = &self.self_ident;
@@ -102,6 +123,7 @@ impl State {
 
 		let impl_try_from_any = self.impl_try_from_any();
 		let impl_from_self_for_any = self.impl_from_self_for_any();
+		let impl_type_urls = self.impl_type_urls();
 
 		let ibc_proto =
 			generate_crate_access_2018("ibc-proto").expect("'ibc-proto' crate not found");
@@ -115,6 +137,8 @@ impl State {
 			#impl_try_from_any
 
 			#impl_from_self_for_any
+
+			#impl_type_urls
 		}
 	}
 }
@@ -42,6 +42,27 @@ use ibc_proto::ibc::core::{
 };
 use tendermint_proto::Protobuf;
 
+/// Type urls of every message [`Ics26Envelope`] decodes.
+pub const TYPE_URLS: &[&str] = &[
+	create_client::TYPE_URL,
+	update_client::TYPE_URL,
+	upgrade_client::TYPE_URL,
+	conn_open_init::TYPE_URL,
+	conn_open_try::TYPE_URL,
+	conn_open_ack::TYPE_URL,
+	conn_open_confirm::TYPE_URL,
+	chan_open_init::TYPE_URL,
+	chan_open_try::TYPE_URL,
+	chan_open_ack::TYPE_URL,
+	chan_open_confirm::TYPE_URL,
+	chan_close_init::TYPE_URL,
+	chan_close_confirm::TYPE_URL,
+	recv_packet::TYPE_URL,
+	acknowledgement::TYPE_URL,
+	timeout::TYPE_URL,
+	timeout_on_close::TYPE_URL,
+];
+
 /// Enumeration of all messages that the local ICS26 module is capable of routing.
 #[derive(Clone, Debug)]
 pub enum Ics26Envelope<C>
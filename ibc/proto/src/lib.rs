@@ -85,6 +85,11 @@ pub mod cosmos {
 				include_proto!("cosmos.base.kv.v1beta1.rs");
 			}
 		}
+		pub mod node {
+			pub mod v1beta1 {
+				include_proto!("cosmos.base.node.v1beta1.rs");
+			}
+		}
 		pub mod query {
 			pub mod v1beta1 {
 				include_proto!("cosmos.base.query.v1beta1.rs");
@@ -157,6 +162,11 @@ pub mod ibc {
 				include_proto!("ibc.applications.transfer.v2.rs");
 			}
 		}
+		pub mod fee {
+			pub mod v1 {
+				include_proto!("ibc.applications.fee.v1.rs");
+			}
+		}
 		pub mod interchain_accounts {
 			pub mod v1 {
 				include_proto!("ibc.applications.interchain_accounts.v1.rs");
@@ -14,6 +14,27 @@ pub struct MsgPushNewWasmCodeResponse {
 	#[prost(bytes = "vec", tag = "1")]
 	pub code_id: ::prost::alloc::vec::Vec<u8>,
 }
+/// Message type to migrate a wasm client to new code
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct MsgMigrateContract {
+	/// signer address, the authority of the module unless migration is permissionless
+	#[prost(string, tag = "1")]
+	pub signer: ::prost::alloc::string::String,
+	/// the client id of the contract
+	#[prost(string, tag = "2")]
+	pub client_id: ::prost::alloc::string::String,
+	/// checksum of the new code the contract is migrated to
+	#[prost(bytes = "vec", tag = "3")]
+	pub checksum: ::prost::alloc::vec::Vec<u8>,
+	/// json encoded message passed to the migrate entry point of the new code
+	#[prost(bytes = "vec", tag = "4")]
+	pub msg: ::prost::alloc::vec::Vec<u8>,
+}
+/// Response in case of successful handling
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct MsgMigrateContractResponse {}
 /// Generated client implementations.
 #[cfg(feature = "client")]
 pub mod msg_client {
@@ -16,12 +16,16 @@
 use alloc::borrow::Cow;
 use async_trait::async_trait;
 use codec::{Decode, Encode};
+use core::marker::PhantomData;
 use ibc::events::IbcEvent;
 use ibc_proto::google::protobuf::Any;
-use sp_core::H256;
+use sp_core::{crypto::AccountId32, H256};
 use subxt::{
 	client::OnlineClient,
-	config::ExtrinsicParams,
+	config::{
+		extrinsic_params::{BaseExtrinsicParams, BaseExtrinsicParamsBuilder, Era},
+		ExtrinsicParams,
+	},
 	error::{Error, MetadataError, StorageAddressError},
 	events::{Phase, StaticEvent},
 	ext::{
@@ -199,6 +203,7 @@ pub trait RuntimeTransactions {
 	type Transfer: Encode + EncodeAsFields + Send + Sync;
 	type Sudo: Encode + EncodeAsFields + Send + Sync;
 	type SendPing: Encode + EncodeAsFields + Send + Sync;
+	type AssetsMint: Encode + EncodeAsFields + Send + Sync;
 	type ParaRuntimeCall;
 
 	type SendPingParams;
@@ -215,6 +220,17 @@ pub trait RuntimeTransactions {
 	fn sudo_sudo(call: Self::ParaRuntimeCall) -> Payload<Self::Sudo>;
 	fn ibc_ping_send_ping(params: Self::SendPingParams) -> Payload<Self::SendPing>;
 	fn ibc_increase_counters() -> Self::ParaRuntimeCall;
+	/// Converts the memo of an ICS20 transfer, returning `None` if the runtime can't carry it, in
+	/// which case the transfer is rejected rather than sent without its memo.
+	fn memo(memo: String) -> Option<Self::MemoMessage>;
+	/// Creates the asset `asset_id` owned by `owner`, to be dispatched with [`Self::sudo_sudo`].
+	fn assets_force_create(asset_id: u128, owner: AccountId32) -> Self::ParaRuntimeCall;
+	/// Mints `amount` of `asset_id` to `beneficiary`, signed by the owner of the asset.
+	fn assets_mint(
+		asset_id: u128,
+		beneficiary: AccountId32,
+		amount: u128,
+	) -> Payload<Self::AssetsMint>;
 }
 
 pub trait BeefyAuthoritySetT {
@@ -283,8 +299,15 @@ pub trait EventRecordT {
 
 pub trait IbcEventsT {
 	type IbcEvent: TryInto<IbcEvent>;
+	/// Error recorded by pallet-ibc in place of the events of a message it failed to handle
+	type IbcError: Encode;
 
+	/// The events of the messages handled successfully
 	fn events(self) -> Vec<Self::IbcEvent>;
+
+	/// The events of the messages along with the errors of the ones that failed, which don't fail
+	/// the dispatch of the extrinsic
+	fn results(self) -> Vec<Result<Self::IbcEvent, Self::IbcError>>;
 }
 
 /// This allows end users of this crate return the correct extrinsic metadata required by their
@@ -324,3 +347,88 @@ pub type CustomExtrinsicParams<T> = <<T as subxt::Config>::ExtrinsicParams as Ex
 	<T as subxt::Config>::Index,
 	<T as subxt::Config>::Hash,
 >>::OtherParams;
+
+/// The kind of extrinsic params of a runtime, to select them with a type parameter of its
+/// [`Config`] rather than defining a config per kind.
+pub trait ExtrinsicParamsKind: core::fmt::Debug + Clone + Send + Sync + 'static {
+	/// Tip of the transactions
+	type Tip: core::fmt::Debug + Encode + Default + From<u128> + Send + Sync + 'static;
+	/// Tip in the signed extra of the transactions
+	type SignedTip: Decode;
+}
+
+/// The extrinsic params of the Polkadot runtimes, with a plain tip.
+#[derive(Debug, Clone)]
+pub enum PolkadotParams {}
+
+impl ExtrinsicParamsKind for PolkadotParams {
+	type Tip = subxt::config::polkadot::PlainTip;
+	type SignedTip = codec::Compact<u128>;
+}
+
+/// The extrinsic params of the Substrate runtimes, whose tips may be paid in an asset.
+#[derive(Debug, Clone)]
+pub enum SubstrateParams {}
+
+impl ExtrinsicParamsKind for SubstrateParams {
+	type Tip = subxt::config::substrate::AssetTip;
+	type SignedTip = (codec::Compact<u128>, Option<u32>);
+}
+
+/// Types generated from the metadata of a parachain runtime, which [`DefaultConfig`] relays with.
+pub trait ParachainRuntime: core::fmt::Debug + Clone + Send + Sync + 'static {
+	/// Runtime call
+	type ParaRuntimeCall: RuntimeCall + Decode + Send;
+	/// Parachain runtime event
+	type ParaRuntimeEvent: AsInner;
+	/// Parachain events. Used for subscriptions
+	type Events: IbcEventsT + AsInnerEvent;
+	/// The event is returned from the subscription
+	type EventRecord: Decode + EventRecordT + Send;
+	/// Runtime storage
+	type Storage: RuntimeStorage;
+	/// Relay/para-chain transactions
+	type Tx: RuntimeTransactions<ParaRuntimeCall = Self::ParaRuntimeCall>;
+	/// Nonce in the signed extra of the transactions
+	type CheckNonce: Decode;
+}
+
+/// Config of a parachain with the runtime `R`, whose extrinsic params are of the kind `P`, the
+/// Polkadot ones unless specified.
+#[derive(Debug, Clone)]
+pub struct DefaultConfig<R, P = PolkadotParams>(PhantomData<(R, P)>);
+
+#[async_trait]
+impl<R: ParachainRuntime, P: ExtrinsicParamsKind> Config for DefaultConfig<R, P> {
+	type AssetId = u128;
+	type Signature = <Self as subxt::Config>::Signature;
+	type Address = <Self as subxt::Config>::Address;
+	type Tip = P::Tip;
+	type ParaRuntimeCall = R::ParaRuntimeCall;
+	type ParaRuntimeEvent = R::ParaRuntimeEvent;
+	type Events = R::Events;
+	type EventRecord = R::EventRecord;
+	type Storage = R::Storage;
+	type Tx = R::Tx;
+	type SignedExtra = (Era, R::CheckNonce, P::SignedTip);
+
+	async fn custom_extrinsic_params(
+		client: &OnlineClient<Self>,
+	) -> Result<CustomExtrinsicParams<Self>, Error> {
+		let params = BaseExtrinsicParamsBuilder::<Self, P::Tip>::new()
+			.era(Era::Immortal, client.genesis_hash());
+		Ok(params)
+	}
+}
+
+impl<R: ParachainRuntime, P: ExtrinsicParamsKind> subxt::Config for DefaultConfig<R, P> {
+	type Index = u32;
+	type Hash = H256;
+	type Hasher = subxt::config::substrate::BlakeTwo256;
+	type AccountId = AccountId32;
+	type Address = sp_runtime::MultiAddress<Self::AccountId, u32>;
+	type Header =
+		subxt::config::substrate::SubstrateHeader<u32, subxt::config::substrate::BlakeTwo256>;
+	type Signature = sp_runtime::MultiSignature;
+	type ExtrinsicParams = BaseExtrinsicParams<Self, P::Tip>;
+}
@@ -21,9 +21,14 @@
 extern crate alloc;
 extern crate core;
 
-use alloc::{string::ToString, vec, vec::Vec};
+use alloc::{
+	format,
+	string::{String, ToString},
+	vec,
+	vec::Vec,
+};
 use anyhow::anyhow;
-use codec::Compact;
+use codec::{Compact, Decode, Encode};
 use core::{
 	fmt,
 	fmt::{Debug, Display, Formatter},
@@ -39,6 +44,7 @@ use ibc::{
 	},
 	Height,
 };
+use scale_info::TypeInfo;
 use serde::{Deserialize, Serialize};
 use sp_core::H256;
 use sp_storage::ChildInfo;
@@ -54,6 +60,33 @@ pub trait HostFunctions: Clone + Send + Sync + Eq + Debug + Default {
 	type BlakeTwo256: hash_db::Hasher<Out = H256> + Debug + 'static;
 }
 
+/// Reasons for rejecting a membership or non-membership proof of the child trie.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProofError {
+	/// The commitment root isn't a 32 byte hash.
+	InvalidRootLength(usize),
+	/// The proof isn't a SCALE encoded list of trie nodes.
+	Decode { path: String, error: String },
+	/// The proof doesn't prove the expected value for the path.
+	Verification { path: String, error: String },
+}
+
+impl Display for ProofError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			ProofError::InvalidRootLength(len) =>
+				write!(f, "invalid commitment root length: {len}"),
+			ProofError::Decode { path, error } =>
+				write!(f, "Failed to decode proof nodes for path: {path}: {error}"),
+			ProofError::Verification { path, error } =>
+				write!(f, "Failed to verify proof for path: {path}, error: {error}"),
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ProofError {}
+
 /// Membership proof verification via child trie host function
 pub fn verify_membership<H, P>(
 	prefix: &CommitmentPrefix,
@@ -61,31 +94,12 @@ pub fn verify_membership<H, P>(
 	root: &CommitmentRoot,
 	path: P,
 	value: Vec<u8>,
-) -> Result<(), anyhow::Error>
+) -> Result<(), ProofError>
 where
 	P: Into<Path>,
 	H: hash_db::Hasher<Out = H256> + Debug + 'static,
 {
-	if root.as_bytes().len() != 32 {
-		return Err(anyhow!("invalid commitment root length: {}", root.as_bytes().len()))
-	}
-	let path: Path = path.into();
-	let path = path.to_string();
-	let mut key = prefix.as_bytes().to_vec();
-	key.extend(path.as_bytes());
-	let trie_proof: Vec<Vec<u8>> = codec::Decode::decode(&mut &*proof.as_bytes())
-		.map_err(|err| anyhow!("Failed to decode proof nodes for path: {path}: {err:#?}"))?;
-	let proof = StorageProof::new(trie_proof);
-	let root = H256::from_slice(root.as_bytes());
-	let child_info = ChildInfo::new_default(prefix.as_bytes());
-	state_machine::read_child_proof_check::<H, _>(
-		root.into(),
-		proof,
-		child_info,
-		vec![(key, Some(value))],
-	)
-	.map_err(|err| anyhow!("Failed to verify proof for path: {path}, error: {err:#?}"))?;
-	Ok(())
+	verify_child_trie_proof::<H, P>(prefix, proof, root, path, Some(value))
 }
 
 /// Non-membership proof verification via child trie host function
@@ -94,29 +108,96 @@ pub fn verify_non_membership<H, P>(
 	proof: &CommitmentProofBytes,
 	root: &CommitmentRoot,
 	path: P,
-) -> Result<(), anyhow::Error>
+) -> Result<(), ProofError>
+where
+	P: Into<Path>,
+	H: hash_db::Hasher<Out = H256> + Debug + 'static,
+{
+	verify_child_trie_proof::<H, P>(prefix, proof, root, path, None)
+}
+
+/// Membership proof verification of several paths against a single proof, whose nodes are
+/// decoded and assembled into a trie once for all the paths. Fails with the first path whose
+/// value isn't proven.
+pub fn verify_membership_batch<H>(
+	prefix: &CommitmentPrefix,
+	proof: &CommitmentProofBytes,
+	root: &CommitmentRoot,
+	items: &[(Path, Vec<u8>)],
+) -> Result<(), ProofError>
+where
+	H: hash_db::Hasher<Out = H256> + Debug + 'static,
+{
+	let items = items
+		.iter()
+		.map(|(path, value)| (path.to_string(), Some(value.clone())))
+		.collect();
+	verify_child_trie_proof_batch::<H>(prefix, proof, root, items)
+}
+
+/// Non-membership proof verification of several paths against a single proof, see
+/// [`verify_membership_batch`].
+pub fn verify_non_membership_batch<H>(
+	prefix: &CommitmentPrefix,
+	proof: &CommitmentProofBytes,
+	root: &CommitmentRoot,
+	paths: &[Path],
+) -> Result<(), ProofError>
+where
+	H: hash_db::Hasher<Out = H256> + Debug + 'static,
+{
+	let items = paths.iter().map(|path| (path.to_string(), None)).collect();
+	verify_child_trie_proof_batch::<H>(prefix, proof, root, items)
+}
+
+fn verify_child_trie_proof<H, P>(
+	prefix: &CommitmentPrefix,
+	proof: &CommitmentProofBytes,
+	root: &CommitmentRoot,
+	path: P,
+	value: Option<Vec<u8>>,
+) -> Result<(), ProofError>
 where
 	P: Into<Path>,
 	H: hash_db::Hasher<Out = H256> + Debug + 'static,
+{
+	let path: Path = path.into();
+	verify_child_trie_proof_batch::<H>(prefix, proof, root, vec![(path.to_string(), value)])
+}
+
+fn verify_child_trie_proof_batch<H>(
+	prefix: &CommitmentPrefix,
+	proof: &CommitmentProofBytes,
+	root: &CommitmentRoot,
+	items: Vec<(String, Option<Vec<u8>>)>,
+) -> Result<(), ProofError>
+where
+	H: hash_db::Hasher<Out = H256> + Debug + 'static,
 {
 	if root.as_bytes().len() != 32 {
-		return Err(anyhow!("invalid commitment root length: {}", root.as_bytes().len()))
+		return Err(ProofError::InvalidRootLength(root.as_bytes().len()))
 	}
-	let path: Path = path.into();
-	let path = path.to_string();
-	let mut key = prefix.as_bytes().to_vec();
-	key.extend(path.as_bytes());
-	let trie_proof: Vec<Vec<u8>> =
-		codec::Decode::decode(&mut &*proof.as_bytes()).map_err(anyhow::Error::msg)?;
+	// errors that aren't specific to an item are reported for all of them
+	let all_paths = || items.iter().map(|(path, _)| path.as_str()).collect::<Vec<_>>().join(", ");
+	let trie_proof: Vec<Vec<u8>> = codec::Decode::decode(&mut &*proof.as_bytes())
+		.map_err(|err| ProofError::Decode { path: all_paths(), error: format!("{err:#?}") })?;
 	let proof = StorageProof::new(trie_proof);
 	let root = H256::from_slice(root.as_bytes());
 	let child_info = ChildInfo::new_default(prefix.as_bytes());
-	state_machine::read_child_proof_check::<H, _>(root, proof, child_info, vec![(key, None)])
-		.map_err(anyhow::Error::msg)?;
-	Ok(())
+	let keys = items.iter().map(|(path, value)| {
+		let mut key = prefix.as_bytes().to_vec();
+		key.extend(path.as_bytes());
+		(key, value.clone())
+	});
+	state_machine::read_child_proof_check_items::<H, _>(root, proof, child_info, keys).map_err(
+		|(index, err)| ProofError::Verification {
+			path: index.map_or_else(all_paths, |index| items[index].0.clone()),
+			error: format!("{err:#?}"),
+		},
+	)
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize, Encode, Decode, TypeInfo)]
 pub enum RelayChain {
 	Polkadot = 0,
 	Kusama = 1,
@@ -188,15 +269,88 @@ impl FromStr for RelayChain {
 	}
 }
 
-/// Attempt to extract the timestamp extrinsic from the parachain header
+/// Index of `set`, the only call of the timestamp pallet.
+pub const TIMESTAMP_SET_CALL_INDEX: u8 = 0;
+
+/// Upper bound (exclusive) on the index of the timestamp inherent among the extrinsics of a
+/// parachain block. Only accepted above 0 if the extrinsic is known to call the timestamp pallet.
+pub const MAX_TIMESTAMP_INHERENT_INDEX: u32 = 8;
+
+/// Identifies the `Timestamp::set` inherent among the extrinsics of a parachain block. Runtimes
+/// order their inherents differently, so the inherent is recognized by its call rather than its
+/// position in the block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimestampCall {
+	/// Index of the timestamp pallet in the runtime
+	pub pallet_index: u8,
+	/// Index of the `set` call in the timestamp pallet
+	pub call_index: u8,
+}
+
+impl TimestampCall {
+	/// The `set` call of the timestamp pallet at `pallet_index`.
+	pub fn new(pallet_index: u8) -> Self {
+		Self { pallet_index, call_index: TIMESTAMP_SET_CALL_INDEX }
+	}
+
+	/// Decodes the timestamp set by the unsigned extrinsic `ext`, which must call this inherent.
+	pub fn decode(&self, ext: &[u8]) -> Result<u64, anyhow::Error> {
+		let (pallet_index, call_index, timestamp) = decode_unsigned_call(ext)?;
+		if pallet_index != self.pallet_index || call_index != self.call_index {
+			Err(anyhow!(
+				"Extrinsic calls {pallet_index}:{call_index} instead of the timestamp inherent"
+			))?
+		}
+		Ok(timestamp)
+	}
+
+	/// Returns the index of the first of the `extrinsics` calling this inherent, along with the
+	/// timestamp it sets.
+	pub fn find(&self, extrinsics: &[Vec<u8>]) -> Result<(usize, u64), anyhow::Error> {
+		extrinsics
+			.iter()
+			.enumerate()
+			.find_map(|(index, ext)| Some((index, self.decode(ext).ok()?)))
+			.ok_or_else(|| anyhow!("No timestamp inherent found among the extrinsics"))
+	}
+}
+
+/// Decodes the pallet and call indices of the unsigned extrinsic `ext`, along with the compact
+/// integer its call starts with.
+fn decode_unsigned_call(ext: &[u8]) -> Result<(u8, u8, u64), anyhow::Error> {
+	// The timestamp inherent is an unsigned extrinsic, encoded as its length, the extrinsic
+	// version and the call. It doesn't have to be the first extrinsic of the block, as long as
+	// the extrinsic proof is checked against its actual index.
+	let input = &mut &ext[..];
+	let _len = Compact::<u32>::decode(input)
+		.map_err(|err| anyhow!("Failed to decode extrinsic length: {err}"))?;
+	let version =
+		u8::decode(input).map_err(|err| anyhow!("Failed to decode extrinsic version: {err}"))?;
+	if version & 0b1000_0000 != 0 {
+		Err(anyhow!("Timestamp extrinsic must be unsigned"))?
+	}
+	let (pallet_index, call_index, timestamp): (u8, u8, Compact<u64>) =
+		codec::Decode::decode(input).map_err(|err| anyhow!("Failed to decode extrinsic: {err}"))?;
+	Ok((pallet_index, call_index, timestamp.into()))
+}
+
+/// Attempt to extract the timestamp extrinsic from the parachain header, calling the timestamp
+/// pallet at any index. Only sound for the first extrinsic of a block, use [`TimestampCall`] if
+/// the index of the timestamp pallet is known.
 pub fn decode_timestamp_extrinsic(ext: &Vec<u8>) -> Result<u64, anyhow::Error> {
-	// Timestamp extrinsic should be the first inherent and hence the first extrinsic
-	// https://github.com/paritytech/substrate/blob/d602397a0bbb24b5d627795b797259a44a5e29e9/primitives/trie/src/lib.rs#L99-L101
-	// Decoding from the [2..] because the timestamp inmherent has two extra bytes before the call
-	// that represents the call length and the extrinsic version.
-	let (_, _, timestamp): (u8, u8, Compact<u64>) = codec::Decode::decode(&mut &ext[2..])
-		.map_err(|err| anyhow!("Failed to decode extrinsic: {err}"))?;
-	Ok(timestamp.into())
+	decode_unsigned_call(ext).map(|(.., timestamp)| timestamp)
+}
+
+/// Decodes the timestamp set by the timestamp inherent `ext`, with [`TimestampCall::decode`] if
+/// the `timestamp_call` of the parachain is known, and [`decode_timestamp_extrinsic`] otherwise.
+pub fn decode_timestamp_inherent(
+	timestamp_call: Option<TimestampCall>,
+	ext: &Vec<u8>,
+) -> Result<u64, anyhow::Error> {
+	match timestamp_call {
+		Some(timestamp_call) => timestamp_call.decode(ext),
+		None => decode_timestamp_extrinsic(ext),
+	}
 }
 
 /// This will verify that the connection delay has elapsed for a given [`ibc::Height`]
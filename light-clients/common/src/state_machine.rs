@@ -45,6 +45,22 @@ pub fn read_child_proof_check<H, I>(
 	child_info: ChildInfo,
 	items: I,
 ) -> Result<(), Error<H>>
+where
+	H: Hasher,
+	H::Out: Debug,
+	I: IntoIterator<Item = (Vec<u8>, Option<Vec<u8>>)>,
+{
+	read_child_proof_check_items(root, proof, child_info, items).map_err(|(_, err)| err)
+}
+
+/// Same as [`read_child_proof_check`], but also returns the index of the item that failed
+/// verification, if any. The tries are built once for all the items.
+pub fn read_child_proof_check_items<H, I>(
+	root: H::Out,
+	proof: StorageProof,
+	child_info: ChildInfo,
+	items: I,
+) -> Result<(), (Option<usize>, Error<H>)>
 where
 	H: Hasher,
 	H::Out: Debug,
@@ -53,7 +69,8 @@ where
 	let memory_db = proof.into_memory_db::<H>();
 	let trie = TrieDBBuilder::<LayoutV0<H>>::new(&memory_db, &root).build();
 	let child_root = trie
-		.get(child_info.prefixed_storage_key().as_slice())?
+		.get(child_info.prefixed_storage_key().as_slice())
+		.map_err(|err| (None, Error::<H>::from(err)))?
 		.map(|r| {
 			let mut hash = H::Out::default();
 
@@ -62,20 +79,26 @@ where
 
 			hash
 		})
-		.ok_or(Error::<H>::ChildRootNotFound)?;
+		.ok_or((None, Error::<H>::ChildRootNotFound))?;
 
 	let child_db = KeySpacedDB::new(&memory_db, child_info.keyspace());
 	let child_trie = TrieDBBuilder::<LayoutV0<H>>::new(&child_db, &child_root).build();
 
-	for (key, value) in items {
-		let recovered = child_trie.get(&key)?.and_then(|val| Decode::decode(&mut &val[..]).ok());
+	for (index, (key, value)) in items.into_iter().enumerate() {
+		let recovered = child_trie
+			.get(&key)
+			.map_err(|err| (Some(index), Error::<H>::from(err)))?
+			.and_then(|val| Decode::decode(&mut &val[..]).ok());
 
 		if recovered != value {
-			Err(Error::ValueMismatch {
-				key: String::from_utf8(key).ok(),
-				expected: value,
-				got: recovered,
-			})?
+			Err((
+				Some(index),
+				Error::ValueMismatch {
+					key: String::from_utf8(key).ok(),
+					expected: value,
+					got: recovered,
+				},
+			))?
 		}
 	}
 
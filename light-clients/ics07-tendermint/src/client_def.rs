@@ -368,6 +368,7 @@ where
 		};
 		let value = expected_consensus_state.encode_to_vec().map_err(Ics02Error::encode)?;
 		verify_membership::<H, _>(client_state, prefix, proof, root, path, value)
+			.map_err(Into::into)
 	}
 
 	fn verify_connection_state<Ctx: ReaderContext>(
@@ -387,6 +388,7 @@ where
 		let path = ConnectionsPath(connection_id.clone());
 		let value = expected_connection_end.encode_vec().map_err(Ics02Error::encode)?;
 		verify_membership::<H, _>(client_state, prefix, proof, root, path, value)
+			.map_err(Into::into)
 	}
 
 	fn verify_channel_state<Ctx: ReaderContext>(
@@ -407,6 +409,7 @@ where
 		let path = ChannelEndsPath(port_id.clone(), *channel_id);
 		let value = expected_channel_end.encode_vec().map_err(Ics02Error::encode)?;
 		verify_membership::<H, _>(client_state, prefix, proof, root, path, value)
+			.map_err(Into::into)
 	}
 
 	fn verify_client_full_state<Ctx: ReaderContext>(
@@ -425,6 +428,7 @@ where
 		let path = ClientStatePath(client_id.clone());
 		let value = expected_client_state.encode_to_vec().map_err(Ics02Error::encode)?;
 		verify_membership::<H, _>(client_state, prefix, proof, root, path, value)
+			.map_err(Into::into)
 	}
 
 	fn verify_packet_data<Ctx: ReaderContext>(
@@ -455,6 +459,7 @@ where
 			commitment_path,
 			commitment.into_vec(),
 		)
+		.map_err(Into::into)
 	}
 
 	fn verify_packet_acknowledgement<Ctx: ReaderContext>(
@@ -484,6 +489,7 @@ where
 			ack_path,
 			ack_commitment.into_vec(),
 		)
+		.map_err(Into::into)
 	}
 
 	fn verify_next_sequence_recv<Ctx: ReaderContext>(
@@ -514,6 +520,7 @@ where
 			seq_path,
 			seq_bytes,
 		)
+		.map_err(Into::into)
 	}
 
 	fn verify_packet_receipt_absence<Ctx: ReaderContext>(
@@ -541,6 +548,7 @@ where
 			root,
 			receipt_path,
 		)
+		.map_err(Into::into)
 	}
 }
 
@@ -551,19 +559,18 @@ pub fn verify_membership<H, P>(
 	root: &CommitmentRoot,
 	path: P,
 	value: Vec<u8>,
-) -> Result<(), Ics02Error>
+) -> Result<(), Error>
 where
 	P: Into<Path>,
 	H: ics23::HostFunctionsProvider,
 {
 	let merkle_path = apply_prefix(prefix, vec![path.into().to_string()]);
-	let merkle_proof: MerkleProof<H> = RawMerkleProof::try_from(proof.clone())
-		.map_err(Ics02Error::invalid_commitment_proof)?
-		.into();
+	let merkle_proof: MerkleProof<H> =
+		RawMerkleProof::try_from(proof.clone()).map_err(Error::ics23_error)?.into();
 
 	merkle_proof
 		.verify_membership(&client_state.proof_specs, root.clone().into(), merkle_path, value, 0)
-		.map_err(|e| Error::ics23_error(e).into())
+		.map_err(Error::ics23_error)
 }
 
 pub fn verify_non_membership<H, P>(
@@ -572,19 +579,18 @@ pub fn verify_non_membership<H, P>(
 	proof: &CommitmentProofBytes,
 	root: &CommitmentRoot,
 	path: P,
-) -> Result<(), Ics02Error>
+) -> Result<(), Error>
 where
 	P: Into<Path>,
 	H: ics23::HostFunctionsProvider,
 {
 	let merkle_path = apply_prefix(prefix, vec![path.into().to_string()]);
-	let merkle_proof: MerkleProof<H> = RawMerkleProof::try_from(proof.clone())
-		.map_err(Ics02Error::invalid_commitment_proof)?
-		.into();
+	let merkle_proof: MerkleProof<H> =
+		RawMerkleProof::try_from(proof.clone()).map_err(Error::ics23_error)?.into();
 
 	merkle_proof
 		.verify_non_membership(&client_state.proof_specs, root.clone().into(), merkle_path)
-		.map_err(|e| Error::ics23_error(e).into())
+		.map_err(Error::ics23_error)
 }
 
 fn verify_delay_passed<Ctx: ReaderContext>(
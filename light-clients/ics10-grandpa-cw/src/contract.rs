@@ -86,6 +86,7 @@ impl light_client_common::HostFunctions for HostFunctions {
 
 impl grandpa_light_client_primitives::HostFunctions for HostFunctions {
 	type Header = RelayChainHeader;
+	type ParachainHeader = sp_runtime::generic::Header<u32, BlakeTwo256>;
 
 	fn ed25519_verify(
 		sig: &sp_core::ed25519::Signature,
@@ -276,7 +277,7 @@ fn process_message(
 			// Check that the substitute client state is valid:
 			// all fields should be the same as in the old state, except for the `relay_chain`,
 			// `para_id`, `latest_para_height`, `latest_relay_height`, `latest_relay_hash`,
-			// `frozen_height`, `current_authorities`, `current_set_id`
+			// `frozen_height`, `current_authorities`, `current_set_id`, `timestamp_pallet_index`
 			let ClientState {
 				relay_chain,
 				latest_relay_height,
@@ -286,6 +287,7 @@ fn process_message(
 				para_id,
 				current_set_id,
 				current_authorities,
+				timestamp_pallet_index,
 				_phantom,
 			} = substitute_client_state.clone();
 			old_client_state.relay_chain = relay_chain;
@@ -296,6 +298,7 @@ fn process_message(
 			old_client_state.frozen_height = frozen_height;
 			old_client_state.current_authorities = current_authorities.clone();
 			old_client_state.current_set_id = current_set_id;
+			old_client_state.timestamp_pallet_index = timestamp_pallet_index;
 
 			if old_client_state != substitute_client_state {
 				return Err(ContractError::Grandpa(
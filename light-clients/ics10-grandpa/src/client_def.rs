@@ -24,10 +24,8 @@ use codec::Decode;
 use core::marker::PhantomData;
 use finality_grandpa::Chain;
 use grandpa_client_primitives::{
-	justification::{
-		find_forced_change, find_scheduled_change, AncestryChain, GrandpaJustification,
-	},
-	ParachainHeadersWithFinalityProof,
+	justification::{find_scheduled_change, AncestryChain, GrandpaJustification},
+	FinalityProof, ParachainHeadersWithFinalityProof,
 };
 use ibc::{
 	core::{
@@ -67,6 +65,34 @@ const CONSENSUS_STATE_UPGRADE_PATH: &[u8] = b"consensus-state-upgrade-path";
 #[derive(Clone, Debug, PartialEq, Eq, Default)]
 pub struct GrandpaClient<T>(PhantomData<T>);
 
+/// Checks that the unknown headers of a finality proof are a contiguous chain of relay chain
+/// headers, sorted by number and ending at the target of the justification.
+pub fn validate_unknown_headers(
+	finality_proof: &FinalityProof<RelayChainHeader>,
+) -> Result<(), Error> {
+	let headers = &finality_proof.unknown_headers;
+	let target = headers.last().ok_or(Error::EmptyUnknownHeaders)?;
+
+	for (parent, header) in headers.iter().zip(headers.iter().skip(1)) {
+		let expected = parent.number + 1;
+		if header.number != expected {
+			return Err(Error::UnsortedUnknownHeaders { expected, found: header.number })
+		}
+		if header.parent_hash != parent.hash() {
+			return Err(Error::BrokenAncestry { number: header.number })
+		}
+	}
+
+	let justification =
+		GrandpaJustification::<RelayChainHeader>::decode(&mut &finality_proof.justification[..])?;
+	let target_hash = justification.commit.target_hash;
+	if target.hash() != target_hash {
+		return Err(Error::MissingTargetHeader { target: target_hash })
+	}
+
+	Ok(())
+}
+
 impl<H> ClientDef for GrandpaClient<H>
 where
 	H: grandpa_client_primitives::HostFunctions<Header = RelayChainHeader>,
@@ -91,6 +117,7 @@ where
 					))
 					.into())
 				}
+				validate_unknown_headers(&header.finality_proof)?;
 				let headers_with_finality_proof = ParachainHeadersWithFinalityProof {
 					finality_proof: header.finality_proof,
 					parachain_headers: header.parachain_headers,
@@ -245,6 +272,7 @@ where
 				parachain_header_proof,
 				client_state.para_id,
 				header.state_root.clone(),
+				client_state.timestamp_call(),
 			)?;
 
 			// Skip duplicate consensus states
@@ -269,13 +297,18 @@ where
 			)))?
 		}
 
+		// parachains may use 64-bit block numbers, see [`ConsensusState::from_header`]
 		let mut heights = consensus_states
 			.iter()
 			.map(|(h, ..)| {
-				// this cast is safe, see [`ConsensusState::from_header`]
-				h.revision_height as u32
+				u32::try_from(h.revision_height).map_err(|_| {
+					Error::Custom(format!(
+						"Parachain height {} exceeds the maximum supported height",
+						h.revision_height
+					))
+				})
 			})
-			.collect::<Vec<_>>();
+			.collect::<Result<Vec<_>, _>>()?;
 
 		heights.sort();
 
@@ -297,6 +330,8 @@ where
 			client_state.current_authorities = scheduled_change.next_authorities;
 		}
 
+		client_state.validate()?;
+
 		H::insert_relay_header_hashes(&finalized);
 
 		Ok((client_state, ConsensusUpdateResult::Batch(consensus_states)))
@@ -329,24 +364,35 @@ where
 			ClientMessage::Header(header) => header,
 			_ => unreachable!("We've checked for misbehavior in line 180; qed"),
 		};
-		//forced authority set change is handled as a misbehaviour
 
 		let ancestry =
 			AncestryChain::<RelayChainHeader>::new(&header.finality_proof.unknown_headers);
 
+		// a forced authority set change is handled as misbehaviour, as nothing in the update proves
+		// the new authorities were legitimately installed. The client has to be recovered
+		// through governance.
+		let finalized = ancestry
+			.ancestry(client_state.latest_relay_hash, header.finality_proof.block)
+			.map_err(|_| Error::Custom(format!("[check_for_misbehaviour] Invalid ancestry!")))?;
+		if grandpa_client::find_forced_change_signal::<RelayChainHeader>(
+			finalized.iter().filter_map(|hash| ancestry.header(hash)),
+			client_state.latest_relay_height,
+		)
+		.is_some()
+		{
+			return Ok(true)
+		}
+
 		for (relay_hash, parachain_header_proof) in header.parachain_headers {
 			let header = ancestry.header(&relay_hash).ok_or_else(|| {
 				Error::Custom(format!("No relay chain header found for hash: {relay_hash:?}"))
 			})?;
 
-			if find_forced_change(header).is_some() {
-				return Ok(true)
-			}
-
 			let (height, consensus_state) = ConsensusState::from_header::<H>(
 				parachain_header_proof,
 				client_state.para_id,
 				header.state_root.clone(),
+				client_state.timestamp_call(),
 			)?;
 
 			match ctx.maybe_consensus_state(&client_id, height)? {
@@ -491,7 +537,7 @@ where
 		};
 		let value = expected_consensus_state.encode_to_vec().map_err(Ics02Error::encode)?;
 		verify_membership::<H::BlakeTwo256, _>(prefix, proof, root, path, value)
-			.map_err(Error::Anyhow)?;
+			.map_err(Error::Proof)?;
 		Ok(())
 	}
 
@@ -511,7 +557,7 @@ where
 		let path = ConnectionsPath(connection_id.clone());
 		let value = expected_connection_end.encode_vec().map_err(Ics02Error::encode)?;
 		verify_membership::<H::BlakeTwo256, _>(prefix, proof, root, path, value)
-			.map_err(Error::Anyhow)?;
+			.map_err(Error::Proof)?;
 		Ok(())
 	}
 
@@ -532,7 +578,7 @@ where
 		let path = ChannelEndsPath(port_id.clone(), *channel_id);
 		let value = expected_channel_end.encode_vec().map_err(Ics02Error::encode)?;
 		verify_membership::<H::BlakeTwo256, _>(prefix, proof, root, path, value)
-			.map_err(Error::Anyhow)?;
+			.map_err(Error::Proof)?;
 		Ok(())
 	}
 
@@ -551,7 +597,7 @@ where
 		let path = ClientStatePath(client_id.clone());
 		let value = expected_client_state.encode_to_vec().map_err(Ics02Error::encode)?;
 		verify_membership::<H::BlakeTwo256, _>(prefix, proof, root, path, value)
-			.map_err(Error::Anyhow)?;
+			.map_err(Error::Proof)?;
 		Ok(())
 	}
 
@@ -582,7 +628,7 @@ where
 			commitment_path,
 			commitment.into_vec(),
 		)
-		.map_err(Error::Anyhow)?;
+		.map_err(Error::Proof)?;
 		Ok(())
 	}
 
@@ -611,7 +657,7 @@ where
 			ack_path,
 			ack.into_vec(),
 		)
-		.map_err(Error::Anyhow)?;
+		.map_err(Error::Proof)?;
 		Ok(())
 	}
 
@@ -641,7 +687,7 @@ where
 			seq_path,
 			seq_bytes,
 		)
-		.map_err(Error::Anyhow)?;
+		.map_err(Error::Proof)?;
 		Ok(())
 	}
 
@@ -669,7 +715,7 @@ where
 			root,
 			receipt_path,
 		)
-		.map_err(Error::Anyhow)?;
+		.map_err(Error::Proof)?;
 		Ok(())
 	}
 }
@@ -34,11 +34,23 @@ pub const GRANDPA_CLIENT_MESSAGE_TYPE_URL: &str = "/ibc.lightclients.grandpa.v1.
 pub const GRANDPA_HEADER_TYPE_URL: &str = "/ibc.lightclients.grandpa.v1.Header";
 pub const GRANDPA_MISBEHAVIOUR_TYPE_URL: &str = "/ibc.lightclients.grandpa.v1.Misbehaviour";
 
+/// Maximum number of relay chain headers accepted in a decoded finality proof, enough to cover a
+/// session of the live relay chains.
+pub const MAX_UNKNOWN_HEADERS: usize = 4096;
+
+fn check_unknown_headers_count(count: usize) -> Result<(), Error> {
+	if count > MAX_UNKNOWN_HEADERS {
+		return Err(Error::TooManyUnknownHeaders { count, max: MAX_UNKNOWN_HEADERS })
+	}
+	Ok(())
+}
+
 /// Relay chain substrate header type
 pub type RelayChainHeader = sp_runtime::generic::Header<u32, BlakeTwo256>;
 
 /// Parachain headers with a Grandpa finality proof.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "scale", derive(Encode, Decode, scale_info::TypeInfo))]
 pub struct Header {
 	/// The grandpa finality proof: contains relay chain headers from the
 	/// last known finalized grandpa block.
@@ -61,6 +73,7 @@ impl Header {
 /// (that is, form a valid canonical chain of blocks where on of the chain is a fork of
 /// the main one)
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "scale", derive(Encode, Decode, scale_info::TypeInfo))]
 pub struct Misbehaviour {
 	/// first proof of misbehaviour
 	pub first_finality_proof: FinalityProof<RelayChainHeader>,
@@ -70,6 +83,7 @@ pub struct Misbehaviour {
 
 /// [`ClientMessage`] for Ics10-GRANDPA
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "scale", derive(Encode, Decode, scale_info::TypeInfo))]
 pub enum ClientMessage {
 	/// This is the variant for header updates
 	Header(Header),
@@ -117,6 +131,7 @@ impl TryFrom<RawHeader> for Header {
 			})
 			.collect::<Result<_, Error>>()?;
 
+		check_unknown_headers_count(finality_proof.unknown_headers.len())?;
 		let unknown_headers = finality_proof
 			.unknown_headers
 			.into_iter()
@@ -178,10 +193,13 @@ impl TryFrom<RawMisbehaviour> for Misbehaviour {
 	type Error = Error;
 
 	fn try_from(value: RawMisbehaviour) -> Result<Self, Self::Error> {
-		Ok(Misbehaviour {
+		let misbehaviour = Misbehaviour {
 			first_finality_proof: Decode::decode(&mut &*value.first_finality_proof)?,
 			second_finality_proof: Decode::decode(&mut &*value.second_finality_proof)?,
-		})
+		};
+		check_unknown_headers_count(misbehaviour.first_finality_proof.unknown_headers.len())?;
+		check_unknown_headers_count(misbehaviour.second_finality_proof.unknown_headers.len())?;
+		Ok(misbehaviour)
 	}
 }
 
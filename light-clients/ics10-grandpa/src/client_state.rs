@@ -19,7 +19,7 @@ use crate::{
 	error::Error,
 	proto::{Authority as RawAuthority, ClientState as RawClientState},
 };
-use alloc::{format, string::ToString, vec::Vec};
+use alloc::{collections::BTreeSet, format, string::ToString, vec::Vec};
 use anyhow::anyhow;
 use core::{marker::PhantomData, time::Duration};
 use ibc::{
@@ -34,8 +34,7 @@ use ibc::{
 	Height,
 };
 use ibc_proto::google::protobuf::Any;
-use light_client_common::RelayChain;
-use serde::{Deserialize, Serialize};
+use light_client_common::{RelayChain, TimestampCall};
 use sp_consensus_grandpa::AuthorityList;
 use sp_core::{ed25519::Public, H256};
 use tendermint_proto::Protobuf;
@@ -43,7 +42,13 @@ use tendermint_proto::Protobuf;
 /// Protobuf type url for GRANDPA ClientState
 pub const GRANDPA_CLIENT_STATE_TYPE_URL: &str = "/ibc.lightclients.grandpa.v1.ClientState";
 
+/// Maximum number of authorities accepted in the authority set of a decoded client state, well
+/// above the validator counts of the live relay chains.
+pub const MAX_AUTHORITIES: usize = 4096;
+
 #[derive(PartialEq, Clone, Debug, Default, Eq)]
+#[cfg_attr(feature = "scale", derive(codec::Encode, codec::Decode, scale_info::TypeInfo))]
+#[cfg_attr(feature = "scale", scale_info(skip_type_params(H)))]
 pub struct ClientState<H> {
 	/// Relay chain
 	pub relay_chain: RelayChain,
@@ -61,7 +66,11 @@ pub struct ClientState<H> {
 	pub current_set_id: u64,
 	/// authorities for the current round
 	pub current_authorities: AuthorityList,
+	/// Index of the timestamp pallet in the parachain runtime, the timestamp inherent is
+	/// recognized by its call index only if unknown
+	pub timestamp_pallet_index: Option<u8>,
 	/// phantom type.
+	#[cfg_attr(feature = "scale", codec(skip))]
 	pub _phantom: PhantomData<H>,
 }
 
@@ -74,32 +83,38 @@ impl<H> From<ClientState<H>> for grandpa_client_primitives::ClientState {
 			latest_relay_height: client_state.latest_relay_height,
 			latest_para_height: client_state.latest_para_height,
 			para_id: client_state.para_id,
+			timestamp_pallet_index: client_state.timestamp_pallet_index,
 		}
 	}
 }
 
 impl<H: Clone> Protobuf<RawClientState> for ClientState<H> {}
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct UpgradeOptions {
 	latest_relay_hash: H256,
 }
 
 impl<H: Clone> ClientState<H> {
-	/// Verify that the client is at a sufficient height and unfrozen at the given height
+	/// Verify that the client is unfrozen and at a sufficient height to verify a proof at the
+	/// given height. Proof heights are parachain heights, so they are compared to
+	/// `latest_para_height`, which lags behind `latest_relay_height`.
 	pub fn verify_height(&self, height: Height) -> Result<(), Error> {
-		let latest_para_height = Height::new(self.para_id.into(), self.latest_para_height.into());
-		if latest_para_height < height {
-			return Err(Error::Custom(format!(
-				"Insufficient height, known height: {latest_para_height}, given height: {height}"
-			)))
+		if let Some(frozen_height) = self.frozen_height {
+			return Err(Error::ClientFrozen { frozen_height, height })
 		}
 
-		match self.frozen_height {
-			Some(frozen_height) if frozen_height <= height =>
-				Err(Error::Custom(format!("Client has been frozen at height {frozen_height}"))),
-			_ => Ok(()),
+		let latest_para_height = self.latest_height();
+		if latest_para_height < height {
+			return Err(Error::InsufficientHeight {
+				latest_para_height,
+				latest_relay_height: self.latest_relay_height,
+				height,
+			})
 		}
+
+		Ok(())
 	}
 
 	pub fn to_any(&self) -> Any {
@@ -127,6 +142,12 @@ impl<H> ClientState<H> {
 		self.frozen_height
 	}
 
+	/// The timestamp inherent of the parachain, whose extrinsic sets the timestamp of the
+	/// consensus states, if the index of its pallet is known.
+	pub fn timestamp_call(&self) -> Option<TimestampCall> {
+		self.timestamp_pallet_index.map(TimestampCall::new)
+	}
+
 	pub fn upgrade(
 		mut self,
 		_upgrade_height: Height,
@@ -152,7 +173,32 @@ impl<H> ClientState<H> {
 				"ClientState frozen height must be greater than zero".to_string(),
 			))
 		}
-		Ok(Self { frozen_height: Some(h), ..self })
+		let client_state = Self { frozen_height: Some(h), ..self };
+		client_state.validate()?;
+		Ok(client_state)
+	}
+
+	/// Checks the invariants between the heights of the client state. They're enforced whenever
+	/// the client state is frozen or updated rather than on decoding, so that the client states
+	/// stored before they were introduced can still be read.
+	pub fn validate(&self) -> Result<(), Error> {
+		// every authority set change is enacted in a block of its own
+		if self.current_set_id > self.latest_relay_height as u64 {
+			return Err(Error::InconsistentSetId {
+				set_id: self.current_set_id,
+				latest_relay_height: self.latest_relay_height,
+			})
+		}
+		// the client is frozen at its latest parachain height on misbehaviour
+		if let Some(frozen_height) = self.frozen_height {
+			if frozen_height.revision_height > self.latest_para_height as u64 {
+				return Err(Error::InconsistentFrozenHeight {
+					frozen_height: frozen_height.revision_height,
+					latest_para_height: self.latest_para_height,
+				})
+			}
+		}
+		Ok(())
 	}
 }
 
@@ -232,6 +278,20 @@ impl<H> TryFrom<RawClientState> for ClientState<H> {
 	type Error = Error;
 
 	fn try_from(raw: RawClientState) -> Result<Self, Self::Error> {
+		let count = raw.current_authorities.len();
+		if count == 0 {
+			return Err(Error::EmptyAuthoritySet)
+		}
+		if count > MAX_AUTHORITIES {
+			return Err(Error::TooManyAuthorities { count, max: MAX_AUTHORITIES })
+		}
+		let mut keys = BTreeSet::new();
+		if let Some(index) =
+			raw.current_authorities.iter().position(|set| !keys.insert(&set.public_key))
+		{
+			return Err(Error::DuplicateAuthority { index })
+		}
+
 		let current_authorities = raw
 			.current_authorities
 			.into_iter()
@@ -242,6 +302,13 @@ impl<H> TryFrom<RawClientState> for ClientState<H> {
 			})
 			.collect::<Result<_, Error>>()?;
 
+		let timestamp_pallet_index = raw
+			.timestamp_pallet_index
+			.map(|index| {
+				u8::try_from(index).map_err(|_| anyhow!("Invalid timestamp pallet index: {index}"))
+			})
+			.transpose()?;
+
 		let relay_chain = RelayChain::from_i32(raw.relay_chain)?;
 		if raw.latest_relay_hash.len() != 32 {
 			Err(anyhow!("Invalid ed25519 public key lenght: {}", raw.latest_relay_hash.len()))?
@@ -259,6 +326,7 @@ impl<H> TryFrom<RawClientState> for ClientState<H> {
 			current_authorities,
 			latest_relay_hash,
 			latest_relay_height: raw.latest_relay_height,
+			timestamp_pallet_index,
 			_phantom: Default::default(),
 		})
 	}
@@ -285,6 +353,7 @@ impl<H> From<ClientState<H>> for RawClientState {
 					weight,
 				})
 				.collect(),
+			timestamp_pallet_index: client_state.timestamp_pallet_index.map(u32::from),
 		}
 	}
 }
@@ -17,7 +17,6 @@ use alloc::{format, vec, vec::Vec};
 use anyhow::anyhow;
 use codec::Decode;
 use core::{convert::Infallible, fmt::Debug};
-use serde::{Deserialize, Serialize};
 use tendermint::time::Time;
 use tendermint_proto::{google::protobuf as tpb, Protobuf};
 
@@ -27,15 +26,15 @@ use crate::{alloc::string::ToString, error::Error};
 use grandpa_client_primitives::{parachain_header_storage_key, ParachainHeaderProofs};
 use ibc::{core::ics23_commitment::commitment::CommitmentRoot, timestamp::Timestamp, Height};
 use ibc_proto::google::protobuf::Any;
-use light_client_common::{decode_timestamp_extrinsic, state_machine};
+use light_client_common::{decode_timestamp_inherent, state_machine, TimestampCall};
 use sp_core::H256;
-use sp_runtime::{generic, traits::BlakeTwo256, SaturatedConversion};
+use sp_runtime::{traits::Header, SaturatedConversion};
 use sp_trie::StorageProof;
 
 /// Protobuf type url for GRANDPA Consensus State
 pub const GRANDPA_CONSENSUS_STATE_TYPE_URL: &str = "/ibc.lightclients.grandpa.v1.ConsensusState";
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ConsensusState {
 	pub timestamp: Time,
 	pub root: CommitmentRoot,
@@ -57,6 +56,7 @@ impl ConsensusState {
 		parachain_header_proof: ParachainHeaderProofs,
 		para_id: u32,
 		relay_state_root: H256,
+		timestamp_call: Option<TimestampCall>,
 	) -> Result<(Height, Self), Error>
 	where
 		H: grandpa_client_primitives::HostFunctions,
@@ -73,18 +73,19 @@ impl ConsensusState {
 		.flatten()
 		.ok_or_else(|| anyhow!("Invalid state proof for parachain header"))?;
 
-		let parachain_header =
-			generic::Header::<u32, BlakeTwo256>::decode(&mut &parachain_header_bytes[..])?;
-		let root = parachain_header.state_root.0.to_vec();
+		let parachain_header = H::ParachainHeader::decode(&mut &parachain_header_bytes[..])
+			.map_err(|e| anyhow!("Failed to decode header of parachain {para_id}: {e}"))?;
+		let root = parachain_header.state_root().as_ref().to_vec();
 
-		let timestamp = decode_timestamp_extrinsic(&parachain_header_proof.extrinsic)?;
+		let timestamp =
+			decode_timestamp_inherent(timestamp_call, &parachain_header_proof.extrinsic)?;
 		let duration = core::time::Duration::from_millis(timestamp);
 		let timestamp = Timestamp::from_nanoseconds(duration.as_nanos().saturated_into::<u64>())?
 			.into_tm_time()
 			.ok_or_else(|| anyhow!("Error decoding Timestamp, timestamp cannot be zero"))?;
 
 		Ok((
-			Height::new(para_id as u64, parachain_header.number as u64),
+			Height::new(para_id as u64, (*parachain_header.number()).saturated_into::<u64>()),
 			Self { root: root.into(), timestamp },
 		))
 	}
@@ -108,6 +109,46 @@ impl ibc::core::ics02_client::client_consensus::ConsensusState for ConsensusStat
 
 impl Protobuf<RawConsensusState> for ConsensusState {}
 
+/// The SCALE encoding of the consensus state, with its timestamp in nanoseconds since the unix
+/// epoch.
+#[cfg(feature = "scale")]
+mod scale {
+	use super::*;
+	use codec::{Encode, Input, Output};
+	use scale_info::{build::Fields, Path, Type, TypeInfo};
+
+	impl Encode for ConsensusState {
+		fn encode_to<T: Output + ?Sized>(&self, dest: &mut T) {
+			Timestamp::from(self.timestamp).nanoseconds().encode_to(dest);
+			self.root.as_bytes().encode_to(dest);
+		}
+	}
+
+	impl Decode for ConsensusState {
+		fn decode<I: Input>(input: &mut I) -> Result<Self, codec::Error> {
+			let nanos = u64::decode(input)?;
+			let root = Vec::<u8>::decode(input)?;
+			let timestamp = Timestamp::from_nanoseconds(nanos)
+				.ok()
+				.and_then(|timestamp| timestamp.into_tm_time())
+				.ok_or_else(|| codec::Error::from("Invalid consensus state timestamp"))?;
+			Ok(Self { timestamp, root: root.into() })
+		}
+	}
+
+	impl TypeInfo for ConsensusState {
+		type Identity = Self;
+
+		fn type_info() -> Type {
+			Type::builder().path(Path::new("ConsensusState", module_path!())).composite(
+				Fields::named()
+					.field(|f| f.ty::<u64>().name("timestamp").type_name("u64"))
+					.field(|f| f.ty::<Vec<u8>>().name("root").type_name("Vec<u8>")),
+			)
+		}
+	}
+}
+
 impl TryFrom<RawConsensusState> for ConsensusState {
 	type Error = Error;
 
@@ -18,8 +18,10 @@ use alloc::{borrow::ToOwned, format, string::String};
 use ibc::{
 	core::{ics02_client, ics04_channel, ics24_host::error::ValidationError},
 	timestamp::{ParseTimestampError, TimestampOverflowError},
+	Height,
 };
 use prost::DecodeError;
+use sp_core::H256;
 
 #[derive(derive_more::From, derive_more::Display, Debug)]
 pub enum Error {
@@ -32,6 +34,75 @@ pub enum Error {
 	ProtoBuf(DecodeError),
 	GrandpaPrimitives(grandpa_client_primitives::error::Error),
 	Anyhow(anyhow::Error),
+	Proof(light_client_common::ProofError),
+	/// The finality proof of a header doesn't contain any relay chain header
+	EmptyUnknownHeaders,
+	/// The relay chain headers of a finality proof aren't sorted by consecutive numbers
+	#[display(fmt = "UnsortedUnknownHeaders: expected header #{expected}, got #{found}")]
+	UnsortedUnknownHeaders {
+		expected: u32,
+		found: u32,
+	},
+	/// A relay chain header of a finality proof isn't the child of the previous one
+	#[display(fmt = "BrokenAncestry: header #{number} doesn't link to its parent")]
+	BrokenAncestry {
+		number: u32,
+	},
+	/// The last relay chain header of a finality proof isn't the justification target
+	#[display(fmt = "MissingTargetHeader: {target:?} isn't the last unknown header")]
+	MissingTargetHeader {
+		target: H256,
+	},
+	/// The client hasn't been updated to the height of a proof yet
+	#[display(
+		fmt = "InsufficientHeight: latest parachain height {latest_para_height} (relay chain height {latest_relay_height}), proof height {height}"
+	)]
+	InsufficientHeight {
+		latest_para_height: Height,
+		latest_relay_height: u32,
+		height: Height,
+	},
+	/// The client was frozen due to a misbehaviour and doesn't verify any proof anymore
+	#[display(fmt = "ClientFrozen: frozen at {frozen_height}, proof height {height}")]
+	ClientFrozen {
+		frozen_height: Height,
+		height: Height,
+	},
+	/// A client state doesn't contain any authority
+	EmptyAuthoritySet,
+	/// A client state contains more authorities than accepted
+	#[display(fmt = "TooManyAuthorities: {count} authorities, at most {max} are accepted")]
+	TooManyAuthorities {
+		count: usize,
+		max: usize,
+	},
+	/// An authority appears more than once in the authority set of a client state
+	#[display(fmt = "DuplicateAuthority: authority #{index} was already in the set")]
+	DuplicateAuthority {
+		index: usize,
+	},
+	/// The authority set id of a client state can't have been reached at its relay chain height
+	#[display(
+		fmt = "InconsistentSetId: set id {set_id} at relay chain height {latest_relay_height}"
+	)]
+	InconsistentSetId {
+		set_id: u64,
+		latest_relay_height: u32,
+	},
+	/// A client state is frozen above its latest parachain height
+	#[display(
+		fmt = "InconsistentFrozenHeight: frozen at {frozen_height}, latest parachain height {latest_para_height}"
+	)]
+	InconsistentFrozenHeight {
+		frozen_height: u64,
+		latest_para_height: u32,
+	},
+	/// A finality proof contains more relay chain headers than accepted
+	#[display(fmt = "TooManyUnknownHeaders: {count} headers, at most {max} are accepted")]
+	TooManyUnknownHeaders {
+		count: usize,
+		max: usize,
+	},
 	Custom(String),
 }
 
@@ -0,0 +1,155 @@
+// Copyright (C) 2022 ComposableFi.
+// SPDX-License-Identifier: Apache-2.0
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The JSON encoding of the client and consensus states, as served by queries. Protobuf stays
+//! the canonical encoding of the states; this one is meant to be readable by indexers.
+//!
+//! Timestamps are RFC 3339 strings and byte strings are `0x` prefixed lowercase hex, whose
+//! length is checked when decoded. The client state is decoded through its protobuf
+//! representation, so that it is validated the same way.
+
+use crate::{
+	client_state::ClientState,
+	consensus_state::ConsensusState,
+	proto::{Authority as RawAuthority, ClientState as RawClientState},
+};
+use alloc::{format, string::String, vec::Vec};
+use light_client_common::RelayChain;
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use tendermint::time::Time;
+
+/// Length of the hashes and state roots of the relay chain and parachains.
+const HASH_LENGTH: usize = 32;
+
+/// Length of the ed25519 public keys of the authorities.
+const PUBLIC_KEY_LENGTH: usize = 32;
+
+fn to_hex(bytes: &[u8]) -> String {
+	format!("0x{}", hex::encode(bytes))
+}
+
+fn from_hex<E: serde::de::Error>(s: &str, length: usize) -> Result<Vec<u8>, E> {
+	let digits = s
+		.strip_prefix("0x")
+		.ok_or_else(|| E::custom(format!("{s:?} isn't 0x prefixed hex")))?;
+	let bytes = hex::decode(digits).map_err(|e| E::custom(format!("{s:?} isn't hex: {e}")))?;
+	if bytes.len() != length {
+		return Err(E::custom(format!("{s:?} is {} bytes long instead of {length}", bytes.len())))
+	}
+	Ok(bytes)
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonConsensusState {
+	timestamp: String,
+	root: String,
+}
+
+impl Serialize for ConsensusState {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		JsonConsensusState {
+			timestamp: self.timestamp.to_rfc3339(),
+			root: to_hex(self.root.as_bytes()),
+		}
+		.serialize(serializer)
+	}
+}
+
+impl<'de> Deserialize<'de> for ConsensusState {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let json = JsonConsensusState::deserialize(deserializer)?;
+		let timestamp = Time::parse_from_rfc3339(&json.timestamp).map_err(|e| {
+			D::Error::custom(format!("Invalid timestamp {:?}: {e}", json.timestamp))
+		})?;
+		let root = from_hex(&json.root, HASH_LENGTH)?;
+		Ok(ConsensusState::new(root, timestamp))
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonAuthority {
+	public_key: String,
+	weight: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonClientState {
+	relay_chain: RelayChain,
+	latest_relay_height: u32,
+	latest_relay_hash: String,
+	/// Parachain height the client was frozen at
+	frozen_height: Option<u64>,
+	latest_para_height: u32,
+	para_id: u32,
+	current_set_id: u64,
+	current_authorities: Vec<JsonAuthority>,
+	timestamp_pallet_index: Option<u8>,
+}
+
+impl<H: Clone> Serialize for ClientState<H> {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		let raw = RawClientState::from(self.clone());
+		JsonClientState {
+			relay_chain: self.relay_chain,
+			latest_relay_height: raw.latest_relay_height,
+			latest_relay_hash: to_hex(&raw.latest_relay_hash),
+			frozen_height: raw.frozen_height,
+			latest_para_height: raw.latest_para_height,
+			para_id: raw.para_id,
+			current_set_id: raw.current_set_id,
+			current_authorities: raw
+				.current_authorities
+				.iter()
+				.map(|authority| JsonAuthority {
+					public_key: to_hex(&authority.public_key),
+					weight: authority.weight,
+				})
+				.collect(),
+			timestamp_pallet_index: self.timestamp_pallet_index,
+		}
+		.serialize(serializer)
+	}
+}
+
+impl<'de, H> Deserialize<'de> for ClientState<H> {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let json = JsonClientState::deserialize(deserializer)?;
+		let current_authorities = json
+			.current_authorities
+			.iter()
+			.map(|authority| {
+				Ok(RawAuthority {
+					public_key: from_hex(&authority.public_key, PUBLIC_KEY_LENGTH)?,
+					weight: authority.weight,
+				})
+			})
+			.collect::<Result<_, D::Error>>()?;
+		let raw = RawClientState {
+			latest_relay_height: json.latest_relay_height,
+			latest_relay_hash: from_hex(&json.latest_relay_hash, HASH_LENGTH)?,
+			current_set_id: json.current_set_id,
+			frozen_height: json.frozen_height,
+			relay_chain: json.relay_chain as i32,
+			para_id: json.para_id,
+			latest_para_height: json.latest_para_height,
+			current_authorities,
+			timestamp_pallet_index: json.timestamp_pallet_index.map(u32::from),
+		};
+		ClientState::try_from(raw).map_err(|e| D::Error::custom(format!("{e}")))
+	}
+}
@@ -25,8 +25,25 @@ pub mod client_message;
 pub mod client_state;
 pub mod consensus_state;
 pub mod error;
+#[cfg(feature = "serde")]
+mod json;
 pub mod proto;
 
+/// Checks at compile time that the client types can be stored by a runtime pallet. It's built
+/// without `std` by `scripts/no_std_checks.sh`.
+#[cfg(feature = "scale")]
+mod no_std_check {
+	use crate::{client_message, client_state::ClientState, consensus_state::ConsensusState};
+
+	fn scale_codec<T: codec::FullCodec + scale_info::TypeInfo + 'static>() {}
+
+	const _: fn() = scale_codec::<ClientState<()>>;
+	const _: fn() = scale_codec::<ConsensusState>;
+	const _: fn() = scale_codec::<client_message::Header>;
+	const _: fn() = scale_codec::<client_message::Misbehaviour>;
+	const _: fn() = scale_codec::<client_message::ClientMessage>;
+}
+
 #[cfg(test)]
 mod mock;
 
@@ -61,6 +61,7 @@ thread_local! {
 
 impl grandpa_client_primitives::HostFunctions for HostFunctionsManager {
 	type Header = sp_runtime::generic::Header<u32, BlakeTwo256>;
+	type ParachainHeader = sp_runtime::generic::Header<u32, BlakeTwo256>;
 
 	fn ed25519_verify(sig: &ed25519::Signature, msg: &[u8], pub_key: &ed25519::Public) -> bool {
 		pub_key.verify(&msg, sig)
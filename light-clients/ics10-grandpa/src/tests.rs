@@ -14,12 +14,15 @@
 // limitations under the License.
 
 use crate::{
-	client_message::{ClientMessage, Header, RelayChainHeader},
-	client_state::ClientState,
+	client_def::{validate_unknown_headers, GrandpaClient},
+	client_message::{ClientMessage, Header, Misbehaviour, RelayChainHeader, MAX_UNKNOWN_HEADERS},
+	client_state::{ClientState, MAX_AUTHORITIES},
 	consensus_state::ConsensusState,
+	error::Error,
 	mock::{
 		AnyClientMessage, AnyClientState, AnyConsensusState, HostFunctionsManager, MockClientTypes,
 	},
+	proto::{ClientState as RawClientState, Header as RawHeader, Misbehaviour as RawMisbehaviour},
 };
 use beefy_prover::helpers::{fetch_timestamp_extrinsic_with_proof, TimeStampExtWithProof};
 use codec::{Decode, Encode};
@@ -27,13 +30,14 @@ use finality_grandpa_rpc::GrandpaApiClient;
 use futures::stream::StreamExt;
 use grandpa_client_primitives::{
 	justification::GrandpaJustification, parachain_header_storage_key, FinalityProof,
-	ParachainHeaderProofs, ParachainHeadersWithFinalityProof,
+	HostFunctions, ParachainHeaderProofs, ParachainHeadersWithFinalityProof,
 };
 use grandpa_prover::{GrandpaProver, JustificationNotification};
 use hyperspace_core::substrate::DefaultConfig as PolkadotConfig;
 use ibc::{
 	core::{
 		ics02_client::{
+			client_def::ClientDef,
 			client_state::ClientState as _,
 			context::{ClientKeeper, ClientReader},
 			handler::{dispatch, ClientResult::Update},
@@ -49,10 +53,13 @@ use ibc::{
 	test_utils::get_dummy_account_id,
 	Height,
 };
-use light_client_common::config::RuntimeStorage;
-use sp_core::{hexdisplay::AsBytesRef, H256};
+use light_client_common::{config::RuntimeStorage, RelayChain, TimestampCall};
+use sp_consensus_grandpa::{ConsensusLog, ScheduledChange, GRANDPA_ENGINE_ID};
+use sp_core::{ed25519, hexdisplay::AsBytesRef, H160, H256};
+use sp_runtime::{traits::Header as _, Digest, DigestItem};
 use std::time::Duration;
 use subxt::config::substrate::{BlakeTwo256, SubstrateHeader};
+use tendermint::time::Time;
 
 #[tokio::test]
 async fn test_continuous_update_of_grandpa_client() {
@@ -146,6 +153,11 @@ async fn test_continuous_update_of_grandpa_client() {
 			para_id: prover.para_id,
 			current_set_id: client_state.current_set_id,
 			current_authorities: client_state.current_authorities,
+			timestamp_pallet_index: prover
+				.para_client
+				.metadata()
+				.pallet_by_name("Timestamp")
+				.map(|pallet| pallet.index()),
 			_phantom: Default::default(),
 		};
 		let subxt_block_number: subxt::rpc::types::BlockNumber = decoded_para_head.number.into();
@@ -177,6 +189,7 @@ async fn test_continuous_update_of_grandpa_client() {
 			header_proof,
 			prover.para_id,
 			latest_relay_header.state_root,
+			client_state.timestamp_call(),
 		)
 		.unwrap();
 
@@ -306,3 +319,634 @@ async fn test_continuous_update_of_grandpa_client() {
 		}
 	}
 }
+
+/// Host functions for a parachain with 64-bit block numbers.
+#[derive(Clone, Default, PartialEq, Debug, Eq)]
+struct U64ParachainHostFunctions;
+
+impl light_client_common::HostFunctions for U64ParachainHostFunctions {
+	type BlakeTwo256 = sp_runtime::traits::BlakeTwo256;
+}
+
+impl HostFunctions for U64ParachainHostFunctions {
+	type Header = RelayChainHeader;
+	type ParachainHeader = sp_runtime::generic::Header<u64, sp_runtime::traits::BlakeTwo256>;
+
+	fn ed25519_verify(sig: &ed25519::Signature, msg: &[u8], pub_key: &ed25519::Public) -> bool {
+		HostFunctionsManager::ed25519_verify(sig, msg, pub_key)
+	}
+
+	fn insert_relay_header_hashes(headers: &[H256]) {
+		HostFunctionsManager::insert_relay_header_hashes(headers)
+	}
+
+	fn contains_relay_header_hash(hash: H256) -> bool {
+		HostFunctionsManager::contains_relay_header_hash(hash)
+	}
+}
+
+/// Stores the encoded `parachain_header` in a relay chain state trie and returns the state root
+/// alongside the proofs expected by [`ConsensusState::from_header`].
+fn parachain_header_proofs(
+	para_id: u32,
+	parachain_header: Vec<u8>,
+) -> (H256, ParachainHeaderProofs) {
+	use sp_trie::{LayoutV0, MemoryDB, TrieDBMutBuilder, TrieMut};
+
+	let mut db = MemoryDB::<sp_runtime::traits::BlakeTwo256>::default();
+	let mut root = Default::default();
+	{
+		let mut trie =
+			TrieDBMutBuilder::<LayoutV0<sp_runtime::traits::BlakeTwo256>>::new(&mut db, &mut root)
+				.build();
+		trie.insert(parachain_header_storage_key(para_id).as_ref(), &parachain_header.encode())
+			.unwrap();
+	}
+	let state_proof = db.drain().into_values().map(|(node, _)| node).collect();
+
+	(
+		root,
+		ParachainHeaderProofs {
+			state_proof,
+			extrinsic: timestamp_extrinsic(TIMESTAMP_PALLET_INDEX, 1_690_000_000_000),
+			extrinsic_proof: vec![],
+		},
+	)
+}
+
+/// Index of the timestamp pallet in the runtime of the parachain of the fixtures.
+const TIMESTAMP_PALLET_INDEX: u8 = 3;
+
+/// Encoded unsigned extrinsic calling `pallet_index:call_index` with the encoded `args`.
+fn unsigned_extrinsic(pallet_index: u8, call_index: u8, args: impl Encode) -> Vec<u8> {
+	let mut extrinsic = vec![0b0000_0100, pallet_index, call_index];
+	extrinsic.extend(args.encode());
+	extrinsic.encode()
+}
+
+/// Encoded unsigned extrinsic calling `Timestamp::set` at `pallet_index`.
+fn timestamp_extrinsic(pallet_index: u8, timestamp: u64) -> Vec<u8> {
+	unsigned_extrinsic(pallet_index, 0, codec::Compact(timestamp))
+}
+
+#[test]
+fn timestamp_inherent_is_found_at_any_position() {
+	let timestamp_call = TimestampCall::new(TIMESTAMP_PALLET_INDEX);
+	// inherents of other pallets, such as the validation data of the parachain system
+	let other_inherents =
+		[unsigned_extrinsic(1, 0, vec![7u8; 40]), unsigned_extrinsic(2, 0, codec::Compact(5u64))];
+	for position in 0..=2 {
+		let mut extrinsics = other_inherents[..position].to_vec();
+		extrinsics.push(timestamp_extrinsic(TIMESTAMP_PALLET_INDEX, 1_690_000_000_000));
+		extrinsics.push(unsigned_extrinsic(4, 2, 10u32));
+		assert_eq!(
+			timestamp_call.find(&extrinsics).unwrap(),
+			(position, 1_690_000_000_000),
+			"timestamp inherent at {position}"
+		);
+	}
+
+	let err = timestamp_call.find(&other_inherents).unwrap_err();
+	assert!(err.to_string().contains("No timestamp inherent found"), "{err}");
+	// the inherents of other pallets are rejected, even when their call looks like `set`
+	let err = timestamp_call.decode(&other_inherents[1]).unwrap_err();
+	assert!(err.to_string().contains("calls 2:0 instead of the timestamp inherent"), "{err}");
+	let err = timestamp_call.decode(&other_inherents[0]).unwrap_err();
+	assert!(err.to_string().contains("instead of the timestamp inherent"), "{err}");
+}
+
+#[test]
+fn consensus_state_timestamp_is_decoded_from_the_pallet_of_the_client_state() {
+	let para_id = 2000;
+	let header = sp_runtime::generic::Header::<u32, sp_runtime::traits::BlakeTwo256> {
+		parent_hash: Default::default(),
+		number: 10,
+		state_root: H256::repeat_byte(1),
+		extrinsics_root: Default::default(),
+		digest: Default::default(),
+	};
+	let (relay_state_root, proofs) = parachain_header_proofs(para_id, header.encode());
+	let client_state = ClientState::<HostFunctionsManager> {
+		timestamp_pallet_index: Some(TIMESTAMP_PALLET_INDEX),
+		..lagging_parachain_client_state()
+	};
+	let (_, consensus_state) = ConsensusState::from_header::<HostFunctionsManager>(
+		proofs.clone(),
+		para_id,
+		relay_state_root,
+		client_state.timestamp_call(),
+	)
+	.unwrap();
+	assert_eq!(consensus_state.timestamp.unix_timestamp(), 1_690_000_000);
+
+	// the timestamp pallet moved in the runtime of the parachain
+	let client_state = ClientState { timestamp_pallet_index: Some(1), ..client_state };
+	let err = ConsensusState::from_header::<HostFunctionsManager>(
+		proofs,
+		para_id,
+		relay_state_root,
+		client_state.timestamp_call(),
+	)
+	.unwrap_err();
+	assert!(err.to_string().contains("instead of the timestamp inherent"), "{err}");
+}
+
+#[test]
+fn consensus_state_from_u64_parachain_header() {
+	let para_id = 2000;
+	let number = u32::MAX as u64 + 1;
+	let header = sp_runtime::generic::Header::<u64, sp_runtime::traits::BlakeTwo256> {
+		parent_hash: Default::default(),
+		number,
+		state_root: H256::repeat_byte(1),
+		extrinsics_root: Default::default(),
+		digest: Default::default(),
+	};
+	let (relay_state_root, proofs) = parachain_header_proofs(para_id, header.encode());
+
+	let (height, consensus_state) = ConsensusState::from_header::<U64ParachainHostFunctions>(
+		proofs.clone(),
+		para_id,
+		relay_state_root,
+		Default::default(),
+	)
+	.unwrap();
+	assert_eq!(height, Height::new(para_id as u64, number));
+	assert_eq!(consensus_state.root.as_bytes(), H256::repeat_byte(1).as_bytes());
+
+	// the default parachain header can't represent the block number
+	let err = ConsensusState::from_header::<HostFunctionsManager>(
+		proofs,
+		para_id,
+		relay_state_root,
+		Default::default(),
+	)
+	.unwrap_err();
+	assert!(err.to_string().contains("Failed to decode header of parachain 2000"), "{err}");
+}
+
+#[test]
+fn consensus_state_from_header_with_wrong_hasher_fails() {
+	let para_id = 2000;
+	// header of a parachain using a hasher with 20-byte outputs
+	let header = (
+		H160::repeat_byte(1),
+		codec::Compact(10u32),
+		H160::repeat_byte(2),
+		H160::repeat_byte(3),
+		sp_runtime::Digest::default(),
+	)
+		.encode();
+	let (relay_state_root, proofs) = parachain_header_proofs(para_id, header);
+
+	let err = ConsensusState::from_header::<HostFunctionsManager>(
+		proofs,
+		para_id,
+		relay_state_root,
+		Default::default(),
+	)
+	.unwrap_err();
+	assert!(err.to_string().contains("Failed to decode header of parachain 2000"), "{err}");
+}
+
+/// Builds a chain of `len` relay chain headers and an unsigned justification of the last one.
+fn unknown_headers_finality_proof(len: u32) -> FinalityProof<RelayChainHeader> {
+	unknown_headers_finality_proof_with_digests(vec![Default::default(); len as usize])
+}
+
+/// Builds a chain of relay chain headers from block 10 with the given `digests`, and an unsigned
+/// justification of the last one.
+fn unknown_headers_finality_proof_with_digests(
+	digests: Vec<Digest>,
+) -> FinalityProof<RelayChainHeader> {
+	let mut headers: Vec<RelayChainHeader> = vec![];
+	for (number, digest) in (10..).zip(digests) {
+		headers.push(RelayChainHeader {
+			parent_hash: headers.last().map(|parent| parent.hash()).unwrap_or_default(),
+			number,
+			state_root: Default::default(),
+			extrinsics_root: Default::default(),
+			digest,
+		});
+	}
+	let target = headers.last().unwrap();
+	let justification = GrandpaJustification::<RelayChainHeader> {
+		round: 1,
+		commit: finality_grandpa::Commit {
+			target_hash: target.hash(),
+			target_number: target.number,
+			precommits: vec![],
+		},
+		votes_ancestries: vec![],
+	};
+	FinalityProof {
+		block: target.hash(),
+		justification: justification.encode(),
+		unknown_headers: headers,
+	}
+}
+
+#[test]
+fn contiguous_unknown_headers_are_valid() {
+	validate_unknown_headers(&unknown_headers_finality_proof(1)).unwrap();
+	validate_unknown_headers(&unknown_headers_finality_proof(5)).unwrap();
+
+	let mut proof = unknown_headers_finality_proof(5);
+	proof.unknown_headers.clear();
+	assert!(matches!(validate_unknown_headers(&proof), Err(Error::EmptyUnknownHeaders)));
+}
+
+#[test]
+fn unknown_headers_without_the_target_are_rejected() {
+	let mut proof = unknown_headers_finality_proof(5);
+	let target = proof.unknown_headers.pop().unwrap();
+	assert!(matches!(
+		validate_unknown_headers(&proof),
+		Err(Error::MissingTargetHeader { target: hash }) if hash == target.hash()
+	));
+}
+
+#[test]
+fn unknown_headers_with_a_broken_link_are_rejected() {
+	let mut proof = unknown_headers_finality_proof(5);
+	proof.unknown_headers[2].parent_hash = H256::repeat_byte(1);
+	assert!(matches!(validate_unknown_headers(&proof), Err(Error::BrokenAncestry { number: 12 })));
+}
+
+#[test]
+fn unsorted_unknown_headers_are_rejected() {
+	let mut proof = unknown_headers_finality_proof(5);
+	proof.unknown_headers.swap(1, 2);
+	assert!(matches!(
+		validate_unknown_headers(&proof),
+		Err(Error::UnsortedUnknownHeaders { expected: 11, found: 12 })
+	));
+
+	// a gap in the chain
+	let mut proof = unknown_headers_finality_proof(5);
+	proof.unknown_headers.remove(2);
+	assert!(matches!(
+		validate_unknown_headers(&proof),
+		Err(Error::UnsortedUnknownHeaders { expected: 12, found: 13 })
+	));
+}
+
+#[test]
+fn forced_authority_set_changes_freeze_the_client() {
+	let ctx = MockContext::<MockClientTypes>::new(
+		ChainId::new("mockgaiaA".to_string(), 1),
+		MockHostType::Mock,
+		5,
+		Height::new(1, 11),
+	);
+	let client_id = ClientId::new(&ClientState::<HostFunctionsManager>::client_type(), 0).unwrap();
+	// the client knows the parent of the first unknown header
+	let client_state = ClientState::<HostFunctionsManager> {
+		latest_relay_hash: H256::default(),
+		latest_relay_height: 9,
+		..lagging_parachain_client_state()
+	};
+	let change = ScheduledChange { next_authorities: vec![], delay: 0 };
+	let forced_change = Digest {
+		logs: vec![DigestItem::Consensus(
+			GRANDPA_ENGINE_ID,
+			ConsensusLog::<u32>::ForcedChange(0, change).encode(),
+		)],
+	};
+	let header = |digests| {
+		ClientMessage::Header(Header {
+			finality_proof: unknown_headers_finality_proof_with_digests(digests),
+			parachain_headers: Default::default(),
+			height: Height::new(2000, 100),
+		})
+	};
+	let client = GrandpaClient::<HostFunctionsManager>::default();
+
+	let digests = vec![Digest::default(); 5];
+	assert!(!client
+		.check_for_misbehaviour(&ctx, client_id.clone(), client_state.clone(), header(digests))
+		.unwrap());
+
+	let mut digests = vec![Digest::default(); 5];
+	digests[2] = forced_change;
+	assert!(client
+		.check_for_misbehaviour(&ctx, client_id, client_state, header(digests))
+		.unwrap());
+}
+
+/// A client of a parachain that produced far fewer blocks than its relay chain.
+fn lagging_parachain_client_state() -> ClientState<HostFunctionsManager> {
+	ClientState {
+		latest_relay_height: 10_000,
+		latest_para_height: 100,
+		para_id: 2000,
+		..Default::default()
+	}
+}
+
+#[test]
+fn proof_heights_are_compared_to_the_parachain_height() {
+	let client_state = lagging_parachain_client_state();
+	client_state.verify_height(Height::new(2000, 100)).unwrap();
+	client_state.verify_height(Height::new(2000, 50)).unwrap();
+
+	match client_state.verify_height(Height::new(2000, 101)) {
+		Err(Error::InsufficientHeight { latest_para_height, latest_relay_height, height }) => {
+			assert_eq!(latest_para_height, Height::new(2000, 100));
+			assert_eq!(latest_relay_height, 10_000);
+			assert_eq!(height, Height::new(2000, 101));
+		},
+		other => panic!("expected an insufficient height error, got {other:?}"),
+	}
+}
+
+#[test]
+fn frozen_clients_reject_all_proofs() {
+	let client_state = lagging_parachain_client_state()
+		.with_frozen_height(Height::new(2000, 80))
+		.unwrap();
+	for height in [Height::new(2000, 50), Height::new(2000, 80), Height::new(2000, 100)] {
+		assert!(
+			matches!(client_state.verify_height(height), Err(Error::ClientFrozen { .. })),
+			"{height}"
+		);
+	}
+}
+
+/// The raw client state of [`lagging_parachain_client_state`] with `count` distinct authorities.
+fn raw_client_state_with_authorities(count: usize) -> RawClientState {
+	let current_authorities = (0..count as u64)
+		.map(|index| {
+			let mut key = [0u8; 32];
+			key[..8].copy_from_slice(&index.to_le_bytes());
+			(ed25519::Public::from_raw(key).into(), 1)
+		})
+		.collect();
+	ClientState { current_authorities, ..lagging_parachain_client_state() }.into()
+}
+
+#[test]
+fn authority_sets_are_bounded() {
+	ClientState::<HostFunctionsManager>::try_from(raw_client_state_with_authorities(1)).unwrap();
+	let client_state = ClientState::<HostFunctionsManager>::try_from(
+		raw_client_state_with_authorities(MAX_AUTHORITIES),
+	)
+	.unwrap();
+	assert_eq!(client_state.current_authorities.len(), MAX_AUTHORITIES);
+
+	assert!(matches!(
+		ClientState::<HostFunctionsManager>::try_from(raw_client_state_with_authorities(0)),
+		Err(Error::EmptyAuthoritySet)
+	));
+	assert!(matches!(
+		ClientState::<HostFunctionsManager>::try_from(raw_client_state_with_authorities(
+			MAX_AUTHORITIES + 1
+		)),
+		Err(Error::TooManyAuthorities { count, max: MAX_AUTHORITIES }) if count == MAX_AUTHORITIES + 1
+	));
+}
+
+#[test]
+fn duplicate_authorities_are_rejected() {
+	let mut raw = raw_client_state_with_authorities(5);
+	raw.current_authorities[3].public_key = raw.current_authorities[1].public_key.clone();
+	// a different weight doesn't make it another authority
+	raw.current_authorities[3].weight = 2;
+	assert!(matches!(
+		ClientState::<HostFunctionsManager>::try_from(raw),
+		Err(Error::DuplicateAuthority { index: 3 })
+	));
+}
+
+#[test]
+fn inconsistent_client_states_are_rejected() {
+	let client_state = ClientState::<HostFunctionsManager> {
+		current_set_id: 10_000,
+		..lagging_parachain_client_state()
+	};
+	client_state.validate().unwrap();
+	client_state.clone().with_frozen_height(Height::new(2000, 100)).unwrap();
+
+	let too_many_set_changes = ClientState { current_set_id: 10_001, ..client_state.clone() };
+	assert!(matches!(
+		too_many_set_changes.validate(),
+		Err(Error::InconsistentSetId { set_id: 10_001, latest_relay_height: 10_000 })
+	));
+
+	assert!(matches!(
+		client_state.with_frozen_height(Height::new(2000, 101)),
+		Err(Error::InconsistentFrozenHeight { frozen_height: 101, latest_para_height: 100 })
+	));
+}
+
+#[test]
+fn inconsistent_stored_client_states_are_still_decoded() {
+	let mut raw = raw_client_state_with_authorities(1);
+	raw.current_set_id = raw.latest_relay_height as u64 + 1;
+	raw.frozen_height = Some(raw.latest_para_height as u64 + 1);
+	let client_state = ClientState::<HostFunctionsManager>::try_from(raw).unwrap();
+	assert_eq!(client_state.frozen_height, Some(Height::new(2000, 101)));
+	assert!(client_state.validate().is_err());
+}
+
+#[test]
+fn unknown_headers_of_headers_are_bounded() {
+	let header = Header {
+		finality_proof: unknown_headers_finality_proof(MAX_UNKNOWN_HEADERS as u32),
+		parachain_headers: Default::default(),
+		height: Height::new(2000, 100),
+	};
+	let mut raw = RawHeader::from(header);
+	let header = Header::try_from(raw.clone()).unwrap();
+	assert_eq!(header.finality_proof.unknown_headers.len(), MAX_UNKNOWN_HEADERS);
+
+	// rejected before the headers are decoded
+	raw.finality_proof.as_mut().unwrap().unknown_headers.push(vec![]);
+	assert!(matches!(
+		Header::try_from(raw),
+		Err(Error::TooManyUnknownHeaders { count, max: MAX_UNKNOWN_HEADERS }) if count == MAX_UNKNOWN_HEADERS + 1
+	));
+}
+
+#[test]
+fn unknown_headers_of_misbehaviours_are_bounded() {
+	let proof = unknown_headers_finality_proof(MAX_UNKNOWN_HEADERS as u32);
+	let raw = RawMisbehaviour {
+		first_finality_proof: proof.encode(),
+		second_finality_proof: proof.encode(),
+	};
+	Misbehaviour::try_from(raw).unwrap();
+
+	let too_long = unknown_headers_finality_proof(MAX_UNKNOWN_HEADERS as u32 + 1);
+	let raw = RawMisbehaviour {
+		first_finality_proof: proof.encode(),
+		second_finality_proof: too_long.encode(),
+	};
+	assert!(matches!(
+		Misbehaviour::try_from(raw),
+		Err(Error::TooManyUnknownHeaders { count, max: MAX_UNKNOWN_HEADERS }) if count == MAX_UNKNOWN_HEADERS + 1
+	));
+}
+
+#[test]
+#[cfg(feature = "scale")]
+fn client_states_round_trip_through_scale() {
+	let raw = raw_client_state_with_authorities(3);
+	let mut client_state = ClientState::<HostFunctionsManager>::try_from(raw).unwrap();
+	client_state.relay_chain = light_client_common::RelayChain::Polkadot;
+	client_state.frozen_height = Some(Height::new(2000, 90));
+
+	let encoded = client_state.encode();
+	assert_eq!(
+		ClientState::<HostFunctionsManager>::decode(&mut &encoded[..]).unwrap(),
+		client_state
+	);
+}
+
+#[test]
+#[cfg(feature = "scale")]
+fn consensus_state_timestamps_are_scale_encoded_as_nanoseconds() {
+	let nanos = 1_690_000_000_123_456_789u64;
+	let consensus_state = ConsensusState {
+		timestamp: ibc::timestamp::Timestamp::from_nanoseconds(nanos)
+			.unwrap()
+			.into_tm_time()
+			.unwrap(),
+		root: vec![1; 32].into(),
+	};
+
+	let encoded = consensus_state.encode();
+	assert_eq!(encoded, (nanos, vec![1u8; 32]).encode());
+	assert_eq!(ConsensusState::decode(&mut &encoded[..]).unwrap(), consensus_state);
+
+	// the zero timestamp can't be represented
+	let zero = (0u64, vec![1u8; 32]).encode();
+	assert!(ConsensusState::decode(&mut &zero[..]).is_err());
+}
+
+#[test]
+#[cfg(feature = "scale")]
+fn client_messages_round_trip_through_scale() {
+	let header = Header {
+		finality_proof: unknown_headers_finality_proof(3),
+		parachain_headers: [(
+			H256::repeat_byte(1),
+			ParachainHeaderProofs {
+				state_proof: vec![vec![1, 2, 3]],
+				extrinsic: vec![4, 5],
+				extrinsic_proof: vec![vec![6]],
+			},
+		)]
+		.into_iter()
+		.collect(),
+		height: Height::new(2000, 100),
+	};
+	let encoded = ClientMessage::Header(header).encode();
+	let ClientMessage::Header(decoded) = ClientMessage::decode(&mut &encoded[..]).unwrap() else {
+		panic!("expected a header")
+	};
+	assert_eq!(decoded.height, Height::new(2000, 100));
+	assert_eq!(decoded.finality_proof, unknown_headers_finality_proof(3));
+	assert_eq!(ClientMessage::Header(decoded).encode(), encoded);
+
+	let misbehaviour = Misbehaviour {
+		first_finality_proof: unknown_headers_finality_proof(2),
+		second_finality_proof: unknown_headers_finality_proof(3),
+	};
+	let encoded = misbehaviour.encode();
+	let decoded = Misbehaviour::decode(&mut &encoded[..]).unwrap();
+	assert_eq!(decoded.first_finality_proof, misbehaviour.first_finality_proof);
+	assert_eq!(decoded.second_finality_proof, misbehaviour.second_finality_proof);
+}
+
+fn json_consensus_state() -> ConsensusState {
+	let timestamp = Time::parse_from_rfc3339("2023-05-17T09:21:45.123456789Z").unwrap();
+	ConsensusState::new(vec![0xab; 32], timestamp)
+}
+
+fn json_client_state() -> ClientState<HostFunctionsManager> {
+	ClientState {
+		relay_chain: RelayChain::Kusama,
+		latest_relay_hash: H256::repeat_byte(0x12),
+		frozen_height: Some(Height::new(2000, 90)),
+		current_set_id: 7,
+		current_authorities: vec![
+			(ed25519::Public::from_raw([1; 32]).into(), 1),
+			(ed25519::Public::from_raw([2; 32]).into(), 2),
+		],
+		timestamp_pallet_index: Some(3),
+		..lagging_parachain_client_state()
+	}
+}
+
+#[test]
+fn consensus_state_json_is_readable() {
+	let root = format!("0x{}", "ab".repeat(32));
+	assert_eq!(
+		json::to_value(json_consensus_state()).unwrap(),
+		json::json!({ "timestamp": "2023-05-17T09:21:45.123456789Z", "root": root }),
+	);
+}
+
+#[test]
+fn client_state_json_is_readable() {
+	assert_eq!(
+		json::to_value(json_client_state()).unwrap(),
+		json::json!({
+			"relay_chain": "Kusama",
+			"latest_relay_height": 10_000,
+			"latest_relay_hash": format!("0x{}", "12".repeat(32)),
+			"frozen_height": 90,
+			"latest_para_height": 100,
+			"para_id": 2000,
+			"current_set_id": 7,
+			"current_authorities": [
+				{ "public_key": format!("0x{}", "01".repeat(32)), "weight": 1 },
+				{ "public_key": format!("0x{}", "02".repeat(32)), "weight": 2 },
+			],
+			"timestamp_pallet_index": 3,
+		}),
+	);
+}
+
+#[test]
+fn states_round_trip_through_json() {
+	let whole_seconds =
+		ConsensusState::new(vec![0; 32], Time::parse_from_rfc3339("2023-05-17T09:21:45Z").unwrap());
+	let milliseconds = ConsensusState::new(
+		H256::repeat_byte(0xcd).as_bytes().to_vec(),
+		Time::parse_from_rfc3339("1970-01-01T00:00:00.001Z").unwrap(),
+	);
+	for consensus_state in [json_consensus_state(), whole_seconds, milliseconds] {
+		let encoded = json::to_string(&consensus_state).unwrap();
+		assert_eq!(json::from_str::<ConsensusState>(&encoded).unwrap(), consensus_state);
+	}
+
+	let unfrozen =
+		ClientState { frozen_height: None, timestamp_pallet_index: None, ..json_client_state() };
+	for client_state in [json_client_state(), unfrozen] {
+		let encoded = json::to_string(&client_state).unwrap();
+		assert_eq!(
+			json::from_str::<ClientState<HostFunctionsManager>>(&encoded).unwrap(),
+			client_state
+		);
+	}
+}
+
+#[test]
+fn malformed_json_roots_are_rejected() {
+	let timestamp = "2023-05-17T09:21:45Z";
+	for root in [
+		"ab".repeat(32),
+		format!("0x{}", "ab".repeat(31)),
+		format!("0x{}", "ab".repeat(33)),
+		format!("0x{}", "zz".repeat(32)),
+	] {
+		let encoded = json::json!({ "timestamp": timestamp, "root": root }).to_string();
+		assert!(json::from_str::<ConsensusState>(&encoded).is_err(), "{root}");
+	}
+
+	let mut client_state = json::to_value(json_client_state()).unwrap();
+	client_state["current_authorities"][0]["public_key"] = format!("0x{}", "01".repeat(31)).into();
+	assert!(json::from_str::<ClientState<HostFunctionsManager>>(&client_state.to_string()).is_err());
+}
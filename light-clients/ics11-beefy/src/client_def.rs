@@ -13,9 +13,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use beefy_light_client_primitives::{
-	ClientState as LightClientState, ParachainHeader, ParachainsUpdateProof,
-};
+use beefy_client::MmrLeafProof;
+use beefy_light_client_primitives::{ClientState as LightClientState, ParachainHeader};
 use codec::{Decode, Encode};
 use core::{fmt::Debug, marker::PhantomData};
 use pallet_mmr_primitives::Proof;
@@ -81,35 +80,27 @@ where
 					current_authorities: client_state.authority.clone(),
 					next_authorities: client_state.next_authority_set.clone(),
 				};
-				// If mmr update exists verify it and return the new light client state
-				// or else return existing light client state
-				let light_client_state = if let Some(mmr_update) = header.mmr_update_proof {
-					beefy_client::verify_mmr_root_with_proof::<H>(light_client_state, mmr_update)
-						.map_err(Error::from)?
-				} else {
-					light_client_state
-				};
+				let mmr_update = header.mmr_update_proof.map(MmrLeafProof::split);
 
 				// Extract parachain headers from the beefy header if they exist
-				if let Some(headers_with_proof) = header.headers_with_proof {
-					let parachain_headers = headers_with_proof
-						.headers
-						.into_iter()
-						.map(|header| ParachainHeader {
-							parachain_header: header.parachain_header.encode(),
-							partial_mmr_leaf: header.partial_mmr_leaf,
-							para_id: client_state.para_id,
-							parachain_heads_proof: header.parachain_heads_proof,
-							heads_leaf_index: header.heads_leaf_index,
-							heads_total_count: header.heads_total_count,
-							extrinsic_proof: header.extrinsic_proof,
-							timestamp_extrinsic: header.timestamp_extrinsic,
-						})
-						.collect::<Vec<_>>();
-
-					let parachain_update_proof = ParachainsUpdateProof {
-						parachain_headers,
-						mmr_proof: Proof {
+				let parachain_headers = match header.headers_with_proof {
+					Some(headers_with_proof) => {
+						let parachain_headers = headers_with_proof
+							.headers
+							.into_iter()
+							.map(|header| ParachainHeader {
+								parachain_header: header.parachain_header.encode(),
+								partial_mmr_leaf: header.partial_mmr_leaf,
+								para_id: client_state.para_id,
+								parachain_heads_proof: header.parachain_heads_proof,
+								heads_leaf_index: header.heads_leaf_index,
+								heads_total_count: header.heads_total_count,
+								extrinsic_proof: header.extrinsic_proof,
+								timestamp_extrinsic: header.timestamp_extrinsic,
+							})
+							.collect::<Vec<_>>();
+
+						let batch_proof = Proof {
 							leaf_indices: headers_with_proof.leaf_indices,
 							leaf_count: headers_with_proof.leaf_count,
 							items: headers_with_proof
@@ -118,16 +109,24 @@ where
 								.map(|item| H256::decode(&mut &*item))
 								.collect::<Result<Vec<_>, _>>()
 								.map_err(Error::from)?,
-						},
-					};
+						};
+						Some((parachain_headers, batch_proof))
+					},
+					None => None,
+				};
 
-					// Perform the parachain header verification
-					beefy_client::verify_parachain_headers::<H>(
-						light_client_state,
-						parachain_update_proof,
-					)
-					.map_err(Error::from)?
-				}
+				// Verify the mmr update if it exists, then the parachain headers against the
+				// resulting mmr root
+				beefy_client::verify_beefy_header::<H>(
+					&light_client_state,
+					mmr_update
+						.as_ref()
+						.map(|(signed_commitment, mmr_proof)| (signed_commitment, mmr_proof)),
+					parachain_headers.as_ref().map(|(parachain_headers, batch_proof)| {
+						(&parachain_headers[..], batch_proof)
+					}),
+				)
+				.map_err(Error::from)?;
 			},
 			ClientMessage::Misbehaviour(_) => unimplemented!(),
 		}
@@ -281,7 +280,7 @@ where
 		};
 		let value = expected_consensus_state.encode_to_vec().map_err(Ics02Error::encode)?;
 		verify_membership::<H::BlakeTwo256, _>(prefix, proof, root, path, value)
-			.map_err(Error::Anyhow)?;
+			.map_err(Error::Proof)?;
 		Ok(())
 	}
 
@@ -302,7 +301,7 @@ where
 		let path = ConnectionsPath(connection_id.clone());
 		let value = expected_connection_end.encode_vec().map_err(Ics02Error::encode)?;
 		verify_membership::<H::BlakeTwo256, _>(prefix, proof, root, path, value)
-			.map_err(Error::Anyhow)?;
+			.map_err(Error::Proof)?;
 		Ok(())
 	}
 
@@ -323,7 +322,7 @@ where
 		let path = ChannelEndsPath(port_id.clone(), *channel_id);
 		let value = expected_channel_end.encode_vec().map_err(Ics02Error::encode)?;
 		verify_membership::<H::BlakeTwo256, _>(prefix, proof, root, path, value)
-			.map_err(Error::Anyhow)?;
+			.map_err(Error::Proof)?;
 		Ok(())
 	}
 
@@ -342,7 +341,7 @@ where
 		let path = ClientStatePath(client_id.clone());
 		let value = expected_client_state.encode_to_vec().map_err(Ics02Error::encode)?;
 		verify_membership::<H::BlakeTwo256, _>(prefix, proof, root, path, value)
-			.map_err(Error::Anyhow)?;
+			.map_err(Error::Proof)?;
 		Ok(())
 	}
 
@@ -373,7 +372,7 @@ where
 			commitment_path,
 			commitment.into_vec(),
 		)
-		.map_err(Error::Anyhow)?;
+		.map_err(Error::Proof)?;
 		Ok(())
 	}
 
@@ -402,7 +401,7 @@ where
 			ack_path,
 			ack.into_vec(),
 		)
-		.map_err(Error::Anyhow)?;
+		.map_err(Error::Proof)?;
 		Ok(())
 	}
 
@@ -432,7 +431,7 @@ where
 			seq_path,
 			seq_bytes,
 		)
-		.map_err(Error::Anyhow)?;
+		.map_err(Error::Proof)?;
 		Ok(())
 	}
 
@@ -460,7 +459,7 @@ where
 			root,
 			receipt_path,
 		)
-		.map_err(Error::Anyhow)?;
+		.map_err(Error::Proof)?;
 		Ok(())
 	}
 }
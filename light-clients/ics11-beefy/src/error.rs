@@ -34,6 +34,7 @@ pub enum Error {
 	Ics04(ics04_channel::error::Error),
 	ProtoBuf(DecodeError),
 	Anyhow(anyhow::Error),
+	Proof(light_client_common::ProofError),
 	Custom(String),
 }
 
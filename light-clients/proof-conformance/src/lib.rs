@@ -0,0 +1,303 @@
+// Copyright (C) 2022 ComposableFi.
+// SPDX-License-Identifier: Apache-2.0
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Fixtures for the proof conformance tests.
+//!
+//! The same matrix of ics24 paths and values is committed to in the two layouts the relayer
+//! proves against each other: the substrate child trie written by pallet-ibc, verified by the
+//! grandpa and beefy light clients, and the ics23 store written by ibc-go, verified by the
+//! tendermint light client. The ics23 store is emulated with the simple iavl tree used by the
+//! pallet-ibc benchmarks, with its proof spec used for both the ibc store and the root store.
+
+use codec::Encode;
+use ibc::{
+	core::{
+		ics02_client::trust_threshold::TrustThreshold,
+		ics04_channel::packet::Sequence,
+		ics23_commitment::{
+			commitment::{CommitmentPrefix, CommitmentProofBytes, CommitmentRoot},
+			specs::ProofSpecs,
+		},
+		ics24_host::{
+			identifier::{ChainId, ChannelId, ClientId, ConnectionId, PortId},
+			path::{
+				AcksPath, ChannelEndsPath, ClientConsensusStatePath, ClientStatePath,
+				CommitmentsPath, ConnectionsPath, ReceiptsPath, SeqRecvsPath,
+			},
+			Path,
+		},
+	},
+	Height,
+};
+use ibc_proto::{
+	cosmos::ics23::v1::CommitmentProof, ibc::core::commitment::v1::MerkleProof as RawMerkleProof,
+};
+use ics07_tendermint::client_state::ClientState as TendermintClientState;
+use sha2::{Digest, Sha256};
+use simple_iavl::avl::AvlTree;
+use sp_core::{Blake2Hasher, H256};
+use sp_state_machine::{prove_child_read_on_trie_backend, InMemoryBackend};
+use sp_storage::{ChildInfo, StateVersion};
+use std::{str::FromStr, time::Duration};
+
+/// Commitment prefix of the ibc store on both chains.
+pub const PREFIX: &[u8] = b"ibc";
+
+/// Hasher of the substrate child trie.
+pub type SubstrateHasher = Blake2Hasher;
+
+/// Host functions of the tendermint light client.
+#[derive(Clone, Copy, Debug, PartialEq, Default, Eq)]
+pub struct HostFunctions;
+
+impl ics23::HostFunctionsProvider for HostFunctions {
+	fn sha2_256(message: &[u8]) -> [u8; 32] {
+		Sha256::digest(message).into()
+	}
+
+	fn sha2_512(_message: &[u8]) -> [u8; 64] {
+		unimplemented!()
+	}
+
+	fn sha2_512_truncated(_message: &[u8]) -> [u8; 32] {
+		unimplemented!()
+	}
+
+	fn sha3_512(_message: &[u8]) -> [u8; 64] {
+		unimplemented!()
+	}
+
+	fn ripemd160(_message: &[u8]) -> [u8; 20] {
+		unimplemented!()
+	}
+}
+
+/// A value committed at an ics24 path.
+#[derive(Debug, Clone)]
+pub struct Case {
+	/// Name of the case, used in assertion messages
+	pub name: String,
+	pub path: Path,
+	pub value: Vec<u8>,
+}
+
+/// Root and membership proof of a case.
+#[derive(Debug, Clone)]
+pub struct Commitment {
+	pub root: CommitmentRoot,
+	pub proof: CommitmentProofBytes,
+}
+
+/// Identifiers used to build the paths of the matrix.
+struct Identifiers {
+	label: &'static str,
+	client_id: ClientId,
+	connection_id: ConnectionId,
+	port_id: PortId,
+	channel_id: ChannelId,
+	sequence: Sequence,
+}
+
+impl Identifiers {
+	fn typical() -> Self {
+		Self {
+			label: "typical",
+			client_id: ClientId::new("07-tendermint", 0).unwrap(),
+			connection_id: ConnectionId::new(0),
+			port_id: PortId::transfer(),
+			channel_id: ChannelId::new(0),
+			sequence: Sequence::from(1),
+		}
+	}
+
+	/// Identifiers of the maximum length accepted by ics24.
+	fn max_length() -> Self {
+		Self {
+			label: "max_length",
+			client_id: ClientId::from_str(&format!("07-tendermint-{}", "9".repeat(50))).unwrap(),
+			connection_id: ConnectionId::from_str(&format!("connection-{}", "9".repeat(53)))
+				.unwrap(),
+			port_id: PortId::from_str(&"p".repeat(128)).unwrap(),
+			channel_id: ChannelId::new(u64::MAX),
+			sequence: Sequence::from(u64::MAX),
+		}
+	}
+
+	/// Every path the relayer proves, with a value of the expected shape.
+	fn cases(&self) -> Vec<Case> {
+		let Self { label, client_id, connection_id, port_id, channel_id, sequence } = self;
+		let (port_id, channel_id, sequence) = (port_id.clone(), *channel_id, *sequence);
+		let paths: Vec<(&str, Path, Vec<u8>)> = vec![
+			("client_state", ClientStatePath(client_id.clone()).into(), b"client state".to_vec()),
+			(
+				"consensus_state",
+				ClientConsensusStatePath {
+					client_id: client_id.clone(),
+					epoch: 1,
+					height: u64::from(sequence),
+				}
+				.into(),
+				b"consensus state".to_vec(),
+			),
+			("connection", ConnectionsPath(connection_id.clone()).into(), b"connection".to_vec()),
+			("channel", ChannelEndsPath(port_id.clone(), channel_id).into(), b"channel".to_vec()),
+			(
+				"next_sequence_recv",
+				SeqRecvsPath(port_id.clone(), channel_id).into(),
+				u64::from(sequence).to_be_bytes().to_vec(),
+			),
+			(
+				"packet_commitment",
+				CommitmentsPath { port_id: port_id.clone(), channel_id, sequence }.into(),
+				vec![0xab; 32],
+			),
+			(
+				"packet_acknowledgement",
+				AcksPath { port_id: port_id.clone(), channel_id, sequence }.into(),
+				vec![0xcd; 32],
+			),
+			("packet_receipt", ReceiptsPath { port_id, channel_id, sequence }.into(), vec![1]),
+		];
+		paths
+			.into_iter()
+			.map(|(name, path, value)| Case { name: format!("{label}/{name}"), path, value })
+			.collect()
+	}
+}
+
+/// The matrix of cases committed to by both layouts.
+pub fn cases() -> Vec<Case> {
+	let mut cases = Identifiers::typical().cases();
+	cases.extend(Identifiers::max_length().cases());
+	cases.push(empty_value_case());
+	cases
+}
+
+/// A packet commitment with an empty value. ibc-go never commits empty values and ics23 refuses
+/// to verify them, while the child trie stores them like any other value.
+pub fn empty_value_case() -> Case {
+	let ids = Identifiers::typical();
+	Case {
+		name: "empty_value".to_string(),
+		path: CommitmentsPath {
+			port_id: ids.port_id,
+			channel_id: ids.channel_id,
+			sequence: Sequence::from(2),
+		}
+		.into(),
+		value: vec![],
+	}
+}
+
+/// A path that isn't part of the matrix, used for non-membership proofs.
+pub fn absent_path() -> Path {
+	let ids = Identifiers::typical();
+	ReceiptsPath { port_id: ids.port_id, channel_id: ids.channel_id, sequence: Sequence::from(3) }
+		.into()
+}
+
+pub fn prefix() -> CommitmentPrefix {
+	CommitmentPrefix::try_from(PREFIX.to_vec()).unwrap()
+}
+
+/// Key of `path` in the child trie, as written by pallet-ibc.
+fn child_trie_key(path: &Path) -> Vec<u8> {
+	let mut key = PREFIX.to_vec();
+	key.extend(path.to_string().as_bytes());
+	key
+}
+
+/// Commits to `cases` in the child trie, the way pallet-ibc does, and returns the membership
+/// proof of each path in `proven`, in order.
+pub fn substrate_commitments(cases: &[Case], proven: &[Path]) -> Vec<Commitment> {
+	proven
+		.iter()
+		.map(|path| substrate_batch_commitment(cases, std::slice::from_ref(path)))
+		.collect()
+}
+
+/// Commits to `cases` in the child trie and returns a single proof of all the paths in `proven`.
+pub fn substrate_batch_commitment(cases: &[Case], proven: &[Path]) -> Commitment {
+	let child_info = ChildInfo::new_default(PREFIX);
+	let entries = cases
+		.iter()
+		.map(|case| (child_trie_key(&case.path), Some(case.value.encode())))
+		.collect();
+	let backend = InMemoryBackend::<SubstrateHasher>::from((
+		vec![(Some(child_info.clone()), entries)],
+		StateVersion::V0,
+	));
+	let root: H256 = *backend.root();
+
+	let keys = proven.iter().map(child_trie_key).collect::<Vec<_>>();
+	let proof = prove_child_read_on_trie_backend(&backend, &child_info, keys.iter()).unwrap();
+	let nodes = proof.into_iter_nodes().collect::<Vec<_>>();
+	Commitment {
+		root: CommitmentRoot::from_bytes(root.as_bytes()),
+		proof: CommitmentProofBytes::try_from(nodes.encode()).unwrap(),
+	}
+}
+
+/// Commits to `cases` in an ics23 store nested in a root store under [`PREFIX`], the way
+/// ibc-go does, and returns the membership proof of each case, in order.
+pub fn ics23_commitments(cases: &[Case]) -> Vec<Commitment> {
+	let mut tree = AvlTree::<Vec<u8>, Vec<u8>>::new();
+	for case in cases {
+		tree.insert(case.path.to_string().into_bytes(), case.value.clone());
+	}
+	let proofs = cases
+		.iter()
+		.map(|case| tree.get_proof(case.path.to_string().as_bytes()).unwrap())
+		.collect::<Vec<_>>();
+
+	// the store root is committed to by the root store
+	let store_root = tree.root_hash().unwrap().as_bytes().to_vec();
+	tree.insert(PREFIX.to_vec(), store_root);
+	let root = tree.root_hash().unwrap().as_bytes().to_vec();
+	let store_proof = tree.get_proof(PREFIX).unwrap();
+
+	proofs
+		.into_iter()
+		.map(|proof| {
+			let proof = RawMerkleProof {
+				proofs: vec![convert_proof(proof), convert_proof(store_proof.clone())],
+			};
+			Commitment {
+				root: CommitmentRoot::from_bytes(&root),
+				proof: CommitmentProofBytes::try_from(proof).unwrap(),
+			}
+		})
+		.collect()
+}
+
+fn convert_proof(proof: ics23::CommitmentProof) -> CommitmentProof {
+	prost::Message::decode(prost::Message::encode_to_vec(&proof).as_slice()).unwrap()
+}
+
+/// Client state of the tendermint light client verifying the ics23 store.
+pub fn tendermint_client_state() -> TendermintClientState<HostFunctions> {
+	let spec = simple_iavl::avl::get_proof_spec();
+	TendermintClientState::new(
+		ChainId::from_string("test-chain"),
+		TrustThreshold::ONE_THIRD,
+		Duration::new(65000, 0),
+		Duration::new(128000, 0),
+		Duration::new(3, 0),
+		Height::new(0, 1),
+		ProofSpecs::from(vec![spec.clone(), spec]),
+		vec![],
+	)
+	.unwrap()
+}
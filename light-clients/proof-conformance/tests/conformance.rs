@@ -0,0 +1,254 @@
+// Copyright (C) 2022 ComposableFi.
+// SPDX-License-Identifier: Apache-2.0
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Checks that the substrate and tendermint light clients each accept the proofs of their own
+//! store layout and reject the proofs of the other one with a structured error, rather than
+//! panicking or accepting them.
+
+use ibc::core::ics23_commitment::error::ErrorDetail as Ics23ErrorDetail;
+use ics07_tendermint::error::{Error as TendermintError, ErrorDetail as TendermintErrorDetail};
+use light_client_common::ProofError;
+use proof_conformance::{
+	absent_path, cases, empty_value_case, ics23_commitments, prefix, substrate_batch_commitment,
+	substrate_commitments, tendermint_client_state, Case, Commitment, HostFunctions,
+	SubstrateHasher,
+};
+
+fn verify_substrate(commitment: &Commitment, case: &Case) -> Result<(), ProofError> {
+	light_client_common::verify_membership::<SubstrateHasher, _>(
+		&prefix(),
+		&commitment.proof,
+		&commitment.root,
+		case.path.clone(),
+		case.value.clone(),
+	)
+}
+
+fn verify_tendermint(commitment: &Commitment, case: &Case) -> Result<(), TendermintError> {
+	ics07_tendermint::client_def::verify_membership::<HostFunctions, _>(
+		&tendermint_client_state(),
+		&prefix(),
+		&commitment.proof,
+		&commitment.root,
+		case.path.clone(),
+		case.value.clone(),
+	)
+}
+
+fn assert_substrate_rejected(result: Result<(), ProofError>, case: &str) {
+	match result {
+		Err(ProofError::Decode { .. } | ProofError::Verification { .. }) => {},
+		other => panic!("{case}: expected a decode or verification error, got {other:?}"),
+	}
+}
+
+fn assert_tendermint_rejected(result: Result<(), TendermintError>, case: &str) {
+	match result {
+		Err(TendermintError(TendermintErrorDetail::Ics23Error(_), _)) => {},
+		other => panic!("{case}: expected an ics23 error, got {other:?}"),
+	}
+}
+
+/// Cases whose values can be committed to in both layouts.
+fn shared_cases() -> Vec<Case> {
+	cases().into_iter().filter(|case| !case.value.is_empty()).collect()
+}
+
+#[test]
+fn substrate_proofs_are_accepted_by_substrate_verifier() {
+	let cases = cases();
+	let paths = cases.iter().map(|case| case.path.clone()).collect::<Vec<_>>();
+	for (case, commitment) in cases.iter().zip(substrate_commitments(&cases, &paths)) {
+		verify_substrate(&commitment, case).unwrap_or_else(|e| panic!("{}: {e}", case.name));
+	}
+}
+
+#[test]
+fn ics23_proofs_are_accepted_by_tendermint_verifier() {
+	let cases = shared_cases();
+	for (case, commitment) in cases.iter().zip(ics23_commitments(&cases)) {
+		verify_tendermint(&commitment, case).unwrap_or_else(|e| panic!("{}: {e}", case.name));
+	}
+}
+
+#[test]
+fn ics23_proofs_are_rejected_by_substrate_verifier() {
+	let cases = shared_cases();
+	for (case, commitment) in cases.iter().zip(ics23_commitments(&cases)) {
+		assert_substrate_rejected(verify_substrate(&commitment, case), &case.name);
+	}
+}
+
+#[test]
+fn substrate_proofs_are_rejected_by_tendermint_verifier() {
+	let cases = shared_cases();
+	let paths = cases.iter().map(|case| case.path.clone()).collect::<Vec<_>>();
+	for (case, commitment) in cases.iter().zip(substrate_commitments(&cases, &paths)) {
+		assert_tendermint_rejected(verify_tendermint(&commitment, case), &case.name);
+	}
+}
+
+/// The child trie commits to empty values, but ics23 refuses to verify them, so an empty value
+/// proven by a parachain can't be verified by a tendermint client and vice versa.
+#[test]
+fn empty_values_are_only_verifiable_in_child_trie() {
+	let case = empty_value_case();
+	let commitment = substrate_commitments(&cases(), &[case.path.clone()]).remove(0);
+	verify_substrate(&commitment, &case).unwrap();
+
+	let mut cases = shared_cases();
+	let commitment = ics23_commitments(&cases).remove(0);
+	cases[0].value = vec![];
+	match verify_tendermint(&commitment, &cases[0]) {
+		Err(TendermintError(TendermintErrorDetail::Ics23Error(e), _)) => assert!(
+			matches!(e.source.detail(), Ics23ErrorDetail::EmptyVerifiedValue(_)),
+			"{:?}",
+			e.source
+		),
+		other => panic!("expected an empty verified value error, got {other:?}"),
+	}
+}
+
+#[test]
+fn mismatched_values_are_rejected_by_both_verifiers() {
+	let cases = shared_cases();
+	let mut mismatched = cases.clone();
+	for case in &mut mismatched {
+		case.value.push(0);
+	}
+
+	let paths = cases.iter().map(|case| case.path.clone()).collect::<Vec<_>>();
+	for (case, commitment) in mismatched.iter().zip(substrate_commitments(&cases, &paths)) {
+		match verify_substrate(&commitment, case) {
+			Err(ProofError::Verification { .. }) => {},
+			other => panic!("{}: expected a verification error, got {other:?}", case.name),
+		}
+	}
+	for (case, commitment) in mismatched.iter().zip(ics23_commitments(&cases)) {
+		assert_tendermint_rejected(verify_tendermint(&commitment, case), &case.name);
+	}
+}
+
+#[test]
+fn non_membership_proofs() {
+	let cases = shared_cases();
+	let absent = absent_path();
+	let commitment = substrate_commitments(&cases, &[absent.clone()]).remove(0);
+	light_client_common::verify_non_membership::<SubstrateHasher, _>(
+		&prefix(),
+		&commitment.proof,
+		&commitment.root,
+		absent.clone(),
+	)
+	.unwrap();
+
+	// the child trie proof of absence isn't an ics23 non-existence proof
+	assert_tendermint_rejected(
+		ics07_tendermint::client_def::verify_non_membership::<HostFunctions, _>(
+			&tendermint_client_state(),
+			&prefix(),
+			&commitment.proof,
+			&commitment.root,
+			absent.clone(),
+		),
+		"substrate non-membership",
+	);
+
+	// the simple iavl tree can't produce non-existence proofs, so an existence proof of another
+	// path is used, which neither verifier may take as a proof of absence
+	let commitment = ics23_commitments(&cases).remove(0);
+	assert_substrate_rejected(
+		light_client_common::verify_non_membership::<SubstrateHasher, _>(
+			&prefix(),
+			&commitment.proof,
+			&commitment.root,
+			absent.clone(),
+		),
+		"ics23 non-membership",
+	);
+	assert_tendermint_rejected(
+		ics07_tendermint::client_def::verify_non_membership::<HostFunctions, _>(
+			&tendermint_client_state(),
+			&prefix(),
+			&commitment.proof,
+			&commitment.root,
+			absent,
+		),
+		"ics23 non-membership",
+	);
+}
+
+#[test]
+fn batch_proofs_are_accepted_by_substrate_verifier() {
+	let cases = cases();
+	let paths = cases.iter().map(|case| case.path.clone()).collect::<Vec<_>>();
+	let commitment = substrate_batch_commitment(&cases, &paths);
+	let items = cases
+		.iter()
+		.map(|case| (case.path.clone(), case.value.clone()))
+		.collect::<Vec<_>>();
+	light_client_common::verify_membership_batch::<SubstrateHasher>(
+		&prefix(),
+		&commitment.proof,
+		&commitment.root,
+		&items,
+	)
+	.unwrap();
+
+	let absent = absent_path();
+	let commitment = substrate_batch_commitment(&cases, &[absent.clone(), paths[0].clone()]);
+	light_client_common::verify_non_membership_batch::<SubstrateHasher>(
+		&prefix(),
+		&commitment.proof,
+		&commitment.root,
+		&[absent],
+	)
+	.unwrap();
+}
+
+#[test]
+fn batch_fails_with_the_first_unproven_path() {
+	let cases = cases();
+	let paths = cases.iter().map(|case| case.path.clone()).collect::<Vec<_>>();
+	let commitment = substrate_batch_commitment(&cases, &paths);
+	let mut items = cases
+		.iter()
+		.map(|case| (case.path.clone(), case.value.clone()))
+		.collect::<Vec<_>>();
+	items[3].1.push(0);
+
+	match light_client_common::verify_membership_batch::<SubstrateHasher>(
+		&prefix(),
+		&commitment.proof,
+		&commitment.root,
+		&items,
+	) {
+		Err(ProofError::Verification { path, .. }) => assert_eq!(path, paths[3].to_string()),
+		other => panic!("expected a verification error, got {other:?}"),
+	}
+
+	// a path present in the trie isn't proven absent, even if the others are
+	let absent = absent_path();
+	let commitment = substrate_batch_commitment(&cases, &[absent.clone(), paths[1].clone()]);
+	match light_client_common::verify_non_membership_batch::<SubstrateHasher>(
+		&prefix(),
+		&commitment.proof,
+		&commitment.root,
+		&[absent, paths[1].clone()],
+	) {
+		Err(ProofError::Verification { path, .. }) => assert_eq!(path, paths[1].to_string()),
+		other => panic!("expected a verification error, got {other:?}"),
+	}
+}
@@ -996,6 +996,7 @@ impl_runtime_apis! {
 		}
 	}
 
+	#[api_version(2)]
 	impl ibc_runtime_api::IbcRuntimeApi<Block, AssetId> for Runtime {
 		fn para_id() -> u32 {
 			<Runtime as cumulus_pallet_parachain_system::Config>::SelfParaId::get().into()
@@ -1130,6 +1131,10 @@ impl_runtime_apis! {
 				}).flatten().collect()
 			}
 		}
+
+		fn simulate_messages(messages: Vec<pallet_ibc::Any>) -> Vec<Result<(), pallet_ibc::errors::IbcError>> {
+			Ibc::simulate_messages(messages)
+		}
 	}
 
 	impl<RuntimeCall, AccountId> simnode_apis::CreateTransactionApi<Block, AccountId, RuntimeCall> for Runtime